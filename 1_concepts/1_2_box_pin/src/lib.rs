@@ -0,0 +1,5 @@
+//! Самоссылающиеся типы, ради которых и существует `Pin`
+
+pub mod pinned_buffer;
+
+pub use pinned_buffer::PinnedBuffer;
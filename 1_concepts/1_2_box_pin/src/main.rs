@@ -218,6 +218,13 @@ async fn main() {
     // Запускаем future
     let result = future.await;
     println!("Future result: {}", result);
+
+    println!("\n=== Testing PinnedBuffer ===");
+
+    // Самоссылающийся буфер существует только закрепленным
+    let buffer = step_1_2::PinnedBuffer::new("Hello, pinned world", 7..13)
+        .expect("срез в пределах строки");
+    println!("Slice {:?} of {:?}", buffer.slice(), buffer.data());
 }
 
 #[cfg(test)]
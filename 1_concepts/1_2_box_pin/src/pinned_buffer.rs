@@ -0,0 +1,107 @@
+//! Самоссылающаяся структура, закрепленная вручную, без `ouroboros`
+//!
+//! `PinnedBuffer` хранит строку и срез внутрь нее же. Обычная ссылка
+//! `&str` на собственное поле в Rust невыразима, поэтому срез хранится
+//! сырым указателем, а его корректность обеспечивает `Pin`.
+
+use std::marker::PhantomPinned;
+use std::ops::Range;
+use std::pin::Pin;
+use std::ptr::NonNull;
+
+/// Строка вместе со срезом, указывающим внутрь нее
+///
+/// Срез остается валидным, пока строку никто не меняет: `push_str` мог бы
+/// перевыделить память, и указатель повис бы. Поэтому буфер создается
+/// только закрепленным (`Pin<Box<Self>>`), а благодаря `PhantomPinned` он
+/// не `Unpin`, так что безопасный код не получит ни `&mut PinnedBuffer`,
+/// ни сам буфер по значению.
+///
+/// Из закрепления нельзя переместить:
+///
+/// ```compile_fail,E0277
+/// use std::pin::Pin;
+/// use step_1_2::PinnedBuffer;
+///
+/// let buffer = PinnedBuffer::new("hello, world", 7..12).unwrap();
+/// let moved: PinnedBuffer = *Pin::into_inner(buffer);
+/// ```
+///
+/// Нельзя и подменить содержимое через `&mut`:
+///
+/// ```compile_fail,E0596
+/// use step_1_2::PinnedBuffer;
+///
+/// let mut a = PinnedBuffer::new("hello", 0..1).unwrap();
+/// let mut b = PinnedBuffer::new("world", 0..1).unwrap();
+/// std::mem::swap(&mut *a, &mut *b);
+/// ```
+///
+/// А незакрепленным его не создать вовсе, поля закрыты:
+///
+/// ```compile_fail,E0451
+/// use std::marker::PhantomPinned;
+/// use std::ptr::NonNull;
+/// use step_1_2::PinnedBuffer;
+///
+/// let buffer = PinnedBuffer {
+///     data: String::from("hello"),
+///     slice: NonNull::from("he"),
+///     _pin: PhantomPinned,
+/// };
+/// ```
+#[derive(Debug)]
+pub struct PinnedBuffer {
+    data: String,
+    /// Указывает внутрь `data`
+    slice: NonNull<str>,
+    _pin: PhantomPinned,
+}
+
+impl PinnedBuffer {
+    /// Закрепляет строку в куче и запоминает ее срез `range`
+    ///
+    /// Возвращает `None`, если `range` выходит за строку или режет символ.
+    pub fn new(data: impl Into<String>, range: Range<usize>) -> Option<Pin<Box<Self>>> {
+        let data = data.into();
+        data.get(range.clone())?;
+        let mut buffer = Box::pin(Self {
+            data,
+            slice: NonNull::from(""),
+            _pin: PhantomPinned,
+        });
+        let slice = NonNull::from(&buffer.data[range]);
+        // SAFETY: заменяется лишь указатель, сам буфер не перемещается.
+        unsafe { buffer.as_mut().get_unchecked_mut().slice = slice };
+        Some(buffer)
+    }
+
+    /// Вся строка
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+
+    /// Запомненный срез строки
+    pub fn slice(&self) -> &str {
+        // SAFETY: указатель ведет в `data`, которую нельзя ни изменить, ни
+        // переместить, пока буфер закреплен, то есть все время его жизни.
+        unsafe { self.slice.as_ref() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slice_survives_moving_the_pin() {
+        let buffer = PinnedBuffer::new("привет, мир", 14..20).unwrap();
+        let buffers = vec![buffer];
+        let buffer = buffers.into_iter().next().unwrap();
+        assert_eq!(buffer.slice(), "мир");
+        assert_eq!(buffer.data(), "привет, мир");
+
+        assert!(PinnedBuffer::new("привет", 0..1).is_none());
+        assert!(PinnedBuffer::new("hello", 3..10).is_none());
+    }
+}
@@ -0,0 +1,855 @@
+//! Определение пути к конфигурационному файлу
+//!
+//! Аргументы командной строки, переменные окружения и файловая система
+//! передаются через [`ArgsProvider`], [`EnvProvider`] и [`FileProvider`],
+//! поэтому функции можно вызвать с произвольными значениями, не трогая
+//! окружение процесса. Пути хранятся как [`Path`], а не `&str`, так что
+//! не-UTF-8 имена файлов и пути Windows не теряются по дороге.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::{env, fmt, fs};
+
+/// Системный путь, используемый, если файл не найден в других местах
+#[cfg(not(windows))]
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/app/app.conf";
+
+/// Системный путь, используемый, если файл не найден в других местах
+#[cfg(windows)]
+pub const DEFAULT_CONFIG_PATH: &str = r"C:\ProgramData\app\app.conf";
+
+/// Переменная окружения с путем к конфигурационному файлу
+pub const CONFIG_ENV_VAR: &str = "APP_CONF";
+
+/// Каталог приложения внутри `$XDG_CONFIG_HOME` или `%APPDATA%`
+pub const CONFIG_DIR: &str = "app";
+
+/// Имя файла внутри [`CONFIG_DIR`]
+pub const CONFIG_FILE: &str = "app.conf";
+
+/// Имя файла внутри домашнего каталога
+pub const HOME_CONFIG_FILE: &str = ".app.conf";
+
+/// Аргумент с путем к конфигурационному файлу
+pub const CONF_FLAG: &str = "--conf";
+
+/// Аргумент с форматом конфигурационного файла, когда его нельзя определить
+pub const FORMAT_FLAG: &str = "--conf-format";
+
+/// Сколько байт из начала файла читается для определения формата
+pub const SNIFF_LEN: usize = 4096;
+
+/// Источник аргументов командной строки, включая имя программы
+pub trait ArgsProvider {
+    fn args(&self) -> Vec<OsString>;
+}
+
+/// Источник переменных окружения
+pub trait EnvProvider {
+    /// Значение переменной или `None`, если она не задана
+    fn var(&self, key: &str) -> Option<OsString>;
+}
+
+/// Аргументы текущего процесса
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessArgs;
+
+impl ArgsProvider for ProcessArgs {
+    fn args(&self) -> Vec<OsString> {
+        env::args_os().collect()
+    }
+}
+
+/// Окружение текущего процесса
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessEnv;
+
+impl EnvProvider for ProcessEnv {
+    fn var(&self, key: &str) -> Option<OsString> {
+        env::var_os(key)
+    }
+}
+
+/// Проверка существования файлов
+pub trait FileProvider {
+    /// Существует ли по пути обычный файл
+    fn is_file(&self, path: &Path) -> bool;
+
+    /// Можно ли прочитать существующий файл; по умолчанию любой
+    fn is_readable(&self, path: &Path) -> bool {
+        self.is_file(path)
+    }
+
+    /// Первые [`SNIFF_LEN`] байт файла или `None`, если их не прочитать;
+    /// по умолчанию содержимое неизвестно
+    fn head(&self, _path: &Path) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+/// Файловая система текущего процесса
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessFiles;
+
+impl FileProvider for ProcessFiles {
+    fn is_file(&self, path: &Path) -> bool {
+        fs::metadata(path).is_ok_and(|meta| meta.is_file())
+    }
+
+    fn is_readable(&self, path: &Path) -> bool {
+        fs::File::open(path).is_ok()
+    }
+
+    fn head(&self, path: &Path) -> Option<Vec<u8>> {
+        let mut head = Vec::new();
+        let file = fs::File::open(path).ok()?;
+        file.take(SNIFF_LEN as u64).read_to_end(&mut head).ok()?;
+        Some(head)
+    }
+}
+
+impl<T: AsRef<OsStr>> ArgsProvider for [T] {
+    fn args(&self) -> Vec<OsString> {
+        self.iter().map(|arg| arg.as_ref().to_owned()).collect()
+    }
+}
+
+impl<T: AsRef<OsStr>, const N: usize> ArgsProvider for [T; N] {
+    fn args(&self) -> Vec<OsString> {
+        self.as_slice().args()
+    }
+}
+
+impl<T: AsRef<OsStr>> ArgsProvider for Vec<T> {
+    fn args(&self) -> Vec<OsString> {
+        self.as_slice().args()
+    }
+}
+
+impl EnvProvider for HashMap<String, String> {
+    fn var(&self, key: &str) -> Option<OsString> {
+        self.get(key).map(OsString::from)
+    }
+}
+
+impl<V: AsRef<OsStr>> EnvProvider for [(&str, V)] {
+    fn var(&self, key: &str) -> Option<OsString> {
+        self.iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value.as_ref().to_owned())
+    }
+}
+
+impl<V: AsRef<OsStr>, const N: usize> EnvProvider for [(&str, V); N] {
+    fn var(&self, key: &str) -> Option<OsString> {
+        self.as_slice().var(key)
+    }
+}
+
+impl<T: AsRef<Path>> FileProvider for [T] {
+    fn is_file(&self, path: &Path) -> bool {
+        self.iter().any(|file| file.as_ref() == path)
+    }
+}
+
+impl<T: AsRef<Path>, const N: usize> FileProvider for [T; N] {
+    fn is_file(&self, path: &Path) -> bool {
+        self.as_slice().is_file(path)
+    }
+}
+
+/// Место, из которого взят путь, в порядке убывания приоритета
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tier {
+    /// Аргумент `--conf`
+    Cli,
+    /// Переменная окружения [`CONFIG_ENV_VAR`]
+    Env,
+    /// `app/app.conf` в `$XDG_CONFIG_HOME` (или в `~/.config`)
+    Xdg,
+    /// `app\app.conf` в `%APPDATA%`, вместо [`Tier::Xdg`] на Windows
+    AppData,
+    /// [`HOME_CONFIG_FILE`] в домашнем каталоге
+    Home,
+    /// [`DEFAULT_CONFIG_PATH`]
+    System,
+}
+
+impl Tier {
+    /// Задан ли путь пользователем явно, а не найден среди путей по умолчанию
+    pub fn is_explicit(self) -> bool {
+        matches!(self, Self::Cli | Self::Env)
+    }
+}
+
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Cli => "--conf argument",
+            Self::Env => "APP_CONF environment variable",
+            Self::Xdg => "XDG config directory",
+            Self::AppData => "APPDATA directory",
+            Self::Home => "home directory",
+            Self::System => "system default",
+        })
+    }
+}
+
+/// Найденный путь к конфигурационному файлу
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigPath {
+    pub path: Cow<'static, Path>,
+    pub tier: Tier,
+}
+
+/// Ошибка определения пути к конфигурационному файлу
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigPathError {
+    /// Аргумент, требующий значения, последний
+    MissingValue(&'static str),
+    /// Аргумент с пустым значением
+    EmptyValue(&'static str),
+    /// Неизвестное значение [`FORMAT_FLAG`]
+    InvalidFormat(String),
+    /// Формат существующего файла не определить ни по расширению, ни по
+    /// содержимому
+    AmbiguousFormat(PathBuf),
+    /// Явно заданного файла не существует
+    NotFound { path: PathBuf, tier: Tier },
+    /// Файл существует, но не читается
+    Unreadable { path: PathBuf, tier: Tier },
+    /// Не существует ни одного файла из путей по умолчанию
+    NoneFound { tried: Vec<PathBuf> },
+}
+
+impl fmt::Display for ConfigPathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingValue(flag) => write!(f, "{flag} argument requires a value"),
+            Self::EmptyValue(flag) => write!(f, "{flag} argument cannot be empty"),
+            Self::InvalidFormat(format) => write!(
+                f,
+                "unknown config format {format:?}, expected toml, yaml or json"
+            ),
+            Self::AmbiguousFormat(path) => write!(
+                f,
+                "can't tell the format of config file {}, set it with {FORMAT_FLAG}",
+                path.display()
+            ),
+            Self::NotFound { path, tier } => {
+                write!(
+                    f,
+                    "config file {} from {tier} does not exist",
+                    path.display()
+                )
+            }
+            Self::Unreadable { path, tier } => {
+                write!(
+                    f,
+                    "config file {} from {tier} is not readable",
+                    path.display()
+                )
+            }
+            Self::NoneFound { tried } => {
+                let tried: Vec<_> = tried
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                write!(f, "no config file found, tried {}", tried.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigPathError {}
+
+/// Определяет путь к конфигурационному файлу, см. [`resolve_config_path`].
+pub fn get_config_path<A, E, F>(
+    args: &A,
+    env: &E,
+    files: &F,
+) -> Result<Cow<'static, Path>, ConfigPathError>
+where
+    A: ArgsProvider + ?Sized,
+    E: EnvProvider + ?Sized,
+    F: FileProvider + ?Sized,
+{
+    resolve_config_path(args, env, files, false).map(|found| found.path)
+}
+
+/// Определяет путь к конфигурационному файлу среди [`config_candidates`].
+///
+/// Явно заданный путь берется как есть, а из путей по умолчанию выбирается
+/// первый существующий файл. Если ни одного нет, возвращается системный
+/// путь. С `require_existing` путь выбирается
+/// [`resolve_existing_config`], то есть отсутствие файла считается
+/// ошибкой. Существующий, но нечитаемый файл всегда ошибка.
+pub fn resolve_config_path<A, E, F>(
+    args: &A,
+    env: &E,
+    files: &F,
+    require_existing: bool,
+) -> Result<ConfigPath, ConfigPathError>
+where
+    A: ArgsProvider + ?Sized,
+    E: EnvProvider + ?Sized,
+    F: FileProvider + ?Sized,
+{
+    let mut candidates = config_candidates(args, env)?;
+    match find_existing(candidates.clone(), files) {
+        Err(ConfigPathError::NotFound { .. } | ConfigPathError::NoneFound { .. })
+            if !require_existing =>
+        {
+            // Последний кандидат это явно заданный или системный путь
+            Ok(candidates.pop().expect("system path is always a candidate"))
+        }
+        found => found,
+    }
+}
+
+/// Пути, где ищется конфигурационный файл, в порядке убывания приоритета:
+/// 1. `--conf` аргумент командной строки (высший приоритет)
+/// 2. переменная окружения [`CONFIG_ENV_VAR`]
+/// 3. `$XDG_CONFIG_HOME/app/app.conf`, где без `$XDG_CONFIG_HOME`
+///    используется `~/.config`; на Windows `%APPDATA%\app\app.conf`
+/// 4. `~/.app.conf`, на Windows в `%USERPROFILE%`
+/// 5. [`DEFAULT_CONFIG_PATH`]
+///
+/// Явно заданный путь (1 или 2) возвращается единственным, иначе
+/// возвращаются пути 3–5.
+///
+/// Системный путь возвращается как `Cow::Borrowed` без аллокации, а
+/// остальные как `Cow::Owned`, пропущенные через [`normalize`]. Пустые и
+/// относительные каталоги из окружения игнорируются, как и пустая
+/// [`CONFIG_ENV_VAR`], а пустой `--conf` считается ошибкой.
+pub fn config_candidates<A, E>(args: &A, env: &E) -> Result<Vec<ConfigPath>, ConfigPathError>
+where
+    A: ArgsProvider + ?Sized,
+    E: EnvProvider + ?Sized,
+{
+    let explicit = |path: &OsStr, tier| {
+        vec![ConfigPath {
+            path: Cow::Owned(normalize(Path::new(path)).into_owned()),
+            tier,
+        }]
+    };
+
+    if let Some(path) = flag_value(&args.args(), CONF_FLAG)? {
+        return Ok(explicit(&path, Tier::Cli));
+    }
+
+    if let Some(path) = env.var(CONFIG_ENV_VAR)
+        && !path.is_empty()
+    {
+        return Ok(explicit(&path, Tier::Env));
+    }
+
+    let mut candidates: Vec<_> = user_candidates(env)
+        .into_iter()
+        .map(|(path, tier)| ConfigPath {
+            path: Cow::Owned(normalize(&path).into_owned()),
+            tier,
+        })
+        .collect();
+    candidates.push(ConfigPath {
+        path: Cow::Borrowed(Path::new(DEFAULT_CONFIG_PATH)),
+        tier: Tier::System,
+    });
+    Ok(candidates)
+}
+
+/// Выбирает первый существующий и читаемый файл из `candidates`.
+///
+/// Отсутствие явно заданного ([`Tier::is_explicit`]) файла сразу считается
+/// ошибкой, а отсутствующие пути по умолчанию пропускаются. Нечитаемый файл
+/// ошибка в обоих случаях: молча взять вместо него следующий было бы
+/// неожиданно.
+pub fn resolve_existing_config<I, F>(
+    candidates: I,
+    files: &F,
+) -> Result<Cow<'static, Path>, ConfigPathError>
+where
+    I: IntoIterator<Item = ConfigPath>,
+    F: FileProvider + ?Sized,
+{
+    find_existing(candidates, files).map(|found| found.path)
+}
+
+/// [`resolve_existing_config`], сохраняющий, откуда взят путь
+fn find_existing<I, F>(candidates: I, files: &F) -> Result<ConfigPath, ConfigPathError>
+where
+    I: IntoIterator<Item = ConfigPath>,
+    F: FileProvider + ?Sized,
+{
+    let mut tried = Vec::new();
+    for candidate in candidates {
+        let ConfigPath { path, tier } = &candidate;
+        if !files.is_file(path) {
+            if tier.is_explicit() {
+                return Err(ConfigPathError::NotFound {
+                    path: path.to_path_buf(),
+                    tier: *tier,
+                });
+            }
+            tried.push(path.to_path_buf());
+            continue;
+        }
+        if !files.is_readable(path) {
+            return Err(ConfigPathError::Unreadable {
+                path: path.to_path_buf(),
+                tier: *tier,
+            });
+        }
+        return Ok(candidate);
+    }
+    Err(ConfigPathError::NoneFound { tried })
+}
+
+/// Непустое значение аргумента `flag`, если он задан
+fn flag_value(args: &[OsString], flag: &'static str) -> Result<Option<OsString>, ConfigPathError> {
+    let Some(i) = args.iter().position(|arg| arg == flag) else {
+        return Ok(None);
+    };
+    match args.get(i + 1) {
+        Some(value) if value.is_empty() => Err(ConfigPathError::EmptyValue(flag)),
+        Some(value) => Ok(Some(value.clone())),
+        None => Err(ConfigPathError::MissingValue(flag)),
+    }
+}
+
+/// Пользовательские пути по умолчанию в порядке приоритета
+fn user_candidates<E: EnvProvider + ?Sized>(env: &E) -> Vec<(PathBuf, Tier)> {
+    let dir = |key| {
+        env.var(key)
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+    };
+    let (config, tier, home) = if cfg!(windows) {
+        (dir("APPDATA"), Tier::AppData, dir("USERPROFILE"))
+    } else {
+        let home = dir("HOME");
+        let xdg = dir("XDG_CONFIG_HOME").or_else(|| home.as_ref().map(|home| home.join(".config")));
+        (xdg, Tier::Xdg, home)
+    };
+    let config = config.map(|dir| (dir.join(CONFIG_DIR).join(CONFIG_FILE), tier));
+    let home = home.map(|dir| (dir.join(HOME_CONFIG_FILE), Tier::Home));
+    config.into_iter().chain(home).collect()
+}
+
+/// Формат конфигурационного файла
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Формат по расширению файла, если оно известно
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "toml" => Some(Self::Toml),
+            "yaml" | "yml" => Some(Self::Yaml),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    /// Формат по началу содержимого файла.
+    ///
+    /// Решает первая строка, не считая пустых и комментариев: `{` означает
+    /// JSON, `---` и `%YAML` YAML, заголовок таблицы `[name]` TOML, а у
+    /// строк вида `key = value` и `key: value` формат определяется тем, что
+    /// встретится раньше, `=` или `:`.
+    pub fn sniff(head: &[u8]) -> Option<Self> {
+        let head = String::from_utf8_lossy(head);
+        let head = head.strip_prefix('\u{feff}').unwrap_or(&head);
+        let line = head
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))?;
+
+        if line.starts_with('{') {
+            return Some(Self::Json);
+        }
+        if line.starts_with("---") || line.starts_with("%YAML") {
+            return Some(Self::Yaml);
+        }
+        if let Some(table) = line.strip_prefix('[') {
+            let table = table.trim_start_matches('[').trim_end_matches(']');
+            let key = |c: char| c.is_alphanumeric() || "_-. \"'".contains(c);
+            let is_table = line.ends_with(']') && !table.is_empty() && table.chars().all(key);
+            return Some(if is_table { Self::Toml } else { Self::Json });
+        }
+        match (line.find('='), line.find(':')) {
+            (Some(eq), Some(colon)) if colon < eq => Some(Self::Yaml),
+            (Some(_), _) => Some(Self::Toml),
+            (None, Some(_)) => Some(Self::Yaml),
+            (None, None) => None,
+        }
+    }
+
+    /// Формат файла по расширению, а если оно неизвестно, по содержимому.
+    ///
+    /// Формат отсутствующего файла, для которого нечего определять, считается
+    /// TOML, а существующий файл, формат которого не определить, ошибка
+    /// [`ConfigPathError::AmbiguousFormat`].
+    pub fn detect<F: FileProvider + ?Sized>(
+        path: &Path,
+        files: &F,
+    ) -> Result<Self, ConfigPathError> {
+        if let Some(format) = Self::from_extension(path) {
+            return Ok(format);
+        }
+        match files.head(path) {
+            Some(head) => {
+                Self::sniff(&head).ok_or_else(|| ConfigPathError::AmbiguousFormat(path.into()))
+            }
+            None => Ok(Self::Toml),
+        }
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = ConfigPathError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_ascii_lowercase().as_str() {
+            "toml" => Ok(Self::Toml),
+            "yaml" | "yml" => Ok(Self::Yaml),
+            "json" => Ok(Self::Json),
+            _ => Err(ConfigPathError::InvalidFormat(format.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Toml => "toml",
+            Self::Yaml => "yaml",
+            Self::Json => "json",
+        })
+    }
+}
+
+/// Конфигурационный файл, готовый к загрузке
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedConfig {
+    pub path: Cow<'static, Path>,
+    pub tier: Tier,
+    pub format: ConfigFormat,
+}
+
+/// Определяет путь к конфигурационному файлу, как [`resolve_config_path`],
+/// и его формат.
+///
+/// Формат берется из аргумента [`FORMAT_FLAG`], а без него определяется
+/// [`ConfigFormat::detect`].
+pub fn resolve_config<A, E, F>(
+    args: &A,
+    env: &E,
+    files: &F,
+    require_existing: bool,
+) -> Result<ResolvedConfig, ConfigPathError>
+where
+    A: ArgsProvider + ?Sized,
+    E: EnvProvider + ?Sized,
+    F: FileProvider + ?Sized,
+{
+    let format = match flag_value(&args.args(), FORMAT_FLAG)? {
+        Some(format) => Some(format.to_string_lossy().parse()?),
+        None => None,
+    };
+    let ConfigPath { path, tier } = resolve_config_path(args, env, files, require_existing)?;
+    let format = match format {
+        Some(format) => format,
+        None => ConfigFormat::detect(&path, files)?,
+    };
+    Ok(ResolvedConfig { path, tier, format })
+}
+
+/// Лексически нормализует путь, не обращаясь к файловой системе: убирает
+/// `.` и повторные разделители и сокращает `..` с предыдущим именем.
+///
+/// Выше корня, в том числе корня UNC-ресурса `\\server\share`, `..` не
+/// поднимается, а в начале относительного пути сохраняется. Пути вида
+/// `\\?\...` возвращаются как есть, так как Windows не разбирает в них `.`
+/// и `..`. Уже нормализованный путь не копируется.
+pub fn normalize(path: &Path) -> Cow<'_, Path> {
+    let mut components = path.components().peekable();
+    if let Some(Component::Prefix(prefix)) = components.peek()
+        && prefix.kind().is_verbatim()
+    {
+        return Cow::Borrowed(path);
+    }
+
+    let mut normalized = PathBuf::new();
+    for component in components {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) => {}
+                _ => normalized.push(component),
+            },
+            component => normalized.push(component),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        normalized.push(Component::CurDir);
+    }
+
+    if normalized.as_os_str() == path.as_os_str() {
+        Cow::Borrowed(path)
+    } else {
+        Cow::Owned(normalized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NO_ENV: [(&str, &str); 0] = [];
+    const NO_FILES: [&str; 0] = [];
+
+    #[test]
+    fn test_default_path_is_borrowed() {
+        let default = Path::new(DEFAULT_CONFIG_PATH);
+        let path = get_config_path(&["program_name"], &NO_ENV, &NO_FILES).unwrap();
+        assert!(matches!(path, Cow::Borrowed(p) if p == default));
+
+        // Пустая переменная окружения не заменяет путь по умолчанию
+        let path = get_config_path(&["program_name"], &[("APP_CONF", "")], &NO_FILES).unwrap();
+        assert!(matches!(path, Cow::Borrowed(p) if p == default));
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let env = [("APP_CONF", "env/path.conf")];
+        let path = get_config_path(&["program_name"], &env, &NO_FILES).unwrap();
+        assert!(matches!(path, Cow::Owned(ref p) if p == Path::new("env/path.conf")));
+
+        // CLI аргумент имеет приоритет над переменной окружения
+        let args = vec!["program_name", "--conf", "cli/path.conf"];
+        let path = get_config_path(&args, &env, &NO_FILES).unwrap();
+        assert!(matches!(path, Cow::Owned(ref p) if p == Path::new("cli/path.conf")));
+    }
+
+    #[test]
+    fn test_invalid_conf_argument() {
+        let err = get_config_path(&["program_name", "--conf"], &NO_ENV, &NO_FILES).unwrap_err();
+        assert_eq!(err, ConfigPathError::MissingValue("--conf"));
+
+        let err = get_config_path(&["program_name", "--conf", ""], &NO_ENV, &NO_FILES).unwrap_err();
+        assert_eq!(err, ConfigPathError::EmptyValue("--conf"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_fallback_chain() {
+        let env = [
+            ("HOME", "/home/user"),
+            ("XDG_CONFIG_HOME", "/xdg/./conf/.."),
+        ];
+        let found = |files: &[&str]| resolve_config_path(&["program_name"], &env, files, false);
+
+        let home = found(&["/home/user/.app.conf", DEFAULT_CONFIG_PATH]).unwrap();
+        assert_eq!(home.tier, Tier::Home);
+        assert_eq!(home.path, Path::new("/home/user/.app.conf"));
+
+        let xdg = found(&["/home/user/.app.conf", "/xdg/app/app.conf"]).unwrap();
+        assert_eq!(xdg.tier, Tier::Xdg);
+        assert_eq!(xdg.path, Path::new("/xdg/app/app.conf"));
+
+        // Без $XDG_CONFIG_HOME используется ~/.config
+        let env = [("HOME", "/home/user")];
+        let files = ["/home/user/.config/app/app.conf"];
+        let xdg = resolve_config_path(&["program_name"], &env, &files, false).unwrap();
+        assert_eq!(xdg.tier, Tier::Xdg);
+
+        // Системный путь не требует аллокации, даже если файла нет
+        let system = found(&[]).unwrap();
+        assert_eq!(system.tier, Tier::System);
+        assert!(matches!(system.path, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_require_existing() {
+        let args = ["program_name", "--conf", "/cli/path.conf"];
+        let err = resolve_config_path(&args, &NO_ENV, &NO_FILES, true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "config file /cli/path.conf from --conf argument does not exist"
+        );
+
+        let env = [("HOME", "/home/user")];
+        let err = resolve_config_path(&["program_name"], &env, &NO_FILES, true).unwrap_err();
+        let tried = [
+            "/home/user/.config/app/app.conf",
+            "/home/user/.app.conf",
+            DEFAULT_CONFIG_PATH,
+        ];
+        assert_eq!(
+            err,
+            ConfigPathError::NoneFound {
+                tried: tried.map(PathBuf::from).to_vec()
+            }
+        );
+
+        let found =
+            resolve_config_path(&["program_name"], &env, &[DEFAULT_CONFIG_PATH], true).unwrap();
+        assert_eq!(found.tier, Tier::System);
+    }
+
+    #[test]
+    fn test_unreadable_file_is_an_error() {
+        struct Unreadable(&'static str);
+
+        impl FileProvider for Unreadable {
+            fn is_file(&self, _: &Path) -> bool {
+                true
+            }
+
+            fn is_readable(&self, path: &Path) -> bool {
+                path != Path::new(self.0)
+            }
+        }
+
+        let candidate = |path: &'static str, tier| ConfigPath {
+            path: Cow::Borrowed(Path::new(path)),
+            tier,
+        };
+        let candidates = [
+            candidate("user.conf", Tier::Home),
+            candidate("system.conf", Tier::System),
+        ];
+        let err =
+            resolve_existing_config(candidates.clone(), &Unreadable("user.conf")).unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigPathError::Unreadable {
+                tier: Tier::Home,
+                ..
+            }
+        ));
+
+        let path = resolve_existing_config(candidates, &Unreadable("other.conf")).unwrap();
+        assert_eq!(path, Path::new("user.conf"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_non_utf8_path() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let conf = OsStr::from_bytes(b"/tmp/\xffconf/../app.conf");
+        let env = [("APP_CONF", conf)];
+        let path = get_config_path(&["program_name"], &env, &NO_FILES).unwrap();
+        assert_eq!(path.as_os_str().as_bytes(), b"/tmp/app.conf");
+
+        let args = [OsStr::new("program_name"), OsStr::new("--conf"), conf];
+        let files = [Path::new("/tmp/app.conf")];
+        let found = resolve_config_path(&args, &NO_ENV, &files, true).unwrap();
+        assert_eq!(found.tier, Tier::Cli);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let normalized = |path: &str| normalize(Path::new(path)).into_owned();
+        assert_eq!(
+            normalized("/etc//app/./conf/../app.conf"),
+            Path::new("/etc/app/app.conf")
+        );
+        assert_eq!(normalized("/../app.conf"), Path::new("/app.conf"));
+        assert_eq!(normalized("../a/./../b"), Path::new("../b"));
+        assert_eq!(normalized("a/.."), Path::new("."));
+
+        // Нормализованный путь не копируется
+        assert!(matches!(normalize(Path::new("/etc/app")), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_windows_paths() {
+        let normalized = |path: &str| normalize(Path::new(path)).into_owned();
+        assert_eq!(
+            normalized(r"\\server\share\..\app\.\app.conf"),
+            Path::new(r"\\server\share\app\app.conf"),
+        );
+        assert_eq!(
+            normalized(r"\\?\C:\app\..\x"),
+            Path::new(r"\\?\C:\app\..\x")
+        );
+
+        // Профиль пользователя может лежать на сетевом ресурсе
+        let env = [("APPDATA", r"\\server\profiles\user\AppData\Roaming")];
+        let files = [r"\\server\profiles\user\AppData\Roaming\app\app.conf"];
+        let found = resolve_config_path(&["program_name"], &env, &files, true).unwrap();
+        assert_eq!(found.tier, Tier::AppData);
+    }
+
+    #[test]
+    fn test_format_detection() {
+        struct Contents(&'static str);
+
+        impl FileProvider for Contents {
+            fn is_file(&self, _: &Path) -> bool {
+                true
+            }
+
+            fn head(&self, _: &Path) -> Option<Vec<u8>> {
+                Some(self.0.as_bytes().to_vec())
+            }
+        }
+
+        let args = ["program_name", "--conf", "app.conf"];
+        let format = |contents| resolve_config(&args, &NO_ENV, &Contents(contents), true);
+        let detected = |contents| format(contents).unwrap().format;
+        assert_eq!(
+            detected("# comment\n\n[server]\nport = 80\n"),
+            ConfigFormat::Toml
+        );
+        assert_eq!(detected("url = \"http://host\"\n"), ConfigFormat::Toml);
+        assert_eq!(
+            detected("\u{feff}server:\n  port: 80\n"),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(detected("---\n"), ConfigFormat::Yaml);
+        assert_eq!(detected("  {\"server\": {}}"), ConfigFormat::Json);
+        assert_eq!(
+            format("just text").unwrap_err(),
+            ConfigPathError::AmbiguousFormat("app.conf".into())
+        );
+
+        // Расширение важнее содержимого, а аргумент важнее расширения
+        let args = ["program_name", "--conf", "app.json"];
+        let found = resolve_config(&args, &NO_ENV, &Contents("a = 1"), true).unwrap();
+        assert_eq!(found.format, ConfigFormat::Json);
+        let args = [
+            "program_name",
+            "--conf",
+            "app.json",
+            "--conf-format",
+            "yaml",
+        ];
+        let found = resolve_config(&args, &NO_ENV, &Contents("a = 1"), true).unwrap();
+        assert_eq!(found.format, ConfigFormat::Yaml);
+        let args = ["program_name", "--conf-format", "ini"];
+        let err = resolve_config(&args, &NO_ENV, &NO_FILES, false).unwrap_err();
+        assert_eq!(err, ConfigPathError::InvalidFormat("ini".into()));
+
+        // Отсутствующий файл по умолчанию читается как TOML
+        let found = resolve_config(&["program_name"], &NO_ENV, &NO_FILES, false).unwrap();
+        assert_eq!(found.format, ConfigFormat::Toml);
+    }
+}
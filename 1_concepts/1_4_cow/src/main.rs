@@ -1,45 +1,6 @@
 use std::borrow::Cow;
-use std::env;
 
-/// Определяет путь к конфигурационному файлу с учетом приоритетов:
-/// 1. --conf аргумент командной строки (высший приоритет)
-/// 2. APP_CONF переменная окружения
-/// 3. /etc/app/app.conf (по умолчанию)
-/// 
-/// Возвращает Cow<str> для эффективного управления памятью:
-/// - Cow::Borrowed для статических строк (без аллокации)
-/// - Cow::Owned для динамически созданных строк
-fn get_config_path() -> Result<Cow<'static, str>, String> {
-    // Проверяем аргументы командной строки
-    let args: Vec<String> = env::args().collect();
-    
-    // Ищем --conf аргумент
-    for (i, arg) in args.iter().enumerate() {
-        if arg == "--conf" {
-            if i + 1 < args.len() {
-                let conf_path = &args[i + 1];
-                if conf_path.is_empty() {
-                    return Err("Error: --conf argument cannot be empty".to_string());
-                }
-                // Возвращаем owned String, так как это динамически созданная строка
-                return Ok(Cow::Owned(conf_path.clone()));
-            } else {
-                return Err("Error: --conf argument requires a value".to_string());
-            }
-        }
-    }
-    
-    // Проверяем переменную окружения APP_CONF
-    if let Ok(env_conf) = env::var("APP_CONF") {
-        if !env_conf.is_empty() {
-            // Возвращаем owned String, так как это значение из переменной окружения
-            return Ok(Cow::Owned(env_conf));
-        }
-    }
-    
-    // Возвращаем путь по умолчанию как статическую строку (без аллокации)
-    Ok(Cow::Borrowed("/etc/app/app.conf"))
-}
+use step_1_4::{ProcessArgs, ProcessEnv, ProcessFiles, get_config_path, resolve_config};
 
 /// Демонстрирует различные способы использования Cow<str>
 fn demonstrate_cow_usage() {
@@ -87,18 +48,21 @@ fn main() {
     println!("Configuration Path Detector");
     println!("============================");
     
-    // Получаем путь к конфигурационному файлу
-    match get_config_path() {
-        Ok(path) => {
-            println!("Configuration file path: {}", path);
+    // Получаем путь к конфигурационному файлу; с --require-existing файл
+    // должен существовать
+    let require_existing = std::env::args_os().any(|arg| arg == "--require-existing");
+    match resolve_config(&ProcessArgs, &ProcessEnv, &ProcessFiles, require_existing) {
+        Ok(found) => {
+            println!("Configuration file path: {} (from {})", found.path.display(), found.tier);
+            println!("Configuration file format: {}", found.format);
             
             // Демонстрируем, что Cow эффективно управляет памятью
-            match path {
+            match found.path {
                 Cow::Borrowed(static_path) => {
-                    println!("✓ Using static path (no allocation): {}", static_path);
+                    println!("✓ Using static path (no allocation): {}", static_path.display());
                 }
                 Cow::Owned(owned_path) => {
-                    println!("✓ Using owned path (allocation occurred): {}", owned_path);
+                    println!("✓ Using owned path (allocation occurred): {}", owned_path.display());
                 }
             }
         }
@@ -114,7 +78,7 @@ fn main() {
     println!("\n=== Memory Efficiency Test ===");
     
     // Тестируем эффективность памяти
-    let default_path = get_config_path().unwrap();
+    let default_path = get_config_path(&ProcessArgs, &ProcessEnv, &ProcessFiles).unwrap();
     println!("Default path type: {}", 
         match default_path {
             Cow::Borrowed(_) => "Borrowed (no allocation)",
@@ -122,121 +86,14 @@ fn main() {
         }
     );
     
-    // Показываем, что можно работать с Cow как с обычной строкой
-    println!("Path length: {}", default_path.len());
-    println!("Path starts with '/etc': {}", default_path.starts_with("/etc"));
+    // Показываем, что можно работать с Cow как с обычным путем
+    println!("Path length: {}", default_path.as_os_str().len());
+    println!("Path file name: {:?}", default_path.file_name());
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::env;
-
-    /// Вспомогательная функция для тестирования с заданными аргументами командной строки
-    fn test_get_config_path_with_args(args: Vec<&str>) -> Result<Cow<'static, str>, String> {
-        // Проверяем аргументы командной строки
-        for (i, arg) in args.iter().enumerate() {
-            if *arg == "--conf" {
-                if i + 1 < args.len() {
-                    let conf_path = args[i + 1];
-                    if conf_path.is_empty() {
-                        return Err("Error: --conf argument cannot be empty".to_string());
-                    }
-                    return Ok(Cow::Owned(conf_path.to_string()));
-                } else {
-                    return Err("Error: --conf argument requires a value".to_string());
-                }
-            }
-        }
-        
-        // Проверяем переменную окружения APP_CONF
-        if let Ok(env_conf) = env::var("APP_CONF") {
-            if !env_conf.is_empty() {
-                return Ok(Cow::Owned(env_conf));
-            }
-        }
-        
-        // Возвращаем путь по умолчанию
-        Ok(Cow::Borrowed("/etc/app/app.conf"))
-    }
-
-    #[test]
-    fn test_default_path() {
-        // Очищаем переменные окружения для чистого теста
-        unsafe { env::remove_var("APP_CONF"); }
-        
-        let result = test_get_config_path_with_args(vec!["program_name"]);
-        assert!(result.is_ok());
-        
-        let path = result.unwrap();
-        match path {
-            Cow::Borrowed(p) => assert_eq!(p, "/etc/app/app.conf"),
-            Cow::Owned(_) => panic!("Expected borrowed path for default"),
-        }
-    }
-
-    #[test]
-    fn test_env_var_priority() {
-        // Устанавливаем переменную окружения
-        unsafe { env::set_var("APP_CONF", "/custom/path.conf"); }
-        
-        let result = test_get_config_path_with_args(vec!["program_name"]);
-        assert!(result.is_ok());
-        
-        let path = result.unwrap();
-        match path {
-            Cow::Owned(p) => assert_eq!(p, "/custom/path.conf"),
-            Cow::Borrowed(_) => panic!("Expected owned path for env var"),
-        }
-        
-        // Очищаем переменную окружения
-        unsafe { env::remove_var("APP_CONF"); }
-    }
-
-    #[test]
-    fn test_command_line_priority() {
-        let result = test_get_config_path_with_args(vec!["program_name", "--conf", "/cli/path.conf"]);
-        assert!(result.is_ok());
-        
-        let path = result.unwrap();
-        match path {
-            Cow::Owned(p) => assert_eq!(p, "/cli/path.conf"),
-            Cow::Borrowed(_) => panic!("Expected owned path for CLI arg"),
-        }
-    }
-
-    #[test]
-    fn test_empty_conf_argument() {
-        let result = test_get_config_path_with_args(vec!["program_name", "--conf"]);
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("requires a value"));
-    }
-
-    #[test]
-    fn test_empty_conf_value() {
-        let result = test_get_config_path_with_args(vec!["program_name", "--conf", ""]);
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("cannot be empty"));
-    }
-
-    #[test]
-    fn test_priority_order() {
-        // Устанавливаем переменную окружения
-        unsafe { env::set_var("APP_CONF", "/env/path.conf"); }
-        
-        // CLI аргумент должен иметь приоритет над переменной окружения
-        let result = test_get_config_path_with_args(vec!["program_name", "--conf", "/cli/path.conf"]);
-        assert!(result.is_ok());
-        
-        let path = result.unwrap();
-        match path {
-            Cow::Owned(p) => assert_eq!(p, "/cli/path.conf"),
-            Cow::Borrowed(_) => panic!("Expected owned path for CLI arg"),
-        }
-        
-        // Очищаем переменную окружения
-        unsafe { env::remove_var("APP_CONF"); }
-    }
 
     #[test]
     fn test_cow_memory_efficiency() {
@@ -246,13 +103,13 @@ mod tests {
         
         // Проверяем, что статическая строка не требует аллокации
         match static_cow {
-            Cow::Borrowed(_) => assert!(true), // Ожидаем Borrowed
+            Cow::Borrowed(_) => {} // Ожидаем Borrowed
             Cow::Owned(_) => panic!("Static string should be borrowed"),
         }
         
         // Проверяем, что owned строка требует аллокации
         match owned_cow {
-            Cow::Owned(_) => assert!(true), // Ожидаем Owned
+            Cow::Owned(_) => {} // Ожидаем Owned
             Cow::Borrowed(_) => panic!("Owned string should be owned"),
         }
     }
@@ -293,7 +150,7 @@ mod tests {
         
         // После мутации borrowed становится owned
         match borrowed_cow {
-            Cow::Owned(_) => assert!(true),
+            Cow::Owned(_) => {}
             Cow::Borrowed(_) => panic!("Borrowed should become owned after mutation"),
         }
     }
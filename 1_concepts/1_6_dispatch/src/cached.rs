@@ -0,0 +1,147 @@
+//! Двухуровневое хранилище со сквозной записью
+//!
+//! `CachedStorage` объединяет быстрый слой (например, `HashMapStorage`
+//! в памяти) с медленным (например, `PersistentStorage` на диске). Медленный
+//! слой остается источником истины, быстрый лишь ускоряет чтения.
+
+use super::Storage;
+
+/// Декоратор, кэширующий медленное `Storage` в быстром
+///
+/// Чтения сначала обращаются к быстрому слою и лишь при промахе — к
+/// медленному. Записи попадают в оба слоя, сначала в медленный, а удаления
+/// вычищают ключ из обоих. Поскольку `get` принимает `&self`, промах не
+/// заполняет кэш: прогреть его можно явным вызовом `warm()`.
+///
+/// Итерация идет по медленному слою, так как быстрый может содержать
+/// лишь часть записей.
+#[derive(Debug)]
+pub struct CachedStorage<Fast, Slow> {
+    fast: Fast,
+    slow: Slow,
+}
+
+impl<Fast, Slow> CachedStorage<Fast, Slow> {
+    /// Объединяет быстрый и медленный слои
+    pub fn new(fast: Fast, slow: Slow) -> Self {
+        Self { fast, slow }
+    }
+
+    /// Копирует в быстрый слой все записи медленного
+    pub fn warm<K, V>(&mut self)
+    where
+        Fast: Storage<K, V>,
+        Slow: Storage<K, V>,
+        K: Clone,
+        V: Clone,
+    {
+        for (key, val) in self.slow.iter() {
+            self.fast.set(key.clone(), val.clone());
+        }
+    }
+}
+
+impl<K, V, Fast, Slow> Storage<K, V> for CachedStorage<Fast, Slow>
+where
+    Fast: Storage<K, V>,
+    Slow: Storage<K, V>,
+    K: Clone,
+    V: Clone,
+{
+    fn set(&mut self, key: K, val: V) {
+        // Пока значение не записано в источник истины, кэш его не видит
+        self.slow.set(key.clone(), val.clone());
+        self.fast.set(key, val);
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        self.fast.get(key).or_else(|| self.slow.get(key))
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.fast.remove(key);
+        self.slow.remove(key)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, &V)> + '_> {
+        self.slow.iter()
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &K> + '_> {
+        self.slow.keys()
+    }
+
+    fn values(&self) -> Box<dyn Iterator<Item = &V> + '_> {
+        self.slow.values()
+    }
+
+    fn set_many(&mut self, entries: Vec<(K, V)>) {
+        self.slow.set_many(entries.clone());
+        self.fast.set_many(entries);
+    }
+
+    fn remove_many(&mut self, keys: &[K]) -> Vec<Option<V>> {
+        self.fast.remove_many(keys);
+        self.slow.remove_many(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistent::PersistentStorage;
+    use crate::{DynamicUserRepository, HashMapStorage, User};
+
+    #[test]
+    fn test_writes_through_to_persistent_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.log");
+        let storage = CachedStorage::new(
+            HashMapStorage::new(),
+            PersistentStorage::open(&path).unwrap(),
+        );
+
+        let mut repo = DynamicUserRepository::new(storage);
+        repo.add_user(User::new(1, "alice@example.com", true));
+        repo.add_user(User::new(2, "bob@example.com", false));
+        repo.remove_user(1);
+        drop(repo);
+
+        let slow = PersistentStorage::open(&path).unwrap();
+        assert_eq!(slow.keys().collect::<Vec<_>>(), vec![&2]);
+
+        // После перезапуска кэш пуст, и чтения идут в медленный слой
+        let mut storage = CachedStorage::new(HashMapStorage::new(), slow);
+        assert_eq!(
+            storage.get(&2),
+            Some(&User::new(2, "bob@example.com", false))
+        );
+        assert_eq!(storage.fast.keys().count(), 0);
+        storage.warm();
+        assert_eq!(storage.fast.keys().collect::<Vec<_>>(), vec![&2]);
+    }
+
+    #[test]
+    fn test_reads_prefer_fast_layer_and_removal_invalidates() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = CachedStorage::new(
+            HashMapStorage::new(),
+            PersistentStorage::open(dir.path().join("users.log")).unwrap(),
+        );
+        storage.set(1, User::new(1, "alice@example.com", true));
+
+        // Значение из быстрого слоя затеняет медленный
+        storage
+            .fast
+            .set(1, User::new(1, "cached@example.com", true));
+        assert_eq!(storage.get(&1).unwrap().email, "cached@example.com");
+
+        assert_eq!(
+            storage.remove(&1),
+            Some(User::new(1, "alice@example.com", true))
+        );
+        assert_eq!(storage.get(&1), None);
+        assert_eq!(storage.fast.get(&1), None);
+        assert_eq!(storage.remove(&1), None);
+    }
+}
@@ -0,0 +1,222 @@
+//! Хранилище со вторичным индексом
+//!
+//! `IndexedStorage` поддерживает отображение «поле значения → ключи» в
+//! актуальном состоянии при каждом `set`/`remove`, так что поиск по полю
+//! не требует перебора всех записей.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use super::Storage;
+use super::transaction::{Change, TransactionalStorage};
+
+/// Декоратор, индексирующий записи любого `Storage` по извлекаемому полю
+///
+/// Поле извлекается замыканием `index_of`, поэтому индексировать можно что
+/// угодно: email, флаг активации и т.п. Индекс не требует уникальности:
+/// одному значению поля может соответствовать несколько ключей, они
+/// хранятся в порядке добавления.
+///
+/// Записи, попавшие во внутреннее хранилище в обход декоратора, в индекс
+/// не попадают. Исключение — записи, существовавшие до оборачивания:
+/// их индексирует `new`.
+pub struct IndexedStorage<K, V, S, I, F> {
+    inner: S,
+    index: HashMap<I, Vec<K>>,
+    index_of: F,
+    _value: PhantomData<V>,
+}
+
+impl<K, V, S, I, F> IndexedStorage<K, V, S, I, F>
+where
+    K: Clone + PartialEq,
+    S: Storage<K, V>,
+    I: Hash + Eq,
+    F: Fn(&V) -> I,
+{
+    /// Оборачивает хранилище, индексируя уже лежащие в нем записи
+    pub fn new(inner: S, index_of: F) -> Self {
+        let mut index: HashMap<I, Vec<K>> = HashMap::new();
+        for (key, val) in inner.iter() {
+            index.entry(index_of(val)).or_default().push(key.clone());
+        }
+        Self {
+            inner,
+            index,
+            index_of,
+            _value: PhantomData,
+        }
+    }
+
+    /// Находит все записи с данным значением поля
+    pub fn find<Q>(&self, field: &Q) -> Vec<&V>
+    where
+        I: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.index
+            .get(field)
+            .into_iter()
+            .flatten()
+            .filter_map(|key| self.inner.get(key))
+            .collect()
+    }
+
+    fn unindex(&mut self, key: &K, field: I) {
+        if let Some(keys) = self.index.get_mut(&field) {
+            keys.retain(|k| k != key);
+            if keys.is_empty() {
+                self.index.remove(&field);
+            }
+        }
+    }
+}
+
+impl<K, V, S, I, F> Storage<K, V> for IndexedStorage<K, V, S, I, F>
+where
+    K: Clone + PartialEq,
+    S: Storage<K, V>,
+    I: Hash + Eq,
+    F: Fn(&V) -> I,
+{
+    fn set(&mut self, key: K, val: V) {
+        // Старое значение могло лежать под другим значением поля
+        if let Some(old) = self.inner.get(&key).map(&self.index_of) {
+            self.unindex(&key, old);
+        }
+        self.index
+            .entry((self.index_of)(&val))
+            .or_default()
+            .push(key.clone());
+        self.inner.set(key, val);
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        self.inner.get(key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let val = self.inner.remove(key)?;
+        self.unindex(key, (self.index_of)(&val));
+        Some(val)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, &V)> + '_> {
+        self.inner.iter()
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &K> + '_> {
+        self.inner.keys()
+    }
+
+    fn values(&self) -> Box<dyn Iterator<Item = &V> + '_> {
+        self.inner.values()
+    }
+
+    fn set_many(&mut self, entries: Vec<(K, V)>) {
+        // Повторы ключа в пачке схлопываются: побеждает последнее значение,
+        // иначе ключ остался бы в индексе и под прежними значениями поля
+        let mut batch: Vec<(K, V)> = Vec::with_capacity(entries.len());
+        for (key, val) in entries {
+            match batch.iter_mut().find(|(k, _)| *k == key) {
+                Some(entry) => entry.1 = val,
+                None => batch.push((key, val)),
+            }
+        }
+        for (key, val) in &batch {
+            if let Some(old) = self.inner.get(key).map(&self.index_of) {
+                self.unindex(key, old);
+            }
+            self.index
+                .entry((self.index_of)(val))
+                .or_default()
+                .push(key.clone());
+        }
+        self.inner.set_many(batch);
+    }
+
+    fn remove_many(&mut self, keys: &[K]) -> Vec<Option<V>> {
+        let removed = self.inner.remove_many(keys);
+        for (key, val) in keys.iter().zip(&removed) {
+            if let Some(val) = val {
+                self.unindex(key, (self.index_of)(val));
+            }
+        }
+        removed
+    }
+}
+
+impl<K, V, S, I, F> TransactionalStorage<K, V> for IndexedStorage<K, V, S, I, F>
+where
+    K: Clone + PartialEq,
+    S: TransactionalStorage<K, V>,
+    I: Hash + Eq,
+    F: Fn(&V) -> I,
+{
+    fn apply(&mut self, log: Vec<Change<K, V>>) {
+        // Индекс переводится из состояния до журнала в состояние после него,
+        // промежуточные изменения ключа в него не попадают
+        let mut keys: Vec<K> = Vec::new();
+        for change in &log {
+            if !keys.contains(change.key()) {
+                keys.push(change.key().clone());
+            }
+        }
+        for key in &keys {
+            if let Some(old) = self.inner.get(key).map(&self.index_of) {
+                self.unindex(key, old);
+            }
+        }
+        self.inner.apply(log);
+        for key in keys {
+            if let Some(field) = self.inner.get(&key).map(&self.index_of) {
+                self.index.entry(field).or_default().push(key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HashMapStorage, User};
+
+    #[test]
+    fn test_index_follows_set_and_remove() {
+        let mut storage = HashMapStorage::new();
+        storage.set(1, User::new(1, "alice@example.com", true));
+        let mut storage = IndexedStorage::new(storage, |user: &User| user.activated);
+
+        storage.set(2, User::new(2, "bob@example.com", false));
+        storage.set(3, User::new(3, "carol@example.com", true));
+        let ids = |users: Vec<&User>| users.iter().map(|user| user.id).collect::<Vec<_>>();
+        assert_eq!(ids(storage.find(&true)), vec![1, 3]);
+        assert_eq!(ids(storage.find(&false)), vec![2]);
+
+        // Перезапись переносит ключ под новое значение поля
+        storage.set(2, User::new(2, "bob@example.com", true));
+        assert_eq!(ids(storage.find(&true)), vec![1, 3, 2]);
+        assert!(storage.find(&false).is_empty());
+
+        storage.remove(&1);
+        storage.remove_many(&[3, 4]);
+        assert_eq!(ids(storage.find(&true)), vec![2]);
+        assert_eq!(storage.index.len(), 1);
+    }
+
+    #[test]
+    fn test_set_many_keeps_last_duplicate() {
+        let mut storage = IndexedStorage::new(HashMapStorage::new(), |user: &User| user.activated);
+        storage.set_many(vec![
+            (1, User::new(1, "alice@example.com", false)),
+            (2, User::new(2, "bob@example.com", false)),
+            (1, User::new(1, "alice@example.com", true)),
+        ]);
+        let ids = |users: Vec<&User>| users.iter().map(|user| user.id).collect::<Vec<_>>();
+        assert_eq!(ids(storage.find(&true)), vec![1]);
+        assert_eq!(ids(storage.find(&false)), vec![2]);
+        assert!(storage.get(&1).unwrap().activated);
+    }
+}
@@ -0,0 +1,237 @@
+//! Хранилище, собирающее метрики обращений
+//!
+//! `InstrumentedStorage` оборачивает любой `Storage` и считает операции,
+//! попадания и промахи, а также строит гистограммы задержек. Так разницу
+//! между статической и динамической диспетчеризацией можно измерить.
+
+use std::cell::Cell;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use super::Storage;
+
+/// Количество корзин гистограммы: последняя вмещает все от 2^31 нс (~2 с)
+const BUCKETS: usize = 32;
+
+/// Гистограмма задержек с корзинами по степеням двойки наносекунд
+///
+/// Корзина `i` считает задержки из `[2^(i-1), 2^i)` нс, а нулевая — менее 1 нс.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    buckets: [u64; BUCKETS],
+}
+
+impl LatencyHistogram {
+    /// Количество замеров
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Верхняя граница задержки, в которую укладывается доля `q` замеров
+    ///
+    /// Точность ограничена шириной корзин, то есть до двух раз.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let target = ((count as f64) * q.clamp(0.0, 1.0)).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        let bucket = self.buckets.iter().position(|n| {
+            seen += n;
+            seen >= target
+        })?;
+        Some(Duration::from_nanos(1 << bucket))
+    }
+}
+
+/// Гистограмма, пополняемая через `&self`, ведь `Storage::get` не берет `&mut`
+#[derive(Debug, Default)]
+struct Recorder {
+    buckets: [Cell<u64>; BUCKETS],
+}
+
+impl Recorder {
+    fn record(&self, elapsed: Duration) {
+        let nanos = elapsed.as_nanos().min(u128::from(u64::MAX)) as u64;
+        let bucket = (u64::BITS - nanos.leading_zeros()) as usize;
+        let cell = &self.buckets[bucket.min(BUCKETS - 1)];
+        cell.set(cell.get() + 1);
+    }
+
+    fn histogram(&self) -> LatencyHistogram {
+        LatencyHistogram {
+            buckets: std::array::from_fn(|i| self.buckets[i].get()),
+        }
+    }
+}
+
+/// Отчет о накопленных метриках
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageMetrics {
+    pub gets: u64,
+    pub sets: u64,
+    pub removes: u64,
+    /// `get` и `remove`, нашедшие значение
+    pub hits: u64,
+    /// `get` и `remove`, не нашедшие значение
+    pub misses: u64,
+    pub get_latency: LatencyHistogram,
+    pub set_latency: LatencyHistogram,
+    pub remove_latency: LatencyHistogram,
+}
+
+impl fmt::Display for StorageMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let p50 = |h: &LatencyHistogram| h.quantile(0.5).unwrap_or_default();
+        write!(
+            f,
+            "get: {} (p50 ≤ {:?}), set: {} (p50 ≤ {:?}), remove: {} (p50 ≤ {:?}), \
+             попаданий: {}, промахов: {}",
+            self.gets,
+            p50(&self.get_latency),
+            self.sets,
+            p50(&self.set_latency),
+            self.removes,
+            p50(&self.remove_latency),
+            self.hits,
+            self.misses,
+        )
+    }
+}
+
+/// Декоратор, измеряющий обращения к любому `Storage`
+///
+/// Итерация не учитывается: ее время зависит от потребителя итератора.
+#[derive(Debug)]
+pub struct InstrumentedStorage<S> {
+    inner: S,
+    gets: Cell<u64>,
+    sets: u64,
+    removes: u64,
+    hits: Cell<u64>,
+    misses: Cell<u64>,
+    get_latency: Recorder,
+    set_latency: Recorder,
+    remove_latency: Recorder,
+}
+
+impl<S> InstrumentedStorage<S> {
+    /// Оборачивает хранилище с обнуленными метриками
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            gets: Cell::new(0),
+            sets: 0,
+            removes: 0,
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+            get_latency: Recorder::default(),
+            set_latency: Recorder::default(),
+            remove_latency: Recorder::default(),
+        }
+    }
+
+    /// Снимает текущие значения метрик
+    pub fn snapshot(&self) -> StorageMetrics {
+        StorageMetrics {
+            gets: self.gets.get(),
+            sets: self.sets,
+            removes: self.removes,
+            hits: self.hits.get(),
+            misses: self.misses.get(),
+            get_latency: self.get_latency.histogram(),
+            set_latency: self.set_latency.histogram(),
+            remove_latency: self.remove_latency.histogram(),
+        }
+    }
+
+    fn count_lookup(&self, found: bool) {
+        let counter = if found { &self.hits } else { &self.misses };
+        counter.set(counter.get() + 1);
+    }
+}
+
+impl<K, V, S> Storage<K, V> for InstrumentedStorage<S>
+where
+    S: Storage<K, V>,
+{
+    fn set(&mut self, key: K, val: V) {
+        let started = Instant::now();
+        self.inner.set(key, val);
+        self.set_latency.record(started.elapsed());
+        self.sets += 1;
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        let started = Instant::now();
+        let val = self.inner.get(key);
+        self.get_latency.record(started.elapsed());
+        self.gets.set(self.gets.get() + 1);
+        self.count_lookup(val.is_some());
+        val
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let started = Instant::now();
+        let val = self.inner.remove(key);
+        self.remove_latency.record(started.elapsed());
+        self.removes += 1;
+        self.count_lookup(val.is_some());
+        val
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, &V)> + '_> {
+        self.inner.iter()
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &K> + '_> {
+        self.inner.keys()
+    }
+
+    fn values(&self) -> Box<dyn Iterator<Item = &V> + '_> {
+        self.inner.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashMapStorage;
+
+    #[test]
+    fn test_counts_operations_hits_and_misses() {
+        let mut storage = InstrumentedStorage::new(HashMapStorage::new());
+        storage.set(1, "alice");
+        storage.set(2, "bob");
+        assert_eq!(storage.get(&1), Some(&"alice"));
+        assert_eq!(storage.get(&3), None);
+        assert_eq!(storage.remove(&2), Some("bob"));
+        assert_eq!(storage.remove(&2), None);
+        assert_eq!(storage.get_many(&[1, 2]).len(), 2);
+
+        let metrics = storage.snapshot();
+        assert_eq!((metrics.gets, metrics.sets, metrics.removes), (4, 2, 2));
+        assert_eq!((metrics.hits, metrics.misses), (3, 3));
+        assert_eq!(metrics.get_latency.count(), 4);
+        assert_eq!(metrics.set_latency.count(), 2);
+        assert!(metrics.remove_latency.quantile(1.0).is_some());
+    }
+
+    #[test]
+    fn test_quantiles_follow_buckets() {
+        let recorder = Recorder::default();
+        recorder.record(Duration::ZERO);
+        for _ in 0..8 {
+            recorder.record(Duration::from_nanos(100));
+        }
+        recorder.record(Duration::from_secs(10));
+        let histogram = recorder.histogram();
+
+        assert_eq!(histogram.count(), 10);
+        assert_eq!(histogram.quantile(0.1), Some(Duration::from_nanos(1)));
+        assert_eq!(histogram.quantile(0.5), Some(Duration::from_nanos(128)));
+        assert_eq!(histogram.quantile(1.0), Some(Duration::from_nanos(1 << 31)));
+        assert_eq!(LatencyHistogram::default().quantile(0.5), None);
+    }
+}
@@ -1,5 +1,22 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+mod cached;
+mod indexed;
+mod instrumented;
+mod persistent;
+mod transaction;
+mod ttl;
+
+use cached::CachedStorage;
+use indexed::IndexedStorage;
+use instrumented::InstrumentedStorage;
+use persistent::PersistentStorage;
+use transaction::TransactionalStorage;
+use ttl::TtlStorage;
 
 // ============================================================================
 // БАЗОВЫЕ СТРУКТУРЫ И ТРЕЙТЫ
@@ -18,13 +35,91 @@ trait Storage<K, V> {
     
     /// Удаляет значение по ключу и возвращает его
     fn remove(&mut self, key: &K) -> Option<V>;
+
+    /// Итерирует по всем парам ключ-значение
+    ///
+    /// Итератор возвращается в `Box`, чтобы трейт оставался object safe
+    /// и мог использоваться через `dyn Storage<K, V>`.
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, &V)> + '_>;
+
+    /// Итерирует по всем ключам
+    fn keys(&self) -> Box<dyn Iterator<Item = &K> + '_>;
+
+    /// Итерирует по всем значениям
+    fn values(&self) -> Box<dyn Iterator<Item = &V> + '_>;
+
+    /// Устанавливает сразу несколько значений
+    ///
+    /// Реализация по умолчанию просто вызывает `set` для каждой пары, а
+    /// хранилища, умеющие вставлять пачкой быстрее, ее переопределяют.
+    /// Через `dyn Storage` вся пачка обходится одним виртуальным вызовом.
+    fn set_many(&mut self, entries: Vec<(K, V)>) {
+        for (key, val) in entries {
+            self.set(key, val);
+        }
+    }
+
+    /// Получает значения по нескольким ключам в порядке ключей
+    fn get_many(&self, keys: &[K]) -> Vec<Option<&V>> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
+    /// Удаляет значения по нескольким ключам и возвращает их в порядке ключей
+    fn remove_many(&mut self, keys: &[K]) -> Vec<Option<V>> {
+        keys.iter().map(|key| self.remove(key)).collect()
+    }
+}
+
+/// Хранилище за указателем, в том числе `Box<dyn Storage<K, V>>`
+///
+/// Позволяет оборачивать trait object декораторами наравне с конкретными
+/// типами.
+impl<K, V, S> Storage<K, V> for Box<S>
+where
+    S: Storage<K, V> + ?Sized,
+{
+    fn set(&mut self, key: K, val: V) {
+        (**self).set(key, val)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        (**self).get(key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        (**self).remove(key)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, &V)> + '_> {
+        (**self).iter()
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &K> + '_> {
+        (**self).keys()
+    }
+
+    fn values(&self) -> Box<dyn Iterator<Item = &V> + '_> {
+        (**self).values()
+    }
+
+    fn set_many(&mut self, entries: Vec<(K, V)>) {
+        (**self).set_many(entries)
+    }
+
+    fn get_many(&self, keys: &[K]) -> Vec<Option<&V>> {
+        (**self).get_many(keys)
+    }
+
+    fn remove_many(&mut self, keys: &[K]) -> Vec<Option<V>> {
+        (**self).remove_many(keys)
+    }
 }
 
 /// Структура пользователя
 /// 
 /// Использует Cow<'static, str> для эффективного хранения строк,
 /// что позволяет избежать лишних аллокаций при работе с литералами.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct User {
     id: u64,
     email: Cow<'static, str>,
@@ -40,8 +135,17 @@ impl User {
             activated,
         }
     }
+
+    /// Поле, по которому репозитории индексируют пользователей
+    fn email_key(&self) -> Cow<'static, str> {
+        self.email.clone()
+    }
 }
 
+/// Хранилище пользователей с индексом по email
+type EmailIndexed<S> =
+    IndexedStorage<u64, User, S, Cow<'static, str>, fn(&User) -> Cow<'static, str>>;
+
 // ============================================================================
 // КОНКРЕТНЫЕ РЕАЛИЗАЦИИ STORAGE
 // ============================================================================
@@ -84,6 +188,23 @@ where
     fn remove(&mut self, key: &K) -> Option<V> {
         self.data.remove(key)
     }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, &V)> + '_> {
+        Box::new(self.data.iter())
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &K> + '_> {
+        Box::new(self.data.keys())
+    }
+
+    fn values(&self) -> Box<dyn Iterator<Item = &V> + '_> {
+        Box::new(self.data.values())
+    }
+
+    fn set_many(&mut self, entries: Vec<(K, V)>) {
+        // Одно выделение памяти под всю пачку вместо постепенного роста
+        self.data.extend(entries);
+    }
 }
 
 // ============================================================================
@@ -106,7 +227,7 @@ where
 /// - Небольшая потеря производительности
 /// - Ограничения object safety
 pub struct DynamicUserRepository {
-    storage: Box<dyn Storage<u64, User>>,
+    storage: EmailIndexed<Box<dyn Storage<u64, User>>>,
 }
 
 impl DynamicUserRepository {
@@ -115,8 +236,9 @@ impl DynamicUserRepository {
     where 
         S: Storage<u64, User> + 'static,
     {
+        let storage: Box<dyn Storage<u64, User>> = Box::new(storage);
         Self {
-            storage: Box::new(storage),
+            storage: IndexedStorage::new(storage, User::email_key),
         }
     }
 
@@ -125,17 +247,30 @@ impl DynamicUserRepository {
         self.storage.set(user.id, user);
     }
 
+    /// Добавляет пользователей пачкой, одним обращением к хранилищу
+    pub fn import_users(&mut self, users: impl IntoIterator<Item = User>) {
+        let entries = users.into_iter().map(|user| (user.id, user)).collect();
+        self.storage.set_many(entries);
+    }
+
     /// Получает пользователя по ID
     pub fn get_user(&self, id: u64) -> Option<&User> {
         self.storage.get(&id)
     }
 
-    /// Обновляет пользователя
+    /// Находит пользователя по email через индекс, без перебора записей
+    pub fn find_by_email(&self, email: &str) -> Option<&User> {
+        self.storage.find(email).into_iter().next()
+    }
+
+    /// Обновляет существующего пользователя
+    ///
+    /// Значение заменяется одной записью, без промежуточного удаления,
+    /// так что пользователь не пропадает из хранилища ни на миг.
     pub fn update_user(&mut self, user: User) -> Option<User> {
-        self.storage.remove(&user.id).map(|_| {
-            self.storage.set(user.id, user.clone());
-            user
-        })
+        self.storage.get(&user.id)?;
+        self.storage.set(user.id, user.clone());
+        Some(user)
     }
 
     /// Удаляет пользователя по ID
@@ -143,11 +278,18 @@ impl DynamicUserRepository {
         self.storage.remove(&id)
     }
 
-    /// Получает все ID пользователей (для демонстрации)
+    /// Получает все ID пользователей в порядке возрастания
     pub fn get_all_user_ids(&self) -> Vec<u64> {
-        // В реальной реализации здесь был бы итератор по ключам
-        // Для простоты возвращаем пустой вектор
-        vec![]
+        let mut ids: Vec<u64> = self.storage.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Получает всех пользователей, упорядоченных по ID
+    pub fn list_users(&self) -> Vec<&User> {
+        let mut users: Vec<&User> = self.storage.values().collect();
+        users.sort_unstable_by_key(|user| user.id);
+        users
     }
 }
 
@@ -175,7 +317,7 @@ pub struct StaticUserRepository<S>
 where 
     S: Storage<u64, User>,
 {
-    storage: S,
+    storage: EmailIndexed<S>,
 }
 
 impl<S> StaticUserRepository<S> 
@@ -184,7 +326,9 @@ where
 {
     /// Создает новый репозиторий с указанной реализацией Storage
     pub fn new(storage: S) -> Self {
-        Self { storage }
+        Self {
+            storage: IndexedStorage::new(storage, User::email_key),
+        }
     }
 
     /// Добавляет пользователя в хранилище
@@ -192,17 +336,30 @@ where
         self.storage.set(user.id, user);
     }
 
+    /// Добавляет пользователей пачкой, одним обращением к хранилищу
+    pub fn import_users(&mut self, users: impl IntoIterator<Item = User>) {
+        let entries = users.into_iter().map(|user| (user.id, user)).collect();
+        self.storage.set_many(entries);
+    }
+
     /// Получает пользователя по ID
     pub fn get_user(&self, id: u64) -> Option<&User> {
         self.storage.get(&id)
     }
 
-    /// Обновляет пользователя
+    /// Находит пользователя по email через индекс, без перебора записей
+    pub fn find_by_email(&self, email: &str) -> Option<&User> {
+        self.storage.find(email).into_iter().next()
+    }
+
+    /// Обновляет существующего пользователя
+    ///
+    /// Значение заменяется одной записью, без промежуточного удаления,
+    /// так что пользователь не пропадает из хранилища ни на миг.
     pub fn update_user(&mut self, user: User) -> Option<User> {
-        self.storage.remove(&user.id).map(|_| {
-            self.storage.set(user.id, user.clone());
-            user
-        })
+        self.storage.get(&user.id)?;
+        self.storage.set(user.id, user.clone());
+        Some(user)
     }
 
     /// Удаляет пользователя по ID
@@ -210,11 +367,18 @@ where
         self.storage.remove(&id)
     }
 
-    /// Получает все ID пользователей (для демонстрации)
+    /// Получает все ID пользователей в порядке возрастания
     pub fn get_all_user_ids(&self) -> Vec<u64> {
-        // В реальной реализации здесь был бы итератор по ключам
-        // Для простоты возвращаем пустой вектор
-        vec![]
+        let mut ids: Vec<u64> = self.storage.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Получает всех пользователей, упорядоченных по ID
+    pub fn list_users(&self) -> Vec<&User> {
+        let mut users: Vec<&User> = self.storage.values().collect();
+        users.sort_unstable_by_key(|user| user.id);
+        users
     }
 }
 
@@ -262,6 +426,18 @@ where
             None
         }
     }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&u64, &V)> + '_> {
+        Box::new(self.data.iter().map(|(key, val)| (key, val)))
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &u64> + '_> {
+        Box::new(self.data.iter().map(|(key, _)| key))
+    }
+
+    fn values(&self) -> Box<dyn Iterator<Item = &V> + '_> {
+        Box::new(self.data.iter().map(|(_, val)| val))
+    }
 }
 
 // ============================================================================
@@ -305,14 +481,18 @@ fn main() {
     // Создаем репозиторий с VecStorage
     let mut static_repo_vec = StaticUserRepository::new(VecStorage::new());
     
-    // Добавляем пользователей
-    static_repo_vec.add_user(user2.clone());
-    static_repo_vec.add_user(user3.clone());
+    // Добавляем пользователей пачкой
+    static_repo_vec.import_users([user2.clone(), user3.clone()]);
     
     // Получаем пользователя
     if let Some(user) = static_repo_vec.get_user(2) {
         println!("Найден пользователь через VecStorage: {:?}", user);
     }
+
+    // Поиск по email идет через вторичный индекс, а не перебором
+    if let Some(user) = static_repo_vec.find_by_email("charlie@example.com") {
+        println!("Найден по email: {:?}", user);
+    }
     
     println!();
 
@@ -327,10 +507,8 @@ fn main() {
     // Создаем репозиторий с HashMapStorage через trait object
     let mut dynamic_repo = DynamicUserRepository::new(HashMapStorage::new());
     
-    // Добавляем пользователей
-    dynamic_repo.add_user(user1.clone());
-    dynamic_repo.add_user(user2.clone());
-    dynamic_repo.add_user(user3.clone());
+    // Добавляем пользователей пачкой: один виртуальный вызов на всех
+    dynamic_repo.import_users([user1.clone(), user2.clone(), user3.clone()]);
     
     // Получаем пользователя
     if let Some(user) = dynamic_repo.get_user(1) {
@@ -343,13 +521,55 @@ fn main() {
         println!("Обновлен пользователь: {:?} -> {:?}", old_user, updated_user);
     }
     
+    // Индекс следует за обновлением: старый адрес больше не находится
+    println!(
+        "Старый email найден: {}, новый: {}",
+        dynamic_repo.find_by_email("bob@example.com").is_some(),
+        dynamic_repo.find_by_email("bob.updated@example.com").is_some(),
+    );
+
     // Удаляем пользователя
     if let Some(removed_user) = dynamic_repo.remove_user(3) {
         println!("Удален пользователь: {:?}", removed_user);
     }
+
+    // Перечисляем оставшихся пользователей
+    println!("Оставшиеся ID пользователей: {:?}", dynamic_repo.get_all_user_ids());
+
+    // Пачками можно и читать, и удалять
+    let found = dynamic_repo.storage.get_many(&[1, 2, 3]);
+    println!("Найдено пачкой: {} из 3", found.iter().flatten().count());
+    let removed = dynamic_repo.storage.remove_many(&[1, 2]);
+    println!("Удалено пачкой: {} из 2", removed.iter().flatten().count());
     
     println!();
 
+    // ========================================================================
+    // ИЗМЕРЕНИЕ РАЗНИЦЫ
+    // ========================================================================
+
+    println!("=== ИЗМЕРЕНИЕ ===");
+    println!("Одна и та же нагрузка на HashMapStorage напрямую и через dyn Storage\n");
+
+    fn exercise<S: Storage<u64, User>>(storage: &mut S) {
+        for id in 0..10_000 {
+            storage.set(id, User::new(id, "load@example.com", true));
+            storage.get(&id);
+            storage.get(&(id + 10_000));
+        }
+    }
+
+    let mut direct = InstrumentedStorage::new(HashMapStorage::new());
+    exercise(&mut direct);
+    println!("Статически:  {}", direct.snapshot());
+
+    let boxed: Box<dyn Storage<u64, User>> = Box::new(HashMapStorage::new());
+    let mut through_vtable = InstrumentedStorage::new(boxed);
+    exercise(&mut through_vtable);
+    println!("Динамически: {}", through_vtable.snapshot());
+
+    println!();
+
     // ========================================================================
     // ДЕМОНСТРАЦИЯ ГИБКОСТИ ДИНАМИЧЕСКОЙ ДИСПЕТЧЕРИЗАЦИИ
     // ========================================================================
@@ -359,7 +579,7 @@ fn main() {
 
     // Функция, которая принимает любой Storage через trait object
     fn demonstrate_storage(storage: Box<dyn Storage<u64, User>>) {
-        let mut repo = DynamicUserRepository { storage };
+        let mut repo = DynamicUserRepository::new(storage);
         
         let test_user = User::new(999, "test@example.com", true);
         repo.add_user(test_user.clone());
@@ -367,6 +587,11 @@ fn main() {
         if let Some(user) = repo.get_user(999) {
             println!("Пользователь успешно сохранен и получен: {:?}", user);
         }
+
+        // Содержимое перечисляется через тот же trait object
+        for (id, user) in repo.storage.iter() {
+            println!("  {} => {}", id, user.email);
+        }
     }
     
     // Используем HashMapStorage
@@ -376,6 +601,52 @@ fn main() {
     // Используем VecStorage
     println!("Используем VecStorage:");
     demonstrate_storage(Box::new(VecStorage::new()));
+
+    // Декоратор с TTL оборачивает любую реализацию и сам является Storage
+    println!("Используем TtlStorage поверх HashMapStorage:");
+    demonstrate_storage(Box::new(TtlStorage::new(HashMapStorage::new(), Duration::from_secs(60))));
+
+    // Просроченные записи скрываются сразу, а вытесняются явно
+    let mut sessions = TtlStorage::new(HashMapStorage::new(), Duration::from_secs(60));
+    sessions.set(user1.id, user1.clone());
+    sessions.set_with_ttl(user2.id, user2.clone(), Duration::ZERO);
+    println!("Сессия пользователя 1 истекает через {:?}", sessions.time_to_live(&user1.id));
+    println!("Вытеснено просроченных сессий: {}", sessions.purge_expired());
+
+    // Транзакция применяет изменения все разом или не применяет вовсе
+    let mut accounts = HashMapStorage::new();
+    accounts.set(user1.id, user1.clone());
+    let mut tx = accounts.begin();
+    tx.remove(user1.id);
+    tx.set(user2.id, user2.clone());
+    tx.rollback();
+    let mut tx = accounts.begin();
+    tx.set(user3.id, user3.clone());
+    tx.commit();
+    println!("После отката и фиксации в хранилище: {} пользователя", accounts.keys().count());
+
+    // Хранилище на диске переживает перезапуск: второй репозиторий
+    // восстанавливает пользователей из журнала первого
+    let log_path = std::env::temp_dir().join("step_1_6_users.log");
+    let _ = std::fs::remove_file(&log_path);
+    match PersistentStorage::open(&log_path) {
+        Ok(storage) => {
+            let mut repo = DynamicUserRepository::new(storage);
+            repo.add_user(user1.clone());
+            repo.add_user(user3.clone());
+            drop(repo);
+
+            let mut storage = PersistentStorage::open(&log_path).expect("журнал только что записан");
+            storage.compact().expect("журнал только что записан");
+
+            // Кэш в памяти поверх журнала: чтения из памяти, записи в оба слоя
+            let mut storage = CachedStorage::new(HashMapStorage::new(), storage);
+            storage.warm();
+            let repo = DynamicUserRepository::new(storage);
+            println!("Восстановлены из {}: {:?}", log_path.display(), repo.get_all_user_ids());
+        }
+        Err(err) => println!("Не удалось открыть {}: {}", log_path.display(), err),
+    }
     
     println!();
 
@@ -481,6 +752,34 @@ where
             StorageEnum::Vec(storage) => storage.remove(key),
         }
     }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&u64, &V)> + '_> {
+        match self {
+            StorageEnum::HashMap(storage) => storage.iter(),
+            StorageEnum::Vec(storage) => storage.iter(),
+        }
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &u64> + '_> {
+        match self {
+            StorageEnum::HashMap(storage) => storage.keys(),
+            StorageEnum::Vec(storage) => storage.keys(),
+        }
+    }
+
+    fn values(&self) -> Box<dyn Iterator<Item = &V> + '_> {
+        match self {
+            StorageEnum::HashMap(storage) => storage.values(),
+            StorageEnum::Vec(storage) => storage.values(),
+        }
+    }
+
+    fn set_many(&mut self, entries: Vec<(u64, V)>) {
+        match self {
+            StorageEnum::HashMap(storage) => storage.set_many(entries),
+            StorageEnum::Vec(storage) => storage.set_many(entries),
+        }
+    }
 }
 
 /// Репозиторий с enum-based диспетчеризацией
@@ -489,32 +788,62 @@ where
 /// статической диспетчеризации при сохранении гибкости выбора
 /// конкретной реализации во время выполнения.
 pub struct EnumUserRepository {
-    storage: StorageEnum<User>,
+    storage: EmailIndexed<StorageEnum<User>>,
 }
 
 impl EnumUserRepository {
     pub fn new(storage: StorageEnum<User>) -> Self {
-        Self { storage }
+        Self {
+            storage: IndexedStorage::new(storage, User::email_key),
+        }
     }
 
     pub fn add_user(&mut self, user: User) {
         self.storage.set(user.id, user);
     }
 
+    /// Добавляет пользователей пачкой, одним обращением к хранилищу
+    pub fn import_users(&mut self, users: impl IntoIterator<Item = User>) {
+        let entries = users.into_iter().map(|user| (user.id, user)).collect();
+        self.storage.set_many(entries);
+    }
+
     pub fn get_user(&self, id: u64) -> Option<&User> {
         self.storage.get(&id)
     }
 
+    /// Находит пользователя по email через индекс, без перебора записей
+    pub fn find_by_email(&self, email: &str) -> Option<&User> {
+        self.storage.find(email).into_iter().next()
+    }
+
+    /// Обновляет существующего пользователя одной транзакцией, так что
+    /// пользователь не пропадает из хранилища ни на миг
     pub fn update_user(&mut self, user: User) -> Option<User> {
-        self.storage.remove(&user.id).map(|_| {
-            self.storage.set(user.id, user.clone());
-            user
-        })
+        let mut tx = self.storage.begin();
+        tx.get(&user.id)?;
+        tx.set(user.id, user.clone());
+        tx.commit();
+        Some(user)
     }
 
     pub fn remove_user(&mut self, id: u64) -> Option<User> {
         self.storage.remove(&id)
     }
+
+    /// Получает все ID пользователей в порядке возрастания
+    pub fn get_all_user_ids(&self) -> Vec<u64> {
+        let mut ids: Vec<u64> = self.storage.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Получает всех пользователей, упорядоченных по ID
+    pub fn list_users(&self) -> Vec<&User> {
+        let mut users: Vec<&User> = self.storage.values().collect();
+        users.sort_unstable_by_key(|user| user.id);
+        users
+    }
 }
 
 fn demonstrate_enum_based_approach() {
@@ -530,12 +859,15 @@ fn demonstrate_enum_based_approach() {
     // Демонстрируем работу с HashMap через enum
     println!("Используем HashMap через enum:");
     let mut enum_repo_hashmap = EnumUserRepository::new(StorageEnum::new_hashmap());
-    enum_repo_hashmap.add_user(user1.clone());
-    enum_repo_hashmap.add_user(user2.clone());
+    enum_repo_hashmap.import_users([user1.clone(), user2.clone()]);
     
     if let Some(user) = enum_repo_hashmap.get_user(100) {
         println!("  Найден пользователь: {:?}", user);
     }
+
+    if let Some(user) = enum_repo_hashmap.find_by_email("enum_user2@example.com") {
+        println!("  Найден по email: {:?}", user);
+    }
     
     // Демонстрируем работу с Vec через enum
     println!("Используем Vec через enum:");
@@ -587,6 +919,32 @@ mod tests {
         assert_eq!(repo.get_user(1), None);
     }
 
+    #[test]
+    fn test_bulk_import_through_dynamic_dispatch() {
+        let mut repo = DynamicUserRepository::new(HashMapStorage::new());
+        repo.import_users(
+            (1..=100).map(|id| User::new(id, format!("user{id}@example.com"), id % 2 == 0)),
+        );
+        assert_eq!(repo.get_all_user_ids(), (1..=100).collect::<Vec<_>>());
+
+        let found: Vec<_> = repo
+            .storage
+            .get_many(&[3, 101, 42])
+            .into_iter()
+            .map(|user| user.map(|user| user.id))
+            .collect();
+        assert_eq!(found, vec![Some(3), None, Some(42)]);
+
+        let removed: Vec<_> = repo
+            .storage
+            .remove_many(&[1, 1, 2])
+            .into_iter()
+            .map(|user| user.map(|user| user.id))
+            .collect();
+        assert_eq!(removed, vec![Some(1), None, Some(2)]);
+        assert_eq!(repo.list_users().len(), 98);
+    }
+
     #[test]
     fn test_static_dispatch_with_vec() {
         let mut repo = StaticUserRepository::new(VecStorage::new());
@@ -632,6 +990,23 @@ mod tests {
         assert_eq!(repo.get_user(1), Some(&user2));
     }
 
+    #[test]
+    fn test_find_by_email_follows_changes() {
+        let mut repo = StaticUserRepository::new(VecStorage::new());
+        repo.import_users([
+            User::new(1, "alice@example.com", true),
+            User::new(2, "bob@example.com", false),
+        ]);
+        assert_eq!(repo.find_by_email("bob@example.com").map(|user| user.id), Some(2));
+
+        repo.update_user(User::new(2, "bob@example.org", false));
+        assert_eq!(repo.find_by_email("bob@example.com"), None);
+        assert_eq!(repo.find_by_email("bob@example.org").map(|user| user.id), Some(2));
+
+        repo.remove_user(1);
+        assert_eq!(repo.find_by_email("alice@example.com"), None);
+    }
+
     #[test]
     fn test_different_storage_implementations() {
         // Тестируем, что обе реализации Storage работают одинаково
@@ -668,6 +1043,52 @@ mod tests {
         assert_eq!(repo.get_user(1), None);
     }
 
+    #[test]
+    fn test_listing_users() {
+        let users = [
+            User::new(3, "c@example.com", true),
+            User::new(1, "a@example.com", false),
+            User::new(2, "b@example.com", true),
+        ];
+
+        let mut dynamic_repo = DynamicUserRepository::new(VecStorage::new());
+        let mut static_repo = StaticUserRepository::new(HashMapStorage::new());
+        let mut enum_repo = EnumUserRepository::new(StorageEnum::new_vec());
+        for user in &users {
+            dynamic_repo.add_user(user.clone());
+            static_repo.add_user(user.clone());
+            enum_repo.add_user(user.clone());
+        }
+        dynamic_repo.remove_user(2);
+        static_repo.remove_user(2);
+        enum_repo.remove_user(2);
+
+        assert_eq!(dynamic_repo.get_all_user_ids(), vec![1, 3]);
+        assert_eq!(static_repo.get_all_user_ids(), vec![1, 3]);
+        assert_eq!(enum_repo.get_all_user_ids(), vec![1, 3]);
+
+        let expected = vec![&users[1], &users[0]];
+        assert_eq!(dynamic_repo.list_users(), expected);
+        assert_eq!(static_repo.list_users(), expected);
+        assert_eq!(enum_repo.list_users(), expected);
+    }
+
+    #[test]
+    fn test_storage_iteration() {
+        let mut storage: Box<dyn Storage<u64, &str>> = Box::new(StorageEnum::new_hashmap());
+        storage.set(1, "one");
+        storage.set(2, "two");
+
+        let mut pairs: Vec<_> = storage.iter().map(|(k, v)| (*k, *v)).collect();
+        pairs.sort_unstable();
+        assert_eq!(pairs, vec![(1, "one"), (2, "two")]);
+
+        let mut values: Vec<_> = storage.values().copied().collect();
+        values.sort_unstable();
+        assert_eq!(values, vec!["one", "two"]);
+        assert_eq!(storage.keys().count(), 2);
+    }
+
     #[test]
     fn test_enum_based_dispatch_with_vec() {
         let mut repo = EnumUserRepository::new(StorageEnum::new_vec());
@@ -682,4 +1103,19 @@ mod tests {
         assert_eq!(removed, Some(user));
         assert_eq!(repo.get_user(1), None);
     }
+
+    #[test]
+    fn test_enum_update_user_is_transactional() {
+        for storage in [StorageEnum::new_hashmap(), StorageEnum::new_vec()] {
+            let mut repo = EnumUserRepository::new(storage);
+            repo.add_user(User::new(1, "old@example.com", true));
+
+            let updated = User::new(1, "new@example.com", true);
+            assert_eq!(repo.update_user(updated.clone()), Some(updated.clone()));
+            assert_eq!(repo.update_user(User::new(2, "b@example.com", true)), None);
+            assert_eq!(repo.get_all_user_ids(), vec![1]);
+            assert_eq!(repo.find_by_email("new@example.com"), Some(&updated));
+            assert_eq!(repo.find_by_email("old@example.com"), None);
+        }
+    }
 }
@@ -0,0 +1,237 @@
+//! Хранилище пользователей, переживающее перезапуск процесса
+//!
+//! Каждое изменение дописывается строкой JSON в конец журнала, а при
+//! открытии журнал проигрывается заново в память. Дописывание в конец
+//! устойчиво к падениям: прерванной может оказаться лишь последняя запись.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Storage, User};
+
+/// Запись журнала изменений
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Record<U> {
+    Set { key: u64, user: U },
+    Remove { key: u64 },
+}
+
+/// Реализация Storage поверх append-only файла
+///
+/// Чтения обслуживаются из памяти, а каждая запись сначала попадает на диск.
+/// Поскольку методы `Storage` не возвращают ошибок, неудачная запись в журнал
+/// приводит к панике: молча потерять изменение хуже, чем упасть.
+#[derive(Debug)]
+pub struct PersistentStorage {
+    path: PathBuf,
+    log: File,
+    data: HashMap<u64, User>,
+}
+
+impl PersistentStorage {
+    /// Открывает журнал, создавая его при отсутствии, и восстанавливает
+    /// из него состояние
+    ///
+    /// Недописанная последняя строка остается от падения во время записи:
+    /// она отбрасывается, а файл обрезается до последней целой записи.
+    /// Испорченная запись в середине журнала считается ошибкой.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let mut log = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+        let mut contents = Vec::new();
+        log.read_to_end(&mut contents)?;
+
+        let mut data = HashMap::new();
+        let mut valid_len = 0;
+        for line in contents.split_inclusive(|byte| *byte == b'\n') {
+            if !line.ends_with(b"\n") {
+                break;
+            }
+            match serde_json::from_slice::<Record<User>>(line)? {
+                Record::Set { key, user } => data.insert(key, user),
+                Record::Remove { key } => data.remove(&key),
+            };
+            valid_len += line.len();
+        }
+        if valid_len < contents.len() {
+            log.set_len(valid_len as u64)?;
+        }
+
+        Ok(Self { path, log, data })
+    }
+
+    /// Переписывает журнал, оставляя в нем лишь актуальные значения
+    ///
+    /// Новый журнал пишется во временный файл и атомарно подменяет старый,
+    /// так что падение посреди сжатия ничего не теряет.
+    pub fn compact(&mut self) -> io::Result<()> {
+        let tmp = self.path.with_extension("compact");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        for (key, user) in &self.data {
+            serde_json::to_writer(&mut writer, &Record::Set { key: *key, user })?;
+            writer.write_all(b"\n")?;
+        }
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+
+        self.log = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+
+    /// Дописывает записи в журнал целыми строками за одну запись на диск
+    fn append(&mut self, records: &[Record<&User>]) {
+        let mut lines = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut lines, record).expect("записи журнала всегда сериализуемы");
+            lines.push(b'\n');
+        }
+        self.log
+            .write_all(&lines)
+            .and_then(|()| self.log.sync_data())
+            .unwrap_or_else(|err| panic!("не удалось записать в {}: {err}", self.path.display()));
+    }
+}
+
+impl Storage<u64, User> for PersistentStorage {
+    fn set(&mut self, key: u64, val: User) {
+        self.append(&[Record::Set { key, user: &val }]);
+        self.data.insert(key, val);
+    }
+
+    fn get(&self, key: &u64) -> Option<&User> {
+        self.data.get(key)
+    }
+
+    fn remove(&mut self, key: &u64) -> Option<User> {
+        if !self.data.contains_key(key) {
+            return None;
+        }
+        self.append(&[Record::Remove { key: *key }]);
+        self.data.remove(key)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&u64, &User)> + '_> {
+        Box::new(self.data.iter())
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &u64> + '_> {
+        Box::new(self.data.keys())
+    }
+
+    fn values(&self) -> Box<dyn Iterator<Item = &User> + '_> {
+        Box::new(self.data.values())
+    }
+
+    fn set_many(&mut self, entries: Vec<(u64, User)>) {
+        // Вся пачка дописывается и сбрасывается на диск однократно
+        let records: Vec<_> = entries
+            .iter()
+            .map(|(key, user)| Record::Set { key: *key, user })
+            .collect();
+        self.append(&records);
+        self.data.extend(entries);
+    }
+
+    fn remove_many(&mut self, keys: &[u64]) -> Vec<Option<User>> {
+        let records: Vec<_> = keys
+            .iter()
+            .filter(|key| self.data.contains_key(key))
+            .map(|key| Record::Remove { key: *key })
+            .collect();
+        if !records.is_empty() {
+            self.append(&records);
+        }
+        keys.iter().map(|key| self.data.remove(key)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DynamicUserRepository;
+
+    #[test]
+    fn test_repository_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.log");
+
+        let mut repo = DynamicUserRepository::new(PersistentStorage::open(&path).unwrap());
+        repo.add_user(User::new(1, "alice@example.com", true));
+        repo.add_user(User::new(2, "bob@example.com", false));
+        repo.update_user(User::new(2, "bob@example.org", true));
+        repo.remove_user(1);
+        repo.import_users([
+            User::new(4, "dave@example.com", true),
+            User::new(5, "erin@example.com", false),
+        ]);
+        drop(repo);
+
+        let mut repo = DynamicUserRepository::new(PersistentStorage::open(&path).unwrap());
+        assert_eq!(repo.get_all_user_ids(), vec![2, 4, 5]);
+        assert_eq!(
+            repo.storage
+                .remove_many(&[4, 5, 4])
+                .iter()
+                .flatten()
+                .count(),
+            2
+        );
+        drop(repo);
+
+        let repo = DynamicUserRepository::new(PersistentStorage::open(&path).unwrap());
+        assert_eq!(repo.get_all_user_ids(), vec![2]);
+        assert_eq!(
+            repo.get_user(2),
+            Some(&User::new(2, "bob@example.org", true))
+        );
+
+        let mut storage = PersistentStorage::open(&path).unwrap();
+        storage.compact().unwrap();
+        storage.set(3, User::new(3, "carol@example.com", true));
+        drop(storage);
+        let log = fs::read_to_string(&path).unwrap();
+        assert_eq!(log.lines().count(), 2);
+        assert_eq!(PersistentStorage::open(&path).unwrap().keys().count(), 2);
+    }
+
+    #[test]
+    fn test_recovers_from_torn_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.log");
+
+        let mut storage = PersistentStorage::open(&path).unwrap();
+        storage.set(1, User::new(1, "alice@example.com", true));
+        drop(storage);
+        // Процесс упал посреди записи второй строки
+        let mut log = OpenOptions::new().append(true).open(&path).unwrap();
+        log.write_all(br#"{"op":"set","key":2,"user":{"id":2,"em"#)
+            .unwrap();
+        drop(log);
+
+        let mut storage = PersistentStorage::open(&path).unwrap();
+        assert_eq!(storage.keys().collect::<Vec<_>>(), vec![&1]);
+        storage.set(3, User::new(3, "carol@example.com", false));
+        drop(storage);
+        let mut ids: Vec<_> = PersistentStorage::open(&path)
+            .unwrap()
+            .keys()
+            .copied()
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 3]);
+
+        // Испорченная целая запись не молча теряется, а возвращается ошибкой
+        fs::write(&path, "not json\n").unwrap();
+        let err = PersistentStorage::open(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,185 @@
+//! Транзакции поверх Storage
+//!
+//! Транзакция копит изменения в журнале, не трогая хранилище, и применяет
+//! их все разом при `commit()`. Откат сводится к тому, чтобы просто
+//! выбросить журнал.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::{HashMapStorage, Storage, StorageEnum, VecStorage};
+
+/// Отложенное изменение записи
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Change<K, V> {
+    Set(K, V),
+    Remove(K),
+}
+
+impl<K, V> Change<K, V> {
+    pub(crate) fn key(&self) -> &K {
+        match self {
+            Change::Set(key, _) | Change::Remove(key) => key,
+        }
+    }
+}
+
+/// Хранилище, умеющее применять изменения транзакциями
+pub(crate) trait TransactionalStorage<K, V>: Storage<K, V> {
+    /// Начинает транзакцию, изменения которой не видны до `commit()`
+    fn begin(&mut self) -> Transaction<'_, K, V, Self>
+    where
+        Self: Sized,
+    {
+        Transaction {
+            storage: self,
+            log: Vec::new(),
+        }
+    }
+
+    /// Применяет журнал изменений целиком
+    fn apply(&mut self, log: Vec<Change<K, V>>);
+}
+
+impl<K, V> TransactionalStorage<K, V> for HashMapStorage<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    fn apply(&mut self, log: Vec<Change<K, V>>) {
+        // Сворачиваем журнал до последнего изменения каждого ключа, чтобы
+        // применить его без промежуточных состояний
+        let mut latest = HashMap::with_capacity(log.len());
+        for change in log {
+            latest.insert(change.key().clone(), change);
+        }
+        for (_, change) in latest {
+            match change {
+                Change::Set(key, val) => {
+                    self.data.insert(key, val);
+                }
+                Change::Remove(key) => {
+                    self.data.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+impl<V> TransactionalStorage<u64, V> for VecStorage<V>
+where
+    V: Clone,
+{
+    fn apply(&mut self, log: Vec<Change<u64, V>>) {
+        // Изменения в памяти не могут сорваться на полпути, поэтому журнал
+        // применяется по порядку
+        for change in log {
+            match change {
+                Change::Set(key, val) => self.set(key, val),
+                Change::Remove(key) => {
+                    self.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+impl<V> TransactionalStorage<u64, V> for StorageEnum<V>
+where
+    V: Clone,
+{
+    fn apply(&mut self, log: Vec<Change<u64, V>>) {
+        match self {
+            StorageEnum::HashMap(storage) => storage.apply(log),
+            StorageEnum::Vec(storage) => storage.apply(log),
+        }
+    }
+}
+
+/// Незавершенная транзакция над хранилищем `S`
+///
+/// Чтения через транзакцию видят ее собственные изменения. Транзакция,
+/// выброшенная без `commit()`, откатывается.
+#[must_use = "изменения транзакции теряются без commit()"]
+pub(crate) struct Transaction<'a, K, V, S> {
+    storage: &'a mut S,
+    log: Vec<Change<K, V>>,
+}
+
+impl<K, V, S> Transaction<'_, K, V, S>
+where
+    K: PartialEq,
+    S: TransactionalStorage<K, V>,
+{
+    /// Откладывает установку значения
+    pub fn set(&mut self, key: K, val: V) {
+        self.log.push(Change::Set(key, val));
+    }
+
+    /// Откладывает удаление, возвращая, было ли что удалять
+    pub fn remove(&mut self, key: K) -> bool {
+        let existed = self.get(&key).is_some();
+        self.log.push(Change::Remove(key));
+        existed
+    }
+
+    /// Получает значение с учетом изменений транзакции
+    pub fn get(&self, key: &K) -> Option<&V> {
+        match self.log.iter().rev().find(|change| change.key() == key) {
+            Some(Change::Set(_, val)) => Some(val),
+            Some(Change::Remove(_)) => None,
+            None => self.storage.get(key),
+        }
+    }
+
+    /// Применяет все изменения транзакции
+    pub fn commit(self) {
+        self.storage.apply(self.log);
+    }
+
+    /// Отменяет все изменения транзакции
+    pub fn rollback(self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::User;
+
+    #[test]
+    fn test_commit_applies_buffered_changes() {
+        let mut storage = HashMapStorage::new();
+        storage.set(1, User::new(1, "alice@example.com", true));
+        storage.set(2, User::new(2, "bob@example.com", true));
+
+        let mut tx = storage.begin();
+        tx.set(1, User::new(1, "alice@example.org", true));
+        assert!(tx.remove(2));
+        assert!(!tx.remove(3));
+        tx.set(3, User::new(3, "carol@example.com", false));
+        assert_eq!(tx.get(&1).unwrap().email, "alice@example.org");
+        assert_eq!(tx.get(&2), None);
+        tx.commit();
+
+        let mut ids: Vec<_> = storage.keys().copied().collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 3]);
+        assert_eq!(storage.get(&1).unwrap().email, "alice@example.org");
+    }
+
+    #[test]
+    fn test_rollback_leaves_storage_untouched() {
+        let mut storage = HashMapStorage::new();
+        storage.set(1, "alice");
+
+        let mut tx = storage.begin();
+        tx.remove(1);
+        tx.set(2, "bob");
+        tx.rollback();
+
+        let mut tx = storage.begin();
+        tx.set(3, "carol");
+        drop(tx);
+
+        assert_eq!(storage.iter().collect::<Vec<_>>(), vec![(&1, &"alice")]);
+    }
+}
@@ -0,0 +1,191 @@
+//! Хранилище с ограниченным временем жизни записей
+//!
+//! `TtlStorage` — декоратор над любым `Storage`: он не хранит значения сам,
+//! а лишь отслеживает сроки их жизни и скрывает просроченные записи.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use common::{Clock, SystemClock};
+
+use super::{HashMapStorage, Storage};
+
+/// Декоратор, добавляющий записям любого `Storage` срок жизни
+///
+/// Просроченные записи сразу перестают быть видны при чтении, но так как
+/// `get` и итераторы принимают `&self`, физически они удаляются лениво:
+/// при следующем изменяющем обращении к ключу (`set`/`remove`) или явным
+/// вызовом `purge_expired()`.
+///
+/// Записи, попавшие во внутреннее хранилище в обход декоратора,
+/// не имеют срока жизни и никогда не истекают.
+#[derive(Debug)]
+pub struct TtlStorage<K, V, S = HashMapStorage<K, V>>
+where
+    K: Hash + Eq + Clone,
+{
+    inner: S,
+    ttl: Duration,
+    deadlines: HashMap<K, Instant>,
+    clock: Arc<dyn Clock>,
+    _value: PhantomData<V>,
+}
+
+impl<K, V, S> TtlStorage<K, V, S>
+where
+    K: Hash + Eq + Clone,
+    S: Storage<K, V>,
+{
+    /// Оборачивает хранилище, задавая срок жизни записей по умолчанию
+    pub fn new(inner: S, ttl: Duration) -> Self {
+        Self::with_clock(inner, ttl, Arc::new(SystemClock))
+    }
+
+    /// То же, что `new`, но время берется из переданных часов,
+    /// например из `MockClock` в тестах
+    pub fn with_clock(inner: S, ttl: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner,
+            ttl,
+            deadlines: HashMap::new(),
+            clock,
+            _value: PhantomData,
+        }
+    }
+
+    /// Устанавливает значение с собственным сроком жизни
+    pub fn set_with_ttl(&mut self, key: K, val: V, ttl: Duration) {
+        self.deadlines.insert(key.clone(), self.clock.now() + ttl);
+        self.inner.set(key, val);
+    }
+
+    /// Возвращает оставшееся время жизни записи
+    pub fn time_to_live(&self, key: &K) -> Option<Duration> {
+        let deadline = self.deadlines.get(key)?;
+        deadline.checked_duration_since(self.clock.now())
+    }
+
+    /// Удаляет все просроченные записи и возвращает их количество
+    pub fn purge_expired(&mut self) -> usize {
+        let now = self.clock.now();
+        let expired: Vec<K> = self
+            .deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            self.deadlines.remove(key);
+            self.inner.remove(key);
+        }
+        expired.len()
+    }
+
+    /// Проверяет, истек ли срок жизни записи к моменту `now`
+    fn is_expired(&self, key: &K, now: Instant) -> bool {
+        self.deadlines
+            .get(key)
+            .is_some_and(|deadline| *deadline <= now)
+    }
+}
+
+impl<K, V, S> Storage<K, V> for TtlStorage<K, V, S>
+where
+    K: Hash + Eq + Clone,
+    S: Storage<K, V>,
+{
+    fn set(&mut self, key: K, val: V) {
+        self.set_with_ttl(key, val, self.ttl);
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        if self.is_expired(key, self.clock.now()) {
+            return None;
+        }
+        self.inner.get(key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        // Просроченная запись вытесняется, но наружу не возвращается
+        let expired = self.is_expired(key, self.clock.now());
+        self.deadlines.remove(key);
+        self.inner.remove(key).filter(|_| !expired)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, &V)> + '_> {
+        let now = self.clock.now();
+        Box::new(
+            self.inner
+                .iter()
+                .filter(move |(key, _)| !self.is_expired(key, now)),
+        )
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &K> + '_> {
+        Box::new(self.iter().map(|(key, _)| key))
+    }
+
+    fn values(&self) -> Box<dyn Iterator<Item = &V> + '_> {
+        Box::new(self.iter().map(|(_, val)| val))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::MockClock;
+
+    use super::*;
+    use crate::VecStorage;
+
+    #[test]
+    fn test_expired_entries_are_hidden_and_purged() {
+        let mut storage = TtlStorage::new(HashMapStorage::new(), Duration::from_secs(3600));
+        storage.set(1, "long-lived");
+        storage.set_with_ttl(2, "expired", Duration::ZERO);
+        storage.set_with_ttl(3, "expired too", Duration::ZERO);
+
+        assert_eq!(storage.get(&1), Some(&"long-lived"));
+        assert_eq!(storage.get(&2), None);
+        assert_eq!(storage.keys().collect::<Vec<_>>(), vec![&1]);
+        assert!(storage.time_to_live(&1).is_some());
+
+        // Просроченная запись вытесняется при удалении, но не возвращается
+        assert_eq!(storage.remove(&2), None);
+        assert_eq!(storage.purge_expired(), 1);
+        assert_eq!(storage.inner.keys().collect::<Vec<_>>(), vec![&1]);
+        assert_eq!(storage.remove(&1), Some("long-lived"));
+    }
+
+    #[test]
+    fn test_refreshing_expired_entry() {
+        let mut storage = TtlStorage::new(VecStorage::new(), Duration::ZERO);
+        storage.set(7, "token");
+        assert_eq!(storage.get(&7), None);
+
+        storage.set_with_ttl(7, "fresh token", Duration::from_secs(60));
+        assert_eq!(storage.get(&7), Some(&"fresh token"));
+        assert_eq!(storage.values().count(), 1);
+    }
+
+    #[test]
+    fn test_expiry_follows_clock() {
+        let clock = MockClock::new();
+        let mut storage = TtlStorage::with_clock(
+            HashMapStorage::new(),
+            Duration::from_secs(60),
+            Arc::new(clock.clone()),
+        );
+        storage.set("session", 1);
+
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(storage.get(&"session"), Some(&1));
+        assert_eq!(storage.time_to_live(&"session"), Some(Duration::from_secs(1)));
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(storage.get(&"session"), None);
+        assert_eq!(storage.purge_expired(), 1);
+    }
+}
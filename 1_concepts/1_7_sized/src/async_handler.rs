@@ -0,0 +1,137 @@
+//! Асинхронный вариант обработки команд
+//!
+//! `async fn` в трейтах пока не совместимы с `dyn`, поэтому трейты объявлены
+//! через `async_trait`: так `dyn AsyncUserRepository` остается возможным
+//! контекстом, а будущие значения — `Send`, как того требуют обработчики axum.
+
+use async_trait::async_trait;
+
+use super::{
+    Command, CommandHandler, CreateUser, User, UserDomainEvent, UserError, UserRepository,
+};
+
+/// Асинхронное хранилище пользователей
+#[async_trait]
+pub trait AsyncUserRepository: Send + Sync {
+    async fn save_user(&mut self, user: User) -> Result<(), UserError>;
+    async fn find_user_by_id(&self, id: u64) -> Result<Option<User>, UserError>;
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, UserError>;
+    async fn delete_user(&mut self, id: u64) -> Result<Option<User>, UserError>;
+    async fn next_id(&mut self) -> Result<u64, UserError>;
+}
+
+/// Любое синхронное хранилище годится и как асинхронное
+#[async_trait]
+impl<R: UserRepository + Sync> AsyncUserRepository for R {
+    async fn save_user(&mut self, user: User) -> Result<(), UserError> {
+        UserRepository::save_user(self, user)
+    }
+
+    async fn find_user_by_id(&self, id: u64) -> Result<Option<User>, UserError> {
+        UserRepository::find_user_by_id(self, id)
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, UserError> {
+        UserRepository::find_user_by_email(self, email)
+    }
+
+    async fn delete_user(&mut self, id: u64) -> Result<Option<User>, UserError> {
+        UserRepository::delete_user(self, id)
+    }
+
+    async fn next_id(&mut self) -> Result<u64, UserError> {
+        UserRepository::next_id(self)
+    }
+}
+
+/// Асинхронный аналог `CommandHandler`
+///
+/// Как и там, `Context` может быть trait object, например
+/// `dyn AsyncUserRepository`.
+#[async_trait]
+pub trait AsyncCommandHandler<C: Command + Sync> {
+    type Context: ?Sized + Send;
+    type Result;
+
+    async fn handle_command(&self, cmd: &C, ctx: &mut Self::Context) -> Self::Result;
+}
+
+#[async_trait]
+impl AsyncCommandHandler<CreateUser> for User {
+    type Context = dyn AsyncUserRepository;
+    type Result = Result<Vec<UserDomainEvent>, UserError>;
+
+    async fn handle_command(&self, cmd: &CreateUser, ctx: &mut Self::Context) -> Self::Result {
+        if ctx.find_user_by_email(&cmd.email).await?.is_some() {
+            return Err(UserError::UserAlreadyExists(cmd.email.to_string()));
+        }
+        if !cmd.email.contains('@') {
+            return Err(UserError::InvalidEmail(cmd.email.to_string()));
+        }
+        let new_user = User::new(ctx.next_id().await?, cmd.email.clone(), cmd.activated);
+        let event = UserDomainEvent::UserCreated {
+            id: new_user.id,
+            email: new_user.email.clone(),
+            activated: new_user.activated,
+        };
+        ctx.save_user(new_user).await?;
+        Ok(vec![event])
+    }
+}
+
+/// Адаптер, позволяющий использовать синхронный обработчик там, где ждут
+/// асинхронный
+///
+/// Обработчик выполняется прямо внутри `poll`, поэтому он не должен
+/// блокироваться надолго.
+pub struct FromSync<H>(pub H);
+
+#[async_trait]
+impl<C, H> AsyncCommandHandler<C> for FromSync<H>
+where
+    C: Command + Sync,
+    H: CommandHandler<C> + Sync,
+    H::Context: Send,
+    H::Result: Send,
+{
+    type Context = H::Context;
+    type Result = H::Result;
+
+    async fn handle_command(&self, cmd: &C, ctx: &mut Self::Context) -> Self::Result {
+        self.0.handle_command(cmd, ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::ids::Sequential;
+    use crate::{ActivateUser, MockUserRepository};
+
+    #[test]
+    fn test_async_and_adapted_handlers_share_repository() {
+        let admin = User::new(1, "admin@example.com", true);
+        let mut repo = MockUserRepository::with_ids(Sequential::new(2));
+        let ctx: &mut dyn AsyncUserRepository = &mut repo;
+
+        let cmd = CreateUser::new("alice@example.com", false);
+        let created = block_on(AsyncCommandHandler::handle_command(&admin, &cmd, ctx));
+        assert_eq!(created.map(|events| events.len()), Ok(1));
+        assert_eq!(
+            block_on(AsyncCommandHandler::handle_command(&admin, &cmd, ctx)),
+            Err(UserError::UserAlreadyExists(
+                "alice@example.com".to_string()
+            ))
+        );
+
+        let activate = FromSync(admin);
+        assert_eq!(
+            block_on(activate.handle_command(&ActivateUser { id: 2 }, &mut repo)),
+            Ok(vec![UserDomainEvent::UserActivated { id: 2 }])
+        );
+        let alice = block_on(AsyncUserRepository::find_user_by_id(&repo, 2)).unwrap();
+        assert!(alice.unwrap().activated);
+    }
+}
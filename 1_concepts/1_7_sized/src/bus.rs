@@ -0,0 +1,226 @@
+//! Шина команд с регистрацией обработчиков во время выполнения
+//!
+//! Обработчики хранятся стертыми до
+//! `dyn Fn(&dyn Any, &mut dyn UserRepository, &mut dyn EventSink)` и ищутся по
+//! `TypeId` команды, поэтому новая команда подключается одним вызовом
+//! `register`, без правки кода диспетчеризации.
+
+use std::any::{Any, TypeId, type_name};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::events::{EventSink, UserDomainEvent};
+use super::{Command, CommandHandler, UserRepository};
+
+/// Обработчик со стертыми типами команды и результата
+type ErasedHandler = Box<dyn Fn(&dyn Any, &mut Repository, &mut dyn EventSink) -> Box<dyn Any>>;
+
+/// Обработчик вместе с типом его результата, который сверяется до вызова:
+/// иначе ошибка о неверном `R` приходила бы уже после изменений
+/// в репозитории и опубликованных событий
+struct Registration {
+    result: TypeId,
+    handler: ErasedHandler,
+}
+
+/// Репозиторий, которым владеет шина; `'static` совпадает с
+/// `CommandHandler::Context = dyn UserRepository`
+type Repository = dyn UserRepository + 'static;
+
+/// Ошибки диспетчеризации команды
+#[derive(Debug, Clone, PartialEq)]
+pub enum BusError {
+    /// Для команды не зарегистрирован обработчик
+    NoHandler(&'static str),
+    /// Обработчик вернул результат другого типа
+    UnexpectedResult {
+        command: &'static str,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BusError::NoHandler(command) => {
+                write!(f, "Нет обработчика для команды {}", command)
+            }
+            BusError::UnexpectedResult { command, expected } => {
+                write!(f, "Обработчик {} вернул не {}", command, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BusError {}
+
+/// Шина команд, владеющая репозиторием
+///
+/// Каждому типу команды соответствует не более одного обработчика:
+/// повторная регистрация заменяет предыдущий.
+pub struct CommandBus {
+    repository: Box<dyn UserRepository>,
+    handlers: HashMap<TypeId, Registration>,
+}
+
+impl CommandBus {
+    /// Создает шину без обработчиков поверх указанного репозитория
+    pub fn new(repository: impl UserRepository + 'static) -> Self {
+        Self {
+            repository: Box::new(repository),
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Репозиторий, над которым выполняются команды
+    ///
+    /// `'static` явно, иначе он не подойдет как `Context` обработчиков
+    /// запросов, объявленный как `dyn UserRepository`.
+    pub fn repository(&self) -> &(dyn UserRepository + 'static) {
+        &*self.repository
+    }
+
+    /// Регистрирует замыкание как обработчик команд типа `C`
+    pub fn register<C, R, F>(&mut self, handler: F)
+    where
+        C: Command + 'static,
+        R: 'static,
+        F: Fn(&C, &mut Repository, &mut dyn EventSink) -> R + 'static,
+    {
+        let erased: ErasedHandler = Box::new(move |cmd, repository, events| {
+            let cmd = cmd
+                .downcast_ref::<C>()
+                .expect("обработчик найден по TypeId команды");
+            Box::new(handler(cmd, repository, events))
+        });
+        self.handlers.insert(
+            TypeId::of::<C>(),
+            Registration {
+                result: TypeId::of::<R>(),
+                handler: erased,
+            },
+        );
+    }
+
+    /// Регистрирует `CommandHandler`, работающий с `dyn UserRepository`
+    ///
+    /// События успешно обработанной команды передаются в `EventSink`
+    /// и остаются в результате, возвращаемом вызывающему.
+    pub fn register_handler<C, H, E>(&mut self, handler: H)
+    where
+        C: Command + 'static,
+        H: CommandHandler<
+                C,
+                Context = dyn UserRepository,
+                Result = Result<Vec<UserDomainEvent>, E>,
+            > + 'static,
+        E: 'static,
+    {
+        self.register(move |cmd: &C, repository, events| {
+            let result = handler.handle_command(cmd, repository);
+            for event in result.iter().flatten() {
+                events.publish(event);
+            }
+            result
+        });
+    }
+
+    /// Выполняет команду ее обработчиком и возвращает его результат
+    ///
+    /// Тип результата `R` обычно выводится из контекста вызова; если он
+    /// не совпал с тем, что вернул обработчик, возвращается
+    /// `BusError::UnexpectedResult`, а обработчик не вызывается. События
+    /// обработчика уходят в `events`.
+    pub fn dispatch<C, R>(&mut self, cmd: C, events: &mut dyn EventSink) -> Result<R, BusError>
+    where
+        C: Command + 'static,
+        R: 'static,
+    {
+        let registration = self
+            .handlers
+            .get(&TypeId::of::<C>())
+            .ok_or(BusError::NoHandler(cmd.command_type()))?;
+        let unexpected = || BusError::UnexpectedResult {
+            command: cmd.command_type(),
+            expected: type_name::<R>(),
+        };
+        if registration.result != TypeId::of::<R>() {
+            return Err(unexpected());
+        }
+        (registration.handler)(&cmd, &mut *self.repository, events)
+            .downcast::<R>()
+            .map(|result| *result)
+            .map_err(|_| unexpected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CreateUser, MockUserRepository, User, UserError};
+
+    struct CountUsers;
+
+    impl Command for CountUsers {
+        fn command_type(&self) -> &'static str {
+            "CountUsers"
+        }
+    }
+
+    #[test]
+    fn test_dispatches_to_registered_handlers() {
+        let mut bus = CommandBus::new(MockUserRepository::new());
+        bus.register_handler::<CreateUser, _, _>(User::new(0, "system@example.com", true));
+
+        let mut events = Vec::new();
+        let created: Result<Vec<UserDomainEvent>, UserError> = bus
+            .dispatch(CreateUser::new("alice@example.com", true), &mut events)
+            .unwrap();
+        assert_eq!(created.as_deref(), Ok(&events[..]));
+        // Неверный тип результата отклоняется до выполнения команды
+        assert!(matches!(
+            bus.dispatch::<_, usize>(CreateUser::new("bob@example.com", true), &mut events),
+            Err(BusError::UnexpectedResult { .. })
+        ));
+        assert_eq!(events.len(), 1);
+        assert!(
+            bus.repository()
+                .find_user_by_email("bob@example.com")
+                .unwrap()
+                .is_none()
+        );
+        assert!(matches!(
+            &events[..],
+            [UserDomainEvent::UserCreated { id: 1, .. }]
+        ));
+        let duplicate: Result<Vec<UserDomainEvent>, UserError> = bus
+            .dispatch(CreateUser::new("alice@example.com", true), &mut events)
+            .unwrap();
+        assert!(duplicate.is_err());
+        assert_eq!(events.len(), 1, "ошибка не порождает событий");
+        assert!(
+            bus.repository()
+                .find_user_by_email("alice@example.com")
+                .unwrap()
+                .is_some()
+        );
+
+        assert_eq!(
+            bus.dispatch::<_, usize>(CountUsers, &mut events),
+            Err(BusError::NoHandler("CountUsers"))
+        );
+
+        // Новая команда подключается без правки dispatch
+        bus.register(|_: &CountUsers, repository, _events| {
+            usize::from(repository.find_user_by_id(1).unwrap().is_some())
+        });
+        assert_eq!(bus.dispatch(CountUsers, &mut events), Ok(1usize));
+        assert!(matches!(
+            bus.dispatch::<_, String>(CountUsers, &mut events),
+            Err(BusError::UnexpectedResult {
+                command: "CountUsers",
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,91 @@
+//! Доменные события, порождаемые обработчиками команд
+//!
+//! Обработчик изменяет хранилище и возвращает список того, что произошло.
+//! Куда отправить события, решает вызывающий: шина команд передает их в
+//! `EventSink`, который может быть журналом аудита или хранилищем событий.
+
+use std::borrow::Cow;
+use std::fmt;
+
+/// Изменение состояния пользователя
+///
+/// Варианты названы полностью, как события записываются в журнал, поэтому
+/// общий префикс `User` здесь намеренный.
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum UserDomainEvent {
+    UserCreated {
+        id: u64,
+        email: Cow<'static, str>,
+        activated: bool,
+    },
+    UserUpdated {
+        id: u64,
+        email: Cow<'static, str>,
+    },
+    UserActivated {
+        id: u64,
+    },
+    UserDeleted {
+        id: u64,
+    },
+}
+
+impl UserDomainEvent {
+    /// ID пользователя, с которым произошло событие
+    pub fn user_id(&self) -> u64 {
+        match self {
+            UserDomainEvent::UserCreated { id, .. }
+            | UserDomainEvent::UserUpdated { id, .. }
+            | UserDomainEvent::UserActivated { id }
+            | UserDomainEvent::UserDeleted { id } => *id,
+        }
+    }
+}
+
+impl fmt::Display for UserDomainEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UserDomainEvent::UserCreated {
+                id,
+                email,
+                activated,
+            } => write!(
+                f,
+                "создан пользователь {} <{}>, активирован: {}",
+                id, email, activated
+            ),
+            UserDomainEvent::UserUpdated { id, email } => {
+                write!(f, "пользователь {} сменил email на <{}>", id, email)
+            }
+            UserDomainEvent::UserActivated { id } => {
+                write!(f, "пользователь {} активирован", id)
+            }
+            UserDomainEvent::UserDeleted { id } => write!(f, "пользователь {} удален", id),
+        }
+    }
+}
+
+/// Получатель доменных событий
+///
+/// Объектно-безопасен, поэтому передается как `&mut dyn EventSink`.
+pub trait EventSink {
+    /// Принимает очередное событие; события приходят в порядке возникновения
+    fn publish(&mut self, event: &UserDomainEvent);
+}
+
+/// Накопление событий, например, для проверки в тестах
+impl EventSink for Vec<UserDomainEvent> {
+    fn publish(&mut self, event: &UserDomainEvent) {
+        self.push(event.clone());
+    }
+}
+
+/// Журнал аудита, печатающий каждое событие
+pub struct AuditLog;
+
+impl EventSink for AuditLog {
+    fn publish(&mut self, event: &UserDomainEvent) {
+        println!("  [аудит] {}", event);
+    }
+}
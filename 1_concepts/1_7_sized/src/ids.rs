@@ -0,0 +1,130 @@
+//! Стратегии генерации ID пользователей
+//!
+//! Обработчик не придумывает ID сам, а берет очередной у контекста через
+//! `UserRepository::next_id`. Хранилище, в свою очередь, делегирует
+//! `IdGenerator`, так что стратегию можно сменить, не трогая обработчики.
+
+use std::collections::hash_map::RandomState;
+use std::fmt::Debug;
+use std::hash::BuildHasher;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Источник ID для новых пользователей
+///
+/// Генератор лишь предлагает ID; проверить, что он не занят, должно
+/// хранилище.
+pub trait IdGenerator: Debug + Send + Sync {
+    fn next_id(&mut self) -> u64;
+}
+
+/// ID по порядку, начиная с заданного
+#[derive(Debug, Clone)]
+pub struct Sequential {
+    next: u64,
+}
+
+impl Sequential {
+    pub fn new(start: u64) -> Self {
+        Self { next: start }
+    }
+}
+
+impl IdGenerator for Sequential {
+    fn next_id(&mut self) -> u64 {
+        let id = self.next;
+        self.next += 1;
+        id
+    }
+}
+
+/// Псевдослучайные ID, не раскрывающие число пользователей
+///
+/// Последовательность определяется начальным значением, поэтому
+/// `RandomIds::seeded` дает одни и те же ID в каждом запуске тестов.
+#[derive(Debug, Clone)]
+pub struct RandomIds {
+    state: u64,
+}
+
+impl RandomIds {
+    /// Генератор со случайным начальным значением
+    pub fn new() -> Self {
+        Self::seeded(RandomState::new().hash_one(SystemTime::now()))
+    }
+
+    /// Детерминированный генератор для тестов
+    pub fn seeded(seed: u64) -> Self {
+        Self { state: seed }
+    }
+}
+
+impl Default for RandomIds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IdGenerator for RandomIds {
+    /// Шаг SplitMix64
+    fn next_id(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// ID, возрастающие со временем создания
+///
+/// Старшие биты — миллисекунды с начала эпохи Unix, младшие
+/// `SEQUENCE_BITS` — номер в пределах миллисекунды. Если часы отстали,
+/// ID все равно продолжают расти.
+#[derive(Debug, Clone, Default)]
+pub struct TimeOrdered {
+    last: u64,
+}
+
+impl TimeOrdered {
+    pub const SEQUENCE_BITS: u32 = 12;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl IdGenerator for TimeOrdered {
+    fn next_id(&mut self) -> u64 {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        self.last = (millis << Self::SEQUENCE_BITS).max(self.last + 1);
+        self.last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generators_yield_distinct_ids() {
+        let mut sequential = Sequential::new(5);
+        let ids: Vec<u64> = (0..3).map(|_| sequential.next_id()).collect();
+        assert_eq!(ids, [5, 6, 7]);
+
+        let take = |generator: &mut dyn IdGenerator| -> Vec<u64> {
+            (0..100).map(|_| generator.next_id()).collect()
+        };
+        let seeded = take(&mut RandomIds::seeded(42));
+        assert_eq!(seeded, take(&mut RandomIds::seeded(42)));
+        assert_ne!(seeded, take(&mut RandomIds::seeded(43)));
+        let mut unique = seeded.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), seeded.len());
+
+        let ordered = take(&mut TimeOrdered::new());
+        assert!(ordered.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
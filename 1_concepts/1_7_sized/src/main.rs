@@ -1,5 +1,20 @@
 use std::borrow::Cow;
 
+mod async_handler;
+mod bus;
+mod events;
+mod ids;
+mod pipeline;
+mod query;
+mod shared;
+
+use bus::CommandBus;
+use events::{AuditLog, UserDomainEvent};
+use ids::{IdGenerator, RandomIds, Sequential, TimeOrdered};
+use pipeline::{Logging, Pipeline, Retry, Validate};
+use query::{GetUserByEmail, GetUserById, ListActivatedUsers, Query, QueryHandler};
+use shared::SharedRepository;
+
 // ============================================================================
 // БАЗОВЫЕ СТРУКТУРЫ И ТРЕЙТЫ
 // ============================================================================
@@ -40,6 +55,58 @@ impl Command for CreateUser {
     }
 }
 
+/// Команда смены email существующего пользователя
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateUser {
+    pub id: u64,
+    pub email: Cow<'static, str>,
+}
+
+impl UpdateUser {
+    /// Создает новую команду смены email
+    pub fn new(id: u64, email: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            id,
+            email: email.into(),
+        }
+    }
+}
+
+impl Command for UpdateUser {
+    fn command_type(&self) -> &'static str {
+        "UpdateUser"
+    }
+}
+
+/// Команда удаления пользователя
+///
+/// Удаление идемпотентно: удалить отсутствующего пользователя не ошибка.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeleteUser {
+    pub id: u64,
+}
+
+impl Command for DeleteUser {
+    fn command_type(&self) -> &'static str {
+        "DeleteUser"
+    }
+}
+
+/// Команда активации пользователя
+///
+/// Активировать можно только существующего пользователя, повторная
+/// активация ничего не меняет.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ActivateUser {
+    pub id: u64,
+}
+
+impl Command for ActivateUser {
+    fn command_type(&self) -> &'static str {
+        "ActivateUser"
+    }
+}
+
 /// Структура пользователя
 /// 
 /// Использует Cow<'static, str> для эффективного хранения строк,
@@ -116,7 +183,10 @@ impl std::error::Error for UserError {}
 /// 
 /// Без ?Sized bound все типы параметров имеют неявную границу Sized,
 /// что запрещает использование trait objects.
-pub trait UserRepository {
+///
+/// `Send` нужен, чтобы `&mut dyn UserRepository` можно было держать внутри
+/// `Send`-будущих (см. `async_handler::FromSync`).
+pub trait UserRepository: Send {
     /// Сохраняет пользователя в хранилище
     /// 
     /// # Аргументы
@@ -152,6 +222,18 @@ pub trait UserRepository {
     /// # Возвращает
     /// * `Result<Option<User>, UserError>` - удаленный пользователь или None
     fn delete_user(&mut self, id: u64) -> Result<Option<User>, UserError>;
+
+    /// Возвращает всех пользователей
+    ///
+    /// # Возвращает
+    /// * `Result<Vec<User>, UserError>` - пользователи в порядке возрастания ID
+    fn list_users(&self) -> Result<Vec<User>, UserError>;
+
+    /// Выдает ID для нового пользователя
+    ///
+    /// # Возвращает
+    /// * `Result<u64, UserError>` - ID, еще не занятый в хранилище
+    fn next_id(&mut self) -> Result<u64, UserError>;
 }
 
 // ============================================================================
@@ -168,7 +250,7 @@ pub trait UserRepository {
 /// ```rust
 /// impl CommandHandler<CreateUser> for User {
 ///     type Context = dyn UserRepository;
-///     type Result = Result<(), UserError>;
+///     type Result = Result<Vec<UserDomainEvent>, UserError>;
 ///     
 ///     fn handle_command(&self, cmd: &CreateUser, ctx: &Self::Context) -> Self::Result {
 ///         // Обработка команды создания пользователя
@@ -215,8 +297,8 @@ impl CommandHandler<CreateUser> for User {
     /// не имеют фиксированного размера во время компиляции.
     type Context = dyn UserRepository;
     
-    /// Результат обработки команды
-    type Result = Result<(), UserError>;
+    /// Результат обработки команды: события, описывающие изменения
+    type Result = Result<Vec<UserDomainEvent>, UserError>;
     
     /// Обрабатывает команду создания пользователя
     /// 
@@ -225,7 +307,7 @@ impl CommandHandler<CreateUser> for User {
     /// * `ctx` - репозиторий пользователей (может быть любая реализация)
     /// 
     /// # Возвращает
-    /// * `Result<(), UserError>` - результат операции
+    /// * `Result<Vec<UserDomainEvent>, UserError>` - событие создания или ошибка
     /// 
     /// # Логика
     /// 1. Проверяем, что пользователь с таким email не существует
@@ -242,18 +324,89 @@ impl CommandHandler<CreateUser> for User {
             return Err(UserError::InvalidEmail(cmd.email.to_string()));
         }
         
-        // Создаем нового пользователя
-        // В реальной системе ID генерировался бы по-другому
-        let new_user = User::new(
-            self.id + 1, // Простая логика генерации ID
-            cmd.email.clone(),
-            cmd.activated,
-        );
+        // Создаем нового пользователя; ID выдает контекст
+        let new_user = User::new(ctx.next_id()?, cmd.email.clone(), cmd.activated);
         
         // Сохраняем пользователя в репозитории
+        let event = UserDomainEvent::UserCreated {
+            id: new_user.id,
+            email: new_user.email.clone(),
+            activated: new_user.activated,
+        };
         ctx.save_user(new_user)?;
         
-        Ok(())
+        Ok(vec![event])
+    }
+}
+
+/// Заменяет сохраненного пользователя с тем же ID
+///
+/// В `UserRepository` нет обновления на месте, поэтому пользователь
+/// пересохраняется, а при ошибке сохранения возвращается прежняя версия.
+fn replace_user(ctx: &mut dyn UserRepository, user: User) -> Result<(), UserError> {
+    let old = ctx
+        .delete_user(user.id)?
+        .ok_or(UserError::UserNotFound(user.id))?;
+    if let Err(e) = ctx.save_user(user) {
+        ctx.save_user(old)?;
+        return Err(e);
+    }
+    Ok(())
+}
+
+impl CommandHandler<UpdateUser> for User {
+    type Context = dyn UserRepository;
+    type Result = Result<Vec<UserDomainEvent>, UserError>;
+
+    /// Меняет email, если он корректен и не занят другим пользователем
+    fn handle_command(&self, cmd: &UpdateUser, ctx: &mut Self::Context) -> Self::Result {
+        let user = ctx
+            .find_user_by_id(cmd.id)?
+            .ok_or(UserError::UserNotFound(cmd.id))?;
+
+        if !cmd.email.contains('@') {
+            return Err(UserError::InvalidEmail(cmd.email.to_string()));
+        }
+        let taken = ctx.find_user_by_email(&cmd.email)?;
+        if taken.is_some_and(|other| other.id != cmd.id) {
+            return Err(UserError::UserAlreadyExists(cmd.email.to_string()));
+        }
+
+        replace_user(ctx, User { email: cmd.email.clone(), ..user })?;
+        Ok(vec![UserDomainEvent::UserUpdated {
+            id: cmd.id,
+            email: cmd.email.clone(),
+        }])
+    }
+}
+
+impl CommandHandler<DeleteUser> for User {
+    type Context = dyn UserRepository;
+    /// Без событий, если пользователя уже не было
+    type Result = Result<Vec<UserDomainEvent>, UserError>;
+
+    fn handle_command(&self, cmd: &DeleteUser, ctx: &mut Self::Context) -> Self::Result {
+        let deleted = ctx.delete_user(cmd.id)?;
+        Ok(deleted
+            .map(|user| UserDomainEvent::UserDeleted { id: user.id })
+            .into_iter()
+            .collect())
+    }
+}
+
+impl CommandHandler<ActivateUser> for User {
+    type Context = dyn UserRepository;
+    type Result = Result<Vec<UserDomainEvent>, UserError>;
+
+    fn handle_command(&self, cmd: &ActivateUser, ctx: &mut Self::Context) -> Self::Result {
+        let user = ctx
+            .find_user_by_id(cmd.id)?
+            .ok_or(UserError::UserNotFound(cmd.id))?;
+        if user.activated {
+            return Ok(Vec::new());
+        }
+        replace_user(ctx, User { activated: true, ..user })?;
+        Ok(vec![UserDomainEvent::UserActivated { id: cmd.id }])
     }
 }
 
@@ -270,20 +423,31 @@ impl CommandHandler<CreateUser> for User {
 /// использоваться в качестве Context благодаря ?Sized bound.
 use std::collections::HashMap;
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct MockUserRepository {
     users: HashMap<u64, User>,
     email_to_id: HashMap<String, u64>,
-    next_id: u64,
+    ids: Box<dyn IdGenerator>,
+}
+
+impl Default for MockUserRepository {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MockUserRepository {
-    /// Создает новый mock репозиторий
+    /// Создает новый mock репозиторий с последовательными ID, начиная с 1
     pub fn new() -> Self {
+        Self::with_ids(Sequential::new(1))
+    }
+
+    /// Создает mock репозиторий, выдающий ID указанным генератором
+    pub fn with_ids(ids: impl IdGenerator + 'static) -> Self {
         Self {
             users: HashMap::new(),
             email_to_id: HashMap::new(),
-            next_id: 1,
+            ids: Box::new(ids),
         }
     }
     
@@ -293,7 +457,6 @@ impl MockUserRepository {
         let email = user.email.to_string();
         self.users.insert(id, user);
         self.email_to_id.insert(email, id);
-        self.next_id = self.next_id.max(id + 1);
     }
     
     /// Получает всех пользователей (для тестов)
@@ -320,7 +483,6 @@ impl UserRepository for MockUserRepository {
         // Сохраняем пользователя
         self.users.insert(id, user);
         self.email_to_id.insert(email, id);
-        self.next_id = self.next_id.max(id + 1);
         
         Ok(())
     }
@@ -346,6 +508,22 @@ impl UserRepository for MockUserRepository {
             Ok(None)
         }
     }
+
+    fn list_users(&self) -> Result<Vec<User>, UserError> {
+        let mut users: Vec<User> = self.users.values().cloned().collect();
+        users.sort_by_key(|user| user.id);
+        Ok(users)
+    }
+
+    /// Пропускает ID, уже занятые, например, через `add_user`
+    fn next_id(&mut self) -> Result<u64, UserError> {
+        loop {
+            let id = self.ids.next_id();
+            if !self.users.contains_key(&id) {
+                return Ok(id);
+            }
+        }
+    }
 }
 
 // ============================================================================
@@ -380,11 +558,12 @@ fn main() {
     
     // Обрабатываем команду создания пользователя
     match user.handle_command(&create_cmd, &mut mock_repo) {
-        Ok(()) => {
-            println!("✓ Команда успешно обработана!");
+        Ok(events) => {
+            println!("✓ Команда успешно обработана! События: {:?}", events);
             
             // Проверяем, что пользователь был создан
-            if let Ok(Some(created_user)) = mock_repo.find_user_by_email("newuser@example.com") {
+            let query = GetUserByEmail::new("newuser@example.com");
+            if let Ok(Some(created_user)) = user.handle_query(&query, &mock_repo) {
                 println!("✓ Новый пользователь создан: {:?}", created_user);
             }
         }
@@ -401,7 +580,7 @@ fn main() {
     println!("Пытаемся создать пользователя с существующим email: {:?}", duplicate_cmd);
     
     match user.handle_command(&duplicate_cmd, &mut mock_repo) {
-        Ok(()) => {
+        Ok(_) => {
             println!("✓ Команда успешно обработана!");
         }
         Err(e) => {
@@ -417,7 +596,7 @@ fn main() {
     println!("Пытаемся создать пользователя с некорректным email: {:?}", invalid_cmd);
     
     match user.handle_command(&invalid_cmd, &mut mock_repo) {
-        Ok(()) => {
+        Ok(_) => {
             println!("✓ Команда успешно обработана!");
         }
         Err(e) => {
@@ -427,6 +606,129 @@ fn main() {
     
     println!();
     
+    // Шина выбирает обработчик по типу команды, так что вызывающему
+    // коду не нужно знать, кто и как ее обработает
+    println!("=== ШИНА КОМАНД ===");
+    // Обработчики возвращают события, а шина передает их в журнал аудита
+    type Handled = Result<Vec<UserDomainEvent>, UserError>;
+    let mut audit = AuditLog;
+    let mut bus = CommandBus::new(MockUserRepository::new());
+    bus.register_handler::<CreateUser, _, _>(user.clone());
+
+    for cmd in [
+        CreateUser::new("bus@example.com", true),
+        CreateUser::new("bus@example.com", true),
+    ] {
+        match bus.dispatch::<_, Handled>(cmd, &mut audit) {
+            Ok(Ok(_)) => println!("✓ Команда выполнена через шину"),
+            Ok(Err(e)) => println!("✗ Ошибка обработчика: {}", e),
+            Err(e) => println!("✗ Ошибка шины: {}", e),
+        }
+    }
+    let query = GetUserByEmail::new("bus@example.com");
+    let created = user.handle_query(&query, bus.repository());
+    if let Ok(Some(created_user)) = &created {
+        println!("✓ Пользователь в репозитории шины: {:?}", created_user);
+    }
+
+    // Остальные команды CRUD подключаются так же
+    bus.register_handler::<UpdateUser, _, _>(user.clone());
+    bus.register_handler::<ActivateUser, _, _>(user.clone());
+    bus.register_handler::<DeleteUser, _, _>(user.clone());
+    let id = created.ok().flatten().map_or(0, |created_user| created_user.id);
+    let updated: Result<Handled, _> =
+        bus.dispatch(UpdateUser::new(id, "renamed@example.com"), &mut audit);
+    let activated: Result<Handled, _> = bus.dispatch(ActivateUser { id }, &mut audit);
+    println!("UpdateUser: {:?}, ActivateUser: {:?}", updated.is_ok(), activated.is_ok());
+    for _ in 0..2 {
+        let deleted: Result<Handled, _> = bus.dispatch(DeleteUser { id }, &mut audit);
+        println!("DeleteUser (идемпотентна): {:?}", deleted);
+    }
+    let missing: Result<Handled, _> = bus.dispatch(ActivateUser { id }, &mut audit);
+    println!("ActivateUser удаленного: {:?}", missing);
+    let query = GetUserById { id };
+    let found = user.handle_query(&query, bus.repository());
+    println!("{} удаленного: {:?}", query.query_type(), found);
+
+    println!();
+
+    // Конвейер сам является CommandHandler, так что оборачивает любой
+    // обработчик и работает с тем же dyn UserRepository
+    println!("=== КОНВЕЙЕР MIDDLEWARE ===");
+    let pipeline = Pipeline::new(user.clone())
+        .with(Logging)
+        .with(Validate(|cmd: &CreateUser| {
+            if cmd.email.len() <= 64 {
+                Ok(())
+            } else {
+                Err(UserError::InvalidEmail(cmd.email.to_string()))
+            }
+        }))
+        .with(Retry::new(3, |result: &Result<_, UserError>| {
+            matches!(result, Err(UserError::InternalError(_)))
+        }));
+    let mut pipeline_repo = MockUserRepository::new();
+    for email in ["pipeline@example.com", "pipeline@example.com", "no-at-sign"] {
+        let cmd = CreateUser::new(email, true);
+        if let Err(e) = pipeline.handle_command(&cmd, &mut pipeline_repo) {
+            println!("✗ Ошибка: {}", e);
+        }
+    }
+
+    println!();
+
+    println!("=== АСИНХРОННЫЕ ОБРАБОТЧИКИ ===");
+    demonstrate_async(&user);
+
+    println!();
+
+    // Каждый поток получает клон SharedRepository и передает его
+    // обработчику как обычный &mut dyn UserRepository
+    println!("=== ОБЩИЙ РЕПОЗИТОРИЙ ДЛЯ ПОТОКОВ ===");
+    let shared_repo = SharedRepository::new(MockUserRepository::new());
+    let workers: Vec<_> = (0..4u64)
+        .map(|n| {
+            let mut repo = shared_repo.clone();
+            std::thread::spawn(move || {
+                let admin = User::new(n * 10, "admin@example.com", true);
+                let cmd = CreateUser::new(format!("thread{}@example.com", n), true);
+                admin.handle_command(&cmd, &mut repo)
+            })
+        })
+        .collect();
+    for worker in workers {
+        if let Ok(Err(e)) = worker.join() {
+            println!("✗ Ошибка в потоке: {}", e);
+        }
+    }
+    match user.handle_query(&ListActivatedUsers, &shared_repo) {
+        Ok(users) => println!("✓ Создано из потоков: {}", users.len()),
+        Err(e) => println!("✗ Ошибка запроса: {}", e),
+    }
+
+    println!();
+
+    // Обработчик берет ID у контекста, поэтому стратегия меняется
+    // вместе с хранилищем, а не в коде обработчика
+    println!("=== ГЕНЕРАТОРЫ ID ===");
+    let repositories = [
+        ("последовательные", MockUserRepository::with_ids(Sequential::new(100))),
+        ("случайные", MockUserRepository::with_ids(RandomIds::new())),
+        ("воспроизводимые", MockUserRepository::with_ids(RandomIds::seeded(7))),
+        ("по времени", MockUserRepository::with_ids(TimeOrdered::new())),
+    ];
+    for (name, mut repo) in repositories {
+        let ids: Vec<u64> = ["a@example.com", "b@example.com"]
+            .into_iter()
+            .filter_map(|email| user.handle_command(&CreateUser::new(email, true), &mut repo).ok())
+            .flatten()
+            .map(|event| event.user_id())
+            .collect();
+        println!("  {}: {:?}", name, ids);
+    }
+
+    println!();
+
     // Показываем все пользователей в репозитории
     println!("=== ТЕКУЩЕЕ СОСТОЯНИЕ РЕПОЗИТОРИЯ ===");
     let all_users = mock_repo.get_all_users();
@@ -434,6 +736,11 @@ fn main() {
     for user in all_users {
         println!("  {:?}", user);
     }
+    // Чтение идет через запросы, которым достаточно &dyn UserRepository
+    match user.handle_query(&ListActivatedUsers, &mock_repo) {
+        Ok(activated) => println!("Активированных: {}", activated.len()),
+        Err(e) => println!("✗ Ошибка запроса: {}", e),
+    }
     
     println!();
     
@@ -459,6 +766,45 @@ fn main() {
     demonstrate_sized_vs_unsized();
 }
 
+// ============================================================================
+// ДОПОЛНИТЕЛЬНАЯ ДЕМОНСТРАЦИЯ: АСИНХРОННЫЕ ОБРАБОТЧИКИ
+// ============================================================================
+
+/// Выполняет команды асинхронно: нативный обработчик работает с
+/// `dyn AsyncUserRepository`, а синхронный подключается через `FromSync`
+///
+/// `User` реализует и `CommandHandler`, и `AsyncCommandHandler`, поэтому
+/// вызовы записаны в полной форме.
+fn demonstrate_async(user: &User) {
+    use async_handler::{AsyncCommandHandler, AsyncUserRepository, FromSync};
+
+    futures::executor::block_on(async {
+        let mut repo = MockUserRepository::new();
+        let ctx: &mut dyn AsyncUserRepository = &mut repo;
+        let cmd = CreateUser::new("async@example.com", false);
+        let id = match AsyncCommandHandler::handle_command(user, &cmd, ctx).await {
+            Ok(events) => {
+                println!("✓ Асинхронная команда выполнена: {:?}", events);
+                events.first().map_or(0, UserDomainEvent::user_id)
+            }
+            Err(e) => {
+                println!("✗ Ошибка: {}", e);
+                0
+            }
+        };
+
+        let activate = FromSync(user.clone());
+        if let Err(e) = activate.handle_command(&ActivateUser { id }, &mut repo).await {
+            println!("✗ Ошибка: {}", e);
+        }
+        if let Ok(Some(activated)) = AsyncUserRepository::find_user_by_id(&repo, id).await {
+            println!("✓ Активирован через FromSync: {:?}", activated);
+        }
+        let deleted = AsyncUserRepository::delete_user(&mut repo, id).await;
+        println!("Удален асинхронно: {:?}", deleted.map(|user| user.is_some()));
+    });
+}
+
 // ============================================================================
 // ДОПОЛНИТЕЛЬНАЯ ДЕМОНСТРАЦИЯ: SIZED VS UNSIZED
 // ============================================================================
@@ -487,7 +833,7 @@ fn demonstrate_sized_vs_unsized() {
     mock_repo.add_user(user.clone());
     
     match user.handle_command(&create_cmd, &mut mock_repo) {
-        Ok(()) => println!("   ✓ Команда успешно обработана с MockUserRepository"),
+        Ok(_) => println!("   ✓ Команда успешно обработана с MockUserRepository"),
         Err(e) => println!("   ✗ Ошибка: {}", e),
     }
     
@@ -510,7 +856,7 @@ fn demonstrate_sized_vs_unsized() {
     let mut trait_object_repo: Box<dyn UserRepository> = Box::new(mock_repo_for_trait);
     
     match user.handle_command(&create_cmd, &mut *trait_object_repo) {
-        Ok(()) => println!("   ✓ Команда успешно обработана с dyn UserRepository"),
+        Ok(_) => println!("   ✓ Команда успешно обработана с dyn UserRepository"),
         Err(e) => println!("   ✗ Ошибка: {}", e),
     }
     
@@ -532,7 +878,7 @@ fn demonstrate_sized_vs_unsized() {
     another_mock_repo.add_user(user.clone());
     
     match user.handle_command(&new_cmd, &mut another_mock_repo) {
-        Ok(()) => println!("   ✓ Команда обработана с другой реализацией репозитория"),
+        Ok(_) => println!("   ✓ Команда обработана с другой реализацией репозитория"),
         Err(e) => println!("   ✗ Ошибка: {}", e),
     }
     
@@ -591,8 +937,15 @@ mod tests {
         // Обрабатываем команду
         let result = user.handle_command(&create_cmd, &mut mock_repo);
         
-        // Проверяем результат
-        assert!(result.is_ok());
+        // Проверяем результат: ровно одно событие о создании
+        assert_eq!(
+            result,
+            Ok(vec![UserDomainEvent::UserCreated {
+                id: 2,
+                email: "newuser@example.com".into(),
+                activated: false,
+            }])
+        );
         
         // Проверяем, что пользователь был создан
         let created_user = mock_repo.find_user_by_email("newuser@example.com").unwrap();
@@ -600,7 +953,7 @@ mod tests {
         
         let created_user = created_user.unwrap();
         assert_eq!(created_user.email, "newuser@example.com");
-        assert_eq!(created_user.activated, false);
+        assert!(!created_user.activated);
     }
 
     #[test]
@@ -701,6 +1054,63 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_update_user_keeps_invariants() {
+        let admin = User::new(1, "admin@example.com", true);
+        let mut repo = MockUserRepository::new();
+        repo.add_user(admin.clone());
+        repo.add_user(User::new(2, "bob@example.com", false));
+
+        let rename = UpdateUser::new(2, "robert@example.com");
+        assert_eq!(
+            admin.handle_command(&rename, &mut repo),
+            Ok(vec![UserDomainEvent::UserUpdated {
+                id: 2,
+                email: "robert@example.com".into(),
+            }])
+        );
+        assert_eq!(repo.find_user_by_email("bob@example.com").unwrap(), None);
+        assert_eq!(
+            repo.find_user_by_id(2).unwrap(),
+            Some(User::new(2, "robert@example.com", false))
+        );
+
+        assert_eq!(
+            admin.handle_command(&UpdateUser::new(2, "admin@example.com"), &mut repo),
+            Err(UserError::UserAlreadyExists("admin@example.com".to_string()))
+        );
+        assert_eq!(
+            admin.handle_command(&UpdateUser::new(2, "robert"), &mut repo),
+            Err(UserError::InvalidEmail("robert".to_string()))
+        );
+        assert_eq!(
+            admin.handle_command(&UpdateUser::new(3, "carol@example.com"), &mut repo),
+            Err(UserError::UserNotFound(3))
+        );
+        assert_eq!(repo.get_all_users().len(), 2);
+    }
+
+    #[test]
+    fn test_activate_and_delete_user() {
+        let admin = User::new(1, "admin@example.com", true);
+        let mut repo = MockUserRepository::new();
+        repo.add_user(User::new(2, "bob@example.com", false));
+
+        // Повторная активация ничего не меняет и не порождает событий
+        for expected in [vec![UserDomainEvent::UserActivated { id: 2 }], vec![]] {
+            assert_eq!(admin.handle_command(&ActivateUser { id: 2 }, &mut repo), Ok(expected));
+            assert!(repo.find_user_by_id(2).unwrap().unwrap().activated);
+        }
+
+        let deleted = admin.handle_command(&DeleteUser { id: 2 }, &mut repo);
+        assert_eq!(deleted, Ok(vec![UserDomainEvent::UserDeleted { id: 2 }]));
+        assert_eq!(admin.handle_command(&DeleteUser { id: 2 }, &mut repo), Ok(vec![]));
+        assert_eq!(
+            admin.handle_command(&ActivateUser { id: 2 }, &mut repo),
+            Err(UserError::UserNotFound(2))
+        );
+    }
+
     #[test]
     fn test_command_trait() {
         let cmd = CreateUser::new("test@example.com", true);
@@ -0,0 +1,243 @@
+//! Конвейер middleware вокруг обработчиков команд
+//!
+//! `Pipeline` сам реализует `CommandHandler` с тем же `Context`, что и
+//! обернутый обработчик, поэтому `dyn UserRepository` и прочие `?Sized`
+//! контексты продолжают работать, а конвейер можно зарегистрировать в шине.
+
+use std::fmt::Debug;
+
+use super::{Command, CommandHandler};
+
+/// Что делать после очередного выполнения обработчика
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Next {
+    /// Вернуть результат вызывающему
+    Done,
+    /// Выполнить обработчик еще раз
+    Retry,
+}
+
+/// Хуки, вызываемые до и после обработчика команд `C` с результатом `R`
+pub trait HandlerMiddleware<C: Command, R> {
+    /// Вызывается до обработчика; `Some` прерывает выполнение с этим результатом
+    fn before(&self, _cmd: &C) -> Option<R> {
+        None
+    }
+
+    /// Вызывается после каждой попытки, `attempt` начинается с 1
+    fn after(&self, _cmd: &C, _result: &R, _attempt: u32) -> Next {
+        Next::Done
+    }
+}
+
+/// Обработчик, обернутый в цепочку middleware
+///
+/// Хуки `before` вызываются в порядке добавления, `after` — в обратном.
+/// Обработчик повторяется, пока хоть один `after` просит `Next::Retry`.
+pub struct Pipeline<C: Command, H: CommandHandler<C>> {
+    handler: H,
+    middlewares: Vec<Box<dyn HandlerMiddleware<C, H::Result>>>,
+}
+
+impl<C: Command, H: CommandHandler<C>> Pipeline<C, H> {
+    /// Создает конвейер без middleware
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            middlewares: Vec::new(),
+        }
+    }
+
+    /// Добавляет middleware в конец цепочки
+    pub fn with(mut self, middleware: impl HandlerMiddleware<C, H::Result> + 'static) -> Self {
+        self.middlewares.push(Box::new(middleware));
+        self
+    }
+}
+
+impl<C: Command, H: CommandHandler<C>> CommandHandler<C> for Pipeline<C, H> {
+    type Context = H::Context;
+    type Result = H::Result;
+
+    fn handle_command(&self, cmd: &C, ctx: &mut Self::Context) -> Self::Result {
+        if let Some(result) = self.middlewares.iter().find_map(|m| m.before(cmd)) {
+            return result;
+        }
+        let mut attempt = 1;
+        loop {
+            let result = self.handler.handle_command(cmd, ctx);
+            // Все after должны увидеть результат, даже если повтор уже решен
+            let next = self.middlewares.iter().rev().fold(Next::Done, |next, m| {
+                match m.after(cmd, &result, attempt) {
+                    Next::Retry => Next::Retry,
+                    Next::Done => next,
+                }
+            });
+            if next == Next::Done {
+                return result;
+            }
+            attempt += 1;
+        }
+    }
+}
+
+/// Печатает команды и результаты их выполнения
+pub struct Logging;
+
+impl<C: Command, R: Debug> HandlerMiddleware<C, R> for Logging {
+    fn before(&self, cmd: &C) -> Option<R> {
+        println!("  → {}", cmd.command_type());
+        None
+    }
+
+    fn after(&self, cmd: &C, result: &R, attempt: u32) -> Next {
+        println!(
+            "  ← {} (попытка {}): {:?}",
+            cmd.command_type(),
+            attempt,
+            result
+        );
+        Next::Done
+    }
+}
+
+/// Отклоняет команды, не прошедшие проверку, не вызывая обработчик
+pub struct Validate<F>(pub F);
+
+impl<C, T, E, F> HandlerMiddleware<C, Result<T, E>> for Validate<F>
+where
+    C: Command,
+    F: Fn(&C) -> Result<(), E>,
+{
+    fn before(&self, cmd: &C) -> Option<Result<T, E>> {
+        (self.0)(cmd).err().map(Err)
+    }
+}
+
+/// Повторяет обработчик, пока результат удовлетворяет `should_retry`
+pub struct Retry<F> {
+    max_attempts: u32,
+    should_retry: F,
+}
+
+impl<F> Retry<F> {
+    /// Не более `max_attempts` выполнений, включая первое
+    pub fn new(max_attempts: u32, should_retry: F) -> Self {
+        Self {
+            max_attempts,
+            should_retry,
+        }
+    }
+}
+
+impl<C, R, F> HandlerMiddleware<C, R> for Retry<F>
+where
+    C: Command,
+    F: Fn(&R) -> bool,
+{
+    fn after(&self, _cmd: &C, result: &R, attempt: u32) -> Next {
+        if attempt < self.max_attempts && (self.should_retry)(result) {
+            Next::Retry
+        } else {
+            Next::Done
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CreateUser, MockUserRepository, User, UserError, UserRepository};
+
+    /// Хранилище, первые `failures` сохранений которого падают
+    struct Flaky {
+        inner: MockUserRepository,
+        failures: u32,
+    }
+
+    impl UserRepository for Flaky {
+        fn save_user(&mut self, user: User) -> Result<(), UserError> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(UserError::InternalError("timeout".to_string()));
+            }
+            self.inner.save_user(user)
+        }
+
+        fn find_user_by_id(&self, id: u64) -> Result<Option<User>, UserError> {
+            self.inner.find_user_by_id(id)
+        }
+
+        fn find_user_by_email(&self, email: &str) -> Result<Option<User>, UserError> {
+            self.inner.find_user_by_email(email)
+        }
+
+        fn delete_user(&mut self, id: u64) -> Result<Option<User>, UserError> {
+            self.inner.delete_user(id)
+        }
+
+        fn list_users(&self) -> Result<Vec<User>, UserError> {
+            self.inner.list_users()
+        }
+
+        fn next_id(&mut self) -> Result<u64, UserError> {
+            self.inner.next_id()
+        }
+    }
+
+    fn pipeline() -> Pipeline<CreateUser, User> {
+        Pipeline::new(User::new(1, "admin@example.com", true))
+            .with(Validate(|cmd: &CreateUser| {
+                if cmd.email.ends_with("@example.com") {
+                    Ok(())
+                } else {
+                    Err(UserError::InvalidEmail(cmd.email.to_string()))
+                }
+            }))
+            .with(Retry::new(3, |result: &Result<_, UserError>| {
+                matches!(result, Err(UserError::InternalError(_)))
+            }))
+    }
+
+    #[test]
+    fn test_retries_transient_errors() {
+        let pipeline = pipeline();
+        let mut repo = Flaky {
+            inner: MockUserRepository::new(),
+            failures: 2,
+        };
+        let ctx: &mut dyn UserRepository = &mut repo;
+
+        let cmd = CreateUser::new("alice@example.com", true);
+        assert_eq!(pipeline.handle_command(&cmd, ctx).unwrap().len(), 1);
+        assert!(
+            repo.inner
+                .find_user_by_email("alice@example.com")
+                .unwrap()
+                .is_some()
+        );
+
+        repo.failures = 3;
+        let cmd = CreateUser::new("bob@example.com", true);
+        assert_eq!(
+            pipeline.handle_command(&cmd, &mut repo),
+            Err(UserError::InternalError("timeout".to_string()))
+        );
+        assert_eq!(repo.failures, 0);
+    }
+
+    #[test]
+    fn test_validation_short_circuits() {
+        let mut repo = Flaky {
+            inner: MockUserRepository::new(),
+            failures: 1,
+        };
+        let cmd = CreateUser::new("alice@elsewhere.org", true);
+        assert_eq!(
+            pipeline().handle_command(&cmd, &mut repo),
+            Err(UserError::InvalidEmail("alice@elsewhere.org".to_string()))
+        );
+        // До хранилища дело не дошло
+        assert_eq!(repo.failures, 1);
+    }
+}
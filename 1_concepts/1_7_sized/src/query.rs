@@ -0,0 +1,127 @@
+//! Сторона чтения: запросы и их обработчики
+//!
+//! Зеркалит `Command`/`CommandHandler`, только контекст передается по `&`,
+//! так что обработчик запроса не может изменить хранилище. `Context` так же
+//! `?Sized`, поэтому контекстом служит `dyn UserRepository`.
+
+use std::borrow::Cow;
+
+use super::{User, UserError, UserRepository};
+
+/// Трейт для запросов, которые только читают данные
+pub trait Query {
+    /// Возвращает тип запроса для логирования и отладки
+    fn query_type(&self) -> &'static str;
+}
+
+/// Трейт для обработки запросов
+pub trait QueryHandler<Q: Query> {
+    /// Тип контекста, может быть trait object благодаря ?Sized bound
+    type Context: ?Sized;
+
+    /// Тип результата запроса
+    type Result;
+
+    /// Выполняет запрос в контексте, доступном только для чтения
+    fn handle_query(&self, query: &Q, ctx: &Self::Context) -> Self::Result;
+}
+
+/// Запрос пользователя по ID
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GetUserById {
+    pub id: u64,
+}
+
+impl Query for GetUserById {
+    fn query_type(&self) -> &'static str {
+        "GetUserById"
+    }
+}
+
+/// Запрос пользователя по email
+#[derive(Debug, Clone, PartialEq)]
+pub struct GetUserByEmail {
+    pub email: Cow<'static, str>,
+}
+
+impl GetUserByEmail {
+    /// Создает запрос пользователя с данным email
+    pub fn new(email: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            email: email.into(),
+        }
+    }
+}
+
+impl Query for GetUserByEmail {
+    fn query_type(&self) -> &'static str {
+        "GetUserByEmail"
+    }
+}
+
+/// Запрос всех активированных пользователей
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ListActivatedUsers;
+
+impl Query for ListActivatedUsers {
+    fn query_type(&self) -> &'static str {
+        "ListActivatedUsers"
+    }
+}
+
+impl QueryHandler<GetUserById> for User {
+    type Context = dyn UserRepository;
+    type Result = Result<Option<User>, UserError>;
+
+    fn handle_query(&self, query: &GetUserById, ctx: &Self::Context) -> Self::Result {
+        ctx.find_user_by_id(query.id)
+    }
+}
+
+impl QueryHandler<GetUserByEmail> for User {
+    type Context = dyn UserRepository;
+    type Result = Result<Option<User>, UserError>;
+
+    fn handle_query(&self, query: &GetUserByEmail, ctx: &Self::Context) -> Self::Result {
+        ctx.find_user_by_email(&query.email)
+    }
+}
+
+impl QueryHandler<ListActivatedUsers> for User {
+    type Context = dyn UserRepository;
+    /// Пользователи в порядке возрастания ID
+    type Result = Result<Vec<User>, UserError>;
+
+    fn handle_query(&self, _query: &ListActivatedUsers, ctx: &Self::Context) -> Self::Result {
+        let mut users = ctx.list_users()?;
+        users.retain(|user| user.activated);
+        Ok(users)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockUserRepository;
+
+    #[test]
+    fn test_queries_read_through_dyn_repository() {
+        let admin = User::new(1, "admin@example.com", true);
+        let mut repo = MockUserRepository::new();
+        repo.add_user(User::new(3, "carol@example.com", true));
+        repo.add_user(User::new(2, "bob@example.com", false));
+        repo.add_user(admin.clone());
+        let ctx: &dyn UserRepository = &repo;
+
+        let bob = admin.handle_query(&GetUserById { id: 2 }, ctx).unwrap();
+        assert_eq!(bob.map(|user| user.email), Some("bob@example.com".into()));
+        let carol = admin.handle_query(&GetUserByEmail::new("carol@example.com"), ctx);
+        assert_eq!(carol.unwrap().map(|user| user.id), Some(3));
+        assert_eq!(admin.handle_query(&GetUserById { id: 4 }, ctx), Ok(None));
+
+        let activated = admin.handle_query(&ListActivatedUsers, ctx).unwrap();
+        let ids: Vec<u64> = activated.iter().map(|user| user.id).collect();
+        assert_eq!(ids, [1, 3]);
+        assert_eq!(ListActivatedUsers.query_type(), "ListActivatedUsers");
+    }
+}
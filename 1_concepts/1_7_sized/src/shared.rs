@@ -0,0 +1,111 @@
+//! Репозиторий, разделяемый между потоками
+//!
+//! `SharedRepository` сам реализует `UserRepository`, поэтому каждый поток
+//! передает обработчикам команд свой клон как обычный `&mut dyn UserRepository`.
+//! Чтения берут блокировку на чтение, изменения — на запись.
+
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use super::{User, UserError, UserRepository};
+
+/// Хранилище, доступное из любого потока
+type Inner = dyn UserRepository + Send + Sync + 'static;
+
+/// Хранилище за `Arc<RwLock<..>>`; клоны ссылаются на одни и те же данные
+///
+/// Каждый метод атомарен сам по себе, но обработчик, читающий и затем
+/// пишущий, может пересечься с другим потоком. Инварианты вроде уникальности
+/// email должно проверять само хранилище в `save_user`.
+#[derive(Clone)]
+pub struct SharedRepository(Arc<RwLock<Inner>>);
+
+impl SharedRepository {
+    /// Оборачивает хранилище для совместного использования
+    pub fn new(repository: impl UserRepository + Sync + 'static) -> Self {
+        Self(Arc::new(RwLock::new(repository)))
+    }
+
+    fn read(&self) -> Result<RwLockReadGuard<'_, Inner>, UserError> {
+        self.0.read().map_err(|_| poisoned())
+    }
+
+    fn write(&self) -> Result<RwLockWriteGuard<'_, Inner>, UserError> {
+        self.0.write().map_err(|_| poisoned())
+    }
+}
+
+/// Поток запаниковал, держа блокировку, и мог оставить данные несогласованными
+fn poisoned() -> UserError {
+    UserError::InternalError("хранилище заблокировано упавшим потоком".to_string())
+}
+
+impl UserRepository for SharedRepository {
+    fn save_user(&mut self, user: User) -> Result<(), UserError> {
+        self.write()?.save_user(user)
+    }
+
+    fn find_user_by_id(&self, id: u64) -> Result<Option<User>, UserError> {
+        self.read()?.find_user_by_id(id)
+    }
+
+    fn find_user_by_email(&self, email: &str) -> Result<Option<User>, UserError> {
+        self.read()?.find_user_by_email(email)
+    }
+
+    fn delete_user(&mut self, id: u64) -> Result<Option<User>, UserError> {
+        self.write()?.delete_user(id)
+    }
+
+    fn list_users(&self) -> Result<Vec<User>, UserError> {
+        self.read()?.list_users()
+    }
+
+    fn next_id(&mut self) -> Result<u64, UserError> {
+        self.write()?.next_id()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::{CommandHandler, CreateUser, DeleteUser, MockUserRepository, UserDomainEvent};
+
+    #[test]
+    fn test_handlers_run_from_many_threads() {
+        let repo = SharedRepository::new(MockUserRepository::new());
+
+        let threads: Vec<_> = (0..8u64)
+            .map(|t| {
+                let mut repo = repo.clone();
+                thread::spawn(move || {
+                    let mut won_shared_email = false;
+                    for i in 0..100 {
+                        let admin = User::new(t * 1000 + i, "admin@example.com", true);
+                        let email = format!("user-{t}-{i}@example.com");
+                        let cmd = CreateUser::new(email, true);
+                        let created = admin.handle_command(&cmd, &mut repo).unwrap();
+                        let id = created[0].user_id();
+                        let deleted = admin.handle_command(&DeleteUser { id }, &mut repo);
+                        assert_eq!(deleted, Ok(vec![UserDomainEvent::UserDeleted { id }]));
+
+                        let cmd = CreateUser::new("shared@example.com", true);
+                        won_shared_email |= admin.handle_command(&cmd, &mut repo).is_ok();
+                    }
+                    won_shared_email
+                })
+            })
+            .collect();
+        let winners = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .filter(|&won| won)
+            .count();
+
+        assert_eq!(winners, 1, "email занимает только один поток");
+        let users = repo.list_users().unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].email, "shared@example.com");
+    }
+}
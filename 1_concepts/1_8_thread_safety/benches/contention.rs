@@ -0,0 +1,67 @@
+//! Сравнение SharedRw с SyncAndSend, когда данные в основном читают:
+//! несколько читателей и один редкий писатель.
+//!
+//! Запуск: cargo bench -p step_1_8 --bench contention
+
+use std::thread;
+
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use step_1_8::{SharedRw, SyncAndSend};
+
+const READERS: usize = 4;
+const READS: usize = 2_000;
+/// Писатель обновляет данные один раз на столько чтений одного читателя
+const READS_PER_WRITE: usize = 100;
+
+/// Доступ к общему вектору, общий для обеих реализаций
+trait Shared: Sync {
+    fn sum(&self) -> u64;
+    fn bump(&self);
+}
+
+impl Shared for SyncAndSend<Vec<u64>> {
+    fn sum(&self) -> u64 {
+        self.get().iter().sum()
+    }
+
+    fn bump(&self) {
+        self.get().iter_mut().for_each(|x| *x += 1);
+    }
+}
+
+impl Shared for SharedRw<Vec<u64>> {
+    fn sum(&self) -> u64 {
+        self.read().iter().sum()
+    }
+
+    fn bump(&self) {
+        self.write().iter_mut().for_each(|x| *x += 1);
+    }
+}
+
+fn read_mostly(shared: &impl Shared) {
+    thread::scope(|s| {
+        for _ in 0..READERS {
+            s.spawn(|| (0..READS).for_each(|_| _ = black_box(shared.sum())));
+        }
+        s.spawn(|| (0..READS / READS_PER_WRITE).for_each(|_| shared.bump()));
+    });
+}
+
+fn readers(c: &mut Criterion) {
+    let data = || (0..1_000).collect::<Vec<u64>>();
+    let mut group = c.benchmark_group("read_mostly");
+    group.sample_size(20);
+    group.bench_function("mutex", |b| {
+        let shared = SyncAndSend::new(data());
+        b.iter(|| read_mostly(&shared))
+    });
+    group.bench_function("rwlock", |b| {
+        let shared = SharedRw::new(data());
+        b.iter(|| read_mostly(&shared))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, readers);
+criterion_main!(benches);
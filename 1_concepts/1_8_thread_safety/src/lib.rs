@@ -0,0 +1,484 @@
+//! Типы с разными сочетаниями авто-трейтов `Send` и `Sync`
+//!
+//! Вместо того чтобы полагаться на побочные свойства полей вроде `RefCell`,
+//! тип оборачивает значение в [`SendOnly`] или [`SyncOnly`] и получает нужное
+//! поведение от маркера в `PhantomData`, не меняя способа доступа к данным.
+//! Заявленные свойства проверяются в тестах: положительные через
+//! `static_assertions`, отрицательные еще и примерами в `tests/compile_fail`,
+//! которые не должны компилироваться.
+
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::{MutexGuard, PoisonError, TryLockError};
+
+mod scoped;
+mod tracked;
+
+pub use scoped::{parallel_map, with_scoped_chunks, with_scoped_threads};
+pub use tracked::{TrackedMutex, TrackedMutexGuard};
+
+/// Значение, которое можно переместить в другой поток, но не разделить
+///
+/// `SendOnly<T>` является `Send`, если `T: Send`, и никогда не является
+/// `Sync`: маркер `*const ()` снимает оба авто-трейта, а `Send` возвращается
+/// вручную.
+#[derive(Debug, Default, Clone)]
+pub struct SendOnly<T> {
+    value: T,
+    _not_sync: PhantomData<*const ()>,
+}
+
+// SAFETY: маркер не хранит данных, поэтому перемещение обертки между
+// потоками перемещает только `T`, а оно `Send`
+unsafe impl<T: Send> Send for SendOnly<T> {}
+
+impl<T> SendOnly<T> {
+    /// Оборачивает значение
+    pub fn new(value: T) -> Self {
+        Self {
+            value,
+            _not_sync: PhantomData,
+        }
+    }
+
+    /// Возвращает ссылку на значение
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Возвращает изменяемую ссылку на значение
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+
+    /// Разворачивает обертку
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+/// Значение, которое можно разделить между потоками, но не переместить
+///
+/// `SyncOnly<T>` является `Sync`, если `T: Sync`, и никогда не является
+/// `Send`: так же устроен `MutexGuard`, который должен освобождаться в
+/// захватившем мьютекс потоке.
+#[derive(Debug, Default, Clone)]
+pub struct SyncOnly<T> {
+    value: T,
+    _not_send: PhantomData<MutexGuard<'static, ()>>,
+}
+
+impl<T> SyncOnly<T> {
+    /// Оборачивает значение
+    pub fn new(value: T) -> Self {
+        Self {
+            value,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Возвращает ссылку на значение
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Возвращает изменяемую ссылку на значение
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+
+    /// Разворачивает обертку
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+/// Примитивы, на которых построены типы ниже
+///
+/// С фичей `loom` их подменяют модели loom, перебирающие все чередования
+/// потоков. Демонстрации в `main.rs` по-прежнему используют `std`.
+mod sync {
+    #[cfg(feature = "loom")]
+    pub use loom::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+    #[cfg(feature = "loom")]
+    pub use loom::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    #[cfg(not(feature = "loom"))]
+    pub use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+    #[cfg(not(feature = "loom"))]
+    pub use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+}
+
+/// OnlySync - Sync, но !Send
+/// 
+/// Этот тип может быть безопасно разделен между потоками (Sync),
+/// но не может быть перемещен между потоками (!Send).
+/// 
+/// Реализация использует Arc<RwLock<T>>, обернутый в `SyncOnly`.
+/// - Arc<RwLock<T>> обеспечивает безопасный параллельный доступ (Sync)
+/// - SyncOnly делает тип !Send, не требуя `unsafe impl`
+#[derive(Debug, Clone)]
+pub struct OnlySync<T> {
+    /// Arc<RwLock<T>> является Sync и позволяет множественное владение
+    data: SyncOnly<sync::Arc<sync::RwLock<T>>>,
+}
+
+impl<T> OnlySync<T> {
+    /// Создает новый экземпляр OnlySync
+    pub fn new(data: T) -> Self {
+        Self {
+            data: SyncOnly::new(sync::Arc::new(sync::RwLock::new(data))),
+        }
+    }
+
+    /// Получает неизменяемую ссылку на данные
+    pub fn get(&self) -> sync::RwLockReadGuard<'_, T> {
+        self.data.get().read().unwrap()
+    }
+
+    /// Получает изменяемую ссылку на данные
+    pub fn get_mut(&self) -> sync::RwLockWriteGuard<'_, T> {
+        self.data.get().write().unwrap()
+    }
+
+    /// Получает количество ссылок
+    pub fn strong_count(&self) -> usize {
+        sync::Arc::strong_count(self.data.get())
+    }
+}
+
+/// OnlySend - Send, но !Sync
+/// 
+/// Этот тип может быть перемещен между потоками (Send),
+/// но не может быть безопасно разделен между потоками (!Sync).
+/// 
+/// Реализация хранит T в `SendOnly`, маркер которого делает тип !Sync
+/// независимо от T. Внутренней мутабельности нет, поэтому изменение
+/// требует `&mut self`.
+#[derive(Debug)]
+pub struct OnlySend<T> {
+    /// SendOnly<T> является Send, если T: Send, но не Sync
+    data: SendOnly<T>,
+}
+
+impl<T> OnlySend<T> {
+    /// Создает новый экземпляр OnlySend
+    pub fn new(data: T) -> Self {
+        Self {
+            data: SendOnly::new(data),
+        }
+    }
+    
+    /// Получает неизменяемую ссылку на данные
+    pub fn get(&self) -> &T {
+        self.data.get()
+    }
+    
+    /// Получает изменяемую ссылку на данные
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+/// SyncAndSend - и Sync, и Send
+/// 
+/// Этот тип может быть как перемещен между потоками (Send),
+/// так и безопасно разделен между потоками (Sync).
+/// 
+/// Реализация использует Arc<Mutex<T>>, который является и Send, и Sync.
+#[derive(Debug, Clone)]
+pub struct SyncAndSend<T> {
+    /// Arc<Mutex<T>> является и Send, и Sync
+    /// Arc обеспечивает атомарное подсчет ссылок для множественного владения
+    /// Mutex обеспечивает внутреннюю мутабельность с блокировкой
+    data: sync::Arc<sync::Mutex<T>>,
+    /// PhantomData для дополнительной информации о типе
+    _phantom: PhantomData<T>,
+}
+
+impl<T> SyncAndSend<T> {
+    /// Создает новый экземпляр SyncAndSend
+    pub fn new(data: T) -> Self {
+        Self {
+            data: sync::Arc::new(sync::Mutex::new(data)),
+            _phantom: PhantomData,
+        }
+    }
+    
+    /// Получает неизменяемую ссылку на данные
+    pub fn get(&self) -> sync::MutexGuard<'_, T> {
+        self.data.lock().unwrap()
+    }
+    
+    /// Получает количество ссылок
+    pub fn strong_count(&self) -> usize {
+        sync::Arc::strong_count(&self.data)
+    }
+}
+
+/// SharedRw - и Sync, и Send, с раздельным доступом на чтение и запись
+///
+/// В отличие от SyncAndSend читатели не выстраиваются в очередь друг за
+/// другом: Arc<RwLock<T>> пускает их одновременно, блокируя только писатель.
+/// Паника под блокировкой не делает данные недоступными: отравленная
+/// блокировка возвращает их как есть, а целостность остается на вызывающем.
+#[derive(Debug)]
+pub struct SharedRw<T> {
+    data: sync::Arc<sync::RwLock<T>>,
+}
+
+impl<T> Clone for SharedRw<T> {
+    fn clone(&self) -> Self {
+        Self {
+            data: sync::Arc::clone(&self.data),
+        }
+    }
+}
+
+impl<T> SharedRw<T> {
+    /// Создает новый экземпляр SharedRw
+    pub fn new(data: T) -> Self {
+        Self {
+            data: sync::Arc::new(sync::RwLock::new(data)),
+        }
+    }
+
+    /// Ждет доступа на чтение, разделяемого с другими читателями
+    pub fn read(&self) -> sync::RwLockReadGuard<'_, T> {
+        self.data.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Ждет исключительного доступа на запись
+    pub fn write(&self) -> sync::RwLockWriteGuard<'_, T> {
+        self.data.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Читает без ожидания, `None`, если данные сейчас пишутся
+    pub fn try_read(&self) -> Option<sync::RwLockReadGuard<'_, T>> {
+        match self.data.try_read() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    /// Пишет без ожидания, `None`, если данные сейчас читаются или пишутся
+    pub fn try_write(&self) -> Option<sync::RwLockWriteGuard<'_, T>> {
+        match self.data.try_write() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    /// Получает количество ссылок
+    pub fn strong_count(&self) -> usize {
+        sync::Arc::strong_count(&self.data)
+    }
+}
+
+/// SharedCounter - и Sync, и Send без блокировок
+///
+/// Клоны разделяют один атомарный счетчик: потоки не ждут друг друга, а
+/// одновременные изменения не теряются. Запись публикует сделанное до нее
+/// (`Release`), чтение видит опубликованное (`Acquire`).
+#[derive(Debug, Clone, Default)]
+pub struct SharedCounter {
+    value: sync::Arc<sync::AtomicU64>,
+}
+
+impl SharedCounter {
+    /// Создает новый экземпляр SharedCounter
+    pub fn new(value: u64) -> Self {
+        Self {
+            value: sync::Arc::new(sync::AtomicU64::new(value)),
+        }
+    }
+
+    /// Возвращает текущее значение
+    pub fn get(&self) -> u64 {
+        self.value.load(sync::Ordering::Acquire)
+    }
+
+    /// Прибавляет `n`, переполнение заворачивает значение через ноль
+    ///
+    /// Возвращает значение до изменения.
+    pub fn fetch_add(&self, n: u64) -> u64 {
+        self.value.fetch_add(n, sync::Ordering::AcqRel)
+    }
+
+    /// Заменяет значение на `new`, только если оно все еще `current`
+    ///
+    /// Возвращает значение до изменения: `Ok` при замене, иначе `Err`.
+    pub fn compare_exchange(&self, current: u64, new: u64) -> Result<u64, u64> {
+        self.value
+            .compare_exchange(current, new, sync::Ordering::AcqRel, sync::Ordering::Acquire)
+    }
+}
+
+/// SharedFlag - и Sync, и Send без блокировок
+///
+/// Общий для клонов атомарный флаг, например, чтобы остановить потоки или
+/// выбрать ровно одного из них через `compare_exchange`.
+#[derive(Debug, Clone, Default)]
+pub struct SharedFlag {
+    value: sync::Arc<sync::AtomicBool>,
+}
+
+impl SharedFlag {
+    /// Создает новый экземпляр SharedFlag
+    pub fn new(value: bool) -> Self {
+        Self {
+            value: sync::Arc::new(sync::AtomicBool::new(value)),
+        }
+    }
+
+    /// Возвращает текущее значение
+    pub fn get(&self) -> bool {
+        self.value.load(sync::Ordering::Acquire)
+    }
+
+    /// Устанавливает значение
+    pub fn set(&self, value: bool) {
+        self.value.store(value, sync::Ordering::Release);
+    }
+
+    /// Устанавливает значение, возвращая прежнее
+    pub fn swap(&self, value: bool) -> bool {
+        self.value.swap(value, sync::Ordering::AcqRel)
+    }
+
+    /// Заменяет значение на `new`, только если оно все еще `current`
+    ///
+    /// Возвращает значение до изменения: `Ok` при замене, иначе `Err`.
+    pub fn compare_exchange(&self, current: bool, new: bool) -> Result<bool, bool> {
+        self.value
+            .compare_exchange(current, new, sync::Ordering::AcqRel, sync::Ordering::Acquire)
+    }
+}
+
+/// NotSyncNotSend - !Sync и !Send
+/// 
+/// Этот тип не может быть ни перемещен между потоками (!Send),
+/// ни безопасно разделен между потоками (!Sync).
+/// 
+/// Реализация использует Rc<RefCell<T>>, который не является ни Send, ни Sync.
+#[derive(Debug, Clone)]
+pub struct NotSyncNotSend<T> {
+    /// Rc<RefCell<T>> не является ни Send, ни Sync
+    /// Rc не является Send (не может быть перемещен между потоками)
+    /// RefCell не является Sync (не может быть разделен между потоками)
+    data: Rc<RefCell<T>>,
+    /// PhantomData для дополнительной информации о типе
+    _phantom: PhantomData<T>,
+}
+
+impl<T> NotSyncNotSend<T> {
+    /// Создает новый экземпляр NotSyncNotSend
+    pub fn new(data: T) -> Self {
+        Self {
+            data: Rc::new(RefCell::new(data)),
+            _phantom: PhantomData,
+        }
+    }
+    
+    /// Получает неизменяемую ссылку на данные
+    pub fn get(&self) -> std::cell::Ref<'_, T> {
+        self.data.borrow()
+    }
+    
+    /// Получает изменяемую ссылку на данные
+    pub fn get_mut(&self) -> std::cell::RefMut<'_, T> {
+        self.data.borrow_mut()
+    }
+    
+    /// Получает количество ссылок
+    pub fn strong_count(&self) -> usize {
+        Rc::strong_count(&self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::thread;
+
+    use static_assertions::{assert_impl_all, assert_not_impl_any};
+
+    use super::*;
+
+    // Маркер снимает один трейт, второй следует за `T`
+    assert_impl_all!(SendOnly<i32>: Send);
+    assert_not_impl_any!(SendOnly<i32>: Sync);
+    assert_not_impl_any!(SendOnly<Rc<i32>>: Send, Sync);
+
+    assert_impl_all!(SyncOnly<i32>: Sync);
+    assert_not_impl_any!(SyncOnly<i32>: Send);
+    assert_not_impl_any!(SyncOnly<Cell<i32>>: Send, Sync);
+
+    assert_impl_all!(SharedRw<i32>: Send, Sync);
+    assert_impl_all!(SharedCounter: Send, Sync);
+    assert_impl_all!(SharedFlag: Send, Sync);
+    assert_not_impl_any!(SharedRw<Cell<i32>>: Sync);
+
+    // Вне loom::model модели блокировок не работают
+    #[cfg(not(feature = "loom"))]
+    #[test]
+    fn test_shared_rw_readers_share_and_poison_is_recovered() {
+        let shared = SharedRw::new(vec![1]);
+        let first = shared.read();
+        let second = shared.try_read().expect("читатели не мешают друг другу");
+        assert!(shared.try_write().is_none());
+        assert_eq!((first.len(), second.len()), (1, 1));
+        drop((first, second));
+
+        let clone = shared.clone();
+        let panicked = thread::spawn(move || {
+            clone.write().push(2);
+            panic!("паника под блокировкой");
+        })
+        .join();
+        assert!(panicked.is_err());
+        assert_eq!(*shared.read(), [1, 2]);
+        shared.try_write().unwrap().push(3);
+        assert_eq!(shared.strong_count(), 1);
+    }
+
+    #[cfg(not(feature = "loom"))]
+    #[test]
+    fn test_atomics_count_across_threads() {
+        let counter = SharedCounter::new(0);
+        let started = SharedFlag::default();
+        let winners = SharedCounter::default();
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    if started.compare_exchange(false, true).is_ok() {
+                        winners.fetch_add(1);
+                    }
+                    (0..1000).for_each(|_| _ = counter.fetch_add(1));
+                });
+            }
+        });
+        assert_eq!(counter.get(), 4000);
+        assert_eq!(winners.get(), 1);
+        assert_eq!(counter.compare_exchange(1, 2), Err(4000));
+        assert_eq!(counter.compare_exchange(4000, 0), Ok(4000));
+        assert!(started.swap(false));
+        assert!(!started.get());
+    }
+
+    #[test]
+    fn test_wrappers_give_access_to_value() {
+        let mut moved = SendOnly::new(vec![1]);
+        moved.get_mut().push(2);
+        let moved = thread::spawn(move || moved.into_inner()).join().unwrap();
+        assert_eq!(moved, [1, 2]);
+
+        let shared = SyncOnly::new(40);
+        let seen = thread::scope(|s| s.spawn(|| *shared.get() + 2).join().unwrap());
+        assert_eq!(seen, 42);
+        assert_eq!(shared.into_inner(), 40);
+    }
+}
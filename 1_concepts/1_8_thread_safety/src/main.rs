@@ -1,165 +1,11 @@
-use std::marker::PhantomData;
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
-use std::cell::RefCell;
-use std::rc::Rc;
-
-/// OnlySync - Sync, но !Send
-/// 
-/// Этот тип может быть безопасно разделен между потоками (Sync),
-/// но не может быть перемещен между потоками (!Send).
-/// 
-/// Реализация использует Arc<RwLock<T>> вместе с маркером `PhantomData<Rc<()>>`.
-/// - Arc<RwLock<T>> обеспечивает безопасный параллельный доступ (Sync)
-/// - PhantomData<Rc<()>> делает тип !Send, так как Rc не является Send
-#[derive(Debug, Clone)]
-pub struct OnlySync<T> {
-    /// Arc<RwLock<T>> является Sync и позволяет множественное владение
-    data: Arc<RwLock<T>>,
-    /// PhantomData с Rc делает тип !Send
-    _not_send: PhantomData<Rc<()>>,
-}
-
-// Тип должен быть Sync (для разделения между потоками), но не Send (для перемещения между потоками)
-unsafe impl<T: Send + Sync> Sync for OnlySync<T> {}
-
-impl<T> OnlySync<T> {
-    /// Создает новый экземпляр OnlySync
-    pub fn new(data: T) -> Self {
-        Self {
-            data: Arc::new(RwLock::new(data)),
-            _not_send: PhantomData,
-        }
-    }
-
-    /// Получает неизменяемую ссылку на данные
-    pub fn get(&self) -> std::sync::RwLockReadGuard<'_, T> {
-        self.data.read().unwrap()
-    }
-
-    /// Получает изменяемую ссылку на данные
-    pub fn get_mut(&self) -> std::sync::RwLockWriteGuard<'_, T> {
-        self.data.write().unwrap()
-    }
-
-    /// Получает количество ссылок
-    pub fn strong_count(&self) -> usize {
-        Arc::strong_count(&self.data)
-    }
-}
-
-/// OnlySend - Send, но !Sync
-/// 
-/// Этот тип может быть перемещен между потоками (Send),
-/// но не может быть безопасно разделен между потоками (!Sync).
-/// 
-/// Реализация использует RefCell<T>, который является Send,
-/// но не Sync, так как RefCell не является Sync.
-#[derive(Debug)]
-pub struct OnlySend<T> {
-    /// RefCell<T> является Send, но не Sync
-    /// RefCell обеспечивает внутреннюю мутабельность, но не может быть разделен между потоками
-    data: RefCell<T>,
-    /// PhantomData для дополнительной информации о типе
-    _phantom: PhantomData<T>,
-}
-
-impl<T> OnlySend<T> {
-    /// Создает новый экземпляр OnlySend
-    pub fn new(data: T) -> Self {
-        Self {
-            data: RefCell::new(data),
-            _phantom: PhantomData,
-        }
-    }
-    
-    /// Получает неизменяемую ссылку на данные
-    pub fn get(&self) -> std::cell::Ref<'_, T> {
-        self.data.borrow()
-    }
-    
-    /// Получает изменяемую ссылку на данные
-    pub fn get_mut(&self) -> std::cell::RefMut<'_, T> {
-        self.data.borrow_mut()
-    }
-}
-
-/// SyncAndSend - и Sync, и Send
-/// 
-/// Этот тип может быть как перемещен между потоками (Send),
-/// так и безопасно разделен между потоками (Sync).
-/// 
-/// Реализация использует Arc<Mutex<T>>, который является и Send, и Sync.
-#[derive(Debug, Clone)]
-pub struct SyncAndSend<T> {
-    /// Arc<Mutex<T>> является и Send, и Sync
-    /// Arc обеспечивает атомарное подсчет ссылок для множественного владения
-    /// Mutex обеспечивает внутреннюю мутабельность с блокировкой
-    data: Arc<Mutex<T>>,
-    /// PhantomData для дополнительной информации о типе
-    _phantom: PhantomData<T>,
-}
-
-impl<T> SyncAndSend<T> {
-    /// Создает новый экземпляр SyncAndSend
-    pub fn new(data: T) -> Self {
-        Self {
-            data: Arc::new(Mutex::new(data)),
-            _phantom: PhantomData,
-        }
-    }
-    
-    /// Получает неизменяемую ссылку на данные
-    pub fn get(&self) -> std::sync::MutexGuard<'_, T> {
-        self.data.lock().unwrap()
-    }
-    
-    /// Получает количество ссылок
-    pub fn strong_count(&self) -> usize {
-        Arc::strong_count(&self.data)
-    }
-}
+use std::time::Instant;
 
-/// NotSyncNotSend - !Sync и !Send
-/// 
-/// Этот тип не может быть ни перемещен между потоками (!Send),
-/// ни безопасно разделен между потоками (!Sync).
-/// 
-/// Реализация использует Rc<RefCell<T>>, который не является ни Send, ни Sync.
-#[derive(Debug, Clone)]
-pub struct NotSyncNotSend<T> {
-    /// Rc<RefCell<T>> не является ни Send, ни Sync
-    /// Rc не является Send (не может быть перемещен между потоками)
-    /// RefCell не является Sync (не может быть разделен между потоками)
-    data: Rc<RefCell<T>>,
-    /// PhantomData для дополнительной информации о типе
-    _phantom: PhantomData<T>,
-}
-
-impl<T> NotSyncNotSend<T> {
-    /// Создает новый экземпляр NotSyncNotSend
-    pub fn new(data: T) -> Self {
-        Self {
-            data: Rc::new(RefCell::new(data)),
-            _phantom: PhantomData,
-        }
-    }
-    
-    /// Получает неизменяемую ссылку на данные
-    pub fn get(&self) -> std::cell::Ref<'_, T> {
-        self.data.borrow()
-    }
-    
-    /// Получает изменяемую ссылку на данные
-    pub fn get_mut(&self) -> std::cell::RefMut<'_, T> {
-        self.data.borrow_mut()
-    }
-    
-    /// Получает количество ссылок
-    pub fn strong_count(&self) -> usize {
-        Rc::strong_count(&self.data)
-    }
-}
+use step_1_8::{
+    NotSyncNotSend, OnlySend, OnlySync, SharedCounter, SyncAndSend, TrackedMutex, parallel_map,
+    with_scoped_chunks, with_scoped_threads,
+};
 
 /// Демонстрация работы с OnlySync
 fn demonstrate_only_sync() {
@@ -172,7 +18,8 @@ fn demonstrate_only_sync() {
     let _only_sync_clone = only_sync.clone();
     println!("Клонирован OnlySync, количество ссылок: {}", only_sync.strong_count());
     
-    // Попытка отправить OnlySync в другой поток приведет к ошибке компиляции
+    // Попытка отправить OnlySync в другой поток приведет к ошибке компиляции,
+    // см. tests/compile_fail
     // Это демонстрирует, что OnlySync не является Send
     println!("OnlySync не может быть отправлен в другой поток (не Send)");
     
@@ -203,7 +50,7 @@ fn demonstrate_sync_and_send() {
     println!("=== Демонстрация SyncAndSend (и Sync, и Send) ===");
     
     let sync_and_send = SyncAndSend::new(42);
-    println!("Создан SyncAndSend с значением: {}", sync_and_send.get());
+    println!("Создан SyncAndSend с значением: {}", *sync_and_send.get());
     
     // SyncAndSend является и Sync, и Send
     let sync_and_send_clone = sync_and_send.clone();
@@ -211,7 +58,7 @@ fn demonstrate_sync_and_send() {
     
     // Можно отправить в другой поток
     let handle = thread::spawn(move || {
-        println!("В другом потоке: {}", sync_and_send_clone.get());
+        println!("В другом потоке: {}", *sync_and_send_clone.get());
     });
     
     handle.join().unwrap();
@@ -229,7 +76,8 @@ fn demonstrate_not_sync_not_send() {
     let _not_sync_not_send_clone = not_sync_not_send.clone();
     println!("Клонирован NotSyncNotSend, количество ссылок: {}", not_sync_not_send.strong_count());
     
-    // Попытка отправить NotSyncNotSend в другой поток приведет к ошибке компиляции
+    // Попытка отправить NotSyncNotSend в другой поток приведет к ошибке компиляции,
+    // см. tests/compile_fail
     // Это демонстрирует, что NotSyncNotSend не является Send
     println!("NotSyncNotSend не может быть отправлен в другой поток (не Send)");
     println!("NotSyncNotSend не может быть разделен между потоками (не Sync)\n");
@@ -240,6 +88,7 @@ fn demonstrate_fearless_concurrency() {
     println!("=== Демонстрация Fearless Concurrency ===");
     
     // Создаем данные, которые будут разделены между потоками
+    let started = Instant::now();
     let shared_data = Arc::new(Mutex::new(0));
     let mut handles = vec![];
     
@@ -261,10 +110,48 @@ fn demonstrate_fearless_concurrency() {
         handle.join().unwrap();
     }
     
+    let with_mutex = started.elapsed();
     println!("Финальное значение: {}", shared_data.lock().unwrap());
+
+    // Тот же подсчет на атомарном счетчике: потоки не ждут блокировку
+    let started = Instant::now();
+    let counter = SharedCounter::new(0);
+    let handles: Vec<_> = (0..5)
+        .map(|_| {
+            let counter = counter.clone();
+            thread::spawn(move || (0..1000).for_each(|_| _ = counter.fetch_add(1)))
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let with_atomic = started.elapsed();
+    println!("Атомарный счетчик: {}", counter.get());
+    println!("Время с Mutex: {:?}, с атомиком: {:?}", with_mutex, with_atomic);
     println!("Все потоки завершили работу без data races!\n");
 }
 
+/// Демонстрация потоков, одалживающих данные без Arc
+fn demonstrate_scoped_threads() {
+    println!("=== Демонстрация scoped-потоков ===");
+
+    // Потоки читают общий вектор по ссылке, не забирая его
+    let words = vec!["scoped", "threads", "borrow"];
+    let lens = with_scoped_threads(&words, words.len(), |i, words| words[i].len());
+    println!("Длины слов: {:?}", lens);
+
+    // Каждый поток меняет свою часть среза
+    let mut numbers: Vec<u32> = (1..=8).collect();
+    with_scoped_chunks(&mut numbers, 2, |part, chunk| {
+        chunk.iter_mut().for_each(|x| *x += 100 * part as u32)
+    });
+    println!("Части изменены на месте: {:?}", numbers);
+
+    let squares = parallel_map(&numbers, 4, |x| x * x);
+    println!("Квадраты: {:?}", squares);
+    println!("Вектор по-прежнему наш: {} элементов\n", numbers.len());
+}
+
 /// Демонстрация различных типов синхронизации
 fn demonstrate_synchronization_types() {
     println!("=== Демонстрация различных типов синхронизации ===");
@@ -299,6 +186,42 @@ fn demonstrate_synchronization_types() {
     println!("Оба типа синхронизации работают корректно!\n");
 }
 
+/// Демонстрация проверки порядка захвата мьютексов
+fn demonstrate_lock_ordering() {
+    println!("=== Демонстрация порядка захвата мьютексов ===");
+
+    let accounts = TrackedMutex::named("accounts", 100);
+    let audit = TrackedMutex::named("audit", Vec::new());
+
+    // Переводы всегда берут счета раньше журнала
+    let (accounts_ref, audit_ref) = (&accounts, &audit);
+    thread::scope(|s| {
+        for amount in [10, 20] {
+            s.spawn(move || {
+                let mut balance = accounts_ref.lock();
+                *balance -= amount;
+                audit_ref.lock().push(amount);
+            });
+        }
+    });
+    println!("Баланс: {}, журнал: {:?}", *accounts.lock(), *audit.lock());
+
+    if !cfg!(debug_assertions) {
+        println!("В релизной сборке порядок не проверяется\n");
+        return;
+    }
+    // Обратный порядок может заблокировать потоки друг о друга, поэтому
+    // TrackedMutex паникует еще до ожидания
+    let reversed = thread::scope(|s| {
+        s.spawn(|| {
+            let _audit = audit.lock();
+            let _accounts = accounts.lock();
+        })
+        .join()
+    });
+    println!("Обратный порядок обнаружен: {}\n", reversed.is_err());
+}
+
 fn main() {
     println!("=== Демонстрация Thread Safety в Rust ===\n");
     
@@ -310,6 +233,8 @@ fn main() {
     
     // Демонстрация fearless concurrency
     demonstrate_fearless_concurrency();
+    demonstrate_scoped_threads();
+    demonstrate_lock_ordering();
     
     // Демонстрация различных типов синхронизации
     demonstrate_synchronization_types();
@@ -326,7 +251,7 @@ fn main() {
     println!("гарантирует отсутствие data races на этапе компиляции!");
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(feature = "loom")))]
 mod tests {
     use super::*;
     use static_assertions::{assert_impl_all, assert_not_impl_any};
@@ -447,4 +372,85 @@ mod tests {
         // Проверяем, что изменения видны через другую ссылку
         assert_eq!(*clone.get(), 42);
     }
-}
\ No newline at end of file
+}
+
+/// Исчерпывающая проверка чередований потоков, см. фичу `loom`
+#[cfg(all(test, feature = "loom"))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+    use step_1_8::{SharedCounter, SharedFlag, SharedRw};
+
+    #[test]
+    fn sync_and_send_clones_mutate_without_lost_updates() {
+        loom::model(|| {
+            let counter = SyncAndSend::new(0);
+            let clone = counter.clone();
+            let handle = thread::spawn(move || *clone.get() += 1);
+            *counter.get() += 1;
+            handle.join().unwrap();
+
+            assert_eq!(*counter.get(), 2);
+            assert_eq!(counter.strong_count(), 1);
+        });
+    }
+
+    #[test]
+    fn shared_rw_reader_never_sees_half_written_data() {
+        loom::model(|| {
+            let shared = SharedRw::new((0, 0));
+            let clone = shared.clone();
+            let handle = thread::spawn(move || {
+                let mut pair = clone.write();
+                pair.0 = 1;
+                pair.1 = 1;
+            });
+            let (first, second) = *shared.read();
+            handle.join().unwrap();
+
+            assert_eq!(first, second);
+            assert_eq!(*shared.read(), (1, 1));
+        });
+    }
+
+    #[test]
+    fn shared_counter_loses_no_increments_and_flag_has_one_winner() {
+        loom::model(|| {
+            let counter = SharedCounter::new(0);
+            let flag = SharedFlag::default();
+            let handle = {
+                let (counter, flag) = (counter.clone(), flag.clone());
+                thread::spawn(move || {
+                    counter.fetch_add(1);
+                    flag.compare_exchange(false, true).is_ok()
+                })
+            };
+            counter.fetch_add(1);
+            let won_here = flag.compare_exchange(false, true).is_ok();
+            let won_there = handle.join().unwrap();
+
+            assert_eq!(counter.get(), 2);
+            assert!(won_here != won_there);
+        });
+    }
+
+    #[test]
+    fn only_sync_is_shared_by_reference() {
+        loom::model(|| {
+            // OnlySync нельзя переместить в поток, только одолжить ему
+            // ссылку, а `thread::spawn` требует `'static`
+            let raw = Box::into_raw(Box::new(OnlySync::new(0)));
+            // SAFETY: указатель получен из `Box` и освобождается ниже,
+            // когда поток, получивший ссылку, уже завершен
+            let shared: &'static OnlySync<i32> = unsafe { &*raw };
+            let handle = thread::spawn(move || *shared.get_mut() += 1);
+            let seen = *shared.get();
+            handle.join().unwrap();
+
+            assert!(seen == 0 || seen == 1);
+            assert_eq!(*shared.get(), 1);
+            // SAFETY: см. выше, других ссылок на буфер не осталось
+            drop(unsafe { Box::from_raw(raw) });
+        });
+    }
+}
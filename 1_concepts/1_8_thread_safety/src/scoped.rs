@@ -0,0 +1,144 @@
+//! Потоки, одалживающие данные вызывающего вместо владения ими
+//!
+//! `std::thread::scope` дожидается всех своих потоков до возврата, поэтому
+//! им можно передать `&T` или `&mut [T]` без `Arc`: заимствование гарантированно
+//! переживет потоки. Паника в потоке пробрасывается вызывающему как есть.
+
+use std::panic;
+use std::thread::{self, ScopedJoinHandle};
+
+/// Запускает `worker` в `worker_count` потоках, разделяющих `data`
+///
+/// Поток получает свой номер от `0` и ссылку на данные. Результаты
+/// возвращаются в порядке номеров. `worker_count == 0` считается за один.
+pub fn with_scoped_threads<T, R, F>(data: &T, worker_count: usize, worker: F) -> Vec<R>
+where
+    T: Sync + ?Sized,
+    R: Send,
+    F: Fn(usize, &T) -> R + Sync,
+{
+    thread::scope(|s| {
+        let handles: Vec<_> = (0..worker_count.max(1))
+            .map(|index| {
+                let worker = &worker;
+                s.spawn(move || worker(index, data))
+            })
+            .collect();
+        join_all(handles)
+    })
+}
+
+/// Делит `data` на `worker_count` непрерывных частей почти равной длины и
+/// отдает каждую своему потоку на изменение
+///
+/// Поток получает номер части и саму часть, результаты возвращаются в
+/// порядке частей. Частей не больше, чем элементов, а пустой срез не
+/// запускает ни одного потока.
+pub fn with_scoped_chunks<T, R, F>(data: &mut [T], worker_count: usize, worker: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(usize, &mut [T]) -> R + Sync,
+{
+    if data.is_empty() {
+        return Vec::new();
+    }
+    let chunk_len = data.len().div_ceil(worker_count.max(1));
+    thread::scope(|s| {
+        let handles: Vec<_> = data
+            .chunks_mut(chunk_len)
+            .enumerate()
+            .map(|(index, chunk)| {
+                let worker = &worker;
+                s.spawn(move || worker(index, chunk))
+            })
+            .collect();
+        join_all(handles)
+    })
+}
+
+/// Применяет `f` к каждому элементу в `worker_count` потоках, сохраняя
+/// порядок элементов
+pub fn parallel_map<T, U, F>(data: &[T], worker_count: usize, f: F) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T) -> U + Sync,
+{
+    if data.is_empty() {
+        return Vec::new();
+    }
+    let chunk_len = data.len().div_ceil(worker_count.max(1));
+    thread::scope(|s| {
+        let handles: Vec<_> = data
+            .chunks(chunk_len)
+            .map(|chunk| {
+                let f = &f;
+                s.spawn(move || chunk.iter().map(f).collect::<Vec<_>>())
+            })
+            .collect();
+        join_all(handles).into_iter().flatten().collect()
+    })
+}
+
+/// Дожидается потоков по порядку, пробрасывая панику с исходным сообщением
+fn join_all<R>(handles: Vec<ScopedJoinHandle<'_, R>>) -> Vec<R> {
+    handles
+        .into_iter()
+        .map(|handle| {
+            handle
+                .join()
+                .unwrap_or_else(|err| panic::resume_unwind(err))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_threads_borrow_callers_data() {
+        let words = vec!["a", "bb", "ccc"];
+        let seen = AtomicUsize::new(0);
+        let lens = with_scoped_threads(&words, 3, |index, words| {
+            seen.fetch_add(1, Ordering::Relaxed);
+            words[index].len()
+        });
+        assert_eq!(lens, [1, 2, 3]);
+        assert_eq!(seen.into_inner(), 3);
+        assert_eq!(with_scoped_threads("shared", 0, |_, s| s.len()), [6]);
+
+        let mut numbers: Vec<u32> = (1..=10).collect();
+        let sums = with_scoped_chunks(&mut numbers, 3, |_, chunk| {
+            chunk.iter_mut().for_each(|x| *x *= 10);
+            chunk.iter().sum::<u32>()
+        });
+        assert_eq!(sums, [100, 260, 190]);
+        assert_eq!(numbers[9], 100);
+        assert_eq!(with_scoped_chunks(&mut [1], 8, |index, _| index), [0]);
+        assert!(with_scoped_chunks(&mut [0u8; 0], 2, |_, _| ()).is_empty());
+    }
+
+    #[test]
+    fn test_parallel_map_keeps_order_and_propagates_panics() {
+        let data: Vec<u64> = (0..100).collect();
+        let squares = parallel_map(&data, 4, |x| x * x);
+        assert_eq!(squares, data.iter().map(|x| x * x).collect::<Vec<_>>());
+        assert!(parallel_map(&[] as &[u8], 4, |x| *x).is_empty());
+
+        let panicked = panic::catch_unwind(|| {
+            parallel_map(&data, 2, |&x| {
+                if x == 99 {
+                    panic!("плохой элемент")
+                } else {
+                    x
+                }
+            })
+        });
+        let message = panicked.unwrap_err();
+        assert_eq!(message.downcast_ref::<&str>(), Some(&"плохой элемент"));
+    }
+}
@@ -0,0 +1,277 @@
+//! Проверка порядка захвата мьютексов
+//!
+//! Взаимная блокировка возможна, только если два потока захватывают одни и
+//! те же мьютексы в разном порядке. [`TrackedMutex`] запоминает, какие
+//! мьютексы поток уже держит (стек в thread-local), и складывает пары
+//! "захвачен под" в общий граф порядка. Захват, замыкающий в графе цикл,
+//! означает, что при неудачном чередовании потоки заблокируют друг друга, и в
+//! отладочной сборке вызывает панику еще до ожидания. В релизной сборке
+//! проверки выключены и обертка сводится к обычному `Mutex`.
+//!
+//! Граф строится на `std` и не моделируется loom.
+
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex, MutexGuard, PoisonError};
+
+/// Порядок захвата, наблюдавшийся во всех потоках
+#[derive(Default)]
+struct OrderGraph {
+    /// Мьютексы, захваченные под данным
+    after: HashMap<usize, HashSet<usize>>,
+    names: HashMap<usize, String>,
+}
+
+impl OrderGraph {
+    /// Путь от `from` до `to` по ребрам графа, если он есть
+    fn path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        let mut parents = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(id) = queue.pop_front() {
+            if id == to {
+                let mut path = vec![to];
+                while let Some(&parent) = path.last().and_then(|last| parents.get(last)) {
+                    path.push(parent);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &next in self.after.get(&id).into_iter().flatten() {
+                if next == from {
+                    continue;
+                }
+                if let Entry::Vacant(entry) = parents.entry(next) {
+                    entry.insert(id);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    fn describe(&self, path: &[usize]) -> String {
+        path.iter()
+            .map(|id| format!("`{}`", self.names[id]))
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    }
+}
+
+static ORDER: LazyLock<Mutex<OrderGraph>> = LazyLock::new(Default::default);
+
+thread_local! {
+    /// Мьютексы, которые держит текущий поток, в порядке захвата
+    static HELD: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+fn order() -> MutexGuard<'static, OrderGraph> {
+    ORDER.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// `Mutex`, проверяющий в отладочной сборке порядок захвата
+///
+/// Паникует, если поток захватывает мьютекс, который уже держит, или
+/// мьютекс, который когда-либо захватывался под одним из удерживаемых сейчас.
+pub struct TrackedMutex<T> {
+    id: usize,
+    inner: Mutex<T>,
+}
+
+impl<T> TrackedMutex<T> {
+    /// Создает мьютекс с именем по его номеру
+    pub fn new(value: T) -> Self {
+        Self::create(None, value)
+    }
+
+    /// Создает мьютекс с именем для сообщений о нарушении порядка
+    pub fn named(name: impl Into<String>, value: T) -> Self {
+        Self::create(Some(name.into()), value)
+    }
+
+    fn create(name: Option<String>, value: T) -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        if cfg!(debug_assertions) {
+            let name = name.unwrap_or_else(|| format!("#{id}"));
+            order().names.insert(id, name);
+        }
+        Self {
+            id,
+            inner: Mutex::new(value),
+        }
+    }
+
+    /// Захватывает мьютекс, дожидаясь его освобождения
+    ///
+    /// Паника в другом потоке под этим мьютексом не делает данные
+    /// недоступными.
+    #[track_caller]
+    pub fn lock(&self) -> TrackedMutexGuard<'_, T> {
+        if cfg!(debug_assertions) {
+            self.check_order();
+        }
+        let guard = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        if cfg!(debug_assertions) {
+            HELD.with_borrow_mut(|held| held.push(self.id));
+        }
+        TrackedMutexGuard { id: self.id, guard }
+    }
+
+    /// Записывает в граф захват под удерживаемыми мьютексами, паникуя, если
+    /// он противоречит записанному раньше
+    #[track_caller]
+    fn check_order(&self) {
+        let held = HELD.with_borrow(Clone::clone);
+        let conflict = {
+            let mut order = order();
+            let conflict = self.conflict(&order, &held);
+            if conflict.is_none() {
+                for &outer in &held {
+                    order.after.entry(outer).or_default().insert(self.id);
+                }
+            }
+            conflict
+        };
+        if let Some(message) = conflict {
+            panic!("{message}");
+        }
+    }
+
+    fn conflict(&self, order: &OrderGraph, held: &[usize]) -> Option<String> {
+        let name = &order.names[&self.id];
+        if held.contains(&self.id) {
+            return Some(format!(
+                "мьютекс `{name}` захватывается повторно тем же потоком"
+            ));
+        }
+        held.iter().find_map(|outer| {
+            let path = order.path(self.id, *outer)?;
+            Some(format!(
+                "возможна взаимная блокировка: `{name}` захватывается под `{}`, \
+                 хотя раньше был порядок {}",
+                order.names[outer],
+                order.describe(&path),
+            ))
+        })
+    }
+}
+
+impl<T> Drop for TrackedMutex<T> {
+    fn drop(&mut self) {
+        if cfg!(debug_assertions) {
+            let mut order = order();
+            order.names.remove(&self.id);
+            order.after.remove(&self.id);
+            for after in order.after.values_mut() {
+                after.remove(&self.id);
+            }
+        }
+    }
+}
+
+impl<T: Default> Default for TrackedMutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> std::fmt::Debug for TrackedMutex<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrackedMutex")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Захват [`TrackedMutex`], снимающий мьютекс со стека потока при освобождении
+pub struct TrackedMutexGuard<'a, T> {
+    id: usize,
+    guard: MutexGuard<'a, T>,
+}
+
+impl<T> Deref for TrackedMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for TrackedMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for TrackedMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        if cfg!(debug_assertions) {
+            // Захваты не обязаны освобождаться в обратном порядке
+            HELD.with_borrow_mut(|held| {
+                if let Some(at) = held.iter().rposition(|&id| id == self.id) {
+                    held.remove(at);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use std::panic;
+    use std::thread;
+
+    use super::*;
+
+    fn panic_message(f: impl FnOnce() + panic::UnwindSafe) -> String {
+        let payload = panic::catch_unwind(f).unwrap_err();
+        payload
+            .downcast::<String>()
+            .map(|message| *message)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_reversed_order_is_reported_before_waiting() {
+        let a = TrackedMutex::named("a", 0);
+        let b = TrackedMutex::named("b", 0);
+        let c = TrackedMutex::named("c", 0);
+        thread::scope(|s| {
+            s.spawn(|| {
+                let _a = a.lock();
+                let _b = b.lock();
+            });
+        });
+        {
+            // Освобождение не в обратном порядке не путает стек потока
+            let b = b.lock();
+            let _c = c.lock();
+            drop(b);
+        }
+
+        let message = panic_message(|| {
+            let _c = c.lock();
+            let _a = a.lock();
+        });
+        assert!(message.contains("`a` захватывается под `c`"), "{message}");
+        assert!(message.contains("`a` -> `b` -> `c`"), "{message}");
+
+        // Паника сняла `c` со стека, согласованный порядок по-прежнему работает
+        let _a = a.lock();
+        *c.lock() += 1;
+    }
+
+    #[test]
+    fn test_relocking_same_mutex_is_reported() {
+        let mutex = TrackedMutex::new(());
+        let message = panic_message(|| {
+            let _first = mutex.lock();
+            let _second = mutex.lock();
+        });
+        assert!(message.contains("повторно"), "{message}");
+        drop(mutex.lock());
+    }
+}
@@ -0,0 +1,12 @@
+//! Примеры, которые не должны компилироваться: каждый пытается обойти
+//! отсутствующий у типа авто-трейт
+//!
+//! Ожидаемые ошибки лежат рядом в `.stderr`, после смены компилятора их
+//! обновляют через `TRYBUILD=overwrite cargo test -p step_1_8`.
+
+#![cfg(not(feature = "loom"))]
+
+#[test]
+fn missing_auto_traits_are_enforced() {
+    trybuild::TestCases::new().compile_fail("tests/compile_fail/*.rs");
+}
@@ -0,0 +1,10 @@
+use step_1_8::{SendOnly, SyncOnly};
+
+fn require_send<T: Send>(_: T) {}
+
+fn require_sync<T: Sync>(_: &T) {}
+
+fn main() {
+    require_send(SyncOnly::new(42));
+    require_sync(&SendOnly::new(42));
+}
@@ -0,0 +1,9 @@
+use step_1_8::NotSyncNotSend;
+
+fn main() {
+    let shared = NotSyncNotSend::new(42);
+    std::thread::scope(|s| {
+        s.spawn(|| *shared.get());
+    });
+    std::thread::spawn(move || *shared.get_mut() += 1);
+}
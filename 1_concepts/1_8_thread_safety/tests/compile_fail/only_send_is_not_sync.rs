@@ -0,0 +1,8 @@
+use step_1_8::OnlySend;
+
+fn main() {
+    let only_send = OnlySend::new(42);
+    std::thread::scope(|s| {
+        s.spawn(|| *only_send.get());
+    });
+}
@@ -0,0 +1,6 @@
+use step_1_8::OnlySync;
+
+fn main() {
+    let only_sync = OnlySync::new(42);
+    std::thread::spawn(move || *only_sync.get_mut() += 1);
+}
@@ -0,0 +1,92 @@
+//! Сравнение ThreadSafeDoublyLinkedList с одной блокировкой на весь список,
+//! как было раньше, когда два потока работают с противоположными концами.
+//!
+//! Запуск: cargo bench -p step_1 --bench thread_safe_list
+
+use std::sync::Mutex;
+use std::thread;
+
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use step_1::{DoublyLinkedList, ThreadSafeDoublyLinkedList};
+
+const OPS: u32 = 10_000;
+
+/// Операции над концами списка, общие для обеих реализаций
+trait Deque: Sync {
+    fn push_front(&self, data: u32);
+    fn push_back(&self, data: u32);
+    fn pop_front(&self) -> Option<u32>;
+}
+
+impl Deque for Mutex<DoublyLinkedList<u32>> {
+    fn push_front(&self, data: u32) {
+        self.lock().unwrap().push_front(data);
+    }
+
+    fn push_back(&self, data: u32) {
+        self.lock().unwrap().push_back(data);
+    }
+
+    fn pop_front(&self) -> Option<u32> {
+        self.lock().unwrap().pop_front()
+    }
+}
+
+impl Deque for ThreadSafeDoublyLinkedList<u32> {
+    fn push_front(&self, data: u32) {
+        ThreadSafeDoublyLinkedList::push_front(self, data);
+    }
+
+    fn push_back(&self, data: u32) {
+        ThreadSafeDoublyLinkedList::push_back(self, data);
+    }
+
+    fn pop_front(&self) -> Option<u32> {
+        ThreadSafeDoublyLinkedList::pop_front(self)
+    }
+}
+
+/// Один поток добавляет в начало, другой — в конец
+fn push_both_ends(list: &impl Deque) {
+    thread::scope(|s| {
+        s.spawn(|| (0..OPS).for_each(|i| list.push_front(black_box(i))));
+        s.spawn(|| (0..OPS).for_each(|i| list.push_back(black_box(i))));
+    });
+}
+
+/// Производитель добавляет в конец, потребитель забирает из начала
+fn producer_consumer(list: &impl Deque) {
+    thread::scope(|s| {
+        s.spawn(|| (0..OPS).for_each(|i| list.push_back(black_box(i))));
+        s.spawn(|| {
+            let mut consumed = 0;
+            while consumed < OPS {
+                match list.pop_front() {
+                    Some(_) => consumed += 1,
+                    None => thread::yield_now(),
+                }
+            }
+        });
+    });
+}
+
+fn opposite_ends(c: &mut Criterion) {
+    let mut group = c.benchmark_group("opposite_ends_10k");
+    group.sample_size(20);
+    group.bench_function("push/single_lock", |b| {
+        b.iter(|| push_both_ends(&Mutex::new(DoublyLinkedList::new())))
+    });
+    group.bench_function("push/two_locks", |b| {
+        b.iter(|| push_both_ends(&ThreadSafeDoublyLinkedList::new()))
+    });
+    group.bench_function("queue/single_lock", |b| {
+        b.iter(|| producer_consumer(&Mutex::new(DoublyLinkedList::new())))
+    });
+    group.bench_function("queue/two_locks", |b| {
+        b.iter(|| producer_consumer(&ThreadSafeDoublyLinkedList::new()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, opposite_ends);
+criterion_main!(benches);
@@ -0,0 +1,2006 @@
+//! Двусвязный список на `Arc`/`Weak` и его потокобезопасные обертки:
+//! `ThreadSafeDoublyLinkedList` и очередь `BoundedBlockingDeque`
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::time::{Duration, Instant};
+
+/// Блокировки, через которые потоки разделяют `ThreadSafeDoublyLinkedList`
+///
+/// С фичей `loom` их подменяют модели loom, перебирающие все чередования
+/// потоков. Узлы остаются на `std`: их трогают только под этими блокировками,
+/// а `Weak` в loom нет.
+mod shared {
+    #[cfg(feature = "loom")]
+    pub use loom::sync::{Arc, Condvar, Mutex, MutexGuard};
+    #[cfg(not(feature = "loom"))]
+    pub use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+}
+
+/// Узел двусвязного списка
+///
+/// Сильные ссылки на узел есть только у списка: у предыдущего узла (или
+/// `head`) и у `tail`. Итераторы, курсоры и хэндлы держат слабые ссылки,
+/// поэтому отцепленный узел принадлежит вызывающему единолично и данные
+/// можно забрать из него, не оставляя в узле `Option`.
+#[derive(Debug)]
+struct Node<T> {
+    data: T,
+    /// Метка списка, в котором сейчас узел, см. [`NodeHandle`]
+    owner: Arc<Owner>,
+    next: Option<Arc<Mutex<Node<T>>>>,
+    prev: Option<Weak<Mutex<Node<T>>>>,
+}
+
+impl<T> Node<T> {
+    fn new(data: T, owner: Arc<Owner>) -> Self {
+        Node {
+            data,
+            owner,
+            next: None,
+            prev: None,
+        }
+    }
+
+    /// Забирает данные отцепленного узла
+    ///
+    /// Другой поток может в этот момент проверять по хэндлу, не лежит ли узел
+    /// в его списке, и ненадолго держать сильную ссылку, см. `owned_node`.
+    /// Проверка не ждет никаких блокировок, кроме блокировки самого узла и
+    /// меток, поэтому ее окончания можно просто дождаться.
+    fn into_data(mut node: Arc<Mutex<Node<T>>>) -> T {
+        let node = loop {
+            match Arc::try_unwrap(node) {
+                Ok(node) => break node,
+                Err(checked) => {
+                    node = checked;
+                    std::thread::yield_now();
+                }
+            }
+        };
+        node.into_inner().unwrap_or_else(PoisonError::into_inner).data
+    }
+}
+
+/// Thread-safe двусвязный список
+#[derive(Debug)]
+pub struct DoublyLinkedList<T> {
+    head: Option<Arc<Mutex<Node<T>>>>,
+    tail: Option<Arc<Mutex<Node<T>>>>,
+    len: usize,
+    /// Метка, которой помечены узлы списка; у списка она всегда корневая
+    owner: Arc<Owner>,
+}
+
+/// Метка принадлежности узлов списку
+///
+/// `append` не перемечает перенесенные узлы, а перенаправляет метку
+/// опустевшего списка на метку принимающего, поэтому занимает O(1). Узел
+/// лежит в списке, если перенаправления с его метки ведут к метке списка.
+#[derive(Debug, Default)]
+struct Owner {
+    forward: Mutex<Option<Arc<Owner>>>,
+}
+
+impl Owner {
+    /// Метка, к которой в итоге ведут перенаправления с `owner`
+    ///
+    /// Пройденные метки перенаправляются сразу на найденную, чтобы следующие
+    /// поиски были короче.
+    fn root(owner: &Arc<Owner>) -> Arc<Owner> {
+        let mut root = owner.clone();
+        loop {
+            let next = root.forward.lock().unwrap().clone();
+            match next {
+                Some(next) => root = next,
+                None => break,
+            }
+        }
+        let mut current = owner.clone();
+        while !Arc::ptr_eq(&current, &root) {
+            let next = current.forward.lock().unwrap().replace(root.clone());
+            current = next.expect("метка ведет к корневой");
+        }
+        root
+    }
+}
+
+/// Стабильная ссылка на элемент [`DoublyLinkedList`]
+///
+/// Возвращается из `push_front` и `push_back` этого списка и
+/// [`ThreadSafeDoublyLinkedList`] и остается действительной,
+/// пока элемент лежит в списке, как бы ни менялись остальные элементы. Хэндл
+/// не продлевает жизнь элемента: если элемент удален или лежит в другом
+/// списке, операции по хэндлу вернут `None`.
+pub struct NodeHandle<T> {
+    node: Weak<Mutex<Node<T>>>,
+}
+
+impl<T> Clone for NodeHandle<T> {
+    fn clone(&self) -> Self {
+        NodeHandle {
+            node: self.node.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for NodeHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NodeHandle")
+            .field("alive", &(self.node.strong_count() > 0))
+            .finish()
+    }
+}
+
+impl<T> DoublyLinkedList<T> {
+    /// Создает новый пустой список
+    pub fn new() -> Self {
+        DoublyLinkedList {
+            head: None,
+            tail: None,
+            len: 0,
+            owner: Arc::default(),
+        }
+    }
+
+    /// Возвращает количество элементов в списке
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Проверяет, пуст ли список
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Добавляет элемент в начало списка и возвращает хэндл на него
+    pub fn push_front(&mut self, data: T) -> NodeHandle<T> {
+        let new_node = Arc::new(Mutex::new(Node::new(data, self.owner.clone())));
+        let handle = NodeHandle { node: Arc::downgrade(&new_node) };
+        
+        match self.head.take() {
+            Some(old_head) => {
+                old_head.lock().unwrap().prev = Some(Arc::downgrade(&new_node));
+                new_node.lock().unwrap().next = Some(old_head);
+            }
+            None => {
+                self.tail = Some(new_node.clone());
+            }
+        }
+        
+        self.head = Some(new_node);
+        self.len += 1;
+        handle
+    }
+
+    /// Добавляет элемент в конец списка и возвращает хэндл на него
+    pub fn push_back(&mut self, data: T) -> NodeHandle<T> {
+        let new_node = Arc::new(Mutex::new(Node::new(data, self.owner.clone())));
+        let handle = NodeHandle { node: Arc::downgrade(&new_node) };
+        
+        match self.tail.take() {
+            Some(old_tail) => {
+                old_tail.lock().unwrap().next = Some(new_node.clone());
+                new_node.lock().unwrap().prev = Some(Arc::downgrade(&old_tail));
+            }
+            None => {
+                self.head = Some(new_node.clone());
+            }
+        }
+        
+        self.tail = Some(new_node);
+        self.len += 1;
+        handle
+    }
+
+    /// Удаляет и возвращает первый элемент списка
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.head.take().map(|old_head| {
+            match old_head.lock().unwrap().next.take() {
+                Some(new_head) => {
+                    new_head.lock().unwrap().prev = None;
+                    self.head = Some(new_head);
+                }
+                None => {
+                    self.tail = None;
+                }
+            }
+            self.len -= 1;
+            Node::into_data(old_head)
+        })
+    }
+
+    /// Удаляет и возвращает последний элемент списка
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.tail.take().map(|old_tail| {
+            match old_tail.lock().unwrap().prev.take() {
+                Some(prev_weak) => {
+                    if let Some(prev) = prev_weak.upgrade() {
+                        prev.lock().unwrap().next = None;
+                        self.tail = Some(prev);
+                    } else {
+                        self.head = None;
+                    }
+                }
+                None => {
+                    self.head = None;
+                }
+            }
+            self.len -= 1;
+            Node::into_data(old_tail)
+        })
+    }
+}
+
+impl<T> Default for DoublyLinkedList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Освобождает узлы по одному
+///
+/// Иначе drop первого узла рекурсивно освобождал бы всю цепочку `next` и
+/// переполнял стек на длинных списках.
+impl<T> Drop for DoublyLinkedList<T> {
+    fn drop(&mut self) {
+        self.tail = None;
+        let mut current = self.head.take();
+        while let Some(node) = current {
+            current = node.lock().unwrap_or_else(PoisonError::into_inner).next.take();
+        }
+    }
+}
+
+impl<T> DoublyLinkedList<T> {
+    /// Возвращает курсор, стоящий на первом элементе
+    ///
+    /// В пустом списке курсор стоит на "призрачном" элементе, см. [`Cursor`].
+    pub fn cursor_front(&mut self) -> Cursor<'_, T> {
+        let current = self.head.as_ref().map(Arc::downgrade);
+        Cursor {
+            list: self,
+            current,
+            index: 0,
+        }
+    }
+
+    /// Возвращает курсор, стоящий на последнем элементе
+    pub fn cursor_back(&mut self) -> Cursor<'_, T> {
+        let current = self.tail.as_ref().map(Arc::downgrade);
+        let index = self.len.saturating_sub(1);
+        Cursor {
+            list: self,
+            current,
+            index,
+        }
+    }
+
+    /// Вставляет новый узел между `prev` и `next`, которые должны быть соседями
+    fn link_between(
+        &mut self,
+        prev: Option<Arc<Mutex<Node<T>>>>,
+        next: Option<Arc<Mutex<Node<T>>>>,
+        data: T,
+    ) {
+        let new_node = Arc::new(Mutex::new(Node::new(data, self.owner.clone())));
+        {
+            let mut node = new_node.lock().unwrap();
+            node.prev = prev.as_ref().map(Arc::downgrade);
+            node.next = next.clone();
+        }
+        match prev {
+            Some(prev) => prev.lock().unwrap().next = Some(new_node.clone()),
+            None => self.head = Some(new_node.clone()),
+        }
+        match next {
+            Some(next) => next.lock().unwrap().prev = Some(Arc::downgrade(&new_node)),
+            None => self.tail = Some(new_node),
+        }
+        self.len += 1;
+    }
+
+    /// Исключает узел из списка, связывая его соседей друг с другом
+    fn unlink(&mut self, node: Arc<Mutex<Node<T>>>) -> T {
+        let (prev, next) = {
+            let mut node = node.lock().unwrap();
+            let prev = node.prev.take().and_then(|prev| prev.upgrade());
+            (prev, node.next.take())
+        };
+        match &prev {
+            Some(prev) => prev.lock().unwrap().next = next.clone(),
+            None => self.head = next.clone(),
+        }
+        match next {
+            Some(next) => next.lock().unwrap().prev = prev.as_ref().map(Arc::downgrade),
+            None => self.tail = prev,
+        }
+        self.len -= 1;
+        Node::into_data(node)
+    }
+}
+
+/// Ошибка позиционной операции: индекс за пределами списка
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexOutOfBounds {
+    pub index: usize,
+    pub len: usize,
+}
+
+impl fmt::Display for IndexOutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "индекс {} вне списка длины {}", self.index, self.len)
+    }
+}
+
+impl std::error::Error for IndexOutOfBounds {}
+
+impl<T> DoublyLinkedList<T> {
+    /// Вставляет элемент так, чтобы он оказался на позиции `index`
+    ///
+    /// `index == len()` добавляет элемент в конец.
+    pub fn insert_at(&mut self, index: usize, data: T) -> Result<(), IndexOutOfBounds> {
+        if index > self.len {
+            return Err(IndexOutOfBounds { index, len: self.len });
+        }
+        let next = self.node_at(index);
+        let prev = match &next {
+            Some(node) => node.lock().unwrap().prev.as_ref().and_then(Weak::upgrade),
+            None => self.tail.clone(),
+        };
+        self.link_between(prev, next, data);
+        Ok(())
+    }
+
+    /// Удаляет и возвращает элемент на позиции `index`
+    pub fn remove_at(&mut self, index: usize) -> Result<T, IndexOutOfBounds> {
+        let node = self
+            .node_at(index)
+            .ok_or(IndexOutOfBounds { index, len: self.len })?;
+        Ok(self.unlink(node))
+    }
+
+    /// Переносит все элементы `other` в конец списка за O(1), оставляя
+    /// `other` пустым
+    ///
+    /// Узлы не перемечаются: прежняя метка `other` перенаправляется на метку
+    /// этого списка, а `other` получает новую.
+    pub fn append(&mut self, other: &mut Self) {
+        let Some(other_head) = other.head.take() else {
+            return;
+        };
+        let moved = std::mem::take(&mut other.owner);
+        *moved.forward.lock().unwrap() = Some(self.owner.clone());
+        match &self.tail {
+            Some(tail) => {
+                other_head.lock().unwrap().prev = Some(Arc::downgrade(tail));
+                tail.lock().unwrap().next = Some(other_head);
+            }
+            None => self.head = Some(other_head),
+        }
+        self.tail = other.tail.take();
+        self.len += std::mem::take(&mut other.len);
+    }
+
+    /// Отделяет элементы начиная с позиции `at` в новый список
+    ///
+    /// Элементы не копируются, но узел на позиции `at` ищется от ближайшего
+    /// конца, а отделенные узлы перемечаются. `at == len()` возвращает пустой
+    /// список.
+    pub fn split_off(&mut self, at: usize) -> Result<Self, IndexOutOfBounds> {
+        if at > self.len {
+            return Err(IndexOutOfBounds { index: at, len: self.len });
+        }
+        let Some(head) = self.node_at(at) else {
+            return Ok(DoublyLinkedList::new());
+        };
+        let prev = head.lock().unwrap().prev.take().and_then(|prev| prev.upgrade());
+        match &prev {
+            Some(prev) => prev.lock().unwrap().next = None,
+            None => self.head = None,
+        }
+        let tail = std::mem::replace(&mut self.tail, prev);
+        let len = self.len - at;
+        self.len = at;
+        let owner = Arc::default();
+        relabel(&head, &owner);
+        Ok(DoublyLinkedList {
+            head: Some(head),
+            tail,
+            len,
+            owner,
+        })
+    }
+
+    /// Находит узел на позиции `index`, идя от ближайшего к нему конца
+    fn node_at(&self, index: usize) -> Option<Arc<Mutex<Node<T>>>> {
+        if index >= self.len {
+            return None;
+        }
+        if index < self.len / 2 {
+            let mut node = self.head.clone()?;
+            for _ in 0..index {
+                let next = node.lock().unwrap().next.clone()?;
+                node = next;
+            }
+            Some(node)
+        } else {
+            let mut node = self.tail.clone()?;
+            for _ in index + 1..self.len {
+                let prev = node.lock().unwrap().prev.as_ref().and_then(Weak::upgrade)?;
+                node = prev;
+            }
+            Some(node)
+        }
+    }
+}
+
+/// Помечает узлы начиная с `head` меткой `owner`
+fn relabel<T>(head: &Arc<Mutex<Node<T>>>, owner: &Arc<Owner>) {
+    let mut current = Some(head.clone());
+    while let Some(node) = current {
+        let mut node = node.lock().unwrap();
+        node.owner = owner.clone();
+        current = node.next.clone();
+    }
+}
+
+impl<T> DoublyLinkedList<T> {
+    /// Удаляет элемент по хэндлу за O(1)
+    ///
+    /// Возвращает `None`, если элемента уже нет в этом списке.
+    pub fn remove(&mut self, handle: &NodeHandle<T>) -> Option<T> {
+        let node = self.owned_node(handle)?;
+        Some(self.unlink(node))
+    }
+
+    /// Узел хэндла, если он лежит в этом списке
+    fn owned_node(&self, handle: &NodeHandle<T>) -> Option<Arc<Mutex<Node<T>>>> {
+        let node = handle.node.upgrade()?;
+        let owned = {
+            let mut node = node.lock().unwrap();
+            node.owner = Owner::root(&node.owner);
+            Arc::ptr_eq(&node.owner, &self.owner)
+        };
+        owned.then_some(node)
+    }
+}
+
+impl<T: Clone> DoublyLinkedList<T> {
+    /// Возвращает копию элемента на позиции `index`
+    pub fn get(&self, index: usize) -> Option<T> {
+        let node = self.node_at(index)?;
+        let data = node.lock().unwrap().data.clone();
+        Some(data)
+    }
+
+    /// Возвращает копию элемента по хэндлу за O(1)
+    pub fn get_by_handle(&self, handle: &NodeHandle<T>) -> Option<T> {
+        let node = self.owned_node(handle)?;
+        let data = node.lock().unwrap().data.clone();
+        Some(data)
+    }
+}
+
+impl<T> DoublyLinkedList<T> {
+    /// Проверяет, есть ли в списке элемент, равный `value`
+    pub fn contains(&self, value: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.position(|data| data == value).is_some()
+    }
+
+    /// Возвращает позицию первого элемента, удовлетворяющего предикату
+    pub fn position(&self, predicate: impl FnMut(&T) -> bool) -> Option<usize> {
+        self.find_node(predicate).map(|(index, _)| index)
+    }
+
+    /// Возвращает курсор, стоящий на первом элементе, удовлетворяющем предикату
+    ///
+    /// Через курсор найденный элемент можно удалить или вставить рядом новые.
+    pub fn find(&mut self, predicate: impl FnMut(&T) -> bool) -> Option<Cursor<'_, T>> {
+        let (index, node) = self.find_node(predicate)?;
+        Some(Cursor {
+            list: self,
+            current: Some(Arc::downgrade(&node)),
+            index,
+        })
+    }
+
+    /// Оставляет только элементы, удовлетворяющие предикату
+    ///
+    /// Остальные узлы отцепляются за один проход, порядок оставшихся не меняется.
+    pub fn retain(&mut self, mut predicate: impl FnMut(&T) -> bool) {
+        let mut current = self.head.clone();
+        while let Some(node) = current {
+            let (keep, next) = {
+                let node = node.lock().unwrap();
+                (predicate(&node.data), node.next.clone())
+            };
+            if !keep {
+                self.unlink(node);
+            }
+            current = next;
+        }
+    }
+
+    /// Находит первый узел, удовлетворяющий предикату, вместе с его позицией
+    fn find_node(
+        &self,
+        mut predicate: impl FnMut(&T) -> bool,
+    ) -> Option<(usize, Arc<Mutex<Node<T>>>)> {
+        let mut current = self.head.clone();
+        let mut index = 0;
+        while let Some(node) = current {
+            let (found, next) = {
+                let node = node.lock().unwrap();
+                (predicate(&node.data), node.next.clone())
+            };
+            if found {
+                return Some((index, node));
+            }
+            current = next;
+            index += 1;
+        }
+        None
+    }
+}
+
+/// Курсор для перемещения по списку в обе стороны и правки на месте
+///
+/// Повторяет нестабильный `std::collections::linked_list::CursorMut`: кроме
+/// элементов, курсор может стоять на "призрачном" элементе между последним
+/// и первым. С него `move_next` переходит в начало, а `move_prev` — в конец.
+pub struct Cursor<'a, T> {
+    list: &'a mut DoublyLinkedList<T>,
+    current: Option<Weak<Mutex<Node<T>>>>,
+    /// Позиция текущего элемента; на призрачном равна длине списка
+    index: usize,
+}
+
+impl<T> Cursor<'_, T> {
+    /// Возвращает позицию текущего элемента или `None` на призрачном
+    pub fn index(&self) -> Option<usize> {
+        self.current.as_ref().map(|_| self.index)
+    }
+
+    /// Переходит к следующему элементу
+    pub fn move_next(&mut self) {
+        match self.node() {
+            Some(node) => {
+                self.current = node.lock().unwrap().next.as_ref().map(Arc::downgrade);
+                self.index += 1;
+            }
+            None => {
+                self.current = self.list.head.as_ref().map(Arc::downgrade);
+                self.index = 0;
+            }
+        }
+    }
+
+    /// Переходит к предыдущему элементу
+    pub fn move_prev(&mut self) {
+        match self.node() {
+            Some(node) => {
+                self.current = node.lock().unwrap().prev.clone();
+                self.index = match self.current {
+                    Some(_) => self.index - 1,
+                    None => self.list.len,
+                };
+            }
+            None => {
+                self.current = self.list.tail.as_ref().map(Arc::downgrade);
+                self.index = self.list.len.saturating_sub(1);
+            }
+        }
+    }
+
+    /// Вставляет элемент перед текущим; на призрачном — в конец списка
+    pub fn insert_before(&mut self, data: T) {
+        let current = self.node();
+        let prev = match &current {
+            Some(node) => node.lock().unwrap().prev.as_ref().and_then(|prev| prev.upgrade()),
+            None => self.list.tail.clone(),
+        };
+        self.list.link_between(prev, current, data);
+        self.index += 1;
+    }
+
+    /// Вставляет элемент после текущего; на призрачном — в начало списка
+    pub fn insert_after(&mut self, data: T) {
+        let current = self.node();
+        let next = match &current {
+            Some(node) => node.lock().unwrap().next.clone(),
+            None => self.list.head.clone(),
+        };
+        if current.is_none() {
+            self.index += 1;
+        }
+        self.list.link_between(current, next, data);
+    }
+
+    /// Удаляет текущий элемент и переходит к следующему
+    ///
+    /// На призрачном элементе ничего не делает и возвращает `None`.
+    pub fn remove_current(&mut self) -> Option<T> {
+        let node = self.node()?;
+        self.current = node.lock().unwrap().next.as_ref().map(Arc::downgrade);
+        Some(self.list.unlink(node))
+    }
+
+    /// Текущий узел; жив, пока курсор заимствует список
+    fn node(&self) -> Option<Arc<Mutex<Node<T>>>> {
+        let node = self.current.as_ref()?;
+        Some(node.upgrade().expect("узлы списка живы, пока он заимствован курсором"))
+    }
+}
+
+impl<T: Clone> Cursor<'_, T> {
+    /// Возвращает копию текущего элемента
+    pub fn current(&self) -> Option<T> {
+        let node = self.node()?;
+        Some(node.lock().unwrap().data.clone())
+    }
+}
+
+/// Thread-safe обертка для DoublyLinkedList
+///
+/// Список разрезан на две половины под своими блокировками, поэтому
+/// добавление и удаление на противоположных концах не мешают друг другу.
+/// Остальные операции блокируют обе половины.
+#[derive(Debug)]
+pub struct ThreadSafeDoublyLinkedList<T> {
+    inner: shared::Arc<Halves<T>>,
+}
+
+/// Половины списка: сначала идут элементы `front`, затем `back`
+///
+/// Обе блокировки всегда берутся в порядке `front`, затем `back`, иначе
+/// встречные операции могли бы заблокировать друг друга навсегда.
+#[derive(Debug)]
+struct Halves<T> {
+    front: shared::Mutex<DoublyLinkedList<T>>,
+    back: shared::Mutex<DoublyLinkedList<T>>,
+}
+
+type Guard<'a, T> = shared::MutexGuard<'a, DoublyLinkedList<T>>;
+
+impl<T> ThreadSafeDoublyLinkedList<T> {
+    /// Создает новый thread-safe список
+    pub fn new() -> Self {
+        Self::from_list(DoublyLinkedList::new())
+    }
+
+    fn from_list(list: DoublyLinkedList<T>) -> Self {
+        ThreadSafeDoublyLinkedList {
+            inner: shared::Arc::new(Halves {
+                front: shared::Mutex::new(list),
+                back: shared::Mutex::new(DoublyLinkedList::new()),
+            }),
+        }
+    }
+
+    /// Возвращает количество элементов в списке
+    pub fn len(&self) -> usize {
+        let (front, back) = self.lock_both();
+        front.len() + back.len()
+    }
+
+    /// Проверяет, пуст ли список
+    pub fn is_empty(&self) -> bool {
+        let (front, back) = self.lock_both();
+        front.is_empty() && back.is_empty()
+    }
+
+    /// Добавляет элемент в начало списка и возвращает хэндл на него
+    pub fn push_front(&self, data: T) -> NodeHandle<T> {
+        self.inner.front.lock().unwrap().push_front(data)
+    }
+
+    /// Добавляет элемент в конец списка и возвращает хэндл на него
+    pub fn push_back(&self, data: T) -> NodeHandle<T> {
+        self.inner.back.lock().unwrap().push_back(data)
+    }
+
+    /// Удаляет и возвращает первый элемент списка
+    ///
+    /// Если передняя половина опустела, забирает в нее все элементы задней
+    /// обменом указателей, чтобы следующие вызовы снова обходились одной
+    /// блокировкой.
+    pub fn pop_front(&self) -> Option<T> {
+        let popped = self.inner.front.lock().unwrap().pop_front();
+        if popped.is_some() {
+            return popped;
+        }
+        let (mut front, mut back) = self.lock_both();
+        if front.is_empty() {
+            std::mem::swap(&mut *front, &mut *back);
+        }
+        front.pop_front()
+    }
+
+    /// Удаляет и возвращает последний элемент списка
+    ///
+    /// Если задняя половина опустела, забирает в нее все элементы передней.
+    pub fn pop_back(&self) -> Option<T> {
+        let popped = self.inner.back.lock().unwrap().pop_back();
+        if popped.is_some() {
+            return popped;
+        }
+        let (mut front, mut back) = self.lock_both();
+        if back.is_empty() {
+            std::mem::swap(&mut *front, &mut *back);
+        }
+        back.pop_back()
+    }
+
+    /// Вставляет элемент на позицию `index`, см. [`DoublyLinkedList::insert_at`]
+    pub fn insert_at(&self, index: usize, data: T) -> Result<(), IndexOutOfBounds> {
+        self.with_whole(|list| list.insert_at(index, data))
+    }
+
+    /// Удаляет и возвращает элемент на позиции `index`
+    pub fn remove_at(&self, index: usize) -> Result<T, IndexOutOfBounds> {
+        self.with_whole(|list| list.remove_at(index))
+    }
+
+    /// Удаляет элемент по хэндлу за O(1), см. [`DoublyLinkedList::remove`]
+    ///
+    /// Половины не сливаются: элемент ищется в той, где он лежит.
+    pub fn remove(&self, handle: &NodeHandle<T>) -> Option<T> {
+        let (mut front, mut back) = self.lock_both();
+        front.remove(handle).or_else(|| back.remove(handle))
+    }
+
+    /// Проверяет, есть ли в списке элемент, равный `value`
+    pub fn contains(&self, value: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        let (front, back) = self.lock_both();
+        front.contains(value) || back.contains(value)
+    }
+
+    /// Возвращает позицию первого элемента, удовлетворяющего предикату
+    ///
+    /// Курсор не может пережить блокировку, поэтому возвращается только позиция.
+    pub fn position(&self, predicate: impl FnMut(&T) -> bool) -> Option<usize> {
+        self.with_whole(|list| list.position(predicate))
+    }
+
+    /// Оставляет только элементы, удовлетворяющие предикату, за одну блокировку
+    pub fn retain(&self, mut predicate: impl FnMut(&T) -> bool) {
+        let (mut front, mut back) = self.lock_both();
+        front.retain(&mut predicate);
+        back.retain(predicate);
+    }
+
+    /// Переносит все элементы `other` в конец списка
+    ///
+    /// Блокировки списков берутся по очереди, а не вместе, поэтому встречные
+    /// `append` из разных потоков не блокируют друг друга навсегда.
+    pub fn append(&self, other: &Self) {
+        let mut taken = other.with_whole(std::mem::take);
+        self.inner.back.lock().unwrap().append(&mut taken);
+    }
+
+    /// Отделяет элементы начиная с позиции `at` в новый список
+    pub fn split_off(&self, at: usize) -> Result<Self, IndexOutOfBounds> {
+        let tail = self.with_whole(|list| list.split_off(at))?;
+        Ok(Self::from_list(tail))
+    }
+
+    fn lock_both(&self) -> (Guard<'_, T>, Guard<'_, T>) {
+        let front = self.inner.front.lock().unwrap();
+        let back = self.inner.back.lock().unwrap();
+        (front, back)
+    }
+
+    /// Собирает весь список в передней половине и передает его `f`
+    fn with_whole<R>(&self, f: impl FnOnce(&mut DoublyLinkedList<T>) -> R) -> R {
+        let (mut front, mut back) = self.lock_both();
+        front.append(&mut back);
+        f(&mut front)
+    }
+}
+
+impl<T> Default for ThreadSafeDoublyLinkedList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Клон ссылается на тот же список, что позволяет передать его в другой поток
+impl<T> Clone for ThreadSafeDoublyLinkedList<T> {
+    fn clone(&self) -> Self {
+        ThreadSafeDoublyLinkedList {
+            inner: shared::Arc::clone(&self.inner),
+        }
+    }
+}
+
+/// Итератор для DoublyLinkedList
+///
+/// Идет навстречу с обоих концов и останавливается, когда концы встретятся,
+/// поэтому поддерживает `rev()`. Держит слабые ссылки на узлы и заимствует
+/// список, чтобы тот не менялся, пока обход не закончен.
+pub struct DoublyLinkedListIter<'a, T> {
+    current: Option<Weak<Mutex<Node<T>>>>,
+    back: Option<Weak<Mutex<Node<T>>>>,
+    /// Сколько элементов осталось между концами
+    remaining: usize,
+    list: PhantomData<&'a DoublyLinkedList<T>>,
+}
+
+impl<'a, T> DoublyLinkedListIter<'a, T> {
+    fn new(list: &'a DoublyLinkedList<T>) -> Self {
+        DoublyLinkedListIter {
+            current: list.head.as_ref().map(Arc::downgrade),
+            back: list.tail.as_ref().map(Arc::downgrade),
+            remaining: list.len,
+            list: PhantomData,
+        }
+    }
+}
+
+impl<T: Clone> Iterator for DoublyLinkedListIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.current.take().and_then(|node| node.upgrade()).map(|node| {
+            let node = node.lock().unwrap();
+            self.current = node.next.as_ref().map(Arc::downgrade);
+            self.remaining -= 1;
+            // Клонируем данные, так как мы не можем переместить их из Arc<Mutex<Node<T>>>
+            node.data.clone()
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T: Clone> DoubleEndedIterator for DoublyLinkedListIter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.back.take().and_then(|node| node.upgrade()).map(|node| {
+            let node = node.lock().unwrap();
+            self.back = node.prev.clone();
+            self.remaining -= 1;
+            node.data.clone()
+        })
+    }
+}
+
+impl<T: Clone> DoublyLinkedList<T> {
+    /// Создает итератор для обхода списка
+    pub fn iter(&self) -> DoublyLinkedListIter<'_, T> {
+        DoublyLinkedListIter::new(self)
+    }
+}
+
+/// Итератор, забирающий элементы из списка без клонирования
+pub struct DoublyLinkedListIntoIter<T> {
+    list: DoublyLinkedList<T>,
+}
+
+impl<T> Iterator for DoublyLinkedListIntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.list.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.list.len, Some(self.list.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for DoublyLinkedListIntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.list.pop_back()
+    }
+}
+
+impl<T> IntoIterator for DoublyLinkedList<T> {
+    type Item = T;
+    type IntoIter = DoublyLinkedListIntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        DoublyLinkedListIntoIter { list: self }
+    }
+}
+
+impl<T> FromIterator<T> for DoublyLinkedList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = DoublyLinkedList::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T> Extend<T> for DoublyLinkedList<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.push_back(data);
+        }
+    }
+}
+
+/// Thread-safe итератор для ThreadSafeDoublyLinkedList
+///
+/// Обходит снимок элементов, сделанный под блокировкой, поэтому не мешает
+/// другим потокам менять список.
+pub struct ThreadSafeDoublyLinkedListIter<T> {
+    inner: std::collections::vec_deque::IntoIter<T>,
+}
+
+impl<T: Clone> ThreadSafeDoublyLinkedListIter<T> {
+    fn new(list: &ThreadSafeDoublyLinkedList<T>) -> Self {
+        let (front, back) = list.lock_both();
+        let snapshot: VecDeque<T> = front.iter().chain(back.iter()).collect();
+        ThreadSafeDoublyLinkedListIter {
+            inner: snapshot.into_iter(),
+        }
+    }
+}
+
+impl<T: Clone> Iterator for ThreadSafeDoublyLinkedListIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<T: Clone> DoubleEndedIterator for ThreadSafeDoublyLinkedListIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+}
+
+impl<T: Clone> ThreadSafeDoublyLinkedList<T> {
+    /// Создает итератор для обхода списка
+    pub fn iter(&self) -> ThreadSafeDoublyLinkedListIter<T> {
+        ThreadSafeDoublyLinkedListIter::new(self)
+    }
+
+    /// Возвращает копию элемента на позиции `index`
+    pub fn get(&self, index: usize) -> Option<T> {
+        let (front, back) = self.lock_both();
+        match index.checked_sub(front.len()) {
+            None => front.get(index),
+            Some(index) => back.get(index),
+        }
+    }
+
+    /// Возвращает копию элемента по хэндлу за O(1)
+    pub fn get_by_handle(&self, handle: &NodeHandle<T>) -> Option<T> {
+        let (front, back) = self.lock_both();
+        front
+            .get_by_handle(handle)
+            .or_else(|| back.get_by_handle(handle))
+    }
+
+    /// Создает итератор для обхода списка с хвоста, например, чтобы найти
+    /// давно не использованные элементы
+    pub fn iter_rev(&self) -> std::iter::Rev<ThreadSafeDoublyLinkedListIter<T>> {
+        self.iter().rev()
+    }
+}
+
+/// Забирает все элементы разом, оставляя список пустым
+///
+/// Клоны разделяют один список, поэтому они тоже увидят его пустым.
+impl<T> IntoIterator for ThreadSafeDoublyLinkedList<T> {
+    type Item = T;
+    type IntoIter = DoublyLinkedListIntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.with_whole(std::mem::take).into_iter()
+    }
+}
+
+impl<T> FromIterator<T> for ThreadSafeDoublyLinkedList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_list(iter.into_iter().collect())
+    }
+}
+
+/// Добавляет элементы в конец под одной блокировкой
+impl<T> Extend<T> for ThreadSafeDoublyLinkedList<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.inner.back.lock().unwrap().extend(iter);
+    }
+}
+
+/// Ошибка добавления в заполненную очередь, возвращающая элемент обратно
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Full<T>(pub T);
+
+impl<T> fmt::Display for Full<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("очередь заполнена")
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for Full<T> {}
+
+/// Двусторонняя очередь ограниченной вместимости поверх
+/// ThreadSafeDoublyLinkedList для схемы producer/consumer
+///
+/// Производители ждут свободного места, а потребители — элементов на
+/// условных переменных, не опрашивая список в цикле. Клон ссылается на ту же
+/// очередь.
+pub struct BoundedBlockingDeque<T> {
+    list: ThreadSafeDoublyLinkedList<T>,
+    capacity: usize,
+    signals: shared::Arc<Signals>,
+}
+
+struct Signals {
+    /// Элементы, которые можно забрать
+    items: Permits,
+    /// Свободные места
+    free: Permits,
+}
+
+/// Счетчик разрешений, которых можно дождаться, как у семафора
+///
+/// Блокировка счетчика своя, поэтому ожидающие не держат блокировки списка.
+struct Permits {
+    count: shared::Mutex<usize>,
+    available: shared::Condvar,
+}
+
+impl Permits {
+    fn new(count: usize) -> Self {
+        Permits {
+            count: shared::Mutex::new(count),
+            available: shared::Condvar::new(),
+        }
+    }
+
+    fn acquire(&self) {
+        let mut count = self.count.lock().unwrap();
+        while *count == 0 {
+            count = self.available.wait(count).unwrap();
+        }
+        *count -= 1;
+    }
+
+    fn try_acquire(&self) -> bool {
+        let mut count = self.count.lock().unwrap();
+        let acquired = *count > 0;
+        if acquired {
+            *count -= 1;
+        }
+        acquired
+    }
+
+    fn acquire_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut count = self.count.lock().unwrap();
+        while *count == 0 {
+            // Пробуждение может быть ложным, поэтому ждем только остаток времени
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            count = self.available.wait_timeout(count, remaining).unwrap().0;
+        }
+        *count -= 1;
+        true
+    }
+
+    fn release(&self) {
+        *self.count.lock().unwrap() += 1;
+        self.available.notify_one();
+    }
+}
+
+impl<T> BoundedBlockingDeque<T> {
+    /// Создает пустую очередь на `capacity` элементов, но не меньше одного
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        BoundedBlockingDeque {
+            list: ThreadSafeDoublyLinkedList::new(),
+            capacity,
+            signals: shared::Arc::new(Signals {
+                items: Permits::new(0),
+                free: Permits::new(capacity),
+            }),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Добавляет элемент в начало, дожидаясь свободного места
+    pub fn push_front(&self, data: T) {
+        self.signals.free.acquire();
+        self.list.push_front(data);
+        self.signals.items.release();
+    }
+
+    /// Добавляет элемент в конец, дожидаясь свободного места
+    pub fn push_back(&self, data: T) {
+        self.signals.free.acquire();
+        self.list.push_back(data);
+        self.signals.items.release();
+    }
+
+    /// Добавляет элемент в начало или сразу возвращает его, если места нет
+    pub fn try_push_front(&self, data: T) -> Result<(), Full<T>> {
+        self.try_push(data, ThreadSafeDoublyLinkedList::push_front)
+    }
+
+    /// Добавляет элемент в конец или сразу возвращает его, если места нет
+    pub fn try_push_back(&self, data: T) -> Result<(), Full<T>> {
+        self.try_push(data, ThreadSafeDoublyLinkedList::push_back)
+    }
+
+    /// Забирает первый элемент, ожидая его не дольше `timeout`
+    pub fn pop_front_timeout(&self, timeout: Duration) -> Option<T> {
+        self.pop(timeout, ThreadSafeDoublyLinkedList::pop_front)
+    }
+
+    /// Забирает последний элемент, ожидая его не дольше `timeout`
+    pub fn pop_back_timeout(&self, timeout: Duration) -> Option<T> {
+        self.pop(timeout, ThreadSafeDoublyLinkedList::pop_back)
+    }
+
+    fn try_push(
+        &self,
+        data: T,
+        push: fn(&ThreadSafeDoublyLinkedList<T>, T) -> NodeHandle<T>,
+    ) -> Result<(), Full<T>> {
+        if !self.signals.free.try_acquire() {
+            return Err(Full(data));
+        }
+        push(&self.list, data);
+        self.signals.items.release();
+        Ok(())
+    }
+
+    fn pop(
+        &self,
+        timeout: Duration,
+        pop: fn(&ThreadSafeDoublyLinkedList<T>) -> Option<T>,
+    ) -> Option<T> {
+        if !self.signals.items.acquire_timeout(timeout) {
+            return None;
+        }
+        // Разрешение выдается только после добавления элемента в список
+        let data = pop(&self.list).expect("на каждое разрешение есть элемент");
+        self.signals.free.release();
+        Some(data)
+    }
+}
+
+impl<T> Clone for BoundedBlockingDeque<T> {
+    fn clone(&self) -> Self {
+        BoundedBlockingDeque {
+            list: self.list.clone(),
+            capacity: self.capacity,
+            signals: shared::Arc::clone(&self.signals),
+        }
+    }
+}
+
+/// Сериализация списков как последовательностей, как у `Vec`
+#[cfg(feature = "serde")]
+mod serde_support {
+    use std::fmt;
+    use std::marker::PhantomData;
+
+    use serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+    use serde::ser::{Serialize, SerializeSeq, Serializer};
+
+    use super::{DoublyLinkedList, ThreadSafeDoublyLinkedList};
+
+    /// Сериализует элементы по ссылке, поэтому `T: Clone` не нужен
+    impl<T: Serialize> Serialize for DoublyLinkedList<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut seq = serializer.serialize_seq(Some(self.len))?;
+            let mut current = self.head.clone();
+            while let Some(node) = current {
+                let node = node.lock().unwrap();
+                seq.serialize_element(&node.data)?;
+                current = node.next.clone();
+            }
+            seq.end()
+        }
+    }
+
+    impl<'de, T: Deserialize<'de>> Deserialize<'de> for DoublyLinkedList<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_seq(ListVisitor(PhantomData))
+        }
+    }
+
+    struct ListVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for ListVisitor<T> {
+        type Value = DoublyLinkedList<T>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a sequence")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut list = DoublyLinkedList::new();
+            while let Some(data) = seq.next_element()? {
+                list.push_back(data);
+            }
+            Ok(list)
+        }
+    }
+
+    /// Сериализует снимок списка под одной блокировкой
+    impl<T: Serialize> Serialize for ThreadSafeDoublyLinkedList<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.with_whole(|list| list.serialize(serializer))
+        }
+    }
+
+    impl<'de, T: Deserialize<'de>> Deserialize<'de> for ThreadSafeDoublyLinkedList<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let list = DoublyLinkedList::deserialize(deserializer)?;
+            Ok(ThreadSafeDoublyLinkedList::from_list(list))
+        }
+    }
+}
+
+/// Параллельный обход списков через rayon
+#[cfg(feature = "rayon")]
+mod parallel {
+    use std::sync::Arc;
+
+    use rayon::prelude::*;
+
+    use super::{DoublyLinkedList, ThreadSafeDoublyLinkedList};
+
+    impl<T: Clone + Send> DoublyLinkedList<T> {
+        /// Параллельный итератор по копиям элементов в порядке списка
+        ///
+        /// Сначала обходит список и собирает слабые ссылки на узлы, а копирует
+        /// данные уже в пуле rayon. Итератор заимствует список, поэтому узлы
+        /// живы, пока он не отработает.
+        pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = T> + '_ {
+            let mut nodes = Vec::with_capacity(self.len);
+            let mut current = self.head.clone();
+            while let Some(node) = current {
+                current = node.lock().unwrap().next.clone();
+                nodes.push(Arc::downgrade(&node));
+            }
+            nodes.into_par_iter().map(|node| {
+                let node = node.upgrade().expect("узлы списка живы, пока он заимствован");
+                node.lock().unwrap().data.clone()
+            })
+        }
+    }
+
+    impl<T: Clone + Send> ThreadSafeDoublyLinkedList<T> {
+        /// Параллельный итератор по снимку элементов
+        ///
+        /// Элементы копируются параллельно под блокировками обеих половин, а
+        /// обрабатываются уже после их снятия.
+        pub fn par_iter(&self) -> rayon::vec::IntoIter<T> {
+            let (front, back) = self.lock_both();
+            let snapshot: Vec<T> = front.par_iter().chain(back.par_iter()).collect();
+            snapshot.into_par_iter()
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "loom")))]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::thread;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_empty_list() {
+        let list = DoublyLinkedList::<i32>::new();
+        assert!(list.is_empty());
+        assert_eq!(list.len(), 0);
+    }
+
+    #[test]
+    fn test_push_front() {
+        let mut list = DoublyLinkedList::new();
+        list.push_front(1);
+        list.push_front(2);
+        list.push_front(3);
+        
+        assert_eq!(list.len(), 3);
+        assert!(!list.is_empty());
+    }
+
+    #[test]
+    fn test_push_back() {
+        let mut list = DoublyLinkedList::new();
+        list.push_back(1);
+        list.push_back(2);
+        list.push_back(3);
+        
+        assert_eq!(list.len(), 3);
+        assert!(!list.is_empty());
+    }
+
+    #[test]
+    fn test_pop_front() {
+        let mut list = DoublyLinkedList::new();
+        list.push_front(1);
+        list.push_front(2);
+        list.push_front(3);
+        
+        assert_eq!(list.pop_front(), Some(3));
+        assert_eq!(list.pop_front(), Some(2));
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.pop_front(), None);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_pop_back() {
+        let mut list = DoublyLinkedList::new();
+        list.push_back(1);
+        list.push_back(2);
+        list.push_back(3);
+        
+        assert_eq!(list.pop_back(), Some(3));
+        assert_eq!(list.pop_back(), Some(2));
+        assert_eq!(list.pop_back(), Some(1));
+        assert_eq!(list.pop_back(), None);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_mixed_operations() {
+        let mut list = DoublyLinkedList::new();
+        
+        // Добавляем элементы
+        list.push_front(1);
+        list.push_back(2);
+        list.push_front(0);
+        list.push_back(3);
+        
+        assert_eq!(list.len(), 4);
+        
+        // Проверяем порядок извлечения
+        assert_eq!(list.pop_front(), Some(0));
+        assert_eq!(list.pop_back(), Some(3));
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.pop_back(), Some(2));
+        assert_eq!(list.pop_front(), None);
+    }
+
+    #[test]
+    fn test_thread_safe_empty() {
+        let list = ThreadSafeDoublyLinkedList::<i32>::new();
+        assert!(list.is_empty());
+        assert_eq!(list.len(), 0);
+    }
+
+    #[test]
+    fn test_thread_safe_operations() {
+        let list = ThreadSafeDoublyLinkedList::new();
+        
+        list.push_front(1);
+        list.push_back(2);
+        list.push_front(0);
+        list.push_back(3);
+        
+        assert_eq!(list.len(), 4);
+        assert!(!list.is_empty());
+        
+        assert_eq!(list.pop_front(), Some(0));
+        assert_eq!(list.pop_back(), Some(3));
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.pop_back(), Some(2));
+        assert_eq!(list.pop_front(), None);
+    }
+
+    #[test]
+    fn test_thread_safe_halves_keep_order() {
+        // Собранные элементы лежат в передней половине, добавленные в конец —
+        // в задней, и извлечение перекладывает их между половинами
+        let list: ThreadSafeDoublyLinkedList<_> = (1..=4).collect();
+        list.push_back(5);
+        list.push_front(0);
+        assert_eq!(list.pop_back(), Some(5));
+        assert_eq!(list.pop_back(), Some(4));
+        list.push_back(6);
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![0, 1, 2, 3, 6]);
+        assert_eq!(list.position(|&x| x == 6), Some(4));
+        assert_eq!(list.pop_front(), Some(0));
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.len(), 3);
+
+        let producer = {
+            let list = list.clone();
+            thread::spawn(move || (7..1000).for_each(|i| drop(list.push_back(i))))
+        };
+        let mut taken = Vec::new();
+        while taken.len() < 500 {
+            taken.extend(list.pop_front());
+        }
+        producer.join().unwrap();
+        assert!(taken.is_sorted());
+        assert_eq!(taken.len() + list.len(), 996);
+    }
+
+    #[test]
+    fn test_thread_safety() {
+        let list = Arc::new(ThreadSafeDoublyLinkedList::new());
+        let mut handles = vec![];
+        
+        // Создаем несколько потоков для записи
+        for i in 0..5 {
+            let list_clone = list.clone();
+            let handle = thread::spawn(move || {
+                for j in 0..10 {
+                    list_clone.push_front(i * 10 + j);
+                    thread::sleep(Duration::from_millis(1));
+                }
+            });
+            handles.push(handle);
+        }
+        
+        // Создаем несколько потоков для чтения
+        for _ in 0..3 {
+            let list_clone = list.clone();
+            let handle = thread::spawn(move || {
+                for _ in 0..10 {
+                    let _ = list_clone.pop_front();
+                    thread::sleep(Duration::from_millis(1));
+                }
+            });
+            handles.push(handle);
+        }
+        
+        // Ждем завершения всех потоков
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        
+        // Проверяем, что список в корректном состоянии: 50 добавлений и
+        // не более 30 успешных извлечений
+        assert!((20..=50).contains(&list.len()));
+    }
+
+    #[test]
+    fn test_concurrent_push_pop() {
+        let list = Arc::new(ThreadSafeDoublyLinkedList::new());
+        let mut handles = vec![];
+        
+        // Поток 1: добавляет элементы
+        let list1 = list.clone();
+        let handle1 = thread::spawn(move || {
+            for i in 0..100 {
+                list1.push_front(i);
+                if i % 10 == 0 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+        });
+        handles.push(handle1);
+        
+        // Поток 2: добавляет элементы в конец
+        let list2 = list.clone();
+        let handle2 = thread::spawn(move || {
+            for i in 100..200 {
+                list2.push_back(i);
+                if i % 10 == 0 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+        });
+        handles.push(handle2);
+        
+        // Поток 3: извлекает элементы
+        let list3 = list.clone();
+        let handle3 = thread::spawn(move || {
+            let mut count = 0;
+            while count < 50 {
+                if list3.pop_front().is_some() {
+                    count += 1;
+                }
+                thread::sleep(Duration::from_millis(2));
+            }
+        });
+        handles.push(handle3);
+        
+        // Ждем завершения всех потоков
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        
+        // Проверяем финальное состояние
+        assert!(!list.is_empty());
+        println!("Final list length: {}", list.len());
+    }
+
+    #[test]
+    fn test_iterator() {
+        let mut list = DoublyLinkedList::new();
+        list.push_back(1);
+        list.push_back(2);
+        list.push_back(3);
+        
+        let mut iter = list.iter();
+        assert_eq!(iter.next(), Some(1));
+        assert_eq!(iter.next(), Some(2));
+        assert_eq!(iter.next(), Some(3));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_cursor_edits_in_place() {
+        let mut list = DoublyLinkedList::new();
+        let mut cursor = list.cursor_front();
+        assert_eq!(cursor.index(), None);
+        assert_eq!(cursor.remove_current(), None);
+        // С призрачного элемента вставка идет в конец или в начало
+        cursor.insert_before(3);
+        cursor.insert_after(1);
+        cursor.move_next();
+        assert_eq!((cursor.index(), cursor.current()), (Some(0), Some(1)));
+        cursor.insert_after(2);
+        cursor.insert_before(0);
+        assert_eq!((cursor.index(), cursor.current()), (Some(1), Some(1)));
+        assert_eq!(list.iter().collect::<Vec<_>>(), [0, 1, 2, 3]);
+
+        let mut cursor = list.cursor_back();
+        cursor.move_prev();
+        assert_eq!(cursor.remove_current(), Some(2));
+        assert_eq!((cursor.index(), cursor.current()), (Some(2), Some(3)));
+        assert_eq!(cursor.remove_current(), Some(3));
+        assert_eq!(cursor.index(), None);
+        cursor.move_prev();
+        assert_eq!((cursor.index(), cursor.current()), (Some(1), Some(1)));
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(cursor.index(), None);
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(0));
+
+        // Обратные ссылки тоже должны остаться согласованными
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.pop_back(), Some(1));
+        assert_eq!(list.pop_back(), Some(0));
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_insert_and_remove_at() {
+        let mut list = DoublyLinkedList::new();
+        assert_eq!(list.remove_at(0), Err(IndexOutOfBounds { index: 0, len: 0 }));
+        list.insert_at(0, 2).unwrap();
+        list.insert_at(0, 0).unwrap();
+        list.insert_at(2, 4).unwrap();
+        list.insert_at(1, 1).unwrap();
+        list.insert_at(3, 3).unwrap();
+        assert_eq!(list.iter().collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+        assert_eq!(list.insert_at(6, 9), Err(IndexOutOfBounds { index: 6, len: 5 }));
+
+        // Середина ближе к хвосту, середина ближе к голове, затем голова и хвост
+        assert_eq!(list.remove_at(3), Ok(3));
+        assert_eq!(list.remove_at(1), Ok(1));
+        assert_eq!(list.remove_at(0), Ok(0));
+        assert_eq!(list.remove_at(1), Ok(4));
+        assert_eq!(list.remove_at(1), Err(IndexOutOfBounds { index: 1, len: 1 }));
+        assert_eq!(list.iter().collect::<Vec<_>>(), [2]);
+
+        // Голова и хвост должны остаться согласованными
+        list.insert_at(1, 5).unwrap();
+        list.push_front(1);
+        assert_eq!(list.pop_back(), Some(5));
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.remove_at(0), Ok(2));
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_thread_safe_insert_and_remove_at() {
+        let list = ThreadSafeDoublyLinkedList::new();
+        list.push_back(1);
+        list.push_back(3);
+        list.insert_at(1, 2).unwrap();
+        assert_eq!(list.iter().collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(list.remove_at(2), Ok(3));
+        let err = list.remove_at(5).unwrap_err();
+        assert_eq!(err.to_string(), "индекс 5 вне списка длины 2");
+    }
+
+    #[test]
+    fn test_collect_extend_and_drain() {
+        let mut list: DoublyLinkedList<_> = vec![String::from("a"), String::from("b")]
+            .into_iter()
+            .collect();
+        list.extend([String::from("c")]);
+        assert_eq!(list.len(), 3);
+        let mut drain = list.into_iter();
+        assert_eq!(drain.size_hint(), (3, Some(3)));
+        assert_eq!(drain.next().as_deref(), Some("a"));
+        assert_eq!(drain.collect::<Vec<_>>(), ["b", "c"]);
+
+        let mut shared: ThreadSafeDoublyLinkedList<_> = (1..=2).collect();
+        shared.extend(3..=4);
+        let clone = shared.clone();
+        assert_eq!(shared.into_iter().collect::<Vec<_>>(), [1, 2, 3, 4]);
+        assert!(clone.is_empty());
+    }
+
+    #[test]
+    fn test_reverse_iteration() {
+        let list: DoublyLinkedList<_> = (1..=5).collect();
+        assert_eq!(list.iter().rev().collect::<Vec<_>>(), [5, 4, 3, 2, 1]);
+
+        // Концы встречаются посередине, не выдавая элементы дважды
+        let mut iter = list.iter();
+        assert_eq!((iter.next(), iter.next_back()), (Some(1), Some(5)));
+        assert_eq!((iter.next_back(), iter.next()), (Some(4), Some(2)));
+        assert_eq!(iter.size_hint(), (1, Some(1)));
+        assert_eq!((iter.next_back(), iter.next(), iter.next_back()), (Some(3), None, None));
+
+        let shared: ThreadSafeDoublyLinkedList<_> = list.into_iter().rev().collect();
+        assert_eq!(shared.iter_rev().collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+        assert_eq!(shared.iter().next_back(), Some(1));
+    }
+
+    #[test]
+    fn test_search_and_retain() {
+        let mut list: DoublyLinkedList<_> = (1..=6).collect();
+        assert!(list.contains(&4));
+        assert!(!list.contains(&7));
+        assert_eq!(list.position(|&x| x > 3), Some(3));
+        assert_eq!(list.position(|&x| x > 6), None);
+
+        let mut cursor = list.find(|&x| x == 3).unwrap();
+        assert_eq!((cursor.index(), cursor.current()), (Some(2), Some(3)));
+        assert_eq!(cursor.remove_current(), Some(3));
+        assert!(list.find(|&x| x == 3).is_none());
+
+        // Удаляются и крайние узлы, и идущие подряд
+        list.retain(|&x| x == 2 || x == 4);
+        assert_eq!(list.iter().collect::<Vec<_>>(), [2, 4]);
+        assert_eq!(list.iter().rev().collect::<Vec<_>>(), [4, 2]);
+        assert_eq!(list.len(), 2);
+        list.retain(|_| false);
+        assert!(list.is_empty());
+        list.push_back(8);
+        assert_eq!(list.iter().collect::<Vec<_>>(), [8]);
+
+        let shared: ThreadSafeDoublyLinkedList<_> = (1..=4).collect();
+        shared.retain(|&x| x % 2 == 1);
+        assert!(shared.contains(&3));
+        assert_eq!(shared.position(|&x| x == 3), Some(1));
+    }
+
+    #[test]
+    fn test_append_and_split_off() {
+        let mut list: DoublyLinkedList<_> = (1..=3).collect();
+        let mut other: DoublyLinkedList<_> = (4..=5).collect();
+        list.append(&mut other);
+        assert_eq!((list.len(), other.len()), (5, 0));
+        assert_eq!(list.iter().rev().collect::<Vec<_>>(), [5, 4, 3, 2, 1]);
+        list.append(&mut other);
+        other.append(&mut list);
+        assert!(list.is_empty());
+        list.append(&mut other);
+
+        let tail = list.split_off(2).unwrap();
+        assert_eq!(list.iter().collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(tail.iter().rev().collect::<Vec<_>>(), [5, 4, 3]);
+        assert_eq!((list.len(), tail.len()), (2, 3));
+        assert!(list.split_off(2).unwrap().is_empty());
+        assert_eq!(list.split_off(3).unwrap_err(), IndexOutOfBounds { index: 3, len: 2 });
+
+        // Отделение с нуля забирает все, оставляя пригодный пустой список
+        let mut all = list.split_off(0).unwrap();
+        assert!(list.is_empty());
+        list.push_back(0);
+        assert_eq!(all.pop_back(), Some(2));
+        assert_eq!(all.pop_front(), Some(1));
+        assert!(DoublyLinkedList::<u8>::new().split_off(0).unwrap().is_empty());
+
+        let shared: ThreadSafeDoublyLinkedList<_> = (1..=2).collect();
+        let other: ThreadSafeDoublyLinkedList<_> = (3..=4).collect();
+        shared.append(&other);
+        shared.append(&shared);
+        assert!(other.is_empty());
+        let tail = shared.split_off(3).unwrap();
+        assert_eq!(shared.iter().collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(tail.iter().collect::<Vec<_>>(), [4]);
+        assert!(shared.split_off(4).is_err());
+    }
+
+    #[test]
+    fn test_node_handles() {
+        let mut list = DoublyLinkedList::new();
+        let one = list.push_back(1);
+        let zero = list.push_front(0);
+        let two = list.push_back(2);
+        list.insert_at(1, 5).unwrap();
+        assert_eq!(list.get_by_handle(&one), Some(1));
+        assert_eq!(list.remove(&one), Some(1));
+        assert_eq!(list.remove(&one), None);
+        assert_eq!(list.get(1), Some(5));
+        assert_eq!(list.get(3), None);
+
+        // Хэндлы следуют за узлами при переносе между списками
+        let mut other = DoublyLinkedList::new();
+        let three = other.push_back(3);
+        list.append(&mut other);
+        assert_eq!(other.get_by_handle(&three), None);
+        let mut tail = list.split_off(2).unwrap();
+        assert_eq!(list.get_by_handle(&two), None);
+        assert_eq!(list.remove(&three), None);
+        assert_eq!(tail.remove(&two), Some(2));
+        assert_eq!(tail.get_by_handle(&three), Some(3));
+        assert_eq!(list.remove(&zero), Some(0));
+        assert_eq!(list.iter().collect::<Vec<_>>(), [5]);
+
+        // Метки перенаправляются по цепочке переносов, а опустевший список
+        // получает новую метку
+        let mut a = DoublyLinkedList::new();
+        let mut b = DoublyLinkedList::new();
+        let mut c = DoublyLinkedList::new();
+        let from_a = a.push_back(1);
+        let from_b = b.push_back(2);
+        b.append(&mut a);
+        c.append(&mut b);
+        let later_in_b = b.push_back(3);
+        assert_eq!(c.get_by_handle(&from_a), Some(1));
+        assert_eq!(c.get_by_handle(&from_b), Some(2));
+        assert_eq!(c.get_by_handle(&later_in_b), None);
+        assert_eq!(b.get_by_handle(&from_a), None);
+        assert_eq!(a.get_by_handle(&from_a), None);
+        assert_eq!(b.remove(&later_in_b), Some(3));
+
+        let shared = ThreadSafeDoublyLinkedList::new();
+        let handles: Vec<_> = (1..=3).map(|x| shared.push_back(x)).collect();
+        let zero = shared.push_front(0);
+        assert_eq!(shared.get(0), Some(0));
+        assert_eq!(shared.get(3), Some(3));
+        assert_eq!(shared.get(4), None);
+        assert_eq!(shared.get_by_handle(&zero), Some(0));
+
+        // LRU: использованный элемент переносится в начало, вытесняется хвост
+        assert_eq!(shared.remove(&handles[1]), Some(2));
+        let two = shared.push_front(2);
+        assert_eq!(shared.remove(&handles[1]), None);
+        assert_eq!(shared.pop_back(), Some(3));
+        assert_eq!(shared.iter().collect::<Vec<_>>(), [2, 0, 1]);
+        assert_eq!(shared.get_by_handle(&two), Some(2));
+        assert_eq!(shared.get_by_handle(&handles[2]), None);
+        // Хэндл задней половины находится и после переноса в переднюю
+        assert_eq!(shared.pop_front(), Some(2));
+        assert_eq!(shared.pop_front(), Some(0));
+        assert_eq!(shared.get_by_handle(&handles[0]), Some(1));
+        assert_eq!(shared.remove(&handles[0]), Some(1));
+        assert!(shared.is_empty());
+    }
+
+    #[test]
+    fn test_foreign_handles_during_concurrent_pops() {
+        let list = ThreadSafeDoublyLinkedList::new();
+        let other = ThreadSafeDoublyLinkedList::<u32>::new();
+        let handles: Vec<_> = (0..10_000).map(|i| list.push_back(i)).collect();
+        let popper = {
+            let list = list.clone();
+            thread::spawn(move || while list.pop_front().is_some() {})
+        };
+        while !popper.is_finished() {
+            for handle in &handles {
+                assert_eq!(other.get_by_handle(handle), None);
+                assert_eq!(other.remove(handle), None);
+            }
+        }
+        popper.join().unwrap();
+    }
+
+    #[test]
+    fn test_bounded_blocking_deque() {
+        let queue = BoundedBlockingDeque::new(2);
+        assert_eq!(queue.pop_back_timeout(Duration::from_millis(10)), None);
+        queue.try_push_back(1).unwrap();
+        queue.try_push_front(0).unwrap();
+        assert_eq!(queue.try_push_back(2), Err(Full(2)));
+        assert_eq!(queue.len(), queue.capacity());
+
+        // Производитель ждет, пока потребитель не освободит место
+        let producer = {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                for i in 2..10 {
+                    queue.push_back(i);
+                }
+            })
+        };
+        let consumed: Vec<_> =
+            std::iter::from_fn(|| queue.pop_front_timeout(Duration::from_secs(5)))
+                .take(10)
+                .collect();
+        producer.join().unwrap();
+        assert_eq!(consumed, (0..10).collect::<Vec<_>>());
+        assert!(queue.is_empty());
+    }
+
+    /// Элемент, записывающий свой номер в общий журнал при освобождении
+    struct Tracked(u32, Rc<RefCell<Vec<u32>>>);
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+
+    // Тесты освобождения имеет смысл гонять и под Miri, который заметит утечки
+    // и двойные освобождения: cargo +nightly miri test -p step_1 drop
+    #[test]
+    fn test_drop_releases_every_element_once() {
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let mut list: DoublyLinkedList<_> =
+            (0..10).map(|i| Tracked(i, dropped.clone())).collect();
+
+        drop(list.pop_front());
+        drop(list.pop_back());
+        drop(list.remove_at(3));
+        list.retain(|item| item.0 % 2 == 0);
+        let mut cursor = list.cursor_front();
+        drop(cursor.remove_current());
+        let tail = list.split_off(1).unwrap();
+        let mut rest = tail.into_iter();
+        drop(rest.next_back());
+        assert_eq!(dropped.borrow().len(), 9);
+
+        drop(rest);
+        drop(list);
+        let mut dropped = dropped.take();
+        dropped.sort_unstable();
+        assert_eq!(dropped, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_drop_long_list() {
+        let len = if cfg!(miri) { 1_000 } else { 200_000 };
+        // Рекурсивное освобождение цепочки переполнило бы стек теста
+        let list: DoublyLinkedList<_> = (0..len).collect();
+        let shared: ThreadSafeDoublyLinkedList<_> = list.into_iter().collect();
+        assert_eq!(shared.len(), len);
+        drop(shared);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_iter() {
+        use rayon::prelude::*;
+
+        let list: DoublyLinkedList<u64> = (1..=1000).collect();
+        let squares: u64 = list.par_iter().map(|x| x * x).sum();
+        assert_eq!(squares, list.iter().map(|x| x * x).sum::<u64>());
+        let doubled: Vec<_> = list.par_iter().map(|x| x * 2).collect();
+        assert_eq!(doubled, (1..=1000).map(|x| x * 2).collect::<Vec<_>>());
+
+        let shared: ThreadSafeDoublyLinkedList<_> = list.into_iter().collect();
+        shared.push_back(1001);
+        shared.push_front(0);
+        assert_eq!(shared.par_iter().len(), 1002);
+        assert_eq!(shared.par_iter().max(), Some(1001));
+        assert_eq!(shared.par_iter().collect::<Vec<_>>(), shared.iter().collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let list: DoublyLinkedList<_> = ["a", "b", "c"].into_iter().map(String::from).collect();
+        let json = serde_json::to_string(&list).unwrap();
+        assert_eq!(json, r#"["a","b","c"]"#);
+        let restored: DoublyLinkedList<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.iter().rev().collect::<Vec<_>>(), ["c", "b", "a"]);
+
+        let shared: ThreadSafeDoublyLinkedList<u32> = serde_json::from_str("[1, 2]").unwrap();
+        shared.push_back(3);
+        assert_eq!(serde_json::to_string(&shared).unwrap(), "[1,2,3]");
+        assert!(serde_json::from_str::<DoublyLinkedList<u32>>("{}").is_err());
+    }
+
+    #[test]
+    fn test_thread_safe_iterator() {
+        let list = ThreadSafeDoublyLinkedList::new();
+        list.push_back(10);
+        list.push_back(20);
+        list.push_back(30);
+        
+        let mut iter = list.iter();
+        assert_eq!(iter.next(), Some(10));
+        assert_eq!(iter.next(), Some(20));
+        assert_eq!(iter.next(), Some(30));
+        assert_eq!(iter.next(), None);
+    }
+}
+
+/// Исчерпывающая проверка чередований потоков, см. фичу `loom`
+#[cfg(all(test, feature = "loom"))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    #[test]
+    fn concurrent_pushes_keep_both_elements() {
+        loom::model(|| {
+            let list = ThreadSafeDoublyLinkedList::new();
+            let front = list.clone();
+            let handle = thread::spawn(move || front.push_front(1));
+            list.push_back(2);
+            handle.join().unwrap();
+
+            assert_eq!(list.len(), 2);
+            assert_eq!(list.iter().collect::<Vec<_>>(), vec![1, 2]);
+        });
+    }
+
+    #[test]
+    fn concurrent_pops_take_distinct_elements() {
+        loom::model(|| {
+            let list = ThreadSafeDoublyLinkedList::new();
+            list.push_back(1);
+            list.push_back(2);
+            let back = list.clone();
+            let handle = thread::spawn(move || back.pop_back());
+            let front = list.pop_front();
+            let back = handle.join().unwrap();
+
+            let mut popped = vec![front.unwrap(), back.unwrap()];
+            popped.sort();
+            assert_eq!(popped, vec![1, 2]);
+            assert!(list.is_empty());
+            assert_eq!(list.pop_front(), None);
+        });
+    }
+
+    #[test]
+    fn push_racing_pop_keeps_links_consistent() {
+        loom::model(|| {
+            let list = ThreadSafeDoublyLinkedList::new();
+            list.push_back(1);
+            let pusher = list.clone();
+            let handle = thread::spawn(move || pusher.push_front(0));
+            assert_eq!(list.pop_back(), Some(1));
+            handle.join().unwrap();
+
+            assert_eq!(list.iter().collect::<Vec<_>>(), vec![0]);
+            assert_eq!(list.pop_back(), Some(0));
+            assert_eq!(list.pop_front(), None);
+        });
+    }
+}
@@ -1,24 +1,10 @@
 use std::marker::PhantomData;
 
 mod post {
-    #[derive(Clone, Debug, PartialEq, Eq)]
-    pub struct Id(u64);
-
-    impl Id {
-        pub fn new(value: u64) -> Self {
-            Self(value)
-        }
+    use btreemap_proc_macro::TypedId;
 
-        pub fn get(&self) -> u64 {
-            self.0
-        }
-    }
-
-    impl From<u64> for Id {
-        fn from(value: u64) -> Self {
-            Self::new(value)
-        }
-    }
+    #[derive(Clone, Debug, PartialEq, Eq, TypedId)]
+    pub struct Id(u64);
 
     #[derive(Clone, Debug, PartialEq, Eq)]
     pub struct Title(String);
@@ -72,24 +58,10 @@ mod post {
 }
 
 mod user {
-    #[derive(Clone, Debug, PartialEq, Eq)]
-    pub struct Id(u64);
-
-    impl Id {
-        pub fn new(value: u64) -> Self {
-            Self(value)
-        }
+    use btreemap_proc_macro::TypedId;
 
-        pub fn get(&self) -> u64 {
-            self.0
-        }
-    }
-
-    impl From<u64> for Id {
-        fn from(value: u64) -> Self {
-            Self::new(value)
-        }
-    }
+    #[derive(Clone, Debug, PartialEq, Eq, TypedId)]
+    pub struct Id(u64);
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
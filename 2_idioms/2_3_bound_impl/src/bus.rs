@@ -0,0 +1,212 @@
+//! Delivery of committed events to projections and process managers.
+//!
+//! [`EventBus`] calls its handlers synchronously right after the events are
+//! committed, while [`BroadcastEventBus`] queues them for async subscribers
+//! running on their own tasks.
+
+use std::sync::{Arc, RwLock};
+
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{Event, Version};
+
+/// An event persisted in the stream of some aggregate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommittedEvent<E> {
+    /// The [`Aggregate::aggregate_type`](crate::Aggregate::aggregate_type) of
+    /// the stream.
+    pub aggregate_type: &'static str,
+    /// The identifier of the aggregate the event belongs to.
+    pub aggregate_id: String,
+    /// The version of the aggregate right after the event.
+    pub version: Version,
+    /// The event itself.
+    pub event: E,
+}
+
+/// A destination of committed events.
+pub trait EventPublisher<E> {
+    /// Delivers the event to subscribers.
+    fn publish(&self, event: &CommittedEvent<E>);
+}
+
+impl<E, P> EventPublisher<E> for Arc<P>
+where
+    P: EventPublisher<E> + ?Sized,
+{
+    fn publish(&self, event: &CommittedEvent<E>) {
+        (**self).publish(event);
+    }
+}
+
+type Handler<E> = Box<dyn Fn(&CommittedEvent<E>) + Send + Sync>;
+
+/// Calls subscribed handlers in the order of subscription, on the thread
+/// committing the events.
+pub struct EventBus<E> {
+    /// Handlers along with the event type they are interested in, if any.
+    handlers: RwLock<Vec<(Option<&'static str>, Handler<E>)>>,
+}
+
+impl<E> Default for EventBus<E> {
+    fn default() -> Self {
+        Self {
+            handlers: RwLock::default(),
+        }
+    }
+}
+
+impl<E> EventBus<E> {
+    /// Creates a bus without subscribers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribes the handler to events of the given [`Event::event_type`].
+    pub fn subscribe<H>(&self, event_type: &'static str, handler: H)
+    where
+        H: Fn(&CommittedEvent<E>) + Send + Sync + 'static,
+    {
+        self.add(Some(event_type), Box::new(handler));
+    }
+
+    /// Subscribes the handler to all events.
+    pub fn subscribe_all<H>(&self, handler: H)
+    where
+        H: Fn(&CommittedEvent<E>) + Send + Sync + 'static,
+    {
+        self.add(None, Box::new(handler));
+    }
+
+    fn add(&self, event_type: Option<&'static str>, handler: Handler<E>) {
+        self.handlers
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((event_type, handler));
+    }
+}
+
+impl<E: Event> EventPublisher<E> for EventBus<E> {
+    fn publish(&self, event: &CommittedEvent<E>) {
+        let event_type = event.event.event_type();
+        let handlers = self.handlers.read().unwrap_or_else(|e| e.into_inner());
+        for (wanted, handler) in handlers.iter() {
+            if wanted.is_none_or(|wanted| wanted == event_type) {
+                handler(event);
+            }
+        }
+    }
+}
+
+/// Queues committed events for async subscribers.
+///
+/// Subscribers lagging behind by more than the capacity of the bus miss the
+/// oldest events, which is reported by [`RecvError::Lagged`].
+pub struct BroadcastEventBus<E> {
+    sender: broadcast::Sender<CommittedEvent<E>>,
+}
+
+impl<E: Clone> BroadcastEventBus<E> {
+    /// Creates a bus retaining up to `capacity` events per subscriber.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Subscribes to events of the given [`Event::event_type`]s published
+    /// after this call.
+    pub fn subscribe(&self, event_types: &[&'static str]) -> EventSubscription<E> {
+        EventSubscription {
+            receiver: self.sender.subscribe(),
+            event_types: Some(event_types.to_vec()),
+        }
+    }
+
+    /// Subscribes to all events published after this call.
+    pub fn subscribe_all(&self) -> EventSubscription<E> {
+        EventSubscription {
+            receiver: self.sender.subscribe(),
+            event_types: None,
+        }
+    }
+}
+
+impl<E: Clone> EventPublisher<E> for BroadcastEventBus<E> {
+    fn publish(&self, event: &CommittedEvent<E>) {
+        // Nobody listening is fine, there is just nothing to deliver.
+        let _ = self.sender.send(event.clone());
+    }
+}
+
+/// Receiving half of a [`BroadcastEventBus`] subscription.
+pub struct EventSubscription<E> {
+    receiver: broadcast::Receiver<CommittedEvent<E>>,
+    event_types: Option<Vec<&'static str>>,
+}
+
+impl<E: Event + Clone> EventSubscription<E> {
+    /// Waits for the next event the subscription is interested in.
+    pub async fn recv(&mut self) -> Result<CommittedEvent<E>, RecvError> {
+        loop {
+            let event = self.receiver.recv().await?;
+            let wanted = self
+                .event_types
+                .as_ref()
+                .is_none_or(|types| types.contains(&event.event.event_type()));
+            if wanted {
+                return Ok(event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::counter::CounterEvent::{self, Decremented, Incremented};
+
+    fn committed(version: u64, event: CounterEvent) -> CommittedEvent<CounterEvent> {
+        CommittedEvent {
+            aggregate_type: "counter",
+            aggregate_id: "c1".into(),
+            version: Version::new(version),
+            event,
+        }
+    }
+
+    #[test]
+    fn dispatches_synchronously_by_event_type() {
+        let bus = EventBus::new();
+        let decrements = Arc::new(Mutex::new(Vec::new()));
+        let total = Arc::new(Mutex::new(0));
+
+        let seen = decrements.clone();
+        bus.subscribe("counter_decremented", move |e| {
+            seen.lock().unwrap().push(e.version)
+        });
+        let count = total.clone();
+        bus.subscribe_all(move |_| *count.lock().unwrap() += 1);
+
+        bus.publish(&committed(1, Incremented));
+        bus.publish(&committed(2, Decremented));
+
+        assert_eq!(*decrements.lock().unwrap(), vec![Version::new(2)]);
+        assert_eq!(*total.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn broadcasts_to_async_subscribers() {
+        let bus = BroadcastEventBus::new(8);
+        let mut increments = bus.subscribe(&["counter_incremented"]);
+        let mut all = bus.subscribe_all();
+
+        bus.publish(&committed(1, Decremented));
+        bus.publish(&committed(2, Incremented));
+
+        assert_eq!(increments.recv().await, Ok(committed(2, Incremented)));
+        assert_eq!(all.recv().await, Ok(committed(1, Decremented)));
+        assert_eq!(all.recv().await, Ok(committed(2, Incremented)));
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{Aggregate, AggregateEvent, HydratedAggregate};
+
+/// A request to change an aggregate.
+///
+/// Commands only *decide* which events should happen given the current state
+/// of the aggregate; the state itself is changed solely by *applying* those
+/// events, so replaying them later yields exactly the same aggregate.
+pub trait AggregateCommand<A: Aggregate> {
+    /// The event produced by this command.
+    type Event: AggregateEvent<A>;
+
+    /// The reason for the command being rejected.
+    type Error;
+
+    /// Validates the command against the aggregate, returning the events it produces.
+    fn decide(self, aggregate: &A) -> Result<Vec<Self::Event>, Self::Error>;
+}
+
+impl<A> HydratedAggregate<A>
+where
+    A: Aggregate,
+{
+    /// Decides the command against the current state, applies the produced
+    /// events and returns them, e.g. for persisting.
+    ///
+    /// Nothing is applied if the command is rejected.
+    pub fn execute<C>(&mut self, command: C) -> Result<Vec<C::Event>, C::Error>
+    where
+        C: AggregateCommand<A>,
+        C::Event: Clone,
+    {
+        let events = command.decide(self.state())?;
+        self.apply_events(events.iter().cloned());
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Version;
+    use crate::counter::{Counter, CounterError, CounterEvent, Decrement, Increment};
+
+    #[test]
+    fn execute_applies_and_returns_events() {
+        let mut aggregate = HydratedAggregate::<Counter>::default();
+
+        let events = aggregate.execute(Increment).unwrap();
+        assert_eq!(events, vec![CounterEvent::Incremented]);
+        aggregate.execute(Increment).unwrap();
+        aggregate.execute(Decrement).unwrap();
+
+        assert_eq!(aggregate.state().value(), 1);
+        assert_eq!(aggregate.version(), Version::new(3));
+    }
+
+    #[test]
+    fn rejected_command_leaves_aggregate_untouched() {
+        let mut aggregate = HydratedAggregate::<Counter>::default();
+
+        assert_eq!(aggregate.execute(Decrement), Err(CounterError::Underflow));
+        assert_eq!(aggregate.state().value(), 0);
+        assert_eq!(aggregate.version(), Version::Initial);
+    }
+}
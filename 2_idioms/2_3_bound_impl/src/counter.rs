@@ -0,0 +1,131 @@
+//! Reference implementation of an aggregate with its events and commands.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Aggregate, AggregateEvent, AggregateId, command::AggregateCommand, event_types,
+    process::ProcessManager,
+};
+
+/// A counter which can never go below zero.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Counter(u32);
+
+impl Counter {
+    /// The current value of the counter.
+    pub fn value(&self) -> u32 {
+        self.0
+    }
+}
+
+impl Aggregate for Counter {
+    fn aggregate_type() -> &'static str {
+        "counter"
+    }
+}
+
+/// Identifier of a [`Counter`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CounterId(pub String);
+
+impl AggregateId<Counter> for CounterId {
+    fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Things that happened to a [`Counter`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CounterEvent {
+    Incremented,
+    Decremented,
+}
+
+event_types! {
+    CounterEvent {
+        Incremented => "counter_incremented",
+        Decremented => "counter_decremented",
+    }
+}
+
+impl AggregateEvent<Counter> for CounterEvent {
+    fn apply_to(self, aggregate: &mut Counter) {
+        match self {
+            CounterEvent::Incremented => aggregate.0 += 1,
+            CounterEvent::Decremented => aggregate.0 -= 1,
+        }
+    }
+}
+
+/// Reasons for a [`Counter`] command to be rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CounterError {
+    /// The counter already holds its maximum value.
+    Overflow,
+    /// The counter is already zero.
+    Underflow,
+}
+
+/// Increases the counter by one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Increment;
+
+impl AggregateCommand<Counter> for Increment {
+    type Event = CounterEvent;
+    type Error = CounterError;
+
+    fn decide(self, counter: &Counter) -> Result<Vec<CounterEvent>, CounterError> {
+        if counter.0 == u32::MAX {
+            return Err(CounterError::Overflow);
+        }
+        Ok(vec![CounterEvent::Incremented])
+    }
+}
+
+/// Decreases the counter by one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Decrement;
+
+impl AggregateCommand<Counter> for Decrement {
+    type Event = CounterEvent;
+    type Error = CounterError;
+
+    fn decide(self, counter: &Counter) -> Result<Vec<CounterEvent>, CounterError> {
+        if counter.0 == 0 {
+            return Err(CounterError::Underflow);
+        }
+        Ok(vec![CounterEvent::Decremented])
+    }
+}
+
+/// Increments a milestones counter each time the watched counter reaches one
+/// of the thresholds on its way up.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MilestoneTracker {
+    target: CounterId,
+    thresholds: Vec<u32>,
+}
+
+impl MilestoneTracker {
+    /// Creates a tracker issuing [`Increment`]s to the `target` counter.
+    pub fn new(target: CounterId, thresholds: impl IntoIterator<Item = u32>) -> Self {
+        Self {
+            target,
+            thresholds: thresholds.into_iter().collect(),
+        }
+    }
+}
+
+impl ProcessManager<Counter> for MilestoneTracker {
+    type Event = CounterEvent;
+    type Command = (CounterId, Increment);
+
+    fn react(&self, counter: &Counter, event: &CounterEvent) -> Vec<Self::Command> {
+        match event {
+            CounterEvent::Incremented if self.thresholds.contains(&counter.0) => {
+                vec![(self.target.clone(), Increment)]
+            }
+            _ => vec![],
+        }
+    }
+}
@@ -0,0 +1,316 @@
+//! Serialization of events into self-describing envelopes.
+//!
+//! Every serializable event declares the stable `event_type` strings it may
+//! carry, and an [`EventRegistry`] maps those strings back to decoders. This
+//! lets persistent stores keep events as `(event_type, payload)` pairs and
+//! restore them without knowing the concrete types upfront.
+//!
+//! Envelopes also record the schema version of their payload, so events
+//! stored in an older shape can be brought up to date by an [`UpcasterChain`]
+//! before being decoded.
+
+use std::{collections::HashMap, error::Error, fmt};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use crate::{Event, upcast::UpcasterChain};
+
+/// An [`Event`] which can be stored in an [`EventEnvelope`].
+pub trait RegisteredEvent: Event + Serialize + DeserializeOwned {
+    /// All the [`Event::event_type`] values this type may produce.
+    ///
+    /// Note: These are persisted along with the events, so should never change.
+    const EVENT_TYPES: &'static [&'static str];
+
+    /// Schema version of the serialized representation.
+    ///
+    /// Note: This should be bumped on every incompatible change of the type,
+    /// along with registering an upcaster from the previous version.
+    const VERSION: u32 = 1;
+}
+
+/// Implements [`Event`] and [`RegisteredEvent`] for an enum, mapping each of
+/// its variants to a stable event type. The schema version defaults to 1.
+///
+/// ```ignore
+/// event_types! {
+///     #[version = 2]
+///     CounterEvent {
+///         Incremented => "counter_incremented",
+///         Decremented => "counter_decremented",
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! event_types {
+    (
+        $(#[version = $version:literal])?
+        $event:ty { $($variant:ident => $event_type:literal),+ $(,)? }
+    ) => {
+        impl $crate::Event for $event {
+            fn event_type(&self) -> &'static str {
+                match self {
+                    $(Self::$variant { .. } => $event_type,)+
+                }
+            }
+        }
+
+        impl $crate::envelope::RegisteredEvent for $event {
+            const EVENT_TYPES: &'static [&'static str] = &[$($event_type),+];
+            $(const VERSION: u32 = $version;)?
+        }
+    };
+}
+
+/// A serialized event along with its type.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EventEnvelope {
+    /// The [`Event::event_type`] of the wrapped event.
+    pub event_type: String,
+    /// The [`RegisteredEvent::VERSION`] the payload was serialized with.
+    ///
+    /// Envelopes stored before versioning was introduced count as version 1.
+    #[serde(default = "EventEnvelope::initial_version")]
+    pub version: u32,
+    /// The serialized event itself.
+    pub payload: Value,
+}
+
+impl EventEnvelope {
+    /// Serializes the event into a new envelope of its current version.
+    pub fn wrap<E>(event: &E) -> Result<Self, serde_json::Error>
+    where
+        E: RegisteredEvent,
+    {
+        Ok(Self {
+            event_type: event.event_type().to_owned(),
+            version: E::VERSION,
+            payload: serde_json::to_value(event)?,
+        })
+    }
+
+    fn initial_version() -> u32 {
+        1
+    }
+}
+
+/// Reasons for an event to fail being restored from its envelope.
+#[derive(Debug)]
+pub enum EnvelopeError {
+    /// No decoder was registered for the event type.
+    UnknownEventType(String),
+    /// The payload doesn't match the type registered for the event type.
+    Payload(serde_json::Error),
+    /// The decoded event reports another event type than the stored one.
+    TypeMismatch {
+        expected: String,
+        actual: &'static str,
+    },
+    /// No upcaster brings the payload to the version of the registered type.
+    UnsupportedVersion {
+        event_type: String,
+        version: u32,
+        expected: u32,
+    },
+    /// An upcaster failed to convert the payload.
+    Upcast {
+        event_type: String,
+        version: u32,
+        reason: String,
+    },
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownEventType(ty) => write!(f, "unknown event type `{ty}`"),
+            Self::Payload(err) => write!(f, "malformed event payload: {err}"),
+            Self::TypeMismatch { expected, actual } => {
+                write!(f, "expected `{expected}` event, decoded `{actual}`")
+            }
+            Self::UnsupportedVersion {
+                event_type,
+                version,
+                expected,
+            } => write!(
+                f,
+                "cannot upcast `{event_type}` event from version {version} to {expected}",
+            ),
+            Self::Upcast {
+                event_type,
+                version,
+                reason,
+            } => write!(
+                f,
+                "failed to upcast `{event_type}` event from version {version}: {reason}",
+            ),
+        }
+    }
+}
+
+impl Error for EnvelopeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Payload(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+type Decoder<E> = fn(Value) -> Result<E, EnvelopeError>;
+
+/// Maps event types to decoders producing `E`, usually an aggregate's event.
+pub struct EventRegistry<E> {
+    /// Decoders along with the version of the payloads they accept.
+    decoders: HashMap<&'static str, (u32, Decoder<E>)>,
+    upcasters: UpcasterChain,
+}
+
+impl<E> Default for EventRegistry<E> {
+    fn default() -> Self {
+        Self {
+            decoders: HashMap::new(),
+            upcasters: UpcasterChain::new(),
+        }
+    }
+}
+
+impl<E> EventRegistry<E> {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers all the event types of `T`, decoded into `E`.
+    ///
+    /// # Panics
+    ///
+    /// If any of the event types is registered already.
+    pub fn register<T>(mut self) -> Self
+    where
+        T: RegisteredEvent + Into<E>,
+    {
+        for &event_type in T::EVENT_TYPES {
+            let decoder: Decoder<E> = |payload| {
+                let event: T = serde_json::from_value(payload).map_err(EnvelopeError::Payload)?;
+                Ok(event.into())
+            };
+            let previous = self.decoders.insert(event_type, (T::VERSION, decoder));
+            assert!(
+                previous.is_none(),
+                "event type `{event_type}` is registered twice",
+            );
+        }
+        self
+    }
+
+    /// Sets the upcasters applied to envelopes before they are decoded.
+    pub fn with_upcasters(mut self, upcasters: UpcasterChain) -> Self {
+        self.upcasters = upcasters;
+        self
+    }
+
+    /// Whether the event type can be decoded by this registry.
+    pub fn contains(&self, event_type: &str) -> bool {
+        self.decoders.contains_key(event_type)
+    }
+
+    /// Restores an event from its type and a payload of the current version.
+    pub fn deserialize_event(&self, event_type: &str, payload: Value) -> Result<E, EnvelopeError>
+    where
+        E: Event,
+    {
+        let (_, decode) = self
+            .decoders
+            .get(event_type)
+            .ok_or_else(|| EnvelopeError::UnknownEventType(event_type.to_owned()))?;
+        let event = decode(payload)?;
+        if event.event_type() != event_type {
+            return Err(EnvelopeError::TypeMismatch {
+                expected: event_type.to_owned(),
+                actual: event.event_type(),
+            });
+        }
+        Ok(event)
+    }
+
+    /// Restores an event from its envelope, upcasting it first if it was
+    /// stored with an older version.
+    pub fn open(&self, envelope: EventEnvelope) -> Result<E, EnvelopeError>
+    where
+        E: Event,
+    {
+        let envelope = self.upcasters.upcast(envelope)?;
+        let &(expected, _) = self
+            .decoders
+            .get(envelope.event_type.as_str())
+            .ok_or_else(|| EnvelopeError::UnknownEventType(envelope.event_type.clone()))?;
+        if envelope.version != expected {
+            return Err(EnvelopeError::UnsupportedVersion {
+                event_type: envelope.event_type,
+                version: envelope.version,
+                expected,
+            });
+        }
+        self.deserialize_event(&envelope.event_type, envelope.payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counter::CounterEvent;
+
+    #[test]
+    fn events_round_trip_through_envelopes() {
+        let registry = EventRegistry::<CounterEvent>::new().register::<CounterEvent>();
+
+        for event in [CounterEvent::Incremented, CounterEvent::Decremented] {
+            let envelope = EventEnvelope::wrap(&event).unwrap();
+            assert_eq!(envelope.event_type, event.event_type());
+            assert_eq!(envelope.version, 1);
+
+            let stored = serde_json::to_string(&envelope).unwrap();
+            let restored: EventEnvelope = serde_json::from_str(&stored).unwrap();
+            assert_eq!(registry.open(restored).unwrap(), event);
+        }
+
+        let legacy = r#"{"event_type": "counter_incremented", "payload": "Incremented"}"#;
+        let legacy: EventEnvelope = serde_json::from_str(legacy).unwrap();
+        assert_eq!(legacy.version, 1);
+        assert_eq!(registry.open(legacy).unwrap(), CounterEvent::Incremented);
+    }
+
+    #[test]
+    fn rejects_unknown_and_mismatching_events() {
+        let registry = EventRegistry::<CounterEvent>::new().register::<CounterEvent>();
+        assert!(registry.contains("counter_incremented"));
+
+        let unknown = registry.deserialize_event("counter_reset", Value::Null);
+        assert!(
+            matches!(unknown, Err(EnvelopeError::UnknownEventType(ty)) if ty == "counter_reset")
+        );
+
+        let payload = serde_json::to_value(CounterEvent::Decremented).unwrap();
+        let mismatch = registry.deserialize_event("counter_incremented", payload);
+        assert!(matches!(
+            mismatch,
+            Err(EnvelopeError::TypeMismatch {
+                actual: "counter_decremented",
+                ..
+            })
+        ));
+
+        let malformed = registry.deserialize_event("counter_incremented", Value::Bool(true));
+        assert!(matches!(malformed, Err(EnvelopeError::Payload(_))));
+    }
+
+    #[test]
+    #[should_panic(expected = "registered twice")]
+    fn duplicate_event_types_panic() {
+        let _ = EventRegistry::<CounterEvent>::new()
+            .register::<CounterEvent>()
+            .register::<CounterEvent>();
+    }
+}
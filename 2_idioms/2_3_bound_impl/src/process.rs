@@ -0,0 +1,158 @@
+//! Process managers (sagas) reacting to events of one aggregate by issuing
+//! commands to others.
+
+use crate::{Aggregate, AggregateEvent, HydratedAggregate, Version};
+
+/// Decides which commands to issue in reaction to an event of aggregate `A`.
+///
+/// Managers are stateless: all they need to know is the state of the source
+/// aggregate right after the event, which is tracked by a [`ProcessDriver`].
+pub trait ProcessManager<A: Aggregate> {
+    /// The event of the source aggregate this manager reacts to.
+    type Event: AggregateEvent<A> + Clone;
+
+    /// The command issued to other aggregates.
+    type Command;
+
+    /// Returns the commands to issue in reaction to the event.
+    fn react(&self, state: &A, event: &Self::Event) -> Vec<Self::Command>;
+}
+
+/// Feeds the event stream of a source aggregate to a [`ProcessManager`],
+/// remembering the position of the last fully handled event.
+///
+/// Commands are delivered at least once: if dispatching fails, the position
+/// isn't advanced, so the event is reacted to again on the next run.
+#[derive(Debug)]
+pub struct ProcessDriver<A, P> {
+    manager: P,
+    source: HydratedAggregate<A>,
+    position: Version,
+}
+
+impl<A, P> ProcessDriver<A, P>
+where
+    A: Aggregate,
+    P: ProcessManager<A>,
+{
+    /// Creates a driver which hasn't handled any events yet.
+    pub fn new(manager: P) -> Self {
+        Self::resume(manager, Version::Initial)
+    }
+
+    /// Creates a driver continuing after the previously saved position.
+    pub fn resume(manager: P, position: Version) -> Self {
+        Self {
+            manager,
+            source: HydratedAggregate::default(),
+            position,
+        }
+    }
+
+    /// The version of the last handled event, to be saved for resuming.
+    pub fn position(&self) -> Version {
+        self.position
+    }
+
+    /// The driven process manager.
+    pub fn manager(&self) -> &P {
+        &self.manager
+    }
+
+    /// Handles all the events of the full source stream after the current
+    /// position, passing the issued commands to `dispatch`.
+    ///
+    /// Returns the number of handled events.
+    pub fn catch_up<'e, I, D, E>(&mut self, stream: I, mut dispatch: D) -> Result<usize, E>
+    where
+        P::Event: 'e,
+        I: IntoIterator<Item = &'e P::Event>,
+        D: FnMut(P::Command) -> Result<(), E>,
+    {
+        let mut handled = 0;
+        let mut version = Version::Initial;
+        for event in stream {
+            version.incr();
+            // The source may already contain the event if dispatching its
+            // commands failed on a previous run.
+            if version > self.source.version() {
+                self.source.apply(event.clone());
+            }
+            if version <= self.position {
+                continue;
+            }
+            for command in self.manager.react(self.source.state(), event) {
+                dispatch(command)?;
+            }
+            self.position = version;
+            handled += 1;
+        }
+        Ok(handled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counter::{
+        Counter,
+        CounterEvent::{Decremented, Incremented},
+        CounterId, Increment, MilestoneTracker,
+    };
+
+    fn tracker() -> MilestoneTracker {
+        MilestoneTracker::new(CounterId("milestones".into()), [2, 3])
+    }
+
+    #[test]
+    fn reacts_to_counter_thresholds() {
+        let mut milestones = HydratedAggregate::<Counter>::default();
+        let mut driver = ProcessDriver::new(tracker());
+
+        let stream = [
+            Incremented,
+            Incremented,
+            Decremented,
+            Incremented,
+            Incremented,
+        ];
+        let handled = driver
+            .catch_up(&stream, |(target, command)| {
+                assert_eq!(target.0, "milestones");
+                milestones.execute(command).map(|_| ())
+            })
+            .unwrap();
+
+        assert_eq!(handled, 5);
+        assert_eq!(driver.position(), Version::new(5));
+        // Threshold 2 is reached twice, threshold 3 once.
+        assert_eq!(milestones.state().value(), 3);
+    }
+
+    #[test]
+    fn resumes_from_saved_position_and_retries_failures() {
+        let stream = vec![Incremented, Incremented, Incremented];
+        let mut issued = Vec::new();
+
+        let mut driver = ProcessDriver::new(tracker());
+        let failed = driver.catch_up(&stream, |_| Err("target unavailable"));
+        assert_eq!(failed, Err("target unavailable"));
+        assert_eq!(driver.position(), Version::new(1));
+
+        let saved = driver.position();
+        let mut driver = ProcessDriver::resume(tracker(), saved);
+        let handled = driver
+            .catch_up(&stream, |command| {
+                issued.push(command);
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        assert_eq!(handled, 2);
+        assert_eq!(issued, vec![(CounterId("milestones".into()), Increment); 2]);
+
+        let handled = driver
+            .catch_up(&stream, |_| -> Result<(), ()> { unreachable!() })
+            .unwrap();
+        assert_eq!(handled, 0);
+    }
+}
@@ -0,0 +1,206 @@
+//! In-memory event store of aggregates publishing committed events.
+
+use std::{collections::HashMap, fmt, marker::PhantomData};
+
+use crate::{
+    Aggregate, AggregateEvent, AggregateId, Entity, HydratedAggregate, Version,
+    bus::{CommittedEvent, EventPublisher},
+};
+
+/// The stream was changed since the aggregate was loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VersionConflict {
+    pub expected: Version,
+    pub actual: Version,
+}
+
+impl fmt::Display for VersionConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expected aggregate at {:?}, but it is at {:?}",
+            self.expected, self.actual,
+        )
+    }
+}
+
+impl std::error::Error for VersionConflict {}
+
+/// Stores event streams of `A` aggregates, keyed by their identifiers.
+pub struct Repository<A, E> {
+    streams: HashMap<String, Vec<E>>,
+    /// Aggregate of every committed event, in the commit order.
+    log: Vec<String>,
+    publishers: Vec<Box<dyn EventPublisher<E> + Send + Sync>>,
+    _aggregate: PhantomData<A>,
+}
+
+impl<A, E> Default for Repository<A, E> {
+    fn default() -> Self {
+        Self {
+            streams: HashMap::new(),
+            log: Vec::new(),
+            publishers: Vec::new(),
+            _aggregate: PhantomData,
+        }
+    }
+}
+
+impl<A, E> Repository<A, E>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + Clone,
+{
+    /// Creates an empty repository publishing nowhere.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a publisher notified about every event after it is committed.
+    pub fn with_publisher<P>(mut self, publisher: P) -> Self
+    where
+        P: EventPublisher<E> + Send + Sync + 'static,
+    {
+        self.publishers.push(Box::new(publisher));
+        self
+    }
+
+    /// Replays the stream of the aggregate, which is empty for unknown ones.
+    pub fn load<I>(&self, id: I) -> Entity<I, A>
+    where
+        I: AggregateId<A>,
+    {
+        let mut aggregate = HydratedAggregate::default();
+        if let Some(stream) = self.streams.get(id.as_str()) {
+            aggregate.apply_events(stream.iter().cloned());
+        }
+        Entity::new(id, aggregate)
+    }
+
+    /// Appends events to the stream of the aggregate, provided it is still at
+    /// the `expected` version, and publishes them once committed.
+    ///
+    /// Returns the new version of the aggregate.
+    pub fn save<I>(
+        &mut self,
+        id: &I,
+        expected: Version,
+        events: Vec<E>,
+    ) -> Result<Version, VersionConflict>
+    where
+        I: AggregateId<A>,
+    {
+        let stream = self.streams.entry(id.as_str().to_owned()).or_default();
+        let actual = Version::new(stream.len() as u64);
+        if actual != expected {
+            return Err(VersionConflict { expected, actual });
+        }
+        stream.extend(events.iter().cloned());
+        self.log
+            .extend(events.iter().map(|_| id.as_str().to_owned()));
+
+        let mut version = actual;
+        for event in events {
+            version.incr();
+            let committed = CommittedEvent {
+                aggregate_type: A::aggregate_type(),
+                aggregate_id: id.as_str().to_owned(),
+                version,
+                event,
+            };
+            for publisher in &self.publishers {
+                publisher.publish(&committed);
+            }
+        }
+        Ok(version)
+    }
+
+    /// Passes all the committed events to `f` in the order they were
+    /// committed across all the streams, e.g. to rebuild a projection.
+    pub fn replay(&self, mut f: impl FnMut(&CommittedEvent<E>)) {
+        let mut cursors = HashMap::<&str, usize>::new();
+        for id in &self.log {
+            let cursor = cursors.entry(id).or_default();
+            let event = self.streams[id][*cursor].clone();
+            *cursor += 1;
+            f(&CommittedEvent {
+                aggregate_type: A::aggregate_type(),
+                aggregate_id: id.clone(),
+                version: Version::new(*cursor as u64),
+                event,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{
+        bus::EventBus,
+        counter::{Counter, CounterEvent, CounterId, Decrement, Increment},
+    };
+
+    #[test]
+    fn saves_and_publishes_committed_events() {
+        let bus = Arc::new(EventBus::<CounterEvent>::new());
+        let projection = Arc::new(Mutex::new(Vec::new()));
+        let seen = projection.clone();
+        bus.subscribe("counter_incremented", move |e| {
+            seen.lock()
+                .unwrap()
+                .push((e.aggregate_id.clone(), e.version))
+        });
+        let mut repository = Repository::<Counter, _>::new().with_publisher(bus);
+
+        let id = CounterId("c1".into());
+        let mut entity = repository.load(id.clone());
+        let loaded_at = entity.aggregate().version();
+        let events = entity.aggregate_mut().execute(Increment).unwrap();
+        assert_eq!(
+            repository.save(&id, loaded_at, events.clone()),
+            Ok(Version::new(1))
+        );
+
+        assert_eq!(
+            repository.save(&id, loaded_at, events),
+            Err(VersionConflict {
+                expected: Version::Initial,
+                actual: Version::new(1),
+            })
+        );
+        assert_eq!(repository.load(id).aggregate().state().value(), 1);
+        assert_eq!(
+            *projection.lock().unwrap(),
+            vec![("c1".to_owned(), Version::new(1))]
+        );
+    }
+
+    #[test]
+    fn replays_events_in_commit_order() {
+        let mut repository = Repository::<Counter, CounterEvent>::new();
+        let (a, b) = (CounterId("a".into()), CounterId("b".into()));
+        repository
+            .save(&a, Version::Initial, vec![CounterEvent::Incremented])
+            .unwrap();
+        repository
+            .save(&b, Version::Initial, vec![CounterEvent::Incremented])
+            .unwrap();
+        let mut entity = repository.load(a.clone());
+        let events = entity.aggregate_mut().execute(Decrement).unwrap();
+        repository.save(&a, Version::new(1), events).unwrap();
+
+        let mut replayed = Vec::new();
+        repository.replay(|e| replayed.push((e.aggregate_id.clone(), e.version, e.event)));
+        assert_eq!(
+            replayed,
+            vec![
+                ("a".to_owned(), Version::new(1), CounterEvent::Incremented),
+                ("b".to_owned(), Version::new(1), CounterEvent::Incremented),
+                ("a".to_owned(), Version::new(2), CounterEvent::Decremented),
+            ]
+        );
+    }
+}
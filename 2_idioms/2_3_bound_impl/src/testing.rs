@@ -0,0 +1,152 @@
+//! Given/when/then harness for behavioral tests of aggregates.
+//!
+//! ```ignore
+//! AggregateTestFixture::<Counter>::given([Incremented])
+//!     .when(Decrement)
+//!     .then_events([Decremented])
+//!     .then_state(&Counter::default());
+//! ```
+
+use std::fmt::Debug;
+
+use crate::{Aggregate, AggregateEvent, HydratedAggregate, command::AggregateCommand};
+
+/// An aggregate hydrated from the given events, awaiting a command.
+#[derive(Debug)]
+pub struct AggregateTestFixture<A> {
+    aggregate: HydratedAggregate<A>,
+}
+
+impl<A: Aggregate> AggregateTestFixture<A> {
+    /// Starts from an aggregate which has no events applied.
+    pub fn given_no_previous_events() -> Self {
+        Self {
+            aggregate: HydratedAggregate::default(),
+        }
+    }
+
+    /// Starts from an aggregate replayed from the events.
+    pub fn given<E, I>(events: I) -> Self
+    where
+        E: AggregateEvent<A>,
+        I: IntoIterator<Item = E>,
+    {
+        let mut fixture = Self::given_no_previous_events();
+        fixture.aggregate.apply_events(events);
+        fixture
+    }
+
+    /// Executes the command against the aggregate.
+    pub fn when<C>(mut self, command: C) -> AggregateTestResult<A, C>
+    where
+        C: AggregateCommand<A>,
+        C::Event: Clone,
+    {
+        let result = self.aggregate.execute(command);
+        AggregateTestResult {
+            aggregate: self.aggregate,
+            result,
+        }
+    }
+}
+
+/// Outcome of the command executed by an [`AggregateTestFixture`].
+///
+/// Failed assertions panic, so these are meant to be used in tests only.
+pub struct AggregateTestResult<A, C: AggregateCommand<A>>
+where
+    A: Aggregate,
+{
+    aggregate: HydratedAggregate<A>,
+    result: Result<Vec<C::Event>, C::Error>,
+}
+
+impl<A, C> AggregateTestResult<A, C>
+where
+    A: Aggregate,
+    C: AggregateCommand<A>,
+{
+    /// Asserts the command succeeded, producing exactly these events.
+    #[track_caller]
+    pub fn then_events<I>(self, expected: I) -> Self
+    where
+        I: IntoIterator<Item = C::Event>,
+        C::Event: PartialEq + Debug,
+        C::Error: Debug,
+    {
+        let expected: Vec<_> = expected.into_iter().collect();
+        match &self.result {
+            Ok(events) => assert_eq!(events, &expected, "unexpected events produced"),
+            Err(err) => panic!("expected events {expected:?}, but command failed: {err:?}"),
+        }
+        self
+    }
+
+    /// Asserts the command was rejected with this error.
+    #[track_caller]
+    pub fn then_error(self, expected: C::Error) -> Self
+    where
+        C::Event: Debug,
+        C::Error: PartialEq + Debug,
+    {
+        match &self.result {
+            Ok(events) => panic!("expected error {expected:?}, but command produced {events:?}"),
+            Err(err) => assert_eq!(err, &expected, "unexpected error returned"),
+        }
+        self
+    }
+
+    /// Asserts the state of the aggregate after the command.
+    #[track_caller]
+    pub fn then_state(self, expected: &A) -> Self
+    where
+        A: PartialEq + Debug,
+    {
+        assert_eq!(
+            self.aggregate.state(),
+            expected,
+            "unexpected aggregate state"
+        );
+        self
+    }
+
+    /// The aggregate after the command, for any further custom assertions.
+    pub fn aggregate(&self) -> &HydratedAggregate<A> {
+        &self.aggregate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Version,
+        counter::{
+            Counter,
+            CounterError::Underflow,
+            CounterEvent::{Decremented, Incremented},
+            Decrement, Increment,
+        },
+    };
+
+    #[test]
+    fn asserts_produced_events_and_errors() {
+        AggregateTestFixture::<Counter>::given([Incremented])
+            .when(Decrement)
+            .then_events([Decremented])
+            .then_state(&Counter::default());
+
+        let result = AggregateTestFixture::<Counter>::given_no_previous_events()
+            .when(Decrement)
+            .then_error(Underflow);
+        assert_eq!(result.aggregate().version(), Version::Initial);
+    }
+
+    #[test]
+    #[should_panic(expected = "command produced [Incremented]")]
+    fn panics_on_unexpected_success() {
+        AggregateTestFixture::<Counter>::given_no_previous_events()
+            .when(Increment)
+            .then_error(Underflow);
+    }
+}
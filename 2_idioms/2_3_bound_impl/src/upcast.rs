@@ -0,0 +1,216 @@
+//! Upgrading of stored events to the current shape of their types.
+//!
+//! Once an event type is persisted, its serialized form can only change along
+//! with an [`Upcaster`] converting the older form. Upcasters are chained in
+//! the order of the changes, so a stream written by any past version of the
+//! application is brought up to date before the events are decoded.
+
+use serde_json::Value;
+
+use crate::envelope::{EnvelopeError, EventEnvelope};
+
+/// Converts stored envelopes of some event type and version into a newer form.
+pub trait Upcaster {
+    /// Whether this upcaster converts envelopes of the event type and version.
+    fn can_upcast(&self, event_type: &str, version: u32) -> bool;
+
+    /// Converts the envelope, possibly changing its event type.
+    fn upcast(&self, envelope: EventEnvelope) -> Result<EventEnvelope, String>;
+}
+
+/// An [`Upcaster`] converting payloads of a single event type to the next
+/// version.
+pub struct PayloadUpcaster<F> {
+    event_type: &'static str,
+    from_version: u32,
+    convert: F,
+}
+
+impl<F> PayloadUpcaster<F>
+where
+    F: Fn(Value) -> Result<Value, String>,
+{
+    /// Creates an upcaster of `event_type` payloads stored with `from_version`.
+    pub fn new(event_type: &'static str, from_version: u32, convert: F) -> Self {
+        Self {
+            event_type,
+            from_version,
+            convert,
+        }
+    }
+}
+
+impl<F> Upcaster for PayloadUpcaster<F>
+where
+    F: Fn(Value) -> Result<Value, String>,
+{
+    fn can_upcast(&self, event_type: &str, version: u32) -> bool {
+        event_type == self.event_type && version == self.from_version
+    }
+
+    fn upcast(&self, envelope: EventEnvelope) -> Result<EventEnvelope, String> {
+        Ok(EventEnvelope {
+            payload: (self.convert)(envelope.payload)?,
+            version: self.from_version + 1,
+            ..envelope
+        })
+    }
+}
+
+/// Upcasters applied in the order they were added, oldest change first.
+#[derive(Default)]
+pub struct UpcasterChain {
+    upcasters: Vec<Box<dyn Upcaster + Send + Sync>>,
+}
+
+impl UpcasterChain {
+    /// Creates a chain leaving envelopes untouched.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends an upcaster, which sees envelopes already converted by the
+    /// previously added ones.
+    pub fn with<U>(mut self, upcaster: U) -> Self
+    where
+        U: Upcaster + Send + Sync + 'static,
+    {
+        self.upcasters.push(Box::new(upcaster));
+        self
+    }
+
+    /// Passes the envelope through every applicable upcaster of the chain.
+    pub fn upcast(&self, mut envelope: EventEnvelope) -> Result<EventEnvelope, EnvelopeError> {
+        for upcaster in &self.upcasters {
+            if !upcaster.can_upcast(&envelope.event_type, envelope.version) {
+                continue;
+            }
+            let (event_type, version) = (envelope.event_type.clone(), envelope.version);
+            envelope = upcaster
+                .upcast(envelope)
+                .map_err(|reason| EnvelopeError::Upcast {
+                    event_type,
+                    version,
+                    reason,
+                })?;
+        }
+        Ok(envelope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        HydratedAggregate,
+        counter::{Counter, CounterEvent},
+        envelope::EventRegistry,
+        event_types,
+    };
+
+    /// Early releases stored every counter change as a single event type.
+    struct LegacyCounterChanged;
+
+    impl Upcaster for LegacyCounterChanged {
+        fn can_upcast(&self, event_type: &str, _: u32) -> bool {
+            event_type == "counter_changed"
+        }
+
+        fn upcast(&self, envelope: EventEnvelope) -> Result<EventEnvelope, String> {
+            let (event_type, payload) = match envelope.payload["delta"].as_i64() {
+                Some(1) => ("counter_incremented", "Incremented"),
+                Some(-1) => ("counter_decremented", "Decremented"),
+                _ => return Err(format!("unsupported delta in {}", envelope.payload)),
+            };
+            Ok(EventEnvelope {
+                event_type: event_type.into(),
+                version: 1,
+                payload: payload.into(),
+            })
+        }
+    }
+
+    #[test]
+    fn replays_legacy_stream() {
+        let registry = EventRegistry::<CounterEvent>::new()
+            .register::<CounterEvent>()
+            .with_upcasters(UpcasterChain::new().with(LegacyCounterChanged));
+        let stream = [
+            json!({"event_type": "counter_changed", "payload": {"delta": 1}}),
+            json!({"event_type": "counter_changed", "payload": {"delta": 1}}),
+            json!({"event_type": "counter_decremented", "payload": "Decremented"}),
+            json!({"event_type": "counter_changed", "payload": {"delta": 1}}),
+        ];
+
+        let mut counter = HydratedAggregate::<Counter>::default();
+        counter.apply_events(stream.into_iter().map(|stored| {
+            let envelope = serde_json::from_value(stored).unwrap();
+            registry.open(envelope).unwrap()
+        }));
+        assert_eq!(counter.state().value(), 2);
+
+        let broken = EventEnvelope {
+            event_type: "counter_changed".into(),
+            version: 1,
+            payload: json!({"delta": 5}),
+        };
+        assert!(matches!(
+            registry.open(broken),
+            Err(EnvelopeError::Upcast { version: 1, .. })
+        ));
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum ScoreEvent {
+        Scored { points: u32 },
+    }
+
+    event_types! {
+        #[version = 3]
+        ScoreEvent { Scored => "scored" }
+    }
+
+    #[test]
+    fn chains_versions_in_order() {
+        // v1 had no payload at all, v2 named the field `pts`.
+        let v1_to_v2 = PayloadUpcaster::new("scored", 1, |_| Ok(json!({"Scored": {"pts": 1}})));
+        let v2_to_v3 = PayloadUpcaster::new("scored", 2, |mut payload: Value| {
+            let pts = payload["Scored"]["pts"].take();
+            Ok(json!({"Scored": {"points": pts}}))
+        });
+        let registry = EventRegistry::<ScoreEvent>::new().register::<ScoreEvent>();
+
+        let v2 = EventEnvelope {
+            event_type: "scored".into(),
+            version: 2,
+            payload: json!({"Scored": {"pts": 7}}),
+        };
+        assert!(matches!(
+            registry.open(v2.clone()),
+            Err(EnvelopeError::UnsupportedVersion {
+                version: 2,
+                expected: 3,
+                ..
+            })
+        ));
+
+        let registry = registry.with_upcasters(UpcasterChain::new().with(v1_to_v2).with(v2_to_v3));
+        assert_eq!(registry.open(v2).unwrap(), ScoreEvent::Scored { points: 7 });
+        let v1 = EventEnvelope {
+            event_type: "scored".into(),
+            version: 1,
+            payload: Value::Null,
+        };
+        assert_eq!(registry.open(v1).unwrap(), ScoreEvent::Scored { points: 1 });
+
+        let current = EventEnvelope::wrap(&ScoreEvent::Scored { points: 2 }).unwrap();
+        assert_eq!(current.version, 3);
+        assert_eq!(
+            registry.open(current).unwrap(),
+            ScoreEvent::Scored { points: 2 }
+        );
+    }
+}
@@ -0,0 +1,498 @@
+//! Vending machine of the step 2 task.
+//!
+//! Besides the demo binary, the model is served over HTTP by the
+//! `step_4_vending` service through [`SharedVendingMachine`].
+
+use std::collections::{hash_map::Entry, BTreeMap, HashMap};
+use std::fmt;
+
+pub mod money;
+pub mod shared;
+
+pub use money::{Currency, Money, MoneyError};
+pub use shared::SharedVendingMachine;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Product {
+    name: String,
+    price: Money,
+}
+
+impl Product {
+    /// Returns `None` for a zero price.
+    pub fn new(name: impl Into<String>, price: Money) -> Option<Self> {
+        (!price.is_zero()).then(|| Self {
+            name: name.into(),
+            price,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn price(&self) -> Money {
+        self.price
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Coin {
+    One,
+    Two,
+    Five,
+    Ten,
+    Twenty,
+    Fifty,
+}
+
+impl Coin {
+    pub const ALL: [Coin; 6] = [
+        Coin::One,
+        Coin::Two,
+        Coin::Five,
+        Coin::Ten,
+        Coin::Twenty,
+        Coin::Fifty,
+    ];
+
+    pub const fn value(self) -> u32 {
+        match self {
+            Coin::One => 1,
+            Coin::Two => 2,
+            Coin::Five => 5,
+            Coin::Ten => 10,
+            Coin::Twenty => 20,
+            Coin::Fifty => 50,
+        }
+    }
+
+    pub const fn money(self, currency: Currency) -> Money {
+        Money::new(self.value() as u64, currency)
+    }
+
+    /// Returns `None` unless `value` is a nominal of some coin.
+    pub fn from_value(value: u32) -> Option<Self> {
+        Coin::ALL.into_iter().find(|coin| coin.value() == value)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StockError {
+    ZeroQuantity,
+    ExceedsCapacity { available: usize, requested: usize },
+    PriceMismatch { expected: Money, found: Money },
+    Money(MoneyError),
+}
+
+impl From<MoneyError> for StockError {
+    fn from(err: MoneyError) -> Self {
+        StockError::Money(err)
+    }
+}
+
+impl fmt::Display for StockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StockError::ZeroQuantity => f.write_str("quantity must be positive"),
+            StockError::ExceedsCapacity { available, requested } => {
+                write!(f, "cannot add {requested} items, only {available} fit")
+            }
+            StockError::PriceMismatch { expected, found } => {
+                write!(f, "product is already sold for {expected}, not {found}")
+            }
+            StockError::Money(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for StockError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PurchaseError {
+    UnknownProduct,
+    OutOfStock,
+    InsufficientPayment { price: Money, paid: Money },
+    CannotProvideChange { change: Money },
+    Money(MoneyError),
+}
+
+impl From<MoneyError> for PurchaseError {
+    fn from(err: MoneyError) -> Self {
+        PurchaseError::Money(err)
+    }
+}
+
+impl fmt::Display for PurchaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PurchaseError::UnknownProduct => f.write_str("unknown product"),
+            PurchaseError::OutOfStock => f.write_str("product is out of stock"),
+            PurchaseError::InsufficientPayment { price, paid } => {
+                write!(f, "paid {paid} of {price}")
+            }
+            PurchaseError::CannotProvideChange { change } => {
+                write!(f, "cannot give {change} of change")
+            }
+            PurchaseError::Money(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PurchaseError {}
+
+#[derive(Debug)]
+struct Slot {
+    product: Product,
+    quantity: u32,
+}
+
+#[derive(Debug)]
+pub struct VendingMachine {
+    capacity: usize,
+    /// Currency of prices and coins accepted by the machine.
+    currency: Currency,
+    slots: HashMap<String, Slot>,
+    coins: BTreeMap<Coin, u32>,
+}
+
+impl VendingMachine {
+    pub fn new(capacity: usize, currency: Currency) -> Self {
+        Self {
+            capacity,
+            currency,
+            slots: HashMap::new(),
+            coins: BTreeMap::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    pub fn total_items(&self) -> usize {
+        self.slots
+            .values()
+            .map(|slot| slot.quantity as usize)
+            .sum()
+    }
+
+    pub fn available_capacity(&self) -> usize {
+        self.capacity.saturating_sub(self.total_items())
+    }
+
+    /// Products in stock with their quantities, ordered by name.
+    pub fn products(&self) -> Vec<(&Product, u32)> {
+        let mut products: Vec<_> = self
+            .slots
+            .values()
+            .map(|slot| (&slot.product, slot.quantity))
+            .collect();
+        products.sort_by(|(a, _), (b, _)| a.name().cmp(b.name()));
+        products
+    }
+
+    pub fn restock(&mut self, product: Product, quantity: u32) -> Result<(), StockError> {
+        if quantity == 0 {
+            return Err(StockError::ZeroQuantity);
+        }
+
+        if product.price.currency() != self.currency {
+            return Err(MoneyError::CurrencyMismatch {
+                expected: self.currency,
+                found: product.price.currency(),
+            }
+            .into());
+        }
+
+        let requested = quantity as usize;
+        let available = self.available_capacity();
+        if requested > available {
+            return Err(StockError::ExceedsCapacity { available, requested });
+        }
+
+        match self.slots.entry(product.name().to_owned()) {
+            Entry::Occupied(mut entry) => {
+                let existing_price = entry.get().product.price;
+                if existing_price != product.price {
+                    return Err(StockError::PriceMismatch {
+                        expected: existing_price,
+                        found: product.price,
+                    });
+                }
+                entry.get_mut().quantity += quantity;
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(Slot { product, quantity });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn add_change(&mut self, coins: impl IntoIterator<Item = Coin>) {
+        for coin in coins {
+            *self.coins.entry(coin).or_insert(0) += 1;
+        }
+    }
+
+    pub fn purchase(
+        &mut self,
+        name: &str,
+        payment: impl IntoIterator<Item = Coin>,
+    ) -> Result<(Product, Vec<Coin>), PurchaseError> {
+        let price = {
+            let slot = self
+                .slots
+                .get(name)
+                .ok_or(PurchaseError::UnknownProduct)?;
+            if slot.quantity == 0 {
+                return Err(PurchaseError::OutOfStock);
+            }
+            slot.product.price
+        };
+
+        let payment_coins: Vec<Coin> = payment.into_iter().collect();
+        let paid = payment_coins
+            .iter()
+            .try_fold(Money::zero(self.currency), |paid, coin| {
+                paid.checked_add(coin.money(self.currency))
+            })?;
+
+        if paid < price {
+            return Err(PurchaseError::InsufficientPayment { price, paid });
+        }
+
+        let change_amount = paid.checked_sub(price)?;
+
+        let mut combined = self.coins.clone();
+        for coin in &payment_coins {
+            *combined.entry(*coin).or_insert(0) += 1;
+        }
+
+        let change = Self::calculate_change(&combined, change_amount.minor_units())
+            .ok_or(PurchaseError::CannotProvideChange {
+                change: change_amount,
+            })?;
+
+        for coin in payment_coins {
+            *self.coins.entry(coin).or_insert(0) += 1;
+        }
+
+        Self::deduct_change(&mut self.coins, &change);
+
+        let mut remove_slot = false;
+        let product = {
+            let slot = self
+                .slots
+                .get_mut(name)
+                .expect("slot must exist while completing purchase");
+            slot.quantity -= 1;
+            if slot.quantity == 0 {
+                remove_slot = true;
+            }
+            slot.product.clone()
+        };
+
+        if remove_slot {
+            self.slots.remove(name);
+        }
+
+        Ok((product, change))
+    }
+
+    fn calculate_change(coins: &BTreeMap<Coin, u32>, amount: u64) -> Option<Vec<Coin>> {
+        if amount == 0 {
+            return Some(Vec::new());
+        }
+
+        let available: Vec<(Coin, u32)> = Coin::ALL
+            .iter()
+            .rev()
+            .filter_map(|coin| coins.get(coin).copied().map(|count| (*coin, count)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+
+        fn backtrack(
+            idx: usize,
+            remaining: u64,
+            coins: &[(Coin, u32)],
+            current: &mut Vec<Coin>,
+        ) -> Option<Vec<Coin>> {
+            if remaining == 0 {
+                return Some(current.clone());
+            }
+
+            if idx == coins.len() {
+                return None;
+            }
+
+            let (coin, count) = coins[idx];
+            let value = u64::from(coin.value());
+            let max_use = (remaining / value).min(u64::from(count));
+
+            for use_count in (0..=max_use).rev() {
+                for _ in 0..use_count {
+                    current.push(coin);
+                }
+
+                let next_remaining = remaining - (value * use_count);
+                if let Some(result) = backtrack(idx + 1, next_remaining, coins, current) {
+                    return Some(result);
+                }
+
+                for _ in 0..use_count {
+                    current.pop();
+                }
+            }
+
+            None
+        }
+
+        backtrack(0, amount, &available, &mut Vec::new())
+    }
+
+    fn deduct_change(coins: &mut BTreeMap<Coin, u32>, change: &[Coin]) {
+        let mut zeroed = Vec::new();
+        for coin in change {
+            if let Some(entry) = coins.get_mut(coin) {
+                *entry -= 1;
+                if *entry == 0 {
+                    zeroed.push(*coin);
+                }
+            }
+        }
+
+        for coin in zeroed {
+            coins.remove(&coin);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eur(cents: u64) -> Money {
+        Money::new(cents, Currency::EUR)
+    }
+
+    #[test]
+    fn purchase_with_change() {
+        let mut machine = VendingMachine::new(3, Currency::EUR);
+        let soda = Product::new("Soda", eur(45)).unwrap();
+        machine.restock(soda, 2).unwrap();
+        machine.add_change([Coin::Twenty, Coin::Twenty, Coin::Five]);
+
+        let (product, change) = machine.purchase("Soda", [Coin::Fifty]).unwrap();
+        assert_eq!(product.name(), "Soda");
+        assert_eq!(product.price(), eur(45));
+        assert_eq!(change, vec![Coin::Five]);
+        assert_eq!(machine.total_items(), 1);
+    }
+
+    #[test]
+    fn insufficient_payment_is_rejected() {
+        let mut machine = VendingMachine::new(1, Currency::EUR);
+        let snack = Product::new("Snack", eur(20)).unwrap();
+        machine.restock(snack, 1).unwrap();
+
+        let err = machine.purchase("Snack", [Coin::Ten]).unwrap_err();
+        assert_eq!(
+            err,
+            PurchaseError::InsufficientPayment {
+                price: eur(20),
+                paid: eur(10)
+            }
+        );
+    }
+
+    #[test]
+    fn cannot_provide_change() {
+        let mut machine = VendingMachine::new(2, Currency::EUR);
+        let water = Product::new("Water", eur(30)).unwrap();
+        machine.restock(water, 1).unwrap();
+        machine.add_change([Coin::Ten]);
+
+        let err = machine.purchase("Water", [Coin::Fifty]).unwrap_err();
+        assert_eq!(
+            err,
+            PurchaseError::CannotProvideChange { change: eur(20) }
+        );
+    }
+
+    #[test]
+    fn non_greedy_change_combination_succeeds() {
+        let mut machine = VendingMachine::new(2, Currency::EUR);
+        let snack = Product::new("Snack", eur(32)).unwrap();
+        machine.restock(snack.clone(), 1).unwrap();
+
+        machine.add_change([
+            Coin::Ten,
+            Coin::Five,
+            Coin::Two,
+            Coin::Two,
+            Coin::Two,
+            Coin::Two,
+        ]);
+
+        let (product, change) = machine.purchase("Snack", [Coin::Fifty]).unwrap();
+
+        assert_eq!(product.name(), snack.name());
+        assert_eq!(change, vec![Coin::Ten, Coin::Two, Coin::Two, Coin::Two, Coin::Two]);
+    }
+
+    #[test]
+    fn restock_respects_capacity() {
+        let mut machine = VendingMachine::new(1, Currency::EUR);
+        let snack = Product::new("Snack", eur(10)).unwrap();
+        machine.restock(snack.clone(), 1).unwrap();
+        let err = machine.restock(snack, 1).unwrap_err();
+        assert_eq!(
+            err,
+            StockError::ExceedsCapacity {
+                available: 0,
+                requested: 1
+            }
+        );
+    }
+
+    #[test]
+    fn restock_rejects_different_price() {
+        let mut machine = VendingMachine::new(2, Currency::EUR);
+        let snack = Product::new("Snack", eur(10)).unwrap();
+        machine.restock(snack.clone(), 1).unwrap();
+
+        let err = machine
+            .restock(Product::new("Snack", eur(20)).unwrap(), 1)
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            StockError::PriceMismatch {
+                expected: eur(10),
+                found: eur(20)
+            }
+        );
+    }
+
+    #[test]
+    fn restock_rejects_foreign_currency() {
+        let mut machine = VendingMachine::new(2, Currency::EUR);
+        let usd = Currency::new("USD").unwrap();
+        let snack = Product::new("Snack", Money::new(10, usd)).unwrap();
+
+        assert_eq!(
+            machine.restock(snack, 1).unwrap_err(),
+            StockError::Money(MoneyError::CurrencyMismatch {
+                expected: Currency::EUR,
+                found: usd
+            })
+        );
+        assert_eq!(Product::new("Free", Money::zero(Currency::EUR)), None);
+    }
+}
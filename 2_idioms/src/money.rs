@@ -0,0 +1,162 @@
+//! Amounts of money tagged with their currency, so that prices and change
+//! in different currencies can't be mixed up silently.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+/// ISO 4217 currency code, e.g. `EUR`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const EUR: Currency = Currency(*b"EUR");
+
+    /// Returns `None` unless `code` is three uppercase ASCII letters.
+    pub fn new(code: &str) -> Option<Self> {
+        let code: [u8; 3] = code.as_bytes().try_into().ok()?;
+        code.iter()
+            .all(u8::is_ascii_uppercase)
+            .then_some(Currency(code))
+    }
+
+    pub fn code(&self) -> &str {
+        std::str::from_utf8(&self.0).expect("currency code is ASCII")
+    }
+}
+
+impl fmt::Debug for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoneyError {
+    CurrencyMismatch {
+        expected: Currency,
+        found: Currency,
+    },
+    /// The result is negative or doesn't fit into `u64` minor units.
+    Overflow,
+}
+
+impl fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoneyError::CurrencyMismatch { expected, found } => {
+                write!(f, "expected an amount in {expected}, found {found}")
+            }
+            MoneyError::Overflow => f.write_str("amount is out of range"),
+        }
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+/// Non-negative amount in minor units (cents) of its currency.
+///
+/// Amounts in different currencies are never equal and not comparable, while
+/// arithmetic on them fails with [`MoneyError::CurrencyMismatch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Money {
+    minor: u64,
+    currency: Currency,
+}
+
+impl Money {
+    pub const fn new(minor: u64, currency: Currency) -> Self {
+        Self { minor, currency }
+    }
+
+    pub const fn zero(currency: Currency) -> Self {
+        Self::new(0, currency)
+    }
+
+    pub fn minor_units(&self) -> u64 {
+        self.minor
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.minor == 0
+    }
+
+    pub fn checked_add(self, rhs: Money) -> Result<Money, MoneyError> {
+        self.combine(rhs, u64::checked_add)
+    }
+
+    pub fn checked_sub(self, rhs: Money) -> Result<Money, MoneyError> {
+        self.combine(rhs, u64::checked_sub)
+    }
+
+    fn combine(self, rhs: Money, op: fn(u64, u64) -> Option<u64>) -> Result<Money, MoneyError> {
+        if self.currency != rhs.currency {
+            return Err(MoneyError::CurrencyMismatch {
+                expected: self.currency,
+                found: rhs.currency,
+            });
+        }
+        let minor = op(self.minor, rhs.minor).ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(minor, self.currency))
+    }
+}
+
+impl PartialOrd for Money {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (self.currency == other.currency).then(|| self.minor.cmp(&other.minor))
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{:02} {}",
+            self.minor / 100,
+            self.minor % 100,
+            self.currency
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic_is_checked_and_currency_aware() {
+        let usd = Currency::new("USD").unwrap();
+        let price = Money::new(145, Currency::EUR);
+
+        assert_eq!(price.to_string(), "1.45 EUR");
+        assert_eq!(
+            price.checked_add(Money::new(5, Currency::EUR)),
+            Ok(Money::new(150, Currency::EUR))
+        );
+        assert_eq!(
+            price.checked_sub(Money::new(200, Currency::EUR)),
+            Err(MoneyError::Overflow)
+        );
+        assert_eq!(
+            price.checked_add(Money::new(5, usd)),
+            Err(MoneyError::CurrencyMismatch {
+                expected: Currency::EUR,
+                found: usd
+            })
+        );
+        assert_eq!(price.partial_cmp(&Money::new(145, usd)), None);
+        assert!(Money::zero(Currency::EUR) < price);
+
+        assert_eq!(Currency::new("usd"), None);
+        assert_eq!(Currency::new("EURO"), None);
+    }
+}
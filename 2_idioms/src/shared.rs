@@ -0,0 +1,383 @@
+//! Vending machine shared between threads, serving many customers at once.
+//!
+//! Every customer inserts coins into a session of their own, so coins of
+//! concurrent customers never mix.
+//!
+//! Sessions are identified by random [`SessionId`]s, which customers can't
+//! guess for each other, and expire once left idle for too long, so abandoned
+//! ones don't pile up.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use common::{Clock, SystemClock};
+
+use crate::{
+    Coin, Currency, Money, MoneyError, Product, PurchaseError, StockError, VendingMachine,
+};
+
+/// Unguessable identifier of a customer session, 128 random bits written as
+/// 32 hex digits. Knowing it is all it takes to spend the coins of the
+/// session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionId(u128);
+
+impl SessionId {
+    fn generate() -> Self {
+        Self(rand::random())
+    }
+}
+
+impl fmt::Display for SessionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl FromStr for SessionId {
+    type Err = SessionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 32 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(SessionError::UnknownSession);
+        }
+        u128::from_str_radix(s, 16)
+            .map(Self)
+            .map_err(|_| SessionError::UnknownSession)
+    }
+}
+
+/// Limits of the customer sessions of a [`SharedVendingMachine`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionLimits {
+    /// Sessions are closed once left alone for that long, the machine keeping
+    /// their coins.
+    pub idle_ttl: Duration,
+    /// Sessions open at once, new ones are refused above it.
+    pub max_open: usize,
+}
+
+impl Default for SessionLimits {
+    fn default() -> Self {
+        Self {
+            idle_ttl: Duration::from_secs(5 * 60),
+            max_open: 1000,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SessionError {
+    /// The session was never opened, or it's closed or expired already.
+    UnknownSession,
+    TooManySessions,
+    Purchase(PurchaseError),
+}
+
+impl From<PurchaseError> for SessionError {
+    fn from(err: PurchaseError) -> Self {
+        SessionError::Purchase(err)
+    }
+}
+
+impl From<MoneyError> for SessionError {
+    fn from(err: MoneyError) -> Self {
+        SessionError::Purchase(err.into())
+    }
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::UnknownSession => f.write_str("unknown session"),
+            SessionError::TooManySessions => f.write_str("too many open sessions"),
+            SessionError::Purchase(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+/// [`VendingMachine`] behind a lock, with customer sessions.
+///
+/// Cloning is cheap: clones operate the same machine.
+#[derive(Debug, Clone)]
+pub struct SharedVendingMachine {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    machine: VendingMachine,
+    sessions: HashMap<SessionId, Session>,
+    limits: SessionLimits,
+    clock: Arc<dyn Clock>,
+}
+
+/// Open session of a customer.
+#[derive(Debug)]
+struct Session {
+    coins: Vec<Coin>,
+    /// When the customer did something with the session the last time.
+    active: Instant,
+}
+
+impl State {
+    /// Session of the customer, unless it's unknown or expired, marked as
+    /// active.
+    fn session(&mut self, id: SessionId) -> Result<&mut Session, SessionError> {
+        let now = self.clock.now();
+        let idle_ttl = self.limits.idle_ttl;
+        let expired = self
+            .sessions
+            .get(&id)
+            .ok_or(SessionError::UnknownSession)?
+            .is_expired(now, idle_ttl);
+        if expired {
+            self.expire(id);
+            return Err(SessionError::UnknownSession);
+        }
+        let session = self
+            .sessions
+            .get_mut(&id)
+            .expect("session is checked above");
+        session.active = now;
+        Ok(session)
+    }
+
+    /// Closes the session, keeping its coins.
+    fn expire(&mut self, id: SessionId) {
+        if let Some(session) = self.sessions.remove(&id) {
+            self.machine.add_change(session.coins);
+        }
+    }
+}
+
+impl Session {
+    fn is_expired(&self, now: Instant, idle_ttl: Duration) -> bool {
+        now.saturating_duration_since(self.active) >= idle_ttl
+    }
+}
+
+impl SharedVendingMachine {
+    pub fn new(machine: VendingMachine) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                machine,
+                sessions: HashMap::new(),
+                limits: SessionLimits::default(),
+                clock: Arc::new(SystemClock),
+            })),
+        }
+    }
+
+    pub fn with_session_limits(self, limits: SessionLimits) -> Self {
+        self.lock().limits = limits;
+        self
+    }
+
+    /// Makes sessions expire by the `clock` instead of the system one.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        self.lock().clock = clock;
+        self
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.lock().machine.currency()
+    }
+
+    /// Products in stock with their quantities, ordered by name.
+    pub fn products(&self) -> Vec<(Product, u32)> {
+        self.lock()
+            .machine
+            .products()
+            .into_iter()
+            .map(|(product, quantity)| (product.clone(), quantity))
+            .collect()
+    }
+
+    pub fn restock(&self, product: Product, quantity: u32) -> Result<(), StockError> {
+        self.lock().machine.restock(product, quantity)
+    }
+
+    pub fn add_change(&self, coins: impl IntoIterator<Item = Coin>) {
+        self.lock().machine.add_change(coins);
+    }
+
+    /// Starts a session without coins, unless [`SessionLimits::max_open`]
+    /// sessions are open already.
+    pub fn open_session(&self) -> Result<SessionId, SessionError> {
+        let mut state = self.lock();
+        let (now, limits) = (state.clock.now(), state.limits);
+        if state.sessions.len() >= limits.max_open {
+            let expired: Vec<_> = state
+                .sessions
+                .iter()
+                .filter(|(_, session)| session.is_expired(now, limits.idle_ttl))
+                .map(|(id, _)| *id)
+                .collect();
+            for id in expired {
+                state.expire(id);
+            }
+        }
+        if state.sessions.len() >= limits.max_open {
+            return Err(SessionError::TooManySessions);
+        }
+        let id = SessionId::generate();
+        state.sessions.insert(
+            id,
+            Session {
+                coins: Vec::new(),
+                active: now,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Amount inserted in the session so far.
+    pub fn credit(&self, session: SessionId) -> Result<Money, SessionError> {
+        let mut state = self.lock();
+        let currency = state.machine.currency();
+        Ok(total(&state.session(session)?.coins, currency)?)
+    }
+
+    /// Inserts the coin, returning the credit of the session.
+    pub fn insert_coin(&self, session: SessionId, coin: Coin) -> Result<Money, SessionError> {
+        let mut state = self.lock();
+        let currency = state.machine.currency();
+        let coins = &mut state.session(session)?.coins;
+        coins.push(coin);
+        Ok(total(coins, currency)?)
+    }
+
+    /// Buys the product with the coins of the session, closing it.
+    ///
+    /// If the purchase fails, the coins stay in the session, so the customer
+    /// may insert more or [`cancel`](Self::cancel) it.
+    pub fn purchase(
+        &self,
+        session: SessionId,
+        name: &str,
+    ) -> Result<(Product, Vec<Coin>), SessionError> {
+        let mut state = self.lock();
+        let coins = state.session(session)?.coins.clone();
+        let (product, change) = state.machine.purchase(name, coins)?;
+        state.sessions.remove(&session);
+        Ok((product, change))
+    }
+
+    /// Closes the session, returning the inserted coins.
+    pub fn cancel(&self, session: SessionId) -> Result<Vec<Coin>, SessionError> {
+        let mut state = self.lock();
+        state.session(session)?;
+        let session = state.sessions.remove(&session);
+        Ok(session.expect("session is checked above").coins)
+    }
+
+    /// Number of sessions neither closed nor expired yet.
+    pub fn open_sessions(&self) -> usize {
+        let state = self.lock();
+        let now = state.clock.now();
+        state
+            .sessions
+            .values()
+            .filter(|session| !session.is_expired(now, state.limits.idle_ttl))
+            .count()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // Every operation leaves the state consistent before it can panic.
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn total(coins: &[Coin], currency: Currency) -> Result<Money, MoneyError> {
+    coins.iter().try_fold(Money::zero(currency), |total, coin| {
+        total.checked_add(coin.money(currency))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eur(cents: u64) -> Money {
+        Money::new(cents, Currency::EUR)
+    }
+
+    #[test]
+    fn sessions_keep_coins_apart() {
+        let machine = SharedVendingMachine::new(VendingMachine::new(5, Currency::EUR));
+        machine
+            .restock(Product::new("Soda", eur(45)).unwrap(), 2)
+            .unwrap();
+        machine.add_change([Coin::Five]);
+
+        let alice = machine.open_session().unwrap();
+        let bob = machine.open_session().unwrap();
+        assert_eq!(machine.open_sessions(), 2);
+        machine.insert_coin(alice, Coin::Twenty).unwrap();
+        assert_eq!(machine.insert_coin(alice, Coin::Twenty), Ok(eur(40)));
+        assert_eq!(machine.insert_coin(bob, Coin::Fifty), Ok(eur(50)));
+
+        assert_eq!(
+            machine.purchase(alice, "Soda"),
+            Err(SessionError::Purchase(PurchaseError::InsufficientPayment {
+                price: eur(45),
+                paid: eur(40),
+            }))
+        );
+        assert_eq!(machine.cancel(alice), Ok(vec![Coin::Twenty, Coin::Twenty]));
+        assert_eq!(machine.credit(alice), Err(SessionError::UnknownSession));
+
+        let (product, change) = machine.purchase(bob, "Soda").unwrap();
+        assert_eq!((product.name(), change), ("Soda", vec![Coin::Five]));
+        assert_eq!(
+            machine.purchase(bob, "Soda"),
+            Err(SessionError::UnknownSession)
+        );
+        assert_eq!(machine.products()[0].1, 1);
+        assert_eq!(machine.open_sessions(), 0);
+    }
+
+    #[test]
+    fn sessions_are_unguessable_and_expire() {
+        let clock = common::MockClock::new();
+        let machine = SharedVendingMachine::new(VendingMachine::new(5, Currency::EUR))
+            .with_clock(Arc::new(clock.clone()))
+            .with_session_limits(SessionLimits {
+                idle_ttl: Duration::from_secs(60),
+                max_open: 2,
+            });
+
+        let alice = machine.open_session().unwrap();
+        assert_eq!(alice.to_string().parse(), Ok(alice));
+        for foreign in ["00000000000000000000000000000001", "1", "not a session"] {
+            let foreign = foreign.parse().unwrap_or(SessionId(1));
+            assert_eq!(
+                machine.insert_coin(foreign, Coin::Fifty),
+                Err(SessionError::UnknownSession)
+            );
+        }
+
+        machine.insert_coin(alice, Coin::Fifty).unwrap();
+        clock.advance(Duration::from_secs(50));
+        let bob = machine.open_session().unwrap();
+        assert_eq!(machine.open_session(), Err(SessionError::TooManySessions));
+        assert_eq!(machine.credit(alice), Ok(eur(50)), "alice is still active");
+
+        assert_eq!(machine.open_sessions(), 2);
+
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(machine.open_sessions(), 0);
+        assert_eq!(machine.cancel(bob), Err(SessionError::UnknownSession));
+        // The expired session of alice is closed to make room for carol.
+        let carol = machine.open_session().unwrap();
+        assert_ne!(carol, alice);
+        assert_eq!(machine.credit(alice), Err(SessionError::UnknownSession));
+        assert_eq!(machine.credit(carol), Ok(eur(0)));
+    }
+}
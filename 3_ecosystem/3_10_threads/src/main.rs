@@ -1,8 +1,11 @@
+use common::metrics::{Counter, Gauge, Histogram};
 use crossbeam_channel::{Receiver, Sender, bounded};
 use rand::rngs::StdRng;
 use rand::{RngCore, SeedableRng};
 use rayon::prelude::*;
+use std::sync::Arc;
 use std::thread;
+use std::time::Instant;
 
 const DEFAULT_MATRIX_SIZE: usize = 4096;
 const DEFAULT_ITERATIONS: usize = 3;
@@ -27,18 +30,55 @@ impl Default for Config {
     }
 }
 
+/// Progress of the pipeline, readable while it runs.
+#[derive(Debug)]
+struct PipelineStats {
+    produced: Counter,
+    consumed: Counter,
+    /// Matrices sent to the consumers, but not taken by them yet
+    queued: Gauge,
+    /// Time of summing a single matrix, in microseconds
+    sum_micros: Histogram,
+}
+
+impl Default for PipelineStats {
+    fn default() -> Self {
+        Self {
+            produced: Counter::default(),
+            consumed: Counter::default(),
+            queued: Gauge::default(),
+            sum_micros: Histogram::new([100, 1_000, 10_000, 100_000, 1_000_000]),
+        }
+    }
+}
+
 fn main() {
-    let results = run_pipeline(Config::default());
+    let stats = Arc::new(PipelineStats::default());
+    let results = run_pipeline(Config::default(), &stats);
     for (idx, sum) in results.iter().enumerate() {
         println!("Matrix #{idx}: sum = {sum}");
     }
+
+    let times = stats.sum_micros.snapshot();
+    let bound = |q| match times.quantile(q) {
+        Some(u64::MAX) => "over 1s".to_owned(),
+        Some(micros) => format!("{micros}µs"),
+        None => "-".to_owned(),
+    };
+    println!(
+        "Summed {} of {} matrices, p50 <= {}, p99 <= {}",
+        stats.consumed.get(),
+        stats.produced.get(),
+        bound(0.5),
+        bound(0.99),
+    );
 }
 
-fn run_pipeline(config: Config) -> Vec<u64> {
+fn run_pipeline(config: Config, stats: &Arc<PipelineStats>) -> Vec<u64> {
     let (tx, rx) = bounded::<Option<Vec<u8>>>(config.consumer_count * 2);
 
-    let producer = spawn_producer(config.clone(), tx);
-    let consumers = spawn_consumers(config.consumer_count, rx);
+    let producer = spawn_producer(config.clone(), tx, stats.clone());
+    let consumers = spawn_consumers(config.consumer_count, rx, stats);
 
     producer
         .join()
@@ -55,7 +95,11 @@ fn run_pipeline(config: Config) -> Vec<u64> {
     results
 }
 
-fn spawn_producer(config: Config, tx: Sender<Option<Vec<u8>>>) -> thread::JoinHandle<()> {
+fn spawn_producer(
+    config: Config,
+    tx: Sender<Option<Vec<u8>>>,
+    stats: Arc<PipelineStats>,
+) -> thread::JoinHandle<()> {
     thread::spawn(move || {
         let mut rng = create_rng(config.rng_seed);
         let matrix_len = config
@@ -66,7 +110,10 @@ fn spawn_producer(config: Config, tx: Sender<Option<Vec<u8>>>) -> thread::JoinHa
         for _ in 0..config.iterations {
             let mut matrix = vec![0u8; matrix_len];
             rng.fill_bytes(&mut matrix);
+            // Counted before sending, so consumers never see it negative
+            stats.queued.inc();
             tx.send(Some(matrix)).expect("channel closed unexpectedly");
+            stats.produced.inc();
         }
 
         for _ in 0..config.consumer_count {
@@ -78,17 +125,24 @@ fn spawn_producer(config: Config, tx: Sender<Option<Vec<u8>>>) -> thread::JoinHa
 fn spawn_consumers(
     consumer_count: usize,
     rx: Receiver<Option<Vec<u8>>>,
+    stats: &Arc<PipelineStats>,
 ) -> Vec<thread::JoinHandle<Vec<u64>>> {
     (0..consumer_count)
         .map(|_| {
             let rx = rx.clone();
+            let stats = stats.clone();
             thread::spawn(move || {
                 let mut sums = Vec::new();
                 while let Ok(message) = rx.recv() {
-                    match message {
-                        Some(matrix) => sums.push(parallel_sum(&matrix)),
-                        None => break,
-                    }
+                    let Some(matrix) = message else { break };
+                    stats.queued.dec();
+                    let started = Instant::now();
+                    sums.push(parallel_sum(&matrix));
+                    let micros = started.elapsed().as_micros();
+                    stats
+                        .sum_micros
+                        .observe(micros.try_into().unwrap_or(u64::MAX));
+                    stats.consumed.inc();
                 }
                 sums
             })
@@ -138,10 +192,14 @@ mod tests {
             rng_seed: Some(42),
         };
 
-        let results = run_pipeline(config.clone());
+        let stats = Arc::new(PipelineStats::default());
+        let results = run_pipeline(config.clone(), &stats);
         let mut expected = expected_sums(config.matrix_size, config.iterations, 42);
 
         assert_eq!(results.len(), config.iterations);
+        assert_eq!((stats.produced.get(), stats.consumed.get()), (5, 5));
+        assert_eq!(stats.queued.get(), 0);
+        assert_eq!(stats.sum_micros.snapshot().count, 5);
         expected.sort_unstable();
         let mut actual = results.clone();
         actual.sort_unstable();
@@ -157,7 +215,7 @@ mod tests {
             rng_seed: Some(7),
         };
 
-        let results = run_pipeline(config.clone());
+        let results = run_pipeline(config.clone(), &Arc::default());
         assert_eq!(results.len(), config.iterations);
         assert!(results.iter().all(|sum| *sum > 0));
     }
@@ -1,10 +1,15 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{CommandFactory, Parser};
+use cli_common::{CommonArgs, Progress, ProgressArgs};
+use common::SystemClock;
+use common::http_client::{HttpClient, is_transient};
+use common::retry::{Backoff, retry_async};
 use futures::stream::{self, StreamExt};
 use sha2::{Digest, Sha256};
-use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
 use tokio::runtime::Builder;
+use tracing::{debug, info};
 
 #[derive(Debug, Parser)]
 #[command(about = "Download web pages concurrently", version)]
@@ -13,12 +18,29 @@ struct Args {
     #[arg(long, default_value_t = num_cpus::get())]
     max_threads: usize,
 
+    /// Download in sorted URL order and stamp files with `SOURCE_DATE_EPOCH`
+    /// (or the Unix epoch), so repeated runs produce identical output
+    #[arg(long)]
+    deterministic: bool,
+
+    #[command(flatten)]
+    common: CommonArgs,
+
+    #[command(flatten)]
+    progress: ProgressArgs,
+
     /// Path to a file containing newline-separated URLs
-    input: PathBuf,
+    #[arg(required_unless_present = "completions")]
+    input: Option<PathBuf>,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
+    if args.common.print_completions(&mut Args::command()) {
+        return Ok(());
+    }
+    args.common.init_tracing("info");
+
     let threads = args.max_threads.max(1);
     let runtime = Builder::new_multi_thread()
         .worker_threads(threads)
@@ -30,13 +52,27 @@ fn main() -> Result<()> {
 }
 
 async fn async_main(args: Args) -> Result<()> {
-    let urls = read_urls(&args.input).await?;
+    let input = args.input.expect("required by clap");
+    let mut urls = read_urls(&input).await?;
+    debug!("read {} URLs from {}", urls.len(), input.display());
     if urls.is_empty() {
         return Ok(());
     }
 
+    let mtime = if args.deterministic {
+        urls.sort();
+        urls.dedup();
+        Some(common::reproducible::source_date_epoch())
+    } else {
+        None
+    };
+
     let output_dir = std::env::current_dir()?;
-    download_all(urls, args.max_threads.max(1), &output_dir).await?;
+    let progress = args.progress.start("step_3_11", Some(urls.len()));
+    let paths = download_all(urls, args.max_threads.max(1), &output_dir, mtime, &progress).await;
+    progress.finish();
+    let paths = paths?;
+    info!("downloaded {} pages", paths.len());
 
     Ok(())
 }
@@ -51,37 +87,67 @@ async fn read_urls(path: &Path) -> Result<Vec<String>> {
         .collect())
 }
 
+/// Downloads every URL into `output_dir`, stamping the files with `mtime`
+/// if it's given.
+///
+/// Returned paths follow the order of `urls` when `mtime` is set, and the
+/// order downloads finished in otherwise. Every download is reported to
+/// `progress`, even if another one has failed.
 async fn download_all(
     urls: Vec<String>,
     max_concurrency: usize,
     output_dir: &Path,
+    mtime: Option<SystemTime>,
+    progress: &Progress,
 ) -> Result<Vec<PathBuf>> {
     if urls.is_empty() {
         return Ok(Vec::new());
     }
 
     tokio::fs::create_dir_all(output_dir).await?;
-    let client = reqwest::Client::builder().no_proxy().build()?;
+    let client = HttpClient::builder().no_proxy().build()?;
 
-    let results = stream::iter(urls.into_iter().map(|url| {
+    let downloads = stream::iter(urls.into_iter().map(|url| {
         let client = client.clone();
         let dir = output_dir.to_path_buf();
-        async move { download_single(&client, &url, &dir).await }
-    }))
-    .buffer_unordered(max_concurrency)
-    .collect::<Vec<Result<PathBuf>>>()
-    .await;
+        let progress = progress.clone();
+        async move {
+            let started = Instant::now();
+            let result = download_single(&client, &url, &dir, mtime).await;
+            match &result {
+                Ok(_) => progress.completed(&url, started.elapsed()),
+                Err(err) => progress.failed(&url, err, started.elapsed()),
+            }
+            result
+        }
+    }));
+    let results: Vec<Result<PathBuf>> = if mtime.is_some() {
+        downloads.buffered(max_concurrency).collect().await
+    } else {
+        downloads.buffer_unordered(max_concurrency).collect().await
+    };
 
     results.into_iter().collect()
 }
 
-async fn download_single(client: &reqwest::Client, url: &str, dir: &Path) -> Result<PathBuf> {
-    let response = client.get(url).send().await?.error_for_status()?;
-    let bytes = response.bytes().await?;
+async fn download_single(
+    client: &HttpClient,
+    url: &str,
+    dir: &Path,
+    mtime: Option<SystemTime>,
+) -> Result<PathBuf> {
+    let bytes = retry_async(&Backoff::default(), &SystemClock, is_transient, |_| {
+        client.get_bytes(url)
+    })
+    .await?;
 
     let filename = sanitize_filename(url);
     let path = dir.join(filename);
     tokio::fs::write(&path, &bytes).await?;
+    if let Some(mtime) = mtime {
+        common::reproducible::set_mtime(&path, mtime)?;
+    }
+    debug!("saved {url} to {}", path.display());
     Ok(path)
 }
 
@@ -95,6 +161,7 @@ fn sanitize_filename(url: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
     use httpmock::Method::GET;
     use httpmock::MockServer;
     use tokio::runtime::Runtime;
@@ -129,7 +196,13 @@ mod tests {
 
         let rt = create_runtime();
         let paths = rt
-            .block_on(download_all(urls.clone(), 2, &output_dir))
+            .block_on(download_all(
+                urls.clone(),
+                2,
+                &output_dir,
+                None,
+                &Progress::disabled(),
+            ))
             .expect("download");
 
         assert_eq!(paths.len(), 2);
@@ -144,6 +217,64 @@ mod tests {
         }
     }
 
+    #[test]
+    fn deterministic_downloads_keep_order_and_mtime() {
+        let server = MockServer::start();
+        for page in ["/a", "/b", "/c"] {
+            server.mock(|when, then| {
+                when.method(GET).path(page);
+                then.status(200).body(page);
+            });
+        }
+
+        let urls: Vec<String> = ["/a", "/b", "/c"].map(|page| server.url(page)).into();
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let mtime = std::time::UNIX_EPOCH;
+
+        let paths = create_runtime()
+            .block_on(download_all(
+                urls.clone(),
+                3,
+                tmp.path(),
+                Some(mtime),
+                &Progress::disabled(),
+            ))
+            .expect("download");
+
+        let expected: Vec<PathBuf> = urls
+            .iter()
+            .map(|url| tmp.path().join(sanitize_filename(url)))
+            .collect();
+        assert_eq!(paths, expected);
+        for path in paths {
+            let modified = fs::metadata(path).and_then(|meta| meta.modified());
+            assert_eq!(modified.expect("mtime"), mtime);
+        }
+    }
+
+    #[test]
+    fn retries_only_server_errors() {
+        let server = MockServer::start();
+        let failing = server.mock(|when, then| {
+            when.method(GET).path("/failing");
+            then.status(503);
+        });
+        let missing = server.mock(|when, then| {
+            when.method(GET).path("/missing");
+            then.status(404);
+        });
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let client = HttpClient::builder().no_proxy().build().expect("client");
+
+        let rt = create_runtime();
+        for url in [server.url("/failing"), server.url("/missing")] {
+            let result = rt.block_on(download_single(&client, &url, tmp.path(), None));
+            assert!(result.is_err());
+        }
+        failing.assert_hits(Backoff::default().max_attempts as usize);
+        missing.assert_hits(1);
+    }
+
     #[test]
     fn sanitize_filename_is_stable() {
         let url = "https://example.com/page";
@@ -0,0 +1,63 @@
+//! Expansion of `btreemap!`, kept on [`proc_macro2`] so it can run outside
+//! the compiler, e.g. under a fuzzer.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Expr, Token};
+
+struct MapEntries {
+    pairs: Punctuated<MapEntry, Token![,]>,
+}
+
+impl Parse for MapEntries {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let pairs = if input.is_empty() {
+            Punctuated::new()
+        } else {
+            Punctuated::parse_terminated(input)?
+        };
+
+        Ok(Self { pairs })
+    }
+}
+
+struct MapEntry {
+    key: Expr,
+    value: Expr,
+}
+
+impl Parse for MapEntry {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let key: Expr = input.parse()?;
+        input.parse::<Token![=>]>()?;
+        let value: Expr = input.parse()?;
+
+        Ok(Self { key, value })
+    }
+}
+
+/// Expands the macro input into a `BTreeMap` expression.
+///
+/// Malformed input yields a [`syn::Error`] rather than a panic.
+pub fn expand(tokens: TokenStream) -> syn::Result<TokenStream> {
+    let entries: MapEntries = syn::parse2(tokens)?;
+
+    if entries.pairs.is_empty() {
+        return Ok(quote!(::std::collections::BTreeMap::new()));
+    }
+
+    let inserts = entries.pairs.iter().map(|entry| {
+        let MapEntry { key, value } = entry;
+        quote! {
+            map.insert(#key, #value);
+        }
+    });
+
+    Ok(quote! {{
+        let mut map = ::std::collections::BTreeMap::new();
+        #(#inserts)*
+        map
+    }})
+}
@@ -1,58 +1,32 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::parse::{Parse, ParseStream};
-use syn::punctuated::Punctuated;
-use syn::{Expr, Token, parse_macro_input};
 
-struct MapEntries {
-    pairs: Punctuated<MapEntry, Token![,]>,
-}
-
-impl Parse for MapEntries {
-    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
-        let pairs = if input.is_empty() {
-            Punctuated::new()
-        } else {
-            Punctuated::parse_terminated(input)?
-        };
-
-        Ok(Self { pairs })
-    }
-}
-
-struct MapEntry {
-    key: Expr,
-    value: Expr,
-}
-
-impl Parse for MapEntry {
-    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
-        let key: Expr = input.parse()?;
-        input.parse::<Token![=>]>()?;
-        let value: Expr = input.parse()?;
-
-        Ok(Self { key, value })
-    }
-}
+mod expand;
+mod typed_id;
 
 #[proc_macro]
 pub fn btreemap(tokens: TokenStream) -> TokenStream {
-    let entries = parse_macro_input!(tokens as MapEntries);
-
-    if entries.pairs.is_empty() {
-        return quote!(::std::collections::BTreeMap::new()).into();
-    }
-
-    let inserts = entries.pairs.iter().map(|entry| {
-        let MapEntry { key, value } = entry;
-        quote! {
-            map.insert(#key, #value);
-        }
-    });
+    expand::expand(tokens.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
 
-    TokenStream::from(quote! {{
-        let mut map = ::std::collections::BTreeMap::new();
-        #(#inserts)*
-        map
-    }})
+/// Derives the constructor, `get()`, `From`, `Display` and `FromStr` of an
+/// ID newtype, plus serde impls with `#[typed_id(serde)]`.
+///
+/// ```
+/// use btreemap_proc_macro::TypedId;
+///
+/// #[derive(Clone, Copy, Debug, PartialEq, TypedId)]
+/// pub struct PostId(u64);
+///
+/// let id: PostId = "42".parse().unwrap();
+/// assert_eq!(id, PostId::new(42));
+/// assert_eq!(id.get(), 42);
+/// assert_eq!(id.to_string(), "42");
+/// ```
+#[proc_macro_derive(TypedId, attributes(typed_id))]
+pub fn typed_id(tokens: TokenStream) -> TokenStream {
+    typed_id::expand(tokens.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
 }
@@ -0,0 +1,184 @@
+//! Expansion of `#[derive(TypedId)]`.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DataStruct, DeriveInput, Fields, Type};
+
+/// Implements the identifier boilerplate for a newtype over an integer or
+/// a `uuid::Uuid`.
+///
+/// Both kinds get `get()`, `From<Inner>`, `Display` and `FromStr` delegating
+/// to the inner value. Integer IDs are constructed with `new(value)`, while
+/// UUID ones get a random `new()` and a matching `Default`. With
+/// `#[typed_id(serde)]` the ID is also (de)serialized as its inner value.
+pub fn expand(tokens: TokenStream) -> syn::Result<TokenStream> {
+    let input: DeriveInput = syn::parse2(tokens)?;
+    let inner = inner_type(&input)?;
+    let serde = wants_serde(&input)?;
+
+    let name = &input.ident;
+    let vis = &input.vis;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let constructor = if is_uuid(inner) {
+        quote! {
+            impl #impl_generics #name #ty_generics #where_clause {
+                /// Generates a new random identifier.
+                #vis fn new() -> Self {
+                    Self(::uuid::Uuid::new_v4())
+                }
+            }
+
+            impl #impl_generics ::core::default::Default for #name #ty_generics #where_clause {
+                fn default() -> Self {
+                    Self::new()
+                }
+            }
+        }
+    } else {
+        quote! {
+            impl #impl_generics #name #ty_generics #where_clause {
+                #vis const fn new(value: #inner) -> Self {
+                    Self(value)
+                }
+            }
+        }
+    };
+
+    let serde = serde.then(|| {
+        quote! {
+            impl #impl_generics ::serde::Serialize for #name #ty_generics #where_clause {
+                fn serialize<S: ::serde::Serializer>(
+                    &self,
+                    serializer: S,
+                ) -> ::core::result::Result<S::Ok, S::Error> {
+                    ::serde::Serialize::serialize(&self.0, serializer)
+                }
+            }
+
+            impl<'de> ::serde::Deserialize<'de> for #name #ty_generics #where_clause {
+                fn deserialize<D: ::serde::Deserializer<'de>>(
+                    deserializer: D,
+                ) -> ::core::result::Result<Self, D::Error> {
+                    <#inner as ::serde::Deserialize<'de>>::deserialize(deserializer).map(Self)
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        #constructor
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            #vis fn get(&self) -> #inner {
+                self.0
+            }
+        }
+
+        impl #impl_generics ::core::convert::From<#inner> for #name #ty_generics #where_clause {
+            fn from(value: #inner) -> Self {
+                Self(value)
+            }
+        }
+
+        impl #impl_generics ::core::fmt::Display for #name #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                ::core::fmt::Display::fmt(&self.0, f)
+            }
+        }
+
+        impl #impl_generics ::core::str::FromStr for #name #ty_generics #where_clause {
+            type Err = <#inner as ::core::str::FromStr>::Err;
+
+            fn from_str(raw: &str) -> ::core::result::Result<Self, Self::Err> {
+                raw.parse().map(Self)
+            }
+        }
+
+        #serde
+    })
+}
+
+/// Type of the single field of a tuple struct.
+fn inner_type(input: &DeriveInput) -> syn::Result<&Type> {
+    match &input.data {
+        Data::Struct(DataStruct {
+            fields: Fields::Unnamed(fields),
+            ..
+        }) if fields.unnamed.len() == 1 => Ok(&fields.unnamed[0].ty),
+        _ => Err(syn::Error::new_spanned(
+            &input.ident,
+            "TypedId can only be derived for tuple structs with a single field",
+        )),
+    }
+}
+
+fn is_uuid(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Uuid"),
+        _ => false,
+    }
+}
+
+/// Whether the struct is marked with `#[typed_id(serde)]`.
+fn wants_serde(input: &DeriveInput) -> syn::Result<bool> {
+    let mut serde = false;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("typed_id")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("serde") {
+                serde = true;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported typed_id option, expected `serde`"))
+            }
+        })?;
+    }
+    Ok(serde)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_anything_but_newtypes() {
+        for input in [
+            quote!(
+                struct Id {
+                    value: u64,
+                }
+            ),
+            quote!(
+                struct Id(u64, u64);
+            ),
+            quote!(
+                enum Id {
+                    A,
+                }
+            ),
+        ] {
+            let err = expand(input).unwrap_err();
+            assert!(err.to_string().contains("single field"), "{err}");
+        }
+
+        let err = expand(quote!(
+            #[typed_id(json)]
+            struct Id(u64);
+        ))
+        .unwrap_err();
+        assert!(err.to_string().contains("unsupported typed_id option"));
+
+        let expanded = expand(quote!(
+            #[typed_id(serde)]
+            pub struct Id(Uuid);
+        ))
+        .unwrap();
+        let expanded = expanded.to_string();
+        assert!(expanded.contains("new_v4"));
+        assert!(expanded.contains(":: serde :: Deserialize"));
+    }
+}
@@ -0,0 +1,215 @@
+//! Parsers for the [`std::fmt`] format spec: sign, width and precision.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// Parses sign, width and precision of a format spec.
+pub fn parse(input: &str) -> (Option<Sign>, Option<usize>, Option<Precision>) {
+    parse_manual(input)
+}
+
+/// Hand-written parser behind [`parse`].
+pub fn parse_manual(input: &str) -> (Option<Sign>, Option<usize>, Option<Precision>) {
+    let chars: Vec<char> = input.chars().collect();
+    let mut index = 0;
+
+    if chars
+        .get(index + 1)
+        .is_some_and(|c| matches!(c, '<' | '^' | '>'))
+    {
+        index += 2;
+    } else if chars
+        .get(index)
+        .is_some_and(|c| matches!(c, '<' | '^' | '>'))
+    {
+        index += 1;
+    }
+
+    let sign = chars.get(index).and_then(|c| match c {
+        '+' => {
+            index += 1;
+            Some(Sign::Plus)
+        }
+        '-' => {
+            index += 1;
+            Some(Sign::Minus)
+        }
+        _ => None,
+    });
+
+    if chars.get(index) == Some(&'#') {
+        index += 1;
+    }
+
+    if chars.get(index) == Some(&'0') {
+        index += 1;
+    }
+
+    let width = {
+        let start = index;
+        while chars.get(index).is_some_and(|c| c.is_ascii_digit()) {
+            index += 1;
+        }
+
+        if start == index {
+            None
+        } else {
+            let value = chars[start..index].iter().collect::<String>().parse().ok();
+
+            if chars.get(index) == Some(&'$') {
+                index += 1;
+            }
+
+            value
+        }
+    };
+
+    let precision = if chars.get(index) == Some(&'.') {
+        index += 1;
+        match chars.get(index) {
+            Some('*') => Some(Precision::Asterisk),
+            Some(c) if c.is_ascii_digit() => {
+                let start = index;
+                while chars.get(index).is_some_and(|c| c.is_ascii_digit()) {
+                    index += 1;
+                }
+
+                let digits: Option<usize> =
+                    chars[start..index].iter().collect::<String>().parse().ok();
+
+                if chars.get(index) == Some(&'$') {
+                    digits.map(Precision::Argument)
+                } else {
+                    digits.map(Precision::Integer)
+                }
+            }
+            _ => None,
+        }
+    } else {
+        None
+    };
+
+    (sign, width, precision)
+}
+
+/// Regex-based parser that must agree with [`parse`] on every input.
+pub fn parse_with_regex(input: &str) -> (Option<Sign>, Option<usize>, Option<Precision>) {
+    static FORMAT_RE: Lazy<Regex> = Lazy::new(|| {
+        // `(?s)` lets the fill be any char, newline included, and `[0-9]`
+        // keeps to ASCII digits like the manual parser does
+        Regex::new(r"(?s)^(?:.?[<^>])?(?P<sign>[+-])?#?0?(?P<width>[0-9]+(?:\$)?)?(?P<precision>\.(?:[0-9]+(?:\$)?|\*))?")
+            .expect("valid regex")
+    });
+
+    let captures = FORMAT_RE.captures(input);
+
+    let sign = captures
+        .as_ref()
+        .and_then(|caps| caps.name("sign"))
+        .and_then(|m| match m.as_str() {
+            "+" => Some(Sign::Plus),
+            "-" => Some(Sign::Minus),
+            _ => None,
+        });
+
+    let width = captures
+        .as_ref()
+        .and_then(|caps| caps.name("width"))
+        .and_then(|m| m.as_str().trim_end_matches('$').parse().ok());
+
+    let precision = captures
+        .as_ref()
+        .and_then(|caps| caps.name("precision"))
+        .and_then(|m| match &m.as_str()[1..] {
+            "*" => Some(Precision::Asterisk),
+            value => {
+                let trimmed = value.trim_end_matches('$');
+                trimmed.parse().ok().map(|number| {
+                    if value.ends_with('$') {
+                        Precision::Argument(number)
+                    } else {
+                        Precision::Integer(number)
+                    }
+                })
+            }
+        });
+
+    (sign, width, precision)
+}
+
+/// Sign flag of a format spec.
+#[derive(Debug, PartialEq)]
+pub enum Sign {
+    Plus,
+    Minus,
+}
+
+/// Precision of a format spec.
+#[derive(Debug, PartialEq)]
+pub enum Precision {
+    Integer(usize),
+    Argument(usize),
+    Asterisk,
+}
+
+#[cfg(test)]
+mod spec {
+    use super::*;
+
+    #[test]
+    fn parses_sign() {
+        for (input, expected) in [
+            ("", None),
+            (">8.*", None),
+            (">+8.*", Some(Sign::Plus)),
+            ("-.1$x", Some(Sign::Minus)),
+            ("a^#043.8?", None),
+        ] {
+            let (sign, ..) = parse(input);
+            assert_eq!(sign, expected);
+            let (sign, ..) = parse_with_regex(input);
+            assert_eq!(sign, expected);
+        }
+    }
+
+    #[test]
+    fn parses_width() {
+        for (input, expected) in [
+            ("", None),
+            (">8.*", Some(8)),
+            (">+8.*", Some(8)),
+            ("-.1$x", None),
+            ("a^#043.8?", Some(43)),
+            ("+1$?", Some(1)),
+        ] {
+            let (_, width, _) = parse(input);
+            assert_eq!(width, expected);
+            let (_, width, _) = parse_with_regex(input);
+            assert_eq!(width, expected);
+        }
+    }
+
+    #[test]
+    fn parses_precision() {
+        for (input, expected) in [
+            ("", None),
+            (">8.*", Some(Precision::Asterisk)),
+            (">+8.*", Some(Precision::Asterisk)),
+            ("-.1$x", Some(Precision::Argument(1))),
+            ("a^#043.8?", Some(Precision::Integer(8))),
+            ("+1$.2$", Some(Precision::Argument(2))),
+        ] {
+            let (_, _, precision) = parse(input);
+            assert_eq!(precision, expected);
+            let (_, _, precision) = parse_with_regex(input);
+            assert_eq!(precision, expected);
+        }
+    }
+
+    #[test]
+    fn parsers_agree_on_fuzzer_findings() {
+        for input in ["#7\u{663}", "\n>+1", "\u{663}.5"] {
+            assert_eq!(parse(input), parse_with_regex(input), "input: {input:?}");
+        }
+    }
+}
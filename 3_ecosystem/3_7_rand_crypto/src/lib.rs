@@ -2,9 +2,12 @@ use std::fmt;
 use std::fs::File;
 use std::io::{self, Read};
 use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use argon2::Argon2;
-use argon2::password_hash::{Error as PasswordHashError, PasswordHasher, SaltString};
+use argon2::password_hash::{
+    Error as PasswordHashError, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+};
 use rand::Rng;
 use rand::distributions::Alphanumeric;
 use rand::rngs::OsRng;
@@ -83,6 +86,30 @@ pub fn new_access_token() -> String {
         .collect()
 }
 
+/// Crockford's Base32 alphabet used by ULIDs.
+const CROCKFORD_BASE32: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Generates a [ULID]: 48 bits of the Unix time in milliseconds followed by
+/// 80 random bits, encoded as 26 Crockford's Base32 symbols.
+///
+/// ULIDs generated in different milliseconds sort lexicographically by time.
+///
+/// [ULID]: https://github.com/ulid/spec
+pub fn new_ulid() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64);
+    encode_ulid(millis, OsRng.r#gen())
+}
+
+fn encode_ulid(millis: u64, random: u128) -> String {
+    let value = (u128::from(millis & 0xFFFF_FFFF_FFFF) << 80) | (random & ((1 << 80) - 1));
+    (0..26)
+        .rev()
+        .map(|i| CROCKFORD_BASE32[((value >> (i * 5)) & 0x1F) as usize] as char)
+        .collect()
+}
+
 /// Calculates SHA3-256 hash of the file located at the provided path.
 pub fn get_file_hash(path: impl AsRef<Path>) -> Result<String> {
     let mut file = File::open(path)?;
@@ -108,6 +135,19 @@ pub fn hash_password(password: impl AsRef<[u8]>) -> Result<String> {
     Ok(hash.to_string())
 }
 
+/// Checks the password against an Argon2 hash produced by [`hash_password`].
+///
+/// The comparison takes constant time. Malformed hashes are errors rather than
+/// mismatches.
+pub fn verify_password(password: impl AsRef<[u8]>, hash: &str) -> Result<bool> {
+    let hash = PasswordHash::new(hash)?;
+    match Argon2::default().verify_password(password.as_ref(), &hash) {
+        Ok(()) => Ok(true),
+        Err(PasswordHashError::Password) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -133,6 +173,20 @@ mod tests {
         assert!(token.chars().all(|c| c.is_ascii_alphanumeric()));
     }
 
+    #[test]
+    fn ulid_is_time_ordered_base32() {
+        assert_eq!(encode_ulid(0, 0), "00000000000000000000000000");
+        assert_eq!(
+            encode_ulid(u64::MAX, u128::MAX),
+            "7ZZZZZZZZZZZZZZZZZZZZZZZZZ"
+        );
+        assert!(encode_ulid(1, u128::MAX) < encode_ulid(2, 0));
+
+        let ulid = new_ulid();
+        assert_eq!(ulid.len(), 26);
+        assert!(ulid.bytes().all(|b| CROCKFORD_BASE32.contains(&b)));
+    }
+
     #[test]
     fn hashing_roundtrip() {
         let dir = tempfile::tempdir().expect("tempdir");
@@ -150,4 +204,12 @@ mod tests {
         let hash = hash_password("s3cret").expect("hash");
         assert!(hash.starts_with("$argon2id$"));
     }
+
+    #[test]
+    fn argon2_hash_verifies_only_its_password() {
+        let hash = hash_password("s3cret").expect("hash");
+        assert!(verify_password("s3cret", &hash).expect("verify"));
+        assert!(!verify_password("S3cret", &hash).expect("verify"));
+        assert!(verify_password("s3cret", "not a hash").is_err());
+    }
 }
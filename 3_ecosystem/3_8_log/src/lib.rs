@@ -0,0 +1,324 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tracing_subscriber::field::Visit;
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::{Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Registry, fmt};
+
+pub mod redact;
+
+/// Installs the JSON logging stack: application events go to STDOUT/STDERR,
+/// events with the `access` target are appended to `access.log`.
+///
+/// `default_filter` is used when `RUST_LOG` isn't set.
+pub fn init_logging(default_filter: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let env_filter =
+        EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new(default_filter))?;
+
+    let app_layer = fmt::layer()
+        .event_format(JsonFormatter::new("app.log"))
+        .with_writer(AppWriter)
+        .with_filter(filter_fn(|meta| meta.target() != "access"));
+
+    let access_layer = fmt::layer()
+        .event_format(JsonFormatter::new("access.log"))
+        .with_writer(AccessWriter::new("access.log")?)
+        .with_filter(filter_fn(|meta| meta.target() == "access"));
+
+    Registry::default()
+        .with(env_filter)
+        .with(app_layer)
+        .with(access_layer)
+        .init();
+
+    Ok(())
+}
+
+struct Rfc3339Timer;
+
+impl Rfc3339Timer {
+    fn now(&self) -> Result<String, time::error::Format> {
+        OffsetDateTime::now_utc().format(&Rfc3339)
+    }
+}
+
+/// Formats events as single-line JSON objects with `msg`, `lvl`, `file` and `time` fields.
+pub struct JsonFormatter {
+    file_label: &'static str,
+    timer: Rfc3339Timer,
+}
+
+impl JsonFormatter {
+    pub fn new(file_label: &'static str) -> Self {
+        Self {
+            file_label,
+            timer: Rfc3339Timer,
+        }
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for JsonFormatter
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+    N: for<'writer> FormatFields<'writer> + 'static,
+{
+    fn format_event(
+        &self,
+        _ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &tracing::Event<'_>,
+    ) -> std::fmt::Result {
+        let mut visitor = JsonVisitor::default();
+        event.record(&mut visitor);
+        let mut map = visitor.finish();
+
+        if let Some(message) = map.remove("message") {
+            map.insert("msg".to_string(), message);
+        }
+
+        map.insert(
+            "lvl".to_string(),
+            serde_json::Value::String(event.metadata().level().to_string()),
+        );
+        map.insert(
+            "file".to_string(),
+            serde_json::Value::String(self.file_label.to_string()),
+        );
+        map.insert(
+            "time".to_string(),
+            serde_json::Value::String(self.timer.now().map_err(|_| std::fmt::Error)?),
+        );
+
+        writeln!(writer, "{}", serde_json::Value::Object(map))
+    }
+}
+
+#[derive(Default)]
+struct JsonVisitor {
+    map: serde_json::Map<String, serde_json::Value>,
+}
+
+impl JsonVisitor {
+    fn finish(self) -> serde_json::Map<String, serde_json::Value> {
+        self.map
+    }
+}
+
+impl Visit for JsonVisitor {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.map.insert(
+            field.name().to_string(),
+            serde_json::Value::String(format!("{:?}", value)),
+        );
+    }
+
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.map.insert(
+            field.name().to_string(),
+            serde_json::Value::String(value.to_string()),
+        );
+    }
+
+    fn record_i64(&mut self, field: &tracing::field::Field, value: i64) {
+        self.map.insert(
+            field.name().to_string(),
+            serde_json::Value::Number(value.into()),
+        );
+    }
+
+    fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
+        self.map.insert(
+            field.name().to_string(),
+            serde_json::Value::Number(value.into()),
+        );
+    }
+
+    fn record_bool(&mut self, field: &tracing::field::Field, value: bool) {
+        self.map
+            .insert(field.name().to_string(), serde_json::Value::Bool(value));
+    }
+
+    fn record_f64(&mut self, field: &tracing::field::Field, value: f64) {
+        if let Some(number) = serde_json::Number::from_f64(value) {
+            self.map
+                .insert(field.name().to_string(), serde_json::Value::Number(number));
+        }
+    }
+}
+
+struct AppWriter;
+
+enum Stream {
+    Stdout(io::Stdout),
+    Stderr(io::Stderr),
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Stdout(stdout) => stdout.write(buf),
+            Stream::Stderr(stderr) => stderr.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Stdout(stdout) => stdout.flush(),
+            Stream::Stderr(stderr) => stderr.flush(),
+        }
+    }
+}
+
+impl<'a> tracing_subscriber::fmt::writer::MakeWriter<'a> for AppWriter {
+    type Writer = Stream;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        Stream::Stdout(io::stdout())
+    }
+
+    fn make_writer_for(&'a self, meta: &tracing::Metadata<'_>) -> Self::Writer {
+        if *meta.level() >= tracing::Level::WARN {
+            Stream::Stderr(io::stderr())
+        } else {
+            Stream::Stdout(io::stdout())
+        }
+    }
+}
+
+struct AccessWriter {
+    file: Arc<std::sync::Mutex<std::fs::File>>,
+}
+
+impl AccessWriter {
+    fn new(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Arc::new(std::sync::Mutex::new(file)),
+        })
+    }
+}
+
+#[derive(Clone)]
+struct FileWriter {
+    file: Arc<std::sync::Mutex<std::fs::File>>,
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut guard = self.file.lock().expect("poisoned access log lock");
+        guard.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut guard = self.file.lock().expect("poisoned access log lock");
+        guard.flush()
+    }
+}
+
+impl<'a> tracing_subscriber::fmt::writer::MakeWriter<'a> for AccessWriter {
+    type Writer = FileWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        FileWriter {
+            file: Arc::clone(&self.file),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+    use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+    use tracing::info;
+    use tracing_subscriber::Registry;
+    use tracing_subscriber::fmt::writer::MakeWriter;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Clone)]
+    struct BufferWriterFactory {
+        buffer: Arc<Mutex<Vec<u8>>>,
+    }
+
+    struct BufferWriter {
+        buffer: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl<'a> MakeWriter<'a> for BufferWriterFactory {
+        type Writer = BufferWriter;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            BufferWriter {
+                buffer: Arc::clone(&self.buffer),
+            }
+        }
+    }
+
+    impl Write for BufferWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut guard = self.buffer.lock().expect("poisoned buffer lock");
+            guard.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            let mut guard = self.buffer.lock().expect("poisoned buffer lock");
+            guard.flush()
+        }
+    }
+
+    #[test]
+    fn json_formatter_writes_expected_fields() {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = Registry::default().with(
+            fmt::layer()
+                .event_format(JsonFormatter::new("app.log"))
+                .with_writer(BufferWriterFactory {
+                    buffer: Arc::clone(&buffer),
+                }),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            info!(target: "app", user_id = 42, "hello json");
+        });
+
+        let contents = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        let json: Value = serde_json::from_str(contents.trim()).expect("valid JSON output");
+
+        assert_eq!(json.get("lvl").and_then(Value::as_str), Some("INFO"));
+        assert_eq!(json.get("file").and_then(Value::as_str), Some("app.log"));
+        assert_eq!(json.get("msg").and_then(Value::as_str), Some("hello json"));
+        assert_eq!(json.get("user_id").and_then(Value::as_i64), Some(42));
+
+        let timestamp = json
+            .get("time")
+            .and_then(Value::as_str)
+            .expect("time field present");
+        OffsetDateTime::parse(timestamp, &Rfc3339).expect("RFC3339 timestamp");
+    }
+
+    #[test]
+    fn access_writer_appends_to_file() {
+        let dir = tempdir().expect("temporary directory");
+        let log_path = dir.path().join("access.log");
+        let log_path_str = log_path.to_str().expect("utf-8 path");
+        let writer = AccessWriter::new(log_path_str).expect("log file created");
+
+        writeln!(&mut writer.make_writer(), "first line").expect("write first line");
+        writeln!(&mut writer.make_writer(), "second line").expect("write second line");
+
+        let contents = std::fs::read_to_string(&log_path).expect("read log file");
+        assert!(contents.contains("first line"));
+        assert!(contents.contains("second line"));
+    }
+}
@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Declarative description of what must never reach the logs.
+///
+/// Field rules match JSON object keys case-insensitively by substring, so
+/// `password` also covers `old_password` and `new_password`. Header rules
+/// match header names exactly (case-insensitively).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct RedactionRules {
+    /// JSON fields whose values are masked, matched case-insensitively by
+    /// substring, so "password" also covers "new_password".
+    #[serde(default = "default_fields")]
+    pub fields: Vec<String>,
+    /// HTTP headers whose values are masked.
+    #[serde(default = "default_headers")]
+    pub headers: Vec<String>,
+    /// Replacement for masked values.
+    #[serde(default = "default_mask")]
+    pub mask: String,
+}
+
+impl Default for RedactionRules {
+    fn default() -> Self {
+        Self {
+            fields: default_fields(),
+            headers: default_headers(),
+            mask: default_mask(),
+        }
+    }
+}
+
+fn default_fields() -> Vec<String> {
+    vec!["password".to_string(), "token".to_string()]
+}
+
+fn default_headers() -> Vec<String> {
+    vec![
+        "authorization".to_string(),
+        "cookie".to_string(),
+        "set-cookie".to_string(),
+    ]
+}
+
+fn default_mask() -> String {
+    "***".to_string()
+}
+
+/// Applies [`RedactionRules`] to payloads before they are logged.
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    fields: Vec<String>,
+    headers: Vec<String>,
+    mask: String,
+}
+
+impl Redactor {
+    pub fn new(rules: RedactionRules) -> Self {
+        Self {
+            fields: rules.fields.iter().map(|f| f.to_lowercase()).collect(),
+            headers: rules.headers.iter().map(|h| h.to_lowercase()).collect(),
+            mask: rules.mask,
+        }
+    }
+
+    /// Masks every value stored under a sensitive key, at any nesting depth.
+    pub fn redact_value(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, nested) in map.iter_mut() {
+                    if self.is_sensitive_field(key) {
+                        *nested = Value::String(self.mask.clone());
+                    } else {
+                        self.redact_value(nested);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.redact_value(item)),
+            _ => {}
+        }
+    }
+
+    /// Renders a raw body for logging.
+    ///
+    /// JSON bodies are redacted field by field. Anything else can't be
+    /// inspected reliably, so only its size is reported.
+    pub fn redact_body(&self, body: &[u8]) -> String {
+        if body.is_empty() {
+            return String::new();
+        }
+        match serde_json::from_slice::<Value>(body) {
+            Ok(mut value) => {
+                self.redact_value(&mut value);
+                value.to_string()
+            }
+            Err(_) => format!("<{} bytes of non-JSON data>", body.len()),
+        }
+    }
+
+    /// Collects headers into a JSON object, masking sensitive values.
+    pub fn redact_headers<'a>(
+        &self,
+        headers: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Value {
+        let map = headers
+            .into_iter()
+            .map(|(name, value)| {
+                let value = if self.headers.contains(&name.to_lowercase()) {
+                    self.mask.clone()
+                } else {
+                    value.to_string()
+                };
+                (name.to_string(), Value::String(value))
+            })
+            .collect::<Map<_, _>>();
+        Value::Object(map)
+    }
+
+    fn is_sensitive_field(&self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        self.fields.iter().any(|field| key.contains(field.as_str()))
+    }
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new(RedactionRules::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn masks_sensitive_fields_at_any_depth() {
+        let redactor = Redactor::default();
+        let body = json!({
+            "name": "alice",
+            "Password": "secret",
+            "sessions": [{"refresh_token": "abc", "id": 1}],
+        });
+
+        let rendered = redactor.redact_body(body.to_string().as_bytes());
+        let value: Value = serde_json::from_str(&rendered).unwrap();
+
+        assert_eq!(value["name"], "alice");
+        assert_eq!(value["Password"], "***");
+        assert_eq!(value["sessions"][0]["refresh_token"], "***");
+        assert_eq!(value["sessions"][0]["id"], 1);
+        assert_eq!(
+            redactor.redact_body(b"password=secret"),
+            "<15 bytes of non-JSON data>"
+        );
+    }
+
+    #[test]
+    fn masks_sensitive_headers() {
+        let redactor = Redactor::new(RedactionRules {
+            headers: vec!["Authorization".to_string()],
+            mask: "[redacted]".to_string(),
+            ..RedactionRules::default()
+        });
+
+        let headers = redactor.redact_headers([
+            ("authorization", "Bearer abc"),
+            ("content-type", "application/json"),
+        ]);
+
+        assert_eq!(headers["authorization"], "[redacted]");
+        assert_eq!(headers["content-type"], "application/json");
+    }
+}
@@ -0,0 +1,173 @@
+//! Provenance of the loaded configuration: which layer provided the final
+//! value of every option.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::flatten::{dotted, leaves};
+use crate::{Cli, layers, merge};
+
+/// Layer of the configuration, in order of increasing precedence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// Default declared in the code.
+    Default,
+    /// Configuration file.
+    File(PathBuf),
+    /// File of the [profile](Cli::profile_conf).
+    Profile(PathBuf),
+    /// `CONF__*` env var.
+    Env,
+    /// File named by the `CONF__*_FILE` env var.
+    SecretFile(String),
+    /// Command line flag.
+    Cli,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::File(path) => write!(f, "file {}", path.display()),
+            Self::Profile(path) => write!(f, "profile {}", path.display()),
+            Self::Env => write!(f, "env"),
+            Self::SecretFile(var) => write!(f, "file from {var}"),
+            Self::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+/// Last segments of the keys whose values are secrets wherever they're set.
+const SECRET_KEYS: [&str; 3] = ["pass", "secret", "override_secret"];
+
+/// Final value of an option and the layer it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setting {
+    /// Dotted path of the option, as in the config file.
+    pub key: String,
+    pub value: Value,
+    pub source: Source,
+}
+
+impl Setting {
+    /// Where the value came from, naming the env var for [`Source::Env`].
+    pub fn origin(&self) -> String {
+        match &self.source {
+            Source::Env => format!("env CONF__{}", self.key.to_uppercase().replace('.', "__")),
+            source => source.to_string(),
+        }
+    }
+
+    /// Whether the value is a secret: read from a secret file or of an
+    /// option holding passwords and keys.
+    pub fn is_secret(&self) -> bool {
+        let name = self.key.rsplit('.').next().unwrap_or_default();
+        matches!(self.source, Source::SecretFile(_)) || SECRET_KEYS.contains(&name)
+    }
+
+    /// Value to show to people, with secrets masked unless they're unset.
+    pub fn masked_value(&self) -> String {
+        if self.is_secret() && !self.value.is_null() {
+            "***".into()
+        } else {
+            self.value.to_string()
+        }
+    }
+}
+
+/// Loads the configuration like [`load_config`](crate::load_config),
+/// recording for every option the last layer that set it.
+///
+/// The configuration isn't validated, so that invalid values can be traced
+/// back as well. Options no layer sets come from the `serde` defaults and
+/// are reported as [`Source::Default`].
+pub fn explain(cli: &Cli) -> Result<Vec<Setting>> {
+    let layers = layers(cli)?;
+    let mut provided = Vec::with_capacity(layers.len());
+    for (source, layer) in &layers {
+        let keys = leaves(layer.clone().try_deserialize()?).into_iter();
+        provided.push((
+            source.clone(),
+            keys.map(|(path, _)| dotted(&path)).collect::<BTreeSet<_>>(),
+        ));
+    }
+    let config = serde_json::to_value(merge(layers)?)?;
+    let settings = leaves(config).into_iter().map(|(path, value)| {
+        let key = dotted(&path);
+        let source = provided.iter().rev().find(|(_, keys)| keys.contains(&key));
+        let source = source.map_or(Source::Default, |(source, _)| source.clone());
+        Setting { key, value, source }
+    });
+    Ok(settings.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serial_test::serial;
+
+    use super::*;
+    use crate::tests::{remove_env, set_env};
+
+    #[test]
+    #[serial]
+    fn names_the_layer_of_every_option() {
+        let dir = tempfile::tempdir().unwrap();
+        let conf = dir.path().join("config.toml");
+        fs::write(
+            &conf,
+            "[server]\nhttp_port = 1001\ngrpc_port = 1002\n\n\
+             [server.limits.route_timeouts]\n\"/admin\" = \"1m\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("config.dev.toml"),
+            "[log.app]\nlevel = \"debug\"\n",
+        )
+        .unwrap();
+        let pass = dir.path().join("db_pass");
+        fs::write(&pass, "s3cret\n").unwrap();
+        set_env("CONF__SERVER__GRPC_PORT", "5050");
+        set_env("CONF__DB__MYSQL__PASS_FILE", &pass);
+        let cli = Cli {
+            conf: conf.clone(),
+            conf_format: None,
+            profile: Some("dev".into()),
+            debug: true,
+        };
+        let settings = explain(&cli);
+        remove_env("CONF__SERVER__GRPC_PORT");
+        remove_env("CONF__DB__MYSQL__PASS_FILE");
+        let settings = settings.unwrap();
+
+        let setting = |key: &str| settings.iter().find(|s| s.key == key).unwrap();
+        assert_eq!(setting("server.http_port").source, Source::File(conf));
+        assert_eq!(setting("server.http_port").value, 1001);
+        assert_eq!(
+            setting("server.grpc_port").origin(),
+            "env CONF__SERVER__GRPC_PORT"
+        );
+        assert_eq!(setting("server.grpc_port").value, 5050);
+        assert!(matches!(
+            setting("log.app.level").source,
+            Source::Profile(_)
+        ));
+        assert_eq!(
+            setting("db.mysql.pass").source,
+            Source::SecretFile("CONF__DB__MYSQL__PASS_FILE".into())
+        );
+        assert_eq!(setting("db.mysql.pass").masked_value(), "***");
+        assert_eq!(setting("server.http_port").masked_value(), "1001");
+        assert_eq!(setting("mode.debug").source, Source::Cli);
+        assert_eq!(setting("db.mysql.port").source, Source::Default);
+        assert_eq!(setting("features.override_secret").value, Value::Null);
+        assert_eq!(setting("features.override_secret").masked_value(), "null");
+        let route = setting("server.limits.route_timeouts.\"/admin\"");
+        assert!(matches!(route.source, Source::File(_)));
+    }
+}
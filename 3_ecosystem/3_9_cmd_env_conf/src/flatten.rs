@@ -0,0 +1,155 @@
+//! Flattening of nested configuration into single options, like the
+//! `CONF__*` env vars setting them.
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Options that are lists, split on [`LIST_SEPARATOR`] when set by env vars.
+pub(crate) const LIST_KEYS: [&str; 2] = ["log.redact.fields", "log.redact.headers"];
+
+pub(crate) const LIST_SEPARATOR: &str = ",";
+
+/// Values of the tree by the keys on their paths. Empty tables count as
+/// values.
+pub(crate) fn leaves(value: Value) -> Vec<(Vec<String>, Value)> {
+    fn walk(value: Value, path: &mut Vec<String>, out: &mut Vec<(Vec<String>, Value)>) {
+        match value {
+            Value::Object(table) if !table.is_empty() => {
+                for (key, value) in table {
+                    path.push(key);
+                    walk(value, path, out);
+                    path.pop();
+                }
+            }
+            value => out.push((path.clone(), value)),
+        }
+    }
+    let mut out = Vec::new();
+    walk(value, &mut Vec::new(), &mut out);
+    out
+}
+
+/// Dotted path as in the config file, quoting the keys that aren't bare.
+pub(crate) fn dotted(path: &[String]) -> String {
+    let keys: Vec<_> = path
+        .iter()
+        .map(|key| {
+            let bare = key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            if bare {
+                key.clone()
+            } else {
+                format!("\"{key}\"")
+            }
+        })
+        .collect();
+    keys.join(".")
+}
+
+/// Renders the value as `{prefix}__{PATH}=value` lines, which can be sourced
+/// by a shell or given to a process as a dotenv file.
+///
+/// Unset options and empty maps are left out. Options that have no env var,
+/// like map entries keyed by paths, are kept as comments rather than silently
+/// dropped.
+pub fn to_env<T: Serialize>(value: &T, prefix: &str) -> Result<String> {
+    let mut out = String::new();
+    for (path, value) in leaves(serde_json::to_value(value)?) {
+        let value = match value {
+            Value::Null | Value::Object(_) => continue,
+            Value::String(value) => Some(value),
+            Value::Array(items) => list(items),
+            value => Some(value.to_string()),
+        };
+        let named = path.iter().all(|key| {
+            key.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        });
+        match value.filter(|_| named) {
+            Some(value) => {
+                let name = path.join("__").to_uppercase();
+                out.push_str(&format!("{prefix}__{name}={}\n", quote(&value)));
+            }
+            None => out.push_str(&format!("# {} can't be set by an env var\n", dotted(&path))),
+        }
+    }
+    Ok(out)
+}
+
+/// Joins the items with [`LIST_SEPARATOR`], if they survive splitting back.
+fn list(items: Vec<Value>) -> Option<String> {
+    let items = items.into_iter().map(|item| match item {
+        Value::String(item) if !item.is_empty() && !item.contains(LIST_SEPARATOR) => Some(item),
+        _ => None,
+    });
+    let items: Vec<_> = items.collect::<Option<_>>()?;
+    (!items.is_empty()).then(|| items.join(LIST_SEPARATOR))
+}
+
+/// Quotes the value for a POSIX shell, unless it needs no quoting.
+fn quote(value: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-.,:/@%+=".contains(c);
+    if !value.is_empty() && value.chars().all(safe) {
+        value.to_owned()
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::tests::{remove_env, set_env};
+    use crate::{AppConfig, Cli, load_config};
+
+    #[test]
+    #[serial]
+    fn env_output_loads_back_into_the_same_config() {
+        let mut config = AppConfig::default();
+        config.server.external_url = "https://example.com/it's".into();
+        config.log.redact.fields = vec!["password".into(), "api_key".into()];
+        config.features.override_secret = Some("s3cret".into());
+        config
+            .server
+            .limits
+            .route_timeouts
+            .insert("/admin".into(), std::time::Duration::from_secs(60).into());
+
+        let rendered = to_env(&config, "CONF").unwrap();
+        assert!(rendered.contains("CONF__SERVER__EXTERNAL_URL='https://example.com/it'\\''s'\n"));
+        assert!(rendered.contains("CONF__LOG__REDACT__FIELDS=password,api_key\n"));
+        assert!(rendered.contains("# server.limits.route_timeouts.\"/admin\" can't be set"));
+
+        let vars: Vec<_> = rendered
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| {
+                let (name, value) = line.split_once('=').unwrap();
+                let value = match value.strip_prefix('\'') {
+                    Some(quoted) => quoted.strip_suffix('\'').unwrap().replace(r"'\''", "'"),
+                    None => value.to_owned(),
+                };
+                (name.to_owned(), value)
+            })
+            .collect();
+        vars.iter().for_each(|(name, value)| set_env(name, value));
+        let cli = Cli {
+            conf: "nonexistent.toml".into(),
+            conf_format: None,
+            profile: None,
+            debug: false,
+        };
+        let loaded = load_config(&cli);
+        vars.iter().for_each(|(name, _)| remove_env(name));
+
+        config.server.limits.route_timeouts.clear();
+        assert_eq!(
+            serde_json::to_value(loaded.unwrap()).unwrap(),
+            serde_json::to_value(config).unwrap(),
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! Descriptions of the configuration generated from the [`AppConfig`] structs:
+//! a JSON Schema and a `config.toml` listing every option commented out with
+//! its default value.
+
+use serde::Serialize;
+use serde_json::Value;
+use toml_edit::{DocumentMut, Item, Key, Table};
+
+use crate::{AppConfig, PostgresConfig, SqliteConfig};
+
+/// Width the comments of the sample config are wrapped at.
+const WIDTH: usize = 78;
+
+/// JSON Schema of the configuration file, with the doc comments of the
+/// fields as descriptions.
+pub fn json_schema() -> Value {
+    schemars::schema_for!(AppConfig).to_value()
+}
+
+/// Default `config.toml` with every option commented out, documented and
+/// set to its default value.
+pub fn sample_config() -> String {
+    let schema = json_schema();
+    let mut defaults = to_document(&AppConfig::default());
+    // Only the section of the selected database backend is serialized
+    let db = defaults["db"].as_table_mut().expect("`db` is a table");
+    let postgres = to_document(&PostgresConfig::default());
+    let sqlite = to_document(&SqliteConfig::default());
+    db.insert("postgres", Item::Table(postgres.as_table().clone()));
+    db.insert("sqlite", Item::Table(sqlite.as_table().clone()));
+    let mut sample = Sample {
+        root: &schema,
+        out: String::new(),
+        new_section: false,
+    };
+    sample.table(&schema, None, defaults.as_table(), &[]);
+    sample.out
+}
+
+fn to_document(value: &impl Serialize) -> DocumentMut {
+    toml::to_string(value)
+        .expect("default config serializes to TOML")
+        .parse()
+        .expect("serialized TOML parses back")
+}
+
+struct Sample<'a> {
+    /// Root of the schema, holding the definitions `$ref`s point to.
+    root: &'a Value,
+    out: String,
+    /// Whether the next header starts a new top-level section.
+    new_section: bool,
+}
+
+impl<'a> Sample<'a> {
+    /// Writes the options of a table, followed by its nested tables.
+    ///
+    /// Options without a default value are absent in `defaults`, so they are
+    /// taken from the `schema` and come last.
+    fn table(&mut self, schema: &'a Value, doc: Option<String>, defaults: &Table, path: &[&str]) {
+        let schema = self.resolve(schema);
+        let properties = schema["properties"].as_object();
+        let property = |key: &str| properties.and_then(|props| props.get(key));
+
+        let mut options: Vec<_> = defaults
+            .iter()
+            .filter(|(_, item)| !item.is_table())
+            .map(|(key, item)| (key, item.as_value().map(|v| v.clone().decorated("", ""))))
+            .collect();
+        let unset = properties
+            .into_iter()
+            .flatten()
+            .map(|(key, _)| key.as_str());
+        options.extend(
+            unset
+                .filter(|key| !defaults.contains_key(key))
+                .map(|key| (key, None)),
+        );
+        // Maps are sections without fixed options, they need the header to
+        // document what goes under it
+        let is_map = properties.is_none();
+
+        if path.len() == 1 {
+            self.new_section = true;
+        }
+        // Tables holding only tables, like `[db]`, need no header of their own
+        if !path.is_empty() && (!options.is_empty() || is_map) {
+            if !self.out.is_empty() {
+                self.out
+                    .push_str(if self.new_section { "\n\n\n\n" } else { "\n" });
+            }
+            self.new_section = false;
+            self.out.push_str(&format!("[{}]\n", path.join(".")));
+            if let Some(doc) = doc {
+                self.comment(&doc, "# ");
+                // Entries of maps follow their example right away
+                if !options.is_empty() && !is_map {
+                    self.out.push('\n');
+                }
+            }
+            if is_map {
+                self.example(schema, None);
+            }
+        }
+        for (i, (key, default)) in options.into_iter().enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+            }
+            let schema = property(key).unwrap_or(&Value::Null);
+            if let Some(doc) = self.doc(schema) {
+                self.comment(&doc, "# ");
+            }
+            self.possible_values(schema);
+            match default {
+                Some(default) => {
+                    // Keys of maps, like route prefixes, may need quoting
+                    let key = Key::new(key);
+                    self.out
+                        .push_str(&format!("#\n# Default:\n#   {key} = {default}\n"))
+                }
+                None => self.out.push_str("#\n# Unset by default.\n"),
+            }
+            self.example(schema, Some(key));
+        }
+        for (key, item) in defaults.iter() {
+            if let Item::Table(nested) = item {
+                let schema = property(key).unwrap_or(&Value::Null);
+                let path = [path, &[key]].concat();
+                self.table(schema, self.doc(schema), nested, &path);
+            }
+        }
+    }
+
+    /// Writes the first example of the schema, as the option under `key` or
+    /// as the entries of a map.
+    fn example(&mut self, schema: &Value, key: Option<&str>) {
+        let Some(example) = schema["examples"].get(0) else {
+            return;
+        };
+        let example = match key {
+            Some(key) => serde_json::json!({ key: example }),
+            None => example.clone(),
+        };
+        let example = toml::to_string(&example).expect("examples serialize to TOML");
+        self.out.push_str("#\n# Example:\n");
+        for line in example.lines() {
+            self.out.push_str(&format!("#   {line}\n"));
+        }
+    }
+
+    /// Definition the schema refers to, or the schema itself. Optional
+    /// values resolve to the schema of the value.
+    fn resolve(&self, schema: &'a Value) -> &'a Value {
+        let not_null = schema["anyOf"]
+            .as_array()
+            .and_then(|variants| variants.iter().find(|variant| variant["type"] != "null"));
+        if let Some(schema) = not_null {
+            return self.resolve(schema);
+        }
+        match schema["$ref"]
+            .as_str()
+            .and_then(|r| r.strip_prefix("#/$defs/"))
+        {
+            Some(name) => &self.root["$defs"][name],
+            None => schema,
+        }
+    }
+
+    /// Doc comment of the field, or of its type if the field has none.
+    fn doc(&self, schema: &'a Value) -> Option<String> {
+        let doc = |schema: &Value| {
+            let parts: Vec<_> = ["title", "description"]
+                .iter()
+                .filter_map(|key| schema[key].as_str())
+                .collect();
+            (!parts.is_empty()).then(|| parts.join("\n\n"))
+        };
+        doc(schema).or_else(|| doc(self.resolve(schema)))
+    }
+
+    /// Lists the variants of an enum with their docs.
+    fn possible_values(&mut self, schema: &'a Value) {
+        let Some(variants) = self.resolve(schema)["oneOf"].as_array() else {
+            return;
+        };
+        self.out.push_str("#\n# Possible values:\n");
+        for variant in variants {
+            let Some(value) = variant["const"].as_str() else {
+                continue;
+            };
+            self.out.push_str(&format!("#   \"{value}\"\n"));
+            if let Some(doc) = self.doc(variant) {
+                self.comment(&doc, "#       ");
+            }
+        }
+    }
+
+    /// Writes the text as lines starting with `prefix`, wrapped at [`WIDTH`]
+    /// and keeping paragraphs apart.
+    fn comment(&mut self, text: &str, prefix: &str) {
+        for (i, paragraph) in text.split("\n\n").enumerate() {
+            if i > 0 {
+                self.out.push_str(prefix.trim_end());
+                self.out.push('\n');
+            }
+            let mut line = String::new();
+            for word in paragraph.split_whitespace() {
+                if !line.is_empty() && prefix.len() + line.len() + 1 + word.len() > WIDTH {
+                    self.out.push_str(&format!("{prefix}{line}\n"));
+                    line.clear();
+                }
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                line.push_str(word);
+            }
+            self.out.push_str(&format!("{prefix}{line}\n"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn committed_config_matches_the_structs() {
+        assert!(
+            include_str!("../config.toml") == sample_config(),
+            "config.toml is outdated, regenerate it with `step_3_9 schema > config.toml`",
+        );
+    }
+
+    #[test]
+    fn uncommented_defaults_load_as_defaults() {
+        let sample = sample_config();
+        let mut lines = sample.lines();
+        let mut uncommented = String::new();
+        while let Some(line) = lines.next() {
+            if line.starts_with('[') {
+                uncommented.push_str(line);
+                uncommented.push('\n');
+            } else if line == "# Default:" {
+                let option = lines.next().unwrap().trim_start_matches("#   ");
+                uncommented.push_str(option);
+                uncommented.push('\n');
+            }
+        }
+        let loaded: AppConfig = toml::from_str(&uncommented).unwrap();
+        assert_eq!(
+            serde_json::to_value(loaded).unwrap(),
+            serde_json::to_value(AppConfig::default()).unwrap(),
+        );
+
+        let schema = json_schema();
+        let secret = &schema["$defs"]["FeaturesConfig"]["properties"]["override_secret"];
+        assert_eq!(secret["examples"][0], "change-me");
+        assert!(sample.contains("#   \"/admin\" = \"1m\"\n"));
+    }
+}
@@ -0,0 +1,244 @@
+//! Checks of the values that deserialize fine but make no sense, like a zero
+//! port or a watchdog lock outliving the watchdog period.
+
+use std::fmt;
+
+use tracing_subscriber::EnvFilter;
+use url::Url;
+
+use crate::{AppConfig, DatabaseConfig, Rate};
+
+/// Value of the configuration that makes no sense.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Dotted path of the value, as in the config file.
+    pub path: String,
+    pub message: String,
+}
+
+/// Every [`Violation`] found in a configuration, in the order of the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationErrors(pub Vec<Violation>);
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration:")?;
+        for Violation { path, message } in &self.0 {
+            write!(f, "\n  {path}: {message}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// Collects the violations found so far.
+#[derive(Default)]
+struct Checks(Vec<Violation>);
+
+impl Checks {
+    fn check(&mut self, ok: bool, path: &str, message: impl Into<String>) {
+        if !ok {
+            self.0.push(Violation {
+                path: path.to_owned(),
+                message: message.into(),
+            });
+        }
+    }
+
+    fn not_empty(&mut self, value: &str, path: &str) {
+        self.check(!value.trim().is_empty(), path, "must not be empty");
+    }
+
+    fn positive(&mut self, positive: bool, path: &str) {
+        self.check(positive, path, "must be greater than zero");
+    }
+
+    fn rate(&mut self, rate: &Rate, path: &str) {
+        self.positive(rate.requests > 0 && !rate.per.is_zero(), path);
+    }
+
+    /// Connection to a database server, configured by the `section`.
+    fn db_server(&mut self, section: &str, host: &str, port: u16, database: &str, user: &str) {
+        self.not_empty(host, &format!("{section}.host"));
+        self.positive(port > 0, &format!("{section}.port"));
+        self.not_empty(database, &format!("{section}.database"));
+        self.not_empty(user, &format!("{section}.user"));
+    }
+}
+
+impl AppConfig {
+    /// Checks the values that deserialization accepts but the servers can't
+    /// work with, reporting all of them at once.
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut checks = Checks::default();
+
+        let server = &self.server;
+        let url = Url::parse(&server.external_url).map_err(|err| err.to_string());
+        let url = url.and_then(|url| match url.scheme() {
+            "http" | "https" if url.has_host() => Ok(()),
+            "http" | "https" => Err("must have a host".to_owned()),
+            scheme => Err(format!("must be http or https, not `{scheme}`")),
+        });
+        checks.check(
+            url.is_ok(),
+            "server.external_url",
+            url.err().unwrap_or_default(),
+        );
+        let ports = [
+            ("server.http_port", server.http_port),
+            ("server.grpc_port", server.grpc_port),
+            ("server.healthz_port", server.healthz_port),
+            ("server.metrics_port", server.metrics_port),
+        ];
+        for (i, &(path, port)) in ports.iter().enumerate() {
+            checks.positive(port > 0, path);
+            let taken_by = ports[..i].iter().find(|(_, other)| *other == port);
+            if let Some((taken_by, _)) = taken_by.filter(|_| port > 0) {
+                checks.check(false, path, format!("must differ from {taken_by}"));
+            }
+        }
+        let limits = &server.limits;
+        checks.positive(limits.max_body_size > 0, "server.limits.max_body_size");
+        checks.positive(!limits.timeout.is_zero(), "server.limits.timeout");
+        for (prefix, timeout) in &limits.route_timeouts {
+            let path = format!("server.limits.route_timeouts.\"{prefix}\"");
+            checks.check(prefix.starts_with('/'), &path, "prefix must start with `/`");
+            checks.positive(!timeout.is_zero(), &path);
+        }
+        let rate_limits = &server.rate_limits;
+        checks.rate(&rate_limits.per_token, "server.rate_limits.per_token");
+        checks.rate(&rate_limits.per_ip, "server.rate_limits.per_ip");
+        for (prefix, rate) in &rate_limits.routes {
+            let path = format!("server.rate_limits.routes.\"{prefix}\"");
+            checks.check(prefix.starts_with('/'), &path, "prefix must start with `/`");
+            checks.rate(rate, &path);
+        }
+        checks.positive(server.events.queue_len > 0, "server.events.queue_len");
+        let auth = &server.auth;
+        if let Some(secret) = &auth.secret {
+            checks.check(
+                !secret.is_empty(),
+                "server.auth.secret",
+                "must not be empty, leave it unset to generate one",
+            );
+        }
+        checks.positive(!auth.access_ttl.is_zero(), "server.auth.access_ttl");
+        checks.check(
+            auth.refresh_ttl > auth.access_ttl,
+            "server.auth.refresh_ttl",
+            format!(
+                "must be longer than server.auth.access_ttl ({})",
+                humantime::format_duration(auth.access_ttl)
+            ),
+        );
+
+        match &self.db {
+            DatabaseConfig::Mysql(c) => {
+                checks.db_server("db.mysql", &c.host, c.port, &c.database, &c.user);
+                let max_open = c.connections.max_open > 0;
+                checks.positive(max_open, "db.mysql.connections.max_open");
+            }
+            DatabaseConfig::Postgres(c) => {
+                checks.db_server("db.postgres", &c.host, c.port, &c.database, &c.user);
+                let max_open = c.connections.max_open > 0;
+                checks.positive(max_open, "db.postgres.connections.max_open");
+            }
+            DatabaseConfig::Sqlite(sqlite) => {
+                let path = sqlite.path.as_os_str();
+                checks.check(!path.is_empty(), "db.sqlite.path", "must not be empty");
+            }
+        }
+
+        let level = EnvFilter::try_new(&self.log.app.level).map_err(|err| err.to_string());
+        checks.check(
+            level.is_ok(),
+            "log.app.level",
+            level.err().unwrap_or_default(),
+        );
+
+        let watchdog = &self.background.watchdog;
+        checks.positive(!watchdog.period.is_zero(), "background.watchdog.period");
+        checks.positive(watchdog.limit > 0, "background.watchdog.limit");
+        checks.check(
+            watchdog.lock_timeout < watchdog.period,
+            "background.watchdog.lock_timeout",
+            format!(
+                "must be shorter than background.watchdog.period ({})",
+                humantime::format_duration(watchdog.period)
+            ),
+        );
+        let stats = &self.background.stats;
+        checks.positive(!stats.period.is_zero(), "background.stats.period");
+        checks.positive(stats.top > 0, "background.stats.top");
+
+        if let Some(secret) = &self.features.override_secret {
+            checks.check(
+                !secret.is_empty(),
+                "features.override_secret",
+                "must not be empty, leave it unset to disable overrides",
+            );
+        }
+
+        if checks.0.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors(checks.0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::PostgresConfig;
+
+    #[test]
+    fn reports_every_violation_with_its_path() {
+        assert_eq!(AppConfig::default().validate(), Ok(()));
+
+        let mut config = AppConfig::default();
+        config.server.external_url = "ftp://files.example.com".into();
+        config.server.grpc_port = config.server.http_port;
+        config.server.metrics_port = 0;
+        config
+            .server
+            .limits
+            .route_timeouts
+            .insert("admin".into(), Duration::from_secs(1).into());
+        config.server.rate_limits.per_ip.requests = 0;
+        config.server.auth.refresh_ttl = Duration::from_secs(60);
+        config.db = DatabaseConfig::Postgres(PostgresConfig {
+            database: " ".into(),
+            ..Default::default()
+        });
+        config.log.app.level = "step_3=loud".into();
+        config.background.watchdog.lock_timeout = Duration::from_secs(60);
+
+        let errors = config.validate().unwrap_err();
+        let paths: Vec<_> = errors.0.iter().map(|v| v.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "server.external_url",
+                "server.grpc_port",
+                "server.metrics_port",
+                "server.limits.route_timeouts.\"admin\"",
+                "server.rate_limits.per_ip",
+                "server.auth.refresh_ttl",
+                "db.postgres.database",
+                "log.app.level",
+                "background.watchdog.lock_timeout",
+            ]
+        );
+        let rendered = errors.to_string();
+        assert!(rendered.starts_with(
+            "invalid configuration:\n  server.external_url: must be http or https, not `ftp`"
+        ));
+        assert!(rendered.contains("server.grpc_port: must differ from server.http_port"));
+        assert!(rendered.contains("shorter than background.watchdog.period (5s)"));
+        assert!(rendered.contains("longer than server.auth.access_ttl (15m)"));
+    }
+}
@@ -0,0 +1,242 @@
+//! Reloading of the configuration when its file changes.
+
+use std::fs::{self, Metadata};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use tracing::{error, info};
+
+use crate::{AppConfig, Cli, load_config};
+
+/// How often the configuration file is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Coarsest modification time resolution of the supported filesystems (FAT).
+const MTIME_GRANULARITY: Duration = Duration::from_secs(2);
+
+/// Reloaded configurations published by [`watch_config`].
+///
+/// Dropping it stops the watcher thread at its next check.
+#[derive(Debug)]
+pub struct ConfigUpdates {
+    receiver: Receiver<AppConfig>,
+    // The watcher holds a `Weak` to it and exits once it's gone
+    _alive: Arc<()>,
+}
+
+impl ConfigUpdates {
+    /// Waits up to `timeout` for the next reloaded configuration.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<AppConfig, RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+}
+
+impl Iterator for ConfigUpdates {
+    type Item = AppConfig;
+
+    fn next(&mut self) -> Option<AppConfig> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// State of a watched file: its metadata, checked on every poll, and its
+/// contents, reread when the metadata changes.
+///
+/// The modification time only advances once per tick of the filesystem's
+/// clock, so a rewrite keeping the size within the tick the file was read in
+/// leaves the metadata as is. Until the read is a whole tick past the
+/// modification time, the contents are reread on every poll.
+struct Snapshot {
+    stamp: Option<(u64, Option<SystemTime>)>,
+    contents: Option<Vec<u8>>,
+    read_at: SystemTime,
+}
+
+impl Snapshot {
+    fn stamp(metadata: &Metadata) -> (u64, Option<SystemTime>) {
+        (metadata.len(), metadata.modified().ok())
+    }
+
+    fn take(file: &Path) -> Self {
+        let read_at = SystemTime::now();
+        Self {
+            stamp: fs::metadata(file).ok().as_ref().map(Self::stamp),
+            contents: fs::read(file).ok(),
+            read_at,
+        }
+    }
+
+    /// Tells whether the file may have changed since it was read without its
+    /// metadata showing it.
+    fn is_racy(&self) -> bool {
+        match self.stamp {
+            Some((_, Some(modified))) => modified + MTIME_GRANULARITY > self.read_at,
+            Some((_, None)) => true,
+            None => false,
+        }
+    }
+
+    /// Refreshes the snapshot, telling whether the contents changed.
+    fn refresh(&mut self, file: &Path) -> bool {
+        let stamp = fs::metadata(file).ok().as_ref().map(Self::stamp);
+        if stamp == self.stamp && !self.is_racy() {
+            return false;
+        }
+        let current = Self::take(file);
+        let changed = current.contents != self.contents;
+        *self = current;
+        changed
+    }
+}
+
+/// Loads the configuration and keeps watching its file and the file of the
+/// profile, publishing every successfully reloaded snapshot over the returned
+/// [`ConfigUpdates`].
+///
+/// A background thread checks the size and the modification time of the
+/// files, rereading them only when those change or are too recent to be
+/// trusted, and reloads when the contents differ, so touching a file doesn't
+/// trigger a reload. A file being
+/// created or removed counts as a change too. A reload that fails to load or
+/// to validate is logged and skipped, so the receiver keeps the last good
+/// configuration. The thread exits once [`ConfigUpdates`] is dropped.
+pub fn watch_config(cli: &Cli) -> Result<(AppConfig, ConfigUpdates)> {
+    let (config, updates, _) = watch_config_every(cli, POLL_INTERVAL)?;
+    Ok((config, updates))
+}
+
+fn watch_config_every(
+    cli: &Cli,
+    interval: Duration,
+) -> Result<(AppConfig, ConfigUpdates, JoinHandle<()>)> {
+    let files: Vec<_> = [Some(cli.conf.clone()), cli.profile_conf()]
+        .into_iter()
+        .flatten()
+        .collect();
+    let mut seen: Vec<_> = files.iter().map(|file| Snapshot::take(file)).collect();
+    let config = load_config(cli)?;
+    let (sender, receiver) = mpsc::channel();
+    let alive = Arc::new(());
+    let watched: Weak<()> = Arc::downgrade(&alive);
+    let cli = cli.clone();
+    let watcher = thread::Builder::new()
+        .name("config-watcher".into())
+        .spawn(move || {
+            loop {
+                thread::sleep(interval);
+                if watched.strong_count() == 0 {
+                    break;
+                }
+                // Refresh every snapshot, not just up to the first change
+                let changed = files
+                    .iter()
+                    .zip(&mut seen)
+                    .fold(false, |changed, (file, seen)| seen.refresh(file) | changed);
+                if !changed {
+                    continue;
+                }
+                match load_config(&cli) {
+                    Ok(config) => {
+                        info!("reloaded configuration from {}", cli.conf.display());
+                        if sender.send(config).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => error!(
+                        "keeping the previous configuration, reloading {} failed: {err:#}",
+                        cli.conf.display()
+                    ),
+                }
+            }
+        })?;
+    let updates = ConfigUpdates {
+        receiver,
+        _alive: alive,
+    };
+    Ok((config, updates, watcher))
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+
+    #[test]
+    #[serial]
+    fn publishes_valid_reloads_and_skips_invalid_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        // Replaced at once, so the watcher never sees a half-written file
+        let write_port = |port: u16| {
+            let tmp = path.with_extension("tmp");
+            fs::write(&tmp, format!("[server]\nhttp_port = {port}\n")).unwrap();
+            fs::rename(&tmp, &path).unwrap();
+        };
+        write_port(9000);
+        let cli = Cli {
+            conf: path.clone(),
+            conf_format: None,
+            profile: None,
+            debug: false,
+        };
+        let interval = Duration::from_millis(10);
+        let (config, updates, _) = watch_config_every(&cli, interval).unwrap();
+        assert_eq!(config.server.http_port, 9000);
+
+        let timeout = Duration::from_secs(5);
+        write_port(9001);
+        assert_eq!(
+            updates.recv_timeout(timeout).unwrap().server.http_port,
+            9001
+        );
+
+        // Conflicts with the default gRPC port, so validation rejects it
+        write_port(8082);
+        assert!(updates.recv_timeout(interval * 20).is_err());
+
+        write_port(9002);
+        assert_eq!(
+            updates.recv_timeout(timeout).unwrap().server.http_port,
+            9002
+        );
+    }
+
+    #[test]
+    fn notices_same_size_rewrites_within_the_mtime_tick() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "http_port = 9000\n").unwrap();
+        let mut snapshot = Snapshot::take(&path);
+        assert!(!snapshot.refresh(&path));
+
+        fs::write(&path, "http_port = 9001\n").unwrap();
+        assert!(snapshot.refresh(&path));
+        assert!(!snapshot.refresh(&path));
+    }
+
+    #[test]
+    #[serial]
+    fn stops_once_the_updates_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "[server]\nhttp_port = 9000\n").unwrap();
+        let cli = Cli {
+            conf: path,
+            conf_format: None,
+            profile: None,
+            debug: false,
+        };
+        let interval = Duration::from_millis(10);
+        let (_, updates, watcher) = watch_config_every(&cli, interval).unwrap();
+        assert!(!watcher.is_finished());
+
+        // Without any change to the file
+        drop(updates);
+        watcher.join().unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+//! Command-line scaffolding shared by the workspace binaries.
+//!
+//! Tools flatten [`CommonArgs`] (and [`ConfigArgs`] if they read a config
+//! file) into their own arguments, so logging, output format and shell
+//! completions behave the same in each of them:
+//!
+//! ```no_run
+//! use clap::{CommandFactory, Parser};
+//! use cli_common::CommonArgs;
+//!
+//! #[derive(Parser)]
+//! struct Cli {
+//!     #[command(flatten)]
+//!     common: CommonArgs,
+//! }
+//!
+//! let cli = Cli::parse();
+//! if cli.common.print_completions(&mut Cli::command()) {
+//!     return;
+//! }
+//! cli.common.init_tracing("info");
+//! ```
+//!
+//! Long-running tools also flatten [`ProgressArgs`] to report their progress
+//! to wrappers, see [`progress`]. Listings are printed as [`Table`]s.
+
+use std::{io, path::PathBuf};
+
+use clap::{ArgAction, Args, ValueEnum};
+use clap_complete::Shell;
+use step_3_8::JsonFormatter;
+use tracing_subscriber::EnvFilter;
+
+pub mod progress;
+pub mod table;
+
+pub use progress::{Progress, ProgressArgs};
+pub use table::Table;
+
+/// Location of the tool's configuration file.
+#[derive(Debug, Clone, Args)]
+pub struct ConfigArgs {
+    /// Path to a configuration file
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+}
+
+/// Format of logs and printed results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+/// Flags every tool accepts.
+#[derive(Debug, Clone, Args)]
+pub struct CommonArgs {
+    /// Log filter, e.g. `debug` or `step_3=trace`; overrides `RUST_LOG`
+    #[arg(long, global = true, value_name = "FILTER")]
+    pub log_level: Option<String>,
+
+    /// Format of logs and of the printed results, if the tool prints any
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+
+    /// Only log errors
+    #[arg(long, short, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Log more: `-v` for debug, `-vv` for trace
+    #[arg(long, short, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    // Exclusive, so tools with subcommands have to make them optional for
+    // `--completions` to work without one.
+    /// Print a completion script for the shell and exit
+    #[arg(long, value_name = "SHELL", exclusive = true)]
+    pub completions: Option<Shell>,
+}
+
+impl CommonArgs {
+    /// Log filter requested on the command line, if any.
+    ///
+    /// `--log-level` wins over `-q`/`-v`, and both win over `RUST_LOG`.
+    pub fn log_filter(&self) -> Option<String> {
+        if let Some(filter) = &self.log_level {
+            return Some(filter.clone());
+        }
+        match (self.quiet, self.verbose) {
+            (true, _) => Some("error".into()),
+            (false, 0) => None,
+            (false, 1) => Some("debug".into()),
+            (false, _) => Some("trace".into()),
+        }
+    }
+
+    /// Installs a `tracing` subscriber logging to STDERR in the requested
+    /// [`Format`].
+    ///
+    /// `default_filter` applies when neither flags nor `RUST_LOG` set one.
+    pub fn init_tracing(&self, default_filter: &str) {
+        let filter = match self.log_filter() {
+            Some(filter) => EnvFilter::new(filter),
+            None => EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| EnvFilter::new(default_filter)),
+        };
+        let builder = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(io::stderr)
+            .with_target(false);
+        match self.format {
+            Format::Text => builder.init(),
+            Format::Json => builder.event_format(JsonFormatter::new("stderr")).init(),
+        }
+    }
+
+    /// Prints the completion script requested with `--completions`, if any.
+    ///
+    /// Returns `true` if the script was printed, so the tool should exit.
+    pub fn print_completions(&self, cmd: &mut clap::Command) -> bool {
+        let Some(shell) = self.completions else {
+            return false;
+        };
+        let name = cmd.get_name().to_string();
+        clap_complete::generate(shell, cmd, name, &mut io::stdout());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{Parser, Subcommand};
+
+    use super::*;
+
+    #[derive(Debug, Parser)]
+    struct Cli {
+        #[command(flatten)]
+        common: CommonArgs,
+        #[command(flatten)]
+        config: ConfigArgs,
+        #[command(subcommand)]
+        command: Option<Command>,
+    }
+
+    #[derive(Debug, Subcommand)]
+    enum Command {
+        Run,
+    }
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(["tool"].iter().chain(args))
+    }
+
+    #[test]
+    fn flags_select_the_log_filter() {
+        let cli = parse(&["run"]).unwrap();
+        assert!(cli.command.is_some());
+        assert_eq!(cli.common.log_filter(), None);
+        assert_eq!(cli.common.format, Format::Text);
+        assert_eq!(cli.config.config, None);
+
+        let cli = parse(&["run", "-vv", "--format", "json", "--config", "a.toml"]).unwrap();
+        assert_eq!(cli.common.log_filter().as_deref(), Some("trace"));
+        assert_eq!(cli.common.format, Format::Json);
+        assert_eq!(cli.config.config, Some(PathBuf::from("a.toml")));
+
+        let cli = parse(&["-q", "run"]).unwrap();
+        assert_eq!(cli.common.log_filter().as_deref(), Some("error"));
+        let cli = parse(&["run", "-v", "--log-level", "tool=debug"]).unwrap();
+        assert_eq!(cli.common.log_filter().as_deref(), Some("tool=debug"));
+
+        assert!(parse(&["run", "-q", "-v"]).is_err());
+    }
+
+    #[test]
+    fn completions_need_no_subcommand() {
+        let cli = parse(&["--completions", "bash"]);
+        assert_eq!(cli.unwrap().common.completions, Some(Shell::Bash));
+        assert!(parse(&["run", "--completions", "bash"]).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! Machine-readable progress of the long-running tools.
+//!
+//! Tools flatten [`ProgressArgs`] into their arguments. With `--progress-json`
+//! they report progress as newline-delimited JSON objects on STDERR, each
+//! tagged with its kind in the `event` field:
+//!
+//! ```text
+//! {"event":"started","tool":"step_3","total":2}
+//! {"event":"item_completed","item":"a.jpg","elapsed_ms":120}
+//! {"event":"item_failed","item":"b.jpg","error":"not a JPEG image","elapsed_ms":3}
+//! {"event":"summary","completed":1,"failed":1,"elapsed_ms":125}
+//! ```
+//!
+//! Logs go to STDERR too, so consumers should skip lines that aren't JSON
+//! objects with an `event` field.
+
+use std::{
+    fmt,
+    io::{self, Write},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use serde::Serialize;
+
+/// Flags of the tools reporting progress.
+#[derive(Debug, Clone, Args)]
+pub struct ProgressArgs {
+    /// Report progress as newline-delimited JSON events on STDERR
+    #[arg(long, global = true)]
+    pub progress_json: bool,
+}
+
+impl ProgressArgs {
+    /// Starts reporting the progress of `total` items, if it's requested.
+    pub fn start(&self, tool: &str, total: Option<usize>) -> Progress {
+        if self.progress_json {
+            Progress::to_writer(tool, total, io::stderr())
+        } else {
+            Progress::disabled()
+        }
+    }
+}
+
+/// Single line of the progress protocol.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    Started {
+        tool: String,
+        /// Number of items to process, if known upfront
+        total: Option<usize>,
+    },
+    ItemCompleted {
+        item: String,
+        elapsed_ms: u64,
+    },
+    ItemFailed {
+        item: String,
+        error: String,
+        elapsed_ms: u64,
+    },
+    /// Totals of the run, reported once all items are done.
+    Summary {
+        completed: u64,
+        failed: u64,
+        elapsed_ms: u64,
+    },
+}
+
+/// Reporter of [`ProgressEvent`]s, doing nothing unless enabled.
+///
+/// Cloning is cheap: clones report to the same output and share the totals.
+#[derive(Clone)]
+pub struct Progress {
+    tracker: Option<Arc<Tracker>>,
+}
+
+struct Tracker {
+    started: Instant,
+    completed: AtomicU64,
+    failed: AtomicU64,
+    output: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Progress {
+    /// Reporter ignoring all the events.
+    pub fn disabled() -> Self {
+        Self { tracker: None }
+    }
+
+    /// Reports the events to `output`, starting with [`ProgressEvent::Started`].
+    pub fn to_writer(
+        tool: &str,
+        total: Option<usize>,
+        output: impl Write + Send + 'static,
+    ) -> Self {
+        let progress = Self {
+            tracker: Some(Arc::new(Tracker {
+                started: Instant::now(),
+                completed: AtomicU64::new(0),
+                failed: AtomicU64::new(0),
+                output: Mutex::new(Box::new(output)),
+            })),
+        };
+        progress.emit(&ProgressEvent::Started {
+            tool: tool.to_owned(),
+            total,
+        });
+        progress
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.tracker.is_some()
+    }
+
+    /// Reports the `item` processed successfully in `elapsed`.
+    pub fn completed(&self, item: &str, elapsed: Duration) {
+        let Some(tracker) = &self.tracker else {
+            return;
+        };
+        tracker.completed.fetch_add(1, Ordering::Relaxed);
+        self.emit(&ProgressEvent::ItemCompleted {
+            item: item.to_owned(),
+            elapsed_ms: millis(elapsed),
+        });
+    }
+
+    /// Reports the `item` failed with the `error` after `elapsed`.
+    ///
+    /// The error is printed with `{:#}`, so `anyhow` errors include their
+    /// causes.
+    pub fn failed(&self, item: &str, error: impl fmt::Display, elapsed: Duration) {
+        let Some(tracker) = &self.tracker else {
+            return;
+        };
+        tracker.failed.fetch_add(1, Ordering::Relaxed);
+        self.emit(&ProgressEvent::ItemFailed {
+            item: item.to_owned(),
+            error: format!("{error:#}"),
+            elapsed_ms: millis(elapsed),
+        });
+    }
+
+    /// Reports the [`ProgressEvent::Summary`] of the items reported so far.
+    pub fn finish(&self) {
+        let Some(tracker) = &self.tracker else {
+            return;
+        };
+        self.emit(&ProgressEvent::Summary {
+            completed: tracker.completed.load(Ordering::Relaxed),
+            failed: tracker.failed.load(Ordering::Relaxed),
+            elapsed_ms: millis(tracker.started.elapsed()),
+        });
+    }
+
+    fn emit(&self, event: &ProgressEvent) {
+        let Some(tracker) = &self.tracker else {
+            return;
+        };
+        let line = serde_json::to_string(event).expect("progress events are serializable");
+        let mut output = tracker
+            .output
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        // Progress is best-effort, a closed STDERR mustn't fail the tool.
+        let _ = writeln!(output, "{line}").and_then(|()| output.flush());
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    /// Output shared with the test, as the reporter owns its writer.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reports_events_as_json_lines() {
+        let buffer = Buffer::default();
+        let progress = Progress::to_writer("tool", Some(2), buffer.clone());
+        progress.completed("a.jpg", Duration::from_millis(12));
+        progress.clone().failed(
+            "b.jpg",
+            anyhow::anyhow!("bad header").context("not a JPEG image"),
+            Duration::ZERO,
+        );
+        progress.finish();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 4);
+        assert_eq!(
+            events[0],
+            serde_json::json!({ "event": "started", "tool": "tool", "total": 2 })
+        );
+        assert_eq!(
+            events[1],
+            serde_json::json!({ "event": "item_completed", "item": "a.jpg", "elapsed_ms": 12 })
+        );
+        assert_eq!(events[2]["error"], "not a JPEG image: bad header");
+        assert_eq!(
+            (
+                &events[3]["event"],
+                &events[3]["completed"],
+                &events[3]["failed"]
+            ),
+            (&"summary".into(), &1.into(), &1.into())
+        );
+
+        let disabled = Progress::disabled();
+        disabled.completed("a.jpg", Duration::ZERO);
+        disabled.finish();
+        assert!(!disabled.is_enabled());
+    }
+}
@@ -0,0 +1,63 @@
+//! Plain-text tables for the listings the tools print.
+
+use std::fmt;
+
+/// Plain-text table with left-aligned columns sized to their widest cell.
+#[derive(Debug, Clone)]
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: Vec<&'static str>) -> Self {
+        Self {
+            headers,
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn row(mut self, row: Vec<String>) -> Self {
+        self.rows.push(row);
+        self
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut widths: Vec<_> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let headers = self.headers.iter().map(|h| h.to_string()).collect();
+        let lines: Vec<_> = std::iter::once(&headers)
+            .chain(&self.rows)
+            .map(|row| {
+                let line = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{cell:width$}"))
+                    .collect::<Vec<_>>()
+                    .join("  ");
+                line.trim_end().to_string()
+            })
+            .collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_columns_by_characters() {
+        let table = Table::new(vec!["NAME", "ROLE"])
+            .row(vec!["Zoë".into(), "admin".into()])
+            .row(vec!["bob".into(), String::new()]);
+        assert_eq!(table.to_string(), "NAME  ROLE\nZoë   admin\nbob");
+    }
+}
@@ -0,0 +1,20 @@
+//! Feeds arbitrary token streams to the `btreemap!` expansion: malformed
+//! input must come back as an error, and well-formed output must be an
+//! expression.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use proc_macro2::TokenStream;
+
+#[path = "../../3_2_macro/btreemap_proc_macro/src/expand.rs"]
+mod expand;
+
+fuzz_target!(|input: &str| {
+    let Ok(tokens) = input.parse::<TokenStream>() else {
+        return;
+    };
+    if let Ok(expanded) = expand::expand(tokens) {
+        syn::parse2::<syn::Expr>(expanded).expect("expansion is an expression");
+    }
+});
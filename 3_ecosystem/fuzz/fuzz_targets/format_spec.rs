@@ -0,0 +1,11 @@
+//! Feeds arbitrary input to both format-spec parsers: neither may panic, and
+//! they must agree on every input.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use step_3_4::{parse, parse_with_regex};
+
+fuzz_target!(|input: &str| {
+    assert_eq!(parse(input), parse_with_regex(input), "input: {input:?}");
+});
@@ -1,12 +1,16 @@
 use std::{
     collections::HashSet,
-    fs,
+    fmt, fs,
     path::{Path, PathBuf},
-    time::Instant,
+    time::{Instant, SystemTime},
 };
 
 use anyhow::{Context, Result, anyhow};
-use clap::Parser;
+use clap::{CommandFactory, Parser};
+use cli_common::{CommonArgs, ConfigArgs, ProgressArgs};
+use common::http_client::{HttpClient, is_transient};
+use common::retry::{Backoff, retry_async};
+use common::{SystemClock, WorkerPool, WorkerPoolConfig};
 use futures::stream::{self, StreamExt};
 use image::ImageEncoder;
 use image::codecs::jpeg::JpegEncoder;
@@ -17,10 +21,16 @@ use url::Url;
 
 #[derive(Debug, Parser)]
 #[command(about = "Strip JPEG metadata and recompress images", version)]
+#[command(mut_arg("config", |arg| arg.env("STEP3_CONFIG")))]
 struct CliArgs {
-    /// Optional path to a configuration file (TOML)
-    #[arg(long, env = "STEP3_CONFIG")]
-    config: Option<PathBuf>,
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    #[command(flatten)]
+    common: CommonArgs,
+
+    #[command(flatten)]
+    progress: ProgressArgs,
 
     /// Maximum number of images processed at once
     #[arg(long, env = "STEP3_CONCURRENCY")]
@@ -45,6 +55,11 @@ struct CliArgs {
     /// Read inputs from STDIN (EOL separated)
     #[arg(long, env = "STEP3_READ_STDIN")]
     read_stdin: bool,
+
+    /// Produce byte-identical outputs across runs: process inputs in sorted
+    /// order and stamp outputs with `SOURCE_DATE_EPOCH` (or the Unix epoch)
+    #[arg(long, env = "STEP3_DETERMINISTIC")]
+    deterministic: bool,
 }
 
 #[derive(Debug, Deserialize, Default, Clone)]
@@ -55,6 +70,9 @@ struct FileConfig {
     inputs: Option<Vec<String>>,
     input_file: Option<PathBuf>,
     read_stdin: Option<bool>,
+    deterministic: Option<bool>,
+    /// Threads decoding and encoding the images
+    workers: Option<WorkerPoolConfig>,
 }
 
 #[derive(Debug, Clone)]
@@ -65,11 +83,14 @@ struct Config {
     inputs: Vec<String>,
     input_file: Option<PathBuf>,
     read_stdin: bool,
+    /// Modification time of written images, fixed in deterministic mode
+    mtime: Option<SystemTime>,
+    workers: WorkerPoolConfig,
 }
 
 impl Config {
     fn from_sources(cli: CliArgs) -> Result<Self> {
-        let file_cfg = load_file_config(cli.config.as_deref())?;
+        let file_cfg = load_file_config(cli.config.config.as_deref())?;
 
         let output_dir = cli
             .output_dir
@@ -94,6 +115,8 @@ impl Config {
 
         let input_file = cli.input_file.or_else(|| file_cfg.input_file.clone());
         let read_stdin = cli.read_stdin || file_cfg.read_stdin.unwrap_or(false);
+        let deterministic = cli.deterministic || file_cfg.deterministic.unwrap_or(false);
+        let mtime = deterministic.then(common::reproducible::source_date_epoch);
 
         Ok(Self {
             concurrency,
@@ -102,6 +125,8 @@ impl Config {
             inputs,
             input_file,
             read_stdin,
+            mtime,
+            workers: file_cfg.workers.unwrap_or_default(),
         })
     }
 }
@@ -128,15 +153,13 @@ fn load_file_config(path: Option<&Path>) -> Result<FileConfig> {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
-        .with_target(false)
-        .init();
-
     let cli = CliArgs::parse();
+    if cli.common.print_completions(&mut CliArgs::command()) {
+        return Ok(());
+    }
+    cli.common.init_tracing("info");
+
+    let progress_args = cli.progress.clone();
     let config = Config::from_sources(cli)?;
 
     tokio::fs::create_dir_all(&config.output_dir)
@@ -152,8 +175,23 @@ async fn main() -> Result<()> {
     let mut seen = HashSet::new();
     inputs.retain(|item| seen.insert(item.clone()));
 
-    let client = reqwest::Client::new();
+    // Outputs must not depend on the order inputs were listed or finished in
+    let deterministic = config.mtime.is_some();
+    let names = if deterministic {
+        inputs.sort();
+        unique_output_names(&inputs)
+    } else {
+        inputs
+            .iter()
+            .enumerate()
+            .map(|(idx, input)| output_name(input, idx))
+            .collect()
+    };
+
+    let client = HttpClient::new()?;
+    let pool = WorkerPool::new("step3-cpu", &config.workers)?;
     let start = Instant::now();
+    let progress = progress_args.start("step_3", Some(inputs.len()));
 
     info!(
         "Processing {} inputs with concurrency {}",
@@ -161,20 +199,39 @@ async fn main() -> Result<()> {
         config.concurrency
     );
 
-    stream::iter(inputs.into_iter().enumerate().map(|(idx, input)| {
+    let tasks = stream::iter(inputs.into_iter().zip(names).map(|(input, name)| {
         let client = client.clone();
+        let pool = pool.clone();
         let cfg = config.clone();
+        let progress = progress.clone();
         async move {
-            if let Err(err) = process_single(idx, &input, &cfg, &client).await {
-                error!(target: "step3", "{}: {err:#}", input);
+            let started = Instant::now();
+            let result = process_single(&input, &name, &cfg, &client, &pool).await;
+            match &result {
+                Ok(_) => progress.completed(&input, started.elapsed()),
+                Err(err) => {
+                    error!(target: "step3", "{}: {err:#}", input);
+                    progress.failed(&input, err, started.elapsed());
+                }
+            }
+            Report {
+                input,
+                output: name,
+                result: result.map_err(|err| format!("{err:#}")),
             }
         }
-    }))
-    .buffer_unordered(config.concurrency)
-    .collect::<Vec<_>>()
-    .await;
+    }));
+    let reports = if deterministic {
+        // Keeps the log in input order too
+        tasks.buffered(config.concurrency).collect().await
+    } else {
+        tasks.buffer_unordered(config.concurrency).collect().await
+    };
 
     info!("Completed processing in {:.2?}", start.elapsed());
+    progress.finish();
+    pool.drain(config.workers.drain_timeout).await;
+    print!("{}", Summary::new(reports));
 
     Ok(())
 }
@@ -212,45 +269,126 @@ async fn collect_inputs(config: &Config) -> Result<Vec<String>> {
     Ok(inputs)
 }
 
+/// Sizes of an image before and after recompression, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Processed {
+    original: usize,
+    encoded: usize,
+}
+
+/// Outcome of processing a single input.
+#[derive(Debug)]
+struct Report {
+    input: String,
+    output: String,
+    result: Result<Processed, String>,
+}
+
+/// Table of the processed inputs, printed once all of them are done.
+struct Summary {
+    reports: Vec<Report>,
+}
+
+impl Summary {
+    fn new(mut reports: Vec<Report>) -> Self {
+        // Inputs finish in any order, the report shouldn't depend on it
+        reports.sort_by(|a, b| a.input.cmp(&b.input));
+        Self { reports }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let input_width = self
+            .reports
+            .iter()
+            .map(|report| report.input.len())
+            .chain(["INPUT".len()])
+            .max()
+            .unwrap_or_default();
+        let output_width = self
+            .reports
+            .iter()
+            .map(|report| report.output.len())
+            .chain(["OUTPUT".len()])
+            .max()
+            .unwrap_or_default();
+
+        writeln!(
+            f,
+            "{:<6}  {:<input_width$}  {:<output_width$}  RESULT",
+            "STATUS", "INPUT", "OUTPUT"
+        )?;
+        for report in &self.reports {
+            let (status, result) = match &report.result {
+                Ok(Processed { original, encoded }) => {
+                    let change = (*encoded as f64 / *original as f64 - 1.0) * 100.0;
+                    (
+                        "ok",
+                        format!("{original} -> {encoded} bytes ({change:+.1}%)"),
+                    )
+                }
+                Err(err) => ("failed", err.clone()),
+            };
+            writeln!(
+                f,
+                "{status:<6}  {:<input_width$}  {:<output_width$}  {result}",
+                report.input, report.output
+            )?;
+        }
+        let processed = self.reports.iter().filter(|r| r.result.is_ok()).count();
+        writeln!(f, "{processed} of {} inputs processed", self.reports.len())
+    }
+}
+
 async fn process_single(
-    index: usize,
     input: &str,
+    file_name: &str,
     config: &Config,
-    client: &reqwest::Client,
-) -> Result<()> {
+    client: &HttpClient,
+    pool: &WorkerPool,
+) -> Result<Processed> {
     let span_start = Instant::now();
     let data = fetch_bytes(input, client).await?;
+    let original = data.len();
 
     let format = image::guess_format(&data).context("Unable to detect image format")?;
     if format != image::ImageFormat::Jpeg {
         return Err(anyhow!("{input} is not a JPEG image"));
     }
 
-    let image = tokio::task::spawn_blocking(move || image::load_from_memory(&data)).await??;
-
-    let encoded = tokio::task::spawn_blocking({
-        let quality = config.quality;
-        move || -> Result<Vec<u8>> {
-            let mut buffer = Vec::new();
-            let mut encoder = JpegEncoder::new_with_quality(&mut buffer, quality);
-            encoder
-                .write_image(
-                    image.as_bytes(),
-                    image.width(),
-                    image.height(),
-                    image.color().into(),
-                )
-                .context("Failed to encode JPEG")?;
-            Ok(buffer)
-        }
-    })
-    .await??;
+    let encoded = pool
+        .run({
+            let quality = config.quality;
+            move || -> Result<Vec<u8>> {
+                let image = image::load_from_memory(&data)?;
+                let mut buffer = Vec::new();
+                let encoder = JpegEncoder::new_with_quality(&mut buffer, quality);
+                encoder
+                    .write_image(
+                        image.as_bytes(),
+                        image.width(),
+                        image.height(),
+                        image.color().into(),
+                    )
+                    .context("Failed to encode JPEG")?;
+                Ok(buffer)
+            }
+        })
+        .await??;
 
-    let file_name = output_name(input, index);
     let destination = config.output_dir.join(file_name);
+    let processed = Processed {
+        original,
+        encoded: encoded.len(),
+    };
     tokio::fs::write(&destination, encoded)
         .await
         .with_context(|| format!("Failed to write image to {}", destination.display()))?;
+    if let Some(mtime) = config.mtime {
+        common::reproducible::set_mtime(&destination, mtime)
+            .with_context(|| format!("Failed to set mtime of {}", destination.display()))?;
+    }
 
     info!(
         target: "step3",
@@ -260,22 +398,16 @@ async fn process_single(
         span_start.elapsed()
     );
 
-    Ok(())
+    Ok(processed)
 }
 
-async fn fetch_bytes(input: &str, client: &reqwest::Client) -> Result<Vec<u8>> {
+async fn fetch_bytes(input: &str, client: &HttpClient) -> Result<Vec<u8>> {
     if let Ok(url) = Url::parse(input) {
-        let response = client
-            .get(url)
-            .send()
-            .await
-            .context("Failed to fetch URL")?
-            .error_for_status()
-            .context("Non-successful status code")?;
-        let bytes = response
-            .bytes()
-            .await
-            .context("Failed to read response body")?;
+        let bytes = retry_async(&Backoff::default(), &SystemClock, is_transient, |_| {
+            client.get_bytes(url.clone())
+        })
+        .await
+        .context("Failed to fetch URL")?;
         Ok(bytes.to_vec())
     } else {
         tokio::fs::read(input)
@@ -285,13 +417,13 @@ async fn fetch_bytes(input: &str, client: &reqwest::Client) -> Result<Vec<u8>> {
 }
 
 fn output_name(input: &str, idx: usize) -> String {
-    if let Ok(url) = Url::parse(input) {
-        if let Some(name) = url
-            .path_segments()
-            .and_then(|mut segments| segments.rev().find(|s| !s.is_empty()))
-        {
-            return normalize_name(name);
-        }
+    let url = Url::parse(input).ok();
+    if let Some(name) = url
+        .as_ref()
+        .and_then(Url::path_segments)
+        .and_then(|segments| segments.rev().find(|s| !s.is_empty()))
+    {
+        return normalize_name(name);
     }
 
     let path = Path::new(input);
@@ -302,6 +434,34 @@ fn output_name(input: &str, idx: usize) -> String {
     format!("image_{idx:04}.jpg")
 }
 
+/// Output names for sorted inputs, with clashing names suffixed by the input
+/// index (and a counter, if even that is taken) so no output overwrites
+/// another one.
+fn unique_output_names(inputs: &[String]) -> Vec<String> {
+    let mut taken = HashSet::new();
+    inputs
+        .iter()
+        .enumerate()
+        .map(|(idx, input)| {
+            let name = output_name(input, idx);
+            if taken.insert(name.clone()) {
+                return name;
+            }
+            let stem = Path::new(&name)
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or("image");
+            let mut name = format!("{stem}_{idx:04}.jpg");
+            let mut attempt = 1;
+            while !taken.insert(name.clone()) {
+                name = format!("{stem}_{idx:04}_{attempt}.jpg");
+                attempt += 1;
+            }
+            name
+        })
+        .collect()
+}
+
 fn normalize_name(name: &str) -> String {
     if name.to_ascii_lowercase().ends_with(".jpg") || name.to_ascii_lowercase().ends_with(".jpeg") {
         name.to_string()
@@ -309,3 +469,67 @@ fn normalize_name(name: &str) -> String {
         format!("{name}.jpg")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_names_never_clash() {
+        let inputs: Vec<String> = [
+            "a/photo.jpg",
+            "b/photo.jpg",
+            "b/photo_0001.jpg",
+            "c/photo.jpg",
+            "c/photo_0001.jpg",
+        ]
+        .map(String::from)
+        .into();
+        let names = unique_output_names(&inputs);
+        assert_eq!(
+            names,
+            [
+                "photo.jpg",
+                "photo_0001.jpg",
+                "photo_0001_0002.jpg",
+                "photo_0003.jpg",
+                "photo_0001_0004.jpg",
+            ]
+        );
+        assert_eq!(names.iter().collect::<HashSet<_>>().len(), inputs.len());
+
+        // The suffixed name of a clash may be taken already too
+        let inputs: Vec<String> = ["x/photo.jpg", "y/photo_0002.jpg", "z/photo.jpg"]
+            .map(String::from)
+            .into();
+        assert_eq!(
+            unique_output_names(&inputs),
+            ["photo.jpg", "photo_0002.jpg", "photo_0002_1.jpg"]
+        );
+    }
+
+    #[test]
+    fn summary_lists_inputs_in_order() {
+        let summary = Summary::new(vec![
+            Report {
+                input: "https://example.com/photos/b.jpg".into(),
+                output: "b.jpg".into(),
+                result: Err("Non-successful status code: 404 Not Found".into()),
+            },
+            Report {
+                input: "photos/a.jpeg".into(),
+                output: "a.jpeg".into(),
+                result: Ok(Processed {
+                    original: 120_000,
+                    encoded: 80_400,
+                }),
+            },
+            Report {
+                input: "c.png".into(),
+                output: "c.png.jpg".into(),
+                result: Err("c.png is not a JPEG image".into()),
+            },
+        ]);
+        insta::assert_snapshot!(summary.to_string());
+    }
+}
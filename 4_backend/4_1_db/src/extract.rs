@@ -0,0 +1,144 @@
+use std::{
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
+};
+use step_4_errors::{AppError, ensure};
+
+use crate::{Db, RoleSlug, permissions::PermissionSet};
+
+/// Statically named permission checked by [`RequirePermission`].
+pub trait Permission: Send + Sync + 'static {
+    const NAME: &'static str;
+}
+
+/// Declares a marker type implementing [`Permission`].
+#[macro_export]
+macro_rules! permission {
+    ($(#[$meta:meta])* $vis:vis $name:ident = $value:literal) => {
+        $(#[$meta])*
+        $vis struct $name;
+
+        impl $crate::extract::Permission for $name {
+            const NAME: &'static str = $value;
+        }
+    };
+}
+
+permission!(
+    /// Allows reading users and their roles.
+    pub UsersRead = "users.read"
+);
+permission!(
+    /// Allows creating, updating and deleting users.
+    pub UsersWrite = "users.write"
+);
+permission!(
+    /// Allows creating and updating roles.
+    pub RolesWrite = "roles.write"
+);
+
+/// Resolves permissions of the caller of a request.
+///
+/// Servers decide how the caller is identified (token, header, ...), while the
+/// permissions themselves come from the persisted role model, see [`RoleDb`].
+#[async_trait]
+pub trait PermissionResolver: Send + Sync {
+    async fn resolve(&self, parts: &Parts) -> Result<PermissionSet, AppError>;
+}
+
+/// Resolver handle which has to be reachable from the router state.
+pub type SharedResolver = Arc<dyn PermissionResolver>;
+
+/// Permissions of the caller, extracted without requiring any of them.
+///
+/// Use `Option<CallerPermissions>` to allow anonymous callers.
+pub struct CallerPermissions(pub PermissionSet);
+
+impl CallerPermissions {
+    /// Fails with [`AppError::Forbidden`] unless the permission is granted.
+    pub fn ensure(&self, permission: &'static str) -> Result<(), AppError> {
+        ensure!(self.0.allows(permission), Forbidden, "{permission}");
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CallerPermissions
+where
+    SharedResolver: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let resolver = SharedResolver::from_ref(state);
+        resolver.resolve(parts).await.map(Self)
+    }
+}
+
+/// Extractor rejecting requests whose caller lacks the `P` permission.
+pub struct RequirePermission<P>(pub PhantomData<P>);
+
+#[async_trait]
+impl<S, P> FromRequestParts<S> for RequirePermission<P>
+where
+    P: Permission,
+    SharedResolver: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let caller = CallerPermissions::from_request_parts(parts, state).await?;
+        caller.ensure(P::NAME)?;
+        Ok(Self(PhantomData))
+    }
+}
+
+/// Role database shared between request handlers.
+#[derive(Clone)]
+pub struct RoleDb(Arc<Mutex<Db>>);
+
+impl RoleDb {
+    /// Opens the database at `path`, creating the schema if needed.
+    pub fn open(path: &str) -> crate::Result<Self> {
+        let mut db = Db::new(path)?;
+        db.ensure_schema()?;
+        Ok(Self::from(db))
+    }
+
+    /// Gives access to the underlying database, e.g. for seeding roles.
+    pub fn with_db<T>(&self, f: impl FnOnce(&mut Db) -> T) -> T {
+        let mut db = self.0.lock().expect("role db mutex poisoned");
+        f(&mut db)
+    }
+
+    /// Resolves permissions of the user the API key was issued to, `None` if
+    /// the key is unknown.
+    pub fn permissions_for_api_key(&self, key: &str) -> Result<Option<PermissionSet>, AppError> {
+        let permissions = self.with_db(|db| match db.user_for_api_key(key)? {
+            Some(user_id) => db.permissions_for_user(user_id).map(Some),
+            None => Ok(None),
+        })?;
+        Ok(permissions)
+    }
+
+    /// Resolves permissions granted by the role.
+    ///
+    /// Missing roles are treated as granting no permissions.
+    pub fn permissions_for_role(&self, slug: &RoleSlug) -> Result<PermissionSet, AppError> {
+        Ok(self.with_db(|db| db.permissions_for_role(slug))?)
+    }
+}
+
+impl From<Db> for RoleDb {
+    fn from(db: Db) -> Self {
+        Self(Arc::new(Mutex::new(db)))
+    }
+}
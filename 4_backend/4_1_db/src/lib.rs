@@ -0,0 +1,695 @@
+use cli_common::Table;
+use rusqlite::{Connection, OptionalExtension as _, ffi, params, types::Type};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use step_4_domain::{find_duplicate_names, normalize_name};
+use step_4_errors::AppError;
+use thiserror::Error;
+
+#[cfg(feature = "axum")]
+pub mod extract;
+pub mod permissions;
+pub mod slug;
+pub mod undo;
+
+use permissions::PermissionSet;
+pub use slug::RoleSlug;
+
+/// Failure of a [`Db`] operation.
+#[derive(Debug, Error)]
+pub enum DbError {
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    /// Permissions given for a role aren't a JSON array of strings.
+    #[error("invalid permissions: {0}")]
+    InvalidPermissions(#[source] serde_json::Error),
+    #[error("role '{0}' not found")]
+    RoleNotFound(RoleSlug),
+    #[error("user with id {0} not found")]
+    UserNotFound(i64),
+    #[error("role '{0}' is assigned to users")]
+    RoleInUse(RoleSlug),
+    #[error("user {0} must keep at least one role")]
+    LastRole(i64),
+    #[error("role '{role}' is not assigned to user {user_id}")]
+    RoleNotAssigned { user_id: i64, role: RoleSlug },
+}
+
+pub type Result<T, E = DbError> = std::result::Result<T, E>;
+
+impl From<DbError> for AppError {
+    fn from(err: DbError) -> Self {
+        match err {
+            DbError::Sqlite(err) => err.into(),
+            DbError::InvalidPermissions(_) => Self::BadRequest(err.to_string()),
+            DbError::RoleNotFound(_)
+            | DbError::UserNotFound(_)
+            | DbError::RoleNotAssigned { .. } => Self::NotFound(err.to_string()),
+            DbError::RoleInUse(_) | DbError::LastRole(_) => Self::Conflict(err.to_string()),
+        }
+    }
+}
+
+/// Role as stored in the `roles` table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Role {
+    pub slug: String,
+    pub name: String,
+    /// JSON array of the granted permissions, see [`PermissionSet`].
+    pub permissions: String,
+}
+
+/// User along with its roles.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct User {
+    pub id: i64,
+    pub name: String,
+    pub email: String,
+    /// Comma-separated slugs of the roles, ordered.
+    pub roles: String,
+}
+
+/// Thin wrapper over a SQLite connection holding users, roles and their links.
+pub struct Db {
+    conn: Connection,
+}
+
+impl Db {
+    pub fn new(path: &str) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute("PRAGMA foreign_keys = ON", [])?;
+        Ok(Self { conn })
+    }
+
+    pub fn ensure_schema(&mut self) -> Result<()> {
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS roles (
+                slug TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                permissions TEXT NOT NULL
+            )",
+            [],
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS users (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                email TEXT NOT NULL UNIQUE
+            )",
+            [],
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS api_keys (
+                digest TEXT PRIMARY KEY,
+                user_id INTEGER NOT NULL,
+                FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS users_roles (
+                user_id INTEGER NOT NULL,
+                role_slug TEXT NOT NULL,
+                PRIMARY KEY(user_id, role_slug),
+                FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+                FOREIGN KEY(role_slug) REFERENCES roles(slug) ON DELETE RESTRICT
+            )",
+            [],
+        )?;
+        self.migrate_name_keys()
+    }
+
+    /// Adds the case-insensitive `users.name_key` column, backfilling it from
+    /// existing names. Fails without changes if some of them collide.
+    fn migrate_name_keys(&mut self) -> Result<()> {
+        let migrated: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('users') WHERE name = 'name_key'",
+            [],
+            |row| row.get(0),
+        )?;
+        if migrated > 0 {
+            return Ok(());
+        }
+
+        let tx = self.conn.transaction()?;
+        let users = tx
+            .prepare("SELECT id, name FROM users")?
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let duplicates = find_duplicate_names(users.iter().map(|(_, name)| name.as_str()));
+        if !duplicates.is_empty() {
+            let groups: Vec<_> = duplicates.iter().map(|group| group.join(" / ")).collect();
+            return Err(rusqlite::Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_CONSTRAINT_UNIQUE),
+                Some(format!(
+                    "user names differing only in case must be renamed first: {}",
+                    groups.join(", ")
+                )),
+            )
+            .into());
+        }
+        tx.execute("ALTER TABLE users ADD COLUMN name_key TEXT", [])?;
+        for (id, name) in &users {
+            tx.execute(
+                "UPDATE users SET name_key = ?1 WHERE id = ?2",
+                params![normalize_name(name), id],
+            )?;
+        }
+        tx.execute("CREATE UNIQUE INDEX users_name_key ON users(name_key)", [])?;
+        Ok(tx.commit()?)
+    }
+
+    pub fn create_role(&mut self, slug: &RoleSlug, name: &str, permissions: &str) -> Result<()> {
+        parse_permissions(permissions)?;
+        self.conn.execute(
+            "INSERT INTO roles (slug, name, permissions) VALUES (?1, ?2, ?3)",
+            params![slug, name, permissions],
+        )?;
+        Ok(())
+    }
+
+    /// Creates the role unless one with the `slug` exists already, returning
+    /// whether it was created.
+    pub fn seed_role(&mut self, slug: &RoleSlug, name: &str, permissions: &str) -> Result<bool> {
+        parse_permissions(permissions)?;
+        let created = self.conn.execute(
+            "INSERT OR IGNORE INTO roles (slug, name, permissions) VALUES (?1, ?2, ?3)",
+            params![slug, name, permissions],
+        )?;
+        Ok(created > 0)
+    }
+
+    pub fn update_role(
+        &mut self,
+        slug: &RoleSlug,
+        name: Option<String>,
+        permissions: Option<String>,
+    ) -> Result<()> {
+        let mut role = self
+            .role(slug)?
+            .ok_or_else(|| DbError::RoleNotFound(slug.clone()))?;
+        if let Some(new_name) = name {
+            role.name = new_name;
+        }
+        if let Some(new_perms) = permissions {
+            parse_permissions(&new_perms)?;
+            role.permissions = new_perms;
+        }
+        self.conn.execute(
+            "UPDATE roles SET name = ?1, permissions = ?2 WHERE slug = ?3",
+            params![role.name, role.permissions, slug],
+        )?;
+        Ok(())
+    }
+
+    /// Deletes the role unless some user has it.
+    pub fn delete_role(&mut self, slug: &RoleSlug) -> Result<()> {
+        let users_count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM users_roles WHERE role_slug = ?1",
+            params![slug],
+            |row| row.get(0),
+        )?;
+        if users_count > 0 {
+            return Err(DbError::RoleInUse(slug.clone()));
+        }
+        let deleted = self
+            .conn
+            .execute("DELETE FROM roles WHERE slug = ?1", params![slug])?;
+        if deleted == 0 {
+            return Err(DbError::RoleNotFound(slug.clone()));
+        }
+        Ok(())
+    }
+
+    /// All roles, ordered by slug.
+    pub fn roles(&mut self) -> Result<Vec<Role>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT slug, name, permissions FROM roles ORDER BY slug")?;
+        let roles = stmt
+            .query_map([], role_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(roles)
+    }
+
+    /// All roles as a table, ordered by slug.
+    pub fn roles_table(&mut self) -> Result<String> {
+        let table = self.roles()?.into_iter().fold(
+            Table::new(vec!["SLUG", "NAME", "PERMISSIONS"]),
+            |table, role| table.row(vec![role.slug, role.name, role.permissions]),
+        );
+        Ok(table.to_string())
+    }
+
+    pub fn role(&mut self, slug: &RoleSlug) -> Result<Option<Role>> {
+        let role = self
+            .conn
+            .query_row(
+                "SELECT slug, name, permissions FROM roles WHERE slug = ?1",
+                params![slug],
+                role_from_row,
+            )
+            .optional()?;
+        Ok(role)
+    }
+
+    /// Creates a user with the role, returning its id.
+    pub fn create_user(&mut self, name: &str, email: &str, role: &RoleSlug) -> Result<i64> {
+        self.ensure_role_exists(role)?;
+        self.conn.execute(
+            "INSERT INTO users (name, name_key, email) VALUES (?1, ?2, ?3)",
+            params![name, normalize_name(name), email],
+        )?;
+        let user_id = self.conn.last_insert_rowid();
+        self.assign_role(user_id, role)?;
+        Ok(user_id)
+    }
+
+    pub fn update_user(
+        &mut self,
+        id: i64,
+        name: Option<String>,
+        email: Option<String>,
+    ) -> Result<()> {
+        let mut user = self.user(id)?.ok_or(DbError::UserNotFound(id))?;
+        if let Some(new_name) = name {
+            user.name = new_name;
+        }
+        if let Some(new_email) = email {
+            user.email = new_email;
+        }
+        self.conn.execute(
+            "UPDATE users SET name = ?1, name_key = ?2, email = ?3 WHERE id = ?4",
+            params![user.name, normalize_name(&user.name), user.email, id],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_user(&mut self, id: i64) -> Result<()> {
+        let deleted = self
+            .conn
+            .execute("DELETE FROM users WHERE id = ?1", params![id])?;
+        if deleted == 0 {
+            return Err(DbError::UserNotFound(id));
+        }
+        Ok(())
+    }
+
+    /// Issues a new API key identifying the user, see
+    /// [`Db::user_for_api_key`].
+    ///
+    /// Only a digest of the key is stored, so it can't be shown again.
+    pub fn issue_api_key(&mut self, user_id: i64) -> Result<String> {
+        self.ensure_user_exists(user_id)?;
+        let key: String = rand::random::<[u8; 32]>()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        self.conn.execute(
+            "INSERT INTO api_keys (digest, user_id) VALUES (?1, ?2)",
+            params![api_key_digest(&key), user_id],
+        )?;
+        Ok(key)
+    }
+
+    /// Id of the user the API key was issued to, `None` if it's unknown.
+    pub fn user_for_api_key(&mut self, key: &str) -> Result<Option<i64>> {
+        let user_id = self
+            .conn
+            .query_row(
+                "SELECT user_id FROM api_keys WHERE digest = ?1",
+                params![api_key_digest(key)],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(user_id)
+    }
+
+    pub fn assign_role(&mut self, user_id: i64, role: &RoleSlug) -> Result<()> {
+        self.ensure_role_exists(role)?;
+        self.ensure_user_exists(user_id)?;
+        self.conn.execute(
+            "INSERT OR IGNORE INTO users_roles (user_id, role_slug) VALUES (?1, ?2)",
+            params![user_id, role],
+        )?;
+        Ok(())
+    }
+
+    pub fn unassign_role(&mut self, user_id: i64, role: &RoleSlug) -> Result<()> {
+        self.ensure_user_exists(user_id)?;
+        let role_count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM users_roles WHERE user_id = ?1",
+            params![user_id],
+            |row| row.get(0),
+        )?;
+        if role_count <= 1 {
+            return Err(DbError::LastRole(user_id));
+        }
+        let removed = self.conn.execute(
+            "DELETE FROM users_roles WHERE user_id = ?1 AND role_slug = ?2",
+            params![user_id, role],
+        )?;
+        if removed == 0 {
+            return Err(DbError::RoleNotAssigned {
+                user_id,
+                role: role.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// All users with their roles, ordered by id.
+    pub fn users(&mut self) -> Result<Vec<User>> {
+        let users = {
+            let mut stmt = self
+                .conn
+                .prepare("SELECT id, name, email FROM users ORDER BY id")?;
+            stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+        };
+        users
+            .into_iter()
+            .map(|(id, name, email)| {
+                Ok(User {
+                    roles: self.roles_for_user(id)?,
+                    id,
+                    name,
+                    email,
+                })
+            })
+            .collect()
+    }
+
+    /// All users with their roles as a table, ordered by id.
+    pub fn users_table(&mut self) -> Result<String> {
+        let table = self.users()?.into_iter().fold(
+            Table::new(vec!["ID", "NAME", "EMAIL", "ROLES"]),
+            |table, user| table.row(vec![user.id.to_string(), user.name, user.email, user.roles]),
+        );
+        Ok(table.to_string())
+    }
+
+    pub fn user(&mut self, id: i64) -> Result<Option<User>> {
+        let user = self
+            .conn
+            .query_row(
+                "SELECT name, email FROM users WHERE id = ?1",
+                params![id],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+            )
+            .optional()?;
+        let Some((name, email)) = user else {
+            return Ok(None);
+        };
+        Ok(Some(User {
+            roles: self.roles_for_user(id)?,
+            id,
+            name,
+            email,
+        }))
+    }
+
+    pub fn roles_for_user(&mut self, user_id: i64) -> Result<String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT role_slug FROM users_roles WHERE user_id = ?1 ORDER BY role_slug")?;
+        let roles = stmt
+            .query_map(params![user_id], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(roles.join(","))
+    }
+
+    /// Collects permissions of every role assigned to the user.
+    pub fn permissions_for_user(&mut self, user_id: i64) -> Result<PermissionSet> {
+        self.collect_permissions(
+            "SELECT r.permissions FROM roles r
+             JOIN users_roles ur ON ur.role_slug = r.slug
+             WHERE ur.user_id = ?1",
+            params![user_id],
+        )
+    }
+
+    /// Same as [`Db::permissions_for_user`], but looks the user up by name,
+    /// ignoring its case.
+    pub fn permissions_for_name(&mut self, name: &str) -> Result<PermissionSet> {
+        self.collect_permissions(
+            "SELECT r.permissions FROM roles r
+             JOIN users_roles ur ON ur.role_slug = r.slug
+             JOIN users u ON u.id = ur.user_id
+             WHERE u.name_key = ?1",
+            params![normalize_name(name)],
+        )
+    }
+
+    /// Permissions granted by the role, none if there is no such role.
+    pub fn permissions_for_role(&mut self, slug: &RoleSlug) -> Result<PermissionSet> {
+        self.collect_permissions(
+            "SELECT permissions FROM roles WHERE slug = ?1",
+            params![slug],
+        )
+    }
+
+    fn collect_permissions(
+        &mut self,
+        query: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<PermissionSet> {
+        let mut stmt = self.conn.prepare(query)?;
+        let mut set = PermissionSet::default();
+        for raw in stmt.query_map(params, |row| row.get::<_, String>(0))? {
+            // A stored value failing to parse is a corrupt `permissions`
+            // column, the only one queried.
+            let parsed = PermissionSet::parse(&raw?).map_err(|err| {
+                rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(err))
+            })?;
+            set.merge(parsed);
+        }
+        Ok(set)
+    }
+
+    fn ensure_role_exists(&mut self, slug: &RoleSlug) -> Result<()> {
+        let exists: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM roles WHERE slug = ?1",
+            params![slug],
+            |row| row.get(0),
+        )?;
+        if exists == 0 {
+            return Err(DbError::RoleNotFound(slug.clone()));
+        }
+        Ok(())
+    }
+
+    fn ensure_user_exists(&mut self, id: i64) -> Result<()> {
+        let exists: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM users WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )?;
+        if exists == 0 {
+            return Err(DbError::UserNotFound(id));
+        }
+        Ok(())
+    }
+}
+
+/// Validates permissions given for a role.
+fn parse_permissions(raw: &str) -> Result<PermissionSet> {
+    PermissionSet::parse(raw).map_err(DbError::InvalidPermissions)
+}
+
+fn role_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Role> {
+    Ok(Role {
+        slug: row.get(0)?,
+        name: row.get(1)?,
+        permissions: row.get(2)?,
+    })
+}
+
+/// Digest an API key is stored under. Keys are random, so a fast hash
+/// without salt is enough.
+fn api_key_digest(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slug(raw: &str) -> RoleSlug {
+        raw.parse().unwrap()
+    }
+
+    #[test]
+    fn manages_users_and_roles() -> Result<()> {
+        let mut db = Db::new(":memory:")?;
+        db.ensure_schema()?;
+
+        db.create_role(&slug("admin"), "Administrator", "[\"all\"]")?;
+        db.create_role(&slug("viewer"), "Viewer", "[]")?;
+        let alice_id = db.create_user("Alice", "alice@example.com", &slug("admin"))?;
+
+        db.assign_role(alice_id, &slug("viewer"))?;
+        assert_eq!(db.roles_for_user(alice_id)?, "admin,viewer");
+
+        db.unassign_role(alice_id, &slug("viewer"))?;
+        assert_eq!(db.roles_for_user(alice_id)?, "admin");
+
+        assert!(matches!(
+            db.unassign_role(alice_id, &slug("admin")),
+            Err(DbError::LastRole(_))
+        ));
+        assert_eq!(db.roles_for_user(alice_id)?, "admin");
+
+        assert!(matches!(
+            db.delete_role(&slug("admin")),
+            Err(DbError::RoleInUse(_))
+        ));
+        assert!(db.role(&slug("admin"))?.is_some());
+        db.delete_role(&slug("viewer"))?;
+        assert!(matches!(
+            db.delete_role(&slug("viewer")),
+            Err(DbError::RoleNotFound(_))
+        ));
+
+        db.update_user(alice_id, None, Some("alice@example.org".into()))?;
+        let alice = db.user(alice_id)?.expect("alice exists");
+        assert_eq!(
+            (alice.email.as_str(), alice.roles.as_str()),
+            ("alice@example.org", "admin")
+        );
+        db.delete_user(alice_id)?;
+        assert!(matches!(
+            db.update_user(alice_id, None, None),
+            Err(DbError::UserNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn resolves_permissions_from_roles() -> Result<()> {
+        let mut db = Db::new(":memory:")?;
+        db.ensure_schema()?;
+
+        db.create_role(
+            &slug("editor"),
+            "Editor",
+            r#"["users.read", "users.write"]"#,
+        )?;
+        db.create_role(&slug("auditor"), "Auditor", r#"["roles.*"]"#)?;
+        db.create_user("Bob", "bob@example.com", &slug("editor"))?;
+        let broken = db.create_role(&slug("broken"), "Broken", "users.read");
+        assert!(matches!(broken, Err(DbError::InvalidPermissions(_))));
+        assert!(matches!(
+            AppError::from(broken.unwrap_err()),
+            AppError::BadRequest(_)
+        ));
+
+        let bob = db.permissions_for_name("Bob")?;
+        assert_eq!(db.permissions_for_name("BOB")?, bob);
+        assert!(bob.allows("users.write"));
+        assert!(!bob.allows("roles.write"));
+
+        let bob_id: i64 =
+            db.conn
+                .query_row("SELECT id FROM users WHERE name = 'Bob'", [], |row| {
+                    row.get(0)
+                })?;
+        db.assign_role(bob_id, &slug("auditor"))?;
+        assert!(db.permissions_for_user(bob_id)?.allows("roles.write"));
+
+        assert!(db.permissions_for_name("nobody")?.is_empty());
+
+        assert!(
+            db.permissions_for_role(&slug("auditor"))?
+                .allows("roles.write")
+        );
+        assert!(db.permissions_for_role(&slug("nobody"))?.is_empty());
+        assert!(!db.seed_role(&slug("auditor"), "Overwritten", "[]")?);
+        assert!(
+            db.permissions_for_role(&slug("auditor"))?
+                .allows("roles.write")
+        );
+        assert!(db.seed_role(&slug("viewer"), "Viewer", r#"["users.read"]"#)?);
+        assert!(
+            db.permissions_for_role(&slug("viewer"))?
+                .allows("users.read")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn identifies_users_by_api_keys() -> Result<()> {
+        let mut db = Db::new(":memory:")?;
+        db.ensure_schema()?;
+        db.create_role(&slug("viewer"), "Viewer", "[]")?;
+        let alice = db.create_user("Alice", "alice@example.com", &slug("viewer"))?;
+
+        let key = db.issue_api_key(alice)?;
+        assert_eq!(key.len(), 64);
+        assert_ne!(db.issue_api_key(alice)?, key);
+        assert_eq!(db.user_for_api_key(&key)?, Some(alice));
+        assert_eq!(db.user_for_api_key("alice")?, None);
+        assert!(matches!(
+            db.issue_api_key(42),
+            Err(DbError::UserNotFound(42))
+        ));
+
+        db.delete_user(alice)?;
+        assert_eq!(db.user_for_api_key(&key)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn migrates_name_keys_rejecting_duplicates() -> Result<()> {
+        let mut db = Db::new(":memory:")?;
+        db.conn.execute(
+            "CREATE TABLE users (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                email TEXT NOT NULL UNIQUE
+            )",
+            [],
+        )?;
+        db.conn.execute(
+            "INSERT INTO users (name, email) VALUES
+                ('Alice', 'a1@example.com'), ('alice', 'a2@example.com')",
+            [],
+        )?;
+        let err = db.ensure_schema().unwrap_err();
+        assert!(err.to_string().contains("Alice / alice"), "{err}");
+
+        db.conn
+            .execute("UPDATE users SET name = 'alice2' WHERE name = 'alice'", [])?;
+        db.ensure_schema()?;
+        db.ensure_schema()?;
+        db.create_role(&slug("viewer"), "Viewer", "[]")?;
+        assert!(
+            db.create_user("ALICE", "a3@example.com", &slug("viewer"))
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn lists_roles_and_users_as_tables() -> Result<()> {
+        let mut db = Db::new(":memory:")?;
+        db.ensure_schema()?;
+        db.create_role(&slug("admin"), "Administrator", r#"["users.*"]"#)?;
+        db.create_role(&slug("viewer"), "Viewer", "[]")?;
+        db.create_user("Alice", "alice@example.com", &slug("admin"))?;
+        db.create_user("Bob", "bob@example.com", &slug("viewer"))?;
+        db.assign_role(1, &slug("viewer"))?;
+
+        insta::assert_snapshot!("roles", db.roles_table()?);
+        insta::assert_snapshot!("users", db.users_table()?);
+        Ok(())
+    }
+}
@@ -1,5 +1,11 @@
-use clap::{Parser, Subcommand};
-use rusqlite::{Connection, Result, params};
+use std::io::{self, BufRead, Write};
+
+use clap::{CommandFactory, Parser, Subcommand, error::ErrorKind};
+use cli_common::{CommonArgs, Format};
+use serde::Serialize;
+use serde_json::json;
+use step_4_1::{Db, DbError, Result, RoleSlug, undo::History};
+use tracing::debug;
 
 #[derive(Parser)]
 #[command(
@@ -12,8 +18,12 @@ struct Cli {
     #[arg(long, default_value = "roles.sqlite")]
     database: String,
 
+    #[command(flatten)]
+    common: CommonArgs,
+
+    // Optional only so that `--completions` works on its own
     #[command(subcommand)]
-    command: Command,
+    command: Option<Command>,
 }
 
 #[derive(Subcommand)]
@@ -21,7 +31,7 @@ enum Command {
     /// Create a new role
     CreateRole {
         #[arg(long)]
-        slug: String,
+        slug: RoleSlug,
         #[arg(long)]
         name: String,
         #[arg(long, default_value = "[]")]
@@ -30,7 +40,7 @@ enum Command {
     /// Update role name or permissions
     UpdateRole {
         #[arg(long)]
-        slug: String,
+        slug: RoleSlug,
         #[arg(long)]
         name: Option<String>,
         #[arg(long)]
@@ -39,14 +49,14 @@ enum Command {
     /// Delete a role if no users rely on it
     DeleteRole {
         #[arg(long)]
-        slug: String,
+        slug: RoleSlug,
     },
     /// List all roles
     ListRoles,
     /// Show a single role
     GetRole {
         #[arg(long)]
-        slug: String,
+        slug: RoleSlug,
     },
     /// Create a new user and assign role
     CreateUser {
@@ -55,7 +65,7 @@ enum Command {
         #[arg(long)]
         email: String,
         #[arg(long)]
-        role: String,
+        role: RoleSlug,
     },
     /// Update user name or email
     UpdateUser {
@@ -76,14 +86,14 @@ enum Command {
         #[arg(long)]
         user_id: i64,
         #[arg(long)]
-        role: String,
+        role: RoleSlug,
     },
     /// Remove role from user (requires user to keep at least one role)
     UnassignRole {
         #[arg(long)]
         user_id: i64,
         #[arg(long)]
-        role: String,
+        role: RoleSlug,
     },
     /// List all users with their roles
     ListUsers,
@@ -92,362 +102,187 @@ enum Command {
         #[arg(long)]
         id: i64,
     },
+    /// Issue an API key identifying the user to the HTTP servers, shown only
+    /// once
+    IssueApiKey {
+        #[arg(long)]
+        user_id: i64,
+    },
+    /// Read commands from STDIN, with `undo` and `redo` of the changes made
+    /// in the session
+    Repl,
+}
+
+/// Single line of the REPL.
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct ReplLine {
+    #[command(subcommand)]
+    command: Command,
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    if cli.common.print_completions(&mut Cli::command()) {
+        return Ok(());
+    }
+    let Some(command) = cli.command else {
+        Cli::command()
+            .error(ErrorKind::MissingSubcommand, "a subcommand is required")
+            .exit();
+    };
+    cli.common.init_tracing("warn");
+
+    debug!("opening database {}", cli.database);
     let mut db = Db::new(&cli.database)?;
     db.ensure_schema()?;
 
-    match cli.command {
+    let format = cli.common.format;
+    match command {
+        Command::Repl => repl(&mut db, format),
+        command => run(&mut db, command, format),
+    }
+}
+
+/// Runs the `command`, printing its results in the `format`.
+fn run(db: &mut Db, command: Command, format: Format) -> Result<()> {
+    match command {
         Command::CreateRole {
             slug,
             name,
             permissions,
-        } => db.create_role(&slug, &name, &permissions)?,
+        } => {
+            db.create_role(&slug, &name, &permissions)?;
+            report(format, format!("Role '{slug}' created."));
+        }
         Command::UpdateRole {
             slug,
             name,
             permissions,
-        } => db.update_role(&slug, name, permissions)?,
-        Command::DeleteRole { slug } => db.delete_role(&slug)?,
-        Command::ListRoles => db.list_roles()?,
-        Command::GetRole { slug } => db.get_role(&slug)?,
-        Command::CreateUser { name, email, role } => db.create_user(&name, &email, &role)?,
-        Command::UpdateUser { id, name, email } => db.update_user(id, name, email)?,
-        Command::DeleteUser { id } => db.delete_user(id)?,
-        Command::AssignRole { user_id, role } => db.assign_role(user_id, &role)?,
-        Command::UnassignRole { user_id, role } => db.unassign_role(user_id, &role)?,
-        Command::ListUsers => db.list_users()?,
-        Command::GetUser { id } => db.get_user(id)?,
-    }
-
-    Ok(())
-}
-
-struct Db {
-    conn: Connection,
-}
-
-impl Db {
-    fn new(path: &str) -> Result<Self> {
-        let conn = Connection::open(path)?;
-        conn.execute("PRAGMA foreign_keys = ON", [])?;
-        Ok(Self { conn })
-    }
-
-    fn ensure_schema(&mut self) -> Result<()> {
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS roles (
-                slug TEXT PRIMARY KEY,
-                name TEXT NOT NULL,
-                permissions TEXT NOT NULL
-            )",
-            [],
-        )?;
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS users (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                name TEXT NOT NULL UNIQUE,
-                email TEXT NOT NULL UNIQUE
-            )",
-            [],
-        )?;
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS users_roles (
-                user_id INTEGER NOT NULL,
-                role_slug TEXT NOT NULL,
-                PRIMARY KEY(user_id, role_slug),
-                FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
-                FOREIGN KEY(role_slug) REFERENCES roles(slug) ON DELETE RESTRICT
-            )",
-            [],
-        )?;
-        Ok(())
-    }
-
-    fn create_role(&mut self, slug: &str, name: &str, permissions: &str) -> Result<()> {
-        self.conn.execute(
-            "INSERT INTO roles (slug, name, permissions) VALUES (?1, ?2, ?3)",
-            params![slug, name, permissions],
-        )?;
-        println!("Role '{slug}' created.");
-        Ok(())
-    }
-
-    fn update_role(
-        &mut self,
-        slug: &str,
-        name: Option<String>,
-        permissions: Option<String>,
-    ) -> Result<()> {
-        let mut role = self.conn.query_row(
-            "SELECT name, permissions FROM roles WHERE slug = ?1",
-            params![slug],
-            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
-        )?;
-        if let Some(new_name) = name {
-            role.0 = new_name;
-        }
-        if let Some(new_perms) = permissions {
-            role.1 = new_perms;
-        }
-        self.conn.execute(
-            "UPDATE roles SET name = ?1, permissions = ?2 WHERE slug = ?3",
-            params![role.0, role.1, slug],
-        )?;
-        println!("Role '{slug}' updated.");
-        Ok(())
-    }
-
-    fn delete_role(&mut self, slug: &str) -> Result<()> {
-        let users_count: i64 = self.conn.query_row(
-            "SELECT COUNT(*) FROM users_roles WHERE role_slug = ?1",
-            params![slug],
-            |row| row.get(0),
-        )?;
-        if users_count > 0 {
-            println!("Cannot delete role '{slug}' while it is assigned to users.");
-            return Ok(());
+        } => {
+            db.update_role(&slug, name, permissions)?;
+            report(format, format!("Role '{slug}' updated."));
         }
-        let deleted = self
-            .conn
-            .execute("DELETE FROM roles WHERE slug = ?1", params![slug])?;
-        if deleted == 0 {
-            println!("Role '{slug}' not found.");
-        } else {
-            println!("Role '{slug}' deleted.");
+        Command::DeleteRole { slug } => {
+            db.delete_role(&slug)?;
+            report(format, format!("Role '{slug}' deleted."));
         }
-        Ok(())
-    }
-
-    fn list_roles(&mut self) -> Result<()> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT slug, name, permissions FROM roles ORDER BY slug")?;
-        let rows = stmt.query_map([], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-            ))
-        })?;
-        for row in rows {
-            let (slug, name, perms) = row?;
-            println!("{slug}: {name} | permissions={perms}");
-        }
-        Ok(())
-    }
-
-    fn get_role(&mut self, slug: &str) -> Result<()> {
-        let role = self.conn.query_row(
-            "SELECT slug, name, permissions FROM roles WHERE slug = ?1",
-            params![slug],
-            |row| {
-                Ok((
-                    row.get::<_, String>(0)?,
-                    row.get::<_, String>(1)?,
-                    row.get::<_, String>(2)?,
-                ))
-            },
-        );
-        match role {
-            Ok((slug, name, perms)) => println!("{slug}: {name} | permissions={perms}"),
-            Err(_) => println!("Role '{slug}' not found."),
+        Command::ListRoles => match format {
+            Format::Text => println!("{}", db.roles_table()?),
+            Format::Json => print(format, "", &db.roles()?),
+        },
+        Command::GetRole { slug } => {
+            let role = db.role(&slug)?.ok_or(DbError::RoleNotFound(slug))?;
+            let text = format!(
+                "{}: {} | permissions={}",
+                role.slug, role.name, role.permissions
+            );
+            print(format, &text, &role);
         }
-        Ok(())
-    }
-
-    fn create_user(&mut self, name: &str, email: &str, role: &str) -> Result<()> {
-        self.ensure_role_exists(role)?;
-        self.conn.execute(
-            "INSERT INTO users (name, email) VALUES (?1, ?2)",
-            params![name, email],
-        )?;
-        let user_id = self.conn.last_insert_rowid();
-        self.assign_role(user_id, role)?;
-        println!("User '{name}' created with id {user_id}.");
-        Ok(())
-    }
-
-    fn update_user(&mut self, id: i64, name: Option<String>, email: Option<String>) -> Result<()> {
-        let user = self.conn.query_row(
-            "SELECT name, email FROM users WHERE id = ?1",
-            params![id],
-            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
-        );
-        let Ok(mut existing) = user else {
-            println!("User with id {id} not found.");
-            return Ok(());
-        };
-        if let Some(new_name) = name {
-            existing.0 = new_name;
+        Command::CreateUser { name, email, role } => {
+            let id = db.create_user(&name, &email, &role)?;
+            let text = format!("User '{name}' created with id {id}.");
+            print(format, &text, &json!({ "id": id }));
         }
-        if let Some(new_email) = email {
-            existing.1 = new_email;
+        Command::UpdateUser { id, name, email } => {
+            db.update_user(id, name, email)?;
+            report(format, format!("User {id} updated."));
         }
-        self.conn.execute(
-            "UPDATE users SET name = ?1, email = ?2 WHERE id = ?3",
-            params![existing.0, existing.1, id],
-        )?;
-        println!("User {id} updated.");
-        Ok(())
-    }
-
-    fn delete_user(&mut self, id: i64) -> Result<()> {
-        let deleted = self
-            .conn
-            .execute("DELETE FROM users WHERE id = ?1", params![id])?;
-        if deleted == 0 {
-            println!("User with id {id} not found.");
-        } else {
-            println!("User {id} deleted.");
+        Command::DeleteUser { id } => {
+            db.delete_user(id)?;
+            report(format, format!("User {id} deleted."));
         }
-        Ok(())
-    }
-
-    fn assign_role(&mut self, user_id: i64, role: &str) -> Result<()> {
-        self.ensure_role_exists(role)?;
-        self.ensure_user_exists(user_id)?;
-        self.conn.execute(
-            "INSERT OR IGNORE INTO users_roles (user_id, role_slug) VALUES (?1, ?2)",
-            params![user_id, role],
-        )?;
-        println!("Assigned role '{role}' to user {user_id}.");
-        Ok(())
-    }
-
-    fn unassign_role(&mut self, user_id: i64, role: &str) -> Result<()> {
-        self.ensure_user_exists(user_id)?;
-        let role_count: i64 = self.conn.query_row(
-            "SELECT COUNT(*) FROM users_roles WHERE user_id = ?1",
-            params![user_id],
-            |row| row.get(0),
-        )?;
-        if role_count <= 1 {
-            println!("User {user_id} must keep at least one role.");
-            return Ok(());
+        Command::AssignRole { user_id, role } => {
+            db.assign_role(user_id, &role)?;
+            report(format, format!("Assigned role '{role}' to user {user_id}."));
         }
-        let removed = self.conn.execute(
-            "DELETE FROM users_roles WHERE user_id = ?1 AND role_slug = ?2",
-            params![user_id, role],
-        )?;
-        if removed == 0 {
-            println!("Role '{role}' not assigned to user {user_id}.");
-        } else {
-            println!("Removed role '{role}' from user {user_id}.");
+        Command::UnassignRole { user_id, role } => {
+            db.unassign_role(user_id, &role)?;
+            report(
+                format,
+                format!("Removed role '{role}' from user {user_id}."),
+            );
         }
-        Ok(())
-    }
-
-    fn list_users(&mut self) -> Result<()> {
-        let rows = {
-            let mut stmt = self
-                .conn
-                .prepare("SELECT id, name, email FROM users ORDER BY id")?;
-            stmt.query_map([], |row| {
-                Ok((
-                    row.get::<_, i64>(0)?,
-                    row.get::<_, String>(1)?,
-                    row.get::<_, String>(2)?,
-                ))
-            })?
-            .collect::<Result<Vec<_>, _>>()?
-        };
-        for (id, name, email) in rows {
-            let roles = self.roles_for_user(id)?;
-            println!("{id}: {name} <{email}> | roles={roles}");
+        Command::ListUsers => match format {
+            Format::Text => println!("{}", db.users_table()?),
+            Format::Json => print(format, "", &db.users()?),
+        },
+        Command::GetUser { id } => {
+            let user = db.user(id)?.ok_or(DbError::UserNotFound(id))?;
+            let text = format!(
+                "{id}: {} <{}> | roles={}",
+                user.name, user.email, user.roles
+            );
+            print(format, &text, &user);
         }
-        Ok(())
-    }
-
-    fn get_user(&mut self, id: i64) -> Result<()> {
-        let user = self.conn.query_row(
-            "SELECT name, email FROM users WHERE id = ?1",
-            params![id],
-            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
-        );
-        match user {
-            Ok((name, email)) => {
-                let roles = self.roles_for_user(id)?;
-                println!("{id}: {name} <{email}> | roles={roles}");
-            }
-            Err(_) => println!("User with id {id} not found."),
+        Command::IssueApiKey { user_id } => {
+            let key = db.issue_api_key(user_id)?;
+            print(format, &key, &json!({ "api_key": key }));
         }
-        Ok(())
+        Command::Repl => println!("Already in the REPL."),
     }
 
-    fn roles_for_user(&mut self, user_id: i64) -> Result<String> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT role_slug FROM users_roles WHERE user_id = ?1 ORDER BY role_slug")?;
-        let roles = stmt
-            .query_map(params![user_id], |row| row.get::<_, String>(0))?
-            .collect::<Result<Vec<_>, _>>()?;
-        Ok(roles.join(","))
-    }
-
-    fn ensure_role_exists(&mut self, slug: &str) -> Result<()> {
-        let exists: i64 = self.conn.query_row(
-            "SELECT COUNT(*) FROM roles WHERE slug = ?1",
-            params![slug],
-            |row| row.get(0),
-        )?;
-        if exists == 0 {
-            return Err(rusqlite::Error::QueryReturnedNoRows);
-        }
-        Ok(())
-    }
+    Ok(())
+}
 
-    fn ensure_user_exists(&mut self, id: i64) -> Result<()> {
-        let exists: i64 = self.conn.query_row(
-            "SELECT COUNT(*) FROM users WHERE id = ?1",
-            params![id],
-            |row| row.get(0),
-        )?;
-        if exists == 0 {
-            return Err(rusqlite::Error::QueryReturnedNoRows);
-        }
-        Ok(())
+/// Prints a result of a command: the `text` for people or the `value` as
+/// JSON.
+fn print(format: Format, text: &str, value: &impl Serialize) {
+    match format {
+        Format::Text => println!("{}", text.trim_end()),
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string(value).expect("results serialize to JSON")
+        ),
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn manages_users_and_roles() -> Result<()> {
-        let mut db = Db::new(":memory:")?;
-        db.ensure_schema()?;
-
-        db.create_role("admin", "Administrator", "[\"all\"]")?;
-        db.create_role("viewer", "Viewer", "[]")?;
-        db.create_user("Alice", "alice@example.com", "admin")?;
-
-        let alice_id: i64 =
-            db.conn
-                .query_row("SELECT id FROM users WHERE name = 'Alice'", [], |row| {
-                    row.get(0)
-                })?;
-
-        db.assign_role(alice_id, "viewer")?;
-        assert_eq!(db.roles_for_user(alice_id)?, "admin,viewer");
-
-        db.unassign_role(alice_id, "viewer")?;
-        assert_eq!(db.roles_for_user(alice_id)?, "admin");
-
-        db.unassign_role(alice_id, "admin")?;
-        assert_eq!(db.roles_for_user(alice_id)?, "admin");
-
-        db.delete_role("admin")?;
-        let remaining: i64 = db.conn.query_row(
-            "SELECT COUNT(*) FROM roles WHERE slug = 'admin'",
-            [],
-            |row| row.get(0),
-        )?;
-        assert_eq!(remaining, 1);
+/// Prints the confirmation of a change.
+fn report(format: Format, message: String) {
+    print(format, &message, &json!({ "message": message }));
+}
 
-        Ok(())
+/// Runs the commands read from STDIN until `exit` or its end.
+///
+/// Every command runs in a transaction, so `undo` reverts all of its changes.
+fn repl(db: &mut Db, format: Format) -> Result<()> {
+    let mut history = History::start(db)?;
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        let Some(Ok(line)) = lines.next() else {
+            return Ok(());
+        };
+        let line = line.trim();
+        let result = match line {
+            "" => continue,
+            "exit" | "quit" => return Ok(()),
+            "undo" => history.undo(db).map(|step| match step {
+                Some(label) => println!("Undone: {label}"),
+                None => println!("Nothing to undo."),
+            }),
+            "redo" => history.redo(db).map(|step| match step {
+                Some(label) => println!("Redone: {label}"),
+                None => println!("Nothing to redo."),
+            }),
+            _ => {
+                let Some(words) = shlex::split(line) else {
+                    eprintln!("Unbalanced quotes.");
+                    continue;
+                };
+                match ReplLine::try_parse_from(words) {
+                    Ok(parsed) => history.record(db, line, |db| run(db, parsed.command, format)),
+                    Err(err) => {
+                        // Also prints the help requested with `help`
+                        let _ = err.print();
+                        continue;
+                    }
+                }
+            }
+        };
+        if let Err(err) = result {
+            eprintln!("Error: {err}");
+        }
     }
 }
@@ -0,0 +1,71 @@
+use std::collections::BTreeSet;
+
+/// Permission granting every other permission.
+pub const WILDCARD: &str = "all";
+
+/// Set of permissions granted to a user through its roles.
+///
+/// Roles store their permissions as a JSON array of strings (e.g.
+/// `["users.read", "users.write"]`). Besides exact matches, `"all"` grants
+/// everything and `"users.*"` grants every permission in the `users` scope.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PermissionSet(BTreeSet<String>);
+
+impl PermissionSet {
+    /// Parses permissions in the format they are stored in the `roles` table.
+    pub fn parse(raw: &str) -> Result<Self, serde_json::Error> {
+        let list: Vec<String> = serde_json::from_str(raw)?;
+        Ok(list.into_iter().collect())
+    }
+
+    /// Checks whether this set grants the given permission.
+    pub fn allows(&self, permission: &str) -> bool {
+        if self.0.contains(WILDCARD) || self.0.contains(permission) {
+            return true;
+        }
+        self.0.iter().any(|granted| {
+            granted
+                .strip_suffix(".*")
+                .and_then(|scope| permission.strip_prefix(scope))
+                .is_some_and(|rest| rest.starts_with('.'))
+        })
+    }
+
+    /// Merges permissions of another role into this set.
+    pub fn merge(&mut self, other: PermissionSet) {
+        self.0.extend(other.0);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+}
+
+impl FromIterator<String> for PermissionSet {
+    fn from_iter<I: IntoIterator<Item = String>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_exact_scoped_and_wildcard_permissions() {
+        let set = PermissionSet::parse(r#"["users.read", "roles.*"]"#).unwrap();
+        assert!(set.allows("users.read"));
+        assert!(!set.allows("users.write"));
+        assert!(set.allows("roles.write"));
+        assert!(!set.allows("rolesx.write"));
+
+        let all = PermissionSet::parse(r#"["all"]"#).unwrap();
+        assert!(all.allows("users.write"));
+
+        assert!(PermissionSet::parse("users.read").is_err());
+    }
+}
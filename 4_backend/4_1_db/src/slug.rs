@@ -0,0 +1,121 @@
+use std::{borrow::Borrow, fmt, str::FromStr};
+
+use rusqlite::types::{ToSql, ToSqlOutput};
+use serde::{Deserialize, Serialize};
+
+/// Maximum length of a [`RoleSlug`].
+pub const MAX_LEN: usize = 64;
+
+/// Identifier of a role in lowercase kebab-case, e.g. `content-editor`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RoleSlug(String);
+
+impl RoleSlug {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Error of parsing a [`RoleSlug`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidRoleSlug(String);
+
+impl fmt::Display for InvalidRoleSlug {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid role slug `{}`: expected up to {MAX_LEN} lowercase latin letters \
+             and digits separated by single dashes",
+            self.0,
+        )
+    }
+}
+
+impl std::error::Error for InvalidRoleSlug {}
+
+impl TryFrom<String> for RoleSlug {
+    type Error = InvalidRoleSlug;
+
+    fn try_from(raw: String) -> Result<Self, Self::Error> {
+        let valid = !raw.is_empty()
+            && raw.len() <= MAX_LEN
+            && raw.split('-').all(|word| {
+                !word.is_empty()
+                    && word
+                        .bytes()
+                        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+            });
+        if valid {
+            Ok(Self(raw))
+        } else {
+            Err(InvalidRoleSlug(raw))
+        }
+    }
+}
+
+impl FromStr for RoleSlug {
+    type Err = InvalidRoleSlug;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        raw.to_owned().try_into()
+    }
+}
+
+impl From<RoleSlug> for String {
+    fn from(slug: RoleSlug) -> Self {
+        slug.0
+    }
+}
+
+impl AsRef<str> for RoleSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for RoleSlug {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RoleSlug {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl ToSql for RoleSlug {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        self.0.to_sql()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_only_lowercase_kebab_case() {
+        for valid in ["admin", "content-editor", "tier-2-support"] {
+            assert_eq!(valid.parse::<RoleSlug>().unwrap().as_str(), valid);
+        }
+        for invalid in [
+            "",
+            "Admin",
+            "content_editor",
+            "-admin",
+            "admin-",
+            "a--b",
+            "ролевая",
+        ] {
+            assert!(invalid.parse::<RoleSlug>().is_err(), "{invalid:?}");
+        }
+        assert!("a".repeat(MAX_LEN + 1).parse::<RoleSlug>().is_err());
+
+        let slug: RoleSlug = serde_json::from_str(r#""viewer""#).unwrap();
+        assert_eq!(serde_json::to_string(&slug).unwrap(), r#""viewer""#);
+        assert!(serde_json::from_str::<RoleSlug>(r#""Viewer""#).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+//! Session-scoped undo and redo of the changes made through [`Db`].
+//!
+//! Temporary triggers log the inverse of every row change, built from the
+//! row's pre-image inside the same transaction. A recorded command turns the
+//! statements it logged into a step of the [`History`]; undoing the step runs
+//! them backwards, which logs the inverse once more for redo. The triggers and
+//! the log are temporary, so the history ends with the connection.
+
+use rusqlite::Connection;
+
+use crate::{Db, Result};
+
+/// Tables tracked by the history along with their columns, except `rowid`
+/// and its aliases, which identify the rows.
+const TRACKED: [(&str, &[&str]); 4] = [
+    ("roles", &["slug", "name", "permissions"]),
+    ("users", &["name", "email", "name_key"]),
+    ("api_keys", &["digest", "user_id"]),
+    ("users_roles", &["user_id", "role_slug"]),
+];
+
+/// Undo and redo stacks of the commands run in a session.
+#[derive(Debug, Default)]
+pub struct History {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+}
+
+/// Statements reverting a command, in the order they were logged.
+#[derive(Debug)]
+struct Step {
+    label: String,
+    statements: Vec<String>,
+}
+
+impl History {
+    /// Starts logging the changes of the `db`, whose schema must be up to date.
+    pub fn start(db: &mut Db) -> Result<Self> {
+        let mut sql = String::from(
+            "CREATE TEMP TABLE IF NOT EXISTS undo_log (
+                seq INTEGER PRIMARY KEY,
+                statement TEXT NOT NULL
+            );",
+        );
+        for (table, columns) in TRACKED {
+            sql.push_str(&triggers(table, columns));
+        }
+        db.conn.execute_batch(&sql)?;
+        Ok(Self::default())
+    }
+
+    /// Runs the command in a transaction, remembering how to revert its
+    /// changes under the `label`.
+    ///
+    /// Commands changing nothing leave no step behind, the others drop the
+    /// steps that could be redone.
+    pub fn record<T>(
+        &mut self,
+        db: &mut Db,
+        label: &str,
+        command: impl FnOnce(&mut Db) -> Result<T>,
+    ) -> Result<T> {
+        let (value, statements) = transaction(db, command)?;
+        if !statements.is_empty() {
+            self.undo.push(Step {
+                label: label.to_owned(),
+                statements,
+            });
+            self.redo.clear();
+        }
+        Ok(value)
+    }
+
+    /// Reverts the latest step, returning its label, or `None` if there's
+    /// nothing to undo.
+    pub fn undo(&mut self, db: &mut Db) -> Result<Option<String>> {
+        replay(db, &mut self.undo, &mut self.redo)
+    }
+
+    /// Applies the latest undone step again, returning its label, or `None`
+    /// if there's nothing to redo.
+    pub fn redo(&mut self, db: &mut Db) -> Result<Option<String>> {
+        replay(db, &mut self.redo, &mut self.undo)
+    }
+}
+
+/// Triggers logging the inverse of every change of the `table`.
+fn triggers(table: &str, columns: &[&str]) -> String {
+    let old = |column: &str| format!("'||quote(old.{column})||'");
+    let assignments: Vec<String> = columns
+        .iter()
+        .map(|column| format!("{column}={}", old(column)))
+        .collect();
+    let values: Vec<String> = columns.iter().map(|column| old(column)).collect();
+    format!(
+        "CREATE TEMP TRIGGER IF NOT EXISTS undo_{table}_insert AFTER INSERT ON {table} BEGIN
+            INSERT INTO undo_log (statement)
+            VALUES ('DELETE FROM {table} WHERE rowid='||new.rowid);
+        END;
+        CREATE TEMP TRIGGER IF NOT EXISTS undo_{table}_update AFTER UPDATE ON {table} BEGIN
+            INSERT INTO undo_log (statement)
+            VALUES ('UPDATE {table} SET {} WHERE rowid='||old.rowid);
+        END;
+        CREATE TEMP TRIGGER IF NOT EXISTS undo_{table}_delete AFTER DELETE ON {table} BEGIN
+            INSERT INTO undo_log (statement)
+            VALUES ('INSERT INTO {table} (rowid, {}) VALUES ('||old.rowid||', {})');
+        END;",
+        assignments.join(", "),
+        columns.join(", "),
+        values.join(", "),
+    )
+}
+
+/// Runs `f` in a transaction, returning the statements it logged.
+///
+/// [`Db`] methods use the connection directly, so the transaction is managed
+/// by hand rather than through [`rusqlite::Transaction`].
+fn transaction<T>(db: &mut Db, f: impl FnOnce(&mut Db) -> Result<T>) -> Result<(T, Vec<String>)> {
+    db.conn.execute_batch("BEGIN")?;
+    let result = f(db).and_then(|value| {
+        let statements = take_log(&db.conn)?;
+        db.conn.execute_batch("COMMIT")?;
+        Ok((value, statements))
+    });
+    if result.is_err() && !db.conn.is_autocommit() {
+        db.conn.execute_batch("ROLLBACK")?;
+    }
+    result
+}
+
+fn take_log(conn: &Connection) -> Result<Vec<String>> {
+    let statements = conn
+        .prepare("SELECT statement FROM undo_log ORDER BY seq")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    conn.execute("DELETE FROM undo_log", [])?;
+    Ok(statements)
+}
+
+/// Reverts the latest step of `from`, moving its inverse to `to`.
+fn replay(db: &mut Db, from: &mut Vec<Step>, to: &mut Vec<Step>) -> Result<Option<String>> {
+    let Some(step) = from.pop() else {
+        return Ok(None);
+    };
+    let result = transaction(db, |db| {
+        // Rows deleted by a cascade may be logged before the row they
+        // reference, so they're restored before it.
+        db.conn.execute_batch("PRAGMA defer_foreign_keys = ON")?;
+        for statement in step.statements.iter().rev() {
+            db.conn.execute(statement, [])?;
+        }
+        Ok(())
+    });
+    match result {
+        Ok(((), statements)) => {
+            let label = step.label.clone();
+            to.push(Step {
+                label: step.label,
+                statements,
+            });
+            Ok(Some(label))
+        }
+        Err(err) => {
+            from.push(step);
+            Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RoleSlug;
+
+    fn slug(raw: &str) -> RoleSlug {
+        raw.parse().unwrap()
+    }
+
+    #[test]
+    fn undoes_and_redoes_commands() -> Result<()> {
+        let mut db = Db::new(":memory:")?;
+        db.ensure_schema()?;
+        let mut history = History::start(&mut db)?;
+
+        history.record(&mut db, "create roles", |db| {
+            db.create_role(&slug("admin"), "Administrator", r#"["all"]"#)?;
+            db.create_role(&slug("viewer"), "Viewer", "[]")
+        })?;
+        history.record(&mut db, "create user", |db| {
+            db.create_user("Alice", "alice@example.com", &slug("admin"))?;
+            db.assign_role(1, &slug("viewer"))
+        })?;
+        let before = db.users_table()?;
+        history.record(&mut db, "rename", |db| {
+            db.update_user(1, Some("Alicia".into()), None)
+        })?;
+        history.record(&mut db, "list", |db| db.users_table())?;
+        history.record(&mut db, "delete", |db| db.delete_user(1))?;
+        assert!(!db.users_table()?.contains("Ali"));
+
+        // Failed commands are rolled back and leave no step
+        let duplicate = history.record(&mut db, "duplicate", |db| {
+            db.create_role(&slug("editor"), "Editor", "[]")?;
+            db.create_role(&slug("editor"), "Editor", "[]")
+        });
+        assert!(duplicate.is_err());
+        assert!(!db.roles_table()?.contains("editor"));
+
+        assert_eq!(history.undo(&mut db)?.as_deref(), Some("delete"));
+        assert!(db.users_table()?.contains("Alicia"));
+        assert_eq!(db.roles_for_user(1)?, "admin,viewer");
+        assert_eq!(history.undo(&mut db)?.as_deref(), Some("rename"));
+        assert_eq!(db.users_table()?, before);
+
+        assert_eq!(history.redo(&mut db)?.as_deref(), Some("rename"));
+        assert_eq!(history.redo(&mut db)?.as_deref(), Some("delete"));
+        assert_eq!(history.redo(&mut db)?, None);
+        assert!(!db.users_table()?.contains("Ali"));
+
+        history.undo(&mut db)?;
+        history.record(&mut db, "new role", |db| {
+            db.create_role(&slug("editor"), "Editor", "[]")
+        })?;
+        assert_eq!(history.redo(&mut db)?, None);
+        for _ in 0..4 {
+            history.undo(&mut db)?;
+        }
+        assert_eq!(history.undo(&mut db)?, None);
+        assert_eq!(
+            db.roles_table()?.lines().count(),
+            1,
+            "only the header is left"
+        );
+        Ok(())
+    }
+}
@@ -1,15 +1,27 @@
 use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
-use axum::extract::State;
-use axum::http::StatusCode;
+use axum::extract::{FromRef, State};
+use axum::http::request::Parts;
+use axum::http::{StatusCode, header};
 use axum::routing::post;
-use axum::{Json, Router};
+use axum::{Json, Router, async_trait};
 use clap::{Parser, Subcommand};
+use common::StateFile;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tokio::signal;
+use step_4_1::RoleSlug;
+use step_4_1::extract::{
+    CallerPermissions, Permission, PermissionResolver, RoleDb, RolesWrite, SharedResolver,
+    UsersWrite,
+};
+use step_4_1::permissions::PermissionSet;
+use step_4_errors::AppError;
+
+/// Version of the [`Store`] schema in the state file.
+const STATE_VERSION: u32 = 1;
 
 #[derive(Parser)]
 #[command(author, version, about = "Thin client/server demo", long_about = None)]
@@ -25,34 +37,84 @@ enum Commands {
         /// Port to listen on.
         #[arg(short, long, default_value_t = 3000)]
         port: u16,
+        /// SQLite database with roles and permissions (see `step_4_1`).
+        #[arg(long, default_value = "roles.sqlite")]
+        roles_db: String,
+        /// File to restore users and roles from on startup and save them to
+        /// on shutdown.
+        #[arg(long)]
+        state_file: Option<PathBuf>,
     },
     /// Send a raw command string to the server.
     Client {
         /// Server address, e.g. http://localhost:3000
         #[arg(short, long, default_value = "http://localhost:3000")]
         server: String,
+        /// API key of the user to act as, required for mutating commands.
+        /// Issued by `step_4_1 issue-api-key`.
+        #[arg(long, env = "API_KEY", hide_env_values = true)]
+        api_key: Option<String>,
         /// Command to execute on the server.
         #[arg(last = true)]
         command: Vec<String>,
     },
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone)]
+struct AppState {
+    store: Arc<Mutex<Store>>,
+    permissions: SharedResolver,
+}
+
+impl FromRef<AppState> for Arc<Mutex<Store>> {
+    fn from_ref(state: &AppState) -> Self {
+        state.store.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedResolver {
+    fn from_ref(state: &AppState) -> Self {
+        state.permissions.clone()
+    }
+}
+
+/// Authenticates the caller by the `Authorization: Bearer` API key issued in
+/// the role database and looks its roles up there.
+struct ApiKeyPermissions {
+    roles: RoleDb,
+}
+
+#[async_trait]
+impl PermissionResolver for ApiKeyPermissions {
+    async fn resolve(&self, parts: &Parts) -> Result<PermissionSet, AppError> {
+        let key = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(AppError::Unauthorized)?;
+        self.roles
+            .permissions_for_api_key(key)?
+            .ok_or(AppError::Unauthorized)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 struct Store {
     users: HashMap<u64, User>,
-    roles: HashMap<String, Role>,
+    roles: HashMap<RoleSlug, Role>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct User {
     id: u64,
     name: String,
-    roles: HashSet<String>,
+    roles: HashSet<RoleSlug>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Role {
-    slug: String,
+    slug: RoleSlug,
     name: String,
 }
 
@@ -69,16 +131,39 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Server { port } => run_server(port).await?,
-        Commands::Client { server, command } => run_client(&server, command).await?,
+        Commands::Server {
+            port,
+            roles_db,
+            state_file,
+        } => run_server(port, &roles_db, state_file).await?,
+        Commands::Client {
+            server,
+            api_key,
+            command,
+        } => run_client(&server, api_key.as_deref(), command).await?,
     }
 
     Ok(())
 }
 
-async fn run_server(port: u16) -> Result<(), Box<dyn std::error::Error>> {
-    let state = Arc::new(Mutex::new(Store::default()));
+async fn run_server(
+    port: u16,
+    roles_db: &str,
+    state_file: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let state_file = state_file.map(|path| StateFile::new(path, STATE_VERSION));
+    let store = match &state_file {
+        Some(file) => file.load()?.unwrap_or_default(),
+        None => Store::default(),
+    };
+    let state = AppState {
+        store: Arc::new(Mutex::new(store)),
+        permissions: Arc::new(ApiKeyPermissions {
+            roles: RoleDb::open(roles_db)?,
+        }),
+    };
 
+    let store = state.store.clone();
     let app = Router::new()
         .route("/command", post(handle_command))
         .with_state(state);
@@ -88,18 +173,24 @@ async fn run_server(port: u16) -> Result<(), Box<dyn std::error::Error>> {
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
     axum::serve(listener, app.into_make_service())
-        .with_graceful_shutdown(shutdown_signal())
+        .with_graceful_shutdown(async {
+            common::shutdown_signal().await;
+            println!("Shutting down server");
+        })
         .await?;
 
+    if let Some(file) = state_file {
+        file.save(&*store.lock().expect("store mutex poisoned"))?;
+        println!("State saved to {}", file.path().display());
+    }
     Ok(())
 }
 
-async fn shutdown_signal() {
-    let _ = signal::ctrl_c().await;
-    println!("Shutting down server");
-}
-
-async fn run_client(server: &str, command: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+async fn run_client(
+    server: &str,
+    api_key: Option<&str>,
+    command: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
     if command.is_empty() {
         eprintln!("Please provide a command to send to the server");
         std::process::exit(1);
@@ -107,7 +198,11 @@ async fn run_client(server: &str, command: Vec<String>) -> Result<(), Box<dyn st
 
     let body = command.join(" ");
     let url = format!("{server}/command");
-    let response = reqwest::Client::new().post(&url).body(body).send().await?;
+    let mut request = reqwest::Client::new().post(&url).body(body);
+    if let Some(key) = api_key {
+        request = request.bearer_auth(key);
+    }
+    let response = request.send().await?;
 
     let status = response.status();
     let text = response.text().await?;
@@ -117,8 +212,13 @@ async fn run_client(server: &str, command: Vec<String>) -> Result<(), Box<dyn st
 
 async fn handle_command(
     State(state): State<Arc<Mutex<Store>>>,
+    caller: Result<CallerPermissions, AppError>,
     body: String,
 ) -> (StatusCode, Json<CommandResponse>) {
+    if let Err(err) = authorize(&caller, body.trim()) {
+        return (err.status(), Json(error_response(&err.to_string())));
+    }
+
     let mut store = state.lock().expect("store mutex poisoned");
     let result = execute_command(&mut store, body.trim());
 
@@ -131,6 +231,25 @@ async fn handle_command(
     (status, Json(result))
 }
 
+/// Permission a command requires, read-only commands need none.
+fn required_permission(input: &str) -> Option<&'static str> {
+    match input.split_whitespace().next()? {
+        "create_user" | "delete_user" | "assign_role" | "unassign_role" => Some(UsersWrite::NAME),
+        "create_role" => Some(RolesWrite::NAME),
+        _ => None,
+    }
+}
+
+/// Checks the caller may run the command. Anonymous callers may run the
+/// read-only ones, while failures of the role database are reported as they
+/// are, rather than as a missing identity.
+fn authorize(caller: &Result<CallerPermissions, AppError>, input: &str) -> Result<(), AppError> {
+    let Some(permission) = required_permission(input) else {
+        return Ok(());
+    };
+    caller.as_ref().map_err(Clone::clone)?.ensure(permission)
+}
+
 fn execute_command(store: &mut Store, input: &str) -> CommandResponse {
     let mut parts = input.split_whitespace();
     let Some(cmd) = parts.next() else {
@@ -153,7 +272,10 @@ fn execute_command(store: &mut Store, input: &str) -> CommandResponse {
                 Some(name) => name.to_string(),
                 None => return error_response("Usage: create_user <id> <name> [role_slug]"),
             };
-            let role = parts.next();
+            let role = match parts.next().map(str::parse::<RoleSlug>).transpose() {
+                Ok(role) => role,
+                Err(err) => return error_response(&err.to_string()),
+            };
 
             if store.users.contains_key(&id) {
                 return error_response("User with provided id already exists");
@@ -161,8 +283,8 @@ fn execute_command(store: &mut Store, input: &str) -> CommandResponse {
 
             let mut roles = HashSet::new();
             if let Some(role_slug) = role {
-                if store.roles.contains_key(role_slug) {
-                    roles.insert(role_slug.to_string());
+                if store.roles.contains_key(&role_slug) {
+                    roles.insert(role_slug);
                 } else {
                     return error_response("Unknown role slug provided");
                 }
@@ -197,15 +319,19 @@ fn execute_command(store: &mut Store, input: &str) -> CommandResponse {
             let Some(name) = parts.next() else {
                 return error_response("Usage: create_role <slug> <name>");
             };
+            let slug: RoleSlug = match slug.parse() {
+                Ok(slug) => slug,
+                Err(err) => return error_response(&err.to_string()),
+            };
 
-            if store.roles.contains_key(slug) {
+            if store.roles.contains_key(&slug) {
                 return error_response("Role with provided slug already exists");
             }
 
             store.roles.insert(
-                slug.to_string(),
+                slug.clone(),
                 Role {
-                    slug: slug.to_string(),
+                    slug,
                     name: name.to_string(),
                 },
             );
@@ -223,12 +349,16 @@ fn execute_command(store: &mut Store, input: &str) -> CommandResponse {
             let Some(role_slug) = parts.next() else {
                 return error_response("Usage: assign_role <user_id> <role_slug>");
             };
+            let role_slug: RoleSlug = match role_slug.parse() {
+                Ok(slug) => slug,
+                Err(err) => return error_response(&err.to_string()),
+            };
 
             let Some(user) = store.users.get_mut(&id) else {
                 return error_response("User not found");
             };
-            if store.roles.contains_key(role_slug) {
-                user.roles.insert(role_slug.to_string());
+            if store.roles.contains_key(&role_slug) {
+                user.roles.insert(role_slug);
                 CommandResponse {
                     status: "ok".into(),
                     message: "Role assigned".into(),
@@ -311,6 +441,10 @@ mod tests {
         let response = execute_command(&mut store, "create_role editor Editor");
         assert_eq!(response.status, "ok");
 
+        let response = execute_command(&mut store, "create_role Content_Editor Editor");
+        assert_eq!(response.status, "error");
+        assert!(response.message.starts_with("invalid role slug"));
+
         let response = execute_command(&mut store, "create_user 1 Alice admin");
         assert_eq!(response.status, "ok");
 
@@ -335,4 +469,69 @@ mod tests {
         assert_eq!(user.roles.len(), 1);
         assert!(user.roles.contains("editor"));
     }
+
+    #[test]
+    fn store_survives_restart_via_state_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = StateFile::new(dir.path().join("state.json"), STATE_VERSION);
+        let mut store = Store::default();
+        execute_command(&mut store, "create_role admin Admin");
+        execute_command(&mut store, "create_user 1 Alice admin");
+        file.save(&store).unwrap();
+
+        let mut restored: Store = file.load().unwrap().expect("state was saved");
+        let response = execute_command(&mut restored, "show_user 1");
+        let user: User = serde_json::from_value(response.data.expect("user details")).unwrap();
+        assert_eq!(user.name, "Alice");
+        assert!(user.roles.contains("admin"));
+        assert_eq!(restored.roles.len(), 1);
+    }
+
+    #[test]
+    fn mutating_commands_require_permissions() {
+        let anonymous = Err(AppError::Unauthorized);
+        assert!(authorize(&anonymous, "list_users").is_ok());
+
+        let err = authorize(&anonymous, "create_role admin Admin").unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNAUTHORIZED);
+        let broken = Err(AppError::Internal("disk I/O error".into()));
+        let err = authorize(&broken, "delete_user 1").unwrap_err();
+        assert_eq!(err.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let editor = Ok(CallerPermissions(
+            PermissionSet::parse(r#"["users.write"]"#).unwrap(),
+        ));
+        assert!(authorize(&editor, "delete_user 1").is_ok());
+        let err = authorize(&editor, "create_role admin Admin").unwrap_err();
+        assert_eq!(err.status(), StatusCode::FORBIDDEN);
+        assert_eq!(err.to_string(), "missing permission 'roles.write'");
+    }
+
+    #[tokio::test]
+    async fn authenticates_callers_by_api_keys() {
+        let roles = RoleDb::open(":memory:").unwrap();
+        let key = roles.with_db(|db| {
+            let editor = "editor".parse().unwrap();
+            db.create_role(&editor, "Editor", r#"["users.write"]"#)?;
+            let id = db.create_user("Alice", "alice@example.com", &editor)?;
+            db.issue_api_key(id)
+        });
+        let resolver = ApiKeyPermissions { roles };
+        let resolve = |authorization: Option<String>| {
+            let mut request = axum::http::Request::builder();
+            if let Some(value) = authorization {
+                request = request.header(header::AUTHORIZATION, value);
+            }
+            let (parts, ()) = request.body(()).unwrap().into_parts();
+            let resolver = &resolver;
+            async move { resolver.resolve(&parts).await }
+        };
+
+        let alice = resolve(Some(format!("Bearer {}", key.unwrap()))).await;
+        assert!(alice.unwrap().allows("users.write"));
+        for authorization in [None, Some("Bearer alice".into()), Some("Alice".into())] {
+            let err = resolve(authorization).await.unwrap_err();
+            assert_eq!(err, AppError::Unauthorized);
+        }
+    }
 }
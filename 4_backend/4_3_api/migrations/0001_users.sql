@@ -0,0 +1,20 @@
+-- Users with their current names, friendships and issued session tokens.
+
+CREATE TABLE users (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    password_hash TEXT NOT NULL
+);
+
+CREATE TABLE friends (
+    user_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    friend_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    PRIMARY KEY (user_id, friend_id)
+);
+
+CREATE TABLE tokens (
+    token TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- Unix time in seconds, NULL if the token never expires
+    expires_at INTEGER
+);
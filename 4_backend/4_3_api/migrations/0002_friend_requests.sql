@@ -0,0 +1,7 @@
+-- Friend requests waiting for their targets to accept or reject them.
+
+CREATE TABLE friend_requests (
+    id TEXT PRIMARY KEY NOT NULL,
+    from_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    to_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE
+);
@@ -0,0 +1,3 @@
+-- Roles of the users, deciding what they may do to the other users.
+
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user';
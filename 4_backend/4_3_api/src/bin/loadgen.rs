@@ -0,0 +1,386 @@
+//! Load generator for the REST (`step_4_3`) and GraphQL (`step_4`) servers.
+//!
+//! Registers a pool of users, logs them in and then issues a mixed workload
+//! of reads (fetching a user with friends) and writes (adding friends) at the
+//! target rate, reporting latency percentiles and error rates per operation.
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context as _, anyhow, bail};
+use clap::{Parser, ValueEnum};
+use cli_common::ProgressArgs;
+use rand::Rng as _;
+use reqwest::{Client, Url};
+use serde_json::{Value, json};
+use tokio::{
+    sync::{Mutex, Semaphore},
+    time::{MissedTickBehavior, interval},
+};
+
+#[derive(Parser, Debug)]
+#[command(about = "Load generator for the REST and GraphQL servers")]
+struct Args {
+    /// API flavour of the server
+    #[arg(long, value_enum, default_value_t = Api::Rest)]
+    api: Api,
+    /// Base URL of the server
+    #[arg(long, default_value = "http://127.0.0.1:8080")]
+    server: String,
+    /// Number of users to register before running the workload
+    #[arg(long, default_value_t = 20)]
+    users: usize,
+    /// Total number of workload requests
+    #[arg(long, default_value_t = 1000)]
+    requests: usize,
+    /// Target requests per second
+    #[arg(long, default_value_t = 100)]
+    rps: u32,
+    /// Share of reads in the workload, the rest are writes
+    #[arg(long, default_value_t = 0.8)]
+    read_ratio: f64,
+    /// Maximum number of requests in flight
+    #[arg(long, default_value_t = 32)]
+    concurrency: usize,
+    #[command(flatten)]
+    progress: ProgressArgs,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Api {
+    Rest,
+    Graphql,
+}
+
+/// Registered and logged in user of the workload.
+#[derive(Clone, Debug)]
+struct Session {
+    id: String,
+    token: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Read,
+    Write,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Op::Read => "read",
+            Op::Write => "write",
+        })
+    }
+}
+
+/// HTTP client speaking either of the APIs.
+struct Target {
+    api: Api,
+    base: Url,
+    client: Client,
+}
+
+impl Target {
+    fn new(api: Api, server: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            api,
+            base: Url::parse(server)?,
+            client: Client::new(),
+        })
+    }
+
+    async fn register_and_login(&self, name: &str, password: &str) -> anyhow::Result<Session> {
+        match self.api {
+            Api::Rest => {
+                let credentials = json!({ "name": name, "password": password });
+                self.client
+                    .post(self.base.join("/register")?)
+                    .json(&credentials)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                let login: Value = self
+                    .client
+                    .post(self.base.join("/login")?)
+                    .json(&credentials)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                let token = string_at(&login, "/token")?;
+                let me: Value = self
+                    .client
+                    .get(self.base.join("/me")?)
+                    .bearer_auth(&token)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                Ok(Session {
+                    id: string_at(&me, "/id")?,
+                    token,
+                })
+            }
+            Api::Graphql => {
+                let vars = json!({ "name": name, "password": password });
+                self.graphql(
+                    None,
+                    "mutation($name: String!, $password: String!) \
+                     { register(name: $name, password: $password) { id } }",
+                    vars.clone(),
+                )
+                .await?;
+                let login = self
+                    .graphql(
+                        None,
+                        "mutation($name: String!, $password: String!) \
+                         { login(name: $name, password: $password) { token user { id } } }",
+                        vars,
+                    )
+                    .await?;
+                Ok(Session {
+                    id: string_at(&login, "/login/user/id")?,
+                    token: string_at(&login, "/login/token")?,
+                })
+            }
+        }
+    }
+
+    /// Fetches the user along with its friends.
+    async fn read(&self, session: &Session) -> anyhow::Result<()> {
+        match self.api {
+            Api::Rest => {
+                self.client
+                    .get(self.base.join(&format!("/users/{}", session.id))?)
+                    .bearer_auth(&session.token)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+            Api::Graphql => {
+                self.graphql(
+                    Some(&session.token),
+                    "query($id: ID!) { user(id: $id) { id name friends { id name } } }",
+                    json!({ "id": session.id }),
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the friend to the user, which is idempotent.
+    async fn write(&self, session: &Session, friend_id: &str) -> anyhow::Result<()> {
+        match self.api {
+            Api::Rest => {
+                self.client
+                    .post(
+                        self.base
+                            .join(&format!("/users/{}/friends/{friend_id}", session.id))?,
+                    )
+                    .bearer_auth(&session.token)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+            Api::Graphql => {
+                self.graphql(
+                    Some(&session.token),
+                    "mutation($id: ID!) { addFriend(friendId: $id) { id } }",
+                    json!({ "id": friend_id }),
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes a GraphQL request, treating reported errors as failures.
+    async fn graphql(
+        &self,
+        token: Option<&str>,
+        query: &str,
+        variables: Value,
+    ) -> anyhow::Result<Value> {
+        let mut request = self
+            .client
+            .post(self.base.join("/graphql")?)
+            .json(&json!({ "query": query, "variables": variables }));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let mut response: Value = request.send().await?.error_for_status()?.json().await?;
+        if let Some(errors) = response.get("errors").filter(|e| !e.is_null()) {
+            bail!("GraphQL errors: {errors}");
+        }
+        Ok(response["data"].take())
+    }
+}
+
+fn string_at(value: &Value, pointer: &str) -> anyhow::Result<String> {
+    value
+        .pointer(pointer)
+        .and_then(Value::as_str)
+        .map(str::to_owned)
+        .ok_or_else(|| anyhow!("missing `{pointer}` in response: {value}"))
+}
+
+/// Latencies and failures collected for a single kind of operation.
+#[derive(Debug, Default)]
+struct Stats {
+    latencies: Vec<Duration>,
+    errors: usize,
+}
+
+impl Stats {
+    fn record(&mut self, latency: Duration, ok: bool) {
+        self.latencies.push(latency);
+        if !ok {
+            self.errors += 1;
+        }
+    }
+
+    fn error_rate(&self) -> f64 {
+        if self.latencies.is_empty() {
+            return 0.0;
+        }
+        self.errors as f64 / self.latencies.len() as f64
+    }
+}
+
+/// Nearest-rank percentile of sorted samples, `p` being in `0.0..=100.0`.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn report(op: Op, stats: &mut Stats) {
+    stats.latencies.sort();
+    let samples = &stats.latencies;
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    println!(
+        "{op:<5}  {:>6}  {:>6.2}%  {:>8.2}  {:>8.2}  {:>8.2}  {:>8.2}",
+        samples.len(),
+        stats.error_rate() * 100.0,
+        ms(percentile(samples, 50.0)),
+        ms(percentile(samples, 90.0)),
+        ms(percentile(samples, 99.0)),
+        ms(samples.last().copied().unwrap_or_default()),
+    );
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    if args.users < 2 {
+        bail!("at least 2 users are required for friendship writes");
+    }
+    if !(0.0..=1.0).contains(&args.read_ratio) {
+        bail!("--read-ratio must be within 0.0..=1.0");
+    }
+    let target = Arc::new(Target::new(args.api, &args.server)?);
+
+    let run_id = rand::thread_rng().r#gen::<u32>();
+    let mut sessions = Vec::with_capacity(args.users);
+    for i in 0..args.users {
+        let session = target
+            .register_and_login(&format!("loadgen-{run_id:08x}-{i}"), "loadgen")
+            .await
+            .with_context(|| format!("failed to set up user #{i}"))?;
+        sessions.push(session);
+    }
+    let sessions = Arc::new(sessions);
+    println!("Registered {} users, running workload...", sessions.len());
+
+    let stats = Arc::new(Mutex::new([Stats::default(), Stats::default()]));
+    let in_flight = Arc::new(Semaphore::new(args.concurrency.max(1)));
+    let mut ticker = interval(Duration::from_secs_f64(1.0 / f64::from(args.rps.max(1))));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let progress = args.progress.start("loadgen", Some(args.requests));
+    let started = Instant::now();
+    let mut tasks = Vec::with_capacity(args.requests);
+    for i in 0..args.requests {
+        ticker.tick().await;
+        let permit = in_flight.clone().acquire_owned().await?;
+        let (op, user, friend) = {
+            let mut rng = rand::thread_rng();
+            let op = if rng.gen_bool(args.read_ratio) {
+                Op::Read
+            } else {
+                Op::Write
+            };
+            let user = rng.gen_range(0..sessions.len());
+            // Shift by a non-zero offset so users never befriend themselves.
+            let friend = (user + rng.gen_range(1..sessions.len())) % sessions.len();
+            (op, user, friend)
+        };
+        let (target, sessions, stats) = (target.clone(), sessions.clone(), stats.clone());
+        let progress = progress.clone();
+        tasks.push(tokio::spawn(async move {
+            let start = Instant::now();
+            let result = match op {
+                Op::Read => target.read(&sessions[user]).await,
+                Op::Write => target.write(&sessions[user], &sessions[friend].id).await,
+            };
+            let latency = start.elapsed();
+            drop(permit);
+            let item = format!("{op} #{i}");
+            match &result {
+                Ok(()) => progress.completed(&item, latency),
+                Err(err) => progress.failed(&item, err, latency),
+            }
+            stats.lock().await[op as usize].record(latency, result.is_ok());
+        }));
+    }
+    for task in tasks {
+        task.await?;
+    }
+    let elapsed = started.elapsed();
+    progress.finish();
+
+    println!(
+        "Completed {} requests in {:.2}s ({:.1} rps)",
+        args.requests,
+        elapsed.as_secs_f64(),
+        args.requests as f64 / elapsed.as_secs_f64(),
+    );
+    println!(
+        "{:<5}  {:>6}  {:>7}  {:>8}  {:>8}  {:>8}  {:>8}",
+        "op", "count", "errors", "p50 ms", "p90 ms", "p99 ms", "max ms",
+    );
+    let mut stats = stats.lock().await;
+    for op in [Op::Read, Op::Write] {
+        report(op, &mut stats[op as usize]);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_nearest_rank_percentiles() {
+        let samples: Vec<_> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&samples, 50.0), Duration::from_millis(50));
+        assert_eq!(percentile(&samples, 99.0), Duration::from_millis(99));
+        assert_eq!(percentile(&samples, 100.0), Duration::from_millis(100));
+        assert_eq!(percentile(&samples, 0.0), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 50.0), Duration::ZERO);
+
+        let mut stats = Stats::default();
+        stats.record(Duration::from_millis(1), true);
+        stats.record(Duration::from_millis(2), false);
+        assert_eq!(stats.error_rate(), 0.5);
+    }
+}
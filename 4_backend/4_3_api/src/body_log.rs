@@ -0,0 +1,146 @@
+//! Debug-mode logging of request and response bodies.
+//!
+//! Bodies are buffered, passed through the [`Redactor`] and only then handed
+//! to `tracing`, so secrets never reach the JSON formatter of `step_3_8`.
+
+use std::sync::Arc;
+
+use axum::{
+    body::{Body, Bytes, to_bytes},
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use step_3_8::redact::Redactor;
+use tracing::debug;
+
+/// Target of the emitted events, so they can be filtered separately.
+pub const TARGET: &str = "http_body";
+
+/// Middleware logging redacted bodies, to be installed with
+/// [`axum::middleware::from_fn_with_state`].
+pub async fn log_bodies(
+    State(redactor): State<Arc<Redactor>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (parts, body) = request.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    };
+    debug!(
+        target: TARGET,
+        method = %parts.method,
+        uri = %parts.uri,
+        headers = %headers_json(&redactor, &parts.headers),
+        body = %redactor.redact_body(&bytes),
+        "request",
+    );
+
+    let response = next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await;
+
+    let (parts, body) = response.into_parts();
+    if is_event_stream(&parts.headers) {
+        // Buffering would hold back the events until the client disconnects.
+        debug!(
+            target: TARGET,
+            status = parts.status.as_u16(),
+            headers = %headers_json(&redactor, &parts.headers),
+            "streaming response",
+        );
+        return Response::from_parts(parts, body);
+    }
+    let bytes = to_bytes(body, usize::MAX).await.unwrap_or_else(|err| {
+        debug!(target: TARGET, error = %err, "failed to buffer response body");
+        Bytes::new()
+    });
+    debug!(
+        target: TARGET,
+        status = parts.status.as_u16(),
+        headers = %headers_json(&redactor, &parts.headers),
+        body = %redactor.redact_body(&bytes),
+        "response",
+    );
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+fn is_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"))
+}
+
+fn headers_json(redactor: &Redactor, headers: &HeaderMap) -> serde_json::Value {
+    redactor.redact_headers(
+        headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.as_str(), value.to_str().ok()?))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+    use std::sync::Mutex;
+
+    use axum::{Router, http::header, middleware::from_fn_with_state, routing::post};
+    use step_3_8::JsonFormatter;
+    use tower::ServiceExt;
+    use tracing_subscriber::{Registry, fmt, layer::SubscriberExt};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_redacted_bodies_and_passes_them_through() {
+        let buffer = SharedBuffer::default();
+        let writer = buffer.clone();
+        let subscriber = Registry::default().with(
+            fmt::layer()
+                .event_format(JsonFormatter::new("app.log"))
+                .with_writer(move || writer.clone()),
+        );
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let router = Router::new()
+            .route("/login", post(|body: String| async move { body }))
+            .layer(from_fn_with_state(
+                Arc::new(Redactor::default()),
+                log_bodies,
+            ));
+        let response = router
+            .oneshot(
+                Request::post("/login")
+                    .header(header::AUTHORIZATION, "Bearer abc")
+                    .body(Body::from(r#"{"name":"alice","password":"secret"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let echoed = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&echoed[..], br#"{"name":"alice","password":"secret"}"#);
+
+        let logs = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(logs.lines().count(), 2);
+        assert!(!logs.contains("secret"));
+        assert!(!logs.contains("Bearer abc"));
+        assert!(logs.contains("alice"));
+    }
+}
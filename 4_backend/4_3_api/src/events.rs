@@ -0,0 +1,471 @@
+//! Server-sent events stream of [`UserEvent`]s.
+//!
+//! Events published by the [`UserService`] are numbered and kept in a bounded
+//! history, so clients reconnecting with the `Last-Event-ID` header receive
+//! whatever they missed before the live events.
+//!
+//! Each connection gets its own bounded queue, filled from the broadcast as
+//! events arrive, so a slow client can't make the server buffer without
+//! limits. What happens once the queue is full is set by [`OverflowPolicy`].
+
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::{Stream, StreamExt as _, stream};
+use serde::Serialize;
+use step_3_9::{EventQueueConfig, OverflowPolicy};
+use step_4_domain::{UserEvent, UserId, UserService};
+use tokio::sync::{
+    Notify,
+    broadcast::{self, error::RecvError},
+    watch,
+};
+use tracing::warn;
+use utoipa::ToSchema;
+
+/// Number of past events available for resuming.
+const HISTORY_LEN: usize = 256;
+
+/// Interval of comments sent to idle connections, keeping proxies from
+/// closing them.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A [`UserEvent`] along with its position in the log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Notification {
+    pub id: u64,
+    pub event: UserEvent,
+}
+
+#[derive(Default)]
+struct History {
+    last_id: u64,
+    recent: VecDeque<Notification>,
+}
+
+/// Counters of the client queues, see [`EventLog::stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+pub struct QueueStats {
+    /// Currently connected clients.
+    pub clients: u64,
+    /// Events discarded from full queues.
+    pub dropped: u64,
+    /// Clients disconnected for falling behind.
+    pub disconnected: u64,
+    /// Most events ever waiting for a single client.
+    pub max_lag: u64,
+}
+
+#[derive(Default)]
+struct QueueMetrics {
+    clients: AtomicU64,
+    dropped: AtomicU64,
+    disconnected: AtomicU64,
+    max_lag: AtomicU64,
+}
+
+/// Numbered log of the recent [`UserEvent`]s, shared by all connections.
+#[derive(Clone)]
+pub struct EventLog {
+    history: Arc<Mutex<History>>,
+    live: broadcast::Sender<Notification>,
+    queue: EventQueueConfig,
+    metrics: Arc<QueueMetrics>,
+    /// Whether the streams are to end, as the server is shutting down.
+    closed: Arc<watch::Sender<bool>>,
+}
+
+impl EventLog {
+    /// Creates a log recording events of the service from now on.
+    pub fn spawn(users: &UserService, queue: EventQueueConfig) -> Self {
+        let log = Self::new(queue);
+        let mut events = users.subscribe();
+        let recorder = log.clone();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => recorder.record(event),
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!(missed, "event log lagged behind the user service");
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        log
+    }
+
+    fn new(queue: EventQueueConfig) -> Self {
+        let (live, _) = broadcast::channel(HISTORY_LEN);
+        Self {
+            history: Arc::default(),
+            live,
+            queue,
+            metrics: Arc::default(),
+            closed: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// Ends every stream, current and future, so the connections don't keep
+    /// a gracefully shutting down server running.
+    pub fn close(&self) {
+        self.closed.send_replace(true);
+    }
+
+    fn record(&self, event: UserEvent) {
+        // Sending under the lock keeps `subscribe` from missing or repeating
+        // the event.
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        history.last_id += 1;
+        let notification = Notification {
+            id: history.last_id,
+            event,
+        };
+        if history.recent.len() == HISTORY_LEN {
+            history.recent.pop_front();
+        }
+        history.recent.push_back(notification.clone());
+        let _ = self.live.send(notification);
+    }
+
+    /// Returns the retained events following `after` along with a receiver of
+    /// the ones recorded later.
+    pub fn subscribe(
+        &self,
+        after: Option<u64>,
+    ) -> (Vec<Notification>, broadcast::Receiver<Notification>) {
+        let history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        let missed = match after {
+            Some(after) => history
+                .recent
+                .iter()
+                .filter(|n| n.id > after)
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        (missed, self.live.subscribe())
+    }
+
+    /// Streams the missed and then the live events.
+    ///
+    /// Live events are queued for the client as they arrive. The stream ends
+    /// if the client falls too far behind, so it reconnects and catches up
+    /// from the history instead, or once the log is closed.
+    pub fn stream(&self, after: Option<u64>) -> impl Stream<Item = Notification> + use<> {
+        let (missed, mut live) = self.subscribe(after);
+        let queue = Arc::new(ClientQueue::new(self.queue.clone(), self.metrics.clone()));
+        let forwarded = Arc::downgrade(&queue);
+        tokio::spawn(async move {
+            loop {
+                let received = live.recv().await;
+                // The client is gone once its stream drops the queue.
+                let Some(queue) = forwarded.upgrade() else {
+                    break;
+                };
+                let open = match received {
+                    Ok(notification) => queue.push(notification),
+                    Err(RecvError::Lagged(missed)) => {
+                        queue.disconnect(missed);
+                        false
+                    }
+                    Err(RecvError::Closed) => {
+                        queue.close();
+                        false
+                    }
+                };
+                if !open {
+                    break;
+                }
+            }
+        });
+        let live = stream::unfold(queue, |queue| async move {
+            let notification = queue.pop().await?;
+            Some((notification, queue))
+        });
+        let mut closed = self.closed.subscribe();
+        stream::iter(missed).chain(live).take_until(async move {
+            let _ = closed.wait_for(|closed| *closed).await;
+        })
+    }
+
+    /// Current counters of the client queues.
+    pub fn stats(&self) -> QueueStats {
+        let metrics = &self.metrics;
+        QueueStats {
+            clients: metrics.clients.load(Ordering::Relaxed),
+            dropped: metrics.dropped.load(Ordering::Relaxed),
+            disconnected: metrics.disconnected.load(Ordering::Relaxed),
+            max_lag: metrics.max_lag.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    events: VecDeque<Notification>,
+    closed: bool,
+}
+
+/// Live events waiting to be sent to a single client.
+struct ClientQueue {
+    state: Mutex<QueueState>,
+    ready: Notify,
+    config: EventQueueConfig,
+    metrics: Arc<QueueMetrics>,
+}
+
+impl ClientQueue {
+    fn new(config: EventQueueConfig, metrics: Arc<QueueMetrics>) -> Self {
+        metrics.clients.fetch_add(1, Ordering::Relaxed);
+        Self {
+            state: Mutex::default(),
+            ready: Notify::new(),
+            config,
+            metrics,
+        }
+    }
+
+    /// Queues the event, applying the overflow policy if the queue is full.
+    ///
+    /// Returns `false` once the queue is closed.
+    fn push(&self, notification: Notification) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.closed {
+            return false;
+        }
+        if state.events.len() >= self.config.queue_len.max(1) {
+            let freed = match self.config.overflow {
+                OverflowPolicy::Disconnect => false,
+                OverflowPolicy::DropOldest => state.events.pop_front().is_some(),
+                OverflowPolicy::CoalescePresence => {
+                    coalesce(&mut state.events, &notification.event)
+                }
+            };
+            if !freed {
+                drop(state);
+                self.disconnect(0);
+                return false;
+            }
+            self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        state.events.push_back(notification);
+        let lag = state.events.len() as u64;
+        self.metrics.max_lag.fetch_max(lag, Ordering::Relaxed);
+        self.ready.notify_one();
+        true
+    }
+
+    /// Waits for the next event, returning `None` once the queue is closed.
+    async fn pop(&self) -> Option<Notification> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+                if let Some(notification) = state.events.pop_front() {
+                    return Some(notification);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.ready.notified().await;
+        }
+    }
+
+    /// Closes the queue after the remaining events are sent.
+    fn close(&self) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).closed = true;
+        self.ready.notify_one();
+    }
+
+    /// Closes the queue right away, discarding the remaining events, which
+    /// the client gets from the history on reconnecting.
+    fn disconnect(&self, missed: u64) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let queued = state.events.len();
+        state.events.clear();
+        state.closed = true;
+        drop(state);
+        self.metrics.disconnected.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            queued,
+            missed, "disconnecting an event stream client lagging behind"
+        );
+        self.ready.notify_one();
+    }
+}
+
+impl Drop for ClientQueue {
+    fn drop(&mut self) {
+        self.metrics.clients.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Frees a slot for the `incoming` event by discarding the queued presence
+/// event it supersedes, or else the oldest presence event.
+fn coalesce(events: &mut VecDeque<Notification>, incoming: &UserEvent) -> bool {
+    let superseded = presence_of(incoming).and_then(|id| {
+        events
+            .iter()
+            .position(|n| presence_of(&n.event) == Some(id))
+    });
+    superseded
+        .or_else(|| events.iter().position(|n| presence_of(&n.event).is_some()))
+        .and_then(|index| events.remove(index))
+        .is_some()
+}
+
+/// User whose login state the event reports, if it's a presence event.
+fn presence_of(event: &UserEvent) -> Option<UserId> {
+    match event {
+        UserEvent::LoggedIn { id } | UserEvent::LoggedOut { id } => Some(*id),
+        _ => None,
+    }
+}
+
+/// Streams [`UserEvent`]s as server-sent events with the `id` of each being
+/// accepted in the `Last-Event-ID` header for resuming.
+pub async fn stream_events(
+    State(log): State<EventLog>,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let after = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok());
+    let events = log.stream(after).map(|notification| {
+        let event = Event::default()
+            .id(notification.id.to_string())
+            .event(notification.event.kind());
+        Ok(match event.json_data(&notification.event) {
+            Ok(event) => event,
+            Err(err) => Event::default().comment(format!("unserializable event: {err}")),
+        })
+    });
+    Sse::new(events).keep_alive(KeepAlive::new().interval(HEARTBEAT_INTERVAL))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue_config(queue_len: usize, overflow: OverflowPolicy) -> EventQueueConfig {
+        EventQueueConfig {
+            queue_len,
+            overflow,
+        }
+    }
+
+    #[tokio::test]
+    async fn resumes_after_last_event_id() {
+        let log = EventLog::new(EventQueueConfig::default());
+        let ids: Vec<_> = (0..3).map(|_| UserId::new()).collect();
+        for &id in &ids {
+            log.record(UserEvent::LoggedIn { id });
+        }
+
+        let (missed, _) = log.subscribe(None);
+        assert!(missed.is_empty(), "fresh connections only get live events");
+
+        let mut resumed = Box::pin(log.stream(Some(1)));
+        log.record(UserEvent::Deleted { id: ids[0] });
+
+        let received: Vec<_> = resumed.by_ref().take(3).collect().await;
+        let received: Vec<_> = received.iter().map(|n| (n.id, n.event.clone())).collect();
+        assert_eq!(
+            received,
+            vec![
+                (2, UserEvent::LoggedIn { id: ids[1] }),
+                (3, UserEvent::LoggedIn { id: ids[2] }),
+                (4, UserEvent::Deleted { id: ids[0] }),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn lagging_clients_are_disconnected() {
+        let log = EventLog::new(queue_config(2, OverflowPolicy::Disconnect));
+        let id = UserId::new();
+        let stream = log.stream(None);
+        assert_eq!(log.stats().clients, 1);
+        for _ in 0..3 {
+            log.record(UserEvent::LoggedIn { id });
+        }
+
+        let received: Vec<_> = stream.collect().await;
+        assert!(received.is_empty(), "queued events are resent on reconnect");
+        let stats = log.stats();
+        assert_eq!(
+            (stats.clients, stats.disconnected, stats.max_lag),
+            (0, 1, 2)
+        );
+    }
+
+    #[tokio::test]
+    async fn streams_end_once_closed() {
+        let log = EventLog::new(EventQueueConfig::default());
+        let id = UserId::new();
+        let mut stream = Box::pin(log.stream(None));
+        log.record(UserEvent::LoggedIn { id });
+        assert_eq!(stream.next().await.map(|n| n.id), Some(1));
+
+        log.close();
+        assert_eq!(stream.next().await, None);
+        assert_eq!(log.stream(None).collect::<Vec<_>>().await, []);
+    }
+
+    #[test]
+    fn full_queues_coalesce_presence() {
+        let metrics = Arc::default();
+        let queue = ClientQueue::new(
+            queue_config(3, OverflowPolicy::CoalescePresence),
+            Arc::clone(&metrics),
+        );
+        let (alice, bob) = (UserId::new(), UserId::new());
+        let events = [
+            UserEvent::LoggedIn { id: alice },
+            UserEvent::LoggedIn { id: bob },
+            UserEvent::Deleted { id: bob },
+            UserEvent::LoggedOut { id: alice },
+            UserEvent::Registered {
+                id: UserId::new(),
+                name: "carol".into(),
+            },
+            UserEvent::Deleted { id: alice },
+        ];
+        for (id, event) in (1..).zip(events) {
+            assert!(queue.push(Notification { id, event }));
+        }
+
+        // Alice's logout superseded her login, then Bob's login and Alice's
+        // logout made room for the events after them.
+        let queued: Vec<_> = queue
+            .state
+            .lock()
+            .unwrap()
+            .events
+            .iter()
+            .map(|n| n.id)
+            .collect();
+        assert_eq!(queued, [3, 5, 6]);
+        assert_eq!(metrics.dropped.load(Ordering::Relaxed), 3);
+
+        // Nothing is left to coalesce.
+        let event = UserEvent::LoggedIn { id: bob };
+        assert!(!queue.push(Notification { id: 7, event }));
+        assert!(queue.state.lock().unwrap().events.is_empty());
+        assert_eq!(metrics.disconnected.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,227 @@
+//! Access tokens, which are JWTs signed with HS256.
+//!
+//! An access token carries the ID of its user and its expiry, so checking it
+//! touches no shared state but the time the user last changed the password.
+//! Unlike the refresh tokens, which are sessions of the
+//! [`UserService`](step_4_domain::UserService) exchanged for new access
+//! tokens, access tokens can't be revoked one by one and therefore live
+//! shortly. A password change or deleting the user revokes all of them at
+//! once, see [`AccessTokens::revoke_all`].
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime},
+};
+
+use common::{Clock, SystemClock};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use step_3_9::AuthConfig;
+use step_4_domain::UserId;
+use tracing::warn;
+
+/// Issuer and verifier of access tokens, cheap to clone into server state.
+#[derive(Clone)]
+pub struct AccessTokens {
+    keys: Arc<Keys>,
+    clock: Arc<dyn Clock>,
+    /// Unix time of the moment the clock was first read at, telling the
+    /// unix time by the clock later on.
+    epoch: (Duration, Instant),
+}
+
+struct Keys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    validation: Validation,
+    ttl: Duration,
+    /// Unix time in seconds tokens of the users must be issued at or after,
+    /// kept only until the older tokens expire anyway.
+    not_before: Mutex<HashMap<UserId, u64>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    sub: UserId,
+    /// Unix time in seconds the token was issued at.
+    iat: u64,
+    /// Unix time in seconds the token expires at.
+    exp: u64,
+}
+
+impl AccessTokens {
+    pub fn new(secret: &[u8], ttl: Duration) -> Self {
+        let mut validation = Validation::new(Algorithm::HS256);
+        // The expiry is checked by the clock of the server, see `verify`.
+        validation.validate_exp = false;
+        let keys = Arc::new(Keys {
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+            validation,
+            ttl,
+            not_before: Mutex::default(),
+        });
+        Self::with_keys(keys, Arc::new(SystemClock))
+    }
+
+    /// Uses the `clock` for expiry and revocations instead of the system time.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self::with_keys(self.keys, clock)
+    }
+
+    fn with_keys(keys: Arc<Keys>, clock: Arc<dyn Clock>) -> Self {
+        let unix = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("the system time is past the unix epoch");
+        let epoch = (unix, clock.now());
+        Self { keys, clock, epoch }
+    }
+
+    /// Uses the configured secret, or a random one if there is none.
+    pub fn from_config(config: &AuthConfig) -> Self {
+        match &config.secret {
+            Some(secret) => Self::new(secret.as_bytes(), config.access_ttl),
+            None => {
+                warn!("no `server.auth.secret` configured, access tokens won't survive a restart");
+                Self::new(&rand::random::<[u8; 32]>(), config.access_ttl)
+            }
+        }
+    }
+
+    /// Lifetime of the issued tokens.
+    pub fn ttl(&self) -> Duration {
+        self.keys.ttl
+    }
+
+    pub fn issue(&self, user: UserId) -> String {
+        // Tokens issued right after a revocation still get past its cutoff.
+        let iat = self.now().max(self.not_before(user));
+        let claims = Claims {
+            sub: user,
+            iat,
+            exp: iat + self.keys.ttl.as_secs(),
+        };
+        jsonwebtoken::encode(&Header::default(), &claims, &self.keys.encoding)
+            .expect("HS256 signs any claims")
+    }
+
+    /// Returns the user of the token, unless it's malformed, forged,
+    /// expired or revoked.
+    pub fn verify(&self, token: &str) -> Option<UserId> {
+        let claims =
+            jsonwebtoken::decode::<Claims>(token, &self.keys.decoding, &self.keys.validation)
+                .ok()?
+                .claims;
+        // Tokens are checked by the very server issuing them, so there is no
+        // clock skew to tolerate.
+        let valid = claims.exp >= self.now() && claims.iat >= self.not_before(claims.sub);
+        valid.then_some(claims.sub)
+    }
+
+    /// Revokes every token of the `user` issued so far, e.g. after the
+    /// password changed or the user was deleted.
+    ///
+    /// Revocations are kept in memory, so a restart of the server brings
+    /// back the unexpired tokens.
+    pub fn revoke_all(&self, user: UserId) {
+        let now = self.now();
+        let mut not_before = self.lock();
+        // Older revocations only cover tokens that have expired since.
+        not_before.retain(|_, cutoff| *cutoff + self.keys.ttl.as_secs() > now);
+        // Whole seconds, so the tokens issued earlier in this one are out too.
+        not_before.insert(user, now + 1);
+    }
+
+    /// Unix time in seconds by the clock.
+    fn now(&self) -> u64 {
+        let (unix, at) = self.epoch;
+        (unix + self.clock.now().saturating_duration_since(at)).as_secs()
+    }
+
+    fn not_before(&self, user: UserId) -> u64 {
+        self.lock().get(&user).copied().unwrap_or_default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<UserId, u64>> {
+        self.keys
+            .not_before
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::MockClock;
+
+    use super::*;
+
+    #[test]
+    fn verifies_only_own_unexpired_tokens() {
+        let tokens = AccessTokens::new(b"secret", Duration::from_secs(60));
+        let user = UserId::new();
+        let token = tokens.issue(user);
+        assert_eq!(tokens.verify(&token), Some(user));
+
+        let other = AccessTokens::new(b"other", Duration::from_secs(60));
+        assert_eq!(other.verify(&token), None, "signed with another secret");
+        assert_eq!(tokens.verify("not.a.token"), None);
+
+        let iat = jsonwebtoken::get_current_timestamp() - 120;
+        let claims = Claims {
+            sub: user,
+            iat,
+            exp: iat + 60,
+        };
+        let expired = jsonwebtoken::encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        assert_eq!(tokens.verify(&expired), None);
+
+        let clock = MockClock::new();
+        let tokens = tokens.with_clock(Arc::new(clock.clone()));
+        let token = tokens.issue(user);
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(tokens.verify(&token), Some(user));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(tokens.verify(&token), None);
+    }
+
+    #[test]
+    fn revokes_tokens_issued_before() {
+        let tokens = AccessTokens::new(b"secret", Duration::from_secs(60));
+        let (user, other) = (UserId::new(), UserId::new());
+        let old = tokens.issue(user);
+        let untouched = tokens.issue(other);
+
+        tokens.revoke_all(user);
+        assert_eq!(tokens.verify(&old), None);
+        assert_eq!(tokens.verify(&untouched), Some(other));
+        let new = tokens.issue(user);
+        assert_eq!(tokens.verify(&new), Some(user));
+    }
+
+    #[test]
+    fn forgets_revocations_once_the_tokens_expire() {
+        let clock = MockClock::new();
+        let tokens = AccessTokens::new(b"secret", Duration::from_secs(60))
+            .with_clock(Arc::new(clock.clone()));
+        let (user, other) = (UserId::new(), UserId::new());
+        let old = tokens.issue(user);
+        tokens.revoke_all(user);
+        assert_eq!(tokens.verify(&old), None);
+
+        clock.advance(Duration::from_secs(30));
+        tokens.revoke_all(other);
+        assert_eq!(tokens.lock().len(), 2);
+        assert_eq!(tokens.verify(&old), None);
+        clock.advance(Duration::from_secs(31));
+        tokens.revoke_all(other);
+        assert_eq!(tokens.lock().len(), 1, "tokens revoked first have expired");
+        assert_eq!(tokens.verify(&old), None);
+    }
+}
@@ -1,40 +1,67 @@
-use std::{
-    collections::{HashMap, HashSet},
-    net::SocketAddr,
-    sync::Arc,
-};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
 
 use axum::{
     Json, Router, async_trait,
-    extract::{FromRef, FromRequestParts, Path, State},
-    http::{Method, StatusCode, header, request::Parts},
-    response::{IntoResponse, Response},
-    routing::{get, post},
+    extract::{
+        ConnectInfo, DefaultBodyLimit, FromRef, FromRequestParts, Path, Query, Request, State,
+    },
+    http::{HeaderMap, HeaderName, Method, StatusCode, header, request::Parts},
+    middleware,
+    response::IntoResponse,
+    routing::{delete, get, post, put},
 };
 use clap::{Parser, Subcommand};
+use common::{Clock, StateFile, SystemClock, http_client::HttpClient};
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
-use thiserror::Error;
-use tokio::sync::Mutex;
-use tower_http::cors::{Any, CorsLayer};
-use utoipa::{OpenApi, ToSchema};
+use step_3_8::redact::Redactor;
+use step_4_1::RoleSlug;
+use step_4_1::extract::{
+    CallerPermissions, Permission, PermissionResolver, RequirePermission, RoleDb, SharedResolver,
+    UsersRead, UsersWrite,
+};
+use step_4_1::permissions::PermissionSet;
+use step_4_domain::{
+    Connection, FriendRequest, FriendRequestId, FriendRequests, Role, SNAPSHOT_VERSION, Token,
+    User, UserId, UserService,
+};
+use step_4_errors::{AppError, ensure};
+use step_4_middleware::{
+    AccessLogLayer, AccessUser, EnabledFeatures, Feature, FeatureFlags, PeerAddr, RateLimitLayer,
+    RequestIdLayer, RequestLimitsLayer, features, request_id::REQUEST_ID_HEADER,
+};
+use tower_http::{
+    cors::{Any, CorsLayer},
+    trace::TraceLayer,
+};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 use utoipa_swagger_ui::SwaggerUi;
-use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-struct User {
-    id: Uuid,
-    name: String,
-    #[serde(skip_serializing)]
-    password: String,
-    friends: HashSet<Uuid>,
-}
+mod body_log;
+mod events;
+mod jwt;
+mod output;
+mod probes;
+mod session;
+mod store;
+
+use events::{EventLog, QueueStats};
+use jwt::AccessTokens;
+use output::{FriendGraph, OutputFormat};
+use probes::Readiness;
+use session::TokenStore;
+use store::{SqliteStore, UserStore};
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 struct PublicUser {
-    id: Uuid,
+    #[schema(value_type = String, format = Uuid)]
+    id: UserId,
     name: String,
-    friends: Vec<Uuid>,
+    #[schema(value_type = Vec<String>)]
+    friends: Vec<UserId>,
+    /// Either `user` or `admin`
+    #[schema(value_type = String, example = "user")]
+    role: Role,
 }
 
 impl From<&User> for PublicUser {
@@ -43,6 +70,7 @@ impl From<&User> for PublicUser {
             id: user.id,
             name: user.name.clone(),
             friends: user.friends.iter().copied().collect(),
+            role: user.role,
         }
     }
 }
@@ -53,27 +81,188 @@ struct UserGraph {
     friends: Vec<PublicUser>,
 }
 
-#[derive(Debug, Default, Clone)]
-struct AuthStore {
-    tokens: HashMap<String, Uuid>,
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct MutualFriends {
+    /// Users linked to both users by friendships in any direction
+    friends: Vec<PublicUser>,
+    /// Degrees of separation, absent if the users aren't connected
+    separation: Option<usize>,
+    /// Shortest chain of users from one to the other, both included
+    #[schema(value_type = Vec<String>)]
+    path: Vec<UserId>,
+}
+
+impl From<Connection> for MutualFriends {
+    fn from(connection: Connection) -> Self {
+        Self {
+            friends: connection
+                .mutual_friends
+                .iter()
+                .map(PublicUser::from)
+                .collect(),
+            separation: connection.separation(),
+            path: connection.path.unwrap_or_default(),
+        }
+    }
+}
+
+/// Request of the user `from` to befriend the user `to`.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct PendingRequest {
+    #[schema(value_type = String, format = Uuid)]
+    id: FriendRequestId,
+    #[schema(value_type = String, format = Uuid)]
+    from: UserId,
+    #[schema(value_type = String, format = Uuid)]
+    to: UserId,
+}
+
+impl From<FriendRequest> for PendingRequest {
+    fn from(request: FriendRequest) -> Self {
+        Self {
+            id: request.id,
+            from: request.from,
+            to: request.to,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct PendingRequests {
+    /// Requests waiting for the user to accept or reject them
+    incoming: Vec<PendingRequest>,
+    /// Requests sent by the user and not answered yet
+    outgoing: Vec<PendingRequest>,
+}
+
+impl From<FriendRequests> for PendingRequests {
+    fn from(requests: FriendRequests) -> Self {
+        Self {
+            incoming: requests.incoming.into_iter().map(Into::into).collect(),
+            outgoing: requests.outgoing.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct SharedState {
+    users: UserService,
+    permissions: SharedResolver,
+    events: EventLog,
+    features: FeatureFlags,
+    tokens: AccessTokens,
+}
+
+impl SharedState {
+    fn new(
+        roles: RoleDb,
+        features: FeatureFlags,
+        events: step_3_9::EventQueueConfig,
+        auth: &step_3_9::AuthConfig,
+    ) -> Self {
+        // Both kinds of tokens expire by the same clock. Sessions of the
+        // users are their refresh tokens.
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let users = UserService::new()
+            .with_clock(clock.clone())
+            .with_session_ttl(auth.refresh_ttl);
+        let tokens = AccessTokens::from_config(auth).with_clock(clock);
+        let permissions = Arc::new(TokenPermissions {
+            users: users.clone(),
+            tokens: tokens.clone(),
+            roles,
+        });
+        let events = EventLog::spawn(&users, events);
+        Self {
+            users,
+            permissions,
+            events,
+            features,
+            tokens,
+        }
+    }
+}
+
+impl FromRef<SharedState> for UserService {
+    fn from_ref(state: &SharedState) -> Self {
+        state.users.clone()
+    }
+}
+
+impl FromRef<SharedState> for EventLog {
+    fn from_ref(state: &SharedState) -> Self {
+        state.events.clone()
+    }
 }
 
-#[derive(Debug, Clone, Default)]
-struct AppState {
-    users: HashMap<Uuid, User>,
-    names: HashMap<String, Uuid>,
-    auth: AuthStore,
+impl FromRef<SharedState> for FeatureFlags {
+    fn from_ref(state: &SharedState) -> Self {
+        state.features.clone()
+    }
 }
 
-#[derive(Clone, Default)]
-struct SharedState(Arc<Mutex<AppState>>);
+impl FromRef<SharedState> for AccessTokens {
+    fn from_ref(state: &SharedState) -> Self {
+        state.tokens.clone()
+    }
+}
 
-impl FromRef<SharedState> for Arc<Mutex<AppState>> {
+impl FromRef<SharedState> for SharedResolver {
     fn from_ref(state: &SharedState) -> Self {
-        state.0.clone()
+        state.permissions.clone()
+    }
+}
+
+/// Resolves the caller by its access token and looks up the permissions of
+/// its [`Role`] in the role database managed by `step_4_1`.
+///
+/// Roles are kept by the users themselves, so names play no part in it.
+struct TokenPermissions {
+    users: UserService,
+    tokens: AccessTokens,
+    roles: RoleDb,
+}
+
+#[async_trait]
+impl PermissionResolver for TokenPermissions {
+    async fn resolve(&self, parts: &Parts) -> Result<PermissionSet, AppError> {
+        let token = bearer_token(parts).ok_or(AppError::Unauthorized)?;
+        let id = self.tokens.verify(token).ok_or(AppError::Unauthorized)?;
+        record_user(parts, id);
+        let user = self
+            .users
+            .user(id)
+            .await
+            .map_err(|_| AppError::Unauthorized)?;
+        self.roles.permissions_for_role(&role_slug(user.role))
     }
 }
 
+/// Built-in roles with their names and permissions, seeded into the role
+/// database unless it defines them already.
+const BUILTIN_ROLES: [(Role, &str, &str); 2] = [
+    (Role::User, "User", "[]"),
+    (Role::Admin, "Administrator", r#"["users.*"]"#),
+];
+
+/// Slug of the `role` in the role database.
+fn role_slug(role: Role) -> RoleSlug {
+    role.as_str().parse().expect("roles are valid slugs")
+}
+
+/// Opens the role database, seeding the [`BUILTIN_ROLES`] into it.
+fn open_roles(path: &str) -> anyhow::Result<RoleDb> {
+    let roles = RoleDb::open(path)?;
+    roles.with_db(|db| {
+        BUILTIN_ROLES
+            .iter()
+            .try_for_each(|(role, name, permissions)| {
+                db.seed_role(&role_slug(*role), name, permissions).map(drop)
+            })
+    })?;
+    Ok(roles)
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 struct RegisterPayload {
     name: String,
@@ -86,72 +275,211 @@ struct LoginPayload {
     password: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct RefreshPayload {
+    #[schema(value_type = String)]
+    refresh_token: Token,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct RenamePayload {
+    name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct RolePayload {
+    /// Either `user` or `admin`
+    #[schema(value_type = String, example = "admin")]
+    role: Role,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct ChangePasswordPayload {
+    old_password: String,
+    new_password: String,
+}
+
+/// Default number of users on a page of `GET /users`.
+const DEFAULT_PER_PAGE: usize = 20;
+/// Maximum number of users on a page of `GET /users`.
+const MAX_PER_PAGE: usize = 100;
+
+#[derive(Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ListQuery {
+    /// Words the names of listed users must contain words starting with, all
+    /// users are listed if absent
+    #[serde(default, alias = "query", skip_serializing_if = "Option::is_none")]
+    q: Option<String>,
+    /// Number of the page, starting from 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    page: Option<usize>,
+    /// Users on a page, up to 100, 20 by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    per_page: Option<usize>,
+}
+
+/// Page of users ordered by name.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct UserPage {
+    items: Vec<PublicUser>,
+    /// Number of users on all the pages
+    total: usize,
+    page: usize,
+    per_page: usize,
+}
+
+impl UserPage {
+    fn pages(&self) -> usize {
+        self.total.div_ceil(self.per_page).max(1)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 struct TokenResponse {
+    /// Access token to authenticate requests with
     token: String,
+    /// Seconds until the access token expires
+    expires_in: u64,
+    /// Token issuing new access tokens via `/token/refresh`
+    #[schema(value_type = String)]
+    refresh_token: Token,
 }
 
-#[derive(Error, Debug)]
-enum ApiError {
-    #[error("user already exists")]
-    UserExists,
-    #[error("user not found")]
-    UserNotFound,
-    #[error("invalid credentials")]
-    InvalidCredentials,
-    #[error("not authorized")]
-    Unauthorized,
-    #[error("failed to parse identifier")]
-    BadIdentifier,
-}
-
-impl IntoResponse for ApiError {
-    fn into_response(self) -> Response {
-        let status = match self {
-            ApiError::UserExists | ApiError::InvalidCredentials => StatusCode::BAD_REQUEST,
-            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
-            ApiError::UserNotFound => StatusCode::NOT_FOUND,
-            ApiError::BadIdentifier => StatusCode::BAD_REQUEST,
-        };
-        (status, self.to_string()).into_response()
+impl TokenResponse {
+    fn new(tokens: &AccessTokens, user: UserId, refresh_token: Token) -> Self {
+        Self {
+            token: tokens.issue(user),
+            expires_in: tokens.ttl().as_secs(),
+            refresh_token,
+        }
     }
 }
 
-#[allow(dead_code)]
-struct AuthenticatedUser(Uuid);
+/// Owner of the access token of the request.
+///
+/// Access tokens are verified by their signature alone, so no lock of the
+/// user store is taken.
+struct AuthenticatedUser(UserId);
 
 #[async_trait]
 impl<S> FromRequestParts<S> for AuthenticatedUser
 where
-    Arc<Mutex<AppState>>: FromRef<S>,
+    AccessTokens: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(parts).ok_or(AppError::Unauthorized)?;
+        let user = AccessTokens::from_ref(state)
+            .verify(token)
+            .ok_or(AppError::Unauthorized)?;
+        record_user(parts, user);
+        Ok(Self(user))
+    }
+}
+
+/// Caller allowed to manage other users, as the built-in admin role is.
+///
+/// The permission is looked up for the role of the user in the role database,
+/// see [`TokenPermissions`].
+type RequireAdmin = RequirePermission<UsersWrite>;
+
+/// Names the user in the access log of the request.
+fn record_user(parts: &Parts, user: UserId) {
+    if let Some(access) = parts.extensions.get::<AccessUser>() {
+        access.record(user);
+    }
+}
+
+/// Feature flags of the request, with its signed debug overrides applied.
+struct Features(EnabledFeatures);
+
+impl Features {
+    /// Rejects the request if the `feature` is disabled for it.
+    fn require(&self, feature: Feature) -> Result<(), AppError> {
+        ensure!(
+            self.0.is_enabled(feature),
+            NotFound,
+            "feature `{feature}` is disabled"
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Features
+where
+    FeatureFlags: FromRef<S>,
     S: Send + Sync,
 {
-    type Rejection = ApiError;
+    type Rejection = std::convert::Infallible;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let token = parts
-            .headers
-            .get(header::AUTHORIZATION)
-            .and_then(|value| value.to_str().ok())
-            .and_then(|value| value.strip_prefix("Bearer "))
-            .ok_or(ApiError::Unauthorized)?
-            .to_string();
+        Ok(Self(
+            FeatureFlags::from_ref(state).for_request(&parts.headers),
+        ))
+    }
+}
 
-        let state: Arc<Mutex<AppState>> = Arc::from_ref(state);
-        let guard = state.lock().await;
-        let user = guard
-            .auth
-            .tokens
-            .get(&token)
-            .ok_or(ApiError::Unauthorized)?;
-        Ok(Self(*user))
+/// Exposes the address of the client to the [`RateLimitLayer`].
+async fn peer_addr(connection: Option<ConnectInfo<SocketAddr>>, mut request: Request) -> Request {
+    if let Some(ConnectInfo(addr)) = connection {
+        request.extensions_mut().insert(PeerAddr(addr.ip()));
     }
+    request
+}
+
+/// Extracts the `Authorization: Bearer` token, without checking it.
+fn bearer_token(parts: &Parts) -> Option<&str> {
+    parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
 }
 
 #[derive(OpenApi)]
 #[openapi(
-    paths(register_user, login_user, get_user_graph, add_friend, remove_friend),
-    components(schemas(RegisterPayload, LoginPayload, TokenResponse, UserGraph, PublicUser)),
+    paths(
+        register_user,
+        login_user,
+        refresh_token,
+        list_users,
+        get_user_graph,
+        get_mutual_friends,
+        send_friend_request,
+        list_friend_requests,
+        accept_friend_request,
+        reject_friend_request,
+        remove_friend,
+        get_me,
+        rename_me,
+        change_password,
+        logout,
+        event_stream,
+        admin_list_users,
+        admin_delete_user,
+        admin_change_role,
+        admin_event_stats
+    ),
+    components(schemas(
+        RegisterPayload,
+        LoginPayload,
+        RefreshPayload,
+        RenamePayload,
+        ChangePasswordPayload,
+        RolePayload,
+        TokenResponse,
+        UserGraph,
+        MutualFriends,
+        PendingRequest,
+        PendingRequests,
+        PublicUser,
+        UserPage,
+        QueueStats
+    )),
     tags((name = "api", description = "Simple REST API"))
 )]
 struct ApiDoc;
@@ -159,16 +487,40 @@ struct ApiDoc;
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Cli::parse();
+    let output = args.output;
+    let client = HttpClient::new()?;
+    let tokens = || match &args.token_file {
+        Some(path) => Ok(TokenStore::new(path)),
+        None => TokenStore::default_location(),
+    };
 
     match args.command {
-        Command::Server { addr } => run_server(addr).await?,
-        Command::Register {
-            server,
-            name,
-            password,
+        Command::Server {
+            addr,
+            roles_db,
+            config,
+            debug,
+            state_file,
+            database,
+            admins,
         } => {
+            let config = step_3_9::load_config(&step_3_9::Cli {
+                conf: config,
+                conf_format: None,
+                profile: None,
+                debug,
+            })?;
+            let store: Option<Arc<dyn UserStore>> = match (database, state_file) {
+                (Some(path), _) => Some(Arc::new(SqliteStore::open(path).await?)),
+                (None, Some(path)) => Some(Arc::new(StateFile::new(path, SNAPSHOT_VERSION))),
+                (None, None) => None,
+            };
+            run_server(addr, &roles_db, config, store, &admins).await?
+        }
+        Command::Register { server, name } => {
+            let password = read_password(PASSWORD_ENV, "Password: ")?;
             let payload = RegisterPayload { name, password };
-            let _ = reqwest::Client::new()
+            let _ = client
                 .post(url(&server, "/register")?)
                 .json(&payload)
                 .send()
@@ -176,45 +528,193 @@ async fn main() -> anyhow::Result<()> {
                 .error_for_status()?;
             println!("Registered successfully");
         }
-        Command::Login {
+        Command::Login { server, name } => {
+            let password = read_password(PASSWORD_ENV, "Password: ")?;
+            let payload = LoginPayload { name, password };
+            let token: TokenResponse = client
+                .post(url(&server, "/login")?)
+                .json(&payload)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            let tokens = tokens()?;
+            tokens.save(&server, token.refresh_token.as_str())?;
+            println!("Token saved to {}", tokens.path().display());
+        }
+        Command::Whoami { server, token } => {
+            let token = access_token(&client, &tokens()?, &server, token).await?;
+            let user: PublicUser = client
+                .get(url(&server, "/users/me")?)
+                .bearer_auth(token)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            println!("{}", output::render(output, &user)?);
+        }
+        Command::Rename {
             server,
+            token,
             name,
-            password,
         } => {
-            let payload = LoginPayload { name, password };
-            let token: TokenResponse = reqwest::Client::new()
-                .post(url(&server, "/login")?)
+            let token = access_token(&client, &tokens()?, &server, token).await?;
+            let user: PublicUser = client
+                .patch(url(&server, "/users/me")?)
+                .bearer_auth(token)
+                .json(&RenamePayload { name })
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            println!("{}", output::render(output, &user)?);
+        }
+        Command::ChangePassword { server, token } => {
+            let tokens = tokens()?;
+            let token = access_token(&client, &tokens, &server, token).await?;
+            let payload = ChangePasswordPayload {
+                old_password: read_password(PASSWORD_ENV, "Old password: ")?,
+                new_password: read_password(NEW_PASSWORD_ENV, "New password: ")?,
+            };
+            let token: TokenResponse = client
+                .post(url(&server, "/users/me/password")?)
+                .bearer_auth(token)
                 .json(&payload)
                 .send()
                 .await?
                 .error_for_status()?
                 .json()
                 .await?;
-            println!("Token: {}", token.token);
+            tokens.save(&server, token.refresh_token.as_str())?;
+            println!(
+                "Password changed, other sessions revoked and token saved to {}",
+                tokens.path().display()
+            );
+        }
+        Command::RevokeToken { server, token } => {
+            let tokens = tokens()?;
+            let token = resolve_token(&tokens, &server, token)?;
+            let payload = RefreshPayload {
+                refresh_token: token.parse()?,
+            };
+            client
+                .post(url(&server, "/logout")?)
+                .json(&payload)
+                .send()
+                .await?
+                .error_for_status()?;
+            if tokens.get(&server)?.as_ref() == Some(&token) {
+                tokens.remove(&server)?;
+            }
+            println!("Token revoked");
+        }
+        Command::SearchUsers {
+            server,
+            token,
+            query,
+        } => {
+            let token = access_token(&client, &tokens()?, &server, token).await?;
+            let users = search_all_users(&client, &server, &token, &query).await?;
+            println!("{}", output::render(output, &users)?);
+        }
+        Command::BrowseUsers {
+            server,
+            token,
+            query,
+            page,
+            per_page,
+        } => {
+            let token = access_token(&client, &tokens()?, &server, token).await?;
+            let query = ListQuery {
+                q: query,
+                page: Some(page),
+                per_page: Some(per_page),
+            };
+            let page = fetch_user_page(&client, &server, &token, &query).await?;
+            println!("{}", output::render(output, &page)?);
+            if output == OutputFormat::Table {
+                println!(
+                    "\nPage {} of {}, {} users in total",
+                    page.page,
+                    page.pages(),
+                    page.total
+                );
+            }
         }
         Command::GetUser { server, token, id } => {
-            let response = reqwest::Client::new()
+            let token = access_token(&client, &tokens()?, &server, token).await?;
+            let response = client
                 .get(url(&server, &format!("/users/{id}"))?)
                 .bearer_auth(token)
                 .send()
                 .await?
                 .error_for_status()?;
             let graph: UserGraph = response.json().await?;
-            println!("{}", serde_json::to_string_pretty(&graph)?);
+            println!("{}", output::render(output, &graph)?);
         }
-        Command::AddFriend {
+        Command::MutualFriends {
             server,
             token,
             id,
-            friend_id,
+            other_id,
         } => {
-            reqwest::Client::new()
-                .post(url(&server, &format!("/users/{id}/friends/{friend_id}"))?)
+            let token = access_token(&client, &tokens()?, &server, token).await?;
+            let mutual: MutualFriends = client
+                .get(url(&server, &format!("/users/{id}/mutual/{other_id}"))?)
+                .bearer_auth(token)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            println!("{}", output::render(output, &mutual)?);
+        }
+        Command::SendFriendRequest { server, token, id } => {
+            let token = access_token(&client, &tokens()?, &server, token).await?;
+            let request: PendingRequest = client
+                .post(url(&server, &format!("/users/{id}/friend-requests"))?)
+                .bearer_auth(token)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            println!("Friend request {} sent", request.id);
+        }
+        Command::FriendRequests { server, token } => {
+            let token = access_token(&client, &tokens()?, &server, token).await?;
+            let requests: PendingRequests = client
+                .get(url(&server, "/friend-requests")?)
+                .bearer_auth(token)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            println!("{}", output::render(output, &requests)?);
+        }
+        Command::AcceptFriendRequest { server, token, id } => {
+            let token = access_token(&client, &tokens()?, &server, token).await?;
+            client
+                .post(url(&server, &format!("/friend-requests/{id}/accept"))?)
+                .bearer_auth(token)
+                .send()
+                .await?
+                .error_for_status()?;
+            println!("Friend request accepted");
+        }
+        Command::RejectFriendRequest { server, token, id } => {
+            let token = access_token(&client, &tokens()?, &server, token).await?;
+            client
+                .post(url(&server, &format!("/friend-requests/{id}/reject"))?)
                 .bearer_auth(token)
                 .send()
                 .await?
                 .error_for_status()?;
-            println!("Friend added");
+            println!("Friend request rejected");
         }
         Command::RemoveFriend {
             server,
@@ -222,7 +722,8 @@ async fn main() -> anyhow::Result<()> {
             id,
             friend_id,
         } => {
-            reqwest::Client::new()
+            let token = access_token(&client, &tokens()?, &server, token).await?;
+            client
                 .post(url(
                     &server,
                     &format!("/users/{id}/friends/{friend_id}/remove"),
@@ -233,6 +734,53 @@ async fn main() -> anyhow::Result<()> {
                 .error_for_status()?;
             println!("Friend removed");
         }
+        Command::ListUsers { server, token } => {
+            let users = fetch_all_users(
+                &client,
+                &server,
+                &access_token(&client, &tokens()?, &server, token).await?,
+            )
+            .await?;
+            println!("{}", output::render(output, &users)?);
+        }
+        Command::DeleteUser { server, token, id } => {
+            let token = access_token(&client, &tokens()?, &server, token).await?;
+            client
+                .delete(url(&server, &format!("/admin/users/{id}"))?)
+                .bearer_auth(token)
+                .send()
+                .await?
+                .error_for_status()?;
+            println!("User deleted");
+        }
+        Command::SetRole {
+            server,
+            token,
+            id,
+            role,
+        } => {
+            let token = access_token(&client, &tokens()?, &server, token).await?;
+            let user: PublicUser = client
+                .put(url(&server, &format!("/admin/users/{id}/role"))?)
+                .bearer_auth(token)
+                .json(&RolePayload { role })
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            println!("{}", output::render(output, &user)?);
+        }
+        Command::ExportGraph { server, token } => {
+            let users = fetch_all_users(
+                &client,
+                &server,
+                &access_token(&client, &tokens()?, &server, token).await?,
+            )
+            .await?;
+            let graph = FriendGraph::from_users(&users);
+            println!("{}", output::render(output, &graph)?);
+        }
     }
 
     Ok(())
@@ -242,27 +790,251 @@ fn url(base: &str, path: &str) -> Result<Url, anyhow::Error> {
     Ok(Url::parse(base)?.join(path)?)
 }
 
-async fn run_server(addr: SocketAddr) -> anyhow::Result<()> {
-    let state = SharedState::default();
-    let router = Router::new()
+/// Takes the password from the `env` variable or prompts for it
+/// interactively.
+fn read_password(env: &str, prompt: &str) -> std::io::Result<String> {
+    match std::env::var(env) {
+        Ok(password) => Ok(password),
+        Err(_) => rpassword::prompt_password(prompt),
+    }
+}
+
+/// Picks the explicitly passed token or the refresh token saved by the
+/// `login` command.
+fn resolve_token(
+    tokens: &TokenStore,
+    server: &str,
+    token: Option<String>,
+) -> anyhow::Result<String> {
+    match token {
+        Some(token) => Ok(token),
+        None => tokens.get(server)?.ok_or_else(|| {
+            anyhow::anyhow!("no saved token for {server}, run `login` or pass `--token`")
+        }),
+    }
+}
+
+/// Picks the explicitly passed access token or exchanges the refresh token
+/// saved by the `login` command for a new one.
+async fn access_token(
+    client: &HttpClient,
+    tokens: &TokenStore,
+    server: &str,
+    token: Option<String>,
+) -> anyhow::Result<String> {
+    if let Some(token) = token {
+        return Ok(token);
+    }
+    let payload = RefreshPayload {
+        refresh_token: resolve_token(tokens, server, None)?.parse()?,
+    };
+    let response: TokenResponse = client
+        .post(url(server, "/token/refresh")?)
+        .json(&payload)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(response.token)
+}
+
+async fn fetch_all_users(
+    client: &HttpClient,
+    server: &str,
+    token: &str,
+) -> anyhow::Result<Vec<PublicUser>> {
+    Ok(client
+        .get(url(server, "/admin/users")?)
+        .bearer_auth(token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+/// Fetches a page of `GET /users`.
+async fn fetch_user_page(
+    client: &HttpClient,
+    server: &str,
+    token: &str,
+    query: &ListQuery,
+) -> anyhow::Result<UserPage> {
+    Ok(client
+        .get(url(server, "/users")?)
+        .query(query)
+        .bearer_auth(token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+/// Fetches every page of the users whose names match the `query`.
+async fn search_all_users(
+    client: &HttpClient,
+    server: &str,
+    token: &str,
+    query: &str,
+) -> anyhow::Result<Vec<PublicUser>> {
+    let mut users = Vec::new();
+    for page in 1.. {
+        let query = ListQuery {
+            q: Some(query.to_owned()),
+            page: Some(page),
+            per_page: Some(MAX_PER_PAGE),
+        };
+        let page = fetch_user_page(client, server, token, &query).await?;
+        let last = page.page >= page.pages();
+        users.extend(page.items);
+        if last {
+            break;
+        }
+    }
+    Ok(users)
+}
+
+async fn run_server(
+    addr: SocketAddr,
+    roles_db: &str,
+    config: step_3_9::AppConfig,
+    store: Option<Arc<dyn UserStore>>,
+    admins: &[UserId],
+) -> anyhow::Result<()> {
+    let mut filter = config.log.app.level.clone();
+    if config.mode.debug {
+        filter.push_str(&format!(",{}=debug", body_log::TARGET));
+    }
+    step_3_8::init_logging(&filter).map_err(|err| anyhow::anyhow!("{err}"))?;
+
+    let features = FeatureFlags::new(&config.features, config.mode.debug);
+    let state = SharedState::new(
+        open_roles(roles_db)?,
+        features,
+        config.server.events.clone(),
+        &config.server.auth,
+    );
+    let snapshot = match &store {
+        Some(store) => store.load().await?,
+        None => None,
+    };
+    if let Some(snapshot) = snapshot {
+        state.users.restore(snapshot).await;
+    }
+    let persisting = match &store {
+        Some(store) => Some(store::persist(&state.users, store.clone()).await),
+        None => None,
+    };
+    for id in admins {
+        if let Err(err) = state.users.change_role(*id, Role::Admin).await {
+            tracing::warn!(%id, "can't make the user an admin: {err}");
+        }
+    }
+    let readiness = Readiness::default();
+    let router = build_router(state.clone(), &config, readiness.clone());
+
+    println!("Running server on {addr}");
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    readiness.set(true);
+    let events = state.events.clone();
+    let shutdown = async move {
+        common::shutdown_signal().await;
+        // In-flight requests finish, while new ones are routed elsewhere and
+        // the never-ending event streams are cut.
+        readiness.set(false);
+        events.close();
+        println!("Shutting down");
+    };
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown)
+    .await?;
+
+    // The final save below covers whatever the task hasn't saved yet.
+    if let Some(task) = persisting {
+        task.abort();
+    }
+    if let Some(store) = store {
+        store.save(&state.users.snapshot().await).await?;
+        println!("State saved to {}", store.path().display());
+    }
+    Ok(())
+}
+
+/// Routes of the API with every layer of the server, reporting the
+/// `readiness` in its probes.
+pub(crate) fn build_router(
+    state: SharedState,
+    config: &step_3_9::AppConfig,
+    readiness: Readiness,
+) -> Router {
+    let mut router = Router::new()
         .route("/register", post(register_user))
         .route("/login", post(login_user))
+        .route("/token/refresh", post(refresh_token))
+        .route("/users", get(list_users))
         .route("/users/:id", get(get_user_graph))
-        .route("/users/:id/friends/:friend_id", post(add_friend))
+        .route("/users/:id/mutual/:other_id", get(get_mutual_friends))
+        .route("/users/:id/friend-requests", post(send_friend_request))
+        .route("/friend-requests", get(list_friend_requests))
+        .route("/friend-requests/:id/accept", post(accept_friend_request))
+        .route("/friend-requests/:id/reject", post(reject_friend_request))
         .route("/users/:id/friends/:friend_id/remove", post(remove_friend))
+        .route("/users/me", get(get_me).patch(rename_me))
+        .route("/users/me/password", post(change_password))
+        // Kept for the clients predating `/users/me`.
+        .route("/me", get(get_me).patch(rename_me))
+        .route("/logout", post(logout))
+        .route("/events", get(event_stream))
+        .route("/admin/users", get(admin_list_users))
+        .route("/admin/users/:id", delete(admin_delete_user))
+        .route("/admin/users/:id/role", put(admin_change_role))
+        .route("/admin/events", get(admin_event_stats))
         .merge(SwaggerUi::new("/docs").url("/api-doc/openapi.json", ApiDoc::openapi()))
-        .with_state(state.clone())
+        .with_state(state)
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
-                .allow_methods([Method::GET, Method::POST])
-                .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION]),
+                .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
+                .allow_headers([
+                    header::CONTENT_TYPE,
+                    header::AUTHORIZATION,
+                    HeaderName::from_static(features::OVERRIDE_HEADER),
+                    REQUEST_ID_HEADER,
+                ])
+                .expose_headers([REQUEST_ID_HEADER]),
         );
-
-    println!("Running server on {addr}");
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, router.into_make_service()).await?;
-    Ok(())
+    if config.mode.debug {
+        let redactor = Arc::new(Redactor::new(config.log.redact.clone()));
+        router = router.layer(middleware::from_fn_with_state(
+            redactor,
+            body_log::log_bodies,
+        ));
+    }
+    // Outermost, so bodies are limited before even the debug logging reads them,
+    // requests over the rate limits don't get that far but are still logged,
+    // and everything logged while handling a request carries its ID.
+    router
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestLimitsLayer::new(config.server.limits.clone()))
+        .layer(RateLimitLayer::new(config.server.rate_limits.clone()))
+        .layer(middleware::map_request(peer_addr))
+        // Server errors are logged within the span of the `RequestIdLayer`,
+        // with their latency.
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(|_: &Request| tracing::Span::current())
+                .on_request(())
+                .on_response(()),
+        )
+        .layer(AccessLogLayer)
+        .layer(RequestIdLayer)
+        // Probes are merged last, so none of the layers above rejects them.
+        .merge(probes::router(readiness))
 }
 
 #[utoipa::path(
@@ -271,27 +1043,14 @@ async fn run_server(addr: SocketAddr) -> anyhow::Result<()> {
     request_body = RegisterPayload,
     responses(
         (status = 200, description = "User registered"),
-        (status = 400, description = "User already exists"),
+        (status = 400, description = "User already exists or invalid credentials"),
     )
 )]
 async fn register_user(
-    State(state): State<Arc<Mutex<AppState>>>,
+    State(users): State<UserService>,
     Json(payload): Json<RegisterPayload>,
-) -> Result<StatusCode, ApiError> {
-    let mut state = state.lock().await;
-    if state.names.contains_key(&payload.name) {
-        return Err(ApiError::UserExists);
-    }
-
-    let id = Uuid::new_v4();
-    let user = User {
-        id,
-        name: payload.name.clone(),
-        password: payload.password,
-        friends: HashSet::new(),
-    };
-    state.names.insert(user.name.clone(), id);
-    state.users.insert(id, user);
+) -> Result<StatusCode, AppError> {
+    users.register(&payload.name, &payload.password).await?;
     Ok(StatusCode::OK)
 }
 
@@ -300,85 +1059,218 @@ async fn register_user(
     path = "/login",
     request_body = LoginPayload,
     responses(
-        (status = 200, body = TokenResponse, description = "Token issued"),
+        (status = 200, body = TokenResponse, description = "Tokens issued"),
         (status = 400, description = "Invalid credentials"),
     )
 )]
 async fn login_user(
-    State(state): State<Arc<Mutex<AppState>>>,
+    State(users): State<UserService>,
+    State(tokens): State<AccessTokens>,
     Json(payload): Json<LoginPayload>,
-) -> Result<Json<TokenResponse>, ApiError> {
-    let mut state = state.lock().await;
-    let user_id = state
-        .names
-        .get(&payload.name)
-        .and_then(|id| state.users.get(id))
-        .filter(|user| user.password == payload.password)
-        .map(|user| user.id)
-        .ok_or(ApiError::InvalidCredentials)?;
+) -> Result<Json<TokenResponse>, AppError> {
+    let session = users.login(&payload.name, &payload.password).await?;
+    Ok(Json(TokenResponse::new(
+        &tokens,
+        session.user_id,
+        session.token,
+    )))
+}
 
-    let token = Uuid::new_v4().to_string();
-    state.auth.tokens.insert(token.clone(), user_id);
-    Ok(Json(TokenResponse { token }))
+#[utoipa::path(
+    post,
+    path = "/token/refresh",
+    request_body = RefreshPayload,
+    responses(
+        (status = 200, body = TokenResponse, description = "Access token issued"),
+        (status = 401, description = "Unknown or expired refresh token"),
+    )
+)]
+async fn refresh_token(
+    State(users): State<UserService>,
+    State(tokens): State<AccessTokens>,
+    Json(payload): Json<RefreshPayload>,
+) -> Result<Json<TokenResponse>, AppError> {
+    let user = users
+        .authenticate(&payload.refresh_token)
+        .await
+        .ok_or(AppError::Unauthorized)?;
+    Ok(Json(TokenResponse::new(
+        &tokens,
+        user,
+        payload.refresh_token,
+    )))
 }
 
 #[utoipa::path(
     get,
-    path = "/users/{id}",
+    path = "/users",
+    params(ListQuery),
     responses(
-        (status = 200, body = UserGraph, description = "User with friends"),
+        (status = 200, body = UserPage, description = "Page of matching users ordered by name"),
+        (status = 400, description = "Invalid page or page size"),
         (status = 401, description = "Unauthorized"),
-        (status = 404, description = "User not found"),
     ),
     security(("token" = []))
 )]
-async fn get_user_graph(
-    State(state): State<Arc<Mutex<AppState>>>,
-    Path(id): Path<String>,
+async fn list_users(
+    State(users): State<UserService>,
+    Query(query): Query<ListQuery>,
     _auth: AuthenticatedUser,
-) -> Result<Json<UserGraph>, ApiError> {
-    let id = Uuid::parse_str(&id).map_err(|_| ApiError::BadIdentifier)?;
-    let state = state.lock().await;
-    let user = state.users.get(&id).ok_or(ApiError::UserNotFound)?;
-    let user_friends: Vec<PublicUser> = user
-        .friends
+) -> Result<Json<UserPage>, AppError> {
+    let page = query.page.unwrap_or(1);
+    let per_page = query.per_page.unwrap_or(DEFAULT_PER_PAGE);
+    ensure!(page > 0, BadRequest, "page must start from 1");
+    ensure!(
+        (1..=MAX_PER_PAGE).contains(&per_page),
+        BadRequest,
+        "per_page must be between 1 and {MAX_PER_PAGE}"
+    );
+
+    let found = match query.q.as_deref().map(str::trim) {
+        Some(q) if !q.is_empty() => users.search(q).await,
+        _ => users.list().await,
+    };
+    let items = found
         .iter()
-        .filter_map(|friend_id| state.users.get(friend_id))
+        .skip((page - 1).saturating_mul(per_page))
+        .take(per_page)
         .map(PublicUser::from)
         .collect();
+    Ok(Json(UserPage {
+        items,
+        total: found.len(),
+        page,
+        per_page,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{id}",
+    responses(
+        (status = 200, body = UserGraph, description = "User with friends"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "User not found"),
+    ),
+    security(("token" = []))
+)]
+async fn get_user_graph(
+    State(users): State<UserService>,
+    Path(id): Path<UserId>,
+    _auth: AuthenticatedUser,
+) -> Result<Json<UserGraph>, AppError> {
+    let user = users.user(id).await?;
+    let friends = users.friends(id).await?;
     let graph = UserGraph {
-        user: PublicUser::from(user),
-        friends: user_friends,
+        user: PublicUser::from(&user),
+        friends: friends.iter().map(PublicUser::from).collect(),
     };
     Ok(Json(graph))
 }
 
 #[utoipa::path(
-    post,
-    path = "/users/{id}/friends/{friend_id}",
+    get,
+    path = "/users/{id}/mutual/{other_id}",
     responses(
-        (status = 200, description = "Friend added"),
-        (status = 400, description = "Invalid identifiers"),
+        (status = 200, body = MutualFriends, description = "Mutual friends and separation"),
         (status = 401, description = "Unauthorized"),
         (status = 404, description = "User not found"),
     ),
     security(("token" = []))
 )]
-async fn add_friend(
-    State(state): State<Arc<Mutex<AppState>>>,
-    Path((id, friend_id)): Path<(String, String)>,
+async fn get_mutual_friends(
+    State(users): State<UserService>,
+    Path((id, other_id)): Path<(UserId, UserId)>,
     _auth: AuthenticatedUser,
-) -> Result<StatusCode, ApiError> {
-    let id = Uuid::parse_str(&id).map_err(|_| ApiError::BadIdentifier)?;
-    let friend_id = Uuid::parse_str(&friend_id).map_err(|_| ApiError::BadIdentifier)?;
+) -> Result<Json<MutualFriends>, AppError> {
+    let connection = users.connection(id, other_id).await?;
+    Ok(Json(connection.into()))
+}
 
-    let mut state = state.lock().await;
-    if !state.users.contains_key(&friend_id) {
-        return Err(ApiError::UserNotFound);
-    }
-    let user = state.users.get_mut(&id).ok_or(ApiError::UserNotFound)?;
-    user.friends.insert(friend_id);
-    Ok(StatusCode::OK)
+#[utoipa::path(
+    post,
+    path = "/users/{id}/friend-requests",
+    responses(
+        (status = 200, body = PendingRequest, description = "Friend request sent to the user"),
+        (status = 400, description = "Invalid identifier or self-friendship"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "User not found or friend requests disabled"),
+        (status = 409, description = "Already friends or a request is pending"),
+    ),
+    security(("token" = []))
+)]
+async fn send_friend_request(
+    State(users): State<UserService>,
+    Path(id): Path<UserId>,
+    AuthenticatedUser(sender): AuthenticatedUser,
+    features: Features,
+) -> Result<Json<PendingRequest>, AppError> {
+    features.require(Feature::FriendRequests)?;
+    let request = users.send_friend_request(sender, id).await?;
+    Ok(Json(request.into()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/friend-requests",
+    responses(
+        (status = 200, body = PendingRequests, description = "Pending requests of the token owner"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Friend requests disabled"),
+    ),
+    security(("token" = []))
+)]
+async fn list_friend_requests(
+    State(users): State<UserService>,
+    AuthenticatedUser(id): AuthenticatedUser,
+    features: Features,
+) -> Result<Json<PendingRequests>, AppError> {
+    features.require(Feature::FriendRequests)?;
+    Ok(Json(users.friend_requests(id).await.into()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/friend-requests/{id}/accept",
+    responses(
+        (status = 200, body = PendingRequest, description = "Request accepted, users are friends"),
+        (status = 400, description = "Invalid identifier"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "No such request sent to the token owner"),
+    ),
+    security(("token" = []))
+)]
+async fn accept_friend_request(
+    State(users): State<UserService>,
+    Path(id): Path<FriendRequestId>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    features: Features,
+) -> Result<Json<PendingRequest>, AppError> {
+    features.require(Feature::FriendRequests)?;
+    let request = users.accept_friend_request(id, user).await?;
+    Ok(Json(request.into()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/friend-requests/{id}/reject",
+    responses(
+        (status = 200, body = PendingRequest, description = "Request rejected"),
+        (status = 400, description = "Invalid identifier"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "No such request sent to the token owner"),
+    ),
+    security(("token" = []))
+)]
+async fn reject_friend_request(
+    State(users): State<UserService>,
+    Path(id): Path<FriendRequestId>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    features: Features,
+) -> Result<Json<PendingRequest>, AppError> {
+    features.require(Feature::FriendRequests)?;
+    let request = users.reject_friend_request(id, user).await?;
+    Ok(Json(request.into()))
 }
 
 #[utoipa::path(
@@ -388,29 +1280,224 @@ async fn add_friend(
         (status = 200, description = "Friend removed"),
         (status = 400, description = "Invalid identifiers"),
         (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Friends of another user without `users.write` permission"),
         (status = 404, description = "User not found"),
     ),
     security(("token" = []))
 )]
 async fn remove_friend(
-    State(state): State<Arc<Mutex<AppState>>>,
-    Path((id, friend_id)): Path<(String, String)>,
+    State(users): State<UserService>,
+    Path((id, friend_id)): Path<(UserId, UserId)>,
+    AuthenticatedUser(caller): AuthenticatedUser,
+    permissions: CallerPermissions,
+) -> Result<StatusCode, AppError> {
+    // Only admins may touch the friends of others.
+    if caller != id {
+        permissions.ensure(UsersWrite::NAME)?;
+    }
+    users.remove_friend(id, friend_id).await?;
+    Ok(StatusCode::OK)
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/me",
+    responses(
+        (status = 200, body = PublicUser, description = "User owning the token"),
+        (status = 401, description = "Unauthorized"),
+    ),
+    security(("token" = []))
+)]
+async fn get_me(
+    State(users): State<UserService>,
+    AuthenticatedUser(id): AuthenticatedUser,
+) -> Result<Json<PublicUser>, AppError> {
+    Ok(Json(PublicUser::from(&users.user(id).await?)))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/users/me",
+    request_body = RenamePayload,
+    responses(
+        (status = 200, body = PublicUser, description = "User renamed"),
+        (status = 400, description = "Name is empty or already taken"),
+        (status = 401, description = "Unauthorized"),
+    ),
+    security(("token" = []))
+)]
+async fn rename_me(
+    State(users): State<UserService>,
+    AuthenticatedUser(id): AuthenticatedUser,
+    Json(payload): Json<RenamePayload>,
+) -> Result<Json<PublicUser>, AppError> {
+    Ok(Json(PublicUser::from(
+        &users.rename(id, &payload.name).await?,
+    )))
+}
+
+/// Revokes every refresh and access token of the user, returning a new pair.
+#[utoipa::path(
+    post,
+    path = "/users/me/password",
+    request_body = ChangePasswordPayload,
+    responses(
+        (status = 200, body = TokenResponse, description = "Password changed, other sessions revoked"),
+        (status = 400, description = "Old password is wrong or new one is empty or the same"),
+        (status = 401, description = "Unauthorized"),
+    ),
+    security(("token" = []))
+)]
+async fn change_password(
+    State(users): State<UserService>,
+    State(tokens): State<AccessTokens>,
+    AuthenticatedUser(id): AuthenticatedUser,
+    Json(payload): Json<ChangePasswordPayload>,
+) -> Result<Json<TokenResponse>, AppError> {
+    let session = users
+        .change_password(id, &payload.old_password, &payload.new_password)
+        .await?;
+    tokens.revoke_all(id);
+    Ok(Json(TokenResponse::new(&tokens, id, session.token)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/logout",
+    request_body = RefreshPayload,
+    responses(
+        (status = 200, description = "Refresh token revoked"),
+        (status = 401, description = "Unknown refresh token"),
+    )
+)]
+async fn logout(
+    State(users): State<UserService>,
+    Json(payload): Json<RefreshPayload>,
+) -> Result<StatusCode, AppError> {
+    users
+        .revoke(&payload.refresh_token)
+        .await
+        .ok_or(AppError::Unauthorized)?;
+    Ok(StatusCode::OK)
+}
+
+#[utoipa::path(
+    get,
+    path = "/events",
+    params(
+        ("Last-Event-ID" = Option<u64>, Header, description = "Id of the last received event to resume after"),
+    ),
+    responses(
+        (status = 200, content_type = "text/event-stream", description = "Stream of user events"),
+        (status = 401, description = "Unauthorized"),
+    ),
+    security(("token" = []))
+)]
+async fn event_stream(
+    log: State<EventLog>,
+    headers: HeaderMap,
     _auth: AuthenticatedUser,
-) -> Result<StatusCode, ApiError> {
-    let id = Uuid::parse_str(&id).map_err(|_| ApiError::BadIdentifier)?;
-    let friend_id = Uuid::parse_str(&friend_id).map_err(|_| ApiError::BadIdentifier)?;
+) -> impl IntoResponse {
+    events::stream_events(log, headers).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/users",
+    responses(
+        (status = 200, body = [PublicUser], description = "All registered users"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Missing `users.read` permission"),
+    ),
+    security(("token" = []))
+)]
+async fn admin_list_users(
+    State(users): State<UserService>,
+    _permission: RequirePermission<UsersRead>,
+) -> Json<Vec<PublicUser>> {
+    Json(users.list().await.iter().map(PublicUser::from).collect())
+}
 
-    let mut state = state.lock().await;
-    let user = state.users.get_mut(&id).ok_or(ApiError::UserNotFound)?;
-    user.friends.remove(&friend_id);
+#[utoipa::path(
+    delete,
+    path = "/admin/users/{id}",
+    responses(
+        (status = 200, description = "User deleted"),
+        (status = 400, description = "Invalid identifier"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Missing `users.write` permission"),
+        (status = 404, description = "User not found"),
+    ),
+    security(("token" = []))
+)]
+async fn admin_delete_user(
+    State(users): State<UserService>,
+    State(tokens): State<AccessTokens>,
+    Path(id): Path<UserId>,
+    _admin: RequireAdmin,
+) -> Result<StatusCode, AppError> {
+    users.delete(id).await?;
+    // Access tokens outlive the sessions deleted along with the user.
+    tokens.revoke_all(id);
     Ok(StatusCode::OK)
 }
 
+#[utoipa::path(
+    put,
+    path = "/admin/users/{id}/role",
+    request_body = RolePayload,
+    responses(
+        (status = 200, body = PublicUser, description = "Role changed"),
+        (status = 400, description = "Invalid identifier or role"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Missing `users.write` permission"),
+        (status = 404, description = "User not found"),
+    ),
+    security(("token" = []))
+)]
+async fn admin_change_role(
+    State(users): State<UserService>,
+    Path(id): Path<UserId>,
+    _admin: RequireAdmin,
+    Json(payload): Json<RolePayload>,
+) -> Result<Json<PublicUser>, AppError> {
+    let user = users.change_role(id, payload.role).await?;
+    Ok(Json(PublicUser::from(&user)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/events",
+    responses(
+        (status = 200, body = QueueStats, description = "Counters of the event stream queues"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Missing `users.read` permission"),
+    ),
+    security(("token" = []))
+)]
+async fn admin_event_stats(
+    State(log): State<EventLog>,
+    _permission: RequirePermission<UsersRead>,
+) -> Json<QueueStats> {
+    Json(log.stats())
+}
+
+/// Environment variable to take passwords from instead of prompting for them.
+const PASSWORD_ENV: &str = "API_PASSWORD";
+/// Environment variable to take the new password of `change-password` from.
+const NEW_PASSWORD_ENV: &str = "API_NEW_PASSWORD";
+
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Simple REST API server and client")]
 struct Cli {
     #[command(subcommand)]
     command: Command,
+    /// Format of the client commands output
+    #[arg(long, global = true, value_enum, default_value_t)]
+    output: OutputFormat,
+    /// File to store tokens in, instead of the OS-specific config directory
+    #[arg(long, global = true, env = "API_TOKEN_FILE")]
+    token_file: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -419,56 +1506,211 @@ enum Command {
     Server {
         #[arg(short, long, default_value = "127.0.0.1:8080")]
         addr: SocketAddr,
+        /// SQLite database with roles and permissions (see `step_4_1`)
+        #[arg(long, default_value = "roles.sqlite")]
+        roles_db: String,
+        /// Configuration file in the format of `step_3_9`
+        #[arg(short, long, env = "CONF_FILE", default_value = "config.toml")]
+        config: PathBuf,
+        /// Enables debug mode, which logs redacted request and response bodies
+        #[arg(long)]
+        debug: bool,
+        /// File to restore users and sessions from on startup and save them to
+        /// on shutdown
+        #[arg(long)]
+        state_file: Option<PathBuf>,
+        /// SQLite database to keep users and sessions in, saving every change.
+        /// Created and migrated on startup
+        #[arg(long, conflicts_with = "state_file")]
+        database: Option<PathBuf>,
+        /// Users to make admins on startup, the only way to get the first one
+        #[arg(long = "admin", value_name = "USER_ID")]
+        admins: Vec<UserId>,
     },
-    /// Register a user via API
+    /// Register a user via API, prompting for its password
     Register {
         #[arg(long, default_value = "http://127.0.0.1:8080")]
         server: String,
         #[arg(long)]
         name: String,
-        #[arg(long)]
-        password: String,
     },
-    /// Login and get a token
+    /// Login, prompting for the password, and save the issued refresh token
     Login {
         #[arg(long, default_value = "http://127.0.0.1:8080")]
         server: String,
         #[arg(long)]
         name: String,
+    },
+    /// Show the user owning the token
+    Whoami {
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        /// Access token to use instead of refreshing the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+    },
+    /// Change the name of the token owner
+    Rename {
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        /// Access token to use instead of refreshing the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
         #[arg(long)]
-        password: String,
+        name: String,
+    },
+    /// Change the password of the token owner, prompting for the old and the
+    /// new ones, and save the refresh token replacing the revoked ones
+    ChangePassword {
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        /// Access token to use instead of refreshing the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+    },
+    /// Revoke the refresh token on the server and forget it locally
+    RevokeToken {
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        /// Refresh token to revoke instead of the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+    },
+    /// Find users by the beginnings of words in their names
+    SearchUsers {
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        /// Access token to use instead of refreshing the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+        query: String,
+    },
+    /// List users page by page, optionally only the ones with words of their
+    /// names starting with the words of the query
+    BrowseUsers {
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        /// Access token to use instead of refreshing the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+        #[arg(short, long)]
+        query: Option<String>,
+        #[arg(long, default_value_t = 1)]
+        page: usize,
+        #[arg(long, default_value_t = DEFAULT_PER_PAGE)]
+        per_page: usize,
     },
     /// Fetch a user with friends
     GetUser {
         #[arg(long, default_value = "http://127.0.0.1:8080")]
         server: String,
+        /// Access token to use instead of refreshing the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
         #[arg(long)]
-        token: String,
+        id: String,
+    },
+    /// Show mutual friends of two users and how far apart they are
+    MutualFriends {
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        /// Access token to use instead of refreshing the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
         #[arg(long)]
         id: String,
+        #[arg(long)]
+        other_id: String,
     },
-    /// Add a friend to a user
-    AddFriend {
+    /// Ask a user to become a friend of the token owner
+    SendFriendRequest {
         #[arg(long, default_value = "http://127.0.0.1:8080")]
         server: String,
+        /// Access token to use instead of refreshing the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
         #[arg(long)]
-        token: String,
+        id: String,
+    },
+    /// List pending friend requests sent to and by the token owner
+    FriendRequests {
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        /// Access token to use instead of refreshing the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+    },
+    /// Accept a friend request sent to the token owner
+    AcceptFriendRequest {
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        /// Access token to use instead of refreshing the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
         #[arg(long)]
         id: String,
+    },
+    /// Reject a friend request sent to the token owner
+    RejectFriendRequest {
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        /// Access token to use instead of refreshing the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
         #[arg(long)]
-        friend_id: String,
+        id: String,
     },
     /// Remove a friend from a user
     RemoveFriend {
         #[arg(long, default_value = "http://127.0.0.1:8080")]
         server: String,
-        #[arg(long)]
-        token: String,
+        /// Access token to use instead of refreshing the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
         #[arg(long)]
         id: String,
         #[arg(long)]
         friend_id: String,
     },
+    /// List all users (requires the admin role)
+    ListUsers {
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        /// Access token to use instead of refreshing the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+    },
+    /// Delete a user (requires the admin role)
+    DeleteUser {
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        /// Access token to use instead of refreshing the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+        #[arg(long)]
+        id: String,
+    },
+    /// Change the role of a user (requires the admin role)
+    SetRole {
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        /// Access token to use instead of refreshing the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+        #[arg(long)]
+        id: String,
+        /// Either `user` or `admin`
+        #[arg(long)]
+        role: Role,
+    },
+    /// Export the friendship graph of all users (requires the admin role)
+    ExportGraph {
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        /// Access token to use instead of refreshing the saved one
+        #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+    },
 }
 
 #[cfg(test)]
@@ -476,10 +1718,14 @@ mod tests {
     use super::*;
     use axum::Json;
     use axum::extract::State;
+    use std::marker::PhantomData;
+    use step_4_domain::ServiceError;
+    use tower::ServiceExt;
 
     #[tokio::test]
     async fn registers_users_and_manages_friendships() {
-        let state = Arc::new(Mutex::new(AppState::default()));
+        let state = UserService::new();
+        let tokens = AccessTokens::new(b"secret", std::time::Duration::from_secs(60));
 
         let status = register_user(
             State(state.clone()),
@@ -500,7 +1746,7 @@ mod tests {
             }),
         )
         .await;
-        assert!(matches!(duplicate, Err(ApiError::UserExists)));
+        assert_eq!(duplicate, Err(ServiceError::UserExists.into()));
 
         register_user(
             State(state.clone()),
@@ -514,6 +1760,7 @@ mod tests {
 
         let Json(token) = login_user(
             State(state.clone()),
+            State(tokens.clone()),
             Json(LoginPayload {
                 name: "alice".into(),
                 password: "secret".into(),
@@ -521,31 +1768,78 @@ mod tests {
         )
         .await
         .expect("login alice");
-        assert!(!token.token.is_empty());
-        {
-            let guard = state.lock().await;
-            assert!(guard.auth.tokens.contains_key(&token.token));
-        }
+        let alice_id = state.find_by_name("alice").await.unwrap().id;
+        let bob_id = state.find_by_name("bob").await.unwrap().id;
+        assert_eq!(tokens.verify(&token.token), Some(alice_id));
+        assert_eq!(token.expires_in, 60);
 
-        let (alice_id, bob_id) = {
-            let guard = state.lock().await;
-            let alice_id = *guard.names.get("alice").unwrap();
-            let bob_id = *guard.names.get("bob").unwrap();
-            (alice_id, bob_id)
-        };
+        let Json(refreshed) = refresh_token(
+            State(state.clone()),
+            State(tokens.clone()),
+            Json(RefreshPayload {
+                refresh_token: token.refresh_token.clone(),
+            }),
+        )
+        .await
+        .expect("refresh");
+        assert_eq!(tokens.verify(&refreshed.token), Some(alice_id));
+        assert_eq!(refreshed.refresh_token, token.refresh_token);
 
-        let status = add_friend(
+        let Json(found) = list_users(
             State(state.clone()),
-            Path((alice_id.to_string(), bob_id.to_string())),
+            Query(ListQuery {
+                q: Some("BO".into()),
+                ..Default::default()
+            }),
             AuthenticatedUser(alice_id),
         )
         .await
-        .expect("add friend");
-        assert_eq!(status, StatusCode::OK);
+        .expect("search users");
+        let found: Vec<_> = found.items.into_iter().map(|user| user.id).collect();
+        assert_eq!(found, [bob_id]);
+
+        let features = || Features(FeatureFlags::new(&Default::default(), false).defaults());
+        let Json(request) = send_friend_request(
+            State(state.clone()),
+            Path(bob_id),
+            AuthenticatedUser(alice_id),
+            features(),
+        )
+        .await
+        .expect("send friend request");
+        assert_eq!((request.from, request.to), (alice_id, bob_id));
+
+        let Json(pending) =
+            list_friend_requests(State(state.clone()), AuthenticatedUser(bob_id), features())
+                .await
+                .expect("list friend requests");
+        assert_eq!(pending.incoming.len(), 1);
+        assert!(pending.outgoing.is_empty());
+
+        let not_target = accept_friend_request(
+            State(state.clone()),
+            Path(request.id),
+            AuthenticatedUser(alice_id),
+            features(),
+        )
+        .await;
+        assert_eq!(
+            not_target.map(|_| ()),
+            Err(ServiceError::FriendRequestNotFound.into())
+        );
+        let Json(accepted) = accept_friend_request(
+            State(state.clone()),
+            Path(request.id),
+            AuthenticatedUser(bob_id),
+            features(),
+        )
+        .await
+        .expect("accept friend request");
+        assert_eq!(accepted.id, request.id);
 
         let Json(graph) = get_user_graph(
             State(state.clone()),
-            Path(alice_id.to_string()),
+            Path(alice_id),
             AuthenticatedUser(alice_id),
         )
         .await
@@ -555,10 +1849,42 @@ mod tests {
         assert_eq!(graph.friends.len(), 1);
         assert_eq!(graph.friends[0].id, bob_id);
 
+        let Json(mutual) = get_mutual_friends(
+            State(state.clone()),
+            Path((bob_id, alice_id)),
+            AuthenticatedUser(alice_id),
+        )
+        .await
+        .expect("get mutual friends");
+        assert!(mutual.friends.is_empty());
+        assert_eq!(mutual.separation, Some(1));
+        assert_eq!(mutual.path, [bob_id, alice_id]);
+
+        let user = || CallerPermissions(PermissionSet::default());
+        let forbidden = remove_friend(
+            State(state.clone()),
+            Path((bob_id, alice_id)),
+            AuthenticatedUser(alice_id),
+            user(),
+        )
+        .await;
+        assert_eq!(forbidden, Err(AppError::Forbidden("users.write".into())));
+        let admin = PermissionSet::parse(r#"["users.*"]"#).unwrap();
+        let status = remove_friend(
+            State(state.clone()),
+            Path((bob_id, alice_id)),
+            AuthenticatedUser(UserId::new()),
+            CallerPermissions(admin),
+        )
+        .await
+        .expect("remove friend of another user as admin");
+        assert_eq!(status, StatusCode::OK);
+
         let status = remove_friend(
             State(state.clone()),
-            Path((alice_id.to_string(), bob_id.to_string())),
+            Path((alice_id, bob_id)),
             AuthenticatedUser(alice_id),
+            user(),
         )
         .await
         .expect("remove friend");
@@ -566,11 +1892,390 @@ mod tests {
 
         let Json(graph_after) = get_user_graph(
             State(state.clone()),
-            Path(alice_id.to_string()),
+            Path(alice_id),
             AuthenticatedUser(alice_id),
         )
         .await
         .expect("get user graph after removal");
         assert!(graph_after.friends.is_empty());
+
+        let Json(me) = get_me(State(state.clone()), AuthenticatedUser(alice_id))
+            .await
+            .expect("whoami");
+        assert_eq!(me.name, "alice");
+
+        let taken = rename_me(
+            State(state.clone()),
+            AuthenticatedUser(alice_id),
+            Json(RenamePayload { name: "Bob".into() }),
+        )
+        .await;
+        assert!(matches!(taken, Err(err) if err == ServiceError::UserExists.into()));
+        let Json(me) = rename_me(
+            State(state.clone()),
+            AuthenticatedUser(alice_id),
+            Json(RenamePayload {
+                name: "Alice".into(),
+            }),
+        )
+        .await
+        .expect("rename");
+        assert_eq!(me.name, "Alice");
+
+        let revoke = || {
+            Json(RefreshPayload {
+                refresh_token: token.refresh_token.clone(),
+            })
+        };
+        let status = logout(State(state.clone()), revoke())
+            .await
+            .expect("logout");
+        assert_eq!(status, StatusCode::OK);
+        let expired = refresh_token(State(state.clone()), State(tokens.clone()), revoke()).await;
+        assert!(matches!(expired, Err(AppError::Unauthorized)));
+        let repeated = logout(State(state.clone()), revoke()).await;
+        assert_eq!(repeated, Err(AppError::Unauthorized));
+    }
+
+    #[tokio::test]
+    async fn lists_users_page_by_page() {
+        let users = UserService::new();
+        for name in ["carol", "alice", "bob", "alice cooper"] {
+            users.register(name, "secret").await.unwrap();
+        }
+        let caller = UserId::new();
+        let list = |q: Option<&str>, page, per_page| {
+            list_users(
+                State(users.clone()),
+                Query(ListQuery {
+                    q: q.map(Into::into),
+                    page,
+                    per_page,
+                }),
+                AuthenticatedUser(caller),
+            )
+        };
+
+        let Json(first) = list(None, None, Some(3)).await.unwrap();
+        let names: Vec<_> = first.items.iter().map(|user| user.name.as_str()).collect();
+        assert_eq!(names, ["alice", "alice cooper", "bob"]);
+        assert_eq!((first.total, first.page, first.pages()), (4, 1, 2));
+
+        let Json(last) = list(None, Some(2), Some(3)).await.unwrap();
+        assert_eq!(last.items.len(), 1);
+        assert_eq!(last.items[0].name, "carol");
+        let Json(beyond) = list(None, Some(3), Some(3)).await.unwrap();
+        assert!(beyond.items.is_empty());
+        assert_eq!(beyond.total, 4);
+
+        let Json(found) = list(Some("ali"), None, None).await.unwrap();
+        assert_eq!((found.total, found.per_page), (2, DEFAULT_PER_PAGE));
+
+        for (page, per_page) in [(Some(0), None), (None, Some(0)), (None, Some(101))] {
+            let invalid = list(None, page, per_page).await;
+            assert!(matches!(invalid, Err(AppError::BadRequest(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn admin_endpoints_follow_the_role_permissions() {
+        let roles = open_roles(":memory:").unwrap();
+        // A user of the role database holding the name grants nothing.
+        roles
+            .with_db(|db| db.create_user("mallory", "mallory@example.com", &role_slug(Role::Admin)))
+            .unwrap();
+        let shared = SharedState::new(
+            roles.clone(),
+            FeatureFlags::new(&Default::default(), false),
+            Default::default(),
+            &Default::default(),
+        );
+
+        let mut tokens = Vec::new();
+        for (name, password) in [("alice", "secret"), ("bob", "hunter2"), ("mallory", "pwd")] {
+            register_user(
+                State(shared.users.clone()),
+                Json(RegisterPayload {
+                    name: name.into(),
+                    password: password.into(),
+                }),
+            )
+            .await
+            .unwrap();
+            let Json(token) = login_user(
+                State(shared.users.clone()),
+                State(shared.tokens.clone()),
+                Json(LoginPayload {
+                    name: name.into(),
+                    password: password.into(),
+                }),
+            )
+            .await
+            .unwrap();
+            tokens.push(token.token);
+        }
+        let [alice, bob, mallory] = &tokens[..] else {
+            unreachable!()
+        };
+        let alice_id = shared.users.find_by_name("alice").await.unwrap().id;
+        let bob_id = shared.users.find_by_name("bob").await.unwrap().id;
+        shared
+            .users
+            .change_role(alice_id, Role::Admin)
+            .await
+            .unwrap();
+
+        let admin = |token: Option<&str>| {
+            let shared = shared.clone();
+            let mut parts = parts_with_token(token);
+            async move { RequireAdmin::from_request_parts(&mut parts, &shared).await }
+        };
+        let permissions = |token: &str| {
+            let shared = shared.clone();
+            let mut parts = parts_with_token(Some(token));
+            async move {
+                CallerPermissions::from_request_parts(&mut parts, &shared)
+                    .await
+                    .unwrap()
+                    .0
+            }
+        };
+        assert!(admin(Some(alice)).await.is_ok(), "alice is an admin");
+        assert!(permissions(alice).await.allows("users.write"));
+        assert!(matches!(admin(None).await, Err(AppError::Unauthorized)));
+        for token in [bob, mallory] {
+            assert!(matches!(
+                admin(Some(token)).await,
+                Err(AppError::Forbidden(_))
+            ));
+            assert!(permissions(token).await.is_empty());
+        }
+
+        // Taking over the name of an admin grants nothing either.
+        shared.users.rename(alice_id, "root").await.unwrap();
+        shared.users.rename(bob_id, "alice").await.unwrap();
+        assert!(admin(Some(alice)).await.is_ok());
+        assert!(matches!(
+            admin(Some(bob)).await,
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(permissions(bob).await.is_empty());
+
+        let Json(promoted) = admin_change_role(
+            State(shared.users.clone()),
+            Path(bob_id),
+            RequirePermission(PhantomData),
+            Json(RolePayload { role: Role::Admin }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(promoted.role, Role::Admin);
+        assert!(admin(Some(bob)).await.is_ok());
+
+        // What admins may do is up to the role database.
+        let set_admin_permissions = |permissions: &str| {
+            roles
+                .with_db(|db| {
+                    db.update_role(&role_slug(Role::Admin), None, Some(permissions.into()))
+                })
+                .unwrap()
+        };
+        set_admin_permissions(r#"["users.read"]"#);
+        assert!(matches!(
+            admin(Some(bob)).await,
+            Err(AppError::Forbidden(_))
+        ));
+        set_admin_permissions(r#"["users.*"]"#);
+
+        let status = admin_delete_user(
+            State(shared.users.clone()),
+            State(shared.tokens.clone()),
+            Path(bob_id),
+            RequirePermission(PhantomData),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert!(matches!(
+            admin(Some(bob)).await,
+            Err(AppError::Unauthorized)
+        ));
+        assert_eq!(shared.tokens.verify(bob), None, "revoked with the user");
+    }
+
+    #[tokio::test]
+    async fn friend_requests_follow_feature_flags() {
+        let config = step_3_9::FeaturesConfig {
+            friend_requests: false,
+            override_secret: Some("secret".into()),
+            ..Default::default()
+        };
+        let flags = FeatureFlags::new(&config, true);
+        let users = UserService::new();
+        let alice_id = UserId::new();
+
+        let mut parts = parts_with_token(None);
+        let Ok(disabled) = Features::from_request_parts(&mut parts, &flags).await;
+        let rejected = send_friend_request(
+            State(users.clone()),
+            Path(UserId::new()),
+            AuthenticatedUser(alice_id),
+            disabled,
+        )
+        .await;
+        assert!(matches!(
+            rejected,
+            Err(AppError::NotFound(message)) if message == "feature `friend_requests` is disabled"
+        ));
+
+        let header = features::sign_override("secret", "friend_requests=on");
+        parts.headers.insert(
+            features::OVERRIDE_HEADER,
+            header.parse().expect("valid header value"),
+        );
+        let Ok(enabled) = Features::from_request_parts(&mut parts, &flags).await;
+        assert!(enabled.require(Feature::FriendRequests).is_ok());
+    }
+
+    #[tokio::test]
+    async fn serves_the_whole_flow_over_http() {
+        let state = SharedState::new(
+            open_roles(":memory:").unwrap(),
+            FeatureFlags::new(&Default::default(), false),
+            Default::default(),
+            &Default::default(),
+        );
+        let router = build_router(state, &Default::default(), Readiness::default());
+
+        for (name, password) in [("alice", "secret"), ("bob", "hunter2"), ("carol", "pwd")] {
+            let payload = format!(r#"{{"name":"{name}","password":"{password}"}}"#);
+            let (status, _) = send(&router, "POST", "/register", None, Some(&payload)).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+        let login = |name: &str, password: &str| {
+            let payload = format!(r#"{{"name":"{name}","password":"{password}"}}"#);
+            let router = router.clone();
+            async move {
+                let (status, body) = send(&router, "POST", "/login", None, Some(&payload)).await;
+                (status, serde_json::from_slice::<TokenResponse>(&body).ok())
+            }
+        };
+        let (status, _) = login("alice", "wrong").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let alice_session = login("alice", "secret").await.1.unwrap();
+        let alice = alice_session.token.clone();
+        let bob = login("bob", "hunter2").await.1.unwrap().token;
+        let carol = login("carol", "pwd").await.1.unwrap().token;
+
+        let (status, body) = send(&router, "GET", "/me", Some(&bob), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let bob_id = serde_json::from_slice::<PublicUser>(&body).unwrap().id;
+        for token in [None, Some("not.a.token")] {
+            let (status, _) = send(&router, "GET", "/me", token, None).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+
+        let uri = format!("/users/{bob_id}/friend-requests");
+        let (status, body) = send(&router, "POST", &uri, Some(&alice), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let request = serde_json::from_slice::<PendingRequest>(&body).unwrap();
+        let (status, _) = send(&router, "POST", &uri, None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // Only the target of the request may accept it.
+        let uri = format!("/friend-requests/{}/accept", request.id);
+        let (status, _) = send(&router, "POST", &uri, Some(&carol), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send(&router, "POST", &uri, Some(&bob), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let uri = format!("/users/{}", request.from);
+        let (status, _) = send(&router, "GET", &uri, None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, body) = send(&router, "GET", &uri, Some(&carol), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let graph = serde_json::from_slice::<UserGraph>(&body).unwrap();
+        assert_eq!(graph.user.name, "alice");
+        assert_eq!(graph.user.friends, [bob_id]);
+        assert_eq!(graph.friends[0].name, "bob");
+
+        let uri = format!("/users/{}/friends/{bob_id}/remove", request.from);
+        let (status, _) = send(&router, "POST", &uri, Some(&carol), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send(&router, "POST", &uri, Some(&alice), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let rename = Some(r#"{"name":"Alicia"}"#);
+        let (status, _) = send(&router, "PATCH", "/users/me", None, rename).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, body) = send(&router, "PATCH", "/users/me", Some(&alice), rename).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_slice::<PublicUser>(&body).unwrap().name,
+            "Alicia"
+        );
+
+        let refresh = format!(r#"{{"refresh_token":"{}"}}"#, alice_session.refresh_token);
+        let wrong = Some(r#"{"old_password":"wrong","new_password":"changed"}"#);
+        let (status, _) = send(&router, "POST", "/users/me/password", Some(&alice), wrong).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let change = Some(r#"{"old_password":"secret","new_password":"changed"}"#);
+        let (status, body) =
+            send(&router, "POST", "/users/me/password", Some(&alice), change).await;
+        assert_eq!(status, StatusCode::OK);
+        let renewed = serde_json::from_slice::<TokenResponse>(&body).unwrap();
+        let (status, _) = send(&router, "POST", "/token/refresh", None, Some(&refresh)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "old sessions are revoked");
+        let (status, _) = send(&router, "GET", "/users/me", Some(&alice), None).await;
+        assert_eq!(
+            status,
+            StatusCode::UNAUTHORIZED,
+            "old access tokens are revoked"
+        );
+        let (status, _) = send(&router, "GET", "/users/me", Some(&renewed.token), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let refresh = format!(r#"{{"refresh_token":"{}"}}"#, renewed.refresh_token);
+        let (status, _) = send(&router, "POST", "/token/refresh", None, Some(&refresh)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    /// Sends the request through the whole stack of the `router`, returning
+    /// the status and the body of the response.
+    async fn send(
+        router: &Router,
+        method: &str,
+        uri: &str,
+        token: Option<&str>,
+        json: Option<&str>,
+    ) -> (StatusCode, axum::body::Bytes) {
+        let mut request = axum::http::Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let body = match json {
+            Some(json) => {
+                request = request.header(header::CONTENT_TYPE, "application/json");
+                axum::body::Body::from(json.to_owned())
+            }
+            None => axum::body::Body::empty(),
+        };
+        let response = router
+            .clone()
+            .oneshot(request.body(body).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, body)
+    }
+
+    fn parts_with_token(token: Option<&str>) -> Parts {
+        let mut request = axum::http::Request::builder();
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        request.body(()).unwrap().into_parts().0
     }
 }
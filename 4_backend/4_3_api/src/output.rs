@@ -0,0 +1,200 @@
+//! Rendering of client command results as JSON or plain-text tables.
+
+use clap::ValueEnum;
+use cli_common::Table;
+use serde::Serialize;
+use step_4_domain::UserId;
+
+use crate::{MutualFriends, PendingRequests, PublicUser, UserGraph, UserPage};
+
+/// Format of the client commands output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Json,
+    #[default]
+    Table,
+}
+
+/// Result that can be printed in any [`OutputFormat`].
+pub trait Render: Serialize {
+    fn table(&self) -> Table;
+}
+
+pub fn render<T: Render>(format: OutputFormat, value: &T) -> serde_json::Result<String> {
+    match format {
+        OutputFormat::Json => serde_json::to_string_pretty(value),
+        OutputFormat::Table => Ok(value.table().to_string()),
+    }
+}
+
+fn user_row(user: &PublicUser) -> Vec<String> {
+    vec![
+        user.id.to_string(),
+        user.name.clone(),
+        user.friends.len().to_string(),
+    ]
+}
+
+const USER_HEADERS: [&str; 3] = ["ID", "NAME", "FRIENDS"];
+
+impl Render for PublicUser {
+    fn table(&self) -> Table {
+        Table::new(USER_HEADERS.to_vec()).row(user_row(self))
+    }
+}
+
+impl Render for Vec<PublicUser> {
+    fn table(&self) -> Table {
+        self.iter()
+            .fold(Table::new(USER_HEADERS.to_vec()), |table, user| {
+                table.row(user_row(user))
+            })
+    }
+}
+
+impl Render for UserPage {
+    fn table(&self) -> Table {
+        self.items.table()
+    }
+}
+
+impl Render for UserGraph {
+    fn table(&self) -> Table {
+        let mut table = Table::new(vec!["RELATION", "ID", "NAME", "FRIENDS"]);
+        table = table.row([vec!["user".to_string()], user_row(&self.user)].concat());
+        for friend in &self.friends {
+            table = table.row([vec!["friend".to_string()], user_row(friend)].concat());
+        }
+        table
+    }
+}
+
+impl Render for MutualFriends {
+    fn table(&self) -> Table {
+        let mut table = Table::new(vec!["RELATION", "ID", "NAME", "FRIENDS"]);
+        for friend in &self.friends {
+            table = table.row([vec!["mutual".to_string()], user_row(friend)].concat());
+        }
+        for step in &self.path {
+            table = table.row(vec!["path".to_string(), step.to_string()]);
+        }
+        table
+    }
+}
+
+impl Render for PendingRequests {
+    fn table(&self) -> Table {
+        let mut table = Table::new(vec!["DIRECTION", "ID", "FROM", "TO"]);
+        for (direction, requests) in [("incoming", &self.incoming), ("outgoing", &self.outgoing)] {
+            for request in requests {
+                table = table.row(vec![
+                    direction.to_string(),
+                    request.id.to_string(),
+                    request.from.to_string(),
+                    request.to.to_string(),
+                ]);
+            }
+        }
+        table
+    }
+}
+
+/// Friendship graph of all users, as exported by the `export-graph` command.
+#[derive(Debug, Serialize)]
+pub struct FriendGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GraphNode {
+    pub id: UserId,
+    pub name: String,
+}
+
+/// Directed edge: `from` has `to` in its friends list.
+#[derive(Debug, Serialize)]
+pub struct GraphEdge {
+    pub from: UserId,
+    pub to: UserId,
+}
+
+impl FriendGraph {
+    pub fn from_users(users: &[PublicUser]) -> Self {
+        let mut nodes: Vec<_> = users
+            .iter()
+            .map(|user| GraphNode {
+                id: user.id,
+                name: user.name.clone(),
+            })
+            .collect();
+        nodes.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut edges: Vec<_> = users
+            .iter()
+            .flat_map(|user| {
+                user.friends.iter().map(|friend| GraphEdge {
+                    from: user.id,
+                    to: *friend,
+                })
+            })
+            .collect();
+        edges.sort_by_key(|edge| (edge.from, edge.to));
+
+        Self { nodes, edges }
+    }
+
+    fn name_of(&self, id: UserId) -> String {
+        self.nodes
+            .iter()
+            .find(|node| node.id == id)
+            .map_or_else(|| id.to_string(), |node| node.name.clone())
+    }
+}
+
+impl Render for FriendGraph {
+    fn table(&self) -> Table {
+        self.edges
+            .iter()
+            .fold(Table::new(vec!["USER", "FRIEND"]), |table, edge| {
+                table.row(vec![self.name_of(edge.from), self.name_of(edge.to)])
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use step_4_domain::Role;
+    use uuid::Uuid;
+
+    #[test]
+    fn renders_friend_graph() {
+        let alice = UserId::from(Uuid::from_u128(1));
+        let bob = UserId::from(Uuid::from_u128(2));
+        let users = vec![
+            PublicUser {
+                id: bob,
+                name: "bob".into(),
+                friends: vec![],
+                role: Role::User,
+            },
+            PublicUser {
+                id: alice,
+                name: "alice".into(),
+                friends: vec![bob],
+                role: Role::Admin,
+            },
+        ];
+        let graph = FriendGraph::from_users(&users);
+
+        assert_eq!(
+            render(OutputFormat::Table, &graph).unwrap(),
+            "USER   FRIEND\nalice  bob",
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&render(OutputFormat::Json, &graph).unwrap()).unwrap();
+        assert_eq!(json["nodes"][0]["name"], "alice");
+        assert_eq!(json["edges"][0]["to"], bob.to_string());
+    }
+}
@@ -0,0 +1,68 @@
+//! Liveness and readiness probes, for orchestrators like Kubernetes.
+//!
+//! `GET /healthz` answers as long as the server handles requests at all, while
+//! `GET /readyz` tells whether it should be sent new ones: not before the
+//! users are restored, and no more once it's shutting down.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use axum::{Router, extract::State, http::StatusCode, routing::get};
+
+/// Whether the server is ready for traffic, initially not.
+#[derive(Clone, Debug, Default)]
+pub struct Readiness(Arc<AtomicBool>);
+
+impl Readiness {
+    pub fn set(&self, ready: bool) {
+        self.0.store(ready, Ordering::Relaxed);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Routes of the probes, to be merged outside of any rate limits.
+pub fn router(readiness: Readiness) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(readiness)
+}
+
+async fn healthz() -> StatusCode {
+    StatusCode::OK
+}
+
+async fn readyz(State(readiness): State<Readiness>) -> StatusCode {
+    if readiness.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn ready_only_while_set() {
+        let readiness = Readiness::default();
+        assert_eq!(healthz().await, StatusCode::OK);
+        assert_eq!(
+            readyz(State(readiness.clone())).await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        readiness.set(true);
+        assert_eq!(readyz(State(readiness.clone())).await, StatusCode::OK);
+        readiness.set(false);
+        assert_eq!(
+            readyz(State(readiness)).await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+}
@@ -0,0 +1,104 @@
+//! Client-side storage of issued tokens.
+//!
+//! Tokens are kept per server in a JSON file inside the OS-specific config
+//! directory (e.g. `~/.config/step_4_3/tokens.json` on Linux), so secrets
+//! don't have to be passed on the command line.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Name of the application directory inside the OS config directory.
+const APP_DIR: &str = "step_4_3";
+
+/// Tokens file, mapping server URLs to their tokens.
+#[derive(Debug, Clone)]
+pub struct TokenStore {
+    path: PathBuf,
+}
+
+impl TokenStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Opens the store at its default OS-appropriate location.
+    pub fn default_location() -> io::Result<Self> {
+        let dir = dirs::config_dir().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no config directory on this OS")
+        })?;
+        Ok(Self::new(dir.join(APP_DIR).join("tokens.json")))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, server: &str) -> io::Result<Option<String>> {
+        Ok(self.read()?.remove(server))
+    }
+
+    pub fn save(&self, server: &str, token: &str) -> io::Result<()> {
+        let mut tokens = self.read()?;
+        tokens.insert(server.to_string(), token.to_string());
+        self.write(&tokens)
+    }
+
+    /// Forgets the token of the server, returning it if there was one.
+    pub fn remove(&self, server: &str) -> io::Result<Option<String>> {
+        let mut tokens = self.read()?;
+        let removed = tokens.remove(server);
+        if removed.is_some() {
+            self.write(&tokens)?;
+        }
+        Ok(removed)
+    }
+
+    fn read(&self) -> io::Result<BTreeMap<String, String>> {
+        match fs::read_to_string(&self.path) {
+            Ok(raw) => serde_json::from_str(&raw).map_err(io::Error::other),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn write(&self, tokens: &BTreeMap<String, String>) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let raw = serde_json::to_string_pretty(tokens).map_err(io::Error::other)?;
+        fs::write(&self.path, raw)?;
+        // Tokens are credentials, so keep them private to the current user.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            fs::set_permissions(&self.path, fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_tokens_per_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = TokenStore::new(dir.path().join("nested").join("tokens.json"));
+
+        assert_eq!(store.get("http://a").unwrap(), None);
+        store.save("http://a", "token-a").unwrap();
+        store.save("http://b", "token-b").unwrap();
+        assert_eq!(store.get("http://a").unwrap().as_deref(), Some("token-a"));
+
+        assert_eq!(
+            store.remove("http://a").unwrap().as_deref(),
+            Some("token-a")
+        );
+        assert_eq!(store.get("http://a").unwrap(), None);
+        assert_eq!(store.get("http://b").unwrap().as_deref(), Some("token-b"));
+    }
+}
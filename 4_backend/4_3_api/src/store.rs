@@ -0,0 +1,565 @@
+//! Server-side storage of users surviving restarts.
+//!
+//! A [`UserStore`] keeps the latest [`Snapshot`] of the [`UserService`]. The
+//! server restores it on startup and, via [`persist`], saves the changes
+//! after every one of them, so a crash loses nothing but the change being
+//! saved.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::async_trait;
+use common::StateFile;
+use sqlx::{
+    Row, SqlitePool,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+};
+use step_4_domain::{
+    AccountEvent, FriendRequest, Role, Snapshot, UserId, UserService,
+    snapshot::{SnapshotEvent, SnapshotSession},
+};
+use tokio::{
+    sync::broadcast::error::{RecvError, TryRecvError},
+    task::JoinHandle,
+};
+use tracing::error;
+
+/// Storage of the [`UserService`] state.
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    /// Where the state is stored, for messages.
+    fn path(&self) -> &Path;
+
+    /// Reads the saved state, if it was saved at all.
+    async fn load(&self) -> anyhow::Result<Option<Snapshot>>;
+
+    /// Replaces the saved state with the `snapshot`.
+    async fn save(&self, snapshot: &Snapshot) -> anyhow::Result<()>;
+
+    /// Turns the `saved` state, the one the store holds, into the `snapshot`.
+    ///
+    /// Stores unable to write just the differences save the whole `snapshot`.
+    async fn save_changes(&self, saved: &Snapshot, snapshot: &Snapshot) -> anyhow::Result<()> {
+        let _ = saved;
+        self.save(snapshot).await
+    }
+}
+
+#[async_trait]
+impl UserStore for StateFile {
+    fn path(&self) -> &Path {
+        StateFile::path(self)
+    }
+
+    async fn load(&self) -> anyhow::Result<Option<Snapshot>> {
+        Ok(StateFile::load(self)?)
+    }
+
+    async fn save(&self, snapshot: &Snapshot) -> anyhow::Result<()> {
+        Ok(StateFile::save(self, snapshot)?)
+    }
+}
+
+/// SQLite database with `users`, `friends`, `friend_requests` and `tokens`
+/// tables.
+///
+/// Only the current names of the accounts are stored, so their renames and
+/// deleted accounts are gone after restoring. Changes update just the rows
+/// they touch, see [`UserStore::save_changes`].
+pub struct SqliteStore {
+    path: PathBuf,
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    /// Opens the database, creating it if it's missing, and migrates it to
+    /// the current schema.
+    pub async fn open(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .foreign_keys(true);
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+        sqlx::migrate!().run(&pool).await?;
+        Ok(Self { path, pool })
+    }
+}
+
+#[async_trait]
+impl UserStore for SqliteStore {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    async fn load(&self) -> anyhow::Result<Option<Snapshot>> {
+        let users = sqlx::query("SELECT id, name, password_hash, role FROM users ORDER BY rowid")
+            .fetch_all(&self.pool)
+            .await?;
+        if users.is_empty() {
+            return Ok(None);
+        }
+        let mut snapshot = Snapshot::default();
+        for row in users {
+            let user = row.try_get::<&str, _>("id")?.parse()?;
+            snapshot.accounts.push(SnapshotEvent {
+                user,
+                event: AccountEvent::Registered {
+                    name: row.try_get("name")?,
+                    password_hash: row.try_get("password_hash")?,
+                },
+            });
+            let role: Role = row.try_get::<&str, _>("role")?.parse()?;
+            if role != Role::default() {
+                snapshot.accounts.push(SnapshotEvent {
+                    user,
+                    event: AccountEvent::RoleChanged { role },
+                });
+            }
+        }
+
+        let friends = sqlx::query("SELECT user_id, friend_id FROM friends ORDER BY rowid")
+            .fetch_all(&self.pool)
+            .await?;
+        for row in friends {
+            snapshot.friendships.push((
+                row.try_get::<&str, _>("user_id")?.parse()?,
+                row.try_get::<&str, _>("friend_id")?.parse()?,
+            ));
+        }
+
+        let requests = sqlx::query("SELECT id, from_id, to_id FROM friend_requests ORDER BY rowid")
+            .fetch_all(&self.pool)
+            .await?;
+        for row in requests {
+            snapshot.friend_requests.push(FriendRequest {
+                id: row.try_get::<&str, _>("id")?.parse()?,
+                from: row.try_get::<&str, _>("from_id")?.parse()?,
+                to: row.try_get::<&str, _>("to_id")?.parse()?,
+            });
+        }
+
+        let now = unix_time();
+        let tokens = sqlx::query("SELECT token, user_id, expires_at FROM tokens")
+            .fetch_all(&self.pool)
+            .await?;
+        for row in tokens {
+            let expires_at: Option<i64> = row.try_get("expires_at")?;
+            if expires_at.is_some_and(|at| at <= now) {
+                continue;
+            }
+            snapshot.sessions.push(SnapshotSession {
+                token: row.try_get::<&str, _>("token")?.parse()?,
+                user: row.try_get::<&str, _>("user_id")?.parse()?,
+                expires_in: expires_at.map(|at| Duration::from_secs((at - now) as u64)),
+            });
+        }
+        Ok(Some(snapshot))
+    }
+
+    async fn save(&self, snapshot: &Snapshot) -> anyhow::Result<()> {
+        let rows = Rows::new(snapshot, unix_time());
+        let mut tx = self.pool.begin().await?;
+        for table in ["tokens", "friend_requests", "friends", "users"] {
+            sqlx::query(&format!("DELETE FROM {table}"))
+                .execute(&mut *tx)
+                .await?;
+        }
+        for (id, account) in &rows.users {
+            insert_user(&mut tx, *id, account).await?;
+        }
+        for friendship in &rows.friends {
+            insert_friend(&mut tx, friendship).await?;
+        }
+        for request in &rows.friend_requests {
+            insert_friend_request(&mut tx, request).await?;
+        }
+        for token in &rows.tokens {
+            insert_token(&mut tx, token).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn save_changes(&self, saved: &Snapshot, snapshot: &Snapshot) -> anyhow::Result<()> {
+        let now = unix_time();
+        let (old, new) = (Rows::new(saved, now), Rows::new(snapshot, now));
+        let mut tx = self.pool.begin().await?;
+
+        let (saved, current) = (old.accounts(), new.accounts());
+        // Deleting the users cascades to their friends, requests and tokens.
+        for id in saved.keys().filter(|id| !current.contains_key(id)) {
+            sqlx::query("DELETE FROM users WHERE id = ?")
+                .bind(id.to_string())
+                .execute(&mut *tx)
+                .await?;
+        }
+        for (id, account) in &new.users {
+            match saved.get(id) {
+                None => insert_user(&mut tx, *id, account).await?,
+                Some(&saved) if saved != account => {
+                    sqlx::query(
+                        "UPDATE users SET name = ?, password_hash = ?, role = ? WHERE id = ?",
+                    )
+                    .bind(&account.name)
+                    .bind(&account.password_hash)
+                    .bind(account.role.as_str())
+                    .bind(id.to_string())
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                Some(_) => {}
+            }
+        }
+
+        let (saved, current) = (set(&old.friends), set(&new.friends));
+        for (user, friend) in saved.difference(&current) {
+            sqlx::query("DELETE FROM friends WHERE user_id = ? AND friend_id = ?")
+                .bind(user.to_string())
+                .bind(friend.to_string())
+                .execute(&mut *tx)
+                .await?;
+        }
+        for friendship in new.friends.iter().filter(|f| !saved.contains(f)) {
+            insert_friend(&mut tx, friendship).await?;
+        }
+
+        let ids = |requests: &[FriendRequest]| -> HashSet<String> {
+            requests
+                .iter()
+                .map(|request| request.id.to_string())
+                .collect()
+        };
+        let (saved, current) = (ids(&old.friend_requests), ids(&new.friend_requests));
+        for id in saved.difference(&current) {
+            sqlx::query("DELETE FROM friend_requests WHERE id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for request in &new.friend_requests {
+            if !saved.contains(&request.id.to_string()) {
+                insert_friend_request(&mut tx, request).await?;
+            }
+        }
+
+        // Tokens keep their expiry, so they're told apart by the token alone.
+        let tokens = |tokens: &[TokenRow]| -> HashSet<String> {
+            tokens.iter().map(|(token, ..)| token.clone()).collect()
+        };
+        let (saved, current) = (tokens(&old.tokens), tokens(&new.tokens));
+        for token in saved.difference(&current) {
+            sqlx::query("DELETE FROM tokens WHERE token = ?")
+                .bind(token)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for token in new
+            .tokens
+            .iter()
+            .filter(|(token, ..)| !saved.contains(token))
+        {
+            insert_token(&mut tx, token).await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// Row of the `tokens` table: the token, its owner and when it expires in
+/// Unix time, if ever.
+type TokenRow = (String, UserId, Option<i64>);
+
+/// Contents of the [`SqliteStore`] tables holding a [`Snapshot`].
+struct Rows {
+    /// Current state of the accounts, in the order they were registered.
+    users: Vec<(UserId, Account)>,
+    friends: Vec<(UserId, UserId)>,
+    friend_requests: Vec<FriendRequest>,
+    tokens: Vec<TokenRow>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Account {
+    name: String,
+    password_hash: String,
+    role: Role,
+}
+
+impl Rows {
+    /// Rows of the `snapshot` taken at the Unix time `now`.
+    fn new(snapshot: &Snapshot, now: i64) -> Self {
+        let mut order = Vec::new();
+        let mut accounts: HashMap<UserId, Account> = HashMap::new();
+        for SnapshotEvent { user, event } in &snapshot.accounts {
+            match event {
+                AccountEvent::Registered {
+                    name,
+                    password_hash,
+                } => {
+                    order.push(*user);
+                    accounts.insert(
+                        *user,
+                        Account {
+                            name: name.clone(),
+                            password_hash: password_hash.clone(),
+                            role: Role::default(),
+                        },
+                    );
+                }
+                AccountEvent::Renamed { name } => {
+                    if let Some(account) = accounts.get_mut(user) {
+                        account.name.clone_from(name);
+                    }
+                }
+                AccountEvent::PasswordChanged { password_hash } => {
+                    if let Some(account) = accounts.get_mut(user) {
+                        account.password_hash.clone_from(password_hash);
+                    }
+                }
+                AccountEvent::RoleChanged { role } => {
+                    if let Some(account) = accounts.get_mut(user) {
+                        account.role = *role;
+                    }
+                }
+                AccountEvent::Deleted => {
+                    accounts.remove(user);
+                }
+            }
+        }
+        Self {
+            users: order
+                .into_iter()
+                .filter_map(|id| Some((id, accounts.remove(&id)?)))
+                .collect(),
+            friends: snapshot.friendships.clone(),
+            friend_requests: snapshot.friend_requests.clone(),
+            tokens: snapshot
+                .sessions
+                .iter()
+                .map(|session| {
+                    (
+                        session.token.as_str().to_owned(),
+                        session.user,
+                        session.expires_in.map(|ttl| now + ttl.as_secs() as i64),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn accounts(&self) -> HashMap<UserId, &Account> {
+        self.users
+            .iter()
+            .map(|(id, account)| (*id, account))
+            .collect()
+    }
+}
+
+type Tx = sqlx::Transaction<'static, sqlx::Sqlite>;
+
+async fn insert_user(tx: &mut Tx, id: UserId, account: &Account) -> sqlx::Result<()> {
+    sqlx::query("INSERT INTO users (id, name, password_hash, role) VALUES (?, ?, ?, ?)")
+        .bind(id.to_string())
+        .bind(&account.name)
+        .bind(&account.password_hash)
+        .bind(account.role.as_str())
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+async fn insert_friend(tx: &mut Tx, (user, friend): &(UserId, UserId)) -> sqlx::Result<()> {
+    sqlx::query("INSERT INTO friends (user_id, friend_id) VALUES (?, ?)")
+        .bind(user.to_string())
+        .bind(friend.to_string())
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+async fn insert_friend_request(tx: &mut Tx, request: &FriendRequest) -> sqlx::Result<()> {
+    sqlx::query("INSERT INTO friend_requests (id, from_id, to_id) VALUES (?, ?, ?)")
+        .bind(request.id.to_string())
+        .bind(request.from.to_string())
+        .bind(request.to.to_string())
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+async fn insert_token(tx: &mut Tx, (token, user, expires_at): &TokenRow) -> sqlx::Result<()> {
+    sqlx::query("INSERT INTO tokens (token, user_id, expires_at) VALUES (?, ?, ?)")
+        .bind(token)
+        .bind(user.to_string())
+        .bind(expires_at)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+fn set<T: Clone + Eq + std::hash::Hash>(items: &[T]) -> HashSet<T> {
+    items.iter().cloned().collect()
+}
+
+/// Saves the changes of the `users` to the `store` after every one of them,
+/// until the returned task is aborted.
+///
+/// The `store` must hold the current state of the `users`, e.g. the one they
+/// were just restored from. Failures are logged, the next change retries the
+/// save.
+pub async fn persist(users: &UserService, store: Arc<dyn UserStore>) -> JoinHandle<()> {
+    let users = users.clone();
+    let mut saved = users.snapshot().await;
+    let mut events = users.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                // Lagging is fine, the snapshot has the missed changes too.
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            }
+            // A single save covers the changes queued in the meantime.
+            while !matches!(
+                events.try_recv(),
+                Err(TryRecvError::Empty | TryRecvError::Closed)
+            ) {}
+            let snapshot = users.snapshot().await;
+            match store.save_changes(&saved, &snapshot).await {
+                Ok(()) => saved = snapshot,
+                Err(err) => error!(
+                    path = %store.path().display(),
+                    "failed to save users: {err:#}"
+                ),
+            }
+        }
+    })
+}
+
+fn unix_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn restores_users_friends_requests_and_tokens_from_sqlite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.sqlite");
+        let service = UserService::new().with_session_ttl(Duration::from_secs(3600));
+        let alice = service.register("alice", "secret").await.unwrap();
+        let bob = service.register("bob", "hunter2").await.unwrap();
+        let carol = service.register("carol", "pwd").await.unwrap();
+        service.rename(bob.id, "Bobby").await.unwrap();
+        service.change_role(bob.id, Role::Admin).await.unwrap();
+        service.add_friend(alice.id, bob.id).await.unwrap();
+        service.add_friend(carol.id, alice.id).await.unwrap();
+        let request = service.send_friend_request(bob.id, alice.id).await.unwrap();
+        service.send_friend_request(carol.id, bob.id).await.unwrap();
+        service.delete(carol.id).await.unwrap();
+        let session = service.login("alice", "secret").await.unwrap();
+
+        let store = SqliteStore::open(&path).await.unwrap();
+        assert_eq!(store.load().await.unwrap(), None);
+        store.save(&service.snapshot().await).await.unwrap();
+        drop(store);
+
+        // Reopening doesn't migrate the database again.
+        let store = SqliteStore::open(&path).await.unwrap();
+        let restored = UserService::new();
+        restored.restore(store.load().await.unwrap().unwrap()).await;
+
+        assert_eq!(restored.list().await, service.list().await);
+        assert_eq!(restored.friend_requests(alice.id).await.incoming, [request]);
+        assert!(restored.friend_requests(bob.id).await.incoming.is_empty());
+        assert_eq!(restored.authenticate(&session.token).await, Some(alice.id));
+        assert!(restored.login("bobby", "hunter2").await.is_ok());
+        assert_eq!(restored.user(bob.id).await.unwrap().role, Role::Admin);
+        assert!(restored.find_by_name("carol").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn persists_every_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(
+            SqliteStore::open(dir.path().join("users.sqlite"))
+                .await
+                .unwrap(),
+        );
+        let service = UserService::new();
+        let persisting = persist(&service, store.clone()).await;
+
+        let alice = service.register("alice", "secret").await.unwrap();
+        let mut saved = None;
+        for _ in 0..100 {
+            saved = store.load().await.unwrap();
+            if saved.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        persisting.abort();
+        let saved = saved.expect("registration is saved");
+        assert_eq!(saved.accounts[0].user, alice.id);
+    }
+
+    #[tokio::test]
+    async fn saves_only_the_changed_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteStore::open(dir.path().join("users.sqlite"))
+            .await
+            .unwrap();
+        let service = UserService::new();
+        let alice = service.register("alice", "secret").await.unwrap();
+        let bob = service.register("bob", "hunter2").await.unwrap();
+        let carol = service.register("carol", "pwd").await.unwrap();
+        service.add_friend(alice.id, carol.id).await.unwrap();
+        let saved = service.snapshot().await;
+        store.save(&saved).await.unwrap();
+        // Rows the changes don't touch keep whatever they hold.
+        sqlx::query("UPDATE users SET name = 'untouched' WHERE id = ?")
+            .bind(carol.id.to_string())
+            .execute(&store.pool)
+            .await
+            .unwrap();
+
+        service.rename(alice.id, "Alicia").await.unwrap();
+        service.add_friend(alice.id, bob.id).await.unwrap();
+        service.remove_friend(alice.id, carol.id).await.unwrap();
+        service.send_friend_request(bob.id, carol.id).await.unwrap();
+        let session = service.login("bob", "hunter2").await.unwrap();
+        store
+            .save_changes(&saved, &service.snapshot().await)
+            .await
+            .unwrap();
+        let saved = service.snapshot().await;
+        service.delete(bob.id).await.unwrap();
+        store
+            .save_changes(&saved, &service.snapshot().await)
+            .await
+            .unwrap();
+
+        let restored = UserService::new();
+        restored.restore(store.load().await.unwrap().unwrap()).await;
+        let names: Vec<_> = restored
+            .list()
+            .await
+            .into_iter()
+            .map(|user| user.name)
+            .collect();
+        assert_eq!(names, ["Alicia", "untouched"]);
+        assert!(restored.friends(alice.id).await.unwrap().is_empty());
+        assert!(restored.friend_requests(carol.id).await.incoming.is_empty());
+        assert_eq!(restored.authenticate(&session.token).await, None);
+    }
+}
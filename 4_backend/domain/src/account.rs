@@ -0,0 +1,295 @@
+//! Event-sourced user accounts built on the `step_2_3` CQRS primitives.
+//!
+//! Registration, renames, password and role changes and deletion are commands
+//! decided against the [`Account`] aggregate replayed from its event stream.
+//! The [`Directory`] of users is merely a projection of the committed
+//! [`AccountEvent`]s, so it can be rebuilt from the stream at any time.
+//! Friendships and sessions are not event-sourced.
+
+use std::collections::{HashMap, HashSet};
+
+use common::SearchIndex;
+use serde::{Deserialize, Serialize};
+use step_2_3::{Aggregate, AggregateEvent, AggregateId, command::AggregateCommand, event_types};
+
+use crate::{Role, ServiceError, User, UserId, UserRecord, normalize_name};
+
+/// Lifecycle of a user account.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum Account {
+    #[default]
+    Unregistered,
+    Active {
+        name: String,
+    },
+    Deleted,
+}
+
+impl Aggregate for Account {
+    fn aggregate_type() -> &'static str {
+        "user"
+    }
+}
+
+/// Identifier of an [`Account`], which is the stringified [`UserId`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AccountId(String);
+
+impl From<UserId> for AccountId {
+    fn from(id: UserId) -> Self {
+        Self(id.to_string())
+    }
+}
+
+impl AggregateId<Account> for AccountId {
+    fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Things that happened to an [`Account`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AccountEvent {
+    Registered { name: String, password_hash: String },
+    Renamed { name: String },
+    PasswordChanged { password_hash: String },
+    RoleChanged { role: Role },
+    Deleted,
+}
+
+event_types! {
+    AccountEvent {
+        Registered => "user_registered",
+        Renamed => "user_renamed",
+        PasswordChanged => "user_password_changed",
+        RoleChanged => "user_role_changed",
+        Deleted => "user_deleted",
+    }
+}
+
+impl AggregateEvent<Account> for AccountEvent {
+    fn apply_to(self, account: &mut Account) {
+        match self {
+            Self::Registered { name, .. } | Self::Renamed { name } => {
+                *account = Account::Active { name }
+            }
+            Self::PasswordChanged { .. } | Self::RoleChanged { .. } => {}
+            Self::Deleted => *account = Account::Deleted,
+        }
+    }
+}
+
+/// Registers a new account.
+///
+/// Uniqueness of the name spans all the accounts, so it's checked against the
+/// [`Directory`] before issuing the command.
+pub struct Register {
+    pub name: String,
+    pub password_hash: String,
+}
+
+impl AggregateCommand<Account> for Register {
+    type Event = AccountEvent;
+    type Error = ServiceError;
+
+    fn decide(self, account: &Account) -> Result<Vec<AccountEvent>, ServiceError> {
+        match account {
+            Account::Unregistered => Ok(vec![AccountEvent::Registered {
+                name: self.name,
+                password_hash: self.password_hash,
+            }]),
+            Account::Active { .. } | Account::Deleted => Err(ServiceError::UserExists),
+        }
+    }
+}
+
+/// Changes the display name of an active account.
+pub struct Rename {
+    pub name: String,
+}
+
+impl AggregateCommand<Account> for Rename {
+    type Event = AccountEvent;
+    type Error = ServiceError;
+
+    fn decide(self, account: &Account) -> Result<Vec<AccountEvent>, ServiceError> {
+        match account {
+            Account::Active { name } if *name == self.name => Ok(vec![]),
+            Account::Active { .. } => Ok(vec![AccountEvent::Renamed { name: self.name }]),
+            Account::Unregistered | Account::Deleted => Err(ServiceError::UserNotFound),
+        }
+    }
+}
+
+/// Replaces the password hash of an active account.
+pub struct ChangePassword {
+    pub password_hash: String,
+}
+
+impl AggregateCommand<Account> for ChangePassword {
+    type Event = AccountEvent;
+    type Error = ServiceError;
+
+    fn decide(self, account: &Account) -> Result<Vec<AccountEvent>, ServiceError> {
+        match account {
+            Account::Active { .. } => Ok(vec![AccountEvent::PasswordChanged {
+                password_hash: self.password_hash,
+            }]),
+            Account::Unregistered | Account::Deleted => Err(ServiceError::UserNotFound),
+        }
+    }
+}
+
+/// Changes the role of an active account.
+pub struct ChangeRole {
+    pub role: Role,
+}
+
+impl AggregateCommand<Account> for ChangeRole {
+    type Event = AccountEvent;
+    type Error = ServiceError;
+
+    fn decide(self, account: &Account) -> Result<Vec<AccountEvent>, ServiceError> {
+        match account {
+            Account::Active { .. } => Ok(vec![AccountEvent::RoleChanged { role: self.role }]),
+            Account::Unregistered | Account::Deleted => Err(ServiceError::UserNotFound),
+        }
+    }
+}
+
+/// Deletes an active account.
+pub struct Delete;
+
+impl AggregateCommand<Account> for Delete {
+    type Event = AccountEvent;
+    type Error = ServiceError;
+
+    fn decide(self, account: &Account) -> Result<Vec<AccountEvent>, ServiceError> {
+        match account {
+            Account::Active { .. } => Ok(vec![AccountEvent::Deleted]),
+            Account::Unregistered | Account::Deleted => Err(ServiceError::UserNotFound),
+        }
+    }
+}
+
+/// Read model of the active users, projected from [`AccountEvent`]s.
+#[derive(Default)]
+pub(crate) struct Directory {
+    pub(crate) records: HashMap<UserId, UserRecord>,
+    /// Users by their [`normalize_name`]d names.
+    pub(crate) names: HashMap<String, UserId>,
+    /// Users by the words of their names.
+    pub(crate) search: SearchIndex<UserId>,
+}
+
+impl Directory {
+    /// Applies a committed event of the account `id`.
+    pub(crate) fn project(&mut self, id: UserId, event: &AccountEvent) {
+        match event {
+            AccountEvent::Registered {
+                name,
+                password_hash,
+            } => {
+                self.names.insert(normalize_name(name), id);
+                self.search.insert(id, name);
+                let user = User {
+                    id,
+                    name: name.clone(),
+                    friends: HashSet::new(),
+                    role: Role::User,
+                };
+                let password_hash = password_hash.clone();
+                self.records.insert(
+                    id,
+                    UserRecord {
+                        user,
+                        password_hash,
+                    },
+                );
+            }
+            AccountEvent::Renamed { name } => {
+                let Some(record) = self.records.get_mut(&id) else {
+                    return;
+                };
+                self.names.remove(&normalize_name(&record.user.name));
+                self.names.insert(normalize_name(name), id);
+                self.search.insert(id, name);
+                record.user.name.clone_from(name);
+            }
+            AccountEvent::PasswordChanged { password_hash } => {
+                if let Some(record) = self.records.get_mut(&id) {
+                    record.password_hash.clone_from(password_hash);
+                }
+            }
+            AccountEvent::RoleChanged { role } => {
+                if let Some(record) = self.records.get_mut(&id) {
+                    record.user.role = *role;
+                }
+            }
+            AccountEvent::Deleted => {
+                if let Some(record) = self.records.remove(&id) {
+                    self.names.remove(&normalize_name(&record.user.name));
+                }
+                self.search.remove(&id);
+                for other in self.records.values_mut() {
+                    other.user.friends.remove(&id);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use step_2_3::HydratedAggregate;
+
+    use super::*;
+
+    #[test]
+    fn enforces_account_lifecycle() {
+        let mut account = HydratedAggregate::<Account>::default();
+        assert_eq!(
+            account.execute(Rename { name: "bob".into() }),
+            Err(ServiceError::UserNotFound)
+        );
+        account
+            .execute(Register {
+                name: "alice".into(),
+                password_hash: "hash".into(),
+            })
+            .unwrap();
+        assert_eq!(
+            account.execute(Register {
+                name: "alice".into(),
+                password_hash: "hash".into(),
+            }),
+            Err(ServiceError::UserExists)
+        );
+        assert_eq!(
+            account.execute(Rename {
+                name: "alice".into()
+            }),
+            Ok(vec![]),
+            "renaming to the same name is a no-op"
+        );
+        account
+            .execute(Rename {
+                name: "Alice".into(),
+            })
+            .unwrap();
+        assert_eq!(
+            account.state(),
+            &Account::Active {
+                name: "Alice".into()
+            }
+        );
+
+        assert_eq!(account.execute(Delete), Ok(vec![AccountEvent::Deleted]));
+        assert_eq!(account.execute(Delete), Err(ServiceError::UserNotFound));
+        assert_eq!(
+            account.execute(Rename { name: "bob".into() }),
+            Err(ServiceError::UserNotFound)
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use std::{fmt, str::FromStr};
+
+use btreemap_proc_macro::TypedId;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Unique identifier of a user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, TypedId)]
+#[typed_id(serde)]
+pub struct UserId(Uuid);
+
+/// Unique identifier of a friend request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, TypedId)]
+#[typed_id(serde)]
+pub struct FriendRequestId(Uuid);
+
+/// Opaque session token issued on login.
+///
+/// Tokens are `MIN_LEN..=MAX_LEN` characters long and consist of ASCII
+/// alphanumerics, `-` and `_` only, so they are safe to put into headers.
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Token(String);
+
+impl Token {
+    pub const MIN_LEN: usize = 16;
+    pub const MAX_LEN: usize = 128;
+
+    /// Generates a new random token.
+    pub fn generate() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Error of parsing a [`Token`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidToken;
+
+impl fmt::Display for InvalidToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("malformed token")
+    }
+}
+
+impl std::error::Error for InvalidToken {}
+
+impl TryFrom<String> for Token {
+    type Error = InvalidToken;
+
+    fn try_from(raw: String) -> Result<Self, Self::Error> {
+        let valid = (Self::MIN_LEN..=Self::MAX_LEN).contains(&raw.len())
+            && raw
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if valid {
+            Ok(Self(raw))
+        } else {
+            Err(InvalidToken)
+        }
+    }
+}
+
+impl FromStr for Token {
+    type Err = InvalidToken;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        raw.to_owned().try_into()
+    }
+}
+
+impl From<Token> for String {
+    fn from(token: Token) -> Self {
+        token.0
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Tokens are credentials, so never print them into logs by accident.
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Token(***)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ids_and_validates_tokens() {
+        let id = UserId::new();
+        assert_eq!(id.to_string().parse::<UserId>().unwrap(), id);
+        assert!("not-a-uuid".parse::<UserId>().is_err());
+
+        let token = Token::generate();
+        assert_eq!(token.as_str().parse::<Token>().unwrap(), token);
+        assert_eq!(format!("{token:?}"), "Token(***)");
+        for invalid in ["short", "with spaces in the token", &"x".repeat(129)] {
+            assert_eq!(invalid.parse::<Token>(), Err(InvalidToken), "{invalid:?}");
+        }
+        assert!(serde_json::from_str::<Token>(r#""bad token""#).is_err());
+    }
+}
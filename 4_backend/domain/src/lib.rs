@@ -0,0 +1,1090 @@
+//! Domain layer shared by the GraphQL (`step_4`) and REST (`step_4_3`) servers.
+//!
+//! Business rules live in [`UserService`] and are tested once here, so the
+//! HTTP and GraphQL layers stay thin adapters translating errors and payloads.
+//! Accounts themselves are event-sourced, see the [`account`] module.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use common::{Clock, SystemClock};
+use serde::{Deserialize, Serialize};
+use step_2_3::{command::AggregateCommand, repository::Repository};
+use step_4_errors::AppError;
+use thiserror::Error;
+use tokio::sync::{Mutex, broadcast};
+
+pub mod account;
+mod id;
+mod name;
+mod password;
+pub mod requests;
+pub mod snapshot;
+pub mod stats;
+
+pub use account::AccountEvent;
+pub use id::{FriendRequestId, InvalidToken, Token, UserId};
+pub use name::{display_name, find_duplicate_names, normalize_name};
+pub use requests::{FriendRequest, FriendRequests};
+pub use snapshot::{SNAPSHOT_VERSION, Snapshot};
+pub use stats::{Connection, GraphStats};
+
+use account::{
+    Account, AccountId, ChangePassword, ChangeRole, Delete, Directory, Register, Rename,
+};
+use password::Verdict;
+
+/// Capacity of the [`UserEvent`] broadcast channel.
+const EVENTS_CAPACITY: usize = 64;
+
+/// Publicly visible part of a user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    pub id: UserId,
+    pub name: String,
+    pub friends: HashSet<UserId>,
+    pub role: Role,
+}
+
+/// Role of a user, deciding what it may do to the other users.
+///
+/// Every user starts as a [`Role::User`], only admins change roles.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    #[default]
+    User,
+    Admin,
+}
+
+impl Role {
+    /// Name of the role, matching its serialized form.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = ServiceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Self::User),
+            "admin" => Ok(Self::Admin),
+            _ => Err(ServiceError::UnknownRole),
+        }
+    }
+}
+
+/// Things that happened to users, published after each successful operation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UserEvent {
+    Registered {
+        id: UserId,
+        name: String,
+    },
+    Renamed {
+        id: UserId,
+        name: String,
+    },
+    LoggedIn {
+        id: UserId,
+    },
+    LoggedOut {
+        id: UserId,
+    },
+    FriendAdded {
+        user: UserId,
+        friend: UserId,
+    },
+    FriendRemoved {
+        user: UserId,
+        friend: UserId,
+    },
+    FriendRequested {
+        request: FriendRequestId,
+        from: UserId,
+        to: UserId,
+    },
+    FriendRequestAccepted {
+        request: FriendRequestId,
+        from: UserId,
+        to: UserId,
+    },
+    FriendRequestRejected {
+        request: FriendRequestId,
+        from: UserId,
+        to: UserId,
+    },
+    RoleChanged {
+        id: UserId,
+        role: Role,
+    },
+    /// Password of the user changed, ending all its previous sessions.
+    PasswordChanged {
+        id: UserId,
+    },
+    Deleted {
+        id: UserId,
+    },
+}
+
+impl UserEvent {
+    /// Name of the event, matching its serialized `type` tag.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Registered { .. } => "registered",
+            Self::Renamed { .. } => "renamed",
+            Self::LoggedIn { .. } => "logged_in",
+            Self::LoggedOut { .. } => "logged_out",
+            Self::FriendAdded { .. } => "friend_added",
+            Self::FriendRemoved { .. } => "friend_removed",
+            Self::FriendRequested { .. } => "friend_requested",
+            Self::FriendRequestAccepted { .. } => "friend_request_accepted",
+            Self::FriendRequestRejected { .. } => "friend_request_rejected",
+            Self::RoleChanged { .. } => "role_changed",
+            Self::PasswordChanged { .. } => "password_changed",
+            Self::Deleted { .. } => "deleted",
+        }
+    }
+}
+
+/// Reasons for a [`UserService`] operation to be rejected.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceError {
+    #[error("user name must not be empty")]
+    EmptyName,
+    #[error("password must not be empty")]
+    EmptyPassword,
+    #[error("user already exists")]
+    UserExists,
+    #[error("user not found")]
+    UserNotFound,
+    #[error("invalid credentials")]
+    InvalidCredentials,
+    #[error("new password must differ from the old one")]
+    SamePassword,
+    #[error("unknown role")]
+    UnknownRole,
+    #[error("user cannot befriend itself")]
+    SelfFriendship,
+    #[error("users are friends already")]
+    AlreadyFriends,
+    #[error("friend request between the users is pending already")]
+    FriendRequestExists,
+    #[error("friend request not found")]
+    FriendRequestNotFound,
+}
+
+impl From<ServiceError> for AppError {
+    fn from(err: ServiceError) -> Self {
+        match err {
+            ServiceError::UserNotFound | ServiceError::FriendRequestNotFound => {
+                Self::NotFound(err.to_string())
+            }
+            ServiceError::AlreadyFriends | ServiceError::FriendRequestExists => {
+                Self::Conflict(err.to_string())
+            }
+            err => Self::BadRequest(err.to_string()),
+        }
+    }
+}
+
+/// Token issued on a successful login.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub token: Token,
+    pub user_id: UserId,
+}
+
+/// Owner of an issued session token.
+struct IssuedToken {
+    owner: UserId,
+    /// Unset if sessions never expire.
+    expires_at: Option<Instant>,
+}
+
+impl IssuedToken {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+struct UserRecord {
+    user: User,
+    password_hash: String,
+}
+
+#[derive(Default)]
+struct Users {
+    /// Event streams of the accounts, the source of truth for the `directory`.
+    accounts: Repository<Account, AccountEvent>,
+    directory: Directory,
+    tokens: HashMap<Token, IssuedToken>,
+    /// Pending friend requests, in the order they were sent.
+    requests: Vec<FriendRequest>,
+    /// Incremented on every change of the friend graph.
+    revision: u64,
+}
+
+impl Users {
+    /// Decides the command against the replayed account of the user, commits
+    /// the produced events and projects them onto the `directory`.
+    fn execute<C>(&mut self, id: UserId, command: C) -> Result<Vec<AccountEvent>, ServiceError>
+    where
+        C: AggregateCommand<Account, Event = AccountEvent, Error = ServiceError>,
+    {
+        let mut account = self.accounts.load(AccountId::from(id));
+        let loaded = account.aggregate().version();
+        let events = account.aggregate_mut().execute(command)?;
+        self.accounts
+            .save(account.id(), loaded, events.clone())
+            .expect("accounts are only changed under the lock");
+        for event in &events {
+            self.directory.project(id, event);
+        }
+        if !events.is_empty() {
+            self.revision += 1;
+        }
+        Ok(events)
+    }
+}
+
+/// Registration, authentication and friendship operations over the user store.
+///
+/// Cloning is cheap: clones share the same store and event channel.
+#[derive(Clone)]
+pub struct UserService {
+    users: Arc<Mutex<Users>>,
+    events: broadcast::Sender<UserEvent>,
+    clock: Arc<dyn Clock>,
+    session_ttl: Option<Duration>,
+}
+
+impl Default for UserService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UserService {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+        Self {
+            users: Arc::default(),
+            events,
+            clock: Arc::new(SystemClock),
+            session_ttl: None,
+        }
+    }
+
+    /// Uses the `clock` for session expiry instead of the system time.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Makes session tokens expire `ttl` after login. They never expire by
+    /// default.
+    pub fn with_session_ttl(mut self, ttl: Duration) -> Self {
+        self.session_ttl = Some(ttl);
+        self
+    }
+
+    /// Subscribes to events published after this call.
+    pub fn subscribe(&self) -> broadcast::Receiver<UserEvent> {
+        self.events.subscribe()
+    }
+
+    /// Registers a user, rejecting names differing from taken ones only in
+    /// case or Unicode composition.
+    pub async fn register(&self, name: &str, password: &str) -> Result<User, ServiceError> {
+        let name = display_name(name);
+        if name.is_empty() {
+            return Err(ServiceError::EmptyName);
+        }
+        if password.is_empty() {
+            return Err(ServiceError::EmptyPassword);
+        }
+
+        // Hashing is slow on purpose, so it's done before taking the lock.
+        let password_hash = password::hash(password).await;
+        let mut users = self.users.lock().await;
+        if users.directory.names.contains_key(&normalize_name(&name)) {
+            return Err(ServiceError::UserExists);
+        }
+        let id = UserId::new();
+        users.execute(
+            id,
+            Register {
+                name,
+                password_hash,
+            },
+        )?;
+        let user = users.directory.records[&id].user.clone();
+        drop(users);
+
+        self.publish(UserEvent::Registered {
+            id: user.id,
+            name: user.name.clone(),
+        });
+        Ok(user)
+    }
+
+    /// Verifies credentials and issues a new session token. The name is
+    /// matched case-insensitively.
+    ///
+    /// Legacy password hashes are replaced with Argon2 ones on success.
+    pub async fn login(&self, name: &str, password: &str) -> Result<Session, ServiceError> {
+        let account = {
+            let users = self.users.lock().await;
+            users
+                .directory
+                .names
+                .get(&normalize_name(name))
+                .and_then(|id| users.directory.records.get(id))
+                .map(|record| (record.user.id, record.password_hash.clone()))
+        };
+        // Verifying is slow on purpose, so it's done without holding the lock.
+        let Some((user_id, password_hash)) = account else {
+            password::verify_nothing(password).await;
+            return Err(ServiceError::InvalidCredentials);
+        };
+        let rehashed = match password::verify(password, &password_hash).await {
+            Verdict::Valid => None,
+            Verdict::Outdated => Some(password::hash(password).await),
+            Verdict::Invalid => return Err(ServiceError::InvalidCredentials),
+        };
+
+        let mut users = self.users.lock().await;
+        // The account might have been deleted or changed in the meantime.
+        let unchanged = users
+            .directory
+            .records
+            .get(&user_id)
+            .is_some_and(|record| record.password_hash == password_hash);
+        if !unchanged {
+            return Err(ServiceError::InvalidCredentials);
+        }
+        if let Some(password_hash) = rehashed {
+            users.execute(user_id, ChangePassword { password_hash })?;
+        }
+        let session = self.start_session(&mut users, user_id);
+        drop(users);
+
+        self.publish(UserEvent::LoggedIn { id: user_id });
+        Ok(session)
+    }
+
+    /// Replaces the password of the user, given its current one, revoking
+    /// all of its sessions and issuing a new one in their place.
+    pub async fn change_password(
+        &self,
+        id: UserId,
+        old: &str,
+        new: &str,
+    ) -> Result<Session, ServiceError> {
+        if new.is_empty() {
+            return Err(ServiceError::EmptyPassword);
+        }
+        if new == old {
+            return Err(ServiceError::SamePassword);
+        }
+        let password_hash = self
+            .users
+            .lock()
+            .await
+            .directory
+            .records
+            .get(&id)
+            .map(|record| record.password_hash.clone())
+            .ok_or(ServiceError::UserNotFound)?;
+        // Both are slow on purpose, so they're done without holding the lock.
+        if password::verify(old, &password_hash).await == Verdict::Invalid {
+            return Err(ServiceError::InvalidCredentials);
+        }
+        let new_hash = password::hash(new).await;
+
+        let mut users = self.users.lock().await;
+        // The password might have been changed in the meantime.
+        let unchanged = users
+            .directory
+            .records
+            .get(&id)
+            .is_some_and(|record| record.password_hash == password_hash);
+        if !unchanged {
+            return Err(ServiceError::InvalidCredentials);
+        }
+        users.execute(
+            id,
+            ChangePassword {
+                password_hash: new_hash,
+            },
+        )?;
+        users.tokens.retain(|_, issued| issued.owner != id);
+        let session = self.start_session(&mut users, id);
+        drop(users);
+
+        self.publish(UserEvent::PasswordChanged { id });
+        Ok(session)
+    }
+
+    /// Issues a new session token of the user.
+    fn start_session(&self, users: &mut Users, owner: UserId) -> Session {
+        let token = Token::generate();
+        let expires_at = self.session_ttl.map(|ttl| self.clock.now() + ttl);
+        users
+            .tokens
+            .insert(token.clone(), IssuedToken { owner, expires_at });
+        Session {
+            token,
+            user_id: owner,
+        }
+    }
+
+    /// Resolves the owner of a session token, forgetting it if expired.
+    pub async fn authenticate(&self, token: &Token) -> Option<UserId> {
+        let mut users = self.users.lock().await;
+        let issued = users.tokens.get(token)?;
+        if issued.is_expired(self.clock.now()) {
+            users.tokens.remove(token);
+            return None;
+        }
+        Some(issued.owner)
+    }
+
+    /// Invalidates a session token, returning its owner if it was valid.
+    pub async fn revoke(&self, token: &Token) -> Option<UserId> {
+        let issued = self.users.lock().await.tokens.remove(token)?;
+        if issued.is_expired(self.clock.now()) {
+            return None;
+        }
+        let id = issued.owner;
+        self.publish(UserEvent::LoggedOut { id });
+        Some(id)
+    }
+
+    pub async fn user(&self, id: UserId) -> Result<User, ServiceError> {
+        let users = self.users.lock().await;
+        users
+            .directory
+            .records
+            .get(&id)
+            .map(|record| record.user.clone())
+            .ok_or(ServiceError::UserNotFound)
+    }
+
+    pub async fn find_by_name(&self, name: &str) -> Result<User, ServiceError> {
+        let users = self.users.lock().await;
+        users
+            .directory
+            .names
+            .get(&normalize_name(name))
+            .and_then(|id| users.directory.records.get(id))
+            .map(|record| record.user.clone())
+            .ok_or(ServiceError::UserNotFound)
+    }
+
+    /// Lists all users ordered by name.
+    pub async fn list(&self) -> Vec<User> {
+        let users = self.users.lock().await;
+        let mut list: Vec<_> = users
+            .directory
+            .records
+            .values()
+            .map(|r| r.user.clone())
+            .collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+
+    /// Finds users whose names contain words starting with every word of the
+    /// `query`, ordered by name.
+    pub async fn search(&self, query: &str) -> Vec<User> {
+        let users = self.users.lock().await;
+        let mut found: Vec<_> = users
+            .directory
+            .search
+            .search(query)
+            .iter()
+            .map(|id| users.directory.records[id].user.clone())
+            .collect();
+        found.sort_by(|a, b| a.name.cmp(&b.name));
+        found
+    }
+
+    /// Resolves friends of the user, skipping ones deleted in the meantime.
+    pub async fn friends(&self, id: UserId) -> Result<Vec<User>, ServiceError> {
+        let users = self.users.lock().await;
+        let record = users
+            .directory
+            .records
+            .get(&id)
+            .ok_or(ServiceError::UserNotFound)?;
+        Ok(record
+            .user
+            .friends
+            .iter()
+            .filter_map(|friend| users.directory.records.get(friend))
+            .map(|friend| friend.user.clone())
+            .collect())
+    }
+
+    /// Finds mutual friends of two users and the shortest chain of
+    /// friendships between them.
+    pub async fn connection(&self, id: UserId, other: UserId) -> Result<Connection, ServiceError> {
+        let users = self.users.lock().await;
+        let records = &users.directory.records;
+        if !records.contains_key(&id) || !records.contains_key(&other) {
+            return Err(ServiceError::UserNotFound);
+        }
+        let graph = stats::friend_graph(records.values().map(|record| &record.user));
+        let theirs: HashSet<_> = graph.neighbors(&other).collect();
+        let mut mutual_friends: Vec<_> = graph
+            .neighbors(&id)
+            .filter(|friend| theirs.contains(friend))
+            .map(|friend| records[friend].user.clone())
+            .collect();
+        mutual_friends.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Connection {
+            mutual_friends,
+            path: graph.shortest_path(&id, &other),
+        })
+    }
+
+    pub async fn add_friend(&self, id: UserId, friend: UserId) -> Result<(), ServiceError> {
+        if id == friend {
+            return Err(ServiceError::SelfFriendship);
+        }
+        let mut users = self.users.lock().await;
+        if !users.directory.records.contains_key(&friend) {
+            return Err(ServiceError::UserNotFound);
+        }
+        let record = users
+            .directory
+            .records
+            .get_mut(&id)
+            .ok_or(ServiceError::UserNotFound)?;
+        let added = record.user.friends.insert(friend);
+        if added {
+            users.revision += 1;
+        }
+        drop(users);
+
+        if added {
+            self.publish(UserEvent::FriendAdded { user: id, friend });
+        }
+        Ok(())
+    }
+
+    /// Removes a friend, returning whether it was in the friends list at all.
+    pub async fn remove_friend(&self, id: UserId, friend: UserId) -> Result<bool, ServiceError> {
+        let mut users = self.users.lock().await;
+        let record = users
+            .directory
+            .records
+            .get_mut(&id)
+            .ok_or(ServiceError::UserNotFound)?;
+        let removed = record.user.friends.remove(&friend);
+        if removed {
+            users.revision += 1;
+        }
+        drop(users);
+
+        if removed {
+            self.publish(UserEvent::FriendRemoved { user: id, friend });
+        }
+        Ok(removed)
+    }
+
+    /// Changes the display name of the user, rejecting names differing from
+    /// taken ones only in case or Unicode composition.
+    pub async fn rename(&self, id: UserId, name: &str) -> Result<User, ServiceError> {
+        let name = display_name(name);
+        if name.is_empty() {
+            return Err(ServiceError::EmptyName);
+        }
+
+        let mut users = self.users.lock().await;
+        let owner = users.directory.names.get(&normalize_name(&name));
+        if owner.is_some_and(|owner| *owner != id) {
+            return Err(ServiceError::UserExists);
+        }
+        let renamed = !users.execute(id, Rename { name })?.is_empty();
+        let user = users.directory.records[&id].user.clone();
+        drop(users);
+
+        if renamed {
+            self.publish(UserEvent::Renamed {
+                id,
+                name: user.name.clone(),
+            });
+        }
+        Ok(user)
+    }
+
+    /// Gives the user the `role`, which is up to the caller to authorize.
+    pub async fn change_role(&self, id: UserId, role: Role) -> Result<User, ServiceError> {
+        let mut users = self.users.lock().await;
+        let current = users
+            .directory
+            .records
+            .get(&id)
+            .map(|record| record.user.role);
+        let changed = current.is_some_and(|current| current != role)
+            && !users.execute(id, ChangeRole { role })?.is_empty();
+        let user = users
+            .directory
+            .records
+            .get(&id)
+            .map(|record| record.user.clone())
+            .ok_or(ServiceError::UserNotFound)?;
+        drop(users);
+
+        if changed {
+            self.publish(UserEvent::RoleChanged { id, role });
+        }
+        Ok(user)
+    }
+
+    /// Deletes the user along with its sessions and friendships.
+    pub async fn delete(&self, id: UserId) -> Result<(), ServiceError> {
+        let mut users = self.users.lock().await;
+        users.execute(id, Delete)?;
+        users.tokens.retain(|_, issued| issued.owner != id);
+        users
+            .requests
+            .retain(|request| request.from != id && request.to != id);
+        drop(users);
+
+        self.publish(UserEvent::Deleted { id });
+        Ok(())
+    }
+
+    /// Current revision of the friend graph, which changes whenever users or
+    /// friendships are added or removed.
+    pub async fn revision(&self) -> u64 {
+        self.users.lock().await.revision
+    }
+
+    /// Copies all users along with the [`revision`](Self::revision) they're
+    /// at, so the friend graph can be analyzed without holding the lock.
+    pub async fn users_at_revision(&self) -> (u64, Vec<User>) {
+        let users = self.users.lock().await;
+        let list = users
+            .directory
+            .records
+            .values()
+            .map(|record| record.user.clone())
+            .collect();
+        (users.revision, list)
+    }
+
+    /// Computes [`GraphStats`] of the current friend graph.
+    pub async fn stats(&self, top: usize) -> GraphStats {
+        let users = self.users.lock().await;
+        GraphStats::compute(
+            users.revision,
+            users.directory.records.values().map(|record| &record.user),
+            top,
+        )
+    }
+
+    fn publish(&self, event: UserEvent) {
+        // Having no subscribers is fine, events are a best-effort notification.
+        let _ = self.events.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::MockClock;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn registers_and_logs_in() {
+        let service = UserService::new();
+        let alice = service.register(" alice ", "secret").await.unwrap();
+        assert_eq!(alice.name, "alice");
+
+        assert_eq!(
+            service.register("alice", "other").await,
+            Err(ServiceError::UserExists)
+        );
+        assert_eq!(
+            service.register("  ", "pwd").await,
+            Err(ServiceError::EmptyName)
+        );
+        assert_eq!(
+            service.register("bob", "").await,
+            Err(ServiceError::EmptyPassword)
+        );
+
+        assert_eq!(
+            service.login("alice", "wrong").await,
+            Err(ServiceError::InvalidCredentials)
+        );
+        let session = service.login("alice", "secret").await.unwrap();
+        assert_eq!(session.user_id, alice.id);
+        assert_eq!(service.authenticate(&session.token).await, Some(alice.id));
+        assert_eq!(service.authenticate(&Token::generate()).await, None);
+
+        assert_eq!(service.revoke(&session.token).await, Some(alice.id));
+        assert_eq!(service.authenticate(&session.token).await, None);
+        assert_eq!(service.revoke(&session.token).await, None);
+    }
+
+    #[tokio::test]
+    async fn rehashes_legacy_passwords_on_login() {
+        let id = UserId::new();
+        let service = UserService::new();
+        service
+            .restore(Snapshot {
+                accounts: vec![snapshot::SnapshotEvent {
+                    user: id,
+                    event: AccountEvent::Registered {
+                        name: "alice".into(),
+                        // SHA-256 of "secret"
+                        password_hash:
+                            "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b"
+                                .into(),
+                    },
+                }],
+                ..Snapshot::default()
+            })
+            .await;
+
+        assert_eq!(
+            service.login("alice", "wrong").await,
+            Err(ServiceError::InvalidCredentials)
+        );
+        assert_eq!(service.login("alice", "secret").await.unwrap().user_id, id);
+        let accounts = service.snapshot().await.accounts;
+        assert!(matches!(
+            &accounts[1].event,
+            AccountEvent::PasswordChanged { password_hash } if password_hash.starts_with("$argon2id$")
+        ));
+        assert!(service.login("alice", "secret").await.is_ok());
+        assert_eq!(service.snapshot().await.accounts.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn changes_passwords_revoking_sessions() {
+        let service = UserService::new();
+        let alice = service.register("alice", "secret").await.unwrap();
+        let bob = service.register("bob", "hunter2").await.unwrap();
+        let first = service.login("alice", "secret").await.unwrap();
+        let second = service.login("alice", "secret").await.unwrap();
+        let other = service.login("bob", "hunter2").await.unwrap();
+
+        for (old, new, err) in [
+            ("wrong", "changed", ServiceError::InvalidCredentials),
+            ("secret", "", ServiceError::EmptyPassword),
+            ("secret", "secret", ServiceError::SamePassword),
+        ] {
+            assert_eq!(service.change_password(alice.id, old, new).await, Err(err));
+        }
+        assert_eq!(service.authenticate(&first.token).await, Some(alice.id));
+
+        let mut events = service.subscribe();
+        let session = service
+            .change_password(alice.id, "secret", "changed")
+            .await
+            .unwrap();
+        assert_eq!(session.user_id, alice.id);
+        assert_eq!(
+            events.recv().await.unwrap(),
+            UserEvent::PasswordChanged { id: alice.id }
+        );
+        for revoked in [&first, &second] {
+            assert_eq!(service.authenticate(&revoked.token).await, None);
+        }
+        assert_eq!(service.authenticate(&session.token).await, Some(alice.id));
+        assert_eq!(service.authenticate(&other.token).await, Some(bob.id));
+
+        assert_eq!(
+            service.login("alice", "secret").await,
+            Err(ServiceError::InvalidCredentials)
+        );
+        assert!(service.login("alice", "changed").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn keeps_roles_by_user_across_renames_and_restores() {
+        let service = UserService::new();
+        let alice = service.register("alice", "secret").await.unwrap();
+        assert_eq!(alice.role, Role::User);
+        assert_eq!(
+            service.change_role(UserId::new(), Role::Admin).await,
+            Err(ServiceError::UserNotFound)
+        );
+
+        let mut events = service.subscribe();
+        let admin = service.change_role(alice.id, Role::Admin).await.unwrap();
+        assert_eq!(admin.role, Role::Admin);
+        service.change_role(alice.id, Role::Admin).await.unwrap();
+        service.rename(alice.id, "root").await.unwrap();
+        assert_eq!(
+            events.recv().await.unwrap(),
+            UserEvent::RoleChanged {
+                id: alice.id,
+                role: Role::Admin
+            }
+        );
+        assert!(matches!(events.recv().await, Ok(UserEvent::Renamed { .. })));
+
+        let mallory = service.register("alice", "pwd").await.unwrap();
+        assert_eq!(mallory.role, Role::User, "roles aren't tied to names");
+        let restored = UserService::new();
+        restored.restore(service.snapshot().await).await;
+        assert_eq!(restored.user(alice.id).await.unwrap().role, Role::Admin);
+        assert_eq!("admin".parse(), Ok(Role::Admin));
+        assert_eq!("root".parse::<Role>(), Err(ServiceError::UnknownRole));
+    }
+
+    #[tokio::test]
+    async fn sessions_expire_by_clock() {
+        let clock = MockClock::new();
+        let service = UserService::new()
+            .with_clock(Arc::new(clock.clone()))
+            .with_session_ttl(Duration::from_secs(60));
+        let alice = service.register("alice", "secret").await.unwrap();
+        let expiring = service.login("alice", "secret").await.unwrap();
+
+        clock.advance(Duration::from_secs(30));
+        let renewed = service.login("alice", "secret").await.unwrap();
+        assert_eq!(service.authenticate(&expiring.token).await, Some(alice.id));
+
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(service.authenticate(&expiring.token).await, None);
+        assert_eq!(service.revoke(&expiring.token).await, None);
+        assert_eq!(service.authenticate(&renewed.token).await, Some(alice.id));
+
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(service.revoke(&renewed.token).await, None);
+    }
+
+    #[tokio::test]
+    async fn names_are_case_insensitive() {
+        let service = UserService::new();
+        let alice = service.register("Alice", "secret").await.unwrap();
+        assert_eq!(alice.name, "Alice", "display name is kept as entered");
+
+        assert_eq!(
+            service.register("alice", "other").await,
+            Err(ServiceError::UserExists)
+        );
+        assert_eq!(
+            service.register(" ALICE ", "other").await,
+            Err(ServiceError::UserExists)
+        );
+
+        for name in ["Alice", "alice", "ALICE", " aLiCe "] {
+            let session = service.login(name, "secret").await.unwrap();
+            assert_eq!(session.user_id, alice.id, "login as {name:?}");
+        }
+        assert_eq!(
+            service.login("ALICE", "Secret").await,
+            Err(ServiceError::InvalidCredentials),
+            "passwords stay case-sensitive"
+        );
+        assert_eq!(service.find_by_name("aLICE").await, Ok(alice.clone()));
+
+        service.delete(alice.id).await.unwrap();
+        service.register("alice", "secret").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn renames_and_replays_accounts() {
+        let service = UserService::new();
+        let alice = service.register("alice", "secret").await.unwrap();
+        let bob = service.register("bob", "hunter2").await.unwrap();
+
+        assert_eq!(
+            service.rename(alice.id, "BOB").await,
+            Err(ServiceError::UserExists)
+        );
+        assert_eq!(
+            service.rename(UserId::new(), "carol").await,
+            Err(ServiceError::UserNotFound)
+        );
+        let renamed = service.rename(alice.id, " Alice ").await.unwrap();
+        assert_eq!(renamed.name, "Alice");
+        assert!(service.login("alice", "secret").await.is_ok());
+        service.rename(bob.id, "robert").await.unwrap();
+        assert_eq!(
+            service.find_by_name("bob").await,
+            Err(ServiceError::UserNotFound)
+        );
+        service.delete(bob.id).await.unwrap();
+        service.register("bob", "other").await.unwrap();
+
+        // The read model is a pure projection of the account events.
+        let users = service.users.lock().await;
+        let mut rebuilt = Directory::default();
+        users.accounts.replay(|committed| {
+            rebuilt.project(committed.aggregate_id.parse().unwrap(), &committed.event);
+        });
+        assert_eq!(rebuilt.names, users.directory.names);
+        for (id, record) in &users.directory.records {
+            assert_eq!(rebuilt.records[id].user.name, record.user.name);
+            assert_eq!(rebuilt.records[id].password_hash, record.password_hash);
+        }
+        assert_eq!(rebuilt.records.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn searches_by_name_prefixes() {
+        let service = UserService::new();
+        let ada = service.register("Ada Lovelace", "secret").await.unwrap();
+        let alan = service.register("Alan Turing", "secret").await.unwrap();
+        service.register("Grace Hopper", "secret").await.unwrap();
+
+        let names = |users: Vec<User>| users.into_iter().map(|u| u.name).collect::<Vec<_>>();
+        assert_eq!(
+            names(service.search("a").await),
+            ["Ada Lovelace", "Alan Turing"]
+        );
+        assert_eq!(names(service.search("TUR al").await), ["Alan Turing"]);
+
+        service.rename(ada.id, "Ada King").await.unwrap();
+        assert_eq!(
+            names(service.search("lovelace").await),
+            Vec::<String>::new()
+        );
+        assert_eq!(names(service.search("king").await), ["Ada King"]);
+        service.delete(alan.id).await.unwrap();
+        assert_eq!(names(service.search("a").await), ["Ada King"]);
+        assert!(service.search("").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn manages_friendships() {
+        let service = UserService::new();
+        let alice = service.register("alice", "secret").await.unwrap();
+        let bob = service.register("bob", "hunter2").await.unwrap();
+
+        assert_eq!(
+            service.add_friend(alice.id, alice.id).await,
+            Err(ServiceError::SelfFriendship)
+        );
+        assert_eq!(
+            service.add_friend(alice.id, UserId::new()).await,
+            Err(ServiceError::UserNotFound)
+        );
+        let revision = service.revision().await;
+        service.add_friend(alice.id, bob.id).await.unwrap();
+        assert_eq!(service.revision().await, revision + 1);
+        service.add_friend(alice.id, bob.id).await.unwrap();
+        assert_eq!(
+            service.revision().await,
+            revision + 1,
+            "no-op keeps revision"
+        );
+        let stats = service.stats(1).await;
+        assert_eq!(stats.revision, revision + 1);
+        assert_eq!(stats.most_connected[0].id, alice.id);
+        let friends = service.friends(alice.id).await.unwrap();
+        assert_eq!(friends, vec![bob.clone()]);
+
+        assert_eq!(service.remove_friend(alice.id, bob.id).await, Ok(true));
+        assert_eq!(service.remove_friend(alice.id, bob.id).await, Ok(false));
+        assert!(service.friends(alice.id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn finds_mutual_friends_and_separation() {
+        let service = UserService::new();
+        let [alice, bob, carol, dave, erin] = [
+            service.register("alice", "secret").await.unwrap(),
+            service.register("bob", "secret").await.unwrap(),
+            service.register("carol", "secret").await.unwrap(),
+            service.register("dave", "secret").await.unwrap(),
+            service.register("erin", "secret").await.unwrap(),
+        ];
+        for (id, friend) in [(alice.id, bob.id), (carol.id, bob.id), (carol.id, dave.id)] {
+            service.add_friend(id, friend).await.unwrap();
+        }
+
+        let connection = service.connection(alice.id, carol.id).await.unwrap();
+        let mutual: Vec<_> = connection.mutual_friends.iter().map(|u| u.id).collect();
+        assert_eq!(mutual, [bob.id]);
+        assert_eq!(connection.path, Some(vec![alice.id, bob.id, carol.id]));
+        assert_eq!(connection.separation(), Some(2));
+
+        let far = service.connection(dave.id, alice.id).await.unwrap();
+        assert!(far.mutual_friends.is_empty());
+        assert_eq!(far.separation(), Some(3));
+        let none = service.connection(alice.id, erin.id).await.unwrap();
+        assert_eq!((none.separation(), none.path), (None, None));
+        assert_eq!(
+            service.connection(alice.id, UserId::new()).await,
+            Err(ServiceError::UserNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_cleans_up_sessions_and_friendships() {
+        let service = UserService::new();
+        let alice = service.register("alice", "secret").await.unwrap();
+        let bob = service.register("bob", "hunter2").await.unwrap();
+        service.add_friend(alice.id, bob.id).await.unwrap();
+        let session = service.login("bob", "hunter2").await.unwrap();
+
+        service.delete(bob.id).await.unwrap();
+
+        assert_eq!(service.authenticate(&session.token).await, None);
+        assert!(service.user(alice.id).await.unwrap().friends.is_empty());
+        assert_eq!(
+            service.find_by_name("bob").await,
+            Err(ServiceError::UserNotFound)
+        );
+        assert_eq!(
+            service.delete(bob.id).await,
+            Err(ServiceError::UserNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn publishes_events() {
+        let service = UserService::new();
+        let mut events = service.subscribe();
+
+        let alice = service.register("alice", "secret").await.unwrap();
+        let bob = service.register("bob", "hunter2").await.unwrap();
+        service.add_friend(alice.id, bob.id).await.unwrap();
+        service.add_friend(alice.id, bob.id).await.unwrap();
+
+        assert_eq!(
+            events.recv().await.unwrap(),
+            UserEvent::Registered {
+                id: alice.id,
+                name: "alice".into()
+            }
+        );
+        assert!(matches!(
+            events.recv().await,
+            Ok(UserEvent::Registered { .. })
+        ));
+        assert_eq!(
+            events.recv().await.unwrap(),
+            UserEvent::FriendAdded {
+                user: alice.id,
+                friend: bob.id
+            }
+        );
+        assert!(events.try_recv().is_err(), "re-adding a friend is a no-op");
+
+        let event = UserEvent::LoggedIn { id: alice.id };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], event.kind());
+    }
+}
@@ -0,0 +1,59 @@
+//! Normalization of user names, making them unique regardless of case.
+
+use std::collections::BTreeMap;
+
+use unicase::UniCase;
+use unicode_normalization::UnicodeNormalization as _;
+
+/// Form of a user name as it is displayed: trimmed and NFC-composed.
+pub fn display_name(name: &str) -> String {
+    name.trim().nfc().collect()
+}
+
+/// Key two user names are compared by: [`display_name`] with full Unicode
+/// case folding, so "Alice", "ALICE" and a decomposed "Ålice" all map to the
+/// same key, and so do "straße" and "STRASSE".
+pub fn normalize_name(name: &str) -> String {
+    // Folding is defined on decomposed text and may break the composition,
+    // so compose the result again.
+    let decomposed: String = name.trim().nfd().collect();
+    UniCase::unicode(decomposed).to_folded_case().nfc().collect()
+}
+
+/// Groups names colliding after [`normalize_name`], for checking data stored
+/// before the normalization was introduced. Groups are ordered by their key.
+pub fn find_duplicate_names<'a>(names: impl IntoIterator<Item = &'a str>) -> Vec<Vec<&'a str>> {
+    let mut groups = BTreeMap::<_, Vec<_>>::new();
+    for name in names {
+        groups.entry(normalize_name(name)).or_default().push(name);
+    }
+    groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_case_and_composition() {
+        assert_eq!(normalize_name(" Alice "), "alice");
+        assert_eq!(normalize_name("ALICE"), normalize_name("alice"));
+        assert_eq!(normalize_name("A\u{030A}sa"), normalize_name("\u{00E5}sa"));
+        assert_eq!(display_name("A\u{030A}sa "), "\u{00C5}sa");
+        assert_ne!(normalize_name("alice"), normalize_name("alicia"));
+        // Case folding, unlike lowercasing, may change the length
+        assert_eq!(normalize_name("STRASSE"), "strasse");
+        assert_eq!(normalize_name("straße"), normalize_name("STRASSE"));
+        assert_eq!(normalize_name("ΟΔΟΣ"), normalize_name("οδος"));
+        assert_eq!(normalize_name("ǰ"), normalize_name("J\u{030C}"));
+
+        assert_eq!(
+            find_duplicate_names(["bob", "Alice", "carol", "alice", "BOB"]),
+            vec![vec!["Alice", "alice"], vec!["bob", "BOB"]],
+        );
+        assert!(find_duplicate_names(["alice", "bob"]).is_empty());
+    }
+}
@@ -0,0 +1,89 @@
+//! Password hashes stored with the accounts.
+//!
+//! New hashes are salted Argon2 ones from `step_3_7`. Accounts registered
+//! before that keep unsalted SHA-256 digests until their next login, which
+//! rehashes the password.
+//!
+//! Argon2 is slow and memory-hungry on purpose, so the async functions run it
+//! on the blocking thread pool, keeping the runtime workers free for other
+//! requests.
+
+use std::sync::LazyLock;
+
+use sha2::{Digest, Sha256};
+
+/// Result of checking a password against a stored hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Verdict {
+    Valid,
+    /// The password matches a legacy hash, which is to be replaced.
+    Outdated,
+    Invalid,
+}
+
+/// Hashes the password with Argon2 and a random salt.
+pub(crate) async fn hash(password: &str) -> String {
+    let password = password.to_owned();
+    blocking(move || hash_now(&password)).await
+}
+
+/// Checks the password in constant time, whatever the format of the hash.
+pub(crate) async fn verify(password: &str, hash: &str) -> Verdict {
+    let (password, hash) = (password.to_owned(), hash.to_owned());
+    blocking(move || verify_now(&password, &hash)).await
+}
+
+/// Takes as long as [`verify`] does, so unknown users can't be told apart
+/// from wrong passwords by the response time.
+pub(crate) async fn verify_nothing(password: &str) {
+    static HASH: LazyLock<String> = LazyLock::new(|| hash_now("nothing"));
+    let password = password.to_owned();
+    blocking(move || verify_now(&password, &HASH)).await;
+}
+
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    tokio::task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))
+}
+
+fn hash_now(password: &str) -> String {
+    step_3_7::hash_password(password).expect("Argon2 with default params hashes any password")
+}
+
+fn verify_now(password: &str, hash: &str) -> Verdict {
+    if hash.starts_with('$') {
+        return match step_3_7::verify_password(password, hash) {
+            Ok(true) => Verdict::Valid,
+            // A malformed hash matches no password.
+            Ok(false) | Err(_) => Verdict::Invalid,
+        };
+    }
+    let digest = format!("{:x}", Sha256::digest(password.as_bytes()));
+    if constant_time_eq(digest.as_bytes(), hash.as_bytes()) {
+        Verdict::Outdated
+    } else {
+        Verdict::Invalid
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn verifies_argon2_and_legacy_hashes() {
+        let hash = hash("secret").await;
+        assert_eq!(verify("secret", &hash).await, Verdict::Valid);
+        assert_eq!(verify("Secret", &hash).await, Verdict::Invalid);
+
+        let legacy = "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b";
+        assert_eq!(verify("secret", legacy).await, Verdict::Outdated);
+        assert_eq!(verify("Secret", legacy).await, Verdict::Invalid);
+        assert_eq!(verify(legacy, legacy).await, Verdict::Invalid);
+    }
+}
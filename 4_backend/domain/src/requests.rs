@@ -0,0 +1,213 @@
+//! Friend requests, befriending two users only once the target accepts.
+//!
+//! Pending requests are kept along with the sessions, outside of the
+//! event-sourced accounts. Accepting a request makes its users friends of each
+//! other, rejecting it just forgets the request.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{FriendRequestId, ServiceError, UserEvent, UserId, UserService, Users};
+
+/// Pending request of the user `from` to befriend the user `to`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FriendRequest {
+    pub id: FriendRequestId,
+    pub from: UserId,
+    pub to: UserId,
+}
+
+/// Pending friend requests of a user, in the order they were sent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FriendRequests {
+    /// Requests waiting for the user to accept or reject them.
+    pub incoming: Vec<FriendRequest>,
+    /// Requests the user is waiting to be answered.
+    pub outgoing: Vec<FriendRequest>,
+}
+
+impl UserService {
+    /// Asks the user `to` to befriend the user `from`.
+    ///
+    /// Only a single request between two users may be pending, whichever of
+    /// them sent it.
+    pub async fn send_friend_request(
+        &self,
+        from: UserId,
+        to: UserId,
+    ) -> Result<FriendRequest, ServiceError> {
+        if from == to {
+            return Err(ServiceError::SelfFriendship);
+        }
+        let mut users = self.users.lock().await;
+        let records = &users.directory.records;
+        let (Some(sender), Some(target)) = (records.get(&from), records.get(&to)) else {
+            return Err(ServiceError::UserNotFound);
+        };
+        if sender.user.friends.contains(&to) && target.user.friends.contains(&from) {
+            return Err(ServiceError::AlreadyFriends);
+        }
+        let pending = users.requests.iter().any(|request| {
+            (request.from, request.to) == (from, to) || (request.from, request.to) == (to, from)
+        });
+        if pending {
+            return Err(ServiceError::FriendRequestExists);
+        }
+        let request = FriendRequest {
+            id: FriendRequestId::new(),
+            from,
+            to,
+        };
+        users.requests.push(request.clone());
+        drop(users);
+
+        self.publish(UserEvent::FriendRequested {
+            request: request.id,
+            from,
+            to,
+        });
+        Ok(request)
+    }
+
+    /// Accepts the request sent to the `user`, making both users friends of
+    /// each other.
+    pub async fn accept_friend_request(
+        &self,
+        id: FriendRequestId,
+        user: UserId,
+    ) -> Result<FriendRequest, ServiceError> {
+        let mut users = self.users.lock().await;
+        let request = users.take_request(id, user)?;
+        for (user, friend) in [(request.from, request.to), (request.to, request.from)] {
+            if let Some(record) = users.directory.records.get_mut(&user) {
+                record.user.friends.insert(friend);
+            }
+        }
+        users.revision += 1;
+        drop(users);
+
+        self.publish(UserEvent::FriendRequestAccepted {
+            request: id,
+            from: request.from,
+            to: request.to,
+        });
+        Ok(request)
+    }
+
+    /// Rejects the request sent to the `user`.
+    pub async fn reject_friend_request(
+        &self,
+        id: FriendRequestId,
+        user: UserId,
+    ) -> Result<FriendRequest, ServiceError> {
+        let request = self.users.lock().await.take_request(id, user)?;
+        self.publish(UserEvent::FriendRequestRejected {
+            request: id,
+            from: request.from,
+            to: request.to,
+        });
+        Ok(request)
+    }
+
+    /// Pending requests sent to and by the `user`.
+    pub async fn friend_requests(&self, user: UserId) -> FriendRequests {
+        let users = self.users.lock().await;
+        let (incoming, outgoing) = users
+            .requests
+            .iter()
+            .filter(|request| request.to == user || request.from == user)
+            .cloned()
+            .partition(|request| request.to == user);
+        FriendRequests { incoming, outgoing }
+    }
+}
+
+impl Users {
+    /// Removes the pending request, unless it's sent to someone else than the
+    /// `user`, which is reported the same as a missing request.
+    fn take_request(
+        &mut self,
+        id: FriendRequestId,
+        user: UserId,
+    ) -> Result<FriendRequest, ServiceError> {
+        let index = self
+            .requests
+            .iter()
+            .position(|request| request.id == id && request.to == user)
+            .ok_or(ServiceError::FriendRequestNotFound)?;
+        Ok(self.requests.remove(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn befriends_only_once_the_target_accepts() {
+        let service = UserService::new();
+        let alice = service.register("alice", "secret").await.unwrap().id;
+        let bob = service.register("bob", "hunter2").await.unwrap().id;
+        let carol = service.register("carol", "pwd").await.unwrap().id;
+
+        let request = service.send_friend_request(alice, bob).await.unwrap();
+        assert_eq!(
+            service.send_friend_request(bob, alice).await,
+            Err(ServiceError::FriendRequestExists)
+        );
+        assert_eq!(
+            service.send_friend_request(alice, alice).await,
+            Err(ServiceError::SelfFriendship)
+        );
+        assert_eq!(
+            service.friend_requests(bob).await,
+            FriendRequests {
+                incoming: vec![request.clone()],
+                outgoing: vec![],
+            }
+        );
+        assert_eq!(
+            service.friend_requests(alice).await.outgoing,
+            std::slice::from_ref(&request)
+        );
+        assert!(service.friends(alice).await.unwrap().is_empty());
+
+        for not_target in [alice, carol] {
+            assert_eq!(
+                service.accept_friend_request(request.id, not_target).await,
+                Err(ServiceError::FriendRequestNotFound)
+            );
+        }
+        assert_eq!(
+            service.accept_friend_request(request.id, bob).await,
+            Ok(request.clone())
+        );
+        assert_eq!(service.friends(alice).await.unwrap()[0].id, bob);
+        assert_eq!(service.friends(bob).await.unwrap()[0].id, alice);
+        assert_eq!(
+            service.friend_requests(bob).await,
+            FriendRequests::default()
+        );
+        assert_eq!(
+            service.send_friend_request(bob, alice).await,
+            Err(ServiceError::AlreadyFriends)
+        );
+
+        let request = service.send_friend_request(carol, alice).await.unwrap();
+        assert_eq!(
+            service.reject_friend_request(request.id, alice).await,
+            Ok(request.clone())
+        );
+        assert_eq!(
+            service.reject_friend_request(request.id, alice).await,
+            Err(ServiceError::FriendRequestNotFound)
+        );
+        assert_eq!(service.friends(carol).await.unwrap(), []);
+
+        service.send_friend_request(carol, bob).await.unwrap();
+        service.delete(carol).await.unwrap();
+        assert_eq!(
+            service.friend_requests(bob).await,
+            FriendRequests::default()
+        );
+    }
+}
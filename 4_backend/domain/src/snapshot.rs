@@ -0,0 +1,171 @@
+//! Serializable [`Snapshot`] of the [`UserService`] state.
+//!
+//! Accounts are stored as their committed events, so restoring replays them
+//! exactly as they happened. Friendships and sessions are stored as is.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AccountEvent, FriendRequest, IssuedToken, Token, UserId, UserService, Users, account::AccountId,
+};
+
+/// Version of the [`Snapshot`] schema, to be bumped on incompatible changes.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// State of a [`UserService`] at some point in time.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Committed events of all the accounts, in the commit order.
+    pub accounts: Vec<SnapshotEvent>,
+    pub friendships: Vec<(UserId, UserId)>,
+    pub sessions: Vec<SnapshotSession>,
+    /// Pending friend requests, in the order they were sent.
+    #[serde(default)]
+    pub friend_requests: Vec<FriendRequest>,
+}
+
+/// Event committed to the account of the `user`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotEvent {
+    pub user: UserId,
+    pub event: AccountEvent,
+}
+
+/// Session token along with its remaining lifetime.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotSession {
+    pub token: Token,
+    pub user: UserId,
+    /// Unset if the session never expires.
+    pub expires_in: Option<Duration>,
+}
+
+impl UserService {
+    /// Captures the current state, skipping expired sessions.
+    pub async fn snapshot(&self) -> Snapshot {
+        let users = self.users.lock().await;
+        let mut accounts = Vec::new();
+        users.accounts.replay(|committed| {
+            accounts.push(SnapshotEvent {
+                user: committed
+                    .aggregate_id
+                    .parse()
+                    .expect("accounts are keyed by user IDs"),
+                event: committed.event.clone(),
+            });
+        });
+        let mut friendships: Vec<_> = users
+            .directory
+            .records
+            .values()
+            .flat_map(|record| {
+                let id = record.user.id;
+                record.user.friends.iter().map(move |friend| (id, *friend))
+            })
+            .collect();
+        let now = self.clock.now();
+        let mut sessions: Vec<_> = users
+            .tokens
+            .iter()
+            .filter(|(_, issued)| !issued.is_expired(now))
+            .map(|(token, issued)| SnapshotSession {
+                token: token.clone(),
+                user: issued.owner,
+                expires_in: issued.expires_at.map(|at| at - now),
+            })
+            .collect();
+        // Sorted, so equal states produce equal snapshots.
+        friendships.sort();
+        sessions.sort_by(|a, b| a.token.as_str().cmp(b.token.as_str()));
+
+        Snapshot {
+            accounts,
+            friendships,
+            sessions,
+            friend_requests: users.requests.clone(),
+        }
+    }
+
+    /// Replaces the current state with the `snapshot`.
+    ///
+    /// Events aren't published for the restored state.
+    pub async fn restore(&self, snapshot: Snapshot) {
+        let mut restored = Users::default();
+        for SnapshotEvent { user, event } in snapshot.accounts {
+            let id = AccountId::from(user);
+            let loaded = restored.accounts.load(id.clone()).aggregate().version();
+            restored
+                .accounts
+                .save(&id, loaded, vec![event.clone()])
+                .expect("the account was just loaded");
+            restored.directory.project(user, &event);
+        }
+        for (user, friend) in snapshot.friendships {
+            let records = &mut restored.directory.records;
+            if !records.contains_key(&friend) {
+                continue;
+            }
+            if let Some(record) = records.get_mut(&user) {
+                record.user.friends.insert(friend);
+            }
+        }
+        restored.requests = snapshot
+            .friend_requests
+            .into_iter()
+            .filter(|request| {
+                let records = &restored.directory.records;
+                records.contains_key(&request.from) && records.contains_key(&request.to)
+            })
+            .collect();
+        let now = self.clock.now();
+        for session in snapshot.sessions {
+            restored.tokens.insert(
+                session.token,
+                IssuedToken {
+                    owner: session.user,
+                    expires_at: session.expires_in.map(|ttl| now + ttl),
+                },
+            );
+        }
+
+        let mut users = self.users.lock().await;
+        restored.revision = users.revision + 1;
+        *users = restored;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn restores_snapshotted_state() {
+        let service = UserService::new();
+        let alice = service.register("alice", "secret").await.unwrap();
+        let bob = service.register("bob", "hunter2").await.unwrap();
+        let carol = service.register("carol", "pwd").await.unwrap();
+        service.rename(bob.id, "Bobby").await.unwrap();
+        service.delete(carol.id).await.unwrap();
+        service.add_friend(alice.id, bob.id).await.unwrap();
+        service.send_friend_request(bob.id, alice.id).await.unwrap();
+        let session = service.login("alice", "secret").await.unwrap();
+
+        let snapshot = service.snapshot().await;
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let restored = UserService::new();
+        restored.restore(serde_json::from_str(&json).unwrap()).await;
+
+        assert_eq!(restored.snapshot().await, snapshot);
+        assert_eq!(restored.list().await, service.list().await);
+        assert_eq!(restored.find_by_name("bobby").await.unwrap().id, bob.id);
+        assert_eq!(restored.authenticate(&session.token).await, Some(alice.id));
+        assert!(restored.login("bobby", "hunter2").await.is_ok());
+        assert_eq!(
+            restored.register("carol", "pwd").await.map(|_| ()),
+            Ok(()),
+            "deleted names are free again"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Friend graph analytics: degrees, connected components and a leaderboard.
+
+use common::Graph;
+
+use crate::{User, UserId};
+
+/// Number of friends of a user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserDegree {
+    pub id: UserId,
+    pub name: String,
+    pub friends: usize,
+}
+
+/// Snapshot of the friend graph statistics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphStats {
+    /// [`UserService::revision`](crate::UserService::revision) the stats were
+    /// computed at.
+    pub revision: u64,
+    pub users: usize,
+    /// Number of friendship links, each direction counted separately.
+    pub friendships: usize,
+    /// Degrees of all users, the most connected first, then by name.
+    pub degrees: Vec<UserDegree>,
+    /// Groups of users linked by friendships in any direction, the largest
+    /// first. Members are ordered by their ids.
+    pub components: Vec<Vec<UserId>>,
+    /// Leading part of `degrees`.
+    pub most_connected: Vec<UserDegree>,
+}
+
+impl GraphStats {
+    /// Computes the stats of the users, keeping `top` of them in the
+    /// leaderboard.
+    pub fn compute<'a>(
+        revision: u64,
+        users: impl IntoIterator<Item = &'a User>,
+        top: usize,
+    ) -> Self {
+        let users: Vec<_> = users.into_iter().collect();
+        let mut components = friend_graph(users.iter().copied()).components();
+        for group in &mut components {
+            group.sort_unstable();
+        }
+        components.sort_unstable_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+
+        let mut degrees: Vec<_> = users
+            .iter()
+            .map(|user| UserDegree {
+                id: user.id,
+                name: user.name.clone(),
+                friends: user.friends.len(),
+            })
+            .collect();
+        degrees
+            .sort_unstable_by(|a, b| b.friends.cmp(&a.friends).then_with(|| a.name.cmp(&b.name)));
+
+        Self {
+            revision,
+            users: users.len(),
+            friendships: degrees.iter().map(|d| d.friends).sum(),
+            most_connected: degrees.iter().take(top).cloned().collect(),
+            degrees,
+            components,
+        }
+    }
+}
+
+/// How two users are linked by friendships in any direction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connection {
+    /// Users linked to both of them, ordered by name.
+    pub mutual_friends: Vec<User>,
+    /// Shortest chain of users from one to the other, both included.
+    pub path: Option<Vec<UserId>>,
+}
+
+impl Connection {
+    /// Degrees of separation: 1 for friends, 2 for friends of friends and so
+    /// on. `None` if the users aren't connected.
+    pub fn separation(&self) -> Option<usize> {
+        self.path.as_ref().map(|path| path.len() - 1)
+    }
+}
+
+/// Graph of the users linked by friendships in any direction.
+///
+/// Friends missing from the `users` are left out.
+pub fn friend_graph<'a>(users: impl IntoIterator<Item = &'a User>) -> Graph<UserId> {
+    let users: Vec<_> = users.into_iter().collect();
+    let mut graph = Graph::new();
+    for user in &users {
+        graph.add_node(user.id);
+    }
+    for user in &users {
+        for friend in &user.friends {
+            if graph.contains(friend) {
+                graph.add_edge(user.id, *friend);
+            }
+        }
+    }
+    graph
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::Role;
+
+    use super::*;
+
+    fn user(name: &str) -> User {
+        User {
+            id: UserId::new(),
+            name: name.into(),
+            friends: HashSet::new(),
+            role: Role::User,
+        }
+    }
+
+    #[test]
+    fn computes_degrees_and_components() {
+        let [mut alice, mut bob, carol, mut dave, erin] =
+            ["alice", "bob", "carol", "dave", "erin"].map(user);
+        alice.friends.extend([bob.id, carol.id]);
+        bob.friends.insert(alice.id);
+        dave.friends.insert(erin.id);
+
+        let users = [&alice, &bob, &carol, &dave, &erin];
+        let stats = GraphStats::compute(7, users, 2);
+
+        assert_eq!(stats.revision, 7);
+        assert_eq!(stats.users, 5);
+        assert_eq!(stats.friendships, 4);
+        let names: Vec<_> = stats
+            .degrees
+            .iter()
+            .map(|d| (d.name.as_str(), d.friends))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("alice", 2),
+                ("bob", 1),
+                ("dave", 1),
+                ("carol", 0),
+                ("erin", 0)
+            ]
+        );
+        assert_eq!(stats.most_connected, stats.degrees[..2]);
+
+        let mut first = vec![alice.id, bob.id, carol.id];
+        first.sort_unstable();
+        let mut second = vec![dave.id, erin.id];
+        second.sort_unstable();
+        assert_eq!(stats.components, [first, second]);
+    }
+}
@@ -0,0 +1,189 @@
+//! Error type shared by the backend servers.
+//!
+//! [`AppError`] classifies failures by how clients see them, so every variant
+//! maps to exactly one HTTP status, see [`AppError::status`]. Conversions from
+//! the storage, HTTP client and password hashing errors are enabled by the
+//! features named after those crates, letting `?` do the translation.
+
+use http::StatusCode;
+use thiserror::Error;
+
+/// Failure of a request, classified by its HTTP status.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum AppError {
+    /// The request is malformed or violates a domain rule.
+    #[error("{0}")]
+    BadRequest(String),
+    /// The caller could not be identified.
+    #[error("not authorized")]
+    Unauthorized,
+    /// The caller is known but lacks the named permission.
+    #[error("missing permission '{0}'")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    /// The request clashes with existing data, e.g. a unique key.
+    #[error("{0}")]
+    Conflict(String),
+    /// A service called on behalf of the request failed.
+    #[error("upstream request failed: {0}")]
+    Upstream(String),
+    /// Storage or another internal dependency failed.
+    #[error("internal error: {0}")]
+    Internal(String),
+    /// The service is out of capacity for now, the request may be retried.
+    #[error("{0}")]
+    Unavailable(String),
+}
+
+impl AppError {
+    /// HTTP status the error is reported with.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Upstream(_) => StatusCode::BAD_GATEWAY,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+/// Returns early with an error unless the condition holds.
+///
+/// The error is either any expression convertible into the function's error
+/// type, or an [`AppError`] variant followed by a format string for its message:
+///
+/// ```
+/// use step_4_errors::{AppError, ensure};
+///
+/// fn rename(name: &str) -> Result<(), AppError> {
+///     ensure!(!name.is_empty(), BadRequest, "name must not be empty");
+///     ensure!(name != "root", AppError::Forbidden("users.root".into()));
+///     Ok(())
+/// }
+///
+/// assert_eq!(rename(""), Err(AppError::BadRequest("name must not be empty".into())));
+/// assert_eq!(rename("root").unwrap_err().to_string(), "missing permission 'users.root'");
+/// ```
+#[macro_export]
+macro_rules! ensure {
+    ($cond:expr, $variant:ident, $($fmt:tt)+) => {
+        if !$cond {
+            return ::core::result::Result::Err(::core::convert::From::from(
+                $crate::AppError::$variant(::std::format!($($fmt)+)),
+            ));
+        }
+    };
+    ($cond:expr, $err:expr $(,)?) => {
+        if !$cond {
+            return ::core::result::Result::Err(::core::convert::From::from($err));
+        }
+    };
+}
+
+#[cfg(feature = "axum")]
+impl axum::response::IntoResponse for AppError {
+    fn into_response(self) -> axum::response::Response {
+        (self.status(), self.to_string()).into_response()
+    }
+}
+
+#[cfg(feature = "rusqlite")]
+impl From<rusqlite::Error> for AppError {
+    fn from(err: rusqlite::Error) -> Self {
+        match &err {
+            rusqlite::Error::QueryReturnedNoRows => Self::NotFound("record not found".into()),
+            rusqlite::Error::SqliteFailure(failure, _)
+                if failure.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                Self::Conflict(err.to_string())
+            }
+            _ => Self::Internal(err.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            sqlx::Error::RowNotFound => Self::NotFound("record not found".into()),
+            sqlx::Error::Database(db)
+                if db.is_unique_violation() || db.is_foreign_key_violation() =>
+            {
+                Self::Conflict(err.to_string())
+            }
+            _ => Self::Internal(err.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl From<reqwest::Error> for AppError {
+    fn from(err: reqwest::Error) -> Self {
+        match err.status() {
+            Some(StatusCode::UNAUTHORIZED) => Self::Unauthorized,
+            Some(StatusCode::NOT_FOUND) => Self::NotFound(err.to_string()),
+            _ => Self::Upstream(err.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "argon2")]
+impl From<argon2::password_hash::Error> for AppError {
+    fn from(err: argon2::password_hash::Error) -> Self {
+        match err {
+            // The hash didn't match, i.e. the credentials are wrong.
+            argon2::password_hash::Error::Password => Self::Unauthorized,
+            err => Self::Internal(err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_age(age: u32) -> Result<u32, AppError> {
+        ensure!(age > 0, BadRequest, "age {age} is out of range");
+        ensure!(age < 150, AppError::Conflict("too old".into()));
+        Ok(age)
+    }
+
+    #[test]
+    fn ensure_returns_errors_with_their_status() {
+        assert_eq!(check_age(30), Ok(30));
+
+        let err = check_age(0).unwrap_err();
+        assert_eq!(err, AppError::BadRequest("age 0 is out of range".into()));
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(check_age(200).unwrap_err().status(), StatusCode::CONFLICT);
+        assert_eq!(AppError::Unauthorized.to_string(), "not authorized");
+    }
+
+    #[cfg(feature = "rusqlite")]
+    #[test]
+    fn rusqlite_errors_are_classified() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE users (name TEXT PRIMARY KEY)")
+            .unwrap();
+        conn.execute("INSERT INTO users VALUES ('alice')", [])
+            .unwrap();
+
+        let duplicate = conn
+            .execute("INSERT INTO users VALUES ('alice')", [])
+            .unwrap_err();
+        assert!(matches!(AppError::from(duplicate), AppError::Conflict(_)));
+
+        let missing = conn
+            .query_row("SELECT name FROM users WHERE name = 'bob'", [], |row| {
+                row.get::<_, String>(0)
+            })
+            .unwrap_err();
+        assert_eq!(AppError::from(missing).status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,146 @@
+//! Access log of every request, one structured event per response.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use http::{Request, Response};
+use tower_layer::Layer;
+use tower_service::Service;
+use tracing::info;
+
+use crate::RequestId;
+
+/// Target of the access log events, which `step_3_8::init_logging` writes to
+/// `access.log`.
+pub const ACCESS_TARGET: &str = "access";
+
+/// Authenticated user of the request, put into the request extensions by the
+/// [`AccessLogLayer`] for the server to [`record`](Self::record) once it knows
+/// the user.
+#[derive(Clone, Debug, Default)]
+pub struct AccessUser(Arc<OnceLock<String>>);
+
+impl AccessUser {
+    /// Records the user, unless it's already recorded.
+    pub fn record(&self, user: impl ToString) {
+        let _ = self.0.set(user.to_string());
+    }
+
+    pub fn get(&self) -> Option<&str> {
+        self.0.get().map(String::as_str)
+    }
+}
+
+/// Logs every response under the [`ACCESS_TARGET`] with the method, path,
+/// [`RequestId`] and [`AccessUser`] of its request, its status and its
+/// latency until the headers are sent.
+///
+/// Must be wrapped by the `RequestIdLayer` for the IDs to be logged.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AccessLogLayer;
+
+impl<S> Layer<S> for AccessLogLayer {
+    type Service = AccessLogService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AccessLogService { inner }
+    }
+}
+
+/// Service created by the [`AccessLogLayer`].
+#[derive(Clone, Debug)]
+pub struct AccessLogService<S> {
+    inner: S,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for AccessLogService<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        let user = AccessUser::default();
+        request.extensions_mut().insert(user.clone());
+        let (method, path) = (request.method().clone(), request.uri().path().to_owned());
+        let request_id = request
+            .extensions()
+            .get::<RequestId>()
+            .map(|id| id.as_str().to_owned());
+        let started = Instant::now();
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let response = response.await?;
+            info!(
+                target: ACCESS_TARGET,
+                %method,
+                path,
+                status = response.status().as_u16(),
+                latency_ms = started.elapsed().as_millis() as u64,
+                request_id,
+                user = user.get(),
+                "request",
+            );
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use http::StatusCode;
+
+    use super::*;
+
+    /// Records the user named by the path, answering `404` to `/nobody`.
+    #[derive(Clone)]
+    struct Login;
+
+    impl Service<Request<()>> for Login {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = std::future::Ready<Result<Response<String>, Infallible>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: Request<()>) -> Self::Future {
+            let name = request.uri().path().trim_start_matches('/');
+            let mut response = Response::new(String::new());
+            if name == "nobody" {
+                *response.status_mut() = StatusCode::NOT_FOUND;
+            } else {
+                let user = request.extensions().get::<AccessUser>().unwrap();
+                user.record(name);
+                user.record("ignored");
+                *response.body_mut() = user.get().unwrap().to_owned();
+            }
+            std::future::ready(Ok(response))
+        }
+    }
+
+    #[tokio::test]
+    async fn exposes_user_slot_to_the_service() {
+        let mut service = AccessLogLayer.layer(Login);
+
+        let response = service.call(Request::get("/alice").body(()).unwrap());
+        assert_eq!(response.await.unwrap().body(), "alice");
+        let response = service.call(Request::get("/nobody").body(()).unwrap());
+        assert_eq!(response.await.unwrap().status(), StatusCode::NOT_FOUND);
+    }
+}
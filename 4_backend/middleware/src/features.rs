@@ -0,0 +1,216 @@
+//! Feature flags from the [`FeaturesConfig`], overridable per request.
+//!
+//! In debug mode a request may switch flags with the [`OVERRIDE_HEADER`]:
+//!
+//! ```text
+//! X-Feature-Override: websocket=on,friend_requests=off;sig=<hex>
+//! ```
+//!
+//! where `sig` is the HMAC-SHA256 of everything before `;sig=` keyed with the
+//! configured `override_secret`, as produced by [`sign_override`]. Overrides
+//! with a missing or wrong signature are ignored.
+
+use std::{fmt, str::FromStr, sync::Arc};
+
+use hmac::{Hmac, Mac};
+use http::HeaderMap;
+use sha2::Sha256;
+use step_3_9::FeaturesConfig;
+use tracing::warn;
+
+/// Header carrying signed per-request overrides of the flags.
+pub const OVERRIDE_HEADER: &str = "x-feature-override";
+
+/// A code path guarded by a flag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Feature {
+    FriendRequests,
+    Websocket,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 2] = [Feature::FriendRequests, Feature::Websocket];
+
+    /// Name of the flag, as used in the config and the override header.
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::FriendRequests => "friend_requests",
+            Feature::Websocket => "websocket",
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Feature {
+    type Err = UnknownFeature;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Feature::ALL
+            .into_iter()
+            .find(|feature| feature.name() == name)
+            .ok_or(UnknownFeature)
+    }
+}
+
+/// The name doesn't match any [`Feature`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownFeature;
+
+/// State of every [`Feature`] for a single request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EnabledFeatures {
+    friend_requests: bool,
+    websocket: bool,
+}
+
+impl EnabledFeatures {
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::FriendRequests => self.friend_requests,
+            Feature::Websocket => self.websocket,
+        }
+    }
+
+    fn set(&mut self, feature: Feature, enabled: bool) {
+        match feature {
+            Feature::FriendRequests => self.friend_requests = enabled,
+            Feature::Websocket => self.websocket = enabled,
+        }
+    }
+
+    /// Iterates over all the features along with their states.
+    pub fn iter(&self) -> impl Iterator<Item = (Feature, bool)> + '_ {
+        Feature::ALL
+            .into_iter()
+            .map(|feature| (feature, self.is_enabled(feature)))
+    }
+}
+
+impl From<&FeaturesConfig> for EnabledFeatures {
+    fn from(config: &FeaturesConfig) -> Self {
+        Self {
+            friend_requests: config.friend_requests,
+            websocket: config.websocket,
+        }
+    }
+}
+
+/// Handle resolving the flags of requests, cheap to clone into server state.
+#[derive(Clone, Debug)]
+pub struct FeatureFlags {
+    defaults: EnabledFeatures,
+    /// Key verifying the [`OVERRIDE_HEADER`], set in debug mode only.
+    override_key: Option<Arc<[u8]>>,
+}
+
+impl FeatureFlags {
+    /// Uses the configured flags, honoring per-request overrides only in
+    /// `debug` mode with an `override_secret` configured.
+    pub fn new(config: &FeaturesConfig, debug: bool) -> Self {
+        let override_key = config
+            .override_secret
+            .as_deref()
+            .filter(|secret| debug && !secret.is_empty())
+            .map(|secret| Arc::from(secret.as_bytes()));
+        Self {
+            defaults: config.into(),
+            override_key,
+        }
+    }
+
+    /// Flags as configured, ignoring any overrides.
+    pub fn defaults(&self) -> EnabledFeatures {
+        self.defaults
+    }
+
+    /// Flags of the request, with its signed overrides applied if allowed.
+    pub fn for_request(&self, headers: &HeaderMap) -> EnabledFeatures {
+        let mut features = self.defaults;
+        let (Some(key), Some(header)) = (&self.override_key, headers.get(OVERRIDE_HEADER)) else {
+            return features;
+        };
+        let Some(overrides) = header.to_str().ok().and_then(|value| verify(key, value)) else {
+            warn!("ignoring feature override with invalid signature");
+            return features;
+        };
+        for item in overrides
+            .split(',')
+            .map(str::trim)
+            .filter(|i| !i.is_empty())
+        {
+            let parsed = item.split_once('=').and_then(|(name, state)| {
+                let enabled = match state.trim() {
+                    "on" | "true" => true,
+                    "off" | "false" => false,
+                    _ => return None,
+                };
+                Some((name.trim().parse().ok()?, enabled))
+            });
+            match parsed {
+                Some((feature, enabled)) => features.set(feature, enabled),
+                None => warn!(override = item, "ignoring unknown feature override"),
+            }
+        }
+        features
+    }
+}
+
+/// Signs the `overrides` (e.g. `websocket=on`) with the `secret`, producing
+/// a value of the [`OVERRIDE_HEADER`].
+pub fn sign_override(secret: &str, overrides: &str) -> String {
+    let mut mac = hmac(secret.as_bytes());
+    mac.update(overrides.as_bytes());
+    let sig = hex::encode(mac.finalize().into_bytes());
+    format!("{overrides};sig={sig}")
+}
+
+/// Returns the overrides of the header value if its signature is valid.
+fn verify<'a>(key: &[u8], value: &'a str) -> Option<&'a str> {
+    let (overrides, sig) = value.rsplit_once(";sig=")?;
+    let sig = hex::decode(sig.trim()).ok()?;
+    let mut mac = hmac(key);
+    mac.update(overrides.as_bytes());
+    mac.verify_slice(&sig).ok()?;
+    Some(overrides)
+}
+
+fn hmac(key: &[u8]) -> Hmac<Sha256> {
+    Hmac::new_from_slice(key).expect("HMAC accepts keys of any length")
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn applies_signed_overrides_in_debug_mode_only() {
+        let config = FeaturesConfig {
+            override_secret: Some("secret".into()),
+            ..FeaturesConfig::default()
+        };
+        let mut headers = HeaderMap::new();
+        let header = sign_override("secret", "websocket=on, friend_requests=off");
+        headers.insert(OVERRIDE_HEADER, HeaderValue::from_str(&header).unwrap());
+
+        let flags = FeatureFlags::new(&config, true);
+        assert!(flags.defaults().is_enabled(Feature::FriendRequests));
+        assert!(!flags.defaults().is_enabled(Feature::Websocket));
+        let overridden = flags.for_request(&headers);
+        assert!(!overridden.is_enabled(Feature::FriendRequests));
+        assert!(overridden.is_enabled(Feature::Websocket));
+
+        let release = FeatureFlags::new(&config, false);
+        assert_eq!(release.for_request(&headers), release.defaults());
+
+        let forged = sign_override("guess", "websocket=on");
+        headers.insert(OVERRIDE_HEADER, HeaderValue::from_str(&forged).unwrap());
+        assert_eq!(flags.for_request(&headers), flags.defaults());
+    }
+}
@@ -0,0 +1,17 @@
+//! Tower middleware shared by the GraphQL (`step_4`) and REST (`step_4_3`)
+//! servers.
+//!
+//! It only depends on `http` and `tower` crates (plus the shared config), so
+//! it fits any axum version.
+
+pub mod access_log;
+pub mod features;
+pub mod limits;
+pub mod rate_limit;
+pub mod request_id;
+
+pub use access_log::{AccessLogLayer, AccessUser};
+pub use features::{EnabledFeatures, Feature, FeatureFlags};
+pub use limits::RequestLimitsLayer;
+pub use rate_limit::{PeerAddr, RateLimitLayer};
+pub use request_id::{RequestId, RequestIdLayer};
@@ -0,0 +1,201 @@
+//! Enforcement of the [`RequestLimits`] from the server configuration.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use http::{Request, Response, StatusCode, header};
+use http_body::Body;
+use http_body_util::Limited;
+use step_3_9::RequestLimits;
+use tower_layer::Layer;
+use tower_service::Service;
+use tracing::warn;
+
+/// Target of the slow request events, so they can be filtered separately.
+pub const SLOW_REQUEST_TARGET: &str = "slow_request";
+
+/// Applies [`RequestLimits`] to the wrapped service:
+/// - bodies larger than `max_body_size` are rejected with `413`, either right
+///   away if announced by `Content-Length`, or once the handler reads them;
+/// - handlers not responding within their route timeout result in `504`;
+/// - requests exceeding `slow_request` are logged with a warning.
+#[derive(Clone, Debug)]
+pub struct RequestLimitsLayer {
+    limits: Arc<RequestLimits>,
+}
+
+impl RequestLimitsLayer {
+    pub fn new(limits: RequestLimits) -> Self {
+        Self {
+            limits: Arc::new(limits),
+        }
+    }
+}
+
+impl<S> Layer<S> for RequestLimitsLayer {
+    type Service = RequestLimitsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestLimitsService {
+            inner,
+            limits: self.limits.clone(),
+        }
+    }
+}
+
+/// Service created by the [`RequestLimitsLayer`].
+#[derive(Clone, Debug)]
+pub struct RequestLimitsService<S> {
+    inner: S,
+    limits: Arc<RequestLimits>,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for RequestLimitsService<S>
+where
+    S: Service<Request<Limited<B>>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    B: Body,
+    ResBody: From<&'static str> + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let limits = self.limits.clone();
+        if content_length(&request).is_some_and(|len| len > limits.max_body_size as u64) {
+            let response = error(StatusCode::PAYLOAD_TOO_LARGE, "request body is too large");
+            return Box::pin(async move { Ok(response) });
+        }
+
+        let timeout = limits.timeout_for(request.uri().path());
+        let (method, path) = (request.method().clone(), request.uri().path().to_owned());
+        let request = request.map(|body| Limited::new(body, limits.max_body_size));
+        let started = Instant::now();
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let response = match tokio::time::timeout(timeout, response).await {
+                Ok(response) => response?,
+                Err(_) => {
+                    warn!(%method, path, ?timeout, "request timed out");
+                    error(StatusCode::GATEWAY_TIMEOUT, "request timed out")
+                }
+            };
+            let elapsed = started.elapsed();
+            if elapsed >= limits.slow_request {
+                warn!(
+                    target: SLOW_REQUEST_TARGET,
+                    %method,
+                    path,
+                    status = response.status().as_u16(),
+                    elapsed_ms = elapsed.as_millis() as u64,
+                    "slow request",
+                );
+            }
+            Ok(response)
+        })
+    }
+}
+
+fn content_length<B>(request: &Request<B>) -> Option<u64> {
+    request
+        .headers()
+        .get(header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+pub(crate) fn error<B: From<&'static str>>(
+    status: StatusCode,
+    message: &'static str,
+) -> Response<B> {
+    let mut response = Response::new(B::from(message));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, convert::Infallible, time::Duration};
+
+    use http_body_util::{BodyExt as _, Full};
+
+    use super::*;
+
+    /// Reads the whole body and sleeps for as many milliseconds as it has
+    /// bytes, answering `413` if it is over the limit.
+    #[derive(Clone)]
+    struct Sleepy;
+
+    impl Service<Request<Limited<Full<&'static [u8]>>>> for Sleepy {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = Pin<Box<dyn Future<Output = Result<Response<String>, Infallible>> + Send>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: Request<Limited<Full<&'static [u8]>>>) -> Self::Future {
+            Box::pin(async move {
+                let Ok(body) = request.into_body().collect().await else {
+                    return Ok(error(StatusCode::PAYLOAD_TOO_LARGE, "too large"));
+                };
+                let len = body.to_bytes().len();
+                tokio::time::sleep(Duration::from_millis(len as u64)).await;
+                Ok(Response::new(len.to_string()))
+            })
+        }
+    }
+
+    fn request(path: &str, body: &'static [u8], announce: bool) -> Request<Full<&'static [u8]>> {
+        let mut request = Request::post(path);
+        if announce {
+            request = request.header(header::CONTENT_LENGTH, body.len());
+        }
+        request.body(Full::new(body)).unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn enforces_body_size_and_route_timeouts() {
+        let limits = RequestLimits {
+            max_body_size: 50,
+            timeout: Duration::from_millis(30),
+            route_timeouts: BTreeMap::from([("/slow".into(), Duration::from_secs(1).into())]),
+            ..RequestLimits::default()
+        };
+        let mut service = RequestLimitsLayer::new(limits).layer(Sleepy);
+
+        let small = service.call(request("/", &[0; 10], true)).await.unwrap();
+        assert_eq!(small.status(), StatusCode::OK);
+        assert_eq!(small.body(), "10");
+
+        for announce in [true, false] {
+            let large = service
+                .call(request("/", &[0; 60], announce))
+                .await
+                .unwrap();
+            assert_eq!(large.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        }
+
+        let timed_out = service.call(request("/", &[0; 40], true)).await.unwrap();
+        assert_eq!(timed_out.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        let slow_route = service
+            .call(request("/slow/1", &[0; 40], true))
+            .await
+            .unwrap();
+        assert_eq!(slow_route.status(), StatusCode::OK);
+    }
+}
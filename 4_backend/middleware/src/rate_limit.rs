@@ -0,0 +1,385 @@
+//! Enforcement of the [`RateLimits`] from the server configuration.
+//!
+//! Every client has a bucket per budget, holding as many requests as the
+//! budget allows and refilled evenly over its period. The buckets live in the
+//! [`RateLimiter`] of the layer, apart from any state of the application.
+//! Only admitted requests create buckets, so rejected ones cost no memory.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::IpAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use common::{Clock, SystemClock};
+use http::{HeaderValue, Request, Response, StatusCode, header};
+use step_3_9::{Rate, RateLimits};
+use tower_layer::Layer;
+use tower_service::Service;
+use tracing::debug;
+
+use crate::limits::error;
+
+/// How often the buckets refilled up to their budgets are forgotten.
+const SWEEP_PERIOD: Duration = Duration::from_secs(60);
+
+/// Address of the client, to be put into the request extensions by the server
+/// before the [`RateLimitLayer`] sees the request.
+///
+/// Requests without it share a single budget per route.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PeerAddr(pub IpAddr);
+
+/// Budget a bucket is spent from, along with the client spending it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Client {
+    Token(String),
+    Ip(Option<IpAddr>),
+    /// Route under the prefix, requested from the address.
+    Route(String, Option<IpAddr>),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    /// Requests left, fractional while being refilled.
+    left: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(rate: Rate, now: Instant) -> Self {
+        Self {
+            left: rate.requests.into(),
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, rate: Rate, now: Instant) {
+        let per_sec = f64::from(rate.requests) / rate.per.as_secs_f64();
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.left = (self.left + elapsed * per_sec).min(rate.requests.into());
+        self.updated = now;
+    }
+
+    /// Time until the bucket holds a whole request again.
+    fn wait(&self, rate: Rate) -> Duration {
+        let missing = (1.0 - self.left).max(0.0);
+        rate.per.mul_f64(missing / f64::from(rate.requests))
+    }
+}
+
+/// Buckets of every client, checked and spent under a lock of their own.
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    clock: Arc<dyn Clock>,
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    map: HashMap<Client, Bucket>,
+    swept: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            clock: Arc::new(SystemClock),
+            buckets: Mutex::new(Buckets {
+                map: HashMap::new(),
+                swept: Instant::now(),
+            }),
+        }
+    }
+
+    /// Uses the `clock` for refilling the buckets instead of the system time.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.buckets
+            .get_mut()
+            .expect("rate limiter lock is poisoned")
+            .swept = clock.now();
+        self.clock = clock;
+        self
+    }
+
+    /// Spends a request to the `path` from every budget it's subject to, or
+    /// from none if any of them is exhausted, returning then how long to
+    /// wait before retrying.
+    pub fn check(
+        &self,
+        path: &str,
+        token: Option<&str>,
+        ip: Option<IpAddr>,
+    ) -> Result<(), Duration> {
+        let mut budgets = vec![(Client::Ip(ip), self.limits.per_ip)];
+        if let Some(token) = token {
+            budgets.push((Client::Token(token.to_owned()), self.limits.per_token));
+        }
+        if let Some((prefix, rate)) = self.limits.route_rate(path) {
+            budgets.push((Client::Route(prefix.to_owned(), ip), rate));
+        }
+
+        let now = self.clock.now();
+        let mut buckets = self.buckets.lock().expect("rate limiter lock is poisoned");
+        if now.saturating_duration_since(buckets.swept) >= SWEEP_PERIOD {
+            buckets.sweep(&self.limits, now);
+        }
+        // Missing buckets are full ones, stored only once spent from.
+        let mut wait = Duration::ZERO;
+        let mut spent = Vec::with_capacity(budgets.len());
+        for (client, rate) in budgets {
+            let mut bucket = match buckets.map.get(&client) {
+                Some(bucket) => *bucket,
+                None => Bucket::full(rate, now),
+            };
+            bucket.refill(rate, now);
+            if bucket.left < 1.0 {
+                wait = wait.max(bucket.wait(rate));
+            }
+            spent.push((client, bucket));
+        }
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        for (client, mut bucket) in spent {
+            bucket.left -= 1.0;
+            buckets.map.insert(client, bucket);
+        }
+        Ok(())
+    }
+}
+
+impl Buckets {
+    /// Forgets the buckets refilled up to their budgets, which are no
+    /// different from the missing ones.
+    fn sweep(&mut self, limits: &RateLimits, now: Instant) {
+        self.map.retain(|client, bucket| {
+            let rate = match client {
+                Client::Token(_) => limits.per_token,
+                Client::Ip(_) => limits.per_ip,
+                Client::Route(prefix, _) => match limits.routes.get(prefix) {
+                    Some(rate) => *rate,
+                    None => return false,
+                },
+            };
+            bucket.refill(rate, now);
+            bucket.left < f64::from(rate.requests)
+        });
+        self.swept = now;
+    }
+}
+
+/// Answers the requests over the [`RateLimits`] with `429` and a
+/// `Retry-After` header, without passing them to the wrapped service.
+///
+/// The clients are told apart by the `Authorization: Bearer` tokens of their
+/// requests and their [`PeerAddr`]es. Tokens are counted unchecked, so every
+/// request is also spent from the budget of its address.
+#[derive(Clone, Debug)]
+pub struct RateLimitLayer {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimitLayer {
+    pub fn new(limits: RateLimits) -> Self {
+        Self::from(RateLimiter::new(limits))
+    }
+}
+
+impl From<RateLimiter> for RateLimitLayer {
+    fn from(limiter: RateLimiter) -> Self {
+        Self {
+            limiter: Arc::new(limiter),
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+/// Service created by the [`RateLimitLayer`].
+#[derive(Clone, Debug)]
+pub struct RateLimitService<S> {
+    inner: S,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for RateLimitService<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    ResBody: From<&'static str> + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let token = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let ip = request.extensions().get::<PeerAddr>().map(|peer| peer.0);
+        let path = request.uri().path();
+        if let Err(wait) = self.limiter.check(path, token, ip) {
+            debug!(path, ?ip, ?wait, "rate limit exceeded");
+            let mut response = error(StatusCode::TOO_MANY_REQUESTS, "too many requests");
+            // Whole seconds, rounded up so the retry isn't too early.
+            let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            return Box::pin(async move { Ok(response) });
+        }
+        Box::pin(self.inner.call(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, convert::Infallible};
+
+    use common::MockClock;
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct Hello;
+
+    impl Service<Request<()>> for Hello {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = std::future::Ready<Result<Response<String>, Infallible>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _: Request<()>) -> Self::Future {
+            std::future::ready(Ok(Response::new("hello".into())))
+        }
+    }
+
+    /// Status and `Retry-After` of the response to the request.
+    async fn call(
+        service: &mut RateLimitService<Hello>,
+        path: &str,
+        token: Option<&str>,
+        ip: [u8; 4],
+    ) -> (StatusCode, Option<HeaderValue>) {
+        let mut request = Request::get(path);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let request = request.extension(PeerAddr(ip.into())).body(()).unwrap();
+        let response = service.call(request).await.unwrap();
+        let retry_after = response.headers().get(header::RETRY_AFTER).cloned();
+        (response.status(), retry_after)
+    }
+
+    fn limits() -> RateLimits {
+        RateLimits {
+            per_token: Rate::new(2, Duration::from_secs(1)),
+            per_ip: Rate::new(4, Duration::from_secs(1)),
+            routes: BTreeMap::from([("/login".into(), Rate::new(1, Duration::from_secs(60)))]),
+        }
+    }
+
+    #[tokio::test]
+    async fn limits_tokens_addresses_and_routes() {
+        let clock = MockClock::new();
+        let limiter = RateLimiter::new(limits()).with_clock(Arc::new(clock.clone()));
+        let service = &mut RateLimitLayer::from(limiter).layer(Hello);
+
+        let alice = Some("alice");
+        assert_eq!(
+            call(service, "/me", alice, [10, 0, 0, 1]).await.0,
+            StatusCode::OK
+        );
+        assert_eq!(
+            call(service, "/me", alice, [10, 0, 0, 2]).await.0,
+            StatusCode::OK
+        );
+        let (limited, retry_after) = call(service, "/me", alice, [10, 0, 0, 3]).await;
+        assert_eq!(limited, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(retry_after, Some(HeaderValue::from(1)));
+
+        // Fresh tokens don't escape the budget of the address.
+        assert_eq!(
+            call(service, "/me", Some("bob"), [10, 0, 0, 1]).await.0,
+            StatusCode::OK
+        );
+        assert_eq!(
+            call(service, "/me", Some("carol"), [10, 0, 0, 1]).await.0,
+            StatusCode::OK
+        );
+        assert_eq!(
+            call(service, "/me", None, [10, 0, 0, 1]).await.0,
+            StatusCode::OK
+        );
+        let (limited, _) = call(service, "/me", Some("dave"), [10, 0, 0, 1]).await;
+        assert_eq!(limited, StatusCode::TOO_MANY_REQUESTS);
+
+        assert_eq!(
+            call(service, "/login", None, [10, 0, 0, 4]).await.0,
+            StatusCode::OK
+        );
+        let (limited, retry_after) = call(service, "/login", None, [10, 0, 0, 4]).await;
+        assert_eq!(limited, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(retry_after, Some(HeaderValue::from(60)));
+        assert_eq!(
+            call(service, "/login", None, [10, 0, 0, 5]).await.0,
+            StatusCode::OK
+        );
+
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(
+            call(service, "/me", alice, [10, 0, 0, 3]).await.0,
+            StatusCode::OK
+        );
+        let (limited, _) = call(service, "/me", alice, [10, 0, 0, 3]).await;
+        assert_eq!(limited, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[test]
+    fn rejected_requests_create_no_buckets() {
+        let clock = MockClock::new();
+        let limiter = RateLimiter::new(limits()).with_clock(Arc::new(clock.clone()));
+        let ip = Some([10, 0, 0, 1].into());
+        let buckets = || limiter.buckets.lock().unwrap().map.len();
+
+        for token in ["a", "b", "c", "d"] {
+            assert!(limiter.check("/me", Some(token), ip).is_ok());
+        }
+        assert_eq!(buckets(), 5);
+        for token in 0..100 {
+            let token = format!("forged-{token}");
+            assert!(limiter.check("/me", Some(&token), ip).is_err());
+        }
+        assert_eq!(buckets(), 5);
+
+        // Once refilled, the buckets are swept away.
+        clock.advance(SWEEP_PERIOD);
+        assert!(limiter.check("/me", None, ip).is_ok());
+        assert_eq!(buckets(), 1);
+    }
+}
@@ -0,0 +1,153 @@
+//! Identification of requests across logs and services.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use http::{HeaderName, HeaderValue, Request, Response};
+use tower_layer::Layer;
+use tower_service::Service;
+use tracing::{Instrument as _, info_span};
+
+/// Header carrying the [`RequestId`], both in requests and responses.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest incoming [`REQUEST_ID_HEADER`] accepted for propagation.
+const MAX_LEN: usize = 128;
+
+/// Identifier of the request, available in its extensions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(pub HeaderValue);
+
+impl RequestId {
+    /// Reuses the incoming [`REQUEST_ID_HEADER`] if it's sane, so a request
+    /// keeps its identifier while passing through several services, or
+    /// generates a new ULID otherwise.
+    fn of<B>(request: &Request<B>) -> Self {
+        let incoming = request.headers().get(&REQUEST_ID_HEADER).filter(|id| {
+            (1..=MAX_LEN).contains(&id.len()) && id.as_bytes().iter().all(u8::is_ascii_graphic)
+        });
+        match incoming {
+            Some(id) => Self(id.clone()),
+            None => Self(HeaderValue::try_from(step_3_7::new_ulid()).expect("ULID is ASCII")),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0
+            .to_str()
+            .expect("only ASCII request IDs are accepted")
+    }
+}
+
+/// Assigns a [`RequestId`] to every request:
+/// - stores it in the request extensions and headers;
+/// - records it in the `request` span wrapping the handling of the request;
+/// - echoes it in the [`REQUEST_ID_HEADER`] of the response.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RequestIdLayer;
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestIdService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestIdService { inner }
+    }
+}
+
+/// Service created by the [`RequestIdLayer`].
+#[derive(Clone, Debug)]
+pub struct RequestIdService<S> {
+    inner: S,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for RequestIdService<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        let id = RequestId::of(&request);
+        let span = info_span!(
+            "request",
+            id = id.as_str(),
+            method = %request.method(),
+            path = request.uri().path(),
+        );
+        request
+            .headers_mut()
+            .insert(REQUEST_ID_HEADER, id.0.clone());
+        request.extensions_mut().insert(id.clone());
+
+        let response = span.in_scope(|| self.inner.call(request));
+        Box::pin(
+            async move {
+                let mut response = response.await?;
+                response.headers_mut().insert(REQUEST_ID_HEADER, id.0);
+                Ok(response)
+            }
+            .instrument(span),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+
+    /// Answers with the [`RequestId`] it sees in the request extensions.
+    #[derive(Clone)]
+    struct Echo;
+
+    impl Service<Request<()>> for Echo {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = std::future::Ready<Result<Response<String>, Infallible>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: Request<()>) -> Self::Future {
+            let id = request.extensions().get::<RequestId>().unwrap();
+            std::future::ready(Ok(Response::new(id.as_str().to_owned())))
+        }
+    }
+
+    #[tokio::test]
+    async fn generates_or_propagates_request_ids() {
+        let mut service = RequestIdLayer.layer(Echo);
+
+        let response = service.call(Request::new(())).await.unwrap();
+        let generated = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        assert_eq!(generated.len(), 26);
+        assert_eq!(response.body(), generated);
+
+        let incoming = Request::builder()
+            .header(REQUEST_ID_HEADER, "upstream-42")
+            .body(())
+            .unwrap();
+        let response = service.call(incoming).await.unwrap();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "upstream-42");
+        assert_eq!(response.body(), "upstream-42");
+
+        let garbage = Request::builder()
+            .header(REQUEST_ID_HEADER, "has spaces")
+            .body(())
+            .unwrap();
+        let response = service.call(garbage).await.unwrap();
+        assert_ne!(response.headers()[REQUEST_ID_HEADER], "has spaces");
+    }
+}
@@ -1,51 +1,30 @@
-use std::{
-    collections::{HashMap, HashSet},
-    sync::Arc,
-};
-
 use async_graphql::{
     Context, EmptySubscription, ErrorExtensions, ID, Object, Schema, SimpleObject,
 };
 use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use std::path::PathBuf;
+
 use axum::{
     Router,
-    extract::State,
+    extract::{DefaultBodyLimit, State},
     http::HeaderMap,
     response::Html,
     routing::{get, post},
 };
-use sha2::{Digest, Sha256};
-use tokio::sync::Mutex;
-use uuid::Uuid;
+use clap::Parser;
+use common::{StateFile, WorkerPool};
+use step_4_domain::{SNAPSHOT_VERSION, ServiceError, Token, UserId, UserService};
+use step_4_middleware::{
+    EnabledFeatures, Feature, FeatureFlags, RequestIdLayer, RequestLimitsLayer,
+};
 
-#[derive(Clone, Default)]
-struct AppState {
-    inner: Arc<Mutex<AppData>>,
-}
+mod stats;
 
-#[derive(Default)]
-struct AppData {
-    users: HashMap<Uuid, UserRecord>,
-    tokens: HashMap<String, Uuid>,
-}
-
-#[derive(Clone)]
-struct UserRecord {
-    id: Uuid,
-    name: String,
-    password_hash: String,
-    friends: HashSet<Uuid>,
-}
-
-impl UserRecord {
-    fn verify_password(&self, password: &str) -> bool {
-        self.password_hash == hash_password(password)
-    }
-}
+use stats::{Stats, StatsCache};
 
 #[derive(Clone)]
 struct AuthedUser {
-    id: Uuid,
+    id: UserId,
 }
 
 #[derive(SimpleObject)]
@@ -56,7 +35,7 @@ struct AuthPayload {
 
 #[derive(Clone)]
 struct User {
-    id: Uuid,
+    id: UserId,
 }
 
 #[Object]
@@ -66,28 +45,14 @@ impl User {
     }
 
     async fn name(&self, ctx: &Context<'_>) -> async_graphql::Result<String> {
-        let state = ctx.data::<AppState>()?;
-        let data = state.inner.lock().await;
-        let user = data
-            .users
-            .get(&self.id)
-            .ok_or_else(|| async_graphql::Error::new("User not found"))?;
-        Ok(user.name.clone())
+        let users = ctx.data::<UserService>()?;
+        Ok(users.user(self.id).await.map_err(service_error)?.name)
     }
 
     async fn friends(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<User>> {
-        let state = ctx.data::<AppState>()?;
-        let data = state.inner.lock().await;
-        let user = data
-            .users
-            .get(&self.id)
-            .ok_or_else(|| async_graphql::Error::new("User not found"))?;
-
-        Ok(user
-            .friends
-            .iter()
-            .filter_map(|id| data.users.get(id).map(|_| User { id: *id }))
-            .collect())
+        let users = ctx.data::<UserService>()?;
+        let friends = users.friends(self.id).await.map_err(service_error)?;
+        Ok(friends.into_iter().map(|u| User { id: u.id }).collect())
     }
 }
 
@@ -107,24 +72,22 @@ impl QueryRoot {
                 .extend_with(|_, e| e.set("code", "UNAUTHORIZED")));
         }
 
-        let state = ctx.data::<AppState>()?;
-        let data = state.inner.lock().await;
-
-        if let Some(id) = id {
-            let uuid = parse_uuid(&id)?;
-            data.users
-                .get(&uuid)
-                .map(|u| User { id: u.id })
-                .ok_or_else(|| async_graphql::Error::new("User not found"))
+        let users = ctx.data::<UserService>()?;
+        let user = if let Some(id) = id {
+            users.user(parse_user_id(&id)?).await
         } else if let Some(name) = name {
-            data.users
-                .values()
-                .find(|u| u.name == name)
-                .map(|u| User { id: u.id })
-                .ok_or_else(|| async_graphql::Error::new("User not found"))
+            users.find_by_name(&name).await
         } else {
-            Err(async_graphql::Error::new("Specify id or name"))
-        }
+            return Err(async_graphql::Error::new("Specify id or name"));
+        };
+        user.map(|u| User { id: u.id }).map_err(service_error)
+    }
+
+    /// Friend graph statistics, recomputed periodically and cached until the
+    /// graph changes.
+    async fn stats(&self, ctx: &Context<'_>) -> async_graphql::Result<Stats> {
+        ensure_authorized(ctx)?;
+        Ok(Stats(ctx.data::<StatsCache>()?.get().await?))
     }
 }
 
@@ -138,23 +101,12 @@ impl MutationRoot {
         name: String,
         password: String,
     ) -> async_graphql::Result<User> {
-        let state = ctx.data::<AppState>()?;
-        let mut data = state.inner.lock().await;
-
-        if data.users.values().any(|u| u.name == name) {
-            return Err(async_graphql::Error::new("User name already taken"));
-        }
-
-        let user = UserRecord {
-            id: Uuid::new_v4(),
-            name,
-            password_hash: hash_password(&password),
-            friends: HashSet::new(),
-        };
-
-        let id = user.id;
-        data.users.insert(id, user);
-        Ok(User { id })
+        let users = ctx.data::<UserService>()?;
+        let user = users
+            .register(&name, &password)
+            .await
+            .map_err(service_error)?;
+        Ok(User { id: user.id })
     }
 
     async fn login(
@@ -163,76 +115,77 @@ impl MutationRoot {
         name: String,
         password: String,
     ) -> async_graphql::Result<AuthPayload> {
-        let state = ctx.data::<AppState>()?;
-        let mut data = state.inner.lock().await;
-        let user_id = data
-            .users
-            .values()
-            .find(|u| u.name == name && u.verify_password(&password))
-            .map(|u| u.id)
-            .ok_or_else(|| async_graphql::Error::new("Invalid credentials"))?;
-
-        let token = Uuid::new_v4().to_string();
-        data.tokens.insert(token.clone(), user_id);
-
+        let users = ctx.data::<UserService>()?;
+        let session = users.login(&name, &password).await.map_err(service_error)?;
         Ok(AuthPayload {
-            token,
-            user: User { id: user_id },
+            token: session.token.into(),
+            user: User {
+                id: session.user_id,
+            },
         })
     }
 
     async fn add_friend(&self, ctx: &Context<'_>, friend_id: ID) -> async_graphql::Result<User> {
+        ensure_enabled(ctx, Feature::FriendRequests)?;
         let user_id = ensure_authorized(ctx)?;
-        let friend_uuid = parse_uuid(&friend_id)?;
-        let state = ctx.data::<AppState>()?;
-        let mut data = state.inner.lock().await;
-
-        let friend_exists = data.users.contains_key(&friend_uuid);
-        if !friend_exists {
-            return Err(async_graphql::Error::new("Friend not found"));
-        }
-
-        let user = data
-            .users
-            .get_mut(&user_id)
-            .ok_or_else(|| async_graphql::Error::new("User not found"))?;
-        user.friends.insert(friend_uuid);
-        Ok(User { id: friend_uuid })
+        let friend_id = parse_user_id(&friend_id)?;
+        let users = ctx.data::<UserService>()?;
+        users
+            .add_friend(user_id, friend_id)
+            .await
+            .map_err(service_error)?;
+        Ok(User { id: friend_id })
     }
 
     async fn remove_friend(&self, ctx: &Context<'_>, friend_id: ID) -> async_graphql::Result<User> {
+        ensure_enabled(ctx, Feature::FriendRequests)?;
         let user_id = ensure_authorized(ctx)?;
-        let friend_uuid = parse_uuid(&friend_id)?;
-        let state = ctx.data::<AppState>()?;
-        let mut data = state.inner.lock().await;
-
-        let user = data
-            .users
-            .get_mut(&user_id)
-            .ok_or_else(|| async_graphql::Error::new("User not found"))?;
-        if !user.friends.remove(&friend_uuid) {
+        let friend_id = parse_user_id(&friend_id)?;
+        let users = ctx.data::<UserService>()?;
+        if !users
+            .remove_friend(user_id, friend_id)
+            .await
+            .map_err(service_error)?
+        {
             return Err(async_graphql::Error::new("Friend not in list"));
         }
 
-        Ok(User { id: friend_uuid })
+        Ok(User { id: friend_id })
     }
 }
 
-fn parse_uuid(id: &ID) -> async_graphql::Result<Uuid> {
-    Uuid::parse_str(id.as_str()).map_err(|_| async_graphql::Error::new("Invalid identifier format"))
+fn parse_user_id(id: &ID) -> async_graphql::Result<UserId> {
+    id.parse()
+        .map_err(|_| async_graphql::Error::new("Invalid identifier format"))
 }
 
-fn ensure_authorized(ctx: &Context<'_>) -> async_graphql::Result<Uuid> {
+fn ensure_authorized(ctx: &Context<'_>) -> async_graphql::Result<UserId> {
     ctx.data::<Option<AuthedUser>>()?
         .as_ref()
         .map(|u| u.id)
         .ok_or_else(|| async_graphql::Error::new("Authorization required"))
 }
 
-fn hash_password(password: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(password.as_bytes());
-    format!("{:x}", hasher.finalize())
+/// Rejects the request if the `feature` is disabled for it, like the REST
+/// routes guarded by the same flag do.
+fn ensure_enabled(ctx: &Context<'_>, feature: Feature) -> async_graphql::Result<()> {
+    if ctx.data::<EnabledFeatures>()?.is_enabled(feature) {
+        return Ok(());
+    }
+    Err(
+        async_graphql::Error::new(format!("feature `{feature}` is disabled"))
+            .extend_with(|_, e| e.set("code", "NOT_FOUND")),
+    )
+}
+
+/// Converts a domain error into a GraphQL one, exposing its kind as `code`.
+fn service_error(err: ServiceError) -> async_graphql::Error {
+    let code = match err {
+        ServiceError::UserNotFound => "NOT_FOUND",
+        ServiceError::InvalidCredentials => "UNAUTHORIZED",
+        _ => "BAD_REQUEST",
+    };
+    async_graphql::Error::new(err.to_string()).extend_with(|_, e| e.set("code", code))
 }
 
 async fn graphiql() -> Html<String> {
@@ -248,25 +201,26 @@ async fn graphql_handler(
     headers: HeaderMap,
     req: GraphQLRequest,
 ) -> GraphQLResponse {
-    let mut request = req.into_inner().data(server_state.state.clone());
-    let auth = extract_auth(headers, &server_state.state).await;
+    let mut request = req
+        .into_inner()
+        .data(server_state.users.clone())
+        .data(server_state.stats.clone())
+        .data(server_state.features.for_request(&headers));
+    let auth = extract_auth(headers, &server_state.users).await;
     request = request.data(auth);
     server_state.schema.execute(request).await.into()
 }
 
-async fn extract_auth(headers: HeaderMap, state: &AppState) -> Option<AuthedUser> {
-    if let Some(token_header) = headers.get(axum::http::header::AUTHORIZATION) {
-        if let Ok(raw_value) = token_header.to_str() {
-            if let Some(token) = raw_value.strip_prefix("Bearer ") {
-                let data = state.inner.lock().await;
-                if let Some(id) = data.tokens.get(token).copied() {
-                    return Some(AuthedUser { id });
-                }
-            }
-        }
-    }
-
-    None
+async fn extract_auth(headers: HeaderMap, users: &UserService) -> Option<AuthedUser> {
+    let token = headers
+        .get(axum::http::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")?
+        .parse::<Token>()
+        .ok()?;
+    let id = users.authenticate(&token).await?;
+    Some(AuthedUser { id })
 }
 
 type AppSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
@@ -274,37 +228,106 @@ type AppSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
 #[derive(Clone)]
 struct ServerState {
     schema: AppSchema,
-    state: AppState,
+    users: UserService,
+    stats: StatsCache,
+    features: FeatureFlags,
+}
+
+#[derive(Parser)]
+#[command(about = "GraphQL server of the user domain")]
+struct Args {
+    /// Configuration file in the format of `step_3_9`
+    #[arg(short, long, env = "CONF_FILE", default_value = "config.toml")]
+    conf: PathBuf,
+    /// File to restore users and sessions from on startup and save them to
+    /// on shutdown
+    #[arg(long, env = "STATE_FILE")]
+    state_file: Option<PathBuf>,
 }
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() {
+    let args = Args::parse();
+    let cli = step_3_9::Cli {
+        conf: args.conf,
+        conf_format: None,
+        profile: None,
+        debug: false,
+    };
+    let config = step_3_9::load_config(&cli).expect("Unable to load configuration");
+    step_3_8::init_logging(&config.log.app.level).expect("Unable to initialize logging");
+
     let schema = Schema::build(QueryRoot, MutationRoot, EmptySubscription).finish();
-    let state = AppState::default();
-    let server_state = ServerState { schema, state };
+    let users = UserService::new();
+    let state_file = args
+        .state_file
+        .map(|path| StateFile::new(path, SNAPSHOT_VERSION));
+    if let Some(file) = &state_file {
+        let snapshot = file.load().expect("Unable to load the state file");
+        if let Some(snapshot) = snapshot {
+            users.restore(snapshot).await;
+        }
+    }
+    let workers = &config.background.workers;
+    let pool = WorkerPool::new("stats", workers).expect("Unable to start the worker pool");
+    let stats = StatsCache::new(users.clone(), config.background.stats.top, pool.clone());
+    stats.spawn(config.background.stats.period);
+    let server_state = ServerState {
+        schema,
+        users: users.clone(),
+        stats,
+        features: FeatureFlags::new(&config.features, config.mode.debug),
+    };
 
     let app = Router::new()
         .route("/", get(graphiql))
         .route("/graphql", post(graphql_handler))
-        .with_state(server_state);
+        .with_state(server_state)
+        // Limits are enforced by `RequestLimitsLayer` instead.
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestLimitsLayer::new(config.server.limits))
+        .layer(RequestIdLayer);
 
     println!("GraphQL server running at http://127.0.0.1:8000");
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8000")
         .await
         .expect("Unable to bind to port");
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app)
+        .with_graceful_shutdown(common::shutdown_signal())
+        .await
+        .unwrap();
+    if !pool.drain(workers.drain_timeout).await {
+        eprintln!(
+            "Background jobs didn't finish in {:?}",
+            workers.drain_timeout
+        );
+    }
+
+    if let Some(file) = state_file {
+        file.save(&users.snapshot().await)
+            .expect("Unable to save the state file");
+        println!("State saved to {}", file.path().display());
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use super::*;
     use async_graphql::Request;
+    use common::WorkerPoolConfig;
     use serde_json::Value;
+    use step_3_9::FeaturesConfig;
+
+    fn default_features() -> EnabledFeatures {
+        FeatureFlags::new(&FeaturesConfig::default(), false).defaults()
+    }
 
     #[tokio::test]
     async fn registers_logs_in_and_manages_friends() {
         let schema = Schema::build(QueryRoot, MutationRoot, EmptySubscription).finish();
-        let state = AppState::default();
+        let state = UserService::new();
 
         schema
             .execute(
@@ -319,22 +342,8 @@ mod tests {
             )
             .await;
 
-        let (alice_id, bob_id) = {
-            let data = state.inner.lock().await;
-            let alice_id = data
-                .users
-                .values()
-                .find(|u| u.name == "Alice")
-                .map(|u| u.id)
-                .unwrap();
-            let bob_id = data
-                .users
-                .values()
-                .find(|u| u.name == "Bob")
-                .map(|u| u.id)
-                .unwrap();
-            (alice_id, bob_id)
-        };
+        let alice_id = state.find_by_name("Alice").await.unwrap().id;
+        let bob_id = state.find_by_name("Bob").await.unwrap().id;
 
         let login_response = schema
             .execute(
@@ -349,16 +358,17 @@ mod tests {
             .as_str()
             .unwrap()
             .to_string();
-        {
-            let data = state.inner.lock().await;
-            assert!(data.tokens.contains_key(&token));
-        }
+        assert_eq!(
+            state.authenticate(&token.parse().unwrap()).await,
+            Some(alice_id)
+        );
 
         let mut add_friend_request = Request::new(format!(
             "mutation {{ addFriend(friendId: \"{bob_id}\") {{ id }} }}",
         ));
         add_friend_request = add_friend_request.data(state.clone());
         add_friend_request = add_friend_request.data(Some(AuthedUser { id: alice_id }));
+        add_friend_request = add_friend_request.data(default_features());
         let add_friend_response = schema.execute(add_friend_request).await;
         assert!(add_friend_response.errors.is_empty());
 
@@ -383,6 +393,7 @@ mod tests {
         remove_friend_request = remove_friend_request.data(state.clone());
         remove_friend_request =
             remove_friend_request.data::<Option<AuthedUser>>(Some(AuthedUser { id: alice_id }));
+        remove_friend_request = remove_friend_request.data(default_features());
         let remove_friend_response = schema.execute(remove_friend_request).await;
         assert!(remove_friend_response.errors.is_empty());
 
@@ -400,4 +411,83 @@ mod tests {
             .unwrap();
         assert!(friends_after.is_empty());
     }
+
+    #[tokio::test]
+    async fn friend_mutations_follow_feature_flags() {
+        let schema = Schema::build(QueryRoot, MutationRoot, EmptySubscription).finish();
+        let users = UserService::new();
+        let alice = users.register("alice", "pwd").await.unwrap();
+        let bob = users.register("bob", "pwd").await.unwrap();
+        let config = FeaturesConfig {
+            friend_requests: false,
+            ..FeaturesConfig::default()
+        };
+        let disabled = FeatureFlags::new(&config, false).defaults();
+
+        for mutation in ["addFriend", "removeFriend"] {
+            let response = schema
+                .execute(
+                    Request::new(format!(
+                        "mutation {{ {mutation}(friendId: \"{}\") {{ id }} }}",
+                        bob.id,
+                    ))
+                    .data(users.clone())
+                    .data(Some(AuthedUser { id: alice.id }))
+                    .data(disabled),
+                )
+                .await;
+            assert_eq!(
+                response.errors[0].message,
+                "feature `friend_requests` is disabled"
+            );
+        }
+        assert!(users.friends(alice.id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn stats_are_cached_until_graph_changes() {
+        let schema = Schema::build(QueryRoot, MutationRoot, EmptySubscription).finish();
+        let users = UserService::new();
+        let pool = WorkerPool::new("stats", &WorkerPoolConfig::default()).unwrap();
+        let cache = StatsCache::new(users.clone(), 1, pool);
+        let alice = users.register("alice", "pwd").await.unwrap();
+        let bob = users.register("bob", "pwd").await.unwrap();
+        users.register("carol", "pwd").await.unwrap();
+        users.add_friend(alice.id, bob.id).await.unwrap();
+
+        let query = || {
+            Request::new(
+                "{ stats { userCount friendshipCount componentCount \
+                 mostConnected { name friendCount } } }",
+            )
+            .data(cache.clone())
+            .data(Some(AuthedUser { id: alice.id }))
+        };
+        let response = schema.execute(query()).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let stats = &response.data.into_json().unwrap()["stats"];
+        assert_eq!(stats["userCount"], 3);
+        assert_eq!(stats["friendshipCount"], 1);
+        assert_eq!(stats["componentCount"], 2);
+        assert_eq!(
+            stats["mostConnected"],
+            serde_json::json!([{ "name": "alice", "friendCount": 1 }])
+        );
+
+        let first = cache.get().await.unwrap();
+        let cached = cache.get().await.unwrap();
+        assert!(Arc::ptr_eq(&first, &cached), "served from cache");
+        users.add_friend(bob.id, alice.id).await.unwrap();
+        let second = cache.get().await.unwrap();
+        assert_eq!(second.friendships, 2);
+
+        let anonymous = schema
+            .execute(
+                Request::new("{ stats { userCount } }")
+                    .data(cache.clone())
+                    .data(None::<AuthedUser>),
+            )
+            .await;
+        assert!(!anonymous.errors.is_empty());
+    }
 }
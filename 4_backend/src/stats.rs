@@ -0,0 +1,130 @@
+//! Background job keeping the friend graph statistics up to date.
+
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use async_graphql::{ID, Object, SimpleObject};
+use common::{WorkerPool, worker_pool::JobError};
+use step_4_domain::{GraphStats, UserService, stats::UserDegree};
+use tokio::{task::JoinHandle, time::MissedTickBehavior};
+
+/// Last computed [`GraphStats`], valid until the friend graph changes.
+///
+/// The stats are computed on the worker pool, keeping the graph traversals
+/// off the async runtime.
+#[derive(Clone)]
+pub struct StatsCache {
+    users: UserService,
+    top: usize,
+    pool: WorkerPool,
+    cached: Arc<RwLock<Option<Arc<GraphStats>>>>,
+}
+
+impl StatsCache {
+    pub fn new(users: UserService, top: usize, pool: WorkerPool) -> Self {
+        Self {
+            users,
+            top,
+            pool,
+            cached: Arc::default(),
+        }
+    }
+
+    /// Returns the cached stats, recomputing them if the graph has changed
+    /// since.
+    pub async fn get(&self) -> Result<Arc<GraphStats>, JobError> {
+        let revision = self.users.revision().await;
+        let cached = self
+            .cached
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        if let Some(stats) = cached
+            && stats.revision == revision
+        {
+            return Ok(stats);
+        }
+        let (revision, users) = self.users.users_at_revision().await;
+        let top = self.top;
+        let stats = self
+            .pool
+            .run(move || Arc::new(GraphStats::compute(revision, &users, top)))
+            .await?;
+        *self.cached.write().unwrap_or_else(|e| e.into_inner()) = Some(stats.clone());
+        Ok(stats)
+    }
+
+    /// Spawns the job refreshing the cache every `period`, so queries rarely
+    /// have to wait for the computation.
+    pub fn spawn(&self, period: Duration) -> JoinHandle<()> {
+        let cache = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(period);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                // The pool only closes on shutdown.
+                if let Err(JobError::Closed) = cache.get().await {
+                    break;
+                }
+            }
+        })
+    }
+}
+
+/// Number of friends of a user.
+#[derive(SimpleObject)]
+pub struct Degree {
+    id: ID,
+    name: String,
+    friend_count: usize,
+}
+
+impl From<&UserDegree> for Degree {
+    fn from(degree: &UserDegree) -> Self {
+        Self {
+            id: ID(degree.id.to_string()),
+            name: degree.name.clone(),
+            friend_count: degree.friends,
+        }
+    }
+}
+
+/// Friend graph statistics.
+pub struct Stats(pub Arc<GraphStats>);
+
+#[Object]
+impl Stats {
+    async fn user_count(&self) -> usize {
+        self.0.users
+    }
+
+    /// Number of friendship links, each direction counted separately.
+    async fn friendship_count(&self) -> usize {
+        self.0.friendships
+    }
+
+    /// Friend counts of all users, the most connected first.
+    async fn degrees(&self) -> Vec<Degree> {
+        self.0.degrees.iter().map(Degree::from).collect()
+    }
+
+    /// Ids of users linked by friendships, the largest groups first.
+    async fn components(&self) -> Vec<Vec<ID>> {
+        self.0
+            .components
+            .iter()
+            .map(|group| group.iter().map(|id| ID(id.to_string())).collect())
+            .collect()
+    }
+
+    async fn component_count(&self) -> usize {
+        self.0.components.len()
+    }
+
+    async fn most_connected(&self) -> Vec<Degree> {
+        self.0.most_connected.iter().map(Degree::from).collect()
+    }
+}
@@ -0,0 +1,530 @@
+//! HTTP service selling from the vending machine of step 2.
+//!
+//! Customers open a session, insert coins into it and buy a product, getting
+//! their change back, or cancel the session to get a refund. The state lives
+//! in [`SharedVendingMachine`], so the handlers only translate between JSON
+//! and the domain model, recording the sales in [`SalesMetrics`]. The
+//! OpenAPI docs are served at `/docs`.
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use axum::{
+    Json, Router,
+    extract::{FromRef, Path, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+};
+use clap::Parser;
+use common::metrics::{Aggregate, MinuteRing};
+use common::{Clock, SystemClock};
+use serde::{Deserialize, Serialize};
+use step_2::shared::{SessionError, SessionId};
+use step_2::{Coin, Currency, Money, Product, PurchaseError, SharedVendingMachine, VendingMachine};
+use step_4_errors::AppError;
+use utoipa::{OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
+
+#[derive(Debug, Parser)]
+#[command(about = "Vending machine HTTP service", version)]
+struct Args {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    addr: SocketAddr,
+}
+
+/// Amount of money.
+#[derive(Debug, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+struct Amount {
+    /// Amount in minor units (cents)
+    amount: u64,
+    /// ISO 4217 currency code
+    currency: String,
+}
+
+impl From<Money> for Amount {
+    fn from(money: Money) -> Self {
+        Self {
+            amount: money.minor_units(),
+            currency: money.currency().code().to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct Slot {
+    product: String,
+    price: Amount,
+    /// Items left in the machine
+    quantity: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct Session {
+    /// Secret identifier of the session, needed to use it
+    id: String,
+    /// Amount inserted so far
+    credit: Amount,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct InsertCoin {
+    /// Nominal of the coin: 1, 2, 5, 10, 20 or 50
+    coin: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct PurchasePayload {
+    product: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct Receipt {
+    product: String,
+    price: Amount,
+    /// Nominals of the coins given as change
+    change: Vec<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct Refund {
+    /// Nominals of the returned coins
+    coins: Vec<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct ProductSales {
+    product: String,
+    sold: u64,
+    revenue: Amount,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct MinuteSales {
+    /// How many minutes ago, `0` being the current minute
+    minutes_ago: u64,
+    sold: u64,
+    revenue: Amount,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct SalesSummary {
+    /// Sales by product, ordered by name
+    products: Vec<ProductSales>,
+    revenue: Amount,
+    /// Minutes of the last hour with any sales, the latest first
+    last_hour: Vec<MinuteSales>,
+    /// Sessions opened, but neither paid, cancelled nor expired yet
+    open_sessions: usize,
+}
+
+impl SalesSummary {
+    fn new(machine: &SharedVendingMachine, metrics: &SalesMetrics) -> Self {
+        let currency = machine.currency();
+        let mut revenue = Aggregate::default();
+        let products = metrics
+            .products()
+            .into_iter()
+            .map(|(product, sales)| {
+                revenue.merge(&sales);
+                ProductSales {
+                    product,
+                    sold: sales.count,
+                    revenue: Money::new(sales.sum, currency).into(),
+                }
+            })
+            .collect();
+        Self {
+            products,
+            revenue: Money::new(revenue.sum, currency).into(),
+            last_hour: metrics
+                .revenue
+                .recent()
+                .into_iter()
+                .map(|(minutes_ago, sales)| MinuteSales {
+                    minutes_ago,
+                    sold: sales.count,
+                    revenue: Money::new(sales.sum, currency).into(),
+                })
+                .collect(),
+            open_sessions: machine.open_sessions(),
+        }
+    }
+}
+
+/// Sales of the machine, the prices being in minor units.
+#[derive(Debug)]
+struct SalesMetrics {
+    /// Sales since the start by product name
+    products: Mutex<BTreeMap<String, Aggregate>>,
+    /// Sales of the last hour
+    revenue: MinuteRing,
+}
+
+impl SalesMetrics {
+    fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            products: Mutex::default(),
+            revenue: MinuteRing::new(clock, 60),
+        }
+    }
+
+    fn record(&self, product: &Product) {
+        let price = product.price().minor_units();
+        self.products
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(product.name().to_owned())
+            .or_default()
+            .record(price);
+        self.revenue.record(price);
+    }
+
+    fn products(&self) -> BTreeMap<String, Aggregate> {
+        self.products
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+#[derive(Clone, FromRef)]
+struct AppState {
+    machine: SharedVendingMachine,
+    metrics: Arc<SalesMetrics>,
+}
+
+/// Translates the domain errors into HTTP statuses.
+fn app_error(err: SessionError) -> AppError {
+    let message = err.to_string();
+    match err {
+        SessionError::UnknownSession | SessionError::Purchase(PurchaseError::UnknownProduct) => {
+            AppError::NotFound(message)
+        }
+        SessionError::Purchase(
+            PurchaseError::OutOfStock | PurchaseError::CannotProvideChange { .. },
+        ) => AppError::Conflict(message),
+        SessionError::TooManySessions => AppError::Unavailable(message),
+        SessionError::Purchase(_) => AppError::BadRequest(message),
+    }
+}
+
+fn session_id(id: &str) -> Result<SessionId, AppError> {
+    id.parse().map_err(app_error)
+}
+
+fn nominals(coins: Vec<Coin>) -> Vec<u32> {
+    coins.into_iter().map(Coin::value).collect()
+}
+
+#[utoipa::path(
+    get,
+    path = "/slots",
+    responses(
+        (status = 200, body = [Slot], description = "Products in stock ordered by name"),
+    )
+)]
+async fn list_slots(State(machine): State<SharedVendingMachine>) -> Json<Vec<Slot>> {
+    let slots = machine
+        .products()
+        .into_iter()
+        .map(|(product, quantity)| Slot {
+            product: product.name().to_owned(),
+            price: product.price().into(),
+            quantity,
+        })
+        .collect();
+    Json(slots)
+}
+
+#[utoipa::path(
+    post,
+    path = "/sessions",
+    responses(
+        (status = 201, body = Session, description = "Session opened"),
+        (status = 503, description = "Too many sessions are open"),
+    )
+)]
+async fn open_session(
+    State(machine): State<SharedVendingMachine>,
+) -> Result<(StatusCode, Json<Session>), AppError> {
+    let session = Session {
+        id: machine.open_session().map_err(app_error)?.to_string(),
+        credit: Money::zero(machine.currency()).into(),
+    };
+    Ok((StatusCode::CREATED, Json(session)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/coins",
+    request_body = InsertCoin,
+    responses(
+        (status = 200, body = Session, description = "Coin accepted"),
+        (status = 400, description = "No coin has such a nominal"),
+        (status = 404, description = "Session not found"),
+    )
+)]
+async fn insert_coin(
+    State(machine): State<SharedVendingMachine>,
+    Path(id): Path<String>,
+    Json(payload): Json<InsertCoin>,
+) -> Result<Json<Session>, AppError> {
+    let coin = Coin::from_value(payload.coin)
+        .ok_or_else(|| AppError::BadRequest(format!("no coin of {}", payload.coin)))?;
+    let credit = machine
+        .insert_coin(session_id(&id)?, coin)
+        .map_err(app_error)?;
+    Ok(Json(Session {
+        id,
+        credit: credit.into(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/sessions/{id}/purchase",
+    request_body = PurchasePayload,
+    responses(
+        (status = 200, body = Receipt, description = "Product sold, session closed"),
+        (status = 400, description = "Inserted coins don't cover the price"),
+        (status = 404, description = "Session or product not found"),
+        (status = 409, description = "Product is out of stock or change can't be given"),
+    )
+)]
+async fn purchase(
+    State(machine): State<SharedVendingMachine>,
+    State(metrics): State<Arc<SalesMetrics>>,
+    Path(id): Path<String>,
+    Json(payload): Json<PurchasePayload>,
+) -> Result<Json<Receipt>, AppError> {
+    let (product, change) = machine
+        .purchase(session_id(&id)?, &payload.product)
+        .map_err(app_error)?;
+    metrics.record(&product);
+    Ok(Json(Receipt {
+        product: product.name().to_owned(),
+        price: product.price().into(),
+        change: nominals(change),
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/sessions/{id}",
+    responses(
+        (status = 200, body = Refund, description = "Session closed, coins returned"),
+        (status = 404, description = "Session not found"),
+    )
+)]
+async fn cancel_session(
+    State(machine): State<SharedVendingMachine>,
+    Path(id): Path<String>,
+) -> Result<Json<Refund>, AppError> {
+    let coins = machine.cancel(session_id(&id)?).map_err(app_error)?;
+    Ok(Json(Refund {
+        coins: nominals(coins),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/reports/sales",
+    responses(
+        (status = 200, body = SalesSummary, description = "Sales since the start"),
+    )
+)]
+async fn sales_report(
+    State(machine): State<SharedVendingMachine>,
+    State(metrics): State<Arc<SalesMetrics>>,
+) -> Json<SalesSummary> {
+    Json(SalesSummary::new(&machine, &metrics))
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        list_slots,
+        open_session,
+        insert_coin,
+        purchase,
+        cancel_session,
+        sales_report
+    ),
+    components(schemas(
+        Amount,
+        Slot,
+        Session,
+        InsertCoin,
+        PurchasePayload,
+        Receipt,
+        Refund,
+        ProductSales,
+        MinuteSales,
+        SalesSummary
+    )),
+    tags((name = "vending", description = "Vending machine API"))
+)]
+struct ApiDoc;
+
+fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/slots", get(list_slots))
+        .route("/sessions", post(open_session))
+        .route("/sessions/:id", delete(cancel_session))
+        .route("/sessions/:id/coins", post(insert_coin))
+        .route("/sessions/:id/purchase", post(purchase))
+        .route("/reports/sales", get(sales_report))
+        .merge(SwaggerUi::new("/docs").url("/api-doc/openapi.json", ApiDoc::openapi()))
+        .with_state(state)
+}
+
+/// Machine stocked with a few drinks and some change.
+fn demo_machine() -> SharedVendingMachine {
+    let currency = Currency::EUR;
+    let machine = SharedVendingMachine::new(VendingMachine::new(30, currency));
+    for (name, price, quantity) in [("Cola", 45, 10), ("Juice", 70, 5), ("Water", 25, 10)] {
+        let product =
+            Product::new(name, Money::new(price, currency)).expect("price must be non-zero");
+        machine
+            .restock(product, quantity)
+            .expect("demo stock fits the machine");
+    }
+    machine.add_change(Coin::ALL.into_iter().flat_map(|coin| [coin; 5]));
+    machine
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let router = router(AppState {
+        machine: demo_machine(),
+        metrics: Arc::new(SalesMetrics::new(Arc::new(SystemClock))),
+    });
+
+    println!("Running server on {}", args.addr);
+    let listener = tokio::net::TcpListener::bind(args.addr).await?;
+    axum::serve(listener, router)
+        .with_graceful_shutdown(common::shutdown_signal())
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use common::MockClock;
+
+    use super::*;
+
+    fn metrics() -> Arc<SalesMetrics> {
+        Arc::new(SalesMetrics::new(Arc::new(MockClock::new())))
+    }
+
+    #[tokio::test]
+    async fn sells_products_through_sessions() {
+        let (machine, metrics) = (demo_machine(), metrics());
+        let state = || State(machine.clone());
+        let stats = || State(metrics.clone());
+
+        let (status, Json(session)) = open_session(state()).await.expect("open");
+        assert_eq!(status, StatusCode::CREATED);
+        for (coin, credit) in [(50, 50), (20, 70)] {
+            let Json(session) =
+                insert_coin(state(), Path(session.id.clone()), Json(InsertCoin { coin }))
+                    .await
+                    .expect("insert coin");
+            assert_eq!(session.credit.amount, credit);
+        }
+        let invalid = insert_coin(
+            state(),
+            Path(session.id.clone()),
+            Json(InsertCoin { coin: 3 }),
+        )
+        .await;
+        assert_eq!(
+            invalid.unwrap_err(),
+            AppError::BadRequest("no coin of 3".into())
+        );
+
+        let unknown = purchase(
+            state(),
+            stats(),
+            Path(session.id.clone()),
+            Json(PurchasePayload {
+                product: "Tea".into(),
+            }),
+        )
+        .await;
+        assert_eq!(
+            unknown.unwrap_err(),
+            AppError::NotFound("unknown product".into())
+        );
+
+        let Json(receipt) = purchase(
+            state(),
+            stats(),
+            Path(session.id.clone()),
+            Json(PurchasePayload {
+                product: "Cola".into(),
+            }),
+        )
+        .await
+        .expect("purchase");
+        assert_eq!(receipt.change, [20, 5]);
+
+        let closed = cancel_session(state(), Path(session.id.clone())).await;
+        assert_eq!(closed.unwrap_err().status(), StatusCode::NOT_FOUND);
+
+        let Json(report) = sales_report(state(), stats()).await;
+        assert_eq!(report.revenue.amount, 45);
+        assert_eq!(report.products[0].product, "Cola");
+        assert_eq!(report.open_sessions, 0);
+        assert_eq!((report.last_hour.len(), report.last_hour[0].sold), (1, 1));
+        assert_eq!(report.last_hour[0].revenue.amount, 45);
+        let Json(slots) = list_slots(state()).await;
+        assert_eq!(slots[0].quantity, 9);
+    }
+
+    #[tokio::test]
+    async fn refunds_cancelled_sessions() {
+        let (machine, metrics) = (demo_machine(), metrics());
+        let (_, Json(session)) = open_session(State(machine.clone())).await.expect("open");
+        let Json(session) = insert_coin(
+            State(machine.clone()),
+            Path(session.id.clone()),
+            Json(InsertCoin { coin: 10 }),
+        )
+        .await
+        .expect("insert coin");
+        assert_eq!(session.credit.amount, 10);
+
+        let Json(report) = sales_report(State(machine.clone()), State(metrics.clone())).await;
+        assert_eq!(report.open_sessions, 1);
+
+        let Json(refund) = cancel_session(State(machine.clone()), Path(session.id.clone()))
+            .await
+            .expect("cancel");
+        assert_eq!(refund.coins, [10]);
+        let Json(report) = sales_report(State(machine.clone()), State(metrics.clone())).await;
+        assert_eq!((report.revenue.amount, report.open_sessions), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn rejects_foreign_sessions() {
+        let machine = demo_machine();
+        let (_, Json(session)) = open_session(State(machine.clone())).await.expect("open");
+        assert_eq!(session.id.len(), 32);
+
+        for foreign in ["1", "00000000000000000000000000000001", "../slots"] {
+            let refund = cancel_session(State(machine.clone()), Path(foreign.into())).await;
+            assert_eq!(
+                refund.unwrap_err(),
+                AppError::NotFound("unknown session".into())
+            );
+        }
+        assert_eq!(machine.open_sessions(), 1);
+    }
+}
@@ -0,0 +1,40 @@
+//! Traversals of a synthetic friend graph with 100k nodes.
+//!
+//! Run with `cargo bench -p common --bench graph`.
+
+use common::Graph;
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use rand::{Rng as _, SeedableRng as _, rngs::StdRng};
+
+const NODES: u32 = 100_000;
+const EDGES_PER_NODE: u32 = 5;
+
+/// Random graph averaging `2 * EDGES_PER_NODE` neighbors per node, the same
+/// on every run.
+fn synthetic_graph() -> Graph<u32> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut graph = Graph::new();
+    for node in 0..NODES {
+        graph.add_node(node);
+        for _ in 0..EDGES_PER_NODE {
+            graph.add_edge(node, rng.gen_range(0..NODES));
+        }
+    }
+    graph
+}
+
+fn graph(c: &mut Criterion) {
+    let mut group = c.benchmark_group("graph_100k");
+    group.sample_size(10);
+    group.bench_function("build", |b| b.iter(synthetic_graph));
+
+    let graph = synthetic_graph();
+    group.bench_function("shortest_path", |b| {
+        b.iter(|| graph.shortest_path(black_box(&0), black_box(&(NODES - 1))))
+    });
+    group.bench_function("components", |b| b.iter(|| graph.components()));
+    group.finish();
+}
+
+criterion_group!(benches, graph);
+criterion_main!(benches);
@@ -0,0 +1,162 @@
+//! Source of time, replaceable in tests.
+//!
+//! Code depending on time takes an `Arc<dyn Clock>` instead of calling
+//! [`Instant::now`] or [`tokio::time::sleep`] directly, so tests can drive a
+//! [`MockClock`] and check expiry deterministically, without real waiting.
+
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+/// Future returned by [`Clock::sleep`].
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Provides the current time and waits for it to pass.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Current point in time.
+    fn now(&self) -> Instant;
+
+    /// Completes once `duration` has passed according to this clock.
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        (**self).sleep(duration)
+    }
+}
+
+/// The real time, with sleeping backed by the `tokio` timer.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// Clock standing still until explicitly [`advance`](MockClock::advance)d.
+///
+/// Clones share the same time.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Debug)]
+struct MockState {
+    now: Instant,
+    /// Wakers of the pending sleeps along with their deadlines.
+    sleepers: Vec<(Instant, Waker)>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClock {
+    /// Creates a clock stopped at the current real time.
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MockState {
+                now: Instant::now(),
+                sleepers: Vec::new(),
+            })),
+        }
+    }
+
+    /// Moves the time forward, completing the sleeps that became due.
+    pub fn advance(&self, by: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.now += by;
+        let now = state.now;
+        let (due, pending) = state
+            .sleepers
+            .drain(..)
+            .partition(|(deadline, _)| *deadline <= now);
+        state.sleepers = pending;
+        drop(state);
+
+        for (_, waker) in due {
+            waker.wake();
+        }
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.state.lock().unwrap().now
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(MockSleep {
+            deadline: self.now() + duration,
+            clock: self.clone(),
+        })
+    }
+}
+
+struct MockSleep {
+    clock: MockClock,
+    deadline: Instant,
+}
+
+impl Future for MockSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.clock.state.lock().unwrap();
+        if state.now >= self.deadline {
+            return Poll::Ready(());
+        }
+        let waker = cx.waker();
+        let registered = state
+            .sleepers
+            .iter()
+            .any(|(deadline, w)| *deadline == self.deadline && w.will_wake(waker));
+        if !registered {
+            state.sleepers.push((self.deadline, waker.clone()));
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn mock_sleep_completes_only_when_advanced() {
+        let clock = MockClock::new();
+        let started = clock.now();
+        let sleep = tokio::spawn(clock.sleep(Duration::from_secs(60)));
+
+        tokio::task::yield_now().await;
+        clock.advance(Duration::from_secs(59));
+        tokio::task::yield_now().await;
+        assert!(!sleep.is_finished());
+
+        clock.advance(Duration::from_secs(1));
+        sleep.await.unwrap();
+        assert_eq!(clock.now() - started, Duration::from_secs(60));
+
+        let shared: Arc<dyn Clock> = Arc::new(clock.clone());
+        shared.sleep(Duration::ZERO).await;
+    }
+}
@@ -0,0 +1,190 @@
+//! Undirected graph stored as adjacency lists.
+//!
+//! Nodes are identified by arbitrary keys, which are mapped to dense indices
+//! on insertion, so traversals work over plain vectors instead of hashing on
+//! every step.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+};
+
+/// Undirected graph without parallel edges.
+#[derive(Clone, Debug)]
+pub struct Graph<K> {
+    nodes: Vec<K>,
+    index: HashMap<K, usize>,
+    adjacency: Vec<Vec<usize>>,
+    edges: usize,
+}
+
+impl<K> Default for Graph<K> {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            index: HashMap::new(),
+            adjacency: Vec::new(),
+            edges: 0,
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> Graph<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the `node` unless it's already in the graph.
+    pub fn add_node(&mut self, node: K) {
+        self.position(node);
+    }
+
+    /// Links the nodes, adding them if missing. Returns whether the edge is
+    /// new; self-loops are ignored.
+    pub fn add_edge(&mut self, a: K, b: K) -> bool {
+        let (a, b) = (self.position(a), self.position(b));
+        if a == b || self.adjacency[a].contains(&b) {
+            return false;
+        }
+        self.adjacency[a].push(b);
+        self.adjacency[b].push(a);
+        self.edges += 1;
+        true
+    }
+
+    pub fn contains(&self, node: &K) -> bool {
+        self.index.contains_key(node)
+    }
+
+    /// Nodes linked to the `node`, in the order the edges were added.
+    pub fn neighbors<'a>(&'a self, node: &K) -> impl Iterator<Item = &'a K> + 'a {
+        let adjacent = match self.index.get(node) {
+            Some(&i) => self.adjacency[i].as_slice(),
+            None => &[],
+        };
+        adjacent.iter().map(|&j| &self.nodes[j])
+    }
+
+    /// Shortest chain of nodes leading from `from` to `to`, both included,
+    /// found by breadth-first search.
+    ///
+    /// Its length minus one is the degree of separation between the nodes.
+    /// Returns `None` if either node is missing or they aren't connected.
+    pub fn shortest_path(&self, from: &K, to: &K) -> Option<Vec<K>> {
+        let (&from, &to) = (self.index.get(from)?, self.index.get(to)?);
+        let mut previous = vec![None; self.nodes.len()];
+        previous[from] = Some(from);
+        let mut queue = VecDeque::from([from]);
+        while let Some(i) = queue.pop_front() {
+            if i == to {
+                break;
+            }
+            for &j in &self.adjacency[i] {
+                if previous[j].is_none() {
+                    previous[j] = Some(i);
+                    queue.push_back(j);
+                }
+            }
+        }
+
+        previous[to]?;
+        let mut path = vec![self.nodes[to].clone()];
+        let mut i = to;
+        while i != from {
+            i = previous[i]?;
+            path.push(self.nodes[i].clone());
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// Groups of nodes reachable from each other. Groups and their members
+    /// follow the order the nodes were added in.
+    pub fn components(&self) -> Vec<Vec<K>> {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut components = Vec::new();
+        let mut queue = VecDeque::new();
+        for start in 0..self.nodes.len() {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            queue.push_back(start);
+            let mut members = Vec::new();
+            while let Some(i) = queue.pop_front() {
+                members.push(i);
+                for &j in &self.adjacency[i] {
+                    if !visited[j] {
+                        visited[j] = true;
+                        queue.push_back(j);
+                    }
+                }
+            }
+            members.sort_unstable();
+            components.push(members.into_iter().map(|i| self.nodes[i].clone()).collect());
+        }
+        components
+    }
+
+    /// Number of nodes.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edges
+    }
+
+    fn position(&mut self, node: K) -> usize {
+        if let Some(&i) = self.index.get(&node) {
+            return i;
+        }
+        let i = self.nodes.len();
+        self.index.insert(node.clone(), i);
+        self.nodes.push(node);
+        self.adjacency.push(Vec::new());
+        i
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_shortest_paths() {
+        let mut graph = Graph::new();
+        for (a, b) in [(1, 2), (2, 3), (3, 4), (1, 5), (5, 4)] {
+            assert!(graph.add_edge(a, b));
+        }
+        assert!(!graph.add_edge(2, 1));
+        assert!(!graph.add_edge(6, 6));
+        assert_eq!((graph.len(), graph.edge_count()), (6, 5));
+
+        assert_eq!(graph.shortest_path(&1, &4), Some(vec![1, 5, 4]));
+        assert_eq!(graph.shortest_path(&3, &3), Some(vec![3]));
+        assert_eq!(graph.shortest_path(&1, &6), None);
+        assert_eq!(graph.shortest_path(&1, &7), None);
+        assert_eq!(graph.neighbors(&1).collect::<Vec<_>>(), [&2, &5]);
+        assert_eq!(graph.neighbors(&7).count(), 0);
+    }
+
+    #[test]
+    fn splits_into_components() {
+        let mut graph = Graph::new();
+        graph.add_node("carol");
+        graph.add_edge("dave", "erin");
+        graph.add_edge("alice", "bob");
+        graph.add_edge("erin", "alice");
+
+        assert_eq!(
+            graph.components(),
+            [vec!["carol"], vec!["dave", "erin", "alice", "bob"]]
+        );
+        assert!(Graph::<u8>::new().components().is_empty());
+    }
+}
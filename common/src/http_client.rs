@@ -0,0 +1,362 @@
+//! Preconfigured HTTP client shared by the command-line tools.
+//!
+//! [`HttpClient`] wraps a `reqwest::Client` with connection pool limits and
+//! default timeouts, caps how many requests run against a single host at
+//! once, logs every request with `tracing` and can cache bodies of successful
+//! `GET` responses. Requests are only built through the [`HttpClient`], so
+//! none of them skip the limits or the logging.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use reqwest::{
+    Client, IntoUrl, Method, Request, RequestBuilder, Response, StatusCode, Url,
+    header::{HeaderName, HeaderValue},
+};
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, warn};
+
+/// Settings of an [`HttpClient`].
+#[derive(Clone, Debug)]
+pub struct HttpClientBuilder {
+    timeout: Duration,
+    connect_timeout: Duration,
+    pool_max_idle_per_host: usize,
+    pool_idle_timeout: Duration,
+    max_per_host: usize,
+    cache_ttl: Option<Duration>,
+    no_proxy: bool,
+}
+
+impl Default for HttpClientBuilder {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+            pool_max_idle_per_host: 8,
+            pool_idle_timeout: Duration::from_secs(90),
+            max_per_host: 8,
+            cache_ttl: None,
+            no_proxy: false,
+        }
+    }
+}
+
+impl HttpClientBuilder {
+    /// Limits the whole request, from connecting to reading the body.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Number of idle connections kept open to every host.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = max;
+        self
+    }
+
+    /// Number of requests running against a single host at once; others
+    /// wait for their turn.
+    pub fn max_per_host(mut self, max: usize) -> Self {
+        self.max_per_host = max.max(1);
+        self
+    }
+
+    /// Serves repeated [`HttpClient::get_bytes`] of a URL from memory for
+    /// `ttl` after a successful response.
+    pub fn cache(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Ignores proxies configured in the environment.
+    pub fn no_proxy(mut self) -> Self {
+        self.no_proxy = true;
+        self
+    }
+
+    pub fn build(self) -> reqwest::Result<HttpClient> {
+        let mut builder = Client::builder()
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(self.pool_idle_timeout);
+        if self.no_proxy {
+            builder = builder.no_proxy();
+        }
+        Ok(HttpClient {
+            client: builder.build()?,
+            max_per_host: self.max_per_host,
+            hosts: Arc::default(),
+            cache: self.cache_ttl.map(|ttl| {
+                Arc::new(Cache {
+                    ttl,
+                    entries: Mutex::default(),
+                })
+            }),
+        })
+    }
+}
+
+/// `reqwest::Client` with per-host concurrency limits, request logging and
+/// an optional response cache.
+///
+/// Cloning is cheap: clones share connections, limits and the cache.
+#[derive(Clone, Debug)]
+pub struct HttpClient {
+    client: Client,
+    max_per_host: usize,
+    hosts: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+    cache: Option<Arc<Cache>>,
+}
+
+impl HttpClient {
+    pub fn builder() -> HttpClientBuilder {
+        HttpClientBuilder::default()
+    }
+
+    /// Client with the default settings.
+    pub fn new() -> reqwest::Result<Self> {
+        Self::builder().build()
+    }
+
+    /// Starts a request, sent through this client by
+    /// [`HttpRequestBuilder::send`].
+    pub fn request(&self, method: Method, url: impl IntoUrl) -> HttpRequestBuilder {
+        HttpRequestBuilder {
+            client: self.clone(),
+            request: self.client.request(method, url),
+        }
+    }
+
+    pub fn get(&self, url: impl IntoUrl) -> HttpRequestBuilder {
+        self.request(Method::GET, url)
+    }
+
+    pub fn post(&self, url: impl IntoUrl) -> HttpRequestBuilder {
+        self.request(Method::POST, url)
+    }
+
+    pub fn put(&self, url: impl IntoUrl) -> HttpRequestBuilder {
+        self.request(Method::PUT, url)
+    }
+
+    pub fn patch(&self, url: impl IntoUrl) -> HttpRequestBuilder {
+        self.request(Method::PATCH, url)
+    }
+
+    pub fn delete(&self, url: impl IntoUrl) -> HttpRequestBuilder {
+        self.request(Method::DELETE, url)
+    }
+
+    /// Downloads the body of a successful `GET` response, or takes it from
+    /// the cache if it's enabled.
+    ///
+    /// Unlike [`HttpRequestBuilder::send`], the host slot is held until the
+    /// whole body is read, and error statuses are returned as errors.
+    pub async fn get_bytes(&self, url: impl IntoUrl) -> reqwest::Result<Bytes> {
+        let url = url.into_url()?;
+        if let Some(body) = self.cache.as_ref().and_then(|cache| cache.get(&url)) {
+            debug!(%url, "http cache hit");
+            return Ok(body);
+        }
+        let _permit = self.acquire(&url).await;
+        let request = self.client.get(url.clone()).build()?;
+        let body = self
+            .execute(request)
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        if let Some(cache) = &self.cache {
+            cache.insert(url, body.clone());
+        }
+        Ok(body)
+    }
+
+    async fn execute(&self, request: Request) -> reqwest::Result<Response> {
+        let (method, url) = (request.method().clone(), request.url().clone());
+        let started = Instant::now();
+        let result = self.client.execute(request).await;
+        let elapsed = started.elapsed();
+        match &result {
+            Ok(response) => {
+                let status = response.status().as_u16();
+                debug!(%method, %url, status, ?elapsed, "http request");
+            }
+            Err(err) => warn!(%method, %url, ?elapsed, "http request failed: {err}"),
+        }
+        result
+    }
+
+    async fn acquire(&self, url: &Url) -> OwnedSemaphorePermit {
+        let host = format!(
+            "{}:{}",
+            url.host_str().unwrap_or_default(),
+            url.port_or_known_default().unwrap_or_default()
+        );
+        let semaphore = self
+            .hosts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max_per_host)))
+            .clone();
+        semaphore
+            .acquire_owned()
+            .await
+            .expect("host semaphores are never closed")
+    }
+}
+
+/// Request started by an [`HttpClient`], sent under its per-host limit.
+#[derive(Debug)]
+#[must_use = "the request isn't sent until `send` is awaited"]
+pub struct HttpRequestBuilder {
+    client: HttpClient,
+    request: RequestBuilder,
+}
+
+impl HttpRequestBuilder {
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.request = self.request.header(name, value);
+        self
+    }
+
+    pub fn bearer_auth(mut self, token: impl std::fmt::Display) -> Self {
+        self.request = self.request.bearer_auth(token);
+        self
+    }
+
+    /// Appends the `query` to the query string of the URL.
+    pub fn query<T: Serialize + ?Sized>(mut self, query: &T) -> Self {
+        self.request = self.request.query(query);
+        self
+    }
+
+    /// Sends the `json` as the body, with the matching `Content-Type`.
+    pub fn json<T: Serialize + ?Sized>(mut self, json: &T) -> Self {
+        self.request = self.request.json(json);
+        self
+    }
+
+    /// Overrides the timeout of the client for this request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.request = self.request.timeout(timeout);
+        self
+    }
+
+    /// Sends the request as soon as its host has a free slot, holding the
+    /// slot until the response headers arrive.
+    pub async fn send(self) -> reqwest::Result<Response> {
+        let request = self.request.build()?;
+        let _permit = self.client.acquire(request.url()).await;
+        self.client.execute(request).await
+    }
+}
+
+/// Whether the request may succeed if repeated: the server was unreachable,
+/// overloaded or failing. Meant as the predicate of
+/// [`retry_async`](crate::retry::retry_async).
+pub fn is_transient(err: &reqwest::Error) -> bool {
+    err.is_timeout()
+        || err.is_connect()
+        || err.status().is_some_and(|status| {
+            status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+        })
+}
+
+/// Bodies of successful `GET` responses by their URLs.
+#[derive(Debug)]
+struct Cache {
+    ttl: Duration,
+    entries: Mutex<HashMap<Url, (Instant, Bytes)>>,
+}
+
+impl Cache {
+    fn get(&self, url: &Url) -> Option<Bytes> {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        match entries.get(url) {
+            Some((stored, body)) if stored.elapsed() < self.ttl => Some(body.clone()),
+            Some(_) => {
+                entries.remove(url);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, url: Url, body: Bytes) {
+        self.entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(url, (Instant::now(), body));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use httpmock::{Method::GET, MockServer};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn caches_successful_bodies() {
+        let server = MockServer::start_async().await;
+        let page = server.mock(|when, then| {
+            when.method(GET).path("/page");
+            then.status(200).body("page");
+        });
+        let missing = server.mock(|when, then| {
+            when.method(GET).path("/missing");
+            then.status(404);
+        });
+        let client = HttpClient::builder()
+            .no_proxy()
+            .cache(Duration::from_secs(60))
+            .build()
+            .unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(client.get_bytes(server.url("/page")).await.unwrap(), "page");
+            let err = client.get_bytes(server.url("/missing")).await.unwrap_err();
+            assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+            assert!(!is_transient(&err));
+        }
+        page.assert_hits(1);
+        missing.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn limits_concurrent_requests_per_host() {
+        let server = MockServer::start_async().await;
+        server.mock(|when, then| {
+            when.method(GET).path("/slow");
+            then.status(200).delay(Duration::from_millis(200));
+        });
+        let client = HttpClient::builder()
+            .no_proxy()
+            .max_per_host(1)
+            .build()
+            .unwrap();
+
+        let started = Instant::now();
+        let (first, second) = tokio::join!(
+            client.get(server.url("/slow")).send(),
+            client.get(server.url("/slow")).send(),
+        );
+        assert!(first.unwrap().status().is_success());
+        assert!(second.unwrap().status().is_success());
+        assert!(started.elapsed() >= Duration::from_millis(400));
+    }
+}
@@ -0,0 +1,19 @@
+//! Building blocks shared by the crates of the workspace.
+
+pub mod clock;
+pub mod graph;
+#[cfg(feature = "http")]
+pub mod http_client;
+pub mod metrics;
+pub mod reproducible;
+pub mod retry;
+pub mod search;
+pub mod state_file;
+pub mod worker_pool;
+
+pub use clock::{Clock, MockClock, SystemClock};
+pub use graph::Graph;
+pub use metrics::{Counter, Gauge, Histogram, MinuteRing};
+pub use search::SearchIndex;
+pub use state_file::{StateFile, shutdown_signal};
+pub use worker_pool::{WorkerPool, WorkerPoolConfig};
@@ -0,0 +1,321 @@
+//! Lightweight in-process metrics, usable without a metrics backend.
+//!
+//! [`Counter`]s, [`Gauge`]s and [`Histogram`]s are plain atomics, cheap to
+//! update from any thread. A [`MinuteRing`] keeps an [`Aggregate`] of the
+//! values recorded in each of the last minutes, so reports can show recent
+//! activity rather than totals since the start.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicI64, AtomicU64, Ordering},
+    },
+    time::Instant,
+};
+
+use serde::Serialize;
+
+use crate::Clock;
+
+/// Monotonically growing count of events.
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Value going up and down, like the number of open connections.
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn dec(&self) {
+        self.add(-1);
+    }
+
+    pub fn add(&self, delta: i64) {
+        self.0.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Distribution of values over buckets fixed upfront.
+#[derive(Debug)]
+pub struct Histogram {
+    /// Inclusive upper bounds of the buckets, in ascending order.
+    bounds: Box<[u64]>,
+    /// Counts of the buckets, with the last one catching values above all the
+    /// bounds.
+    counts: Box<[AtomicU64]>,
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    /// Creates a histogram with the given bucket bounds, sorted and
+    /// deduplicated.
+    pub fn new(bounds: impl Into<Vec<u64>>) -> Self {
+        let mut bounds = bounds.into();
+        bounds.sort_unstable();
+        bounds.dedup();
+        let counts = (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect();
+        Self {
+            bounds: bounds.into(),
+            counts,
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, value: u64) {
+        let bucket = self.bounds.partition_point(|&bound| bound < value);
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let buckets: Vec<Bucket> = self
+            .bounds
+            .iter()
+            .copied()
+            .chain([u64::MAX])
+            .zip(&self.counts)
+            .map(|(le, count)| Bucket {
+                le,
+                count: count.load(Ordering::Relaxed),
+            })
+            .collect();
+        HistogramSnapshot {
+            count: buckets.iter().map(|bucket| bucket.count).sum(),
+            sum: self.sum.load(Ordering::Relaxed),
+            buckets,
+        }
+    }
+}
+
+/// Bucket of a [`HistogramSnapshot`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Bucket {
+    /// Inclusive upper bound, `u64::MAX` for the overflow bucket.
+    pub le: u64,
+    pub count: u64,
+}
+
+/// Counts of a [`Histogram`] at some moment.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HistogramSnapshot {
+    pub count: u64,
+    pub sum: u64,
+    pub buckets: Vec<Bucket>,
+}
+
+impl HistogramSnapshot {
+    /// Upper bound of the bucket holding the `q` quantile, `q` being in
+    /// `0.0..=1.0`.
+    ///
+    /// Returns `None` if nothing was observed.
+    pub fn quantile(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        self.buckets.iter().find_map(|bucket| {
+            seen += bucket.count;
+            (seen >= rank).then_some(bucket.le)
+        })
+    }
+}
+
+/// Summary of the values recorded within some period.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Aggregate {
+    pub count: u64,
+    pub sum: u64,
+    /// Smallest value, `0` if none was recorded.
+    pub min: u64,
+    pub max: u64,
+}
+
+impl Aggregate {
+    pub fn record(&mut self, value: u64) {
+        self.min = if self.count == 0 {
+            value
+        } else {
+            self.min.min(value)
+        };
+        self.max = self.max.max(value);
+        self.count += 1;
+        self.sum = self.sum.saturating_add(value);
+    }
+
+    /// Combines the values of both periods.
+    pub fn merge(&mut self, other: &Aggregate) {
+        if other.count == 0 {
+            return;
+        }
+        self.min = if self.count == 0 {
+            other.min
+        } else {
+            self.min.min(other.min)
+        };
+        self.max = self.max.max(other.max);
+        self.count += other.count;
+        self.sum = self.sum.saturating_add(other.sum);
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum as f64 / self.count as f64)
+    }
+}
+
+/// [`Aggregate`]s of the values recorded in each of the last minutes.
+///
+/// Minutes are counted from the creation of the ring, and the ones older
+/// than its capacity are dropped as new values come in.
+#[derive(Debug)]
+pub struct MinuteRing {
+    clock: Arc<dyn Clock>,
+    started: Instant,
+    capacity: u64,
+    /// Non-empty minutes, numbered since `started`, the oldest first.
+    minutes: Mutex<VecDeque<(u64, Aggregate)>>,
+}
+
+impl MinuteRing {
+    /// Creates a ring keeping `capacity` minutes, at least one.
+    pub fn new(clock: Arc<dyn Clock>, capacity: usize) -> Self {
+        Self {
+            started: clock.now(),
+            clock,
+            capacity: capacity.max(1) as u64,
+            minutes: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn record(&self, value: u64) {
+        let mut minutes = self.lock();
+        // Read under the lock, so no minute kept is later than the current one.
+        let now = self.minute();
+        match minutes.back_mut() {
+            Some((minute, aggregate)) if *minute == now => aggregate.record(value),
+            _ => {
+                let mut aggregate = Aggregate::default();
+                aggregate.record(value);
+                minutes.push_back((now, aggregate));
+            }
+        }
+        while minutes
+            .front()
+            .is_some_and(|(minute, _)| now - minute >= self.capacity)
+        {
+            minutes.pop_front();
+        }
+    }
+
+    /// Aggregate of the last `minutes`, including the current one.
+    pub fn last(&self, minutes: usize) -> Aggregate {
+        let mut total = Aggregate::default();
+        for (_, aggregate) in self
+            .recent()
+            .iter()
+            .take_while(|(ago, _)| *ago < minutes as u64)
+        {
+            total.merge(aggregate);
+        }
+        total
+    }
+
+    /// Aggregates of the non-empty minutes still kept, along with how many
+    /// minutes ago they were, the current one first.
+    pub fn recent(&self) -> Vec<(u64, Aggregate)> {
+        let minutes = self.lock();
+        let now = self.minute();
+        minutes
+            .iter()
+            .rev()
+            .map(|&(minute, aggregate)| (now - minute, aggregate))
+            .filter(|(ago, _)| *ago < self.capacity)
+            .collect()
+    }
+
+    fn minute(&self) -> u64 {
+        (self.clock.now() - self.started).as_secs() / 60
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<(u64, Aggregate)>> {
+        self.minutes.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::MockClock;
+
+    #[test]
+    fn histogram_buckets_values() {
+        let histogram = Histogram::new([100, 10, 1000]);
+        for value in [5, 10, 11, 500, 5000] {
+            histogram.observe(value);
+        }
+
+        let snapshot = histogram.snapshot();
+        let counts: Vec<_> = snapshot.buckets.iter().map(|b| (b.le, b.count)).collect();
+        assert_eq!(counts, [(10, 2), (100, 1), (1000, 1), (u64::MAX, 1)]);
+        assert_eq!((snapshot.count, snapshot.sum), (5, 5526));
+        assert_eq!(snapshot.quantile(0.5), Some(100));
+        assert_eq!(snapshot.quantile(0.0), Some(10));
+        assert_eq!(Histogram::new([1]).snapshot().quantile(0.5), None);
+    }
+
+    #[test]
+    fn minute_ring_drops_old_minutes() {
+        let clock = MockClock::new();
+        let ring = MinuteRing::new(Arc::new(clock.clone()), 3);
+        ring.record(5);
+        ring.record(1);
+        clock.advance(Duration::from_secs(60));
+        ring.record(10);
+
+        let expected = Aggregate {
+            count: 3,
+            sum: 16,
+            min: 1,
+            max: 10,
+        };
+        assert_eq!(ring.last(3), expected);
+        assert_eq!(ring.last(1).sum, 10);
+        let (ago, first) = ring.recent()[1];
+        assert_eq!((ago, first.count, first.min, first.max), (1, 2, 1, 5));
+
+        clock.advance(Duration::from_secs(2 * 60));
+        assert_eq!(ring.recent().len(), 1);
+        ring.record(7);
+        assert_eq!(ring.last(3).count, 2);
+        assert_eq!(ring.last(3).mean(), Some(8.5));
+    }
+}
@@ -0,0 +1,46 @@
+//! Helpers for tools producing byte-identical artifacts across runs.
+//!
+//! Following the <https://reproducible-builds.org> convention, files are
+//! stamped with the `SOURCE_DATE_EPOCH` time instead of the current one.
+
+use std::{
+    fs::File,
+    io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Time to stamp artifacts with: `SOURCE_DATE_EPOCH` if it's set to a number
+/// of seconds, the Unix epoch otherwise.
+pub fn source_date_epoch() -> SystemTime {
+    parse_epoch(std::env::var("SOURCE_DATE_EPOCH").ok().as_deref())
+}
+
+fn parse_epoch(value: Option<&str>) -> SystemTime {
+    let secs = value.and_then(|value| value.trim().parse().ok());
+    UNIX_EPOCH + Duration::from_secs(secs.unwrap_or(0))
+}
+
+/// Sets the modification time of an already written file.
+pub fn set_mtime(path: &Path, time: SystemTime) -> io::Result<()> {
+    File::options().write(true).open(path)?.set_modified(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamps_files_with_the_epoch() {
+        assert_eq!(parse_epoch(None), UNIX_EPOCH);
+        assert_eq!(parse_epoch(Some("not a number")), UNIX_EPOCH);
+        let time = parse_epoch(Some("1700000000"));
+        assert_eq!(time, UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("artifact");
+        std::fs::write(&path, b"data").unwrap();
+        set_mtime(&path, time).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().modified().unwrap(), time);
+    }
+}
@@ -0,0 +1,216 @@
+//! Retrying fallible operations with exponential backoff.
+//!
+//! The [`Backoff`] policy decides how many attempts are made and how long to
+//! wait between them, while the caller decides which errors are worth
+//! retrying at all. Waits are randomized by default, so clients failing at
+//! the same moment don't retry in lockstep.
+
+use std::time::Duration;
+
+use rand::Rng as _;
+
+use crate::Clock;
+
+/// How many times to attempt an operation and how long to wait in between.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Backoff {
+    /// Attempts in total, including the first one.
+    pub max_attempts: u32,
+    /// Wait after the first failed attempt.
+    pub initial_delay: Duration,
+    /// Upper bound of a single wait.
+    pub max_delay: Duration,
+    /// Factor the wait grows by after every failed attempt.
+    pub multiplier: f64,
+    /// Whether to wait a random time between half of the delay and all of it.
+    pub jitter: bool,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: true,
+        }
+    }
+}
+
+impl Backoff {
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn with_initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    pub fn without_jitter(mut self) -> Self {
+        self.jitter = false;
+        self
+    }
+
+    /// Wait after the failed `attempt`, numbered from 1, before the next one.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self
+            .initial_delay
+            .mul_f64(self.multiplier.powi(exponent).min(u32::MAX.into()))
+            .min(self.max_delay);
+        if self.jitter {
+            rand::thread_rng().gen_range(delay / 2..=delay)
+        } else {
+            delay
+        }
+    }
+}
+
+/// Calls `op` with the attempt number until it succeeds, fails with an error
+/// not passing `should_retry`, or runs out of attempts, sleeping the thread
+/// between the attempts.
+///
+/// The error of the last attempt is returned.
+pub fn retry<T, E>(
+    backoff: &Backoff,
+    mut should_retry: impl FnMut(&E) -> bool,
+    mut op: impl FnMut(u32) -> Result<T, E>,
+) -> Result<T, E> {
+    let mut attempt = 1;
+    loop {
+        match op(attempt) {
+            Err(err) if attempt < backoff.max_attempts && should_retry(&err) => {
+                std::thread::sleep(backoff.delay(attempt));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Asynchronous [`retry`], waiting between the attempts on the `clock`.
+pub async fn retry_async<T, E, F, Fut>(
+    backoff: &Backoff,
+    clock: &dyn Clock,
+    mut should_retry: impl FnMut(&E) -> bool,
+    mut op: F,
+) -> Result<T, E>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 1;
+    loop {
+        match op(attempt).await {
+            Err(err) if attempt < backoff.max_attempts && should_retry(&err) => {
+                clock.sleep(backoff.delay(attempt)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    };
+
+    use super::*;
+    use crate::MockClock;
+
+    #[test]
+    fn delays_grow_up_to_the_limit() {
+        let backoff = Backoff {
+            max_delay: Duration::from_secs(1),
+            ..Backoff::default()
+        }
+        .with_initial_delay(Duration::from_millis(300))
+        .without_jitter();
+        let delays: Vec<_> = (1..=4).map(|attempt| backoff.delay(attempt)).collect();
+        assert_eq!(
+            delays,
+            [300, 600, 1000, 1000].map(Duration::from_millis).to_vec()
+        );
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(1));
+
+        let jittered = Backoff::default().delay(2);
+        assert!((Duration::from_millis(200)..=Duration::from_millis(400)).contains(&jittered));
+    }
+
+    #[test]
+    fn retries_only_retryable_errors() {
+        let backoff = Backoff::default()
+            .with_max_attempts(4)
+            .with_initial_delay(Duration::ZERO);
+
+        let mut attempts = Vec::new();
+        let result = retry(
+            &backoff,
+            |err: &&str| *err == "transient",
+            |attempt| {
+                attempts.push(attempt);
+                if attempt < 3 {
+                    Err("transient")
+                } else {
+                    Ok(attempt)
+                }
+            },
+        );
+        assert_eq!((result, attempts), (Ok(3), vec![1, 2, 3]));
+
+        let mut calls = 0;
+        let result: Result<(), _> = retry(
+            &backoff,
+            |err| *err == "transient",
+            |_| {
+                calls += 1;
+                Err("fatal")
+            },
+        );
+        assert_eq!((result, calls), (Err("fatal"), 1));
+
+        let result: Result<(), _> = retry(&backoff, |_| true, Err);
+        assert_eq!(result, Err(4), "gives up after max_attempts");
+    }
+
+    #[tokio::test]
+    async fn waits_on_the_clock_between_attempts() {
+        let clock = MockClock::new();
+        let started = clock.now();
+        let calls = Arc::new(AtomicU32::new(0));
+        let task = tokio::spawn({
+            let clock = clock.clone();
+            let calls = calls.clone();
+            async move {
+                let backoff = Backoff::default()
+                    .with_initial_delay(Duration::from_secs(1))
+                    .without_jitter();
+                retry_async(
+                    &backoff,
+                    &clock,
+                    |_| true,
+                    |attempt| {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        async move { if attempt < 3 { Err(()) } else { Ok(attempt) } }
+                    },
+                )
+                .await
+            }
+        });
+
+        for expected in 1..3 {
+            while calls.load(Ordering::SeqCst) < expected {
+                tokio::task::yield_now().await;
+            }
+            clock.advance(Duration::from_secs(expected.into()));
+        }
+        assert_eq!(task.await.unwrap(), Ok(3));
+        assert_eq!(clock.now() - started, Duration::from_secs(3));
+    }
+}
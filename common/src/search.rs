@@ -0,0 +1,133 @@
+//! In-memory full-text index with prefix search.
+//!
+//! Text is split into lowercase alphanumeric tokens, and every token points
+//! to the documents containing it. The index is updated one document at a
+//! time, so owners keep it in sync with their data instead of rebuilding it
+//! or scanning all documents on every search.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Splits the `text` into lowercase tokens of letters and digits.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Inverted index from tokens to the keys of documents containing them.
+#[derive(Clone, Debug)]
+pub struct SearchIndex<K> {
+    postings: BTreeMap<String, BTreeSet<K>>,
+    /// Tokens of every indexed document, to unlink them on removal.
+    documents: BTreeMap<K, BTreeSet<String>>,
+}
+
+impl<K> Default for SearchIndex<K> {
+    fn default() -> Self {
+        Self {
+            postings: BTreeMap::new(),
+            documents: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K: Ord + Clone> SearchIndex<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Indexes the `text` as the document `key`, replacing its previous text.
+    pub fn insert(&mut self, key: K, text: &str) {
+        self.remove(&key);
+        let tokens: BTreeSet<String> = tokenize(text).collect();
+        for token in &tokens {
+            self.postings
+                .entry(token.clone())
+                .or_default()
+                .insert(key.clone());
+        }
+        self.documents.insert(key, tokens);
+    }
+
+    /// Removes the document `key`, returning whether it was indexed.
+    pub fn remove(&mut self, key: &K) -> bool {
+        let Some(tokens) = self.documents.remove(key) else {
+            return false;
+        };
+        for token in tokens {
+            if let Some(keys) = self.postings.get_mut(&token) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.postings.remove(&token);
+                }
+            }
+        }
+        true
+    }
+
+    /// Keys of the documents in which every token of the `query` is a prefix
+    /// of some token, in ascending order.
+    ///
+    /// A query without tokens matches nothing.
+    pub fn search(&self, query: &str) -> Vec<K> {
+        let mut matches: Option<BTreeSet<K>> = None;
+        for prefix in tokenize(query) {
+            let found: BTreeSet<K> = self
+                .postings
+                .range(prefix.clone()..)
+                .take_while(|(token, _)| token.starts_with(&prefix))
+                .flat_map(|(_, keys)| keys.iter().cloned())
+                .collect();
+            let narrowed = match matches {
+                Some(previous) => previous.intersection(&found).cloned().collect(),
+                None => found,
+            };
+            if narrowed.is_empty() {
+                return Vec::new();
+            }
+            matches = Some(narrowed);
+        }
+        matches.map(Vec::from_iter).unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenizes_into_lowercase_words() {
+        let tokens: Vec<_> = tokenize("Ada  Lovelace, née Byron-King (1815)").collect();
+        assert_eq!(tokens, ["ada", "lovelace", "née", "byron", "king", "1815"]);
+    }
+
+    #[test]
+    fn finds_documents_matching_all_prefixes() {
+        let mut index = SearchIndex::new();
+        index.insert(1, "Ada Lovelace");
+        index.insert(2, "Alan Turing");
+        index.insert(3, "Grace Hopper");
+
+        assert_eq!(index.search("a"), [1, 2]);
+        assert_eq!(index.search("LOVE ada"), [1]);
+        assert_eq!(index.search("ada turing"), Vec::<i32>::new());
+        assert_eq!(index.search(" -- "), Vec::<i32>::new());
+
+        index.insert(1, "Ada King");
+        assert_eq!(index.search("love"), Vec::<i32>::new());
+        assert_eq!(index.search("king"), [1]);
+
+        assert!(index.remove(&2));
+        assert!(!index.remove(&2));
+        assert_eq!(index.search("a"), [1]);
+        assert_eq!(index.len(), 2);
+    }
+}
@@ -0,0 +1,138 @@
+//! Snapshots of in-memory server state surviving restarts.
+//!
+//! A [`StateFile`] stores the state as JSON wrapped into an envelope with the
+//! schema version, so a file written by an incompatible build is rejected
+//! instead of being misread:
+//!
+//! ```json
+//! {"version": 1, "state": {...}}
+//! ```
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+/// JSON file holding a schema-versioned snapshot of some state.
+#[derive(Clone, Debug)]
+pub struct StateFile {
+    path: PathBuf,
+    version: u32,
+}
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    version: u32,
+    state: &'a T,
+}
+
+#[derive(Deserialize)]
+struct RawEnvelope {
+    version: u32,
+    state: serde_json::Value,
+}
+
+impl StateFile {
+    /// Snapshots stored at `path` must be of the schema `version`.
+    pub fn new(path: impl Into<PathBuf>, version: u32) -> Self {
+        Self {
+            path: path.into(),
+            version,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the snapshot, if it was saved at all.
+    ///
+    /// Malformed files and the ones of another schema version are
+    /// [`io::ErrorKind::InvalidData`] errors.
+    pub fn load<T: DeserializeOwned>(&self) -> io::Result<Option<T>> {
+        let contents = match fs::read(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let envelope: RawEnvelope = serde_json::from_slice(&contents)?;
+        if envelope.version != self.version {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} has schema version {}, but {} is expected",
+                    self.path.display(),
+                    envelope.version,
+                    self.version,
+                ),
+            ));
+        }
+        Ok(Some(serde_json::from_value(envelope.state)?))
+    }
+
+    /// Replaces the snapshot with the `state`.
+    ///
+    /// The file is replaced atomically, so a crash while saving leaves the
+    /// previous snapshot intact.
+    pub fn save<T: Serialize>(&self, state: &T) -> io::Result<()> {
+        let envelope = Envelope {
+            version: self.version,
+            state,
+        };
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&envelope)?)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+/// Completes on `Ctrl+C` or, on Unix, `SIGTERM`, so servers can shut down
+/// gracefully and save their state.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[test]
+    fn roundtrips_only_matching_schema_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let file = StateFile::new(&path, 1);
+        assert_eq!(file.load::<BTreeMap<u64, String>>().unwrap(), None);
+
+        let state = BTreeMap::from([(1, "alice".to_owned()), (2, "bob".to_owned())]);
+        file.save(&state).unwrap();
+        assert_eq!(file.load().unwrap(), Some(state));
+
+        let err = StateFile::new(&path, 2)
+            .load::<BTreeMap<u64, String>>()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
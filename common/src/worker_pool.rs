@@ -0,0 +1,266 @@
+//! Fixed set of threads running CPU-bound jobs on behalf of async code.
+//!
+//! Jobs wait for a free worker in a bounded queue, so a burst of submissions
+//! makes the submitters wait instead of piling up in memory. A panicking job
+//! only fails its own caller, and [`WorkerPool::drain`] lets the queued jobs
+//! finish before the workers stop.
+
+use std::{
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot, watch};
+
+/// Settings of a [`WorkerPool`], read from a config section.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct WorkerPoolConfig {
+    /// Number of worker threads.
+    #[serde(default = "default_workers")]
+    pub workers: usize,
+    /// Number of jobs waiting for a worker before submitters have to wait.
+    #[serde(default = "default_queue_len")]
+    pub queue_len: usize,
+    /// Time the queued jobs get to finish on shutdown.
+    #[serde(default = "default_drain_timeout", with = "humantime_serde")]
+    #[cfg_attr(feature = "schemars", schemars(with = "String"))]
+    pub drain_timeout: Duration,
+}
+
+impl Default for WorkerPoolConfig {
+    fn default() -> Self {
+        Self {
+            workers: default_workers(),
+            queue_len: default_queue_len(),
+            drain_timeout: default_drain_timeout(),
+        }
+    }
+}
+
+fn default_workers() -> usize {
+    4
+}
+
+fn default_queue_len() -> usize {
+    64
+}
+
+fn default_drain_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+/// Reason a job submitted with [`WorkerPool::run`] produced no result.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobError {
+    /// The pool is drained and accepts no more jobs.
+    Closed,
+    Panicked,
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::Closed => f.write_str("worker pool is closed"),
+            JobError::Panicked => f.write_str("job panicked"),
+        }
+    }
+}
+
+impl std::error::Error for JobError {}
+
+/// Counters of a [`WorkerPool`], see [`WorkerPool::stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PoolStats {
+    /// Worker threads still running.
+    pub workers: u64,
+    /// Jobs waiting for a worker.
+    pub queued: u64,
+    /// Jobs being run right now.
+    pub running: u64,
+    pub completed: u64,
+    pub panicked: u64,
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// State shared by the pool handles and the workers.
+#[derive(Debug)]
+struct Shared {
+    /// Number of workers that haven't exited yet.
+    workers: watch::Sender<u64>,
+    running: AtomicU64,
+    completed: AtomicU64,
+    panicked: AtomicU64,
+}
+
+/// Threads running jobs from a bounded queue.
+///
+/// Cloning is cheap: clones submit to the same workers. Once the last clone
+/// is dropped, the workers finish the queued jobs and exit.
+#[derive(Clone, Debug)]
+pub struct WorkerPool {
+    /// Taken by [`WorkerPool::drain`] to stop accepting jobs.
+    queue: Arc<Mutex<Option<mpsc::Sender<Job>>>>,
+    shared: Arc<Shared>,
+}
+
+impl WorkerPool {
+    /// Starts the workers, naming their threads after the pool.
+    pub fn new(name: &str, config: &WorkerPoolConfig) -> std::io::Result<Self> {
+        let workers = config.workers.max(1);
+        let (sender, receiver) = mpsc::channel::<Job>(config.queue_len.max(1));
+        let receiver = Arc::new(Mutex::new(receiver));
+        let shared = Arc::new(Shared {
+            workers: watch::Sender::new(workers as u64),
+            running: AtomicU64::new(0),
+            completed: AtomicU64::new(0),
+            panicked: AtomicU64::new(0),
+        });
+        for i in 0..workers {
+            let receiver = Arc::clone(&receiver);
+            let shared = Arc::clone(&shared);
+            thread::Builder::new()
+                .name(format!("{name}-{i}"))
+                .spawn(move || {
+                    loop {
+                        // Idle workers queue up on the lock, so only one of
+                        // them waits for the next job at a time.
+                        let job = receiver
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .blocking_recv();
+                        let Some(job) = job else { break };
+                        job();
+                    }
+                    shared.workers.send_modify(|workers| *workers -= 1);
+                })?;
+        }
+        Ok(Self {
+            queue: Arc::new(Mutex::new(Some(sender))),
+            shared,
+        })
+    }
+
+    /// Runs the job on a worker, waiting for a free slot in the queue first.
+    ///
+    /// A panic of the job is caught and reported as [`JobError::Panicked`],
+    /// leaving the worker to carry on with the next job.
+    pub async fn run<F, T>(&self, job: F) -> Result<T, JobError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let queue = self
+            .queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .ok_or(JobError::Closed)?;
+        let (result, received) = oneshot::channel();
+        let shared = Arc::clone(&self.shared);
+        let job: Job = Box::new(move || {
+            shared.running.fetch_add(1, Ordering::Relaxed);
+            let outcome = panic::catch_unwind(AssertUnwindSafe(job));
+            shared.running.fetch_sub(1, Ordering::Relaxed);
+            let counter = match outcome {
+                Ok(_) => &shared.completed,
+                Err(_) => &shared.panicked,
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+            // The submitter may have stopped waiting for the result.
+            let _ = result.send(outcome.map_err(|_| JobError::Panicked));
+        });
+        queue.send(job).await.map_err(|_| JobError::Closed)?;
+        received.await.unwrap_or(Err(JobError::Closed))
+    }
+
+    /// Stops accepting jobs and waits up to `timeout` for the queued and
+    /// running ones to finish.
+    ///
+    /// Returns whether all the workers exited in time.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        self.queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        let mut workers = self.shared.workers.subscribe();
+        tokio::time::timeout(timeout, workers.wait_for(|&workers| workers == 0))
+            .await
+            .is_ok()
+    }
+
+    /// Current counters of the pool.
+    pub fn stats(&self) -> PoolStats {
+        let queued = self
+            .queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .map_or(0, |queue| queue.max_capacity() - queue.capacity());
+        let shared = &self.shared;
+        PoolStats {
+            workers: *shared.workers.borrow(),
+            queued: queued as u64,
+            running: shared.running.load(Ordering::Relaxed),
+            completed: shared.completed.load(Ordering::Relaxed),
+            panicked: shared.panicked.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(workers: usize, queue_len: usize) -> WorkerPoolConfig {
+        WorkerPoolConfig {
+            workers,
+            queue_len,
+            ..WorkerPoolConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn isolates_panicking_jobs() {
+        let pool = WorkerPool::new("test", &config(1, 1)).unwrap();
+
+        assert_eq!(pool.run(|| 2 + 2).await, Ok(4));
+        let panicked = pool.run(|| -> u32 { panic!("job failed") }).await;
+        assert_eq!(panicked, Err(JobError::Panicked));
+        assert_eq!(pool.run(|| "still working").await, Ok("still working"));
+
+        let stats = pool.stats();
+        assert_eq!((stats.workers, stats.completed, stats.panicked), (1, 2, 1));
+    }
+
+    #[tokio::test]
+    async fn drains_queued_jobs() {
+        let pool = WorkerPool::new("test", &config(2, 8)).unwrap();
+        let slow = |i| {
+            move || {
+                thread::sleep(Duration::from_millis(50));
+                i
+            }
+        };
+
+        // The jobs are queued before the pool starts draining.
+        let (first, second, third, drained) = tokio::join!(
+            pool.run(slow(1)),
+            pool.run(slow(2)),
+            pool.run(slow(3)),
+            pool.drain(Duration::from_secs(5)),
+        );
+        assert_eq!((first, second, third), (Ok(1), Ok(2), Ok(3)));
+        assert!(drained);
+        assert_eq!(pool.run(|| ()).await, Err(JobError::Closed));
+        assert_eq!(pool.stats().workers, 0);
+    }
+}
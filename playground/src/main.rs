@@ -0,0 +1,398 @@
+//! Simulation tying the crates of the course together.
+//!
+//! Seeded users of the event-sourced [`UserService`] log in, make friends,
+//! rename themselves and buy snacks from a [`SharedVendingMachine`], one
+//! simulated minute at a time driven by a [`MockClock`], so sessions expire
+//! without real waiting. The published [`UserEvent`]s are logged through the
+//! JSON logging stack of step 3.8, and the final state is saved to a
+//! [`StateFile`] and restored to check that it survives a restart.
+//!
+//! The same seed always plays the same scenario, which makes the run usable
+//! as an integration test of the crates composing.
+
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::{Context, ensure};
+use clap::Parser;
+use common::{MockClock, StateFile};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use step_2::{Coin, Currency, Money, Product, SharedVendingMachine, VendingMachine};
+use step_4_domain::{
+    SNAPSHOT_VERSION, ServiceError, Snapshot, Token, UserEvent, UserId, UserService,
+};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+use tracing::{debug, info, warn};
+
+/// Names of the seeded users, numbered once they run out.
+const NAMES: [&str; 8] = [
+    "Ada", "Alan", "Barbara", "Dennis", "Edsger", "Frances", "Grace", "Linus",
+];
+
+/// Suffixes users pick when renaming themselves.
+const NICKNAMES: [&str; 4] = ["Jr", "II", "the Great", "of Rust"];
+
+/// Snacks of the vending machine along with their prices in cents.
+const PRODUCTS: [(&str, u64); 4] = [("Chips", 45), ("Coffee", 35), ("Tea", 20), ("Water", 15)];
+
+/// Coins customers pay with.
+const COINS: [Coin; 4] = [Coin::Five, Coin::Ten, Coin::Twenty, Coin::Fifty];
+
+const SESSION_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Simulated minutes between restocks of the vending machine.
+const RESTOCK_EVERY: u32 = 60;
+
+#[derive(Debug, Parser)]
+#[command(
+    about = "Users interacting with the course crates over simulated time",
+    version
+)]
+struct Args {
+    #[command(flatten)]
+    scenario: Scenario,
+    /// File to save the final state of the users to
+    #[arg(long)]
+    state: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+struct Scenario {
+    /// Seed of the random choices, the same seed plays the same scenario
+    #[arg(long, default_value_t = 42)]
+    seed: u64,
+    /// Number of users to register, at least two
+    #[arg(long, default_value_t = 8)]
+    users: usize,
+    /// Simulated minutes to run for
+    #[arg(long, default_value_t = 240)]
+    minutes: u32,
+}
+
+/// Outcome of a run, the same for runs of the same scenario.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Report {
+    /// Names of the users at the end, sorted.
+    users: Vec<String>,
+    /// Friendships at the end, counted in both directions.
+    friendships: usize,
+    /// Published user events by kind.
+    events: BTreeMap<&'static str, u64>,
+    expired_sessions: u64,
+    /// Snacks sold by product name.
+    sold: BTreeMap<String, u32>,
+    revenue: u64,
+    failed_purchases: u64,
+}
+
+/// Simulated user along with its session.
+struct Actor {
+    id: UserId,
+    name: String,
+    password: String,
+    session: Option<Token>,
+}
+
+struct Simulation {
+    rng: StdRng,
+    clock: MockClock,
+    service: UserService,
+    events: broadcast::Receiver<UserEvent>,
+    machine: SharedVendingMachine,
+    currency: Currency,
+    actors: Vec<Actor>,
+    minute: u32,
+    report: Report,
+}
+
+impl Simulation {
+    fn new(seed: u64) -> Self {
+        let clock = MockClock::new();
+        let service = UserService::new()
+            .with_clock(Arc::new(clock.clone()))
+            .with_session_ttl(SESSION_TTL);
+        let currency = Currency::new("USD").expect("valid currency code");
+        let machine = SharedVendingMachine::new(VendingMachine::new(100, currency));
+        machine.add_change(COINS.iter().flat_map(|&coin| [coin; 10]));
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            clock,
+            events: service.subscribe(),
+            service,
+            machine,
+            currency,
+            actors: Vec::new(),
+            minute: 0,
+            report: Report::default(),
+        }
+    }
+
+    async fn register(&mut self, count: usize) -> anyhow::Result<()> {
+        for i in 0..count {
+            let name = match NAMES.get(i) {
+                Some(name) => name.to_string(),
+                None => format!("{} {}", NAMES[i % NAMES.len()], i / NAMES.len() + 1),
+            };
+            let password = format!("{}-secret", name.to_lowercase());
+            let user = self.service.register(&name, &password).await?;
+            self.actors.push(Actor {
+                id: user.id,
+                name: user.name,
+                password,
+                session: None,
+            });
+            self.drain_events();
+        }
+        Ok(())
+    }
+
+    /// Plays a minute: a few users act, and the machine is restocked once in
+    /// a while.
+    async fn tick(&mut self) -> anyhow::Result<()> {
+        if self.minute.is_multiple_of(RESTOCK_EVERY) {
+            self.restock();
+        }
+        for _ in 0..self.rng.gen_range(0..=3) {
+            let actor = self.rng.gen_range(0..self.actors.len());
+            self.act(actor).await?;
+            self.drain_events();
+        }
+        self.minute += 1;
+        self.clock.advance(Duration::from_secs(60));
+        Ok(())
+    }
+
+    /// Makes the user log in if its session is gone, or do something else
+    /// otherwise.
+    async fn act(&mut self, i: usize) -> anyhow::Result<()> {
+        let actor = &self.actors[i];
+        let authenticated = match &actor.session {
+            Some(token) => self.service.authenticate(token).await.is_some(),
+            None => false,
+        };
+        if !authenticated {
+            if actor.session.is_some() {
+                debug!(minute = self.minute, user = %actor.name, "session expired");
+                self.report.expired_sessions += 1;
+            }
+            let session = self.service.login(&actor.name, &actor.password).await?;
+            self.actors[i].session = Some(session.token);
+            return Ok(());
+        }
+
+        match self.rng.gen_range(0..100) {
+            0..50 => self.buy(i)?,
+            50..75 => self.befriend(i).await?,
+            75..85 => self.unfriend(i).await?,
+            85..92 => self.rename(i).await?,
+            _ => {
+                if let Some(token) = self.actors[i].session.take() {
+                    self.service.revoke(&token).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn buy(&mut self, i: usize) -> anyhow::Result<()> {
+        let (product, price) = PRODUCTS[self.rng.gen_range(0..PRODUCTS.len())];
+        let session = self.machine.open_session()?;
+        let mut credit = 0;
+        while credit < price {
+            let coin = COINS[self.rng.gen_range(0..COINS.len())];
+            credit = self.machine.insert_coin(session, coin)?.minor_units();
+        }
+        let user = &self.actors[i].name;
+        match self.machine.purchase(session, product) {
+            Ok((sold, change)) => {
+                *self.report.sold.entry(sold.name().to_owned()).or_default() += 1;
+                self.report.revenue += sold.price().minor_units();
+                info!(minute = self.minute, %user, product, change = change.len(), "snack bought");
+            }
+            Err(err) => {
+                warn!(minute = self.minute, %user, product, %err, "purchase failed");
+                self.report.failed_purchases += 1;
+                self.machine.cancel(session)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn befriend(&mut self, i: usize) -> anyhow::Result<()> {
+        let others = self.actors.len() - 1;
+        let friend = (i + self.rng.gen_range(1..=others)) % self.actors.len();
+        self.service
+            .add_friend(self.actors[i].id, self.actors[friend].id)
+            .await?;
+        Ok(())
+    }
+
+    async fn unfriend(&mut self, i: usize) -> anyhow::Result<()> {
+        let id = self.actors[i].id;
+        // Friends are unordered, sorting keeps the choice reproducible.
+        let mut friends = self.service.friends(id).await?;
+        if friends.is_empty() {
+            return Ok(());
+        }
+        friends.sort_by(|a, b| a.name.cmp(&b.name));
+        let friend = &friends[self.rng.gen_range(0..friends.len())];
+        self.service.remove_friend(id, friend.id).await?;
+        Ok(())
+    }
+
+    async fn rename(&mut self, i: usize) -> anyhow::Result<()> {
+        let actor = &self.actors[i];
+        let first_name = actor.name.split(' ').next().unwrap_or_default();
+        let name = format!(
+            "{first_name} {}",
+            NICKNAMES[self.rng.gen_range(0..NICKNAMES.len())]
+        );
+        match self.service.rename(actor.id, &name).await {
+            Ok(user) => self.actors[i].name = user.name,
+            Err(ServiceError::UserExists) => {
+                warn!(minute = self.minute, user = %actor.name, %name, "name is taken");
+            }
+            Err(err) => return Err(err.into()),
+        }
+        Ok(())
+    }
+
+    fn restock(&mut self) {
+        for (name, price) in PRODUCTS {
+            let product =
+                Product::new(name, Money::new(price, self.currency)).expect("prices are positive");
+            if let Err(err) = self.machine.restock(product, 10) {
+                warn!(minute = self.minute, product = name, %err, "restock failed");
+            }
+        }
+    }
+
+    /// Logs and counts the events published since the last call.
+    fn drain_events(&mut self) {
+        loop {
+            match self.events.try_recv() {
+                Ok(event) => {
+                    info!(minute = self.minute, event = event.kind(), "user event");
+                    *self.report.events.entry(event.kind()).or_default() += 1;
+                }
+                Err(TryRecvError::Lagged(missed)) => {
+                    warn!(missed, "user events were missed");
+                }
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+    }
+
+    /// Completes the report with the final state of the users.
+    async fn finish(mut self) -> (Report, Snapshot) {
+        let users = self.service.list().await;
+        self.report.users = users.iter().map(|user| user.name.clone()).collect();
+        self.report.friendships = users.iter().map(|user| user.friends.len()).sum();
+        (self.report, self.service.snapshot().await)
+    }
+}
+
+/// Plays the scenario, then checks the final state of the users survives
+/// saving it to the `state` file, if any, and restoring.
+async fn simulate(scenario: &Scenario, state: Option<&StateFile>) -> anyhow::Result<Report> {
+    let mut simulation = Simulation::new(scenario.seed);
+    simulation.register(scenario.users.max(2)).await?;
+    for _ in 0..scenario.minutes {
+        simulation.tick().await?;
+    }
+    let (report, mut snapshot) = simulation.finish().await;
+
+    if let Some(state) = state {
+        state
+            .save(&snapshot)
+            .with_context(|| format!("failed to save {}", state.path().display()))?;
+        snapshot = state
+            .load()
+            .with_context(|| format!("failed to load {}", state.path().display()))?
+            .context("saved state is missing")?;
+    }
+    let restored = UserService::new();
+    restored.restore(snapshot).await;
+    let names: Vec<_> = restored
+        .list()
+        .await
+        .into_iter()
+        .map(|user| user.name)
+        .collect();
+    ensure!(
+        names == report.users,
+        "restored users differ from the simulated ones"
+    );
+    Ok(report)
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    step_3_8::init_logging("info").map_err(|err| anyhow::anyhow!("{err}"))?;
+
+    let state = args
+        .state
+        .map(|path| StateFile::new(path, SNAPSHOT_VERSION));
+    let report = simulate(&args.scenario, state.as_ref()).await?;
+
+    println!(
+        "{} users, {} friendships after {} minutes",
+        report.users.len(),
+        report.friendships / 2,
+        args.scenario.minutes
+    );
+    for (kind, count) in &report.events {
+        println!("  {kind}: {count}");
+    }
+    println!("  expired sessions: {}", report.expired_sessions);
+    for (product, sold) in &report.sold {
+        println!("  {product} sold: {sold}");
+    }
+    println!(
+        "  revenue: {}, failed purchases: {}",
+        Money::new(
+            report.revenue,
+            Currency::new("USD").expect("valid currency code")
+        ),
+        report.failed_purchases
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn same_seed_plays_same_scenario() {
+        let scenario = Scenario {
+            seed: 7,
+            users: 10,
+            minutes: 180,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let state = StateFile::new(dir.path().join("users.json"), SNAPSHOT_VERSION);
+
+        let report = simulate(&scenario, Some(&state)).await.unwrap();
+        assert_eq!(simulate(&scenario, None).await.unwrap(), report);
+        assert!(state.path().exists());
+
+        assert_eq!(report.users.len(), 10);
+        assert_eq!(report.events["registered"], 10);
+        assert!(report.events["logged_in"] > 10, "users log in again");
+        assert!(report.expired_sessions > 0);
+        assert!(report.revenue > 0);
+        assert_ne!(
+            simulate(
+                &Scenario {
+                    seed: 8,
+                    ..scenario
+                },
+                None
+            )
+            .await
+            .unwrap(),
+            report
+        );
+    }
+}